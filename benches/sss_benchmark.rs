@@ -1,4 +1,7 @@
+use std::collections::HashMap;
+
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use gf256::gf256;
 use shard::sss::{combine_shares, generate_refresh_key, refresh_shares, split_secret};
 
 fn bench_split_secret(c: &mut Criterion) {
@@ -10,6 +13,17 @@ fn bench_split_secret(c: &mut Criterion) {
     });
 }
 
+fn bench_split_large_secret(c: &mut Criterion) {
+    // built with and without the `rayon` feature, this shows what splitting in parallel gains
+    let secret = vec![0x5a; 1 << 18];
+    let mut group = c.benchmark_group("split_secret_256k");
+    group.sample_size(10);
+    group.bench_function("threshold_10_of_20", |b| {
+        b.iter(|| split_secret(black_box(&secret), black_box(10), black_box(20)))
+    });
+    group.finish();
+}
+
 fn bench_combine_shares(c: &mut Criterion) {
     c.bench_function("combine_shares", |b| {
         let secret = b"this is a very secret message";
@@ -20,6 +34,46 @@ fn bench_combine_shares(c: &mut Criterion) {
     });
 }
 
+/// Combines the shares as `combine_shares` did before the Lagrange weights were computed once,
+/// interpolating the points of each byte on their own.
+fn combine_shares_per_byte(shares_map: &HashMap<u8, Vec<u8>>) -> Vec<u8> {
+    let secret_length = shares_map.values().next().map_or(0, Vec::len);
+    (0..secret_length)
+        .map(|i| {
+            let points: Vec<(gf256, gf256)> = shares_map
+                .iter()
+                .map(|(&k, v)| (gf256::new(k), gf256::new(v[i])))
+                .collect();
+            let mut value = gf256::new(0);
+            for (a, &(a_x, a_y)) in points.iter().enumerate() {
+                let mut weight = gf256::new(1);
+                for (b, &(b_x, _)) in points.iter().enumerate() {
+                    if a != b {
+                        weight *= b_x / (a_x + b_x);
+                    }
+                }
+                value += weight * a_y;
+            }
+            value.into()
+        })
+        .collect()
+}
+
+fn bench_combine_large_secret(c: &mut Criterion) {
+    let secret = vec![0x5a; 4096];
+    let shares_map = split_secret(&secret, 10, 10).unwrap();
+    assert_eq!(combine_shares_per_byte(&shares_map), secret);
+
+    let mut group = c.benchmark_group("combine_shares_4k");
+    group.bench_function("per_byte_interpolation", |b| {
+        b.iter(|| combine_shares_per_byte(black_box(&shares_map)))
+    });
+    group.bench_function("weights_at_zero", |b| {
+        b.iter(|| combine_shares(black_box(&shares_map)))
+    });
+    group.finish();
+}
+
 fn bench_refresh_shares(c: &mut Criterion) {
     c.bench_function("refresh_shares", |b| {
         let secret = b"benchmark secret";
@@ -47,7 +101,9 @@ fn bench_generate_refresh_key(c: &mut Criterion) {
 criterion_group!(
     benches,
     bench_split_secret,
+    bench_split_large_secret,
     bench_combine_shares,
+    bench_combine_large_secret,
     bench_refresh_shares,
     bench_generate_refresh_key
 );
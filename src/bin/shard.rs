@@ -1,509 +1,5258 @@
-use clap::{crate_version, Parser};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::Parser;
 
 use futures::prelude::*;
+use libp2p::identity::Keypair;
 use libp2p::PeerId;
 use libp2p::{core::Multiaddr, multiaddr::Protocol};
-use rand::seq::IteratorRandom;
+use rand::seq::{IteratorRandom, SliceRandom};
 use rand::RngCore;
-use shard::config::ShardConfig;
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use shard::chunked::{chunk_key, ChunkManifest, ChunkReader, ChunkWriter, SplitProgress};
+use shard::cli::args::{
+    write_completions, CliArgument, ConfigCommand, Opt, ProfileCommand, ProviderArg, SecretArg,
+    SecretFormat,
+};
+use shard::cli::error::{classify, CliError, ErrorKind};
+use shard::cli::logging::{default_level, log_filter, subscriber};
+use shard::cli::output::{
+    peer_ids, to_json, AccessOutput, CombineOutput, ErrorDetail, ErrorOutput, InfoOutput,
+    KeyListing, KeygenOutput, KeysOutput, LsOutput, ProfileListing, ProfilesOutput, ProvideOutput,
+    ProviderEventOutput, ProviderInfo, ProviderOutcome, ProvidersChangeOutput, RefreshOutput,
+    RegistrationOutcome, RotateOwnerOutput, ShareFilesOutput, SplitOutput, SplitPlanOutput,
+    UnansweredProvider,
+};
+use shard::cli::prompt;
+use shard::client::{Client, NotReady, ReadyCriteria};
+use shard::config::{
+    env_flag, env_list, env_setting, resolve, ShardConfig, BOOTSTRAPPERS_ENV, BOOTSTRAPPER_ENV,
+    CONFIG_DIR_ENV, CONFIG_FILE, DB_PATH_ENV, DEFAULT_PROFILE, EXTERNAL_ADDRESSES_ENV, KEY_FILE,
+    LISTEN_ADDRESSES_ENV, NETWORK_ID_ENV, NO_CONFIG_DIR_ENV, PROFILE_ENV, REFRESH_INTERVAL_ENV,
+    REQUEST_TIMEOUT_ENV, RETIRED_KEY_FILE,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
-use std::net::IpAddr;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::Arc;
-use tokio::time::Duration;
-use tokio::{spawn, time};
-use tracing::{debug, error};
+use tokio::spawn;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, error, info_span, warn, Span};
+use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
 
-use shard::constants::DEFAULT_REFRESH_SECONDS;
-use shard::event::Event;
-use shard::network;
-use shard::protocol::Request;
+use shard::constants::{
+    DEFAULT_CHUNK_BYTES, DEFAULT_RATE_LIMIT_BURST, DEFAULT_REFRESH_SECONDS, DEFAULT_STATUS_SECONDS,
+    DEFAULT_TIMEOUT_SECONDS, DEFAULT_TOMBSTONE_SECONDS, DEFAULT_WATCH_SECONDS,
+    PROVIDER_EVENT_BUFFER,
+};
+use shard::network::{self, start_listeners, wait_for_listen_addrs, NetworkConfig};
+use shard::offline::{combine_from_files, refresh_dir, split_to_dir};
+#[cfg(feature = "sss16")]
+use shard::protocol::ShareField;
+use shard::protocol::{
+    FieldShare, RegisterShareStatus, RelayGrant, ShareIndex, StatShareStatus, TraceId,
+};
 use shard::provider::{
-    dao, execute_get_share, execute_refresh_share, execute_register_share, refresh_loop,
+    dao, now_unix, record_audit, reload_signals, scan_integrity, shutdown_signal, watch_config,
+    DaoOptions, DbBackend, ProviderEvent, RateLimit, ShardNode, SharedAudit, SharedDao,
+};
+use shard::repository::{
+    AuditLog, AuditOperation, AuditOutcome, DaoQuotas, EncryptionKey, FlushPolicy,
 };
+use shard::rotation::{rotate_owner, RotationReport, ROTATION_REPORT_FILE};
 use shard::sss::combine_shares;
-use shard::sss::generate_refresh_key;
+#[cfg(not(feature = "sss16"))]
+use shard::sss::insert_share;
 use shard::sss::split_secret;
+use shard::sss::{generate_refresh_key, Polynomial};
 
-#[derive(Debug, Parser)]
-#[command(name = "shard")]
-#[command(author = "lodge <jay.logelin@gmail.com>")]
-#[command(version = crate_version!())]
-#[command(
-    about = "SHARD (SHARD Holds And Refreshes (Discrete) Data))",
-    long_about = "SHARD (SHARD Holds And Refreshes (Discrete) Data) threshold network allows users to split secrets into shares, distribute them to share providers, and recombine them at a threshold to rebuild the secret. A node will provide shares to the shard, and refresh them automatically at a specified interval. It works by generating a new refresh key and then updating the shares across the network. The provider node persists all shares to a database, and will use the database on restart. Note that the database is in-memory by default, but can be set to a file-based database using the --db-path flag. Shares can only be retrieved or re-registered by the same client that registers the share with the network, identified by the client's peer ID, which is derived from their public key. Shares are automatically refreshed without changing the secret itself between share providers, enhancing the overall security of the network over time. The refresh interval is set using the --refresh-interval flag, and is set to 30 minutes by default. Default configuration is located at ~/.shard/conf.toml."
-)]
-enum CliArgument {
-    /// (Provider) Run a share provider node that provides shares to shard users, and refresh them automatically at a specified interval.
-    Provide {
-        /// use embedded database for persistence
-        /// otherwise use memory database
-        #[clap(long, short)]
-        db_path: Option<String>,
-
-        /// Share refresh interval in seconds
-        // #[clap(long, short, default_value_t = 60)]
-        #[clap(long, short)]
-        refresh_interval: Option<u64>,
-    },
-    /// (Client) Combine shares from the network to rebuild a secret.
-    Combine {
-        /// key of the share to get.
-        #[clap(long, short)]
-        key: String,
-
-        /// Share threshold, if none is provided, uses the number of shares
-        #[clap(long, short)]
-        threshold: Option<usize>,
-
-        /// Verbose mode displays the shares
-        #[clap(long, short)]
-        verbose: bool,
-    },
-    /// (Client) Split a secret into shares and propagate them across the network.
-    Split {
-        /// Share threshold.
-        #[clap(long, short)]
-        threshold: usize,
-
-        /// Number of shares to generate.
-        #[clap(long, short)]
-        shares: usize,
-
-        /// key to use to register shares for the secret
-        #[clap(long, short)]
-        key: Option<String>,
-
-        /// Secret to split.
-        #[clap(long)]
-        secret: String,
-
-        /// Verbose mode displays the shares
-        #[clap(long, short)]
-        verbose: bool,
-    },
-
-    /// (Client) Get the list of share providers for a secret.
-    Ls {
-        /// key of the secret.
-        #[clap(long, short)]
-        key: String,
-    },
-
-    /// (Client) Refresh the shares
-    Refresh {
-        /// key of the secret.
-        #[clap(long, short)]
-        key: String,
-
-        /// Share threshold.
-        #[clap(long, short)]
-        threshold: usize,
-
-        /// Key size.
-        #[clap(long, short)]
-        size: usize,
-    },
-}
-
-#[derive(Parser, Debug)]
-#[clap(name = "shard Threshold Network")]
-struct Opt {
-    /// Fixed value to generate deterministic peer ID.
-    #[clap(long, short)]
-    secret_key_seed: Option<u8>,
+/// The key seed every client used to share as its identity, kept for `--legacy-sender` so that
+/// shares registered before the node identity was persisted can still be reached.
+const LEGACY_SENDER_SEED: u8 = 42;
 
-    /// Address of a peer to connect to.
-    #[clap(long, short)]
-    peer: Option<Multiaddr>,
+/// What `split --interactive` prompts for.
+const SECRET_PROMPT: &str = "Secret to split";
 
-    /// Address to listen on.
-    #[clap(long, short)]
-    listen_address: Option<Multiaddr>,
+/// Creates a new file at `path` that only its owner can read and write, failing with
+/// `AlreadyExists` if there is one already, for rebuilt secrets and exported shares.
+fn create_private(path: &Path) -> std::io::Result<File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
 
-    /// If known, the external address of this node. Will be used to correctly advertise our external address across all transports.
-    #[clap(long, env)]
-    external_address: Option<IpAddr>,
+/// Writes a rebuilt secret to `out` byte for byte, or prints it in `format` if no file is given.
+///
+/// # Arguments
+/// * `secret` - The rebuilt secret.
+/// * `out` - The file to write the secret to. It is created only its owner can read, and must
+///   not exist yet.
+/// * `format` - How to print it.
+/// * `force` - Whether to print a raw secret to a terminal.
+/// * `stdout` - Where to print it.
+/// * `is_terminal` - Whether `stdout` is a terminal.
+///
+/// # Returns
+/// An error if the secret cannot be written, or is raw and would land on a terminal without
+/// `force`.
+fn output_secret(
+    secret: &[u8],
+    out: Option<&Path>,
+    format: SecretFormat,
+    force: bool,
+    stdout: &mut dyn Write,
+    is_terminal: bool,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = out {
+        create_private(path)
+            .and_then(|mut file| file.write_all(secret))
+            .map_err(|e| format!("cannot write the secret to {}: {}", path.display(), e))?;
+        writeln!(stdout, "🔑 secret written to {}", path.display())?;
+        return Ok(());
+    }
+    match format {
+        SecretFormat::Raw if is_terminal && !force => {
+            return Err(
+                "refusing to print a raw secret to a terminal; redirect stdout, use --out or pass \
+                 --force"
+                    .into(),
+            );
+        }
+        SecretFormat::Raw => stdout.write_all(secret)?,
+        SecretFormat::Hex => writeln!(stdout, "{}", hex::encode(secret))?,
+        SecretFormat::Base64 => writeln!(stdout, "{}", BASE64_STANDARD.encode(secret))?,
+        SecretFormat::Utf8 => match std::str::from_utf8(secret) {
+            Ok(text) => writeln!(stdout, "🔑 secret: {:#?}", text)?,
+            Err(_) => writeln!(
+                stdout,
+                "🔑 secret (not UTF-8, hex): {}",
+                hex::encode(secret)
+            )?,
+        },
+    }
+    stdout.flush()?;
+    Ok(())
+}
 
-    /// Subcommand to run.
-    #[clap(subcommand)]
-    argument: CliArgument,
+/// Prints a rebuilt secret as `combine` does: as a `CombineOutput` with `json`, or through
+/// `output_secret` otherwise.
+///
+/// # Arguments
+/// * `key` - The key the secret was registered under.
+/// * `secret` - The rebuilt secret.
+/// * `shares_used` - The number of shares it was rebuilt from.
+/// * `out` - The file to write the secret to.
+/// * `format` - How to print it without `json`.
+/// * `force` - Whether to print a raw secret to a terminal.
+/// * `json` - Whether to print the result as JSON.
+///
+/// # Returns
+/// An error if the secret cannot be written or printed.
+fn print_combined(
+    key: String,
+    secret: &[u8],
+    shares_used: usize,
+    out: Option<PathBuf>,
+    format: Option<SecretFormat>,
+    force: bool,
+    json: bool,
+) -> Result<(), Box<dyn Error>> {
+    if json {
+        if let Some(path) = &out {
+            // the JSON document stands in for the line telling where the secret went
+            let mut quiet = std::io::sink();
+            output_secret(
+                secret,
+                Some(path),
+                SecretFormat::Raw,
+                force,
+                &mut quiet,
+                false,
+            )?;
+        }
+        let output = CombineOutput {
+            key,
+            secret_b64: out.is_none().then(|| BASE64_STANDARD.encode(secret)),
+            out: out.map(|path| path.display().to_string()),
+            shares_used,
+            chunks: None,
+        };
+        println!("{}", to_json(&output)?);
+        return Ok(());
+    }
+    let stdout = std::io::stdout();
+    let is_terminal = stdout.is_terminal();
+    output_secret(
+        secret,
+        out.as_deref(),
+        format.unwrap_or_default(),
+        force,
+        &mut stdout.lock(),
+        is_terminal,
+    )
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let config = ShardConfig::new()?;    
-    let sender = get_sender();
-    debug!("sender ID: {}", sender);
+/// Generates a random key to register a secret under when none is given.
+fn random_key() -> String {
+    let mut key = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    hex::encode(key)
+}
 
-    let _ = tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .try_init();
+/// Prints the share files written by `split --offline` or `refresh --offline`.
+fn print_share_files(
+    output: &ShareFilesOutput,
+    verb: &str,
+    json: bool,
+) -> Result<(), Box<dyn Error>> {
+    if json {
+        println!("{}", to_json(output)?);
+        return Ok(());
+    }
+    println!(
+        "📝 {} {} share files of {} at threshold {}, epoch {}:",
+        verb,
+        output.files.len(),
+        output.key,
+        output.threshold,
+        output.epoch
+    );
+    for file in &output.files {
+        println!("  {}", file);
+    }
+    Ok(())
+}
 
-    let opt = Opt::parse();
+/// Runs `split`, `combine` or `refresh` with `--offline`, on share files alone.
+///
+/// # Arguments
+/// * `argument` - The command, which must have `--offline` set.
+/// * `json` - Whether to print the result as JSON.
+///
+/// # Returns
+/// An error if the share files cannot be written, read or combined.
+fn run_offline(argument: CliArgument, json: bool) -> Result<(), Box<dyn Error>> {
+    match argument {
+        CliArgument::Split {
+            threshold,
+            shares,
+            secret,
+            secret_file,
+            interactive,
+            trim_newline,
+            key,
+            out_dir,
+            ..
+        } => {
+            let out_dir = out_dir.expect("--out-dir is required with --offline");
+            let secret = match interactive {
+                true => prompt::confirmed_secret(SECRET_PROMPT)?,
+                false => read_secret(
+                    secret,
+                    secret_file.as_deref(),
+                    trim_newline,
+                    std::io::stdin(),
+                )?,
+            };
+            let key = key.unwrap_or_else(random_key);
+            let files = split_to_dir(&secret, &key, threshold, shares, &out_dir)?;
+            let output = ShareFilesOutput {
+                key,
+                threshold,
+                epoch: 0,
+                files: files.iter().map(|p| p.display().to_string()).collect(),
+            };
+            print_share_files(&output, "Wrote", json)
+        }
+        CliArgument::Combine {
+            key,
+            out,
+            format,
+            force,
+            from,
+            ..
+        } => {
+            let (found, threshold, secret) = combine_from_files(&from)?;
+            if let Some(key) = key.filter(|key| *key != found) {
+                return Err(CliError::new(
+                    ErrorKind::Usage,
+                    format!("the share files are of {}, not {}", found, key),
+                )
+                .into());
+            }
+            print_combined(found, &secret, threshold, out, format, force, json)
+        }
+        CliArgument::Refresh { from, .. } => {
+            let dir = from.expect("--from is required with --offline");
+            let files = refresh_dir(&dir)?;
+            let (key, threshold) = files
+                .manifest
+                .map(|manifest| (manifest.key, manifest.threshold))
+                .unwrap_or_default();
+            let output = ShareFilesOutput {
+                key,
+                threshold,
+                epoch: files.shares.first().map_or(0, |(_, share)| share.epoch),
+                files: (files.shares.iter())
+                    .map(|(path, _)| path.display().to_string())
+                    .collect(),
+            };
+            print_share_files(&output, "Refreshed", json)
+        }
+        _ => unreachable!("only split, combine and refresh run offline"),
+    }
+}
 
-    let (mut network_client, mut network_events, network_event_loop, local_peer_id) =
-        network::new(opt.secret_key_seed).await?;
+/// Picks the identity client commands run as, which owns the shares they register.
+///
+/// # Arguments
+/// * `secret_key_seed` - The `--secret-key-seed` given, if any, which takes precedence.
+/// * `legacy_sender` - Whether to run as the identity every client shared before this one existed.
+/// * `config` - The configuration whose persisted key is used otherwise, generated on first use,
+///   or whose key is read from the environment with `--no-config`.
+///
+/// # Returns
+/// The keypair to run the swarm with, or an error if there is neither a seed nor a key to take it
+/// from.
+fn client_identity(
+    secret_key_seed: Option<u8>,
+    legacy_sender: bool,
+    config: &ShardConfig,
+) -> Result<Keypair, Box<dyn Error>> {
+    if legacy_sender {
+        warn!("--legacy-sender is deprecated, move the shares to your own identity.");
+        return Ok(network::seeded_keypair(LEGACY_SENDER_SEED));
+    }
+    match secret_key_seed {
+        Some(seed) => Ok(network::seeded_keypair(seed)),
+        None => Ok(config.key_or_generate()?),
+    }
+}
 
-    // Spawn the network task for it to run in the background.
-    spawn(network_event_loop.run(opt.external_address));
+/// Loads the configuration a command runs with: the selected profile of the configuration
+/// directory or, with `--no-config` or `NO_CONFIG_DIR_ENV`, the environment alone.
+///
+/// # Arguments
+/// * `opt` - The command line options.
+///
+/// # Returns
+/// The configuration, and whether it was selected explicitly rather than by default: with
+/// `--config`, `--profile` or their environment variables, or by a key in the environment.
+fn load_config(opt: &Opt) -> Result<(ShardConfig, bool), Box<dyn Error>> {
+    let explicit = opt.config.is_some() || opt.profile.is_some();
+    if opt.no_config || (!explicit && env_flag(NO_CONFIG_DIR_ENV)?) {
+        let config = ShardConfig::from_env()?;
+        let given = config.env_key.is_some();
+        return Ok((config, given));
+    }
+    let config_env = env_setting::<PathBuf>(CONFIG_DIR_ENV)?;
+    let profile = resolve(opt.profile.clone(), env_setting(PROFILE_ENV)?, None, None);
+    let dir = ShardConfig::resolve_dir(opt.config.clone())?;
+    let name = profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+    let config = ShardConfig::load_profile(&dir, name)?;
+    Ok((
+        config,
+        explicit || config_env.is_some() || profile.is_some(),
+    ))
+}
 
-    // In case a listen address was provided use it, otherwise listen on any
-    // address.
-    match opt.listen_address {
-        Some(addr) => network_client
-            .start_listening(addr)
-            .await
-            .expect("Listening not to fail."),
-        None => network_client
-            .start_listening("/ip4/0.0.0.0/tcp/0".parse()?)
-            .await
-            .expect("Listening not to fail."),
-    };
+/// Lists the profiles of the configuration directory `dir` with the peer id of their identity
+/// key, without creating any profile or key.
+///
+/// # Returns
+/// The profiles, or an error if they cannot be listed or a key file is corrupt.
+fn list_profiles(dir: &Path) -> Result<ProfilesOutput, Box<dyn Error>> {
+    let mut profiles = Vec::new();
+    for name in ShardConfig::profiles(dir)? {
+        let key = ShardConfig::profile_key(dir, &name)?;
+        profiles.push(ProfileListing {
+            dir: ShardConfig::profile_dir(dir, &name)?.display().to_string(),
+            peer_id: key.map(|key| key.public().to_peer_id().to_string()),
+            name,
+        });
+    }
+    Ok(ProfilesOutput { profiles })
+}
 
-    /*
-    if let Some(addr) = config.bootstrapper {
-        let Some(Protocol::P2p(peer_id)) = addr.iter().last() else {
-            return Err("Expect peer multiaddr to contain peer ID.".into());
-        };
+/// Prints the profiles listed by `profile list`, one per line with the peer id of its key.
+fn print_profiles(output: &ProfilesOutput, out: &mut dyn Write) -> std::io::Result<()> {
+    if output.profiles.is_empty() {
+        writeln!(out, "👤 No profiles found.")?;
+    } else {
+        writeln!(out, "👤 {} profiles:", output.profiles.len())?;
+    }
+    for profile in &output.profiles {
+        match &profile.peer_id {
+            Some(peer_id) => writeln!(out, "  {}: {}", profile.name, peer_id)?,
+            None => writeln!(out, "  {}: no identity key yet", profile.name)?,
+        }
+    }
+    Ok(())
+}
 
-        // if the peer is the same as the local peer, don't dial
-        if peer_id != local_peer_id {
-            debug!("👢 Bootstrapping to peer at {}.", addr);
-            network_client
-                .dial(peer_id, addr)
-                .await
-                .expect("Dial to succeed");
+/// Runs `config show` or `config set`.
+///
+/// # Arguments
+/// * `config` - The configuration loaded, which `set` changes and saves.
+/// * `command` - The subcommand run.
+/// * `json` - Whether to print the configuration as JSON rather than as text.
+/// * `out` - Where to print.
+///
+/// # Returns
+/// An error if the setting is invalid or the configuration cannot be saved.
+fn configure(
+    mut config: ShardConfig,
+    command: &ConfigCommand,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<(), Box<dyn Error>> {
+    if let ConfigCommand::Set { key, value } = command {
+        config.update(|config| config.set(key, value))?;
+    }
+    if json {
+        writeln!(out, "{}", to_json(&config)?)?;
+        return Ok(());
+    }
+    let file = config.dir.join(CONFIG_FILE);
+    match (command, config.from_env) {
+        (ConfigCommand::Set { key, .. }, _) => {
+            writeln!(out, "✅ Set {} in {}", key, file.display())?;
+            return Ok(());
         }
+        (ConfigCommand::Show, true) => writeln!(out, "# read from the SHARD_ variables")?,
+        (ConfigCommand::Show, false) => writeln!(out, "# {}", file.display())?,
     }
-     */
+    write!(out, "{}", toml::to_string_pretty(&config)?)?;
+    Ok(())
+}
 
-    // In case the user provided an address of a peer on the CLI, dial it.
-    if let Some(addr) = opt.peer {
-        debug!("Dialing peer at {}.", addr);
-        let Some(Protocol::P2p(peer_id)) = addr.iter().last() else {
-            return Err("Expect peer multiaddr to contain peer ID.".into());
-        };
-        network_client
-            .dial(peer_id, addr)
-            .await
-            .expect("Dial to succeed");
-    } else if let Some(addr) = config.bootstrapper {
-        let Some(Protocol::P2p(peer_id)) = addr.iter().last() else {
-            return Err("Expect peer multiaddr to contain peer ID.".into());
-        };
+/// Fills in the database and refresh interval of a provider left out on the command line, from
+/// the environment, then the configuration, then the built-in default, with `resolve`.
+///
+/// # Arguments
+/// * `db_path` - The `--db-path` given, replaced by the one resolved.
+/// * `refresh_interval` - The `--refresh-interval` given, replaced by the one resolved.
+/// * `in_memory` - Whether the shares are asked to be kept in memory, which a database path from
+///   the environment or the configuration does not change.
+/// * `config` - The configuration loaded.
+///
+/// # Returns
+/// An error if the environment holds a setting that does not parse.
+fn resolve_provider_settings(
+    db_path: &mut Option<String>,
+    refresh_interval: &mut Option<u64>,
+    in_memory: bool,
+    config: &ShardConfig,
+) -> Result<(), Box<dyn Error>> {
+    let (env_db_path, file_db_path) = match in_memory {
+        true => (None, None),
+        false => (env_setting(DB_PATH_ENV)?, config.db_path.clone()),
+    };
+    *db_path = resolve(db_path.take(), env_db_path, file_db_path, None);
+    *refresh_interval = resolve(
+        refresh_interval.take(),
+        env_setting(REFRESH_INTERVAL_ENV)?,
+        config.refresh_interval_secs,
+        Some(DEFAULT_REFRESH_SECONDS),
+    );
+    Ok(())
+}
 
-        // if the peer is the same as the local peer, don't dial
-        if peer_id != local_peer_id {
-            debug!("👢 Bootstrapping to peer at {}.", addr);
-            network_client
-                .dial(peer_id, addr)
-                .await
-                .expect("Dial to succeed");
+/// Generates the identity key persisted in the configuration directory or, with `show`, reads it.
+///
+/// # Arguments
+/// * `config` - The configuration whose directory holds the key.
+/// * `force` - Whether to replace a key persisted already.
+/// * `show` - Whether to read the persisted key instead of generating one.
+///
+/// # Returns
+/// The peer id of the key and the file it is persisted in, or an error if a key exists already
+/// without `force`, or none exists with `show`.
+fn keygen(config: &ShardConfig, force: bool, show: bool) -> Result<KeygenOutput, Box<dyn Error>> {
+    let key_file = config.dir.join(KEY_FILE);
+    let key = match show {
+        true => config
+            .key()?
+            .ok_or_else(|| format!("no identity key in {}", config.dir.display()))?,
+        false if !force && key_file.exists() => {
+            return Err(CliError::new(
+                ErrorKind::Usage,
+                format!(
+                    "an identity key already exists in {}, pass --force to replace it",
+                    key_file.display()
+                ),
+            )
+            .into())
         }
+        false => config.generate_key()?,
+    };
+    Ok(KeygenOutput {
+        peer_id: key.public().to_peer_id().to_string(),
+        key_file: key_file.display().to_string(),
+        generated: !show,
+    })
+}
+
+/// Prints shares hex-encoded, one per line, ordered by value.
+fn print_shares(shares: &HashMap<ShareIndex, Vec<u8>>, out: &mut dyn Write) -> std::io::Result<()> {
+    writeln!(out, "🐛 shares: ")?;
+    let mut items: Vec<_> = shares.iter().collect();
+    items.sort_by(|a, b| a.1.cmp(b.1));
+    for (_, value) in items {
+        writeln!(out, "  {}", hex::encode(value))?;
     }
+    Ok(())
+}
 
-    debug!("Waiting for network to be ready...");
+/// The shares of a secret by index, with the field it was split over, which decides how they are
+/// combined.
+///
+/// # Fields
+/// * `field` - The field the secret was split over. Only with the `sss16` feature.
+/// * `shares` - The shares, by index.
+#[derive(Debug, Default)]
+struct SplitShares {
+    #[cfg(feature = "sss16")]
+    field: ShareField,
+    shares: HashMap<ShareIndex, Vec<u8>>,
+}
 
-    match opt.argument {
-        // Providing a share.
-        CliArgument::Provide {
-            db_path,
-            refresh_interval,
-        } => {
-            // check if the db_path is set, if so use sled, otherwise use HashMap
-            let dao = dao(db_path).unwrap();
-
-            // check if refresh is set, if not use a default of 30 minutes
-            let refresh = refresh_interval.unwrap_or(DEFAULT_REFRESH_SECONDS);
-            debug!("Using refresh_seconds: {}", refresh);
-
-            // spawn a refresh task to run every refresh_seconds seconds
-            let dao_clone = Arc::clone(&dao);
-            let mut network_client_clone = network_client.clone();
-            spawn(async move {
-                let mut interval = time::interval(Duration::from_secs(refresh));
-                refresh_loop(
-                    &mut interval,
-                    dao_clone,
-                    &mut network_client_clone,
-                    local_peer_id,
-                )
-                .await;
+impl SplitShares {
+    /// Splits `secret` into `shares` shares, over GF(2^16) when there are more of them than
+    /// GF(2^8) holds and the `sss16` feature is enabled, and over GF(2^8) otherwise.
+    ///
+    /// # Returns
+    /// The shares, or an error if the threshold or the number of shares is invalid.
+    fn split(secret: &[u8], threshold: usize, shares: usize) -> Result<Self, shard::sss::Error> {
+        #[cfg(feature = "sss16")]
+        if shares > usize::from(ShareField::Gf256.max_index()) {
+            return Ok(SplitShares {
+                field: ShareField::Gf65536,
+                shares: shard::sss16::split_secret(secret, threshold, shares)?,
             });
+        }
+        // the identity without the `sss16` feature
+        #[allow(clippy::useless_conversion)]
+        let shares = split_secret(secret, threshold, shares)?
+            .into_iter()
+            .map(|(index, share)| (index.into(), share))
+            .collect();
+        Ok(SplitShares {
+            #[cfg(feature = "sss16")]
+            field: ShareField::Gf256,
+            shares,
+        })
+    }
 
-            loop {
-                match network_events.next().await {
-                    // Reply with the content of the file on incoming requests.
-                    Some(Event::InboundRequest { request, channel }) => match request {
-                        Request::RegisterShare(req) => {
-                            let sender = PeerId::from_bytes(&req.sender).unwrap();
-                            execute_register_share(
-                                &req.key,
-                                &sender,
-                                req.share,
-                                req.threshold,
-                                channel,
-                                &dao,
-                                &mut network_client,
-                            )
-                            .await?;
-                        }
-                        Request::GetShare(req) => {
-                            let sender = PeerId::from_bytes(&req.sender).unwrap();
-                            execute_get_share(
-                                &req.key,
-                                &sender,
-                                channel,
-                                &dao,
-                                &mut network_client,
-                            )
-                            .await?;
-                        }
-                        Request::RefreshShare(req) => {
-                            let sender = PeerId::from_bytes(&req.sender).unwrap();
-                            execute_refresh_share(
-                                &req.key,
-                                &sender,
-                                &req.refresh_key,
-                                Some(channel),
-                                &dao,
-                                &mut network_client,
-                            )
-                            .await?;
-                        }
-                    },
-                    e => debug!("unhandled client event: {e:?}"),
-                }
-            }
+    /// The share held under `index`, with the field the secret was split over.
+    fn get(&self, index: ShareIndex) -> FieldShare {
+        FieldShare {
+            share: (index, self.shares.get(&index).cloned().unwrap_or_default()),
+            #[cfg(feature = "sss16")]
+            field: self.field,
         }
+    }
 
-        // Locating and getting a share.
-        CliArgument::Combine {
-            key,
-            threshold,
-            verbose,
-        } => {
-            // sleep for a bit to give the network time to bootstrap
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    /// Adds a share fetched to those to combine.
+    ///
+    /// # Returns
+    /// An error if a different share is held under its index already.
+    #[cfg(not(feature = "sss16"))]
+    fn insert(&mut self, share: FieldShare) -> Result<(), shard::sss::Error> {
+        let (index, share) = share.share;
+        insert_share(&mut self.shares, index, share)
+    }
 
-            debug!("Looking for providers of share {}...", key);
-            // Locate all nodes providing the share.
-            let providers = network_client.get_providers(key.clone()).await;
-            if providers.is_empty() {
-                return Err(format!("Could not find providers for share key: {key}.").into());
+    /// Adds a share fetched to those to combine.
+    ///
+    /// # Returns
+    /// An error if a different share is held under its index already, or if the share was split
+    /// over a different field than those held.
+    #[cfg(feature = "sss16")]
+    fn insert(&mut self, share: FieldShare) -> Result<(), shard::sss::Error> {
+        let FieldShare {
+            share: (index, share),
+            field,
+        } = share;
+        if self.shares.is_empty() {
+            self.field = field;
+        } else if field != self.field {
+            return Err(shard::sss::Error::InconsistentShares);
+        }
+        match self.shares.get(&index) {
+            Some(held) if *held != share => Err(match field {
+                ShareField::Gf256 => u8::try_from(index).map_or(
+                    shard::sss::Error::DuplicateWideIndex(index),
+                    shard::sss::Error::DuplicateIndex,
+                ),
+                ShareField::Gf65536 => shard::sss::Error::DuplicateWideIndex(index),
+            }),
+            Some(_) => Ok(()),
+            None => {
+                self.shares.insert(index, share);
+                Ok(())
             }
+        }
+    }
 
-            debug!("Found {} providers for share {}.", providers.len(), key);
-            // get the threshold number of shares, if threshold is None, use the number of providers
-            let threshold = threshold.unwrap_or_else(|| providers.len());
-
-            // Request a share from each node.
-            let requests = providers.into_iter().map(|p| {
-                let mut network_client = network_client.clone();
-                let name = key.clone();
-                async move { network_client.request_share(p, name, sender).await }.boxed()
-            });
+    /// Combines the shares.
+    ///
+    /// # Returns
+    /// The secret, or an error if the shares cannot be combined.
+    #[cfg(not(feature = "sss16"))]
+    fn combine(&self, key: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        combine_shares(&self.shares).map_err(|e| uncombinable(key, e))
+    }
 
-            debug!("Requesting share from providers.");
+    /// Combines the shares over the field they were split over.
+    ///
+    /// # Returns
+    /// The secret, or an error if the shares cannot be combined.
+    #[cfg(feature = "sss16")]
+    fn combine(&self, key: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        match self.field {
+            ShareField::Gf256 => {
+                let shares = self
+                    .shares
+                    .iter()
+                    .map(|(&index, share)| Ok((u8::try_from(index)?, share.clone())))
+                    .collect::<Result<HashMap<u8, Vec<u8>>, std::num::TryFromIntError>>()
+                    .map_err(|_| uncombinable(key, shard::sss::Error::InvalidIndex))?;
+                combine_shares(&shares).map_err(|e| uncombinable(key, e))
+            }
+            ShareField::Gf65536 => {
+                shard::sss16::combine_shares(&self.shares).map_err(|e| uncombinable(key, e))
+            }
+        }
+    }
+}
 
-            let rng = &mut rand::thread_rng();
-            let sample = requests.choose_multiple(rng, threshold);
+/// Reads the secret to split from the one source given on the command line.
+///
+/// # Arguments
+/// * `secret` - The value of `--secret`; `-` reads the secret from `stdin`.
+/// * `secret_file` - The value of `--secret-file`.
+/// * `trim_newline` - Whether to drop one trailing newline from the secret, which is otherwise
+///   kept byte for byte.
+/// * `stdin` - The standard input to read `--secret -` from.
+///
+/// # Returns
+/// The secret's bytes, or an error if not exactly one source is given, it cannot be read or the
+/// secret is empty.
+fn read_secret(
+    secret: Option<SecretArg>,
+    secret_file: Option<&Path>,
+    trim_newline: bool,
+    mut stdin: impl Read,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut bytes = match (secret, secret_file) {
+        (Some(SecretArg(secret)), None) if secret == "-" => {
+            let mut bytes = Vec::new();
+            stdin.read_to_end(&mut bytes)?;
+            bytes
+        }
+        (Some(SecretArg(secret)), None) => secret.into_bytes(),
+        (None, Some(path)) => std::fs::read(path)
+            .map_err(|e| format!("cannot read the secret from {}: {}", path.display(), e))?,
+        _ => {
+            return Err(CliError::new(
+                ErrorKind::Usage,
+                "exactly one of --secret or --secret-file is required",
+            )
+            .into())
+        }
+    };
+    if trim_newline {
+        if bytes.ends_with(b"\r\n") {
+            bytes.truncate(bytes.len() - 2);
+        } else if bytes.ends_with(b"\n") {
+            bytes.pop();
+        }
+    }
+    if bytes.is_empty() {
+        return Err("the secret is empty".into());
+    }
+    Ok(bytes)
+}
 
-            let shares = futures::future::join_all(sample).await;
-            let u: Vec<&(u8, Vec<u8>)> = shares
-                .iter()
-                .map(|r| {
-                    if let Err(e) = r {
-                        error!("Error: {:?}", e);
-                    }
-                    r.as_ref().unwrap()
-                })
-                .collect();
+/// Opens the provider's audit log from the `provide` command line options, if one is configured.
+fn open_audit(path: Option<&str>, retention: Option<u64>) -> Result<SharedAudit, Box<dyn Error>> {
+    path.map(|path| Ok(Arc::new(AuditLog::open(path)?.with_retention(retention))))
+        .transpose()
+}
 
-            // create a shares map for combining
-            let shares_map: HashMap<u8, Vec<u8>> = u.iter().map(|(k, v)| (*k, v.clone())).collect();
-            shares.iter().for_each(|r| {
-                if let Err(e) = r {
-                    error!("Error: {:?}", e);
-                }
-                debug!("Received r: {:?}", r.as_ref().unwrap());
-            });
+/// Describes the provider's share database from the `provide` command line options.
+#[allow(clippy::too_many_arguments)]
+fn dao_options(
+    db_path: Option<String>,
+    backend: Option<DbBackend>,
+    encryption_key_file: Option<PathBuf>,
+    flush_policy: FlushPolicy,
+    snapshot_path: Option<PathBuf>,
+    quotas: DaoQuotas,
+    read_only: bool,
+    isolate_owners: bool,
+) -> Result<DaoOptions, Box<dyn Error>> {
+    let encryption_key = encryption_key_file
+        .map(|path| EncryptionKey::load_or_create(&path))
+        .transpose()?;
+    Ok(DaoOptions {
+        backend,
+        db_path,
+        encryption_key,
+        flush_policy,
+        snapshot_path,
+        quotas,
+        read_only,
+        isolate_owners,
+    })
+}
 
-            let secret = combine_shares(&shares_map);
-            debug!("Received shares: {:?}", &shares);
+/// Opens the share database described by the command line options.
+#[allow(clippy::too_many_arguments)]
+fn open_dao(
+    db_path: Option<String>,
+    backend: Option<DbBackend>,
+    encryption_key_file: Option<PathBuf>,
+    flush_policy: FlushPolicy,
+    snapshot_path: Option<PathBuf>,
+    quotas: DaoQuotas,
+    read_only: bool,
+    isolate_owners: bool,
+) -> Result<SharedDao, Box<dyn Error>> {
+    dao(dao_options(
+        db_path,
+        backend,
+        encryption_key_file,
+        flush_policy,
+        snapshot_path,
+        quotas,
+        read_only,
+        isolate_owners,
+    )?)
+    .map_err(Into::into)
+}
 
-            // if the debug flag is set, print the shares
-            if verbose {
-                println!("🐛 shares: ");
-                let mut items: Vec<_> = shares_map.iter().collect();
-                items.sort_by(|a, b| a.1.cmp(b.1));
+/// How providers register the shares of a split.
+///
+/// # Fields
+/// * `threshold` - The number of shares needed to rebuild the secret.
+/// * `ttl` - The lifetime of the shares in seconds.
+/// * `recreate` - Whether to register shares providers deleted recently.
+/// * `refresh_every` - How often providers refresh the shares, in seconds.
+/// * `replace` - Whether to replace shares held under the key with a different layout.
+#[derive(Debug, Clone, Copy)]
+struct ShareOptions {
+    threshold: usize,
+    ttl: Option<u64>,
+    recreate: bool,
+    refresh_every: Option<u64>,
+    replace: bool,
+}
 
-                // Now items is sorted by key, and you can iterate over it to get the values in order
-                for (_, value) in items {
-                    println!("  {}", hex::encode(value));
-                }
-            }
-            
-            let secret = secret.expect("Unable to combine shares at threshold");
-            let secret_string = String::from_utf8(secret).unwrap_or_else(|_| {
-                format!("Error: Unable to combine shares at threshold")
-            });
+/// Resolves the providers given with `--provider`, dialing those given by address.
+///
+/// # Returns
+/// The peer ids of the providers, each once and in the order given, or an error naming a
+/// provider that could not be dialed.
+async fn pin_providers(
+    network_client: &mut Client,
+    pinned: Vec<ProviderArg>,
+) -> Result<Vec<PeerId>, Box<dyn Error>> {
+    let mut peers = Vec::new();
+    for provider in pinned {
+        let peer = provider.peer_id();
+        if let ProviderArg::Addr(_, addr) = provider {
+            network_client
+                .dial(peer, addr.clone())
+                .await
+                .map_err(|e| format!("cannot dial pinned provider {}: {}", addr, e))?;
+        }
+        if !peers.contains(&peer) {
+            peers.push(peer);
+        }
+    }
+    Ok(peers)
+}
 
-            println!("🔑 secret: {:#?}", secret_string);
+/// Selects the `shares` providers to place the shares of a secret on. Pinned providers are the
+/// only ones used, and are never stood in for; otherwise they are sampled from those available,
+/// and the rest kept to take the shares of providers out of storage.
+///
+/// # Arguments
+/// * `available` - The providers found on the network.
+/// * `pinned` - The providers given with `--provider`.
+/// * `shares` - The number of shares.
+///
+/// # Returns
+/// The providers to place the shares on and the spare providers, or an error if there are too
+/// few providers.
+fn choose_providers(
+    available: HashSet<PeerId>,
+    pinned: &[PeerId],
+    shares: usize,
+) -> Result<(Vec<PeerId>, Vec<PeerId>), CliError> {
+    let rng = &mut rand::thread_rng();
+    if !pinned.is_empty() {
+        if pinned.len() < shares {
+            return Err(CliError::new(
+                ErrorKind::Usage,
+                format!(
+                    "{} providers were pinned for {} shares, pin at least as many providers as shares",
+                    pinned.len(),
+                    shares
+                ),
+            ));
         }
+        let sample = pinned.iter().copied().choose_multiple(rng, shares);
+        return Ok((sample, Vec::new()));
+    }
 
-        // Splitting a secret.
-        CliArgument::Split {
-            threshold,
-            shares,
-            secret,
-            key,
-            verbose,
-        } => {
-            // sleep for a bit to give the network time to bootstrap
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    if available.is_empty() {
+        return Err(CliError::new(
+            ErrorKind::NoProviders,
+            "Could not find providers.",
+        ));
+    }
+    // check that there are the correct number of providers
+    if available.len() < shares {
+        return Err(CliError::new(
+            ErrorKind::QuorumNotMet,
+            format!(
+                "Not enough providers ({}) to accomodate shares. Wait for more providers to join",
+                available.len()
+            ),
+        ));
+    }
+    debug!("*** Found {} providers.", available.len());
 
-            // if key is None assign a random key
-            let key = key.unwrap_or_else(|| {
-                let mut rng = rand::thread_rng();
-                let mut key = [0u8; 32];
-                rng.fill_bytes(&mut key);
-                hex::encode(key)
-            });
+    // select shares number of providers
+    let sample = available.iter().copied().choose_multiple(rng, shares);
+    // providers left out of the sample take the shares of providers out of storage
+    let spares = available
+        .into_iter()
+        .filter(|p| !sample.contains(p))
+        .collect();
+    Ok((sample, spares))
+}
 
-            let split_shares = split_secret(secret.as_bytes(), threshold, shares)?;
-            debug!("Shares: {:?}", split_shares);
-            // Locate all nodes providing the share.
-            let providers = network_client.get_all_providers().await;
-            if providers.is_empty() {
-                return Err(format!("Could not find providers.").into());
-            }
-            // check that there are the correct number of providers
-            if providers.len() < shares {
-                return Err(format!(
-                    "Not enough providers ({}) to accomodate shares. Wait for more providers to join", providers.len()
-                )
-                .into());
-            }
+/// Finds the providers to place the `shares` shares of a split on: the pinned ones, or those
+/// found on the network once there are `shares` of them. A dry run settles for the providers
+/// found when `timeout` passes, so that it can report a plan short of providers.
+///
+/// # Arguments
+/// * `network_client` - The client to look the providers up with.
+/// * `ready` - What the network must reach before providers are looked up.
+/// * `pinned` - The providers given with `--provider`, already dialed.
+/// * `shares` - The number of shares.
+/// * `timeout` - How long to wait for the providers.
+/// * `dry_run` - Whether to settle for fewer providers than shares.
+///
+/// # Returns
+/// The providers found, or an error if the network was not ready in time.
+async fn discover_split_providers(
+    network_client: &mut Client,
+    ready: &ReadyCriteria,
+    pinned: &[PeerId],
+    shares: usize,
+    timeout: Duration,
+    dry_run: bool,
+) -> Result<HashSet<PeerId>, Box<dyn Error>> {
+    // pinned providers are used as they are, without looking for others
+    let criteria = match pinned.is_empty() {
+        true => ReadyCriteria {
+            min_providers: shares,
+            ..ready.clone()
+        },
+        false => ready.clone(),
+    };
+    match network_client.await_ready(&criteria, timeout).await {
+        Ok(available) => Ok(available),
+        Err(NotReady::Providers { .. }) if dry_run => {
+            Ok(lookup_providers(network_client.get_all_providers(), timeout).await?)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
 
-            debug!("*** Found {} providers.", providers.len());
+/// Plans a split without registering anything, selecting the providers with
+/// `choose_providers` as the split itself does.
+///
+/// # Arguments
+/// * `key` - The key the shares would be registered under.
+/// * `threshold` - The number of shares needed to rebuild the secret.
+/// * `shares` - The number of shares.
+/// * `share_bytes` - The length of each share.
+/// * `chunks` - The number of chunks a file would be split into.
+/// * `available` - The providers found on the network.
+/// * `pinned` - The providers given with `--provider`.
+///
+/// # Returns
+/// The plan, and the error the split would fail with if it is not feasible.
+fn plan_split(
+    key: &str,
+    threshold: usize,
+    shares: usize,
+    share_bytes: u64,
+    chunks: Option<u64>,
+    available: HashSet<PeerId>,
+    pinned: &[PeerId],
+) -> (SplitPlanOutput, Option<CliError>) {
+    let discovered = match pinned.is_empty() {
+        true => available.len(),
+        false => pinned.len(),
+    };
+    let (selected, spares, error) = match choose_providers(available, pinned, shares) {
+        Ok((selected, spares)) => (selected, spares, None),
+        Err(e) => (Vec::new(), Vec::new(), Some(e)),
+    };
+    let plan = SplitPlanOutput {
+        key: key.to_string(),
+        threshold,
+        shares,
+        share_bytes,
+        chunks,
+        discovered,
+        pinned: !pinned.is_empty(),
+        providers: peer_ids(selected),
+        spares: peer_ids(spares),
+        feasible: error.is_none(),
+        reason: error.as_ref().map(|e| e.to_string()),
+    };
+    (plan, error)
+}
 
-            // select shares number of providers
-            let rng = &mut rand::thread_rng();
-            let providers_sample = providers.into_iter().choose_multiple(rng, shares);
+/// Prints the plan of `split --dry-run`. Why an infeasible plan would fail is left to the error
+/// the command exits with.
+fn print_split_plan(plan: &SplitPlanOutput, out: &mut dyn Write) -> std::io::Result<()> {
+    writeln!(out, "📝 Dry run, nothing was registered.")?;
+    writeln!(out, "    key: {:?}", plan.key)?;
+    writeln!(
+        out,
+        "    threshold: {} of {} shares of {} bytes",
+        plan.threshold, plan.shares, plan.share_bytes
+    )?;
+    if let Some(chunks) = plan.chunks {
+        writeln!(out, "    chunks: {}", chunks)?;
+    }
+    match plan.pinned {
+        true => writeln!(out, "    providers pinned: {}", plan.discovered)?,
+        false => writeln!(out, "    providers found: {}", plan.discovered)?,
+    }
+    for (title, providers) in [("selected", &plan.providers), ("spares", &plan.spares)] {
+        if !providers.is_empty() {
+            writeln!(out, "    {}:", title)?;
+        }
+        for provider in providers {
+            writeln!(out, "      {}", provider)?;
+        }
+    }
+    if plan.feasible {
+        writeln!(out, "✅ Every share can be placed.")?;
+    }
+    Ok(())
+}
 
-            // make sure to only send shares to only shares number of providers
-            let requests = providers_sample
-                .clone()
-                .into_iter()
-                .enumerate()
-                .map(|(i, p)| {
-                    let mut network_client = network_client.clone();
-                    let share_id = (i + 1) as u8;
-                    let share = split_shares.get(&share_id).ok_or("Share not found");
-                    let k = &key;
-                    async move {
+/// Registers share `i` of a secret split under `key` with the `i`-th of `providers`, counting
+/// from 1, all at once. A provider out of storage hands its share on to one of `spares`.
+///
+/// # Arguments
+/// * `network_client` - The client to send the registrations with.
+/// * `owner` - The identity key of the owner of the shares, which grants every provider it places
+///   a share on to relay refreshes of it.
+/// * `key` - The key to register the shares under.
+/// * `shares` - The shares of the secret, by index.
+/// * `providers` - The providers to place the shares on.
+/// * `spares` - The providers to place a share on instead when one is out of storage.
+/// * `options` - How providers register the shares.
+///
+/// # Returns
+/// The index of every share placed with the provider holding it, and what every provider asked
+/// answered.
+async fn register_shares(
+    network_client: &Client,
+    owner: &Keypair,
+    key: &str,
+    shares: &SplitShares,
+    providers: &[PeerId],
+    spares: Vec<PeerId>,
+    options: ShareOptions,
+) -> (Vec<(ShareIndex, PeerId)>, Vec<ProviderOutcome>) {
+    let sender = owner.public().to_peer_id();
+    let spares = Arc::new(std::sync::Mutex::new(spares));
+    let requests = providers.iter().enumerate().map(|(i, &p)| {
+        let mut network_client = network_client.clone();
+        let share_id = (i + 1) as ShareIndex;
+        let share = shares.get(share_id);
+        let spares = Arc::clone(&spares);
+        async move {
+            let mut peer = p;
+            let mut outcomes = Vec::new();
+            let mut outcome = |peer: PeerId, status, reason| {
+                outcomes.push(ProviderOutcome {
+                    peer: peer.to_string(),
+                    status,
+                    reason,
+                })
+            };
+            loop {
+                let status = match RelayGrant::signed(key, peer, owner) {
+                    Ok(grant) => {
                         network_client
                             .request_register_share(
-                                (share_id, share.unwrap().to_vec()),
-                                k.to_string(),
-                                threshold as u64,
-                                p,
+                                share.clone(),
+                                key.to_string(),
+                                options.threshold as u64,
+                                options.ttl,
+                                options.recreate,
+                                options.refresh_every,
+                                options.replace,
+                                Some(grant),
+                                peer,
                                 sender,
                             )
                             .await
                     }
-                    .boxed()
-                });
-
-            // Await all of the requests and ensure they all succee
-            futures::future::join_all(requests)
-                .await
-                .iter()
-                .for_each(|r| {
-                    if let Err(e) = r {
+                    Err(e) => Err(e.into()),
+                };
+                match status {
+                    Ok(RegisterShareStatus::Registered) => {
+                        outcome(peer, RegistrationOutcome::Registered, None);
+                        return (Some((share_id, peer)), outcomes);
+                    }
+                    Ok(RegisterShareStatus::QuotaExceeded(quota)) => {
+                        let reason =
+                            format!("{} is {}, {} used", quota.limit, quota.max, quota.used);
+                        warn!(%peer, reason, "Provider is out of storage.");
+                        outcome(peer, RegistrationOutcome::QuotaExceeded, Some(reason));
+                        let spare = spares.lock().unwrap().pop();
+                        match spare {
+                            Some(spare) => peer = spare,
+                            None => {
+                                error!("No provider left for share {}.", share_id);
+                                return (None, outcomes);
+                            }
+                        }
+                    }
+                    Ok(RegisterShareStatus::Conflict(reason)) => {
+                        error!(
+                            "Provider {} holds a different share under the key, \
+                             pass --replace to overwrite it: {}",
+                            peer, reason
+                        );
+                        outcome(peer, RegistrationOutcome::Conflict, Some(reason));
+                        return (None, outcomes);
+                    }
+                    Ok(RegisterShareStatus::Refused(reason)) => {
+                        error!("Provider {} refused share: {}", peer, reason);
+                        outcome(peer, RegistrationOutcome::Refused, Some(reason));
+                        return (None, outcomes);
+                    }
+                    Err(e) => {
                         error!("Error: {:?}", e);
+                        outcome(peer, RegistrationOutcome::Failed, Some(e.to_string()));
+                        return (None, outcomes);
                     }
-                });
-
-            if verbose {
-                println!("🐛 shares: ");
-                let mut items: Vec<_> = split_shares.iter().collect();
-                items.sort_by(|a, b| a.1.cmp(b.1));
-
-                // Now items is sorted by key, and you can iterate over it to get the values in order
-                for (_, value) in items {
-                    println!("  {}", hex::encode(value));
                 }
             }
-
-            println!("✂️  Secret has been split and distributed across network.");
-            println!("    key: {:#?}", key);
-            println!("    threshold: {:#?}", threshold);
-            println!("    providers: {:#?}", providers_sample)
         }
-        CliArgument::Ls { key } => {
-            let providers = network_client.get_providers(key.clone()).await;
-            if providers.is_empty() {
-                return Err(format!("Could not find provider for share key: {key}.").into());
-            }
+    });
 
-            // println!("Found {} providers for share {}.", providers.len(), key);
-            println!("✂️  Share Providers: {:#?}", providers);
-        }
-        CliArgument::Refresh {
-            key,
-            threshold,
-            size,
-        } => {
-            // sleep for a bit to give the network time to bootstrap
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    // Await all of the requests, keeping the providers that took a share
+    let mut placed = Vec::new();
+    let mut outcomes = Vec::new();
+    for (share, asked) in futures::future::join_all(requests).await {
+        placed.extend(share);
+        outcomes.extend(asked);
+    }
+    (placed, outcomes)
+}
 
-            let providers = network_client.get_providers(key.clone()).await;
-            if providers.is_empty() {
-                return Err(format!("Could not find providers for share key: {key}.").into());
-            }
+/// Reads the `share` sent under `key` back from `peer` and checks it against the one sent.
+///
+/// # Returns
+/// Why the share read back is not the one sent, if it is not.
+async fn verify_share(
+    network_client: &Client,
+    sender: PeerId,
+    key: &str,
+    share: &FieldShare,
+    peer: PeerId,
+) -> Result<(), String> {
+    let mut network_client = network_client.clone();
+    let (index, sent) = &share.share;
+    let read_back = network_client
+        .request_field_share(peer, key.to_string(), sender, None)
+        .await
+        .map_err(|e| format!("the share could not be read back: {}", e))?;
+    let (read, data) = &read_back.share;
+    if read != index {
+        return Err(format!("share {} was read back as share {}", index, read));
+    }
+    #[cfg(feature = "sss16")]
+    if read_back.field != share.field {
+        return Err(format!(
+            "share {} split over {} was read back over {}",
+            index, share.field, read_back.field
+        ));
+    }
+    if Sha256::digest(data) != Sha256::digest(sent) {
+        return Err(format!("share {} was read back altered", index));
+    }
+    Ok(())
+}
 
-            debug!("Found {} providers for share {}.", providers.len(), key);
+/// Reads every share placed by `register_shares` back from its provider, and places each share
+/// that does not come back intact on one of `spares` instead, verifying it again there. The
+/// share is deleted from the provider that failed, so that it is not combined later.
+///
+/// # Arguments
+/// * `network_client` - The client to send the requests with.
+/// * `owner` - The identity key of the owner of the shares, which grants every provider it places
+///   a share on to relay refreshes of it.
+/// * `key` - The key the shares are registered under.
+/// * `shares` - The shares of the secret, by index.
+/// * `placed` - The index of every share placed with the provider holding it.
+/// * `spares` - The providers to place a share on instead, none of which holds a share yet.
+/// * `options` - How providers register the shares.
+/// * `outcomes` - What every provider asked answered, updated with the verification.
+///
+/// # Returns
+/// The index of every share placed and verified with the provider holding it.
+#[allow(clippy::too_many_arguments)]
+async fn verify_shares(
+    network_client: &Client,
+    owner: &Keypair,
+    key: &str,
+    shares: &SplitShares,
+    placed: Vec<(ShareIndex, PeerId)>,
+    mut spares: Vec<PeerId>,
+    options: ShareOptions,
+    outcomes: &mut Vec<ProviderOutcome>,
+) -> Vec<(ShareIndex, PeerId)> {
+    let sender = owner.public().to_peer_id();
+    let checks = placed.into_iter().map(|(index, peer)| async move {
+        let share = shares.get(index);
+        let result = verify_share(network_client, sender, key, &share, peer).await;
+        (index, peer, result)
+    });
 
-            let refresh_key = generate_refresh_key(threshold, size).unwrap();
-            debug!("🔑 Refresh Key: {:#?}", refresh_key);
+    let mut verified = Vec::new();
+    for (index, peer, result) in futures::future::join_all(checks).await {
+        let Err(reason) = result else {
+            verified.push((index, peer));
+            continue;
+        };
+        error!("Provider {} failed verification: {}", peer, reason);
+        let registered = outcomes.iter_mut().find(|outcome| {
+            outcome.peer == peer.to_string() && outcome.status == RegistrationOutcome::Registered
+        });
+        if let Some(outcome) = registered {
+            outcome.status = RegistrationOutcome::Unverified;
+            outcome.reason = Some(reason);
+        }
+        let mut client = network_client.clone();
+        if let Err(e) = client
+            .request_delete_share(key.to_string(), peer, sender)
+            .await
+        {
+            debug!(
+                "Could not delete the unverified share from {}: {:?}",
+                peer, e
+            );
+        }
 
-            let requests = providers.clone().into_iter().map(|p| {
-                let k = key.clone();
-                let ref_key = refresh_key.clone();
-                let mut network_client = network_client.clone();
-                debug!("🔄 Refreshing share for key: {:?} to peer {:?}", &k, p);
-                async move {
-                    network_client
-                        .request_refresh_shares(k, ref_key, p, sender)
+        let share = shares.get(index);
+        while let Some(spare) = spares.pop() {
+            let status = match RelayGrant::signed(key, spare, owner) {
+                Ok(grant) => {
+                    client
+                        .request_register_share(
+                            share.clone(),
+                            key.to_string(),
+                            options.threshold as u64,
+                            options.ttl,
+                            options.recreate,
+                            options.refresh_every,
+                            options.replace,
+                            Some(grant),
+                            spare,
+                            sender,
+                        )
                         .await
                 }
-                .boxed()
+                Err(e) => Err(e.into()),
+            };
+            let (status, reason) = match status {
+                Ok(RegisterShareStatus::Registered) => {
+                    match verify_share(network_client, sender, key, &share, spare).await {
+                        Ok(()) => (RegistrationOutcome::Registered, None),
+                        Err(reason) => (RegistrationOutcome::Unverified, Some(reason)),
+                    }
+                }
+                Ok(RegisterShareStatus::QuotaExceeded(quota)) => (
+                    RegistrationOutcome::QuotaExceeded,
+                    Some(format!(
+                        "{} is {}, {} used",
+                        quota.limit, quota.max, quota.used
+                    )),
+                ),
+                Ok(RegisterShareStatus::Conflict(reason)) => {
+                    (RegistrationOutcome::Conflict, Some(reason))
+                }
+                Ok(RegisterShareStatus::Refused(reason)) => {
+                    (RegistrationOutcome::Refused, Some(reason))
+                }
+                Err(e) => (RegistrationOutcome::Failed, Some(e.to_string())),
+            };
+            outcomes.push(ProviderOutcome {
+                peer: spare.to_string(),
+                status,
+                reason,
             });
-
-            // Await all of the requests and ensure they all succeed
-            futures::future::join_all(requests).await;
-
-            debug!("Found {} providers for share {}.", providers.len(), key);
-            println!(
-                "🔄 Refreshed {} shares for key: {:?}",
-                providers.len(),
-                &key
-            );
+            if status == RegistrationOutcome::Registered {
+                verified.push((index, spare));
+                break;
+            }
         }
     }
+    verified.sort();
+    verified
+}
+
+/// Lists the providers that did not register a share, one per line.
+fn outcome_report(outcomes: &[ProviderOutcome]) -> String {
+    outcomes
+        .iter()
+        .filter(|o| o.status != RegistrationOutcome::Registered)
+        .map(|o| {
+            format!(
+                "\n  {}: {}",
+                o.peer,
+                o.reason.as_deref().unwrap_or("failed")
+            )
+        })
+        .collect()
+}
+
+/// Registers share `i` of a secret split under `key` with the `i`-th of `providers`, counting
+/// from 1, all at once.
+///
+/// # Returns
+/// The providers that did not register their share, with the reason.
+async fn register_with_each(
+    network_client: &Client,
+    owner: &Keypair,
+    key: &str,
+    shares: SplitShares,
+    providers: &[PeerId],
+    options: ShareOptions,
+) -> Vec<(PeerId, String)> {
+    let sender = owner.public().to_peer_id();
+    let requests = providers.iter().enumerate().map(|(i, &peer)| {
+        let mut network_client = network_client.clone();
+        let share_id = (i + 1) as ShareIndex;
+        let share = shares.get(share_id);
+        let key = key.to_string();
+        async move {
+            let status = match RelayGrant::signed(&key, peer, owner) {
+                Ok(grant) => {
+                    network_client
+                        .request_register_share(
+                            share,
+                            key,
+                            options.threshold as u64,
+                            options.ttl,
+                            options.recreate,
+                            options.refresh_every,
+                            options.replace,
+                            Some(grant),
+                            peer,
+                            sender,
+                        )
+                        .await
+                }
+                Err(e) => Err(e.into()),
+            };
+            let reason = match status {
+                Ok(RegisterShareStatus::Registered) => return None,
+                Ok(RegisterShareStatus::QuotaExceeded(quota)) => format!(
+                    "out of storage, {} is {}, {} used",
+                    quota.limit, quota.max, quota.used
+                ),
+                Ok(RegisterShareStatus::Conflict(reason)) => {
+                    format!("{}, pass --replace to overwrite it", reason)
+                }
+                Ok(RegisterShareStatus::Refused(reason)) => reason,
+                Err(e) => e.to_string(),
+            };
+            Some((peer, reason))
+        }
+    });
+    futures::future::join_all(requests)
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Lists the providers that turned down a share, one per line.
+fn failure_report(failed: &[(PeerId, String)]) -> String {
+    failed
+        .iter()
+        .map(|(peer, reason)| format!("\n  {}: {}", peer, reason))
+        .collect()
+}
+
+/// Splits the file at `path` a chunk at a time, registering the shares of each chunk with
+/// `providers` before reading the next, so that only one chunk is held in memory. The manifest
+/// of the chunks is registered under `key` once every chunk is.
+///
+/// How far the split got is kept next to the file after every chunk. A split of the same file
+/// under the same key picks up from there, with the providers it started with, as long as the
+/// chunks already registered still hash the same.
+///
+/// # Arguments
+/// * `network_client` - The client to send the registrations with.
+/// * `owner` - The identity key of the owner of the shares, which grants every provider it places
+///   a share on to relay refreshes of it.
+/// * `path` - The file to split.
+/// * `key` - The key to register the manifest under.
+/// * `providers` - The providers to place the shares on, one share of each chunk each, unless
+///   the split is resumed.
+/// * `chunk_bytes` - The length of the chunks.
+/// * `options` - How providers register the shares.
+/// * `progress` - Told the number of chunks registered and the total after every chunk. An error
+///   it returns stops the split, which can be resumed.
+///
+/// # Returns
+/// The manifest registered under `key`, or an error naming the chunk and the providers that did
+/// not register it.
+#[allow(clippy::too_many_arguments)]
+async fn split_file(
+    network_client: &Client,
+    owner: &Keypair,
+    path: &Path,
+    key: &str,
+    providers: Vec<PeerId>,
+    chunk_bytes: u64,
+    options: ShareOptions,
+    progress: &mut dyn FnMut(u64, u64) -> Result<(), Box<dyn Error>>,
+) -> Result<ChunkManifest, Box<dyn Error>> {
+    let file =
+        File::open(path).map_err(|e| format!("cannot read the file {}: {}", path.display(), e))?;
+    let length = file.metadata()?.len();
+    if length == 0 {
+        return Err("the file is empty".into());
+    }
+
+    let progress_path = SplitProgress::path_for(path);
+    let mut state = match SplitProgress::load(&progress_path)? {
+        Some(saved) => {
+            if saved.key != key
+                || saved.length != length
+                || saved.chunk_bytes != chunk_bytes
+                || saved.threshold != options.threshold
+                || saved.providers.len() != providers.len()
+            {
+                return Err(format!(
+                    "{} records an interrupted split with another key or layout, remove it to \
+                     start over",
+                    progress_path.display()
+                )
+                .into());
+            }
+            debug!(
+                "Resuming the split of {} at chunk {}.",
+                key, saved.completed
+            );
+            saved
+        }
+        None => SplitProgress {
+            key: key.to_string(),
+            length,
+            chunk_bytes,
+            threshold: options.threshold,
+            providers: providers.iter().map(ToString::to_string).collect(),
+            completed: 0,
+            prefix_sha256: String::new(),
+        },
+    };
+    let providers = state
+        .providers
+        .iter()
+        .map(|peer| peer.parse::<PeerId>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let total = length.div_ceil(chunk_bytes);
+    let mut reader = ChunkReader::new(BufReader::new(file), chunk_bytes);
+    for _ in 0..state.completed {
+        reader.next_chunk()?;
+    }
+    if state.completed > 0 && reader.sha256() != state.prefix_sha256 {
+        return Err(format!(
+            "{} changed since its split was interrupted, remove {} to start over",
+            path.display(),
+            progress_path.display()
+        )
+        .into());
+    }
+
+    while let Some(chunk) = reader.next_chunk()? {
+        let index = state.completed;
+        let shares = SplitShares::split(&chunk, options.threshold, providers.len())?;
+        let chunk_key = chunk_key(key, index);
+        let failed = register_with_each(
+            network_client,
+            owner,
+            &chunk_key,
+            shares,
+            &providers,
+            options,
+        )
+        .await;
+        if !failed.is_empty() {
+            return Err(format!(
+                "chunk {} of {} was not registered, run the split again to resume:{}",
+                index + 1,
+                total,
+                failure_report(&failed)
+            )
+            .into());
+        }
+        state.completed += 1;
+        state.prefix_sha256 = reader.sha256();
+        state.save(&progress_path)?;
+        progress(state.completed, total)?;
+    }
+
+    let manifest = ChunkManifest {
+        length,
+        chunk_bytes,
+        threshold: options.threshold,
+        providers: state.providers,
+        sha256: reader.sha256(),
+    };
+    let shares = SplitShares::split(&manifest.to_bytes()?, options.threshold, providers.len())?;
+    let failed = register_with_each(network_client, owner, key, shares, &providers, options).await;
+    if !failed.is_empty() {
+        return Err(format!(
+            "the manifest was not registered, run the split again to resume:{}",
+            failure_report(&failed)
+        )
+        .into());
+    }
+    std::fs::remove_file(&progress_path)?;
+    Ok(manifest)
+}
+
+/// Fetches `threshold` shares of `key`, or the shares of `share_ids` when any are given. That
+/// many providers are asked at once, and the others in turn for the shares that could not be
+/// fetched.
+///
+/// # Arguments
+/// * `network_client` - The client to send the requests with.
+/// * `sender` - The peer the requests are sent as.
+/// * `owner` - The owner of the shares, when it granted the sender access to them.
+/// * `key` - The key of the shares.
+/// * `providers` - The providers holding a share under the key.
+/// * `threshold` - The number of shares needed.
+/// * `share_ids` - The only shares to fetch, by index, or none to fetch any.
+///
+/// # Returns
+/// The shares by index, or an error naming the providers that did not send their share.
+async fn fetch_shares(
+    network_client: &Client,
+    sender: PeerId,
+    owner: Option<PeerId>,
+    key: &str,
+    providers: &[PeerId],
+    threshold: usize,
+    share_ids: &[ShareIndex],
+) -> Result<SplitShares, Box<dyn Error>> {
+    let wanted = match share_ids.is_empty() {
+        true => threshold,
+        false => share_ids.len(),
+    };
+    let mut shares = SplitShares::default();
+    let mut failed = Vec::new();
+    let mut denied = 0;
+    let mut candidates = providers.iter().copied();
+    while shares.shares.len() < wanted {
+        let batch: Vec<PeerId> = candidates
+            .by_ref()
+            .take(wanted - shares.shares.len())
+            .collect();
+        if batch.is_empty() && !share_ids.is_empty() {
+            let missing: Vec<String> = share_ids
+                .iter()
+                .filter(|index| !shares.shares.contains_key(*index))
+                .map(ShareIndex::to_string)
+                .collect();
+            let message = format!(
+                "share {} of {} was not sent by any provider asked:{}",
+                missing.join(", "),
+                key,
+                failure_report(&failed)
+            );
+            return Err(CliError::new(ErrorKind::QuorumNotMet, message).into());
+        }
+        if batch.is_empty() {
+            // refused by every provider asked, rather than short of providers
+            let kind = match denied > 0 && denied == failed.len() {
+                true => ErrorKind::Denied,
+                false => ErrorKind::QuorumNotMet,
+            };
+            let message = format!(
+                "only {} of the {} shares needed for {} could be fetched:{}",
+                shares.shares.len(),
+                threshold,
+                key,
+                failure_report(&failed)
+            );
+            return Err(CliError::new(kind, message).into());
+        }
+        let requests = batch.into_iter().map(|peer| {
+            let mut network_client = network_client.clone();
+            let key = key.to_string();
+            async move {
+                let result = network_client
+                    .request_field_share(peer, key, sender, owner)
+                    .await;
+                (peer, result)
+            }
+        });
+        for (peer, result) in futures::future::join_all(requests).await {
+            match result {
+                Ok(share) if !share_ids.is_empty() && !share_ids.contains(&share.share.0) => {
+                    debug!("Skipped share {} of {} from {}.", share.share.0, key, peer);
+                }
+                Ok(share) => {
+                    debug!("Received share {} of {} from {}.", share.share.0, key, peer);
+                    shares.insert(share).map_err(|e| uncombinable(key, e))?;
+                }
+                Err(e) => {
+                    error!("Error: {:?}", e);
+                    if classify(&e) == ErrorKind::Denied {
+                        denied += 1;
+                    }
+                    failed.push((peer, e.to_string()))
+                }
+            }
+        }
+    }
+    Ok(shares)
+}
+
+/// Rebuilds a chunk of a file from `threshold` of its shares, or those of `share_ids`, fetched
+/// with `fetch_shares`.
+///
+/// # Returns
+/// The chunk, or an error naming the providers that did not send their share.
+async fn fetch_chunk(
+    network_client: &Client,
+    sender: PeerId,
+    owner: Option<PeerId>,
+    key: &str,
+    providers: &[PeerId],
+    threshold: usize,
+    share_ids: &[ShareIndex],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let shares = fetch_shares(
+        network_client,
+        sender,
+        owner,
+        key,
+        providers,
+        threshold,
+        share_ids,
+    )
+    .await?;
+    shares.combine(key)
+}
+
+/// Looks providers up once with `lookup`, giving up after `timeout`.
+///
+/// # Returns
+/// The providers found, or a `Timeout` error if `timeout` passes first.
+async fn lookup_providers(
+    lookup: impl Future<Output = HashSet<PeerId>>,
+    timeout: Duration,
+) -> Result<HashSet<PeerId>, CliError> {
+    tokio::time::timeout(timeout, lookup)
+        .await
+        .map_err(|_| CliError::new(ErrorKind::Timeout, "timed out looking for providers"))
+}
+
+/// Refuses to split another secret under `key` while providers still hold shares `sender`
+/// registered under it, which the new shares would be mixed with.
+///
+/// # Arguments
+/// * `network_client` - The client to look the providers up with.
+/// * `sender` - The owner of the shares.
+/// * `key` - The key the secret is about to be split under.
+/// * `timeout` - How long to look for providers.
+///
+/// # Returns
+/// An error listing the providers holding shares of `key`, if any, or if the lookup timed out.
+async fn check_key_unused(
+    network_client: &Client,
+    sender: PeerId,
+    key: &str,
+    timeout: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let mut client = network_client.clone();
+    let holding = lookup_providers(client.providers_holding(sender, key), timeout).await?;
+    if holding.is_empty() {
+        return Ok(());
+    }
+    let mut peers: Vec<String> = holding.iter().map(ToString::to_string).collect();
+    peers.sort();
+    let message = format!(
+        "{} providers already hold shares under {}, pass --force to overwrite them or choose \
+         another key:\n  {}",
+        peers.len(),
+        key,
+        peers.join("\n  ")
+    );
+    Err(CliError::new(ErrorKind::Usage, message).into())
+}
+
+/// Prints a change of the providers watched by `ls --watch` on one line: every provider the
+/// first time, and those added and removed after.
+fn print_providers_change(
+    change: &ProvidersChangeOutput,
+    first: bool,
+    out: &mut dyn Write,
+) -> std::io::Result<()> {
+    if first && change.providers.is_empty() {
+        return writeln!(out, "[{}] 👀 {}: no providers", change.time, change.key);
+    }
+    if first {
+        return writeln!(
+            out,
+            "[{}] 👀 {}: {} providers: {}",
+            change.time,
+            change.key,
+            change.providers.len(),
+            change.providers.join(", ")
+        );
+    }
+    let added = change.added.iter().map(|peer| format!("+{}", peer));
+    let removed = change.removed.iter().map(|peer| format!("-{}", peer));
+    writeln!(
+        out,
+        "[{}] 👀 {}: {} ({} providers)",
+        change.time,
+        change.key,
+        added.chain(removed).collect::<Vec<_>>().join(" "),
+        change.providers.len()
+    )
+}
+
+/// Watches the providers of the shares `sender` registered under `key`, printing every change.
+///
+/// # Arguments
+/// * `network_client` - The client to look the providers up with.
+/// * `sender` - The owner of the shares.
+/// * `key` - The key to watch.
+/// * `interval` - The time between two lookups.
+/// * `expect` - The number of providers to stop at, if any; the watch runs on otherwise.
+/// * `timeout` - How long to wait for `expect` providers.
+/// * `json` - Whether to print the changes as JSON, one document per line.
+/// * `out` - Where to print them.
+///
+/// # Returns
+/// Once `expect` providers are found, or an error if `timeout` runs out before.
+#[allow(clippy::too_many_arguments)]
+async fn watch_ls(
+    network_client: &Client,
+    sender: PeerId,
+    key: &str,
+    interval: Duration,
+    expect: Option<usize>,
+    timeout: Duration,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<(), Box<dyn Error>> {
+    let record = Client::provider_key(&sender, key);
+    let mut changes = Box::pin(network_client.watch_providers(record, interval));
+    let deadline = Instant::now() + timeout;
+    let mut found = 0;
+    let mut first = true;
+    loop {
+        let change = match expect {
+            Some(wanted) => tokio::time::timeout_at(deadline, changes.next())
+                .await
+                .map_err(|_| {
+                    let message = format!(
+                        "timed out watching {} (found {} of {} providers)",
+                        key, found, wanted
+                    );
+                    CliError::new(ErrorKind::Timeout, message)
+                })?,
+            None => changes.next().await,
+        };
+        let Some(change) = change else {
+            return Ok(());
+        };
+        found = change.providers.len();
+        let output = ProvidersChangeOutput {
+            time: now_unix(),
+            key: key.to_string(),
+            providers: peer_ids(change.providers),
+            added: peer_ids(change.added),
+            removed: peer_ids(change.removed),
+        };
+        match json {
+            true => writeln!(out, "{}", to_json(&output)?)?,
+            false => print_providers_change(&output, first, out)?,
+        }
+        out.flush()?;
+        first = false;
+        if expect.is_some_and(|wanted| found >= wanted) {
+            return Ok(());
+        }
+    }
+}
+
+/// The error of a command that found no provider holding the shares of `key`.
+fn no_providers(key: &str) -> Box<dyn Error> {
+    let message = format!("Could not find providers for share key: {key}.");
+    CliError::new(ErrorKind::NoProviders, message).into()
+}
+
+/// The error of a refresh of `key` that `failed` did not apply, though `refreshed` others did,
+/// listing the providers that failed one per line.
+fn partial_refresh(key: &str, refreshed: usize, failed: &[PeerId]) -> Box<dyn Error> {
+    let peers: String = failed.iter().map(|peer| format!("\n  {peer}")).collect();
+    let message = format!(
+        "{} of {} providers did not refresh their share of {key}:{peers}",
+        failed.len(),
+        refreshed + failed.len()
+    );
+    CliError::new(ErrorKind::PartialFailure, message).into()
+}
+
+/// Generates the key a refresh of a secret of `size` bytes, split with `threshold`, sends.
+///
+/// # Returns
+/// One polynomial per byte of the secret, or a usage error if the threshold is below 2 or the
+/// secret is empty.
+fn new_refresh_key(threshold: usize, size: usize) -> Result<Vec<Polynomial>, CliError> {
+    if size == 0 {
+        return Err(CliError::new(
+            ErrorKind::Usage,
+            "--size must be at least 1, the size of the secret in bytes",
+        ));
+    }
+    generate_refresh_key(threshold, size).map_err(|e| {
+        CliError::new(
+            ErrorKind::Usage,
+            format!("cannot refresh with --threshold {threshold}: {e}"),
+        )
+    })
+}
+
+/// The error a combine fails with when the shares fetched for `key` cannot be combined, rather
+/// than printing whatever they would rebuild.
+fn uncombinable(key: &str, error: shard::sss::Error) -> Box<dyn Error> {
+    let message = format!("Unable to combine the shares of {key}: {error}.");
+    CliError::new(ErrorKind::Other, message).into()
+}
+
+/// How `combine` waits for the providers of a secret to be found.
+///
+/// # Fields
+/// * `min_providers` - The number of providers to wait for, when more than the threshold.
+/// * `budget` - How long to keep looking for providers.
+/// * `report` - Whether to report on stderr how many providers were found while waiting.
+#[derive(Debug, Clone, Copy)]
+struct ProviderWait {
+    min_providers: usize,
+    budget: Duration,
+    report: bool,
+}
+
+/// Waits until `deadline` for the network to be ready with the providers `criteria` asks for,
+/// not counting those of `excluded`, reporting how many were found as they are with `report`.
+///
+/// # Returns
+/// The providers found but for `excluded`, or an error saying how many were if `deadline` passes
+/// first.
+async fn wait_for_providers(
+    network_client: &Client,
+    criteria: &ReadyCriteria,
+    excluded: &[PeerId],
+    deadline: Instant,
+    report: bool,
+) -> Result<HashSet<PeerId>, NotReady> {
+    let mut criteria = criteria.clone();
+    let wanted = criteria.min_providers;
+    loop {
+        let skipped = criteria.min_providers - wanted;
+        let progress = |found: usize, _| {
+            if report {
+                eprintln!(
+                    "found {}/{} providers…",
+                    found.saturating_sub(skipped),
+                    wanted
+                );
+            }
+        };
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let mut providers = network_client
+            .clone()
+            .await_ready_with_progress(&criteria, timeout, progress)
+            .await
+            .map_err(|not_ready| match not_ready {
+                NotReady::Providers { found, .. } => NotReady::Providers {
+                    found: found.saturating_sub(skipped),
+                    wanted,
+                },
+                not_ready => not_ready,
+            })?;
+        let found = providers.len();
+        providers.retain(|peer| !excluded.contains(peer));
+        if providers.len() >= wanted || providers.len() == found {
+            return Ok(providers);
+        }
+        // wait for as many more providers as were excluded
+        criteria.min_providers = wanted + found - providers.len();
+    }
+}
+
+/// Asks `providers` in turn for the threshold the shares of `key` were split with, until one
+/// describes its share.
+///
+/// # Returns
+/// The threshold, or `None` if no provider described its share.
+async fn share_threshold(
+    network_client: &Client,
+    sender: PeerId,
+    key: &str,
+    providers: &HashSet<PeerId>,
+) -> Option<usize> {
+    for &peer in providers {
+        let mut network_client = network_client.clone();
+        match network_client
+            .request_stat_share(key.to_string(), peer, sender)
+            .await
+        {
+            Ok(StatShareStatus::Found(metadata)) => return Some(metadata.threshold as usize),
+            Ok(status) => debug!(
+                "{} did not describe its share of {}: {:?}",
+                peer, key, status
+            ),
+            Err(e) => debug!("{} did not describe its share of {}: {}", peer, key, e),
+        }
+    }
+    None
+}
+
+/// Asks every one of `providers` for the epoch its share of `key` is at, for a refresh to advance
+/// them all past.
+///
+/// # Returns
+/// The latest epoch a provider reported, or an error if no provider described its share.
+async fn share_epoch(
+    network_client: &Client,
+    sender: PeerId,
+    key: &str,
+    providers: &HashSet<PeerId>,
+) -> Result<u64, Box<dyn Error>> {
+    let stats = providers.iter().map(|&peer| {
+        let mut network_client = network_client.clone();
+        async move {
+            let status = network_client
+                .request_stat_share(key.to_string(), peer, sender)
+                .await;
+            match status {
+                Ok(StatShareStatus::Found(metadata)) => Some(metadata.epoch),
+                Ok(status) => {
+                    debug!(
+                        "{} did not describe its share of {}: {:?}",
+                        peer, key, status
+                    );
+                    None
+                }
+                Err(e) => {
+                    debug!("{} did not describe its share of {}: {}", peer, key, e);
+                    None
+                }
+            }
+        }
+    });
+    let epochs = futures::future::join_all(stats).await;
+    epochs.into_iter().flatten().max().ok_or_else(|| {
+        let message = format!("No provider described its share of {key}.");
+        CliError::new(ErrorKind::NoProviders, message).into()
+    })
+}
+
+/// Which providers and shares `combine` uses, instead of any it finds.
+///
+/// # Fields
+/// * `pinned` - The only providers to ask, or none to look them up.
+/// * `excluded` - The providers never to ask.
+/// * `share_ids` - The only shares to combine, by index, or none to combine any.
+#[derive(Debug, Clone, Default)]
+struct ShareSelection {
+    pinned: Vec<PeerId>,
+    excluded: Vec<PeerId>,
+    share_ids: Vec<ShareIndex>,
+}
+
+/// Fetches the shares of the secret split under `key`, from the pinned providers or from those
+/// found on the DHT once there are `threshold` of them, and at least as many as the shares were
+/// split with.
+///
+/// # Arguments
+/// * `network_client` - The client to send the requests with.
+/// * `sender` - The peer the requests are sent as.
+/// * `owner` - The owner of the shares, when it granted the sender access to them.
+/// * `key` - The key of the shares.
+/// * `threshold` - The number of shares needed, or `None` to fetch one from every provider found.
+/// * `selection` - The providers and shares to use.
+/// * `ready` - What the network must reach before providers are looked up.
+/// * `wait` - How to wait for the network to be ready and for providers to be found.
+///
+/// # Returns
+/// The shares by index, or an error if too few providers were found or sent their share, or if
+/// the providers or shares selected are fewer than the threshold.
+#[allow(clippy::too_many_arguments)]
+async fn combine_secret(
+    network_client: &Client,
+    sender: PeerId,
+    owner: Option<PeerId>,
+    key: &str,
+    threshold: Option<usize>,
+    selection: &ShareSelection,
+    ready: &ReadyCriteria,
+    wait: ProviderWait,
+) -> Result<SplitShares, Box<dyn Error>> {
+    let deadline = Instant::now() + wait.budget;
+    let mut threshold = threshold;
+    let excluded = &selection.excluded;
+    let providers = match selection.pinned.is_empty() {
+        true => {
+            debug!("Looking for providers of share {}...", key);
+            let mut criteria = ReadyCriteria {
+                min_providers: threshold
+                    .unwrap_or(1)
+                    .max(wait.min_providers)
+                    .max(selection.share_ids.len()),
+                provider_key: Some(Client::provider_key(&owner.unwrap_or(sender), key)),
+                ..ready.clone()
+            };
+            let mut providers =
+                wait_for_providers(network_client, &criteria, excluded, deadline, wait.report)
+                    .await?;
+
+            // never combine below the threshold the shares were split with, once one of them
+            // tells it; only the owner may describe its shares
+            let split_with = match owner {
+                Some(_) => None,
+                None => share_threshold(network_client, sender, key, &providers).await,
+            };
+            if let Some(split_with) = split_with {
+                threshold = Some(threshold.map_or(split_with, |t| t.max(split_with)));
+                if providers.len() < split_with {
+                    criteria.min_providers = split_with;
+                    let more = wait_for_providers(
+                        network_client,
+                        &criteria,
+                        excluded,
+                        deadline,
+                        wait.report,
+                    );
+                    providers.extend(more.await?);
+                }
+            }
+            providers
+        }
+        false => {
+            network_client
+                .clone()
+                .await_ready(ready, wait.budget)
+                .await?;
+            let providers: HashSet<PeerId> = selection.pinned.iter().copied().collect();
+            if owner.is_none() {
+                if let Some(split_with) =
+                    share_threshold(network_client, sender, key, &providers).await
+                {
+                    threshold = Some(threshold.map_or(split_with, |t| t.max(split_with)));
+                }
+            }
+            providers
+        }
+    };
+    debug!("Found {} providers for share {}.", providers.len(), key);
+
+    // get the threshold number of shares, if threshold is None, use the number of providers
+    let threshold = threshold.unwrap_or(providers.len());
+    let share_ids = &selection.share_ids;
+    if !share_ids.is_empty() && share_ids.len() < threshold {
+        let message = format!(
+            "{} needs {} shares, but only {} were given with --share-id",
+            key,
+            threshold,
+            share_ids.len()
+        );
+        return Err(CliError::new(ErrorKind::QuorumNotMet, message).into());
+    }
+    if !selection.pinned.is_empty() && providers.len() < threshold.max(share_ids.len()) {
+        let message = format!(
+            "{} needs {} shares, but only {} providers were given with --provider",
+            key,
+            threshold.max(share_ids.len()),
+            providers.len()
+        );
+        return Err(CliError::new(ErrorKind::QuorumNotMet, message).into());
+    }
+    let rng = &mut rand::thread_rng();
+    let mut providers: Vec<PeerId> = providers.into_iter().collect();
+    providers.shuffle(rng);
+
+    debug!("Requesting share from providers.");
+    fetch_shares(
+        network_client,
+        sender,
+        owner,
+        key,
+        &providers,
+        threshold,
+        share_ids,
+    )
+    .await
+}
+
+/// Rebuilds the file described by `manifest` into `out` a chunk at a time, checking it against
+/// the manifest's hash once it is written.
+///
+/// # Arguments
+/// * `network_client` - The client to send the requests with.
+/// * `sender` - The peer the requests are sent as.
+/// * `owner` - The owner of the shares, when it granted the sender access to them.
+/// * `key` - The key the manifest is registered under.
+/// * `manifest` - The manifest of the file.
+/// * `selection` - The providers of the manifest to ask and the shares to use.
+/// * `out` - The file to write, created only its owner can read. It must not exist yet.
+/// * `progress` - Told the number of chunks written and the total after every chunk.
+///
+/// # Returns
+/// An error if a chunk cannot be rebuilt or written, or the file does not match its manifest.
+#[allow(clippy::too_many_arguments)]
+async fn combine_file(
+    network_client: &Client,
+    sender: PeerId,
+    owner: Option<PeerId>,
+    key: &str,
+    manifest: &ChunkManifest,
+    selection: &ShareSelection,
+    out: &Path,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Result<(), Box<dyn Error>> {
+    let providers: Vec<PeerId> = manifest
+        .providers
+        .iter()
+        .map(|peer| peer.parse::<PeerId>())
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|peer| selection.pinned.is_empty() || selection.pinned.contains(peer))
+        .filter(|peer| !selection.excluded.contains(peer))
+        .collect();
+    let file = create_private(out)
+        .map_err(|e| format!("cannot write the file to {}: {}", out.display(), e))?;
+    let mut writer = ChunkWriter::new(BufWriter::new(file));
+    let total = manifest.chunks();
+    for index in 0..total {
+        let chunk_key = chunk_key(key, index);
+        let chunk = fetch_chunk(
+            network_client,
+            sender,
+            owner,
+            &chunk_key,
+            &providers,
+            manifest.threshold,
+            &selection.share_ids,
+        )
+        .await?;
+        writer.write_chunk(&chunk)?;
+        progress(index + 1, total);
+    }
+    writer.finish(manifest)?;
+    Ok(())
+}
+
+/// Shows how many chunks of a file were handled on stderr, when it is a terminal.
+fn chunk_progress(verb: &'static str) -> impl FnMut(u64, u64) {
+    let show = std::io::stderr().is_terminal();
+    move |done, total| {
+        if show {
+            eprint!("\r⏳ {} chunk {}/{}", verb, done, total);
+            if done == total {
+                eprintln!();
+            }
+        }
+    }
+}
+
+/// Prints an event published by the provider with `--print-events`, as a line of JSON with
+/// `--json`.
+fn print_provider_event(
+    event: &ProviderEvent,
+    json: bool,
+    out: &mut dyn Write,
+) -> Result<(), Box<dyn Error>> {
+    if json {
+        writeln!(out, "{}", to_json(&ProviderEventOutput::from(event))?)?;
+    } else {
+        writeln!(out, "📣 Provider {}", event)?;
+    }
+    Ok(())
+}
+
+/// Prints what `info` reports about the local node and, with `--network`, every provider heard
+/// from.
+fn print_info(output: &InfoOutput, out: &mut dyn Write) -> std::io::Result<()> {
+    let list = |addrs: &[String]| match addrs {
+        [] => "none".to_string(),
+        addrs => addrs.join(", "),
+    };
+    writeln!(out, "🆔 peer id: {}", output.peer_id)?;
+    writeln!(
+        out,
+        "   version: {} ({})",
+        output.version, output.agent_version
+    )?;
+    writeln!(out, "   listening on: {}", list(&output.listen_addrs))?;
+    writeln!(
+        out,
+        "   external addresses: {}",
+        list(&output.external_addrs)
+    )?;
+    writeln!(out, "   connected peers: {}", output.connected_peers)?;
+    writeln!(out, "   routing table: {} peers", output.routing_table_size)?;
+    writeln!(
+        out,
+        "   publicly reachable: {}",
+        if output.reachable { "yes" } else { "no" }
+    )?;
+    let Some(providers) = &output.providers else {
+        return Ok(());
+    };
+    writeln!(out, "🌐 {} providers:", providers.len())?;
+    for provider in providers {
+        writeln!(
+            out,
+            "  {}: {} shares, {} bytes, up {}s, version {}, {}",
+            provider.peer,
+            provider.shares,
+            provider.db_bytes,
+            provider.uptime_secs,
+            provider.version,
+            if provider.reachable {
+                "reachable"
+            } else {
+                "not reachable"
+            }
+        )?;
+        let metrics = &provider.metrics;
+        let oldest = match metrics.oldest_refresh_age_secs {
+            Some(age) => format!("refreshed {}s ago", age),
+            None => "refresh unknown".to_string(),
+        };
+        writeln!(
+            out,
+            "    {} registered, {} served, {} refreshed ({} own), {} failed, oldest {}",
+            metrics.registrations,
+            metrics.gets_served,
+            metrics.refreshes_applied,
+            metrics.refreshes_initiated,
+            metrics.failures.values().sum::<u64>(),
+            oldest
+        )?;
+    }
+    Ok(())
+}
+
+/// Asks each provider for the keys `sender` holds shares under there, and merges the answers
+/// into one listing. Each key is described by the first of its providers that reports the
+/// metadata of its share. Providers that do not answer are listed rather than failing the whole
+/// listing.
+///
+/// # Arguments
+/// * `network_client` - The client to send the requests with.
+/// * `providers` - The providers to ask.
+/// * `sender` - The peer whose keys are listed.
+///
+/// # Returns
+/// The merged listing, ordered by key, and the providers that did not answer.
+async fn collect_keys(
+    network_client: &Client,
+    providers: impl IntoIterator<Item = PeerId>,
+    sender: PeerId,
+) -> KeysOutput {
+    let listings = providers.into_iter().map(|p| {
+        let mut network_client = network_client.clone();
+        async move { (p, network_client.request_all_keys(p, sender).await) }
+    });
+
+    let mut held: BTreeMap<String, Vec<PeerId>> = BTreeMap::new();
+    let mut unanswered = Vec::new();
+    for (peer, result) in futures::future::join_all(listings).await {
+        match result {
+            Ok(keys) => {
+                for key in keys {
+                    held.entry(key).or_default().push(peer);
+                }
+            }
+            Err(e) => unanswered.push(UnansweredProvider {
+                peer: peer.to_string(),
+                reason: e.to_string(),
+            }),
+        }
+    }
+    unanswered.sort_by(|a, b| a.peer.cmp(&b.peer));
+
+    let described = held.into_iter().map(|(key, holders)| {
+        let mut network_client = network_client.clone();
+        async move {
+            let mut metadata = None;
+            for &peer in &holders {
+                match network_client
+                    .request_stat_share(key.clone(), peer, sender)
+                    .await
+                {
+                    Ok(StatShareStatus::Found(found)) => {
+                        metadata = Some(found);
+                        break;
+                    }
+                    Ok(status) => debug!("No metadata for {} from {}: {:?}", key, peer, status),
+                    Err(e) => debug!("No metadata for {} from {}: {:?}", key, peer, e),
+                }
+            }
+            KeyListing {
+                threshold: metadata.as_ref().map(|m| m.threshold),
+                last_refreshed_unix: metadata.map(|m| m.last_refreshed_unix),
+                providers: peer_ids(holders),
+                key,
+            }
+        }
+    });
+    KeysOutput {
+        keys: futures::future::join_all(described).await,
+        unanswered,
+    }
+}
+
+/// Prints the keys found by `keys` with their providers, followed by the providers that did not
+/// answer.
+fn print_keys(output: &KeysOutput, out: &mut dyn Write) -> std::io::Result<()> {
+    if output.keys.is_empty() {
+        writeln!(out, "🗝️  No keys found.")?;
+    } else {
+        writeln!(out, "🗝️  {} keys:", output.keys.len())?;
+    }
+    for listing in &output.keys {
+        match (listing.threshold, listing.last_refreshed_unix) {
+            (Some(threshold), Some(refreshed)) => writeln!(
+                out,
+                "  {}: threshold {}, last refreshed at {}",
+                listing.key, threshold, refreshed
+            )?,
+            _ => writeln!(out, "  {}:", listing.key)?,
+        }
+        for provider in &listing.providers {
+            writeln!(out, "    {}", provider)?;
+        }
+    }
+    for provider in &output.unanswered {
+        writeln!(
+            out,
+            "⚠️ Provider {} did not answer: {}",
+            provider.peer, provider.reason
+        )?;
+    }
+    Ok(())
+}
+
+/// Grants or revokes `reader`'s access to the shares of `key` on every provider holding one,
+/// printing how many providers applied the change as text or, with `json`, as an `AccessOutput`.
+/// The providers are looked up once the network is `ready`, waiting at most `timeout` for each.
+#[allow(clippy::too_many_arguments)]
+async fn change_access(
+    network_client: Client,
+    sender: PeerId,
+    key: String,
+    reader: PeerId,
+    grant: bool,
+    json: bool,
+    ready: &ReadyCriteria,
+    timeout: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let mut providers_client = network_client.clone();
+    providers_client.await_ready(ready, timeout).await?;
+    let lookup = providers_client.get_providers(Client::provider_key(&sender, &key));
+    let providers = lookup_providers(lookup, timeout).await?;
+    if providers.is_empty() {
+        return Err(no_providers(&key));
+    }
+
+    let requests = providers.into_iter().map(|p| {
+        let k = key.clone();
+        let mut network_client = network_client.clone();
+        async move {
+            if grant {
+                network_client
+                    .request_grant_access(k, p, sender, reader)
+                    .await
+            } else {
+                network_client
+                    .request_revoke_access(k, p, sender, reader)
+                    .await
+            }
+        }
+        .boxed()
+    });
+
+    let results = futures::future::join_all(requests).await;
+    for e in results.iter().filter_map(|r| r.as_ref().err()) {
+        error!("Error: {:?}", e);
+    }
+    let changed = results.iter().filter(|r| r.is_ok()).count();
+    if json {
+        let output = AccessOutput {
+            key,
+            reader: reader.to_string(),
+            granted: grant,
+            changed,
+        };
+        println!("{}", to_json(&output)?);
+    } else if grant {
+        println!(
+            "🔓 Granted {} access to {} shares for key: {:?}",
+            reader, changed, &key
+        );
+    } else {
+        println!(
+            "🔒 Revoked {} access to {} shares for key: {:?}",
+            reader, changed, &key
+        );
+    }
+    Ok(())
+}
+
+/// Reads the key `rotate-owner` hands the shares over to.
+///
+/// # Arguments
+/// * `new_key` - A key file or, if there is no such file, the name of the profile whose identity
+///   key to take.
+/// * `dir` - The configuration directory the profiles are kept in.
+///
+/// # Returns
+/// The key, or a usage error if neither the file nor the profile holds one.
+fn rotation_key(new_key: &str, dir: &Path) -> Result<Keypair, Box<dyn Error>> {
+    let path = Path::new(new_key);
+    let key = match path.exists() {
+        true => ShardConfig::key_file(path)?,
+        false => ShardConfig::profile_key(dir, new_key).ok().flatten(),
+    };
+    key.ok_or_else(|| {
+        let message = format!("{new_key} is neither a key file nor a profile with an identity key");
+        CliError::new(ErrorKind::Usage, message).into()
+    })
+}
+
+/// Hands every share `owner` registered over to `new_key`, printing what was handed over as text
+/// or, with `json`, as a `RotateOwnerOutput`.
+///
+/// A rotation that did not complete is saved in the configuration directory and resumed with the
+/// providers it left pending the next time it is run. Once every transfer has completed, the new
+/// key replaces the persisted identity key if `owner` is that key.
+///
+/// # Arguments
+/// * `network_client` - The client of the node of `owner`.
+/// * `owner` - The identity key the shares are registered under.
+/// * `new_key` - The identity key to hand the shares over to.
+/// * `config` - The configuration whose directory keeps the identity key and the rotation report.
+/// * `persisted` - Whether `owner` is the identity key persisted in `config`.
+/// * `json` - Whether to print JSON.
+/// * `ready` - When the network is ready to look the providers up.
+/// * `timeout` - How long to wait for the network and each lookup.
+///
+/// # Returns
+/// An error if no provider was found, or a rotation to another key is pending.
+#[allow(clippy::too_many_arguments)]
+async fn rotate_identity(
+    mut network_client: Client,
+    owner: &Keypair,
+    new_key: &Keypair,
+    config: &ShardConfig,
+    persisted: bool,
+    json: bool,
+    ready: &ReadyCriteria,
+    timeout: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let old_owner = owner.public().to_peer_id();
+    let new_owner = new_key.public().to_peer_id();
+    if old_owner == new_owner {
+        let message = format!("{new_owner} is the identity already");
+        return Err(CliError::new(ErrorKind::Usage, message).into());
+    }
+    let path = config.dir.join(ROTATION_REPORT_FILE);
+    // a report left by another identity is of a rotation this one cannot resume
+    let previous =
+        RotationReport::load(&path)?.filter(|report| report.old_owner == old_owner.to_string());
+    if let Some(report) = previous
+        .as_ref()
+        .filter(|r| r.new_owner != new_owner.to_string())
+    {
+        let message = format!(
+            "a rotation to {} is pending, resume it with that key first",
+            report.new_owner
+        );
+        return Err(CliError::new(ErrorKind::Usage, message).into());
+    }
+
+    network_client.await_ready(ready, timeout).await?;
+    let providers: Vec<PeerId> = match &previous {
+        Some(report) => report.pending_providers(),
+        None => {
+            let lookup = network_client.get_all_providers();
+            lookup_providers(lookup, timeout)
+                .await?
+                .into_iter()
+                .collect()
+        }
+    };
+    if providers.is_empty() && previous.is_none() {
+        let message = "Could not find any providers to hand the shares over on.";
+        return Err(CliError::new(ErrorKind::NoProviders, message).into());
+    }
+
+    let rotated = rotate_owner(&network_client, owner, new_key, providers).await;
+    let resumed = previous.is_some();
+    let report = match previous {
+        Some(mut report) => {
+            report.merge(rotated);
+            report
+        }
+        None => rotated,
+    };
+    let identity_replaced = report.is_complete() && persisted;
+    if report.is_complete() {
+        if persisted {
+            config.replace_key(new_key)?;
+        }
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+    } else {
+        report.save(&path)?;
+    }
 
+    if json {
+        let output = RotateOwnerOutput {
+            report,
+            resumed,
+            identity_replaced,
+        };
+        println!("{}", to_json(&output)?);
+        return Ok(());
+    }
+    println!(
+        "🔁 Handed {} shares over to {}",
+        report.transferred.len(),
+        new_owner
+    );
+    for pending in &report.pending {
+        match &pending.key {
+            Some(key) => println!(
+                "⚠️ Provider {} did not hand over {:?}: {}",
+                pending.provider, key, pending.reason
+            ),
+            None => println!(
+                "⚠️ Provider {} did not hand over its shares: {}",
+                pending.provider, pending.reason
+            ),
+        }
+    }
+    if !report.is_complete() {
+        println!("⚠️ Run rotate-owner again with the same key to resume the rotation");
+    } else if identity_replaced {
+        println!(
+            "🔑 {} is the identity now, {} was kept in {}",
+            new_owner,
+            old_owner,
+            config.dir.join(RETIRED_KEY_FILE).display()
+        );
+    }
     Ok(())
 }
 
-fn get_sender() -> PeerId {
-    // create a deterministic peer id from a fixed value
-    let mut bytes = [0u8; 32];
-    bytes[0] = 42;
-    let id_keys = libp2p::identity::Keypair::ed25519_from_bytes(bytes).unwrap();
-    //let id_keys = libp2p::identity::Keypair::generate_ed25519();
-    id_keys.public().to_peer_id()
+/// Opens the span a split, combine or refresh runs in, for the requests it sends to be traced
+/// across the providers that log them.
+///
+/// # Arguments
+/// * `argument` - The command run.
+/// * `trace_id` - The trace id the requests of the command are sent with.
+///
+/// # Returns
+/// The span of the operation, or a disabled span for any other command.
+fn operation_span(argument: &CliArgument, trace_id: TraceId) -> Span {
+    match argument {
+        CliArgument::Split { key, .. } => info_span!("split", %trace_id, key = key.as_deref()),
+        CliArgument::Combine { key, .. } => info_span!("combine", %trace_id, key = key.as_deref()),
+        CliArgument::Refresh { key, .. } => info_span!("refresh", %trace_id, key = key.as_deref()),
+        _ => Span::none(),
+    }
+}
+
+/// Prints the error a command failed with on stderr, as text or, with `json`, as an
+/// `ErrorOutput`.
+///
+/// # Returns
+/// The code the process exits with for the kind of the error.
+fn report_error(error: &(dyn Error + 'static), json: bool, out: &mut dyn Write) -> u8 {
+    let kind = classify(error);
+    let _ = match json {
+        true => {
+            let output = ErrorOutput {
+                error: ErrorDetail {
+                    code: kind.exit_code(),
+                    kind: kind.name().to_string(),
+                    message: error.to_string(),
+                },
+            };
+            writeln!(out, "{}", to_json(&output).unwrap_or_default())
+        }
+        false => writeln!(out, "Error: {}", error),
+    };
+    kind.exit_code()
+}
+
+/// Runs `provide` as a `ShardNode` until SIGTERM or Ctrl-C, printing the addresses it listens
+/// on and, with `--print-events`, what it does.
+///
+/// # Arguments
+/// * `argument` - The `provide` command.
+/// * `network` - How the provider joins the network.
+/// * `config` - The configuration, whose bootstrappers and denied peers are reloaded on SIGHUP.
+/// * `json` - Whether to print as JSON.
+///
+/// # Returns
+/// An error if the provider cannot be started or cannot start providing its DAO.
+async fn provide(
+    argument: CliArgument,
+    network: NetworkConfig,
+    config: &ShardConfig,
+    json: bool,
+) -> Result<(), Box<dyn Error>> {
+    let CliArgument::Provide {
+        db_path,
+        db_backend,
+        db_encryption_key_file,
+        flush_policy,
+        snapshot_path,
+        isolate_owners,
+        max_entries_per_owner,
+        max_bytes_per_owner,
+        max_total_bytes,
+        audit_log,
+        audit_retention,
+        refresh_interval,
+        refresh_jitter,
+        replication_margin,
+        status_interval,
+        max_share_bytes,
+        rate_limit,
+        rate_limit_burst,
+        shutdown_grace,
+        pid_file,
+        print_events,
+        #[cfg(feature = "metrics")]
+        metrics_addr,
+        ..
+    } = argument
+    else {
+        unreachable!("only provide runs a provider");
+    };
+    let audit = open_audit(audit_log.as_deref(), audit_retention)?;
+    let dao_options = dao_options(
+        db_path,
+        db_backend,
+        db_encryption_key_file,
+        flush_policy.unwrap_or_default(),
+        snapshot_path,
+        DaoQuotas {
+            max_entries_per_owner,
+            max_bytes_per_owner,
+            max_total_bytes,
+        },
+        false,
+        isolate_owners,
+    )?;
+
+    let mut builder = ShardNode::builder()
+        .config(network)
+        .dao_options(dao_options)
+        .audit(audit);
+    if let Some(interval) = refresh_interval {
+        builder = builder.refresh_interval(Duration::from_secs(interval));
+    }
+    if let Some(percent) = refresh_jitter {
+        builder = builder.refresh_jitter(percent);
+    }
+    if let Some(margin) = replication_margin {
+        builder = builder.replication_margin(margin);
+    }
+    if let Some(interval) = status_interval {
+        builder = builder.status_interval(Duration::from_secs(interval));
+    }
+    if let Some(bytes) = max_share_bytes {
+        builder = builder.max_share_bytes(bytes);
+    }
+    if let Some(per_second) = rate_limit {
+        builder = builder.rate_limit(RateLimit {
+            burst: rate_limit_burst.unwrap_or(DEFAULT_RATE_LIMIT_BURST),
+            per_second,
+        });
+    }
+    if let Some(grace) = shutdown_grace {
+        builder = builder.shutdown_grace(Duration::from_secs(grace));
+    }
+    if print_events {
+        builder = builder.events(PROVIDER_EVENT_BUFFER);
+    }
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = metrics_addr {
+        builder = builder.metrics_addr(addr);
+    }
+
+    if let Some(path) = &pid_file {
+        std::fs::write(path, format!("{}\n", std::process::id()))
+            .map_err(|e| format!("cannot write the pid file {}: {}", path.display(), e))?;
+    }
+    let mut node = builder.build().await?;
+
+    // the bootstrappers and the denied peers are reloaded from conf.toml on SIGHUP
+    if !config.from_env {
+        spawn(watch_config(
+            config.clone(),
+            node.peer_id(),
+            node.client(),
+            reload_signals(),
+        ));
+    }
+
+    if json {
+        let output = ProvideOutput {
+            peer_id: node.peer_id().to_string(),
+            listen_addrs: node
+                .listen_addrs()
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        };
+        println!("{}", to_json(&output)?);
+    } else {
+        for addr in node.listen_addrs() {
+            println!(
+                "👂 Listening on {}",
+                addr.clone().with(Protocol::P2p(node.peer_id()))
+            );
+        }
+    }
+
+    if let Some(mut events) = node.events() {
+        spawn(async move {
+            while let Some(event) = events.recv().await {
+                if let Err(e) = print_provider_event(&event, json, &mut std::io::stdout()) {
+                    error!("Could not print the provider event {event}: {e}");
+                }
+            }
+        });
+    }
+
+    // stop providing cleanly on SIGTERM or Ctrl-C
+    let stopped = tokio::select! {
+        () = shutdown_signal() => Ok(()),
+        stopped = node.stopped() => stopped,
+    };
+    let shutdown = node.shutdown().await;
+    if let Some(path) = &pid_file {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(stopped.and(shutdown)?)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // the argument parser exits with 2 on usage errors itself
+    let opt = Opt::parse();
+
+    // logs go to stderr, so that they never mix with what a command prints
+    let rust_log = std::env::var(EnvFilter::DEFAULT_ENV).ok();
+    let filter = log_filter(
+        opt.verbosity,
+        opt.quiet,
+        default_level(&opt.argument),
+        rust_log.as_deref(),
+    );
+    let format = opt.log_format.unwrap_or_default();
+    let _ = subscriber(filter, format, std::io::stderr).try_init();
+
+    let json = opt.json;
+    match run(opt).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => ExitCode::from(report_error(&*e, json, &mut std::io::stderr())),
+    }
+}
+
+/// Runs the command `opt` asks for.
+async fn run(mut opt: Opt) -> Result<(), Box<dyn Error>> {
+    if let CliArgument::Completions { shell } = opt.argument {
+        write_completions(shell, "shard", &mut std::io::stdout());
+        return Ok(());
+    }
+
+    // share files are split, combined and refreshed without joining the network
+    if matches!(
+        opt.argument,
+        CliArgument::Split { offline: true, .. }
+            | CliArgument::Combine { offline: true, .. }
+            | CliArgument::Refresh { offline: true, .. }
+    ) {
+        return run_offline(opt.argument, opt.json);
+    }
+
+    // clients connect with the identity their shares are registered under, so that providers can
+    // check it against the sender of their requests
+    // profiles are listed without creating the default one
+    if let CliArgument::Profile {
+        command: ProfileCommand::List,
+    } = opt.argument
+    {
+        if opt.no_config {
+            let message = "profiles are kept in the configuration directory, which --no-config \
+                           has none of";
+            return Err(CliError::new(ErrorKind::Usage, message).into());
+        }
+        let output = list_profiles(&ShardConfig::resolve_dir(opt.config.clone())?)?;
+        match opt.json {
+            true => println!("{}", to_json(&output)?),
+            false => print_profiles(&output, &mut std::io::stdout())?,
+        }
+        return Ok(());
+    }
+
+    let (config, config_given) = load_config(&opt)?;
+    // the configuration is shown and changed without joining the network
+    if let CliArgument::Config { command } = &opt.argument {
+        return configure(config, command, opt.json, &mut std::io::stdout());
+    }
+    if let CliArgument::Provide {
+        db_path,
+        db_backend,
+        snapshot_path,
+        refresh_interval,
+        ..
+    } = &mut opt.argument
+    {
+        let in_memory = snapshot_path.is_some() || *db_backend == Some(DbBackend::Memory);
+        resolve_provider_settings(db_path, refresh_interval, in_memory, &config)?;
+    }
+
+    // Maintenance operations run against the database alone and exit without joining the network.
+    // Provider records are only held in memory, so a purge needs no DHT cleanup.
+    if let CliArgument::Provide {
+        db_path,
+        db_backend,
+        db_encryption_key_file,
+        flush_policy,
+        snapshot_path,
+        isolate_owners,
+        export,
+        import,
+        on_conflict,
+        purge_owner,
+        tombstone_window,
+        stats,
+        scan_only,
+        audit_log,
+        audit_retention,
+        dump_audit,
+        ..
+    } = &opt.argument
+    {
+        let audit = open_audit(audit_log.as_deref(), *audit_retention)?;
+        if let (true, Some(audit)) = (*dump_audit, &audit) {
+            for record in audit.read_range(0, u64::MAX)? {
+                println!("{}", record);
+            }
+            match audit.verify() {
+                Ok(()) => println!("audit chain intact"),
+                Err(e) => println!("audit chain broken: {}", e),
+            }
+            return Ok(());
+        }
+        if export.is_some() || import.is_some() || purge_owner.is_some() || *stats || *scan_only {
+            // exports, stats and scans only read, so an embedded database is opened read-only for
+            // them
+            let read_only = import.is_none()
+                && purge_owner.is_none()
+                && db_path.is_some()
+                && db_backend.unwrap_or(DbBackend::Sled) == DbBackend::Sled;
+            let dao = open_dao(
+                db_path.clone(),
+                *db_backend,
+                db_encryption_key_file.clone(),
+                flush_policy.unwrap_or_default(),
+                snapshot_path.clone(),
+                // operators restoring or inspecting the database are not held to owner quotas
+                DaoQuotas::default(),
+                read_only,
+                *isolate_owners,
+            )?;
+            if *scan_only {
+                let report = scan_integrity(&dao, false)?;
+                println!("{}", report);
+                for key in &report.corrupt {
+                    println!("  corrupt: {}", key);
+                }
+                for key in &report.bad_owner {
+                    println!("  unparsable owner: {}", key);
+                }
+            }
+            let dao = dao.lock().unwrap();
+            if let Some(path) = export {
+                // the dump holds every share, so it is never written over another file
+                let file = create_private(path)
+                    .map_err(|e| format!("cannot write the export to {}: {}", path.display(), e))?;
+                let count = dao.export(&mut BufWriter::new(file))?;
+                println!("exported {} entries to {}", count, path.display());
+            }
+            if let Some(path) = import {
+                let report = dao.import(&mut BufReader::new(File::open(path)?), *on_conflict)?;
+                println!("{}", report);
+            }
+            if let Some(owner) = purge_owner {
+                let window = tombstone_window.unwrap_or(DEFAULT_TOMBSTONE_SECONDS);
+                let purged = dao.tombstone_by_owner(
+                    &owner.to_bytes(),
+                    "owner purged",
+                    now_unix(),
+                    Duration::from_secs(window),
+                )?;
+                for key in &purged {
+                    record_audit(
+                        &audit,
+                        AuditOperation::Delete,
+                        key,
+                        None,
+                        AuditOutcome::Success,
+                    );
+                }
+                println!("purged {} shares owned by {}", purged.len(), owner);
+            }
+            if *stats {
+                let stats = dao.stats()?;
+                println!("{}", stats);
+                for (owner, count) in &stats.per_owner {
+                    match PeerId::from_bytes(owner) {
+                        Ok(owner) => println!("  {}: {}", owner, count),
+                        Err(_) => println!("  {:02x?}: {}", owner, count),
+                    }
+                }
+            }
+            dao.flush()?;
+            if let Some(audit) = &audit {
+                audit.flush()?;
+            }
+            return Ok(());
+        }
+    }
+
+    // the identity key is managed without joining the network
+    if let CliArgument::Keygen { force, show } = opt.argument {
+        if config.from_env {
+            let message = "keygen persists the key in the configuration directory, \
+                           which --no-config has none of";
+            return Err(CliError::new(ErrorKind::Usage, message).into());
+        }
+        let output = keygen(&config, force, show)?;
+        if opt.json {
+            println!("{}", to_json(&output)?);
+        } else if output.generated {
+            println!(
+                "🔑 Generated identity {} in {}",
+                output.peer_id, output.key_file
+            );
+        } else {
+            println!("🔑 Identity {} in {}", output.peer_id, output.key_file);
+        }
+        return Ok(());
+    }
+
+    // several providers are often started from one home directory, so they only share its key
+    // when pointed at the configuration, or given a key in the environment, explicitly
+    let identity = match (&opt.argument, opt.secret_key_seed) {
+        (CliArgument::Provide { .. }, Some(seed)) => network::seeded_keypair(seed),
+        (CliArgument::Provide { .. }, None) if config_given => config.key_or_generate()?,
+        (CliArgument::Provide { .. }, None) => Keypair::generate_ed25519(),
+        _ => client_identity(opt.secret_key_seed, opt.legacy_sender, &config)?,
+    };
+    // the old key keeps signing the transfers of a rotation once the node runs as that identity
+    let rotation = match &opt.argument {
+        CliArgument::RotateOwner { new_key } => {
+            if config.from_env {
+                let message = "rotate-owner replaces the key in the configuration directory, \
+                               which --no-config has none of";
+                return Err(CliError::new(ErrorKind::Usage, message).into());
+            }
+            let dir = ShardConfig::resolve_dir(opt.config.clone())?;
+            Some((identity.clone(), rotation_key(new_key, &dir)?))
+        }
+        _ => None,
+    };
+    let timeout = resolve(
+        opt.timeout,
+        env_setting(REQUEST_TIMEOUT_ENV)?,
+        config.request_timeout_secs,
+        Some(DEFAULT_TIMEOUT_SECONDS),
+    );
+    let timeout = Duration::from_secs(timeout.unwrap_or(DEFAULT_TIMEOUT_SECONDS));
+    let network_id = resolve(
+        opt.network_id.clone(),
+        env_setting(NETWORK_ID_ENV)?,
+        config.network_id.clone(),
+        None,
+    );
+    // In case listen addresses were provided use them, otherwise listen on any
+    // address.
+    let listen_addrs = resolve(
+        Some(opt.listen_address).filter(|addrs| !addrs.is_empty()),
+        env_list(LISTEN_ADDRESSES_ENV)?,
+        Some(config.listen_addresses.clone()).filter(|addrs| !addrs.is_empty()),
+        None,
+    );
+    // --external-address only swaps the IP of the listen addresses, these are announced as given
+    let external_addrs = resolve(
+        None,
+        env_list(EXTERNAL_ADDRESSES_ENV)?,
+        Some(config.external_addresses.clone()).filter(|addrs| !addrs.is_empty()),
+        None,
+    );
+    // In case the user provided an address of a peer on the CLI, dial it instead of the
+    // bootstrappers, which come from the environment or else the configuration.
+    let configured = Some(config.bootstrap_addrs()).filter(|addrs| !addrs.is_empty());
+    let mut from_env: Vec<Multiaddr> = env_setting(BOOTSTRAPPER_ENV)?.into_iter().collect();
+    from_env.extend(env_list(BOOTSTRAPPERS_ENV)?.unwrap_or_default());
+    let from_env = Some(from_env).filter(|addrs| !addrs.is_empty());
+    let bootstrappers = resolve(None, from_env, configured, None).unwrap_or_default();
+    let peer = match opt.peer {
+        Some(addr) => {
+            let Some(Protocol::P2p(peer_id)) = addr.iter().last() else {
+                let message = "Expect peer multiaddr to contain peer ID.";
+                return Err(CliError::new(ErrorKind::Usage, message).into());
+            };
+            Some((peer_id, addr))
+        }
+        None => None,
+    };
+
+    if let CliArgument::Provide { .. } = opt.argument {
+        let network_config = NetworkConfig {
+            keypair: identity,
+            network_id,
+            timeout,
+            listen_addrs: listen_addrs.unwrap_or_default(),
+            external_addrs: external_addrs.unwrap_or_default(),
+            external_address: opt.external_address,
+            // a provider pointed at a peer bootstraps from it alone
+            bootstrappers: peer.map_or(bootstrappers, |(_, addr)| vec![addr]),
+            blocked_peers: config.blocked_peers(),
+        };
+        return provide(opt.argument, network_config, &config, opt.json).await;
+    }
+
+    // the shares a client splits are registered under its identity, which signs their grants
+    let owner = identity.clone();
+    // the network events are only read by providers
+    let (mut network_client, _network_events, network_event_loop, local_peer_id) =
+        network::with_network(identity, timeout, network_id.as_deref()).await?;
+    let sender = local_peer_id;
+    debug!("sender ID: {}", sender);
+
+    // Spawn the network task for it to run in the background.
+    spawn(network_event_loop.run(opt.external_address));
+    // denied peers are refused before any connection is made
+    for peer in config.blocked_peers() {
+        network_client.block_peer(peer).await;
+    }
+    start_listeners(&mut network_client, listen_addrs.unwrap_or_default()).await?;
+    for addr in external_addrs.unwrap_or_default() {
+        network_client.add_external_address(addr).await;
+    }
+
+    let dialed = peer.is_some() || !bootstrappers.is_empty();
+    if let Some((peer_id, addr)) = peer {
+        debug!("Dialing peer at {}.", addr);
+        tokio::time::timeout(timeout, network_client.dial(peer_id, addr.clone()))
+            .await
+            .map_err(|_| CliError::new(ErrorKind::Timeout, format!("timed out dialing {}", addr)))?
+            .map_err(|e| {
+                CliError::new(ErrorKind::Network, format!("could not dial {addr}: {e}"))
+            })?;
+    } else if !bootstrappers.is_empty() {
+        let connected = tokio::time::timeout(
+            timeout,
+            network_client.bootstrap(&bootstrappers, local_peer_id),
+        )
+        .await
+        .map_err(|_| CliError::new(ErrorKind::Timeout, "timed out dialing the bootstrappers"))?;
+        debug!(
+            "Connected to {} of {} bootstrappers.",
+            connected,
+            bootstrappers.len()
+        );
+    }
+
+    // commands wait for a connection to the peers dialed and for the routing table to be
+    // bootstrapped from them, giving up once the timeout has passed
+    let ready = ReadyCriteria {
+        min_connections: usize::from(dialed),
+        bootstrap: dialed,
+        ..Default::default()
+    };
+
+    let trace_id = TraceId::random();
+    let span = operation_span(&opt.argument, trace_id);
+    if !span.is_none() {
+        network_client = network_client.with_trace(trace_id);
+    }
+    // run is driven by block_on on the main thread alone, so the span is never entered on a
+    // thread it was not entered on across the awaits below
+    let _entered = span.enter();
+
+    match opt.argument {
+        // Locating and getting a share.
+        CliArgument::Combine {
+            key,
+            threshold,
+            verbose,
+            owner,
+            out,
+            format,
+            force,
+            provider,
+            exclude_peer,
+            share_id,
+            wait,
+            min_providers,
+            ..
+        } => {
+            let key = key.expect("--key is required without --offline");
+            let selection = ShareSelection {
+                pinned: pin_providers(&mut network_client, provider).await?,
+                excluded: exclude_peer,
+                share_ids: share_id,
+            };
+            let wait = ProviderWait {
+                min_providers: min_providers.unwrap_or(0),
+                budget: wait.map_or(timeout, Duration::from_secs),
+                report: wait.is_some(),
+            };
+            let shares_map = combine_secret(
+                &network_client,
+                sender,
+                owner,
+                &key,
+                threshold,
+                &selection,
+                &ready,
+                wait,
+            )
+            .await?;
+
+            let secret = shares_map.combine(&key);
+
+            // if the debug flag is set, print the shares
+            if verbose && opt.json {
+                print_shares(&shares_map.shares, &mut std::io::stderr())?;
+            } else if verbose {
+                print_shares(&shares_map.shares, &mut std::io::stdout())?;
+            }
+
+            let secret = secret?;
+            if let Some(manifest) = ChunkManifest::from_bytes(&secret) {
+                let manifest = manifest?;
+                let out = out.ok_or_else(|| {
+                    let message = "the key holds a file split in chunks, pass --out";
+                    CliError::new(ErrorKind::Usage, message)
+                })?;
+                let mut progress = chunk_progress("combined");
+                combine_file(
+                    &network_client,
+                    sender,
+                    owner,
+                    &key,
+                    &manifest,
+                    &selection,
+                    &out,
+                    &mut progress,
+                )
+                .await?;
+                if opt.json {
+                    let output = CombineOutput {
+                        key,
+                        secret_b64: None,
+                        out: Some(out.display().to_string()),
+                        shares_used: manifest.threshold,
+                        chunks: Some(manifest.chunks()),
+                    };
+                    println!("{}", to_json(&output)?);
+                } else {
+                    println!(
+                        "🔑 file of {} bytes written to {}",
+                        manifest.length,
+                        out.display()
+                    );
+                }
+                return Ok(());
+            }
+            print_combined(
+                key,
+                &secret,
+                shares_map.shares.len(),
+                out,
+                format,
+                force,
+                opt.json,
+            )?;
+        }
+
+        // Splitting a secret.
+        CliArgument::Split {
+            threshold,
+            shares,
+            secret,
+            secret_file,
+            interactive,
+            file,
+            chunk_bytes,
+            trim_newline,
+            key,
+            ttl,
+            recreate,
+            refresh_every,
+            replace,
+            force,
+            no_verify,
+            provider,
+            dry_run,
+            verbose,
+            ..
+        } => {
+            // the secret is asked for before the network is searched, which can take a while
+            let prompted = interactive
+                .then(|| prompt::confirmed_secret(SECRET_PROMPT))
+                .transpose()?;
+            // a random key is unused, no lookup needed
+            let check_key = key.is_some() && !force && !dry_run;
+            // if key is None assign a random key
+            let key = key.unwrap_or_else(random_key);
+            span.record("key", key.as_str());
+
+            let pinned = pin_providers(&mut network_client, provider).await?;
+            let available = discover_split_providers(
+                &mut network_client,
+                &ready,
+                &pinned,
+                shares,
+                timeout,
+                dry_run,
+            )
+            .await?;
+
+            if dry_run {
+                let (share_bytes, chunks) = match &file {
+                    Some(path) => {
+                        // checks the threshold and shares the way each chunk is split
+                        SplitShares::split(&[], threshold, shares)?;
+                        let len = std::fs::metadata(path)
+                            .map_err(|e| format!("cannot read the file {}: {}", path.display(), e))?
+                            .len();
+                        let chunk = chunk_bytes.unwrap_or(DEFAULT_CHUNK_BYTES);
+                        (len.min(chunk), Some(len.div_ceil(chunk)))
+                    }
+                    None => {
+                        let stdin = std::io::stdin().lock();
+                        let secret = match prompted {
+                            Some(prompted) => prompted,
+                            None => {
+                                read_secret(secret, secret_file.as_deref(), trim_newline, stdin)?
+                            }
+                        };
+                        SplitShares::split(&secret, threshold, shares)?;
+                        (secret.len() as u64, None)
+                    }
+                };
+                let (plan, infeasible) = plan_split(
+                    &key,
+                    threshold,
+                    shares,
+                    share_bytes,
+                    chunks,
+                    available,
+                    &pinned,
+                );
+                match opt.json {
+                    true => println!("{}", to_json(&plan)?),
+                    false => print_split_plan(&plan, &mut std::io::stdout())?,
+                }
+                return infeasible.map_or(Ok(()), |e| Err(e.into()));
+            }
+
+            if check_key {
+                check_key_unused(&network_client, sender, &key, timeout).await?;
+            }
+
+            let options = ShareOptions {
+                threshold,
+                ttl,
+                recreate,
+                refresh_every,
+                replace,
+            };
+
+            if let Some(path) = file {
+                let (providers, _) = choose_providers(available, &pinned, shares)?;
+                let mut show = chunk_progress("split");
+                let manifest = split_file(
+                    &network_client,
+                    &owner,
+                    &path,
+                    &key,
+                    providers,
+                    chunk_bytes.unwrap_or(DEFAULT_CHUNK_BYTES),
+                    options,
+                    &mut |done, total| {
+                        show(done, total);
+                        Ok(())
+                    },
+                )
+                .await?;
+
+                if opt.json {
+                    let providers = manifest
+                        .providers
+                        .iter()
+                        .map(|peer| ProviderOutcome {
+                            peer: peer.clone(),
+                            status: RegistrationOutcome::Registered,
+                            reason: None,
+                        })
+                        .collect();
+                    let output = SplitOutput {
+                        key,
+                        threshold,
+                        shares,
+                        providers,
+                        chunks: Some(manifest.chunks()),
+                    };
+                    println!("{}", to_json(&output)?);
+                    return Ok(());
+                }
+                println!(
+                    "✂️  File has been split in {} chunks and distributed across network.",
+                    manifest.chunks()
+                );
+                println!("    key: {:#?}", key);
+                println!("    threshold: {:#?}", threshold);
+                println!("    providers: {:#?}", manifest.providers);
+                return Ok(());
+            }
+
+            let secret = match prompted {
+                Some(prompted) => prompted,
+                None => read_secret(
+                    secret,
+                    secret_file.as_deref(),
+                    trim_newline,
+                    std::io::stdin().lock(),
+                )?,
+            };
+            let split_shares = SplitShares::split(&secret, threshold, shares)?;
+            debug!("Shares: {:?}", split_shares);
+            let (providers_sample, spare_providers) = choose_providers(available, &pinned, shares)?;
+
+            let (placed, mut outcomes) = register_shares(
+                &network_client,
+                &owner,
+                &key,
+                &split_shares,
+                &providers_sample,
+                spare_providers.clone(),
+                options,
+            )
+            .await;
+            let registered = placed.len();
+            let placed = match no_verify {
+                true => placed,
+                false => {
+                    // spares register_shares stood in with hold a share already
+                    let spares = spare_providers
+                        .into_iter()
+                        .filter(|spare| !placed.iter().any(|(_, peer)| peer == spare))
+                        .collect();
+                    verify_shares(
+                        &network_client,
+                        &owner,
+                        &key,
+                        &split_shares,
+                        placed,
+                        spares,
+                        options,
+                        &mut outcomes,
+                    )
+                    .await
+                }
+            };
+            if placed.len() < registered {
+                return Err(format!(
+                    "{} of the shares could not be verified on any provider:{}",
+                    registered - placed.len(),
+                    outcome_report(&outcomes)
+                )
+                .into());
+            }
+            let providers_sample: Vec<PeerId> = placed.into_iter().map(|(_, peer)| peer).collect();
+            if !pinned.is_empty() && providers_sample.len() < shares {
+                return Err(format!(
+                    "not every pinned provider registered its share:{}",
+                    outcome_report(&outcomes)
+                )
+                .into());
+            }
+
+            if verbose && opt.json {
+                print_shares(&split_shares.shares, &mut std::io::stderr())?;
+            } else if verbose {
+                print_shares(&split_shares.shares, &mut std::io::stdout())?;
+            }
+
+            if opt.json {
+                let output = SplitOutput {
+                    key,
+                    threshold,
+                    shares,
+                    providers: outcomes,
+                    chunks: None,
+                };
+                println!("{}", to_json(&output)?);
+                return Ok(());
+            }
+            println!("✂️  Secret has been split and distributed across network.");
+            println!("    key: {:#?}", key);
+            println!("    threshold: {:#?}", threshold);
+            println!("    providers: {:#?}", providers_sample)
+        }
+        CliArgument::Ls { key, watch, expect } => {
+            network_client.await_ready(&ready, timeout).await?;
+            if let Some(interval) = watch {
+                let interval = Duration::from_secs(interval.unwrap_or(DEFAULT_WATCH_SECONDS));
+                let mut stdout = std::io::stdout();
+                let watch = watch_ls(
+                    &network_client,
+                    sender,
+                    &key,
+                    interval,
+                    expect,
+                    timeout,
+                    opt.json,
+                    &mut stdout,
+                );
+                // Ctrl-C ends a watch as it was meant to
+                tokio::select! {
+                    result = watch => result?,
+                    () = shutdown_signal() => {}
+                }
+                return Ok(());
+            }
+            let lookup = network_client.get_providers(Client::provider_key(&sender, &key));
+            let providers = lookup_providers(lookup, timeout).await?;
+            if providers.is_empty() {
+                return Err(no_providers(&key));
+            }
+
+            if opt.json {
+                let output = LsOutput {
+                    key,
+                    providers: peer_ids(providers),
+                };
+                println!("{}", to_json(&output)?);
+                return Ok(());
+            }
+            println!("✂️  Share Providers: {:#?}", providers);
+        }
+        CliArgument::Keys { provider } => {
+            let providers = match provider {
+                Some(provider) => HashSet::from([provider]),
+                None => {
+                    network_client.await_ready(&ready, timeout).await?;
+                    lookup_providers(network_client.get_all_providers(), timeout).await?
+                }
+            };
+            if providers.is_empty() {
+                return Err(
+                    CliError::new(ErrorKind::NoProviders, "Could not find providers.").into(),
+                );
+            }
+
+            let output = collect_keys(&network_client, providers, sender).await;
+            if opt.json {
+                println!("{}", to_json(&output)?);
+                return Ok(());
+            }
+            print_keys(&output, &mut std::io::stdout())?;
+        }
+        CliArgument::Refresh {
+            key,
+            threshold,
+            size,
+            provider,
+            ..
+        } => {
+            let key = key.expect("--key is required without --offline");
+            let threshold = threshold.expect("--threshold is required without --offline");
+            let size = size.expect("--size is required without --offline");
+            let refresh_key = new_refresh_key(threshold, size)?;
+            network_client.await_ready(&ready, timeout).await?;
+            let pinned = pin_providers(&mut network_client, provider).await?;
+            let providers: HashSet<PeerId> = match pinned.is_empty() {
+                true => {
+                    let lookup = network_client.get_providers(Client::provider_key(&sender, &key));
+                    lookup_providers(lookup, timeout).await?
+                }
+                false => pinned.into_iter().collect(),
+            };
+            if providers.is_empty() {
+                return Err(no_providers(&key));
+            }
+
+            debug!("Found {} providers for share {}.", providers.len(), key);
+
+            debug!("🔑 Refresh Key: {:#?}", refresh_key);
+
+            // every provider moves to the same epoch, one past the latest any of them is at, so
+            // a redelivered request is not applied twice
+            let epoch = Some(share_epoch(&network_client, sender, &key, &providers).await? + 1);
+
+            let requests = providers.clone().into_iter().map(|p| {
+                let k = key.clone();
+                let ref_key = refresh_key.clone();
+                let mut network_client = network_client.clone();
+                debug!("🔄 Refreshing share for key: {:?} to peer {:?}", &k, p);
+                async move {
+                    let result = network_client
+                        .request_refresh_shares(k, ref_key, p, sender, epoch, None)
+                        .await;
+                    (p, result)
+                }
+                .boxed()
+            });
+
+            // Await all of the requests, sorting the providers by whether they applied it
+            let mut refreshed = Vec::new();
+            let mut failed = Vec::new();
+            for (peer, result) in futures::future::join_all(requests).await {
+                match result {
+                    Ok(true) => refreshed.push(peer),
+                    Ok(false) => failed.push(peer),
+                    Err(e) => {
+                        error!("Error: {:?}", e);
+                        failed.push(peer);
+                    }
+                }
+            }
+
+            let partial = match failed.is_empty() {
+                true => None,
+                false => Some(partial_refresh(&key, refreshed.len(), &failed)),
+            };
+            if opt.json {
+                let output = RefreshOutput {
+                    key,
+                    refreshed: peer_ids(refreshed),
+                    failed: peer_ids(failed),
+                };
+                println!("{}", to_json(&output)?);
+            } else {
+                println!(
+                    "🔄 Refreshed {} shares for key: {:?}, {} failed",
+                    refreshed.len(),
+                    &key,
+                    failed.len()
+                );
+            }
+            if let Some(error) = partial {
+                return Err(error);
+            }
+        }
+        CliArgument::Grant { key, peer } => {
+            change_access(
+                network_client,
+                sender,
+                key,
+                peer,
+                true,
+                opt.json,
+                &ready,
+                timeout,
+            )
+            .await?;
+        }
+        CliArgument::Revoke { key, peer } => {
+            change_access(
+                network_client,
+                sender,
+                key,
+                peer,
+                false,
+                opt.json,
+                &ready,
+                timeout,
+            )
+            .await?;
+        }
+        CliArgument::RotateOwner { .. } => {
+            let (owner, new_key) = rotation.expect("the new key is read for rotate-owner");
+            let persisted = opt.secret_key_seed.is_none() && !opt.legacy_sender;
+            rotate_identity(
+                network_client,
+                &owner,
+                &new_key,
+                &config,
+                persisted,
+                opt.json,
+                &ready,
+                timeout,
+            )
+            .await?;
+        }
+        CliArgument::Info { network, watch } => {
+            wait_for_listen_addrs(&mut network_client).await;
+            if network {
+                // providers publish their status every period, so wait for a round of them
+                tokio::time::sleep(Duration::from_secs(DEFAULT_STATUS_SECONDS + 1)).await;
+            }
+            let stdout = std::io::stdout();
+            let clear = watch && !opt.json && stdout.is_terminal();
+            loop {
+                let providers = if network {
+                    let statuses = network_client.provider_statuses().await;
+                    Some(statuses.iter().map(ProviderInfo::from).collect())
+                } else {
+                    None
+                };
+                let output = InfoOutput::new(&network_client.network_info().await, providers);
+                if opt.json {
+                    println!("{}", to_json(&output)?);
+                } else {
+                    let mut stdout = stdout.lock();
+                    if clear {
+                        write!(stdout, "\x1b[2J\x1b[H")?;
+                    }
+                    print_info(&output, &mut stdout)?;
+                    stdout.flush()?;
+                }
+                if !watch {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+
+        CliArgument::Provide { .. }
+        | CliArgument::Keygen { .. }
+        | CliArgument::Config { .. }
+        | CliArgument::Profile { .. }
+        | CliArgument::Completions { .. } => {
+            unreachable!("returns before joining the network")
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shard::constants::LISTEN_ADDR_POLLS;
+    use shard::event::Event;
+    use shard::protocol::{Failure, Request};
+    use shard::provider::{run_loop, ProviderOptions};
+    use tokio_util::sync::CancellationToken;
+
+    #[test]
+    fn test_secret_from_stdin_is_read_byte_for_byte() {
+        let binary = b"\x00secret\xff\n".to_vec();
+        let read = |trim| {
+            read_secret(
+                Some(SecretArg("-".to_string())),
+                None,
+                trim,
+                binary.as_slice(),
+            )
+        };
+        assert_eq!(read(false).unwrap(), binary);
+        assert_eq!(read(true).unwrap(), b"\x00secret\xff");
+
+        let crlf = read_secret(
+            Some(SecretArg("-".to_string())),
+            None,
+            true,
+            b"secret\r\n\n".as_slice(),
+        );
+        assert_eq!(crlf.unwrap(), b"secret\r\n");
+    }
+
+    #[test]
+    fn test_secret_from_file_is_read_byte_for_byte() {
+        let path = std::env::temp_dir().join(format!("shard-secret-{}", rand::random::<u64>()));
+        let binary = b"\xff\x00\r\n".to_vec();
+        std::fs::write(&path, &binary).unwrap();
+
+        let stdin = std::io::empty();
+        assert_eq!(
+            read_secret(None, Some(&path), false, stdin).unwrap(),
+            binary
+        );
+        assert_eq!(
+            read_secret(None, Some(&path), true, stdin).unwrap(),
+            b"\xff\x00"
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        let e = read_secret(None, Some(&path), false, stdin).unwrap_err();
+        assert!(e.to_string().contains("cannot read the secret"), "{}", e);
+    }
+
+    #[test]
+    fn test_split_shares_combine() {
+        let shares = SplitShares::split(b"sorting hat", 2, 3).unwrap();
+        let mut fetched = SplitShares::default();
+        for index in [1, 3] {
+            fetched.insert(shares.get(index)).unwrap();
+        }
+        assert_eq!(fetched.combine("hat").unwrap(), b"sorting hat");
+
+        let altered = FieldShare::from((1, vec![0; 11]));
+        let err = fetched.insert(altered).unwrap_err();
+        assert_eq!(err, shard::sss::Error::DuplicateIndex(1));
+    }
+
+    #[cfg(feature = "sss16")]
+    #[test]
+    fn test_split_shares_combine_over_the_field_they_carry() {
+        let shares = SplitShares::split(b"sorting hat", 2, 3).unwrap();
+        assert_eq!(shares.field, ShareField::Gf256);
+        let mut fetched = SplitShares::default();
+        for index in [1, 3] {
+            fetched.insert(shares.get(index)).unwrap();
+        }
+
+        // a share of another field is not combined with them
+        let wide = FieldShare {
+            share: (2, vec![0, 1]),
+            field: ShareField::Gf65536,
+        };
+        let err = fetched.insert(wide).unwrap_err();
+        assert_eq!(err, shard::sss::Error::InconsistentShares);
+    }
+
+    #[cfg(feature = "sss16")]
+    #[test]
+    fn test_split_shares_beyond_gf256_are_split_over_gf65536() {
+        let shares = SplitShares::split(b"sorting hat", 3, 300).unwrap();
+        assert_eq!(shares.field, ShareField::Gf65536);
+        let mut fetched = SplitShares::default();
+        for index in [1, 150, 300] {
+            fetched.insert(shares.get(index)).unwrap();
+        }
+        assert_eq!(fetched.combine("hat").unwrap(), b"sorting hat");
+    }
+
+    #[test]
+    fn test_binary_secret_round_trips_through_out() {
+        let blob: Vec<u8> = (0..=255u8).rev().chain([0x00, 0xff, b'\n']).collect();
+        let shares = split_secret(&blob, 3, 5).unwrap();
+        let subset: HashMap<u8, Vec<u8>> = shares
+            .into_iter()
+            .filter(|(index, _)| *index != 2 && *index != 4)
+            .collect();
+        let secret = combine_shares(&subset).unwrap();
+
+        let path = std::env::temp_dir().join(format!("shard-out-{}", rand::random::<u64>()));
+        let mut stdout = Vec::new();
+        output_secret(
+            &secret,
+            Some(&path),
+            SecretFormat::Raw,
+            false,
+            &mut stdout,
+            true,
+        )
+        .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), blob);
+        assert!(String::from_utf8(stdout).unwrap().contains("written to"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // a secret is never written over a file that is there already
+        let again = output_secret(
+            b"other",
+            Some(&path),
+            SecretFormat::Raw,
+            false,
+            &mut Vec::new(),
+            true,
+        );
+        assert!(again
+            .unwrap_err()
+            .to_string()
+            .starts_with("cannot write the secret to"));
+        assert_eq!(std::fs::read(&path).unwrap(), blob);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_secret_formats() {
+        let print = |secret: &[u8], format, force, is_terminal| {
+            let mut stdout = Vec::new();
+            output_secret(secret, None, format, force, &mut stdout, is_terminal).map(|()| stdout)
+        };
+        let binary = [0x00, 0xff, 0x10];
+        assert_eq!(
+            print(&binary, SecretFormat::Raw, false, false).unwrap(),
+            binary
+        );
+        assert!(print(&binary, SecretFormat::Raw, false, true).is_err());
+        assert_eq!(
+            print(&binary, SecretFormat::Raw, true, true).unwrap(),
+            binary
+        );
+        assert_eq!(
+            print(&binary, SecretFormat::Hex, false, true).unwrap(),
+            b"00ff10\n"
+        );
+        assert_eq!(
+            print(&binary, SecretFormat::Base64, false, true).unwrap(),
+            b"AP8Q\n"
+        );
+        assert_eq!(
+            print(&binary, SecretFormat::Utf8, false, true).unwrap(),
+            "🔑 secret (not UTF-8, hex): 00ff10\n".as_bytes()
+        );
+        assert_eq!(
+            print(b"hunter2", SecretFormat::Utf8, false, true).unwrap(),
+            "🔑 secret: \"hunter2\"\n".as_bytes()
+        );
+
+        assert_eq!("base64".parse(), Ok(SecretFormat::Base64));
+        assert!("binary".parse::<SecretFormat>().is_err());
+    }
+
+    #[test]
+    fn test_literal_and_empty_secrets() {
+        let stdin = std::io::empty();
+        let literal = read_secret(Some(SecretArg("hunter2".to_string())), None, false, stdin);
+        assert_eq!(literal.unwrap(), b"hunter2");
+        assert!(read_secret(None, None, false, stdin).is_err());
+
+        let empty = read_secret(
+            Some(SecretArg("-".to_string())),
+            None,
+            true,
+            b"\n".as_slice(),
+        );
+        assert_eq!(empty.unwrap_err().to_string(), "the secret is empty");
+    }
+
+    #[test]
+    fn test_partial_refresh_lists_the_failed_providers() {
+        let failed = [PeerId::random(), PeerId::random()];
+        let err = partial_refresh("k", 3, &failed);
+        assert_eq!(classify(&*err), ErrorKind::PartialFailure);
+        let message = err.to_string();
+        assert!(message.starts_with("2 of 5 providers did not refresh their share of k:"));
+        assert!(failed
+            .iter()
+            .all(|peer| message.contains(&format!("\n  {peer}"))));
+        assert_eq!(report_error(&*err, false, &mut Vec::new()), 8);
+    }
+
+    #[test]
+    fn test_refresh_key_of_an_invalid_secret_is_a_usage_error() {
+        assert_eq!(new_refresh_key(3, 4).unwrap().len(), 4);
+        assert_eq!(new_refresh_key(1, 4).unwrap_err().kind, ErrorKind::Usage);
+        assert_eq!(new_refresh_key(3, 0).unwrap_err().kind, ErrorKind::Usage);
+    }
+
+    #[test]
+    fn test_client_identity() {
+        let dir = std::env::temp_dir().join(format!("shard-identity-{}", rand::random::<u64>()));
+        let config = ShardConfig::load(&dir).unwrap();
+        let peer = |key: Keypair| key.public().to_peer_id();
+
+        let own = peer(client_identity(None, false, &config).unwrap());
+        assert_eq!(own, peer(config.key().unwrap().unwrap()));
+        assert_eq!(own, peer(client_identity(None, false, &config).unwrap()));
+
+        let seeded = peer(client_identity(Some(7), false, &config).unwrap());
+        assert_eq!(seeded, peer(network::seeded_keypair(7)));
+        // with --no-config and no key in the environment, only a seed gives an identity
+        let keyless = ShardConfig {
+            from_env: true,
+            dir: PathBuf::new(),
+            ..config.clone()
+        };
+        assert_eq!(
+            seeded,
+            peer(client_identity(Some(7), false, &keyless).unwrap())
+        );
+        let err = client_identity(None, false, &keyless).unwrap_err();
+        assert_eq!(classify(&*err), ErrorKind::Usage);
+        let legacy = peer(client_identity(None, true, &config).unwrap());
+        assert_eq!(legacy, peer(network::seeded_keypair(LEGACY_SENDER_SEED)));
+        assert!(own != seeded && own != legacy);
+
+        let both = Opt::try_parse_from(["shard", "-s", "1", "--legacy-sender", "ls", "-k", "k"]);
+        assert!(both.is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_print_provider_event() {
+        let peer = PeerId::random();
+        let initiated = ProviderEvent::RefreshInitiated {
+            key: "k".to_string(),
+            peers: vec![peer],
+        };
+        let mut out = Vec::new();
+        print_provider_event(&initiated, false, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "📣 Provider initiated the refresh of k on 1 peers\n"
+        );
+
+        let mut out = Vec::new();
+        print_provider_event(&initiated, true, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "{{\"event\":\"refresh_initiated\",\"key\":\"k\",\"peers\":[\"{}\"]}}\n",
+                peer
+            )
+        );
+        let registered = ProviderEvent::Registered {
+            key: "k".to_string(),
+            owner: peer,
+        };
+        let mut out = Vec::new();
+        print_provider_event(&registered, true, &mut out).unwrap();
+        let line: ProviderEventOutput = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            line,
+            ProviderEventOutput::Registered {
+                key: "k".to_string(),
+                owner: peer.to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_events_stay_valid_while_handlers_run() {
+        let mut node = ShardNode::builder()
+            .config(NetworkConfig {
+                listen_addrs: vec!["/memory/0".parse().unwrap()],
+                ..Default::default()
+            })
+            .events(PROVIDER_EVENT_BUFFER)
+            .build()
+            .await
+            .unwrap();
+        let mut events = node.events().unwrap();
+        let provider = node.peer_id();
+
+        let (mut client, _events, event_loop, owner) = network::new(None).await.unwrap();
+        spawn(event_loop.run(None));
+        client
+            .start_listening("/memory/0".parse().unwrap())
+            .await
+            .unwrap();
+        client
+            .dial(provider, node.listen_addrs()[0].clone())
+            .await
+            .unwrap();
+
+        // a registration, a share served and a refusal each run a handler with an event
+        client
+            .request_register_share(
+                (1, b"share".to_vec()),
+                "key".to_string(),
+                2,
+                None,
+                false,
+                None,
+                false,
+                None,
+                provider,
+                owner,
+            )
+            .await
+            .unwrap();
+        let served = client
+            .request_share(provider, "key".to_string(), owner, None)
+            .await;
+        assert_eq!(served.unwrap(), (1, b"share".to_vec()));
+        let missing = client
+            .request_share(provider, "missing".to_string(), owner, None)
+            .await;
+        assert!(missing.is_err());
+
+        node.shutdown().await.unwrap();
+        let mut out = Vec::new();
+        let mut printed = 0;
+        while let Some(event) = events.recv().await {
+            print_provider_event(&event, true, &mut out).unwrap();
+            printed += 1;
+        }
+        assert_eq!(printed, 3);
+        // stdout holds nothing but the events, one JSON document per line
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let kinds: Vec<&str> = lines
+            .iter()
+            .map(|line| line["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, ["registered", "served", "rejected"]);
+    }
+
+    #[test]
+    fn test_print_keys() {
+        let output = KeysOutput {
+            keys: vec![
+                KeyListing {
+                    key: "a".to_string(),
+                    providers: vec!["p1".to_string(), "p2".to_string()],
+                    threshold: Some(2),
+                    last_refreshed_unix: Some(1700000000),
+                },
+                KeyListing {
+                    key: "b".to_string(),
+                    providers: vec!["p2".to_string()],
+                    threshold: None,
+                    last_refreshed_unix: None,
+                },
+            ],
+            unanswered: vec![UnansweredProvider {
+                peer: "p3".to_string(),
+                reason: "timeout".to_string(),
+            }],
+        };
+        let mut out = Vec::new();
+        print_keys(&output, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "🗝️  2 keys:\n",
+                "  a: threshold 2, last refreshed at 1700000000\n",
+                "    p1\n    p2\n",
+                "  b:\n",
+                "    p2\n",
+                "⚠️ Provider p3 did not answer: timeout\n"
+            )
+        );
+
+        let mut out = Vec::new();
+        let empty = KeysOutput {
+            keys: vec![],
+            unanswered: vec![],
+        };
+        print_keys(&empty, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "🗝️  No keys found.\n");
+    }
+
+    /// Starts a provider with a memory database listening on a free local port, running
+    /// `run_loop` on `local` until the test ends.
+    async fn spawn_provider(local: &tokio::task::LocalSet) -> (PeerId, Multiaddr) {
+        let (mut client, events, event_loop, provider) = network::new(None).await.unwrap();
+        spawn(event_loop.run(None));
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap();
+        client.start_listening(addr.clone()).await.unwrap();
+        local.spawn_local(async move {
+            run_loop(
+                DaoOptions::default(),
+                ProviderOptions::default(),
+                provider,
+                &mut client,
+                events,
+                CancellationToken::new(),
+            )
+            .await
+        });
+        (provider, addr)
+    }
+
+    /// Starts a provider that acknowledges every share it is sent but returns it with its first
+    /// byte flipped, answering nothing else.
+    async fn spawn_flipping_provider() -> (PeerId, Multiaddr) {
+        let (mut client, mut events, event_loop, provider) = network::new(None).await.unwrap();
+        spawn(event_loop.run(None));
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+        client.start_listening(addr).await.unwrap();
+        let addr = wait_for_listen_addrs(&mut client).await.remove(0);
+        spawn(async move {
+            let mut held = HashMap::new();
+            while let Some(event) = events.next().await {
+                let Event::InboundRequest {
+                    request, channel, ..
+                } = event
+                else {
+                    continue;
+                };
+                match request {
+                    Request::RegisterShare(request) => {
+                        let share = FieldShare {
+                            share: request.share,
+                            #[cfg(feature = "sss16")]
+                            field: request.field,
+                        };
+                        held.insert(request.key, share);
+                        client.respond_register_share(Ok(()), channel).await;
+                    }
+                    Request::GetShare(request) => {
+                        let result = held.get(&request.key).cloned().map(|mut share| {
+                            share.share.1[0] ^= 0xff;
+                            share
+                        });
+                        client
+                            .respond_share(result.ok_or(Failure::NotFound), channel)
+                            .await;
+                    }
+                    _ => {}
+                }
+            }
+        });
+        (provider, addr)
+    }
+
+    #[test]
+    fn test_provider_settings_fall_back_to_the_config() {
+        let config = ShardConfig {
+            db_path: Some("/var/lib/shard".to_string()),
+            refresh_interval_secs: Some(600),
+            bootstrapper: None,
+            bootstrappers: Vec::new(),
+            listen_addresses: Vec::new(),
+            external_addresses: Vec::new(),
+            network_id: None,
+            request_timeout_secs: None,
+            denied_peers: Vec::new(),
+            allowed_peers: Vec::new(),
+            dir: PathBuf::new(),
+            from_env: false,
+            env_key: None,
+        };
+        let settings = |db_path: Option<&str>, interval, in_memory, config| {
+            let mut db_path = db_path.map(str::to_string);
+            let mut interval = interval;
+            resolve_provider_settings(&mut db_path, &mut interval, in_memory, config).unwrap();
+            (db_path, interval)
+        };
+        assert_eq!(
+            settings(None, None, false, &config),
+            (Some("/var/lib/shard".to_string()), Some(600))
+        );
+        assert_eq!(
+            settings(Some("shares.db"), Some(60), false, &config),
+            (Some("shares.db".to_string()), Some(60))
+        );
+        // a provider kept in memory is not moved to the configured database
+        assert_eq!(settings(None, None, true, &config), (None, Some(600)));
+        let unset = ShardConfig {
+            db_path: None,
+            refresh_interval_secs: None,
+            ..config.clone()
+        };
+        assert_eq!(
+            settings(None, None, false, &unset),
+            (None, Some(DEFAULT_REFRESH_SECONDS))
+        );
+    }
+
+    #[test]
+    fn test_commands_run_as_the_profile_selected() {
+        let dir = std::env::temp_dir().join(format!("shard-profiles-{}", rand::random::<u64>()));
+        let opt = |args: &[&str]| {
+            let mut full = vec!["shard", "--config", dir.to_str().unwrap()];
+            full.extend(args);
+            Opt::try_parse_from(full).unwrap()
+        };
+        let mut generated = HashMap::new();
+        for profile in ["owner", "provider"] {
+            let (config, given) = load_config(&opt(&["keygen", "--profile", profile])).unwrap();
+            assert!(given);
+            generated.insert(profile, keygen(&config, false, false).unwrap().peer_id);
+        }
+        assert_ne!(generated["owner"], generated["provider"]);
+        for profile in ["owner", "provider"] {
+            let (config, _) = load_config(&opt(&["--profile", profile, "ls", "-k", "k"])).unwrap();
+            let identity = client_identity(None, false, &config).unwrap();
+            assert_eq!(
+                identity.public().to_peer_id().to_string(),
+                generated[profile]
+            );
+        }
+        let (default, _) = load_config(&opt(&["ls", "-k", "k"])).unwrap();
+        assert_eq!(default.dir, dir);
+
+        let listed = list_profiles(&dir).unwrap();
+        let names: Vec<_> = listed.profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, [DEFAULT_PROFILE, "owner", "provider"]);
+        assert_eq!(listed.profiles[0].peer_id, None);
+        assert_eq!(
+            listed.profiles[1].peer_id.as_deref(),
+            Some(generated["owner"].as_str())
+        );
+        let mut out = Vec::new();
+        print_profiles(&listed, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&format!("  provider: {}\n", generated["provider"])));
+        assert!(out.contains("  default: no identity key yet\n"));
+
+        let invalid = load_config(&opt(&["--profile", "../up", "ls", "-k", "k"])).unwrap_err();
+        assert_eq!(classify(&*invalid), ErrorKind::Usage);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_config_is_set_and_shown() {
+        let dir = std::env::temp_dir().join(format!("shard-configure-{}", rand::random::<u64>()));
+        let run = |args: &[&str]| {
+            let mut full = vec!["shard", "--config", dir.to_str().unwrap()];
+            full.extend(args);
+            let opt = Opt::try_parse_from(full).unwrap();
+            let CliArgument::Config { command } = &opt.argument else {
+                panic!("expected config");
+            };
+            let mut out = Vec::new();
+            let config = load_config(&opt).unwrap().0;
+            configure(config, command, opt.json, &mut out).map(|()| String::from_utf8(out).unwrap())
+        };
+        let set = run(&["config", "set", "refresh_interval_secs", "600"]).unwrap();
+        assert!(
+            set.starts_with("✅ Set refresh_interval_secs in "),
+            "{}",
+            set
+        );
+        let shown = run(&["config", "show"]).unwrap();
+        assert!(shown.contains("refresh_interval_secs = 600\n"), "{}", shown);
+        let json = run(&["--json", "config", "show"]).unwrap();
+        assert!(json.contains(r#""refresh_interval_secs":600"#), "{}", json);
+
+        let invalid = run(&["config", "set", "refresh_interval_secs", "soon"]).unwrap_err();
+        assert_eq!(classify(&*invalid), ErrorKind::Usage);
+        let unknown = run(&["config", "set", "colour", "blue"]).unwrap_err();
+        assert!(unknown.to_string().contains("bootstrappers"), "{}", unknown);
+        assert_eq!(
+            ShardConfig::load(&dir).unwrap().refresh_interval_secs,
+            Some(600)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_keygen_round_trips_through_the_config() {
+        assert!(Opt::try_parse_from(["shard", "keygen", "--force", "--show"]).is_err());
+
+        let dir = std::env::temp_dir().join(format!("shard-keygen-{}", rand::random::<u64>()));
+        let config = ShardConfig::load(&dir).unwrap();
+        assert!(keygen(&config, false, true).is_err());
+
+        let generated = keygen(&config, false, false).unwrap();
+        assert!(generated.generated);
+        let key = config.key().unwrap().unwrap();
+        assert_eq!(generated.peer_id, key.public().to_peer_id().to_string());
+
+        let refused = keygen(&config, false, false).unwrap_err().to_string();
+        assert!(refused.contains("--force"), "{}", refused);
+        let shown = keygen(&config, false, true).unwrap();
+        assert!(!shown.generated);
+        assert_eq!(shown.peer_id, generated.peer_id);
+
+        let replaced = keygen(&config, true, false).unwrap();
+        assert_ne!(replaced.peer_id, generated.peer_id);
+        assert_eq!(
+            keygen(&config, false, true).unwrap().peer_id,
+            replaced.peer_id
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_key_is_a_key_file_or_a_profile() {
+        let dir = std::env::temp_dir().join(format!("shard-rotate-{}", rand::random::<u64>()));
+        let profile = ShardConfig::load(&ShardConfig::profile_dir(&dir, "next").unwrap()).unwrap();
+        let generated = keygen(&profile, false, false).unwrap().peer_id;
+        let from_profile = rotation_key("next", &dir).unwrap();
+        assert_eq!(from_profile.public().to_peer_id().to_string(), generated);
+
+        let key_file = profile.dir.join(KEY_FILE);
+        let from_file = rotation_key(key_file.to_str().unwrap(), &dir).unwrap();
+        assert_eq!(from_file.public().to_peer_id().to_string(), generated);
+
+        for missing in ["absent", "../up"] {
+            let err = rotation_key(missing, &dir).unwrap_err();
+            assert_eq!(classify(&*err), ErrorKind::Usage);
+        }
+        let opt = Opt::try_parse_from(["shard", "rotate-owner", "--new-key", "next"]).unwrap();
+        assert!(matches!(opt.argument, CliArgument::RotateOwner { new_key } if new_key == "next"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_listeners_start_despite_an_unsupported_address() {
+        let (mut client, _events, event_loop, _) = network::new(None).await.unwrap();
+        spawn(event_loop.run(None));
+
+        // there is no QUIC transport, so only the TCP listener starts
+        let quic = "/ip4/127.0.0.1/udp/0/quic-v1".parse::<Multiaddr>().unwrap();
+        let tcp = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+        let started = start_listeners(&mut client, vec![quic.clone(), tcp]).await;
+        assert_eq!(started, Ok(1));
+        let listen_addrs = wait_for_listen_addrs(&mut client).await;
+        assert_eq!(listen_addrs.len(), 1);
+        assert!(listen_addrs[0]
+            .to_string()
+            .starts_with("/ip4/127.0.0.1/tcp/"));
+
+        let error = start_listeners(&mut client, vec![quic]).await.unwrap_err();
+        assert!(
+            error.starts_with("could not listen on any address (/ip4/127.0.0.1/udp/0/quic-v1: ")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_combine_times_out_on_an_empty_network() {
+        let local = tokio::task::LocalSet::new();
+        let (provider, addr) = spawn_provider(&local).await;
+
+        local
+            .run_until(async move {
+                let timeout = Duration::from_secs(1);
+                let (mut client, _events, event_loop, sender) =
+                    network::with_timeout(Keypair::generate_ed25519(), timeout)
+                        .await
+                        .unwrap();
+                spawn(event_loop.run(None));
+                client.dial(provider, addr).await.unwrap();
+
+                let started = Instant::now();
+                let result = combine_secret(
+                    &client,
+                    sender,
+                    None,
+                    "missing",
+                    Some(3),
+                    &ShareSelection::default(),
+                    &ReadyCriteria::default(),
+                    ProviderWait {
+                        min_providers: 0,
+                        budget: timeout,
+                        report: false,
+                    },
+                )
+                .await;
+                let err = result.unwrap_err();
+                assert_eq!(
+                    err.to_string(),
+                    "timed out waiting for providers (found 0 of 3)"
+                );
+                assert!(started.elapsed() < 3 * timeout);
+
+                let mut stderr = Vec::new();
+                assert_eq!(report_error(&*err, true, &mut stderr), 3);
+                assert_eq!(
+                    String::from_utf8(stderr).unwrap(),
+                    concat!(
+                        r#"{"error":{"code":3,"kind":"no_providers","#,
+                        r#""message":"timed out waiting for providers (found 0 of 3)"}}"#,
+                        "\n"
+                    )
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_split_dry_run_plans_without_registering() {
+        let local = tokio::task::LocalSet::new();
+        let (first, first_addr) = spawn_provider(&local).await;
+        let (second, second_addr) = spawn_provider(&local).await;
+
+        local
+            .run_until(async move {
+                let (mut client, _events, event_loop, sender) = network::new(None).await.unwrap();
+                spawn(event_loop.run(None));
+                client.dial(first, first_addr).await.unwrap();
+                client.dial(second, second_addr).await.unwrap();
+                let ready = ReadyCriteria::default();
+                let timeout = Duration::from_secs(10);
+
+                let available =
+                    discover_split_providers(&mut client, &ready, &[], 2, timeout, true)
+                        .await
+                        .unwrap();
+                let (plan, infeasible) = plan_split("plan", 2, 2, 7, None, available, &[]);
+                assert!(infeasible.is_none());
+                assert!(plan.feasible && !plan.pinned);
+                assert_eq!(plan.discovered, 2);
+                assert_eq!(plan.providers, peer_ids([first, second]));
+                let mut printed = Vec::new();
+                print_split_plan(&plan, &mut printed).unwrap();
+                let printed = String::from_utf8(printed).unwrap();
+                assert!(printed.contains("threshold: 2 of 2 shares of 7 bytes"));
+                assert!(printed.contains("✅ Every share can be placed."));
+
+                // there is no third provider: a split gives up, a dry run reports the shortage
+                let short = Duration::from_secs(1);
+                let split = discover_split_providers(&mut client, &ready, &[], 3, short, false);
+                assert!(split.await.is_err());
+                let available = discover_split_providers(&mut client, &ready, &[], 3, short, true)
+                    .await
+                    .unwrap();
+                let (plan, infeasible) = plan_split("plan", 2, 3, 7, None, available, &[]);
+                assert_eq!(infeasible.unwrap().kind, ErrorKind::QuorumNotMet);
+                assert!(!plan.feasible && plan.providers.is_empty());
+                assert!(plan.reason.is_some());
+
+                // pinned providers are never stood in for
+                let pinned = [first, second];
+                let (plan, infeasible) = plan_split("plan", 2, 3, 7, None, HashSet::new(), &pinned);
+                assert_eq!(infeasible.unwrap().kind, ErrorKind::Usage);
+                assert!(plan.pinned && !plan.feasible);
+                assert_eq!(plan.discovered, 2);
+
+                assert!(collect_keys(&client, pinned, sender).await.keys.is_empty());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_split_refuses_a_key_in_use() {
+        let local = tokio::task::LocalSet::new();
+        let (provider, addr) = spawn_provider(&local).await;
+
+        local
+            .run_until(async move {
+                let (mut client, _events, event_loop, sender) = network::new(None).await.unwrap();
+                spawn(event_loop.run(None));
+                client.dial(provider, addr).await.unwrap();
+                let timeout = Duration::from_secs(10);
+
+                check_key_unused(&client, sender, "twice", timeout)
+                    .await
+                    .unwrap();
+                let shares = split_secret(b"first", 2, 2).unwrap();
+                let status = client
+                    .request_register_share(
+                        (1, shares[&1].clone()),
+                        "twice".to_string(),
+                        2,
+                        None,
+                        false,
+                        None,
+                        false,
+                        None,
+                        provider,
+                        sender,
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(status, RegisterShareStatus::Registered);
+
+                // the second split under the key is refused, naming the provider
+                let err = check_key_unused(&client, sender, "twice", timeout)
+                    .await
+                    .unwrap_err();
+                assert!(err.to_string().contains(&provider.to_string()));
+                assert_eq!(report_error(&*err, false, &mut Vec::new()), 2);
+
+                // another owner's key of the same name is not in the way
+                let stranger = PeerId::random();
+                check_key_unused(&client, stranger, "twice", timeout)
+                    .await
+                    .unwrap();
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_ls_watch_waits_for_the_expected_providers() {
+        let local = tokio::task::LocalSet::new();
+        let (first, first_addr) = spawn_provider(&local).await;
+        let (second, second_addr) = spawn_provider(&local).await;
+
+        local
+            .run_until(async move {
+                let (mut client, _events, event_loop, sender) = network::new(None).await.unwrap();
+                spawn(event_loop.run(None));
+                client.dial(first, first_addr).await.unwrap();
+                client.dial(second, second_addr).await.unwrap();
+                let shares = SplitShares::split(b"replicated", 2, 2).unwrap();
+                let register = |index: ShareIndex, provider: PeerId| {
+                    let mut client = client.clone();
+                    let share = shares.get(index);
+                    async move {
+                        let key = "watched".to_string();
+                        let status = client
+                            .request_register_share(
+                                share, key, 2, None, false, None, false, None, provider, sender,
+                            )
+                            .await
+                            .unwrap();
+                        assert_eq!(status, RegisterShareStatus::Registered);
+                    }
+                };
+                register(1, first).await;
+
+                // the second share is placed mid-watch
+                let interval = Duration::from_millis(100);
+                let timeout = Duration::from_secs(10);
+                let mut out = Vec::new();
+                let watch = watch_ls(
+                    &client,
+                    sender,
+                    "watched",
+                    interval,
+                    Some(2),
+                    timeout,
+                    false,
+                    &mut out,
+                );
+                let late = async {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                    register(2, second).await;
+                };
+                let (result, ()) = futures::join!(watch, late);
+                result.unwrap();
+                let out = String::from_utf8(out).unwrap();
+                let lines: Vec<&str> = out.lines().collect();
+                assert_eq!(lines.len(), 2, "{}", out);
+                assert!(lines[0].ends_with(&format!("watched: 1 providers: {}", first)));
+                assert!(lines[1].ends_with(&format!("watched: +{} (2 providers)", second)));
+
+                // nothing more turns up
+                let short = Duration::from_millis(500);
+                let mut out = Vec::new();
+                let watch = watch_ls(
+                    &client,
+                    sender,
+                    "watched",
+                    interval,
+                    Some(3),
+                    short,
+                    true,
+                    &mut out,
+                );
+                let err = watch.await.unwrap_err();
+                assert_eq!(report_error(&*err, false, &mut Vec::new()), 6);
+                let change: ProvidersChangeOutput =
+                    serde_json::from_slice(out.split(|b| *b == b'\n').next().unwrap()).unwrap();
+                assert_eq!(change.providers, peer_ids([first, second]));
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_altered_share_is_detected_and_placed_elsewhere() {
+        let local = tokio::task::LocalSet::new();
+        let (good, good_addr) = spawn_provider(&local).await;
+        let (spare, spare_addr) = spawn_provider(&local).await;
+        let (flipping, flipping_addr) = spawn_flipping_provider().await;
+
+        local
+            .run_until(async move {
+                let owner = Keypair::generate_ed25519();
+                let (mut client, _events, event_loop, sender) =
+                    network::with_identity(owner.clone()).await.unwrap();
+                spawn(event_loop.run(None));
+                client.dial(good, good_addr).await.unwrap();
+                client.dial(spare, spare_addr).await.unwrap();
+                client.dial(flipping, flipping_addr).await.unwrap();
+                let options = ShareOptions {
+                    threshold: 2,
+                    ttl: None,
+                    recreate: false,
+                    refresh_every: None,
+                    replace: false,
+                };
+                let shares = SplitShares::split(b"gillyweed", 2, 2).unwrap();
+                let (placed, mut outcomes) = register_shares(
+                    &client,
+                    &owner,
+                    "verified",
+                    &shares,
+                    &[good, flipping],
+                    Vec::new(),
+                    options,
+                )
+                .await;
+                assert_eq!(placed, vec![(1, good), (2, flipping)]);
+
+                let verified = verify_shares(
+                    &client,
+                    &owner,
+                    "verified",
+                    &shares,
+                    placed,
+                    vec![spare],
+                    options,
+                    &mut outcomes,
+                )
+                .await;
+                assert_eq!(verified, vec![(1, good), (2, spare)]);
+                let status = |peer: PeerId| {
+                    let outcome = outcomes.iter().find(|o| o.peer == peer.to_string());
+                    outcome.map(|o| o.status)
+                };
+                assert_eq!(status(good), Some(RegistrationOutcome::Registered));
+                assert_eq!(status(flipping), Some(RegistrationOutcome::Unverified));
+                assert_eq!(status(spare), Some(RegistrationOutcome::Registered));
+                assert!(outcome_report(&outcomes).contains("share 2 was read back altered"));
+
+                let mut read_back = SplitShares::default();
+                for peer in [good, spare] {
+                    let share = client
+                        .request_field_share(peer, "verified".to_string(), sender, None)
+                        .await
+                        .unwrap();
+                    read_back.insert(share).unwrap();
+                }
+                assert_eq!(read_back.combine("verified").unwrap(), b"gillyweed");
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_combine_as_a_stranger_is_denied() {
+        let local = tokio::task::LocalSet::new();
+        let (provider, addr) = spawn_provider(&local).await;
+
+        local
+            .run_until(async move {
+                let (mut owner_client, _owner_events, event_loop, owner) =
+                    network::new(None).await.unwrap();
+                spawn(event_loop.run(None));
+                owner_client.dial(provider, addr.clone()).await.unwrap();
+                let shares = split_secret(b"owl post", 2, 2).unwrap();
+                owner_client
+                    .request_register_share(
+                        (1, shares[&1].clone()),
+                        "private".to_string(),
+                        2,
+                        None,
+                        false,
+                        None,
+                        false,
+                        None,
+                        provider,
+                        owner,
+                    )
+                    .await
+                    .unwrap();
+
+                // the stranger finds the owner's provider, which refuses it the share
+                let (mut client, _events, event_loop, stranger) = network::new(None).await.unwrap();
+                spawn(event_loop.run(None));
+                client.dial(provider, addr).await.unwrap();
+                let wait = ProviderWait {
+                    min_providers: 0,
+                    budget: Duration::from_secs(10),
+                    report: false,
+                };
+                let ready = ReadyCriteria::default();
+                let result = combine_secret(
+                    &client,
+                    stranger,
+                    Some(owner),
+                    "private",
+                    Some(1),
+                    &ShareSelection::default(),
+                    &ready,
+                    wait,
+                )
+                .await;
+                let err = result.unwrap_err();
+                assert_eq!(report_error(&*err, false, &mut Vec::new()), 5);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_combine_waits_for_a_late_provider() {
+        let local = tokio::task::LocalSet::new();
+        let (first, first_addr) = spawn_provider(&local).await;
+        let (second, second_addr) = spawn_provider(&local).await;
+
+        local
+            .run_until(async move {
+                let (mut client, _events, event_loop, sender) = network::new(None).await.unwrap();
+                spawn(event_loop.run(None));
+                client.dial(first, first_addr).await.unwrap();
+
+                let shares = SplitShares::split(b"pumpkin juice", 2, 2).unwrap();
+                let register = |share_id: ShareIndex, peer: PeerId| {
+                    let mut client = client.clone();
+                    let share = shares.get(share_id);
+                    async move {
+                        client
+                            .request_register_share(
+                                share,
+                                "late".to_string(),
+                                2,
+                                None,
+                                false,
+                                None,
+                                false,
+                                None,
+                                peer,
+                                sender,
+                            )
+                            .await
+                            .unwrap()
+                    }
+                };
+                register(1, first).await;
+
+                // the second provider joins while combine is already looking for providers
+                let wait = ProviderWait {
+                    min_providers: 0,
+                    budget: Duration::from_secs(10),
+                    report: true,
+                };
+                let ready = ReadyCriteria::default();
+                let selection = ShareSelection::default();
+                let combine = combine_secret(
+                    &client, sender, None, "late", None, &selection, &ready, wait,
+                );
+                let join = async {
+                    client.clone().dial(second, second_addr).await.unwrap();
+                    register(2, second).await;
+                };
+                let (shares, _) = futures::join!(combine, join);
+                let shares = shares.unwrap();
+                assert_eq!(shares.shares.len(), 2);
+                assert_eq!(shares.combine("late").unwrap(), b"pumpkin juice");
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_combine_uses_only_the_providers_and_shares_selected() {
+        let local = tokio::task::LocalSet::new();
+        let (first, first_addr) = spawn_provider(&local).await;
+        let (second, second_addr) = spawn_provider(&local).await;
+        let (third, third_addr) = spawn_provider(&local).await;
+
+        local
+            .run_until(async move {
+                let owner = Keypair::generate_ed25519();
+                let (mut client, _events, event_loop, sender) =
+                    network::with_identity(owner.clone()).await.unwrap();
+                spawn(event_loop.run(None));
+                client.dial(first, first_addr).await.unwrap();
+                client.dial(second, second_addr).await.unwrap();
+                client.dial(third, third_addr).await.unwrap();
+                let options = ShareOptions {
+                    threshold: 2,
+                    ttl: None,
+                    recreate: false,
+                    refresh_every: None,
+                    replace: false,
+                };
+                let shares = SplitShares::split(b"mandrake root", 2, 3).unwrap();
+                let (placed, _) = register_shares(
+                    &client,
+                    &owner,
+                    "chosen",
+                    &shares,
+                    &[first, second, third],
+                    Vec::new(),
+                    options,
+                )
+                .await;
+                assert_eq!(placed, vec![(1, first), (2, second), (3, third)]);
+
+                let wait = ProviderWait {
+                    min_providers: 0,
+                    budget: Duration::from_secs(10),
+                    report: false,
+                };
+                let ready = &ReadyCriteria::default();
+                let combine = |selection: ShareSelection| {
+                    let client = client.clone();
+                    async move {
+                        combine_secret(
+                            &client, sender, None, "chosen", None, &selection, ready, wait,
+                        )
+                        .await
+                    }
+                };
+
+                // the excluded provider is never asked, and the other two meet the threshold
+                let excluded = combine(ShareSelection {
+                    excluded: vec![second],
+                    ..Default::default()
+                });
+                let combined = excluded.await.unwrap();
+                assert_eq!(
+                    combined.shares.keys().copied().collect::<HashSet<_>>(),
+                    [1, 3].into()
+                );
+                assert_eq!(combined.combine("chosen").unwrap(), b"mandrake root");
+
+                let chosen = combine(ShareSelection {
+                    share_ids: vec![2, 3],
+                    ..Default::default()
+                });
+                let combined = chosen.await.unwrap();
+                assert_eq!(
+                    combined.shares.keys().copied().collect::<HashSet<_>>(),
+                    [2, 3].into()
+                );
+
+                // one provider cannot meet a threshold of 2, whatever it holds
+                let alone = combine(ShareSelection {
+                    pinned: vec![first],
+                    ..Default::default()
+                });
+                let err = alone.await.unwrap_err();
+                assert_eq!(classify(&*err), ErrorKind::QuorumNotMet);
+                assert!(err.to_string().contains("only 1 providers were given"));
+
+                let missing = combine(ShareSelection {
+                    pinned: vec![first, second],
+                    share_ids: vec![1, 3],
+                    ..Default::default()
+                });
+                let err = missing.await.unwrap_err();
+                assert_eq!(classify(&*err), ErrorKind::QuorumNotMet);
+                assert!(err
+                    .to_string()
+                    .starts_with("share 3 of chosen was not sent"));
+
+                // two providers sending different shares of the same index fail the combine
+                for (peer, secret) in [(first, b"mandrake root"), (second, b"mandrake leaf")] {
+                    let mut shares = SplitShares::split(secret, 2, 2).unwrap();
+                    shares.shares.remove(&2);
+                    let (placed, _) = register_shares(
+                        &client,
+                        &owner,
+                        "mixed",
+                        &shares,
+                        &[peer],
+                        vec![],
+                        options,
+                    )
+                    .await;
+                    assert_eq!(placed, vec![(1, peer)]);
+                }
+                let selection = ShareSelection {
+                    pinned: vec![first, second],
+                    ..Default::default()
+                };
+                let mixed = combine_secret(
+                    &client,
+                    sender,
+                    None,
+                    "mixed",
+                    Some(2),
+                    &selection,
+                    ready,
+                    wait,
+                );
+                let err = mixed.await.unwrap_err();
+                assert_eq!(
+                    err.to_string(),
+                    "Unable to combine the shares of mixed: two different shares have the index 1."
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_share_epoch_is_the_latest_of_the_providers() {
+        let local = tokio::task::LocalSet::new();
+        let (first, first_addr) = spawn_provider(&local).await;
+        let (second, second_addr) = spawn_provider(&local).await;
+
+        local
+            .run_until(async move {
+                let owner = Keypair::generate_ed25519();
+                let (mut client, _events, event_loop, sender) =
+                    network::with_identity(owner.clone()).await.unwrap();
+                spawn(event_loop.run(None));
+                client.dial(first, first_addr).await.unwrap();
+                client.dial(second, second_addr).await.unwrap();
+                let options = ShareOptions {
+                    threshold: 2,
+                    ttl: None,
+                    recreate: false,
+                    refresh_every: None,
+                    replace: false,
+                };
+                let shares = SplitShares::split(b"felix felicis", 2, 2).unwrap();
+                let (placed, _) = register_shares(
+                    &client,
+                    &owner,
+                    "epoch",
+                    &shares,
+                    &[first, second],
+                    Vec::new(),
+                    options,
+                )
+                .await;
+                assert_eq!(placed.len(), 2);
+                let providers = HashSet::from([first, second]);
+                let epoch = share_epoch(&client, sender, "epoch", &providers).await;
+                assert_eq!(epoch.unwrap(), 0);
+
+                // a provider a refresh reached is ahead of one it did not
+                let refresh_key = new_refresh_key(2, 13).unwrap();
+                let refreshed = client
+                    .request_refresh_shares(
+                        "epoch".to_string(),
+                        refresh_key,
+                        first,
+                        sender,
+                        Some(1),
+                        None,
+                    )
+                    .await;
+                assert!(refreshed.unwrap());
+                let epoch = share_epoch(&client, sender, "epoch", &providers).await;
+                assert_eq!(epoch.unwrap(), 1);
+
+                let missing = share_epoch(&client, sender, "missing", &providers).await;
+                assert_eq!(
+                    missing.unwrap_err().to_string(),
+                    "No provider described its share of missing."
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_provider_answers_requests_arriving_together() {
+        let local = tokio::task::LocalSet::new();
+        let (provider, addr) = spawn_provider(&local).await;
+
+        local
+            .run_until(async move {
+                let (mut client, _events, event_loop, sender) = network::new(None).await.unwrap();
+                spawn(event_loop.run(None));
+                client.dial(provider, addr).await.unwrap();
+
+                // each answer is sent through the provider's event loop while the other requests
+                // are still arriving on it
+                let requests = (0..32).map(|i| {
+                    let mut client = client.clone();
+                    async move {
+                        client
+                            .request_stat_share(format!("key-{}", i), provider, sender)
+                            .await
+                    }
+                });
+                for status in futures::future::join_all(requests).await {
+                    assert_eq!(status.unwrap(), StatShareStatus::NotFound);
+                }
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_merged_across_providers() {
+        let local = tokio::task::LocalSet::new();
+        let (first, first_addr) = spawn_provider(&local).await;
+        let (second, second_addr) = spawn_provider(&local).await;
+        let silent = PeerId::random();
+
+        local
+            .run_until(async move {
+                let (mut client, _events, event_loop, sender) = network::new(None).await.unwrap();
+                spawn(event_loop.run(None));
+                client.dial(first, first_addr).await.unwrap();
+                client.dial(second, second_addr).await.unwrap();
+
+                let holdings = [(first, ["both", "first"]), (second, ["both", "second"])];
+                for (provider, keys) in holdings {
+                    for key in keys {
+                        let status = client
+                            .request_register_share(
+                                (1, vec![7]),
+                                key.to_string(),
+                                2,
+                                None,
+                                false,
+                                None,
+                                false,
+                                None,
+                                provider,
+                                sender,
+                            )
+                            .await
+                            .unwrap();
+                        assert_eq!(status, RegisterShareStatus::Registered);
+                    }
+                }
+
+                let output = collect_keys(&client, [first, second, silent], sender).await;
+                let listed: Vec<(&str, Vec<String>)> = output
+                    .keys
+                    .iter()
+                    .map(|listing| (listing.key.as_str(), listing.providers.clone()))
+                    .collect();
+                assert_eq!(
+                    listed,
+                    vec![
+                        ("both", peer_ids([first, second])),
+                        ("first", peer_ids([first])),
+                        ("second", peer_ids([second])),
+                    ]
+                );
+                assert!(output
+                    .keys
+                    .iter()
+                    .all(|listing| listing.threshold == Some(2)));
+                assert!(output
+                    .keys
+                    .iter()
+                    .all(|listing| listing.last_refreshed_unix.is_some()));
+
+                // a provider that cannot be reached is reported, not fatal
+                assert_eq!(output.unanswered.len(), 1);
+                assert_eq!(output.unanswered[0].peer, silent.to_string());
+
+                // the listing is per owner
+                let stranger = collect_keys(&client, [first], PeerId::random()).await;
+                assert!(stranger.keys.is_empty());
+            })
+            .await;
+    }
+
+    #[test]
+    fn test_print_info() {
+        let mut output = InfoOutput {
+            peer_id: "peer".to_string(),
+            version: "0.1.0".to_string(),
+            agent_version: "shard/0.1.0".to_string(),
+            listen_addrs: vec![
+                "/ip4/127.0.0.1/tcp/4001".to_string(),
+                "/ip4/10.0.0.2/tcp/4001".to_string(),
+            ],
+            external_addrs: vec![],
+            connected_peers: 2,
+            routing_table_size: 5,
+            reachable: false,
+            providers: None,
+        };
+        let print = |output: &InfoOutput| {
+            let mut out = Vec::new();
+            print_info(output, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            print(&output),
+            concat!(
+                "🆔 peer id: peer\n",
+                "   version: 0.1.0 (shard/0.1.0)\n",
+                "   listening on: /ip4/127.0.0.1/tcp/4001, /ip4/10.0.0.2/tcp/4001\n",
+                "   external addresses: none\n",
+                "   connected peers: 2\n",
+                "   routing table: 5 peers\n",
+                "   publicly reachable: no\n"
+            )
+        );
+
+        output.providers = Some(vec![ProviderInfo {
+            peer: "provider".to_string(),
+            shares: 4,
+            db_bytes: 512,
+            uptime_secs: 60,
+            version: "0.1.0".to_string(),
+            reachable: true,
+            metrics: Default::default(),
+        }]);
+        assert!(print(&output).ends_with(concat!(
+            "🌐 1 providers:\n",
+            "  provider: 4 shares, 512 bytes, up 60s, version 0.1.0, reachable\n",
+            "    0 registered, 0 served, 0 refreshed (0 own), 0 failed, oldest refresh unknown\n"
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_info_reports_a_dialable_listen_address() {
+        let (mut node, _events, event_loop, node_id) = network::new(None).await.unwrap();
+        spawn(event_loop.run(None));
+        node.start_listening("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .await
+            .unwrap();
+        wait_for_listen_addrs(&mut node).await;
+
+        let output = InfoOutput::new(&node.network_info().await, None);
+        assert_eq!(output.peer_id, node_id.to_string());
+        assert_eq!(output.connected_peers, 0);
+        assert!(!output.reachable);
+        let addr: Multiaddr = output.listen_addrs[0].parse().unwrap();
+        // the port the transport bound, not the 0 asked for
+        assert!(!addr.iter().any(|protocol| protocol == Protocol::Tcp(0)));
+
+        let (mut client, _events, event_loop, client_id) = network::new(None).await.unwrap();
+        spawn(event_loop.run(None));
+        client.dial(node_id, addr).await.unwrap();
+        assert_eq!(client.connected_peers().await, vec![node_id]);
+        // the listener learns of the connection once its side of the handshake completes
+        let mut connected = node.connected_peers().await;
+        for _ in 0..LISTEN_ADDR_POLLS {
+            if !connected.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            connected = node.connected_peers().await;
+        }
+        assert_eq!(connected, vec![client_id]);
+        assert_eq!(node.network_info().await.connected_peers, 1);
+    }
+
+    #[test]
+    fn test_pinned_providers_are_never_stood_in_for() {
+        let available: HashSet<PeerId> = (0..5).map(|_| PeerId::random()).collect();
+        let pinned: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+
+        let (sample, spares) = choose_providers(available.clone(), &pinned, 2).unwrap();
+        assert_eq!(sample.len(), 2);
+        assert!(sample.iter().all(|p| pinned.contains(p)));
+        assert!(spares.is_empty());
+        assert!(choose_providers(available.clone(), &pinned, 4).is_err());
+
+        let (sample, spares) = choose_providers(available, &[], 2).unwrap();
+        assert_eq!((sample.len(), spares.len()), (2, 3));
+    }
+
+    #[tokio::test]
+    async fn test_pinned_providers_hold_every_share() {
+        let local = tokio::task::LocalSet::new();
+        let (first, first_addr) = spawn_provider(&local).await;
+        let (second, second_addr) = spawn_provider(&local).await;
+        let (third, third_addr) = spawn_provider(&local).await;
+
+        local
+            .run_until(async move {
+                let owner = Keypair::generate_ed25519();
+                let (mut client, _events, event_loop, sender) =
+                    network::with_identity(owner.clone()).await.unwrap();
+                spawn(event_loop.run(None));
+                client.dial(second, second_addr).await.unwrap();
+                client.dial(third, third_addr).await.unwrap();
+
+                // the first provider is only reachable through the address it is pinned by
+                let pinned = pin_providers(
+                    &mut client,
+                    vec![
+                        ProviderArg::Addr(first, first_addr.with(Protocol::P2p(first))),
+                        ProviderArg::Peer(second),
+                    ],
+                )
+                .await
+                .unwrap();
+                assert_eq!(pinned, vec![first, second]);
+
+                let available = HashSet::from([first, second, third]);
+                let (sample, spares) = choose_providers(available, &pinned, 2).unwrap();
+                let options = ShareOptions {
+                    threshold: 2,
+                    ttl: None,
+                    recreate: false,
+                    refresh_every: None,
+                    replace: false,
+                };
+                let shares = SplitShares::split(b"butterbeer", 2, 2).unwrap();
+                let (placed, outcomes) =
+                    register_shares(&client, &owner, "pinned", &shares, &sample, spares, options)
+                        .await;
+                assert_eq!(placed.len(), 2);
+                assert_eq!(outcome_report(&outcomes), "");
+
+                let output = collect_keys(&client, [first, second, third], sender).await;
+                assert_eq!(output.keys.len(), 1);
+                assert_eq!(output.keys[0].key, "pinned");
+                assert_eq!(output.keys[0].providers, peer_ids([first, second]));
+
+                // a pinned provider that cannot be reached is reported, not replaced
+                let unreachable = PeerId::random();
+                let (placed, outcomes) = register_shares(
+                    &client,
+                    &owner,
+                    "unreachable",
+                    &shares,
+                    &[first, unreachable],
+                    Vec::new(),
+                    options,
+                )
+                .await;
+                assert_eq!(placed, vec![(1, first)]);
+                assert!(outcome_report(&outcomes).contains(&unreachable.to_string()));
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_file_split_resumes_and_combines() {
+        let local = tokio::task::LocalSet::new();
+        let (first, first_addr) = spawn_provider(&local).await;
+        let (second, second_addr) = spawn_provider(&local).await;
+
+        local
+            .run_until(async move {
+                let owner = Keypair::generate_ed25519();
+                let (mut client, _events, event_loop, sender) =
+                    network::with_identity(owner.clone()).await.unwrap();
+                spawn(event_loop.run(None));
+                client.dial(first, first_addr).await.unwrap();
+                client.dial(second, second_addr).await.unwrap();
+
+                let dir =
+                    std::env::temp_dir().join(format!("shard-file-{}", rand::random::<u64>()));
+                std::fs::create_dir_all(&dir).unwrap();
+                let path = dir.join("backup.bin");
+                let data: Vec<u8> = (0..2 * 1024 * 1024 + 123)
+                    .map(|i| (i * 7 % 256) as u8)
+                    .collect();
+                std::fs::write(&path, &data).unwrap();
+                let options = ShareOptions {
+                    threshold: 2,
+                    ttl: None,
+                    recreate: false,
+                    refresh_every: None,
+                    replace: false,
+                };
+                let chunk_bytes = 64 * 1024;
+
+                // the first attempt stops after 5 of its 33 chunks
+                let interrupted = split_file(
+                    &client,
+                    &owner,
+                    &path,
+                    "backup",
+                    vec![first, second],
+                    chunk_bytes,
+                    options,
+                    &mut |done, _| match done {
+                        5 => Err("interrupted".into()),
+                        _ => Ok(()),
+                    },
+                )
+                .await;
+                assert_eq!(interrupted.unwrap_err().to_string(), "interrupted");
+                let saved = SplitProgress::load(&SplitProgress::path_for(&path))
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(saved.completed, 5);
+
+                // the second picks up at chunk 6, with the providers the first one started with
+                let mut reported = Vec::new();
+                let manifest = split_file(
+                    &client,
+                    &owner,
+                    &path,
+                    "backup",
+                    vec![second, first],
+                    chunk_bytes,
+                    options,
+                    &mut |done, total| {
+                        reported.push((done, total));
+                        Ok(())
+                    },
+                )
+                .await
+                .unwrap();
+                assert_eq!(reported.first(), Some(&(6, 33)));
+                assert_eq!(reported.last(), Some(&(33, 33)));
+                assert_eq!(
+                    manifest.providers,
+                    vec![first.to_string(), second.to_string()]
+                );
+                assert!(!SplitProgress::path_for(&path).exists());
+
+                // the key holds the manifest, the chunks are rebuilt from their own keys
+                let providers = [first, second];
+                let secret = fetch_chunk(&client, sender, None, "backup", &providers, 2, &[])
+                    .await
+                    .unwrap();
+                let registered = ChunkManifest::from_bytes(&secret).unwrap().unwrap();
+                assert_eq!(registered, manifest);
+
+                let out = dir.join("restored.bin");
+                let mut written = Vec::new();
+                combine_file(
+                    &client,
+                    sender,
+                    None,
+                    "backup",
+                    &registered,
+                    &ShareSelection::default(),
+                    &out,
+                    &mut |done, _| written.push(done),
+                )
+                .await
+                .unwrap();
+                assert_eq!(written.len(), 33);
+                assert_eq!(std::fs::read(&out).unwrap(), data);
+                std::fs::remove_dir_all(dir).unwrap();
+            })
+            .await;
+    }
+
+    #[test]
+    fn test_print_shares_orders_by_value() {
+        let shares = HashMap::from([(1, vec![0x0b]), (2, vec![0x0a])]);
+        let mut out = Vec::new();
+        print_shares(&shares, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "🐛 shares: \n  0a\n  0b\n");
+    }
 }
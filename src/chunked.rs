@@ -0,0 +1,400 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Bytes opening the secret registered under the key of a chunked file, marking it as the file's
+/// manifest rather than a secret of its own.
+pub const MANIFEST_MAGIC: &[u8] = b"shard-chunked-file\0";
+
+/// The extension of the local file recording how far the split of a file got, next to the file.
+pub const PROGRESS_EXTENSION: &str = "shard-progress";
+
+/// Computes the key the shares of a chunk of a file are registered under.
+///
+/// # Arguments
+///
+/// * `key` - The key the file is split under.
+/// * `index` - The index of the chunk, from 0.
+///
+/// # Returns
+///
+/// The key of the chunk.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::chunked::chunk_key;
+///
+/// assert_eq!(chunk_key("backup", 3), "backup#chunk-3");
+/// ```
+pub fn chunk_key(key: &str, index: u64) -> String {
+    format!("{}#chunk-{}", key, index)
+}
+
+/// Describes a file split in chunks. It is itself split and registered under the file's key, once
+/// every chunk is, so that a key only holds a manifest when the whole file can be rebuilt.
+///
+/// Chunks are `chunk_bytes` long, except for the last one, and each is split into shares on its
+/// own and registered under `chunk_key`. The share of index `i` of every chunk is held by the
+/// `i`-th provider.
+///
+/// # Fields
+///
+/// * `length` - The length of the file in bytes.
+/// * `chunk_bytes` - The length of every chunk but the last.
+/// * `threshold` - The number of shares needed to rebuild a chunk.
+/// * `providers` - The peer ids of the providers, by share index from 1.
+/// * `sha256` - The hex-encoded SHA-256 of the file, checked once it is rebuilt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkManifest {
+    pub length: u64,
+    pub chunk_bytes: u64,
+    pub threshold: usize,
+    pub providers: Vec<String>,
+    pub sha256: String,
+}
+
+impl ChunkManifest {
+    /// The number of chunks the file was split into.
+    pub fn chunks(&self) -> u64 {
+        self.length.div_ceil(self.chunk_bytes.max(1))
+    }
+
+    /// Encodes the manifest as the secret registered under the file's key.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, serde_json::Error> {
+        let mut bytes = MANIFEST_MAGIC.to_vec();
+        bytes.extend(serde_json::to_vec(self)?);
+        Ok(bytes)
+    }
+
+    /// Decodes a secret registered under a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret` - The rebuilt secret.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the secret is not a manifest, otherwise the manifest or why it does not parse.
+    pub fn from_bytes(secret: &[u8]) -> Option<Result<Self, serde_json::Error>> {
+        secret
+            .strip_prefix(MANIFEST_MAGIC)
+            .map(serde_json::from_slice)
+    }
+}
+
+/// How far the split of a file got, kept next to the file until the split completes, so that a
+/// split that failed part way resumes with the chunk it stopped at.
+///
+/// # Fields
+///
+/// * `key` - The key the file is split under.
+/// * `length` - The length of the file in bytes.
+/// * `chunk_bytes` - The length of every chunk but the last.
+/// * `threshold` - The number of shares needed to rebuild a chunk.
+/// * `providers` - The peer ids of the providers, by share index from 1.
+/// * `completed` - The number of chunks registered with every provider.
+/// * `prefix_sha256` - The hex-encoded SHA-256 of the completed chunks, so that a file changed
+///   since is not resumed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SplitProgress {
+    pub key: String,
+    pub length: u64,
+    pub chunk_bytes: u64,
+    pub threshold: usize,
+    pub providers: Vec<String>,
+    pub completed: u64,
+    pub prefix_sha256: String,
+}
+
+impl SplitProgress {
+    /// The path the progress of splitting `file` is kept at.
+    pub fn path_for(file: &Path) -> PathBuf {
+        let mut path = file.as_os_str().to_owned();
+        path.push(".");
+        path.push(PROGRESS_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    /// Reads the progress kept at `path`.
+    ///
+    /// # Returns
+    ///
+    /// The progress, or `None` if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or does not hold a progress record.
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let progress = serde_json::from_slice(&fs::read(path)?)
+            .map_err(|e| format!("invalid split progress in {}: {}", path.display(), e))?;
+        Ok(Some(progress))
+    }
+
+    /// Writes the progress to `path`, replacing the progress kept there.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let tmp = path.with_extension(format!("{}.tmp", PROGRESS_EXTENSION));
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Reads a file a chunk at a time, hashing what it read, so that a file of any size is split
+/// with one chunk in memory.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::chunked::ChunkReader;
+///
+/// let mut reader = ChunkReader::new(&b"hello world"[..], 4);
+/// let mut chunks = Vec::new();
+/// while let Some(chunk) = reader.next_chunk().unwrap() {
+///     chunks.push(chunk);
+/// }
+/// assert_eq!(chunks, vec![b"hell".to_vec(), b"o wo".to_vec(), b"rld".to_vec()]);
+/// ```
+pub struct ChunkReader<R> {
+    reader: R,
+    chunk_bytes: u64,
+    hasher: Sha256,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Creates a reader of `chunk_bytes` long chunks of `reader`.
+    pub fn new(reader: R, chunk_bytes: u64) -> Self {
+        ChunkReader {
+            reader,
+            chunk_bytes: chunk_bytes.max(1),
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Reads the next chunk, shorter than `chunk_bytes` only at the end of the file.
+    ///
+    /// # Returns
+    ///
+    /// The chunk, or `None` once the whole file was read.
+    pub fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut chunk = Vec::new();
+        (&mut self.reader)
+            .take(self.chunk_bytes)
+            .read_to_end(&mut chunk)?;
+        if chunk.is_empty() {
+            return Ok(None);
+        }
+        self.hasher.update(&chunk);
+        Ok(Some(chunk))
+    }
+
+    /// The hex-encoded SHA-256 of the chunks read so far.
+    pub fn sha256(&self) -> String {
+        hex::encode(self.hasher.clone().finalize())
+    }
+}
+
+/// Writes the rebuilt chunks of a file in order, hashing them, so that the file is checked
+/// against its manifest without being held in memory.
+pub struct ChunkWriter<W> {
+    writer: W,
+    written: u64,
+    hasher: Sha256,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    /// Creates a writer of chunks to `writer`.
+    pub fn new(writer: W) -> Self {
+        ChunkWriter {
+            writer,
+            written: 0,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Writes the next chunk of the file.
+    pub fn write_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.writer.write_all(chunk)?;
+        self.hasher.update(chunk);
+        self.written += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// Flushes the file and checks it against the manifest it was rebuilt from.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be flushed, or its length or hash differ from the
+    /// manifest's.
+    pub fn finish(mut self, manifest: &ChunkManifest) -> Result<W, Box<dyn Error>> {
+        self.writer.flush()?;
+        if self.written != manifest.length {
+            return Err(format!(
+                "rebuilt {} bytes, the manifest records {}",
+                self.written, manifest.length
+            )
+            .into());
+        }
+        if hex::encode(self.hasher.finalize()) != manifest.sha256 {
+            return Err("the rebuilt file does not match the hash in its manifest".into());
+        }
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sss::{combine_shares, split_secret};
+    use std::collections::HashMap;
+    use std::io::{BufReader, BufWriter};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("shard-{}-{}", name, rand::random::<u64>()))
+    }
+
+    /// Splits `input` chunk by chunk and rebuilds it into `output` from `threshold` shares of
+    /// each chunk, as a split and combine over the network do.
+    fn round_trip(input: &Path, output: &Path, chunk_bytes: u64) -> ChunkManifest {
+        let length = fs::metadata(input).unwrap().len();
+        let mut reader =
+            ChunkReader::new(BufReader::new(fs::File::open(input).unwrap()), chunk_bytes);
+        let mut writer = ChunkWriter::new(BufWriter::new(fs::File::create(output).unwrap()));
+        while let Some(chunk) = reader.next_chunk().unwrap() {
+            let shares = split_secret(&chunk, 2, 3).unwrap();
+            let subset: HashMap<u8, Vec<u8>> = shares
+                .into_iter()
+                .filter(|(index, _)| *index != 2)
+                .collect();
+            writer
+                .write_chunk(&combine_shares(&subset).unwrap())
+                .unwrap();
+        }
+        let manifest = ChunkManifest {
+            length,
+            chunk_bytes,
+            threshold: 2,
+            providers: vec![],
+            sha256: reader.sha256(),
+        };
+        writer.finish(&manifest).unwrap();
+        manifest
+    }
+
+    #[test]
+    fn test_file_round_trips_chunk_by_chunk() {
+        let (input, output) = (temp_path("chunked-in"), temp_path("chunked-out"));
+        let data: Vec<u8> = (0..3 * 1024 * 1024 + 17).map(|i| (i % 251) as u8).collect();
+        fs::write(&input, &data).unwrap();
+
+        let manifest = round_trip(&input, &output, 64 * 1024);
+        assert_eq!(manifest.chunks(), 49);
+        assert_eq!(fs::read(&output).unwrap(), data);
+        fs::remove_file(input).unwrap();
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn test_rebuilt_file_is_checked_against_the_manifest() {
+        let manifest = ChunkManifest {
+            length: 3,
+            chunk_bytes: 2,
+            threshold: 2,
+            providers: vec![],
+            sha256: hex::encode(Sha256::digest(b"abc")),
+        };
+        let mut writer = ChunkWriter::new(Vec::new());
+        writer.write_chunk(b"ab").unwrap();
+        writer.write_chunk(b"c").unwrap();
+        assert_eq!(writer.finish(&manifest).unwrap(), b"abc");
+
+        let mut writer = ChunkWriter::new(Vec::new());
+        writer.write_chunk(b"ab").unwrap();
+        writer.write_chunk(b"d").unwrap();
+        assert!(writer.finish(&manifest).is_err());
+
+        let mut writer = ChunkWriter::new(Vec::new());
+        writer.write_chunk(b"ab").unwrap();
+        assert!(writer.finish(&manifest).is_err());
+    }
+
+    #[test]
+    fn test_manifest_and_progress_encoding() {
+        let manifest = ChunkManifest {
+            length: 10,
+            chunk_bytes: 4,
+            threshold: 2,
+            providers: vec!["a".to_string(), "b".to_string()],
+            sha256: "00".to_string(),
+        };
+        assert_eq!(manifest.chunks(), 3);
+        let bytes = manifest.to_bytes().unwrap();
+        assert_eq!(
+            ChunkManifest::from_bytes(&bytes).unwrap().unwrap(),
+            manifest
+        );
+        assert!(ChunkManifest::from_bytes(b"hunter2").is_none());
+        let garbled = [MANIFEST_MAGIC, b"{"].concat();
+        assert!(ChunkManifest::from_bytes(&garbled).unwrap().is_err());
+
+        let file = temp_path("progress");
+        let path = SplitProgress::path_for(&file);
+        assert!(path.to_string_lossy().ends_with(".shard-progress"));
+        assert_eq!(SplitProgress::load(&path).unwrap(), None);
+        let progress = SplitProgress {
+            key: "key".to_string(),
+            length: 10,
+            chunk_bytes: 4,
+            threshold: 2,
+            providers: manifest.providers,
+            completed: 1,
+            prefix_sha256: "00".to_string(),
+        };
+        progress.save(&path).unwrap();
+        assert_eq!(SplitProgress::load(&path).unwrap(), Some(progress));
+        fs::write(&path, "nonsense").unwrap();
+        assert!(SplitProgress::load(&path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    /// The peak resident memory of the process in kB, as reported by Linux.
+    fn peak_rss_kb() -> u64 {
+        let status = fs::read_to_string("/proc/self/status").unwrap();
+        let line = status
+            .lines()
+            .find(|line| line.starts_with("VmHWM:"))
+            .unwrap();
+        line.split_whitespace().nth(1).unwrap().parse().unwrap()
+    }
+
+    #[test]
+    #[ignore = "writes a 100 MB file; run alone, as other tests add to the peak memory"]
+    fn test_memory_stays_bounded_for_a_large_file() {
+        let (input, output) = (
+            temp_path("chunked-large-in"),
+            temp_path("chunked-large-out"),
+        );
+        {
+            let mut file = BufWriter::new(fs::File::create(&input).unwrap());
+            let block: Vec<u8> = (0..1024 * 1024).map(|i| (i % 253) as u8).collect();
+            for _ in 0..100 {
+                file.write_all(&block).unwrap();
+            }
+        }
+
+        let before = peak_rss_kb();
+        let manifest = round_trip(&input, &output, 32 * 1024);
+        let grown = peak_rss_kb() - before;
+        assert_eq!(manifest.length, 100 * 1024 * 1024);
+        assert!(grown < 16 * 1024, "peak memory grew by {} kB", grown);
+        assert_eq!(fs::metadata(&output).unwrap().len(), manifest.length);
+        fs::remove_file(input).unwrap();
+        fs::remove_file(output).unwrap();
+    }
+}
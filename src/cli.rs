@@ -0,0 +1,14 @@
+/// The options and subcommands the command line parses.
+pub mod args;
+
+/// How a failed command is classified, and the code the process exits with for it.
+pub mod error;
+
+/// The log filter built from `-v`, `-q` and `RUST_LOG`.
+pub mod logging;
+
+/// Prompts for secrets and passphrases on the terminal without echoing them.
+pub mod prompt;
+
+/// The documents the command line prints with `--json`, one per invocation.
+pub mod output;
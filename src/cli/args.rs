@@ -0,0 +1,1208 @@
+//! The command line shared by the shard binaries: its options, its subcommands and the values
+//! they parse, and the shell completions generated from them.
+
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{crate_version, ArgAction, ArgGroup, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use libp2p::{core::Multiaddr, multiaddr::Protocol, PeerId};
+use std::convert::Infallible;
+use std::fmt;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::cli::logging::{LogFormat, LOG_FORMAT_ENV};
+use crate::provider::DbBackend;
+use crate::repository::{ConflictPolicy, FlushPolicy, ShareIndex};
+
+/// The options every shard command takes, and the subcommand to run.
+#[derive(Parser, Debug)]
+#[clap(name = "shard Threshold Network")]
+pub struct Opt {
+    /// Fixed value to generate deterministic peer ID. Client commands otherwise use the identity
+    /// key persisted in the configuration directory, generated on first use.
+    #[clap(long, short)]
+    pub secret_key_seed: Option<u8>,
+
+    /// Directory holding conf.toml and the identity key, written with a default configuration
+    /// if missing. Defaults to $SHARD_CONFIG_DIR, then ~/.shard. Providers only run as its
+    /// identity key when it is given.
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+
+    /// Read the configuration from the SHARD_ environment variables alone, without a
+    /// configuration directory, as setting SHARD_NO_CONFIG_DIR=1 does. Nothing is written, and
+    /// the identity key is taken from SHARD_KEY.
+    #[clap(long, conflicts_with_all = ["config", "profile"])]
+    pub no_config: bool,
+
+    /// Run as the named profile, with the conf.toml and identity key of profiles/<name> in the
+    /// configuration directory. The default profile is the configuration directory itself.
+    /// Defaults to $SHARD_PROFILE, then default.
+    #[clap(long, global = true)]
+    pub profile: Option<String>,
+
+    /// (Deprecated) Run client commands as the identity every client shared before identities
+    /// were persisted, to reach shares registered back then. Removed in the next release.
+    #[clap(long, conflicts_with = "secret_key_seed")]
+    pub legacy_sender: bool,
+
+    /// Address of a peer to connect to.
+    #[clap(long, short)]
+    pub peer: Option<Multiaddr>,
+
+    /// Address to listen on. Repeat it to listen on several. Defaults to the configured
+    /// listen_addresses, then every IPv4 and IPv6 interface.
+    #[clap(long, short)]
+    pub listen_address: Vec<Multiaddr>,
+
+    /// Only talk to nodes of this network, such as staging, keeping it apart from the default
+    /// network. Letters, digits, '-', '_' and '.'.
+    #[clap(long)]
+    pub network_id: Option<String>,
+
+    /// If known, the external address of this node. Will be used to correctly advertise our external address across all transports.
+    #[clap(long, env)]
+    pub external_address: Option<IpAddr>,
+
+    /// Print a single JSON document on stdout instead of text. Warnings and verbose output go
+    /// to stderr.
+    #[clap(long, global = true)]
+    pub json: bool,
+
+    /// Log more, one level above the default per -v, up to trace. Providers log info and other
+    /// commands warnings by default. RUST_LOG takes precedence when set.
+    #[clap(short = 'v', action = ArgAction::Count, global = true, conflicts_with = "quiet")]
+    pub verbosity: u8,
+
+    /// Log less, one level below the default per -q, down to nothing.
+    #[clap(long, short, action = ArgAction::Count, global = true)]
+    pub quiet: u8,
+
+    /// How logs are written to stderr: pretty or compact text, or json, one object per line.
+    /// Defaults to pretty.
+    #[clap(
+        long,
+        global = true,
+        env = LOG_FORMAT_ENV,
+        value_parser = one_of::<LogFormat>(&["json", "pretty", "compact"])
+    )]
+    pub log_format: Option<LogFormat>,
+
+    /// Seconds to wait for the network before giving up: for the answer to each request, for
+    /// the bootstrappers to be dialed and the routing table bootstrapped from them, and for
+    /// enough providers to be found. The provider's own loop runs on regardless. Defaults to 30.
+    #[clap(long, global = true, value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: Option<u64>,
+
+    /// Subcommand to run.
+    #[clap(subcommand)]
+    pub argument: CliArgument,
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "shard")]
+#[command(author = "lodge <jay.logelin@gmail.com>")]
+#[command(version = crate_version!())]
+#[command(
+    about = "SHARD (SHARD Holds And Refreshes (Discrete) Data))",
+    long_about = "SHARD (SHARD Holds And Refreshes (Discrete) Data) threshold network allows users to split secrets into shares, distribute them to share providers, and recombine them at a threshold to rebuild the secret. A node will provide shares to the shard, and refresh them automatically at a specified interval. It works by generating a new refresh key and then updating the shares across the network. The provider node persists all shares to a database, and will use the database on restart. Note that the database is in-memory by default, but can be set to a file-based database using the --db-path flag. Shares can only be retrieved or re-registered by the same client that registers the share with the network, identified by the client's peer ID, which is derived from their public key. Shares are automatically refreshed without changing the secret itself between share providers, enhancing the overall security of the network over time. The refresh interval is set using the --refresh-interval flag, and is set to 30 minutes by default. Default configuration is located at ~/.shard/conf.toml."
+)]
+// parsed once at startup, so the size of the provide options does not matter
+#[allow(clippy::large_enum_variant)]
+pub enum CliArgument {
+    /// (Provider) Run a share provider node that provides shares to shard users, and refresh them automatically at a specified interval.
+    Provide {
+        /// use embedded database for persistence
+        /// otherwise use memory database
+        #[clap(long, short)]
+        db_path: Option<String>,
+
+        /// database backend: sled, sqlite or memory.
+        /// defaults to sled when --db-path is set, otherwise memory
+        #[clap(long, value_parser = one_of::<DbBackend>(&["sled", "sqlite", "memory"]))]
+        db_backend: Option<DbBackend>,
+
+        /// encrypt the embedded database at rest with the key in this file,
+        /// generating a new key if the file does not exist
+        #[clap(long, requires = "db_path")]
+        db_encryption_key_file: Option<PathBuf>,
+
+        /// when the embedded database is flushed to disk between registrations, which are always
+        /// flushed before they are acknowledged: every-write, every:N (writes), interval:SECONDS
+        /// or never. defaults to interval:1
+        #[clap(long)]
+        flush_policy: Option<FlushPolicy>,
+
+        /// keep the memory database in this snapshot file across restarts. the snapshot is
+        /// saved on shutdown and on every flush
+        #[clap(long, conflicts_with = "db_path")]
+        snapshot_path: Option<PathBuf>,
+
+        /// keep each owner's shares in trees of their own in the embedded database, so that
+        /// requests for one owner never touch another's and purging an owner drops its trees
+        #[clap(long, requires = "db_path")]
+        isolate_owners: bool,
+
+        /// refuse registrations that would take an owner past this many shares
+        #[clap(long)]
+        max_entries_per_owner: Option<u64>,
+
+        /// refuse registrations that would take an owner past this many stored bytes
+        #[clap(long)]
+        max_bytes_per_owner: Option<u64>,
+
+        /// refuse registrations that would take the database past this many stored bytes
+        #[clap(long)]
+        max_total_bytes: Option<u64>,
+
+        /// record every register, get, refresh and delete in a hash-chained audit log kept in
+        /// the embedded database at this path
+        #[clap(long)]
+        audit_log: Option<String>,
+
+        /// keep only this many of the most recent audit records
+        #[clap(long, requires = "audit_log")]
+        audit_retention: Option<u64>,
+
+        /// print the audit log, check its hash chain and exit
+        #[clap(long, requires = "audit_log")]
+        dump_audit: bool,
+
+        /// Share refresh interval in seconds
+        // #[clap(long, short, default_value_t = 60)]
+        #[clap(long, short)]
+        refresh_interval: Option<u64>,
+
+        /// spread of the refresh schedule in percent: each share is refreshed up to this much of
+        /// its interval early or late, so providers do not refresh in lockstep. defaults to 10
+        #[clap(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        refresh_jitter: Option<u8>,
+
+        /// how many providers beyond its threshold a share should have. a provider finding fewer
+        /// when the share is due for a refresh publishes an alert naming the share and its
+        /// owner. defaults to 1
+        #[clap(long)]
+        replication_margin: Option<u64>,
+
+        /// how often, in seconds, to publish the provider's health status to the network.
+        /// defaults to 10
+        #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+        status_interval: Option<u64>,
+
+        /// print what the provider does as it happens: shares registered, served and refreshed,
+        /// refreshes it coordinated and requests it rejected. one JSON object a line with --json
+        #[clap(long)]
+        print_events: bool,
+
+        /// serve the provider's metrics to Prometheus on /metrics at this address, such as
+        /// 127.0.0.1:9464, along with a health check on /healthz
+        #[cfg(feature = "metrics")]
+        #[clap(long)]
+        metrics_addr: Option<std::net::SocketAddr>,
+
+        /// refuse shares larger than this many bytes. defaults to 65536
+        #[clap(long)]
+        max_share_bytes: Option<usize>,
+
+        /// throttle each peer to this many requests a second of each operation. the local node's
+        /// own requests are never throttled. unlimited by default
+        #[clap(long)]
+        rate_limit: Option<f64>,
+
+        /// how many requests of an operation a throttled peer can send at once. defaults to 10
+        #[clap(long, requires = "rate_limit")]
+        rate_limit_burst: Option<u32>,
+
+        /// how long, in seconds, to wait for the refresh in flight when asked to stop by SIGTERM
+        /// or Ctrl-C, before flushing the shares and exiting. defaults to 30
+        #[clap(long)]
+        shutdown_grace: Option<u64>,
+
+        /// write the process id to this file while providing, for supervisors. it is removed on
+        /// a graceful shutdown
+        #[clap(long)]
+        pid_file: Option<PathBuf>,
+
+        /// write a backup of the database to this file and exit; the file is created readable by
+        /// its owner alone, and must not exist yet
+        #[clap(long, conflicts_with = "import")]
+        export: Option<PathBuf>,
+
+        /// load a backup written by --export into the database and exit
+        #[clap(long)]
+        import: Option<PathBuf>,
+
+        /// how --import treats keys that already exist with the same owner: skip or overwrite.
+        /// keys owned by another peer are always skipped and reported
+        #[clap(
+            long,
+            default_value = "skip",
+            requires = "import",
+            value_parser = one_of::<ConflictPolicy>(&["skip", "overwrite"])
+        )]
+        on_conflict: ConflictPolicy,
+
+        /// delete every share registered by this peer id from the database and exit
+        #[clap(long, conflicts_with_all = ["export", "import"])]
+        purge_owner: Option<PeerId>,
+
+        /// how long, in seconds, the owner is refused when registering a purged share again
+        /// without --recreate. defaults to one day
+        #[clap(long, requires = "purge_owner")]
+        tombstone_window: Option<u64>,
+
+        /// print the number and size of the stored shares and exit
+        #[clap(long)]
+        stats: bool,
+
+        /// check that every stored share reads back with a valid owner, report the damaged ones
+        /// and exit. unlike a provider starting up, nothing is quarantined
+        #[clap(long)]
+        scan_only: bool,
+    },
+    /// (Client) Combine shares from the network to rebuild a secret.
+    Combine {
+        /// key of the share to get.
+        #[clap(long, short, required_unless_present = "offline")]
+        key: Option<String>,
+
+        /// Share threshold, if none is provided, uses the number of shares
+        #[clap(long, short)]
+        threshold: Option<usize>,
+
+        /// Verbose mode displays the shares. -v raises the log level instead.
+        #[clap(long)]
+        verbose: bool,
+
+        /// Peer id of the owner of the shares, when combining shares another peer granted access
+        /// to. Defaults to this client.
+        #[clap(long)]
+        owner: Option<PeerId>,
+
+        /// Write the rebuilt secret to this file, byte for byte, instead of printing it. Required
+        /// for a file split with `split --file`, which is rebuilt a chunk at a time. The file is
+        /// created readable by its owner alone, and must not exist yet.
+        #[clap(long)]
+        out: Option<PathBuf>,
+
+        /// How to print the rebuilt secret: raw, hex, base64 or utf8. utf8 falls back to hex for
+        /// a secret that is not valid UTF-8. defaults to utf8
+        #[clap(
+            long,
+            conflicts_with = "out",
+            value_parser = one_of::<SecretFormat>(&["raw", "hex", "base64", "utf8"])
+        )]
+        format: Option<SecretFormat>,
+
+        /// Print a raw secret even when stdout is a terminal.
+        #[clap(long)]
+        force: bool,
+
+        /// Only ask this provider for its share, given by peer id or multiaddr. Repeat it to ask
+        /// several. Fails if fewer providers are given than the threshold.
+        #[clap(long, visible_alias = "from-peer")]
+        provider: Vec<ProviderArg>,
+
+        /// Never ask this provider for its share, such as one known to be compromised or flaky.
+        /// Repeat it to exclude several.
+        #[clap(long, conflicts_with = "provider")]
+        exclude_peer: Vec<PeerId>,
+
+        /// Only combine the share of this index, failing if no provider sends it. Repeat it to
+        /// give the exact shares to use, at least as many as the threshold.
+        #[clap(long, value_parser = clap::value_parser!(ShareIndex).range(1..))]
+        share_id: Vec<ShareIndex>,
+
+        /// Keep looking for providers for this many seconds, reporting how many were found, for
+        /// providers still joining the network. Defaults to --timeout.
+        #[clap(long, conflicts_with = "provider")]
+        wait: Option<u64>,
+
+        /// Wait for this many providers before asking them for their shares, rather than only
+        /// for the threshold. Shares are never combined below the threshold they were split with.
+        #[clap(long, conflicts_with = "provider")]
+        min_providers: Option<usize>,
+
+        /// Rebuild the secret from share files written by `split --offline`, given with --from,
+        /// without joining the network.
+        #[clap(
+            long,
+            requires = "from",
+            conflicts_with_all = [
+                "threshold",
+                "owner",
+                "provider",
+                "exclude_peer",
+                "share_id",
+                "wait",
+                "min_providers"
+            ]
+        )]
+        offline: bool,
+
+        /// Share files to rebuild the secret from with --offline, or directories holding them.
+        #[clap(long, num_args = 1.., requires = "offline")]
+        from: Vec<PathBuf>,
+    },
+    /// (Client) Split a secret into shares and propagate them across the network.
+    #[clap(group(ArgGroup::new("secret_source").required(true).args(["secret", "secret_file", "file", "interactive"])))]
+    Split {
+        /// Share threshold.
+        #[clap(long, short)]
+        threshold: usize,
+
+        /// Number of shares to generate.
+        #[clap(long, short)]
+        shares: usize,
+
+        /// key to use to register shares for the secret
+        #[clap(long, short)]
+        key: Option<String>,
+
+        /// Secret to split, or - to read it from stdin until EOF. Prefer --secret-file or stdin,
+        /// which keep the secret out of shell history and the process list.
+        #[clap(long)]
+        secret: Option<SecretArg>,
+
+        /// Read the secret to split from this file, byte for byte.
+        #[clap(long)]
+        secret_file: Option<PathBuf>,
+
+        /// Prompt for the secret to split on the terminal, without echoing it, and confirm it by
+        /// entering it again.
+        #[clap(long)]
+        interactive: bool,
+
+        /// Split this file a chunk at a time, so that files of any size can be split. Each chunk
+        /// is registered under a key of its own, and a manifest of the chunks under the key once
+        /// they all are. A split that fails part way resumes when run again.
+        #[clap(long)]
+        file: Option<PathBuf>,
+
+        /// Length in bytes of the chunks --file is split into. Defaults to 32768.
+        #[clap(
+            long,
+            conflicts_with_all = ["secret", "secret_file"],
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        chunk_bytes: Option<u64>,
+
+        /// Drop one trailing newline (\n or \r\n) from the secret, as added by `echo` or an
+        /// editor. Without it the secret is split exactly as read.
+        #[clap(long)]
+        trim_newline: bool,
+
+        /// Lifetime of the shares in seconds, after which providers destroy them.
+        #[clap(long)]
+        ttl: Option<u64>,
+
+        /// Register the shares even if providers deleted them recently.
+        #[clap(long)]
+        recreate: bool,
+
+        /// How often providers refresh the shares, in seconds. Defaults to each provider's
+        /// refresh interval.
+        #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+        refresh_every: Option<u64>,
+
+        /// Replace shares providers hold under the key with a different threshold or length.
+        #[clap(long)]
+        replace: bool,
+
+        /// Skip reading every share back from its provider once the secret is split, which
+        /// checks that the share was stored intact and places it elsewhere if it was not.
+        #[clap(long)]
+        no_verify: bool,
+
+        /// Split even if providers already hold shares this client registered under the key,
+        /// overwriting them. Without it such a split is refused, so that the shares of two
+        /// secrets are never mixed under one key.
+        #[clap(long)]
+        force: bool,
+
+        /// Place shares only on this provider, given by peer id or multiaddr. Repeat it to pin
+        /// at least --shares providers; a pinned provider that turns a share down fails the split
+        /// instead of being stood in for.
+        #[clap(long)]
+        provider: Vec<ProviderArg>,
+
+        /// Find and select the providers, and print the plan without registering any share.
+        /// Fails if the shares could not all be placed.
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Write the shares to files in --out-dir, with a manifest of the secret, instead of
+        /// registering them with providers. The network is never joined.
+        #[clap(
+            long,
+            requires = "out_dir",
+            conflicts_with_all = [
+                "file",
+                "provider",
+                "dry_run",
+                "ttl",
+                "refresh_every",
+                "force",
+                "no_verify"
+            ]
+        )]
+        offline: bool,
+
+        /// Directory to write the share files to with --offline.
+        #[clap(long, requires = "offline")]
+        out_dir: Option<PathBuf>,
+
+        /// Verbose mode displays the shares. -v raises the log level instead.
+        #[clap(long)]
+        verbose: bool,
+    },
+
+    /// (Client) Get the list of share providers for a secret.
+    Ls {
+        /// key of the secret.
+        #[clap(long, short)]
+        key: String,
+
+        /// Keep looking the providers up, every this many seconds (5 by default), and print
+        /// each change until interrupted.
+        #[clap(long, num_args = 0..=1, value_parser = clap::value_parser!(u64).range(1..))]
+        watch: Option<Option<u64>>,
+
+        /// With --watch, exit as soon as this many providers are found, or fail once --timeout
+        /// runs out before they are.
+        #[clap(long, requires = "watch")]
+        expect: Option<usize>,
+    },
+
+    /// (Client) List the keys this client holds shares under across the network.
+    Keys {
+        /// Only ask this provider, instead of every provider found on the network.
+        #[clap(long)]
+        provider: Option<PeerId>,
+    },
+
+    /// (Client) Refresh the shares
+    Refresh {
+        /// key of the secret.
+        #[clap(long, short, required_unless_present = "offline")]
+        key: Option<String>,
+
+        /// Share threshold.
+        #[clap(long, short, required_unless_present = "offline")]
+        threshold: Option<usize>,
+
+        /// Key size.
+        #[clap(long, short, required_unless_present = "offline")]
+        size: Option<usize>,
+
+        /// Only refresh the share of this provider, given by peer id or multiaddr. Repeat it to
+        /// refresh several.
+        #[clap(long)]
+        provider: Vec<ProviderArg>,
+
+        /// Refresh every share file written by `split --offline` in the --from directory, without
+        /// joining the network. The key, threshold and size are read from the files.
+        #[clap(
+            long,
+            requires = "from",
+            conflicts_with_all = ["key", "threshold", "size", "provider"]
+        )]
+        offline: bool,
+
+        /// Directory of the share files to refresh with --offline.
+        #[clap(long, requires = "offline")]
+        from: Option<PathBuf>,
+    },
+
+    /// (Client) Let another peer get the shares of a secret.
+    Grant {
+        /// key of the secret.
+        #[clap(long, short)]
+        key: String,
+
+        /// Peer id of the reader to grant access to.
+        #[clap(long, short)]
+        peer: PeerId,
+    },
+
+    /// (Client) Stop letting a peer get the shares of a secret.
+    Revoke {
+        /// key of the secret.
+        #[clap(long, short)]
+        key: String,
+
+        /// Peer id of the reader to revoke access from.
+        #[clap(long, short)]
+        peer: PeerId,
+    },
+
+    /// (Client) Hand every share of this identity over to a new identity key, and switch to it
+    /// once every provider has. An interrupted rotation is resumed by running it again.
+    RotateOwner {
+        /// The new identity key: a key file, or the name of the profile whose key to take.
+        #[clap(long)]
+        new_key: String,
+    },
+
+    /// (Client) Show information about this node.
+    Info {
+        /// also list the health of every provider heard from on the network
+        #[clap(long)]
+        network: bool,
+
+        /// refresh the information every second until interrupted
+        #[clap(long)]
+        watch: bool,
+    },
+
+    /// (Client) Generate the identity key client commands run as, persisted in the configuration
+    /// directory, and print its peer id.
+    Keygen {
+        /// replace the identity key already persisted, losing access to the shares registered
+        /// with it
+        #[clap(long)]
+        force: bool,
+
+        /// print the peer id of the persisted identity key instead of generating one
+        #[clap(long, conflicts_with = "force")]
+        show: bool,
+    },
+
+    /// (Client) Show or change the settings of conf.toml.
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// (Client) Manage the profiles of the configuration directory, each with its own identity
+    /// key and configuration.
+    Profile {
+        #[clap(subcommand)]
+        command: ProfileCommand,
+    },
+
+    /// Print the script completing shard's subcommands and flags in a shell, to be sourced from
+    /// the shell's startup file.
+    Completions {
+        /// shell to complete in
+        shell: Shell,
+    },
+}
+
+/// The subcommands of `config`.
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the configuration, as conf.toml holds it.
+    Show,
+
+    /// Set a setting of conf.toml and save it. Lists are given separated by commas, and an empty
+    /// value unsets the setting.
+    Set {
+        /// setting to change, such as bootstrappers or refresh_interval_secs
+        key: String,
+
+        /// value to set it to
+        value: String,
+    },
+}
+
+/// The subcommands of `profile`.
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommand {
+    /// List the profiles of the configuration directory with the peer id of their identity key.
+    List,
+}
+
+/// A secret given on the command line, kept out of the debug output of the parsed options.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretArg(pub String);
+
+impl FromStr for SecretArg {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(SecretArg(s.to_string()))
+    }
+}
+
+impl fmt::Debug for SecretArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretArg(<redacted>)")
+    }
+}
+
+/// A provider given with `--provider`, by its peer id or by an address ending in it.
+///
+/// # Variants
+/// * `Peer` - A provider the node is expected to reach through the network.
+/// * `Addr` - A provider to dial at the address first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderArg {
+    Peer(PeerId),
+    Addr(PeerId, Multiaddr),
+}
+
+impl ProviderArg {
+    pub fn peer_id(&self) -> PeerId {
+        match self {
+            ProviderArg::Peer(peer) | ProviderArg::Addr(peer, _) => *peer,
+        }
+    }
+}
+
+impl FromStr for ProviderArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(peer) = s.parse::<PeerId>() {
+            return Ok(ProviderArg::Peer(peer));
+        }
+        let addr: Multiaddr = s
+            .parse()
+            .map_err(|_| format!("{} is neither a peer id nor a multiaddr", s))?;
+        match addr.iter().last() {
+            Some(Protocol::P2p(peer)) => Ok(ProviderArg::Addr(peer, addr)),
+            _ => Err(format!(
+                "{} does not end in the provider's /p2p/ peer id",
+                s
+            )),
+        }
+    }
+}
+
+/// How `combine` prints a rebuilt secret on stdout.
+///
+/// # Variants
+/// * `Raw` - The secret's bytes, exactly.
+/// * `Hex` - The secret hex-encoded, on a line of its own.
+/// * `Base64` - The secret base64-encoded, on a line of its own.
+/// * `Utf8` - The secret as text, or hex-encoded if it is not valid UTF-8.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SecretFormat {
+    Raw,
+    Hex,
+    Base64,
+    #[default]
+    Utf8,
+}
+
+impl FromStr for SecretFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(SecretFormat::Raw),
+            "hex" => Ok(SecretFormat::Hex),
+            "base64" => Ok(SecretFormat::Base64),
+            "utf8" => Ok(SecretFormat::Utf8),
+            other => Err(format!(
+                "unknown secret format {}, expected one of raw, hex, base64, utf8",
+                other
+            )),
+        }
+    }
+}
+
+/// Parses a value with its `FromStr` implementation, offering `names` in the help and the shell
+/// completions as the values it accepts.
+///
+/// # Arguments
+/// * `names` - Every value `T` parses from.
+///
+/// # Returns
+/// The parser of the values.
+fn one_of<T>(names: &'static [&'static str]) -> impl TypedValueParser<Value = T>
+where
+    T: FromStr<Err = String> + Clone + Send + Sync + 'static,
+{
+    PossibleValuesParser::new(names).try_map(|name| name.parse::<T>())
+}
+
+/// Writes the script completing the command line in `shell` to `out`.
+///
+/// # Arguments
+/// * `shell` - The shell to complete in.
+/// * `bin_name` - The name the binary is run as.
+/// * `out` - Where to write the script.
+pub fn write_completions(shell: Shell, bin_name: &str, out: &mut dyn Write) {
+    clap_complete::generate(shell, &mut Opt::command(), bin_name, out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parse_split(args: &[&str]) -> Result<Opt, clap::Error> {
+        Opt::try_parse_from(["shard", "split", "-t", "2", "-s", "3"].iter().chain(args))
+    }
+
+    fn split_sources(opt: Opt) -> (Option<SecretArg>, Option<PathBuf>, bool) {
+        match opt.argument {
+            CliArgument::Split {
+                secret,
+                secret_file,
+                trim_newline,
+                ..
+            } => (secret, secret_file, trim_newline),
+            _ => panic!("expected split"),
+        }
+    }
+
+    #[test]
+    fn test_split_requires_exactly_one_secret_source() {
+        assert!(parse_split(&[]).is_err());
+        assert!(parse_split(&["--secret", "hunter2", "--secret-file", "secret.bin"]).is_err());
+
+        let (secret, file, trim) = split_sources(parse_split(&["--secret", "hunter2"]).unwrap());
+        assert_eq!(secret, Some(SecretArg("hunter2".to_string())));
+        assert_eq!((file, trim), (None, false));
+
+        let (secret, file, _) = split_sources(parse_split(&["--secret", "-"]).unwrap());
+        assert_eq!(secret, Some(SecretArg("-".to_string())));
+        assert_eq!(file, None);
+
+        let opt = parse_split(&["--secret-file", "secret.bin", "--trim-newline"]).unwrap();
+        let (secret, file, trim) = split_sources(opt);
+        assert_eq!(secret, None);
+        assert_eq!((file, trim), (Some(PathBuf::from("secret.bin")), true));
+    }
+
+    #[test]
+    fn test_secret_is_redacted_from_debug_output() {
+        let opt = parse_split(&["--secret", "hunter2"]).unwrap();
+        let debug = format!("{:?}", opt);
+        assert!(!debug.contains("hunter2"), "{}", debug);
+        assert!(debug.contains("<redacted>"));
+    }
+
+    #[test]
+    fn test_config_flags() {
+        let opt = Opt::try_parse_from(["shard", "--config", "/tmp/conf", "ls", "-k", "k"]).unwrap();
+        assert_eq!(opt.config, Some(PathBuf::from("/tmp/conf")));
+        assert!(!opt.no_config);
+        assert!(
+            Opt::try_parse_from(["shard", "--no-config", "ls", "-k", "k"])
+                .unwrap()
+                .no_config
+        );
+        let both = [
+            "shard",
+            "--config",
+            "/tmp/conf",
+            "--no-config",
+            "ls",
+            "-k",
+            "k",
+        ];
+        assert!(Opt::try_parse_from(both).is_err());
+    }
+
+    #[test]
+    fn test_json_is_global() {
+        assert!(
+            !Opt::try_parse_from(["shard", "ls", "-k", "k"])
+                .unwrap()
+                .json
+        );
+        assert!(
+            Opt::try_parse_from(["shard", "--json", "ls", "-k", "k"])
+                .unwrap()
+                .json
+        );
+        assert!(
+            Opt::try_parse_from(["shard", "ls", "-k", "k", "--json"])
+                .unwrap()
+                .json
+        );
+        let split = parse_split(&["--json", "--secret", "hunter2"]).unwrap();
+        assert!(split.json);
+    }
+
+    #[test]
+    fn test_keys_provider_flag() {
+        let opt = Opt::try_parse_from(["shard", "keys"]).unwrap();
+        assert!(matches!(opt.argument, CliArgument::Keys { provider: None }));
+        let peer = PeerId::random();
+        let opt = Opt::try_parse_from(["shard", "keys", "--provider", &peer.to_string()]).unwrap();
+        assert!(matches!(opt.argument, CliArgument::Keys { provider } if provider == Some(peer)));
+        assert!(Opt::try_parse_from(["shard", "keys", "--provider", "nonsense"]).is_err());
+    }
+
+    #[test]
+    fn test_listen_address_repeats() {
+        let listen = |args: &[&str]| Opt::try_parse_from(args).map(|opt| opt.listen_address);
+        assert!(listen(&["shard", "provide"]).unwrap().is_empty());
+        assert_eq!(
+            listen(&[
+                "shard",
+                "-l",
+                "/ip4/0.0.0.0/tcp/40837",
+                "--listen-address",
+                "/ip4/127.0.0.1/tcp/40838",
+                "provide"
+            ])
+            .unwrap(),
+            vec![
+                "/ip4/0.0.0.0/tcp/40837".parse::<Multiaddr>().unwrap(),
+                "/ip4/127.0.0.1/tcp/40838".parse().unwrap()
+            ]
+        );
+        assert!(listen(&["shard", "-l", "nonsense", "provide"]).is_err());
+    }
+
+    #[test]
+    fn test_timeout_is_global() {
+        let timeout = |args: &[&str]| Opt::try_parse_from(args).map(|opt| opt.timeout);
+        assert_eq!(timeout(&["shard", "ls", "-k", "k"]).unwrap(), None);
+        assert_eq!(
+            timeout(&["shard", "--timeout", "5", "ls", "-k", "k"]).unwrap(),
+            Some(5)
+        );
+        assert_eq!(
+            timeout(&["shard", "combine", "-k", "k", "--timeout", "1"]).unwrap(),
+            Some(1)
+        );
+        assert!(timeout(&["shard", "--timeout", "0", "ls", "-k", "k"]).is_err());
+    }
+
+    #[test]
+    fn test_combine_wait_flags() {
+        let wait = |args: &[&str]| {
+            Opt::try_parse_from(["shard", "combine", "-k", "k"].iter().chain(args)).map(|opt| {
+                match opt.argument {
+                    CliArgument::Combine {
+                        wait,
+                        min_providers,
+                        ..
+                    } => (wait, min_providers),
+                    _ => panic!("expected combine"),
+                }
+            })
+        };
+        assert_eq!(wait(&[]).unwrap(), (None, None));
+        assert_eq!(
+            wait(&["--wait", "60", "--min-providers", "3"]).unwrap(),
+            (Some(60), Some(3))
+        );
+        // pinned providers are not looked up, so there is nothing to wait for
+        let pinned = [
+            "--provider",
+            "12D3KooWPjceQrSwdWXPyLLeABRXmuqt69Rg3sBYbU1Nft9HyQ6X",
+        ];
+        assert!(wait(&[&pinned[..], &["--wait", "60"]].concat()).is_err());
+        assert!(wait(&[&pinned[..], &["--min-providers", "3"]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_combine_selection() {
+        let peer = "12D3KooWPjceQrSwdWXPyLLeABRXmuqt69Rg3sBYbU1Nft9HyQ6X";
+        let selection = |args: &[&str]| {
+            let args = ["shard", "combine", "-k", "k"].iter().chain(args);
+            Opt::try_parse_from(args).map(|opt| match opt.argument {
+                CliArgument::Combine {
+                    provider,
+                    exclude_peer,
+                    share_id,
+                    ..
+                } => (provider.len(), exclude_peer.len(), share_id),
+                _ => panic!("expected combine"),
+            })
+        };
+        assert_eq!(selection(&[]).unwrap(), (0, 0, vec![]));
+        assert_eq!(
+            selection(&["--from-peer", peer, "--provider", peer]).unwrap(),
+            (2, 0, vec![])
+        );
+        assert_eq!(
+            selection(&["--exclude-peer", peer, "--share-id", "2", "--share-id", "5"]).unwrap(),
+            (0, 1, vec![2, 5])
+        );
+        assert!(selection(&["--share-id", "0"]).is_err());
+        assert!(selection(&["--from-peer", peer, "--exclude-peer", peer]).is_err());
+    }
+
+    #[test]
+    fn test_info_flags() {
+        let info = |args: &[&str]| {
+            Opt::try_parse_from(["shard"].iter().chain(args)).map(|opt| (opt.json, opt.argument))
+        };
+        assert!(matches!(
+            info(&["info"]).unwrap(),
+            (
+                false,
+                CliArgument::Info {
+                    network: false,
+                    watch: false
+                }
+            )
+        ));
+        assert!(matches!(
+            info(&["info", "--watch", "--network", "--json"]).unwrap(),
+            (
+                true,
+                CliArgument::Info {
+                    network: true,
+                    watch: true
+                }
+            )
+        ));
+        assert!(info(&["info", "--watch=yes"]).is_err());
+    }
+
+    #[test]
+    fn test_split_file_flags() {
+        let opt = parse_split(&["--file", "backup.tar", "--chunk-bytes", "1024"]).unwrap();
+        assert!(matches!(
+            opt.argument,
+            CliArgument::Split { file: Some(ref path), chunk_bytes: Some(1024), .. }
+                if path == Path::new("backup.tar")
+        ));
+        assert!(parse_split(&["--file", "a", "--secret", "b"]).is_err());
+        assert!(parse_split(&["--secret", "b", "--chunk-bytes", "1024"]).is_err());
+        assert!(parse_split(&["--file", "a", "--chunk-bytes", "0"]).is_err());
+
+        let opt = parse_split(&["--file", "backup.tar", "--dry-run"]).unwrap();
+        assert!(matches!(
+            opt.argument,
+            CliArgument::Split { dry_run: true, .. }
+        ));
+    }
+
+    #[test]
+    fn test_provider_flags() {
+        let peer = PeerId::random();
+        let addr = format!("/ip4/127.0.0.1/tcp/4001/p2p/{}", peer);
+        let opt = parse_split(&[
+            "--secret",
+            "b",
+            "--provider",
+            &peer.to_string(),
+            "--provider",
+            &addr,
+        ])
+        .unwrap();
+        let CliArgument::Split { provider, .. } = opt.argument else {
+            panic!("expected split");
+        };
+        assert_eq!(
+            provider,
+            vec![
+                ProviderArg::Peer(peer),
+                ProviderArg::Addr(peer, addr.parse().unwrap())
+            ]
+        );
+        assert!(parse_split(&["--secret", "b", "--provider", "/ip4/127.0.0.1/tcp/4001"]).is_err());
+
+        let peer = peer.to_string();
+        let combine = ["shard", "combine", "-k", "k", "--provider", &peer];
+        assert!(Opt::try_parse_from(combine).is_ok());
+        let refresh = [
+            "shard",
+            "refresh",
+            "-k",
+            "k",
+            "-t",
+            "2",
+            "-s",
+            "3",
+            "--provider",
+            &peer,
+        ];
+        assert!(Opt::try_parse_from(refresh).is_ok());
+    }
+
+    #[test]
+    fn test_offline_flags() {
+        let parse = |args: &[&str]| Opt::try_parse_from(["shard"].iter().chain(args));
+        assert!(parse(&["split", "-t", "2", "-s", "3", "--secret", "s", "--offline"]).is_err());
+        let split = [
+            "split",
+            "-t",
+            "2",
+            "-s",
+            "3",
+            "--secret",
+            "s",
+            "--offline",
+            "--out-dir",
+            "d",
+        ];
+        assert!(parse(&split).is_ok());
+        assert!(parse(&[&split[..], &["--dry-run"]].concat()).is_err());
+        assert!(parse(&[
+            "split",
+            "-t",
+            "2",
+            "-s",
+            "3",
+            "--secret",
+            "s",
+            "--out-dir",
+            "d"
+        ])
+        .is_err());
+
+        let opt = parse(&["combine", "--offline", "--from", "a.json", "b.json"]).unwrap();
+        let CliArgument::Combine { key, from, .. } = opt.argument else {
+            panic!("expected combine");
+        };
+        assert_eq!(key, None);
+        assert_eq!(from, vec![PathBuf::from("a.json"), PathBuf::from("b.json")]);
+        assert!(parse(&["combine"]).is_err());
+        assert!(parse(&["combine", "--offline"]).is_err());
+        assert!(parse(&["combine", "--offline", "--from", "d", "--wait", "5"]).is_err());
+
+        assert!(parse(&["refresh", "--offline", "--from", "d"]).is_ok());
+        assert!(parse(&["refresh", "--offline", "--from", "d", "-k", "k"]).is_err());
+        assert!(parse(&["refresh", "-k", "k"]).is_err());
+    }
+
+    #[test]
+    fn test_verbosity_flags_repeat() {
+        let parse = |args: &[&str]| {
+            Opt::try_parse_from(["shard"].iter().chain(args)).map(|opt| (opt.verbosity, opt.quiet))
+        };
+        assert_eq!(parse(&["ls", "-k", "k"]).unwrap(), (0, 0));
+        assert_eq!(parse(&["-vv", "ls", "-k", "k"]).unwrap(), (2, 0));
+        assert_eq!(parse(&["ls", "-k", "k", "-v"]).unwrap(), (1, 0));
+        assert_eq!(parse(&["provide", "-qq"]).unwrap(), (0, 2));
+        assert!(parse(&["-v", "-q", "ls", "-k", "k"]).is_err());
+
+        let opt = Opt::try_parse_from(["shard", "combine", "-k", "k", "--verbose", "-v"]).unwrap();
+        let CliArgument::Combine { verbose, .. } = opt.argument else {
+            panic!("expected combine");
+        };
+        assert!(verbose);
+        assert_eq!(opt.verbosity, 1);
+    }
+
+    #[test]
+    fn test_log_format_is_global() {
+        let parse = |args: &[&str]| {
+            Opt::try_parse_from(["shard"].iter().chain(args)).map(|opt| opt.log_format)
+        };
+        assert_eq!(
+            parse(&["provide", "--log-format", "json"]).unwrap(),
+            Some(LogFormat::Json)
+        );
+        assert_eq!(
+            parse(&["--log-format", "compact", "ls", "-k", "k"]).unwrap(),
+            Some(LogFormat::Compact)
+        );
+        assert!(parse(&["provide", "--log-format", "logfmt"]).is_err());
+    }
+
+    #[test]
+    fn test_interactive_is_a_secret_source() {
+        let opt = parse_split(&["--interactive"]).unwrap();
+        let CliArgument::Split { interactive, .. } = opt.argument else {
+            panic!("expected split");
+        };
+        assert!(interactive);
+        assert!(parse_split(&["--interactive", "--secret", "s"]).is_err());
+        assert!(parse_split(&["--interactive", "--file", "f"]).is_err());
+    }
+
+    #[test]
+    fn test_ls_watch() {
+        let ls = |args: &[&str]| {
+            Opt::try_parse_from(["shard", "ls", "-k", "k"].iter().chain(args)).map(|opt| match opt
+                .argument
+            {
+                CliArgument::Ls { watch, expect, .. } => (watch, expect),
+                _ => panic!("expected ls"),
+            })
+        };
+        assert_eq!(ls(&[]).unwrap(), (None, None));
+        assert_eq!(ls(&["--watch"]).unwrap(), (Some(None), None));
+        assert_eq!(ls(&["--watch", "2"]).unwrap(), (Some(Some(2)), None));
+        assert_eq!(
+            ls(&["--watch", "--expect", "3"]).unwrap(),
+            (Some(None), Some(3))
+        );
+        assert!(ls(&["--watch", "0"]).is_err());
+        assert!(ls(&["--expect", "3"]).is_err());
+    }
+
+    #[test]
+    fn test_profile_is_global() {
+        let keygen = Opt::try_parse_from(["shard", "keygen", "--profile", "owner"]).unwrap();
+        assert_eq!(keygen.profile.as_deref(), Some("owner"));
+        let list = Opt::try_parse_from(["shard", "profile", "list"]).unwrap();
+        assert!(matches!(
+            list.argument,
+            CliArgument::Profile {
+                command: ProfileCommand::List
+            }
+        ));
+        assert!(Opt::try_parse_from(["shard", "--no-config", "--profile", "x", "info"]).is_err());
+    }
+
+    #[test]
+    fn test_config_subcommands() {
+        let set = Opt::try_parse_from(["shard", "config", "set", "network_id", "staging"]).unwrap();
+        assert!(matches!(
+            set.argument,
+            CliArgument::Config {
+                command: ConfigCommand::Set { key, value }
+            } if key == "network_id" && value == "staging"
+        ));
+        assert!(Opt::try_parse_from(["shard", "config", "set", "network_id"]).is_err());
+        let show = Opt::try_parse_from(["shard", "--profile", "owner", "config", "show"]).unwrap();
+        assert_eq!(show.profile.as_deref(), Some("owner"));
+    }
+
+    #[test]
+    fn test_completions_name_every_subcommand() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let mut script = Vec::new();
+            write_completions(shell, "shard", &mut script);
+            let script = String::from_utf8(script).unwrap();
+            for subcommand in Opt::command().get_subcommands() {
+                let name = subcommand.get_name();
+                assert!(script.contains(name), "{} misses {}", shell, name);
+            }
+            for format in ["raw", "hex", "base64", "utf8"] {
+                assert!(script.contains(format), "{} misses {}", shell, format);
+            }
+        }
+        assert!(Opt::try_parse_from(["shard", "completions", "tcsh"]).is_err());
+    }
+
+    #[test]
+    fn test_enumerated_values() {
+        let format = |value: &str| {
+            Opt::try_parse_from(["shard", "combine", "-k", "k", "--format", value]).map(|opt| {
+                match opt.argument {
+                    CliArgument::Combine { format, .. } => format,
+                    _ => panic!("expected combine"),
+                }
+            })
+        };
+        assert_eq!(format("base64").unwrap(), Some(SecretFormat::Base64));
+        assert!(format("binary").is_err());
+        let backend = Opt::try_parse_from(["shard", "provide", "--db-backend", "memory"]).unwrap();
+        assert!(matches!(
+            backend.argument,
+            CliArgument::Provide {
+                db_backend: Some(DbBackend::Memory),
+                ..
+            }
+        ));
+        assert!(Opt::try_parse_from(["shard", "provide", "--db-backend", "redis"]).is_err());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_addr_is_a_socket_address() {
+        let opt = Opt::try_parse_from(["shard", "provide", "--metrics-addr", "127.0.0.1:9464"]);
+        let CliArgument::Provide { metrics_addr, .. } = opt.unwrap().argument else {
+            panic!("expected provide");
+        };
+        assert_eq!(metrics_addr, Some("127.0.0.1:9464".parse().unwrap()));
+        assert!(Opt::try_parse_from(["shard", "provide", "--metrics-addr", "localhost"]).is_err());
+    }
+}
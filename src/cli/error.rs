@@ -0,0 +1,284 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::client::{ClientError, NotReady};
+use crate::config::ConfigError;
+use crate::protocol::Failure;
+use crate::repository::RepoError;
+
+/// The class of a failed command, which decides the code the process exits with so that scripts
+/// can tell failures apart without parsing messages.
+///
+/// # Variants
+///
+/// * `Other` - Any failure not classified below. Exits with 1.
+/// * `Usage` - The command line asks for something that cannot be done as given. Exits with 2,
+///   like the errors of the argument parser itself.
+/// * `NoProviders` - No provider holds the shares asked for. Exits with 3.
+/// * `QuorumNotMet` - Fewer providers were found or answered than the threshold needs. Exits
+///   with 4.
+/// * `Denied` - The providers refused the sender, which neither owns nor may read the shares.
+///   Exits with 5.
+/// * `Timeout` - The network did not answer in time. Exits with 6.
+/// * `Storage` - A database could not be read or written. Exits with 7.
+/// * `PartialFailure` - Some of the providers asked did not carry out the command, though others
+///   did. Exits with 8.
+/// * `Network` - A peer could not be dialed. Exits with 9.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::cli::error::ErrorKind;
+///
+/// assert_eq!(ErrorKind::Denied.exit_code(), 5);
+/// assert_eq!(ErrorKind::Denied.name(), "denied");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Other,
+    Usage,
+    NoProviders,
+    QuorumNotMet,
+    Denied,
+    Timeout,
+    Storage,
+    PartialFailure,
+    Network,
+}
+
+impl ErrorKind {
+    /// Returns the code the process exits with on a failure of this kind.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Usage => 2,
+            ErrorKind::NoProviders => 3,
+            ErrorKind::QuorumNotMet => 4,
+            ErrorKind::Denied => 5,
+            ErrorKind::Timeout => 6,
+            ErrorKind::Storage => 7,
+            ErrorKind::PartialFailure => 8,
+            ErrorKind::Network => 9,
+        }
+    }
+
+    /// Returns the snake case name of the kind, as printed in the `--json` error document.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Other => "other",
+            ErrorKind::Usage => "usage",
+            ErrorKind::NoProviders => "no_providers",
+            ErrorKind::QuorumNotMet => "quorum_not_met",
+            ErrorKind::Denied => "denied",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Storage => "storage",
+            ErrorKind::PartialFailure => "partial_failure",
+            ErrorKind::Network => "network",
+        }
+    }
+}
+
+/// A failure of a command, with the kind it exits as.
+///
+/// # Fields
+///
+/// * `kind` - The class of the failure.
+/// * `message` - What went wrong, for people.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::cli::error::{classify, CliError, ErrorKind};
+///
+/// let error: Box<dyn std::error::Error> = CliError::new(ErrorKind::NoProviders, "none").into();
+/// assert_eq!(classify(&*error), ErrorKind::NoProviders);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl CliError {
+    /// Creates a failure of `kind` described by `message`.
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        CliError {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for CliError {}
+
+/// Classifies the error a command failed with, from its type.
+///
+/// # Arguments
+///
+/// * `error` - The error the command failed with.
+///
+/// # Returns
+///
+/// The kind of a `CliError`; `NoProviders`, `QuorumNotMet` or `Timeout` for a network that was
+/// not ready, by what it lacked; `Denied` or `Storage` for a provider's `Failure` of that kind;
+/// `Storage` for a `RepoError`; `Network` for a peer that could not be dialed; `Usage` for an
+/// invalid setting, in the environment or given to be saved, an invalid profile name or a
+/// configuration with no file to save to; and `Other` for anything else. A `shard::Error` or a `ClientError` is classified by the error it wraps,
+/// and a `shard::Error::Invalid` as `Usage`.
+pub fn classify(error: &(dyn Error + 'static)) -> ErrorKind {
+    if let Some(error) = error.downcast_ref::<crate::Error>() {
+        return match error {
+            crate::Error::Client(error) => classify(error),
+            crate::Error::Config(error) => classify(error),
+            crate::Error::Storage(error) => classify(error),
+            crate::Error::Failure(failure) => classify(failure),
+            crate::Error::Invalid(_) => ErrorKind::Usage,
+            crate::Error::Sss(_) | crate::Error::Network(_) => ErrorKind::Other,
+        };
+    }
+    if let Some(error) = error.downcast_ref::<ClientError>() {
+        return match error {
+            ClientError::Failure(failure) => classify(failure),
+            ClientError::NotReady(not_ready) => classify(not_ready),
+            ClientError::Dial(_) => ErrorKind::Network,
+            _ => ErrorKind::Other,
+        };
+    }
+    if let Some(error) = error.downcast_ref::<CliError>() {
+        return error.kind;
+    }
+    if let Some(not_ready) = error.downcast_ref::<NotReady>() {
+        return match not_ready {
+            NotReady::Providers { found: 0, .. } => ErrorKind::NoProviders,
+            NotReady::Providers { .. } => ErrorKind::QuorumNotMet,
+            NotReady::Connections { .. } | NotReady::Bootstrap(_) => ErrorKind::Timeout,
+        };
+    }
+    if let Some(failure) = error.downcast_ref::<Failure>() {
+        return match failure {
+            Failure::NotOwner { .. } | Failure::NotReader => ErrorKind::Denied,
+            Failure::StorageError(_) | Failure::Corrupt | Failure::QuotaExceeded(_) => {
+                ErrorKind::Storage
+            }
+            _ => ErrorKind::Other,
+        };
+    }
+    if error.is::<RepoError>() {
+        return ErrorKind::Storage;
+    }
+    if let Some(
+        ConfigError::InvalidEnv(..)
+        | ConfigError::InvalidProfile(..)
+        | ConfigError::InvalidSetting(..)
+        | ConfigError::NotSaved,
+    ) = error.downcast_ref::<ConfigError>()
+    {
+        return ErrorKind::Usage;
+    }
+    ErrorKind::Other
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::swarm::DialError;
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let kinds = [
+            ErrorKind::Other,
+            ErrorKind::Usage,
+            ErrorKind::NoProviders,
+            ErrorKind::QuorumNotMet,
+            ErrorKind::Denied,
+            ErrorKind::Timeout,
+            ErrorKind::Storage,
+            ErrorKind::PartialFailure,
+            ErrorKind::Network,
+        ];
+        let codes: std::collections::HashSet<u8> = kinds.iter().map(|k| k.exit_code()).collect();
+        assert_eq!(codes.len(), kinds.len());
+        assert!(!codes.contains(&0));
+    }
+
+    #[test]
+    fn test_errors_are_classified_by_type() {
+        let kind = |error: Box<dyn Error>| classify(&*error);
+        assert_eq!(
+            kind(CliError::new(ErrorKind::Usage, "pass --out").into()),
+            ErrorKind::Usage
+        );
+        assert_eq!(
+            kind(
+                NotReady::Providers {
+                    found: 0,
+                    wanted: 3
+                }
+                .into()
+            ),
+            ErrorKind::NoProviders
+        );
+        assert_eq!(
+            kind(
+                NotReady::Providers {
+                    found: 2,
+                    wanted: 3
+                }
+                .into()
+            ),
+            ErrorKind::QuorumNotMet
+        );
+        assert_eq!(kind(NotReady::Bootstrap(None).into()), ErrorKind::Timeout);
+        assert_eq!(kind(Failure::NotReader.into()), ErrorKind::Denied);
+        assert_eq!(
+            kind(Failure::StorageError("disk full".to_string()).into()),
+            ErrorKind::Storage
+        );
+        assert_eq!(kind(RepoError::ReadOnly.into()), ErrorKind::Storage);
+        let invalid = ConfigError::InvalidEnv("SHARD_DB_PATH".to_string(), "empty".to_string());
+        assert_eq!(kind(invalid.into()), ErrorKind::Usage);
+        assert_eq!(kind("something else".into()), ErrorKind::Other);
+    }
+
+    #[test]
+    fn test_wrapped_errors_are_classified_by_what_they_wrap() {
+        let kind = |error: crate::Error| classify(&*Box::<dyn Error>::from(error));
+        assert_eq!(
+            kind(ClientError::from(Failure::NotReader).into()),
+            ErrorKind::Denied
+        );
+        assert_eq!(
+            kind(
+                NotReady::Providers {
+                    found: 0,
+                    wanted: 3
+                }
+                .into()
+            ),
+            ErrorKind::NoProviders
+        );
+        assert_eq!(kind(RepoError::ReadOnly.into()), ErrorKind::Storage);
+        assert_eq!(
+            kind(crate::Error::Failure(Failure::Corrupt)),
+            ErrorKind::Storage
+        );
+        assert_eq!(
+            kind(crate::Error::Invalid("pick one".to_string())),
+            ErrorKind::Usage
+        );
+        assert_eq!(
+            kind(crate::sss::Error::InvalidThreshold.into()),
+            ErrorKind::Other
+        );
+        let refused: Box<dyn Error> = ClientError::Refused("busy".to_string()).into();
+        assert_eq!(classify(&*refused), ErrorKind::Other);
+        let undialed: Box<dyn Error> = ClientError::Dial(DialError::Aborted).into();
+        assert_eq!(classify(&*undialed), ErrorKind::Network);
+    }
+}
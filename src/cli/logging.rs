@@ -0,0 +1,378 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::Record;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields, MakeWriter};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
+
+use crate::cli::args::CliArgument;
+
+/// The environment variable the log format is read from when `--log-format` is not given.
+pub const LOG_FORMAT_ENV: &str = "SHARD_LOG_FORMAT";
+
+/// How log lines are written.
+///
+/// # Variants
+///
+/// * `Pretty` - One line per event with its time, level, target and fields, coloured on a
+///   terminal. The default.
+/// * `Compact` - Like `Pretty`, with the fields of the spans the event is in folded into it.
+/// * `Json` - One JSON object per line (see `JsonFormat`), for log pipelines.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::cli::logging::LogFormat;
+///
+/// assert_eq!("json".parse::<LogFormat>(), Ok(LogFormat::Json));
+/// assert_eq!(LogFormat::default(), LogFormat::Pretty);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Compact,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(LogFormat::Pretty),
+            "compact" => Ok(LogFormat::Compact),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "unknown log format {s:?}, expected json, pretty or compact"
+            )),
+        }
+    }
+}
+
+/// Writes each event as a JSON object on a line of its own, with its `timestamp`, `level`,
+/// `target`, the `fields` it carries, `message` among them, and, when it is in any span, the
+/// names of the `spans` it is in, outermost first, and their fields merged in a `context`, the
+/// innermost span's taking precedence. The `trace_id` of a request is found there.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFormat;
+
+impl<S> FormatEvent<S, JsonSpanFields> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonSpanFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+        let mut fields = JsonFields::default();
+        event.record(&mut fields);
+
+        let mut line = Map::new();
+        line.insert("timestamp".to_string(), Value::String(timestamp));
+        let metadata = event.metadata();
+        line.insert(
+            "level".to_string(),
+            Value::String(metadata.level().to_string()),
+        );
+        line.insert(
+            "target".to_string(),
+            Value::String(metadata.target().to_string()),
+        );
+        line.insert("fields".to_string(), Value::Object(fields.0));
+        if let Some(scope) = ctx.event_scope() {
+            let mut spans = Vec::new();
+            let mut context = Map::new();
+            for span in scope.from_root() {
+                spans.push(Value::String(span.name().to_string()));
+                if let Some(fields) = span.extensions().get::<FormattedFields<JsonSpanFields>>() {
+                    context.extend(JsonSpanFields::parse(fields));
+                }
+            }
+            line.insert("spans".to_string(), Value::Array(spans));
+            line.insert("context".to_string(), Value::Object(context));
+        }
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+/// Keeps the fields of each span as a JSON object, for `JsonFormat` to write them with the
+/// events in the span.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonSpanFields;
+
+impl JsonSpanFields {
+    /// Reads back the fields kept for a span.
+    fn parse(fields: &FormattedFields<JsonSpanFields>) -> Map<String, Value> {
+        serde_json::from_str(&fields.fields).unwrap_or_default()
+    }
+}
+
+impl<'w> FormatFields<'w> for JsonSpanFields {
+    fn format_fields<R: RecordFields>(&self, mut writer: Writer<'w>, fields: R) -> fmt::Result {
+        let mut json = JsonFields::default();
+        fields.record(&mut json);
+        write!(writer, "{}", Value::Object(json.0))
+    }
+
+    fn add_fields(
+        &self,
+        current: &'w mut FormattedFields<Self>,
+        fields: &Record<'_>,
+    ) -> fmt::Result {
+        let mut json = JsonFields(JsonSpanFields::parse(current));
+        fields.record(&mut json);
+        current.fields = Value::Object(json.0).to_string();
+        Ok(())
+    }
+}
+
+/// Collects the fields of an event as JSON values, keeping numbers and booleans as such.
+#[derive(Default)]
+struct JsonFields(Map<String, Value>);
+
+impl Visit for JsonFields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.0
+            .insert(field.name().to_string(), Value::from(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), Value::from(format!("{value:?}")));
+    }
+}
+
+/// Builds the subscriber the command line logs with.
+///
+/// # Arguments
+///
+/// * `filter` - Which events are logged (see `log_filter`).
+/// * `format` - How they are written.
+/// * `writer` - Where they are written, stderr for the command line.
+///
+/// # Returns
+///
+/// The subscriber, to be installed as the global default.
+pub fn subscriber<W>(
+    filter: EnvFilter,
+    format: LogFormat,
+    writer: W,
+) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer);
+    match format {
+        LogFormat::Pretty => Box::new(builder.finish()),
+        LogFormat::Compact => Box::new(builder.compact().finish()),
+        LogFormat::Json => Box::new(
+            builder
+                .fmt_fields(JsonSpanFields)
+                .event_format(JsonFormat)
+                .finish(),
+        ),
+    }
+}
+
+/// The levels `-v` and `-q` step through, quietest first.
+const LEVELS: [LevelFilter; 6] = [
+    LevelFilter::OFF,
+    LevelFilter::ERROR,
+    LevelFilter::WARN,
+    LevelFilter::INFO,
+    LevelFilter::DEBUG,
+    LevelFilter::TRACE,
+];
+
+/// The level a command logs at without `-v` or `-q`: a provider runs on and reports what it
+/// does, while the one-shot commands keep to warnings so that what they print stays clean.
+///
+/// # Arguments
+///
+/// * `argument` - The command run.
+///
+/// # Returns
+///
+/// `INFO` for `provide` and `WARN` for any other command.
+pub fn default_level(argument: &CliArgument) -> LevelFilter {
+    match argument {
+        CliArgument::Provide { .. } => LevelFilter::INFO,
+        _ => LevelFilter::WARN,
+    }
+}
+
+/// Builds the filter of the log output from the `-v` and `-q` flags.
+///
+/// # Arguments
+///
+/// * `verbose` - The number of times `-v` was given, each one level more verbose than `default`.
+/// * `quiet` - The number of times `-q` was given, each one level quieter than `default`.
+/// * `default` - The level logged at without either flag.
+/// * `rust_log` - The value of `RUST_LOG`, which takes precedence over the flags when set.
+///
+/// # Returns
+///
+/// The filter parsed from `rust_log` if it is set and not empty, or one of the level the flags
+/// step to, stopping at `TRACE` and `OFF`.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::cli::logging::log_filter;
+/// use tracing_subscriber::filter::LevelFilter;
+///
+/// assert_eq!(log_filter(1, 0, LevelFilter::WARN, None).to_string(), "info");
+/// assert_eq!(log_filter(1, 0, LevelFilter::WARN, Some("shard=trace")).to_string(), "shard=trace");
+/// ```
+pub fn log_filter(
+    verbose: u8,
+    quiet: u8,
+    default: LevelFilter,
+    rust_log: Option<&str>,
+) -> EnvFilter {
+    if let Some(directives) = rust_log.filter(|directives| !directives.trim().is_empty()) {
+        return EnvFilter::builder().parse_lossy(directives);
+    }
+    let start = LEVELS
+        .iter()
+        .position(|level| *level == default)
+        .unwrap_or(2) as i32;
+    let index = (start + verbose as i32 - quiet as i32).clamp(0, LEVELS.len() as i32 - 1);
+    EnvFilter::default().add_directive(LEVELS[index as usize].into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    /// Collects what a subscriber writes.
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_flags_step_from_the_default_level() {
+        let filter =
+            |verbose, quiet, default| log_filter(verbose, quiet, default, None).to_string();
+        assert_eq!(filter(0, 0, LevelFilter::WARN), "warn");
+        assert_eq!(filter(1, 0, LevelFilter::WARN), "info");
+        assert_eq!(filter(2, 0, LevelFilter::WARN), "debug");
+        assert_eq!(filter(3, 0, LevelFilter::WARN), "trace");
+        assert_eq!(filter(9, 0, LevelFilter::WARN), "trace");
+        assert_eq!(filter(1, 0, LevelFilter::INFO), "debug");
+        assert_eq!(filter(2, 0, LevelFilter::INFO), "trace");
+        assert_eq!(filter(0, 1, LevelFilter::WARN), "error");
+        assert_eq!(filter(0, 2, LevelFilter::WARN), "off");
+        assert_eq!(filter(0, 9, LevelFilter::WARN), "off");
+        assert_eq!(filter(0, 0, LevelFilter::INFO), "info");
+        assert_eq!(filter(0, 1, LevelFilter::INFO), "warn");
+        assert_eq!(filter(0, 3, LevelFilter::INFO), "off");
+    }
+
+    #[test]
+    fn test_rust_log_takes_precedence() {
+        let filter = |rust_log| log_filter(2, 0, LevelFilter::WARN, rust_log).to_string();
+        assert_eq!(filter(Some("libp2p_kad=trace")), "libp2p_kad=trace");
+        assert_eq!(filter(Some("error")), "error");
+        assert_eq!(filter(Some("")), "debug");
+        assert_eq!(filter(None), "debug");
+    }
+
+    #[test]
+    fn test_json_lines_carry_the_fields_of_the_event() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let buffer = Arc::clone(&written);
+        let writer = move || Capture(Arc::clone(&buffer));
+        let filter = log_filter(0, 0, LevelFilter::INFO, None);
+        tracing::subscriber::with_default(subscriber(filter, LogFormat::Json, writer), || {
+            let span = tracing::info_span!("provide", peer = "provider", key = "other-name");
+            let _entered = span.enter();
+            let request = tracing::info_span!("request", trace_id = tracing::field::Empty);
+            request.record("trace_id", "000000000000002a");
+            let _entered = request.enter();
+            tracing::warn!(
+                key = "shared-name",
+                shares = 3u64,
+                refused = true,
+                "Refused share."
+            );
+            tracing::debug!("filtered out");
+        });
+
+        let written = String::from_utf8(written.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 1, "{written}");
+        let line: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["target"], module_path!());
+        assert_eq!(line["fields"]["message"], "Refused share.");
+        assert_eq!(line["fields"]["key"], "shared-name");
+        assert_eq!(line["fields"]["shares"], 3);
+        assert_eq!(line["fields"]["refused"], true);
+        assert_eq!(line["spans"], serde_json::json!(["provide", "request"]));
+        assert_eq!(
+            line["context"],
+            serde_json::json!({
+                "peer": "provider",
+                "key": "other-name",
+                "trace_id": "000000000000002a"
+            })
+        );
+        assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn test_providers_log_more_by_default() {
+        let provide = crate::cli::args::Opt::try_parse_from(["shard", "provide"]).unwrap();
+        assert_eq!(default_level(&provide.argument), LevelFilter::INFO);
+        let ls = crate::cli::args::Opt::try_parse_from(["shard", "ls", "-k", "k"]).unwrap();
+        assert_eq!(default_level(&ls.argument), LevelFilter::WARN);
+    }
+}
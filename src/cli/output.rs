@@ -0,0 +1,869 @@
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use crate::network::NetworkInfo;
+use crate::protocol::{MetricsSnapshot, ProviderStatus};
+use crate::provider::ProviderEvent;
+use crate::rotation::RotationReport;
+
+/// Serializes an output document as the single line of JSON printed on stdout with `--json`.
+///
+/// # Arguments
+///
+/// * `output` - The document to print.
+///
+/// # Returns
+///
+/// The document as compact JSON, or an error if it cannot be serialized.
+///
+/// # Examples
+///
+/// ```
+/// use shard::cli::output::{to_json, LsOutput};
+///
+/// let output = LsOutput { key: "key".to_string(), providers: vec![] };
+/// assert_eq!(to_json(&output).unwrap(), r#"{"key":"key","providers":[]}"#);
+/// ```
+pub fn to_json<T: Serialize>(output: &T) -> Result<String, serde_json::Error> {
+    serde_json::to_string(output)
+}
+
+/// Lists peers as their base58 ids, sorted.
+///
+/// # Arguments
+///
+/// * `peers` - The peers to list.
+///
+/// # Returns
+///
+/// The sorted peer ids.
+pub fn peer_ids(peers: impl IntoIterator<Item = PeerId>) -> Vec<String> {
+    let mut peers: Vec<String> = peers.into_iter().map(|peer| peer.to_string()).collect();
+    peers.sort();
+    peers
+}
+
+/// How a provider answered the registration of a share.
+///
+/// # Variants
+///
+/// * `Registered` - The provider stored the share.
+/// * `QuotaExceeded` - The provider is out of storage; the share went to a spare provider.
+/// * `Conflict` - The provider holds a different share under the key.
+/// * `Refused` - The provider refused the share.
+/// * `Failed` - The request did not get an answer.
+/// * `Unverified` - The provider registered the share but did not return it intact when it was
+///   read back; the share went to a spare provider if one was left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationOutcome {
+    Registered,
+    QuotaExceeded,
+    Conflict,
+    Refused,
+    Failed,
+    Unverified,
+}
+
+/// A provider a share was sent to, and how it answered.
+///
+/// # Fields
+///
+/// * `peer` - The provider's peer id.
+/// * `status` - How the provider answered.
+/// * `reason` - Why the share was not registered, if it was not.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderOutcome {
+    pub peer: String,
+    pub status: RegistrationOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// What `split` prints with `--json`.
+///
+/// # Fields
+///
+/// * `key` - The key the shares were registered under.
+/// * `threshold` - The number of shares needed to rebuild the secret.
+/// * `shares` - The number of shares the secret was split into.
+/// * `providers` - Every provider a share was sent to, including those that turned it down.
+/// * `chunks` - The number of chunks a file was split into, with `--file`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SplitOutput {
+    pub key: String,
+    pub threshold: usize,
+    pub shares: usize,
+    pub providers: Vec<ProviderOutcome>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<u64>,
+}
+
+/// What `split --dry-run` prints with `--json`: the providers the shares would be placed on,
+/// without any being registered.
+///
+/// # Fields
+///
+/// * `key` - The key the shares would be registered under.
+/// * `threshold` - The number of shares needed to rebuild the secret.
+/// * `shares` - The number of shares the secret would be split into.
+/// * `share_bytes` - The length of each share, or of each share of a chunk with `--file`.
+/// * `chunks` - The number of chunks a file would be split into, with `--file`.
+/// * `discovered` - The number of providers found on the network, or pinned.
+/// * `pinned` - Whether the providers were pinned with `--provider`.
+/// * `providers` - The providers the shares would be placed on.
+/// * `spares` - The providers standing in for those out of storage.
+/// * `feasible` - Whether the split would place every share.
+/// * `reason` - Why the split would fail, when it is not feasible.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SplitPlanOutput {
+    pub key: String,
+    pub threshold: usize,
+    pub shares: usize,
+    pub share_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<u64>,
+    pub discovered: usize,
+    pub pinned: bool,
+    pub providers: Vec<String>,
+    pub spares: Vec<String>,
+    pub feasible: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// What `split --offline` and `refresh --offline` print with `--json`.
+///
+/// # Fields
+///
+/// * `key` - The key the secret was split under.
+/// * `threshold` - The number of shares needed to rebuild the secret.
+/// * `epoch` - The number of times the shares were refreshed.
+/// * `files` - The share files written.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareFilesOutput {
+    pub key: String,
+    pub threshold: usize,
+    pub epoch: u64,
+    pub files: Vec<String>,
+}
+
+/// What `combine` prints with `--json`.
+///
+/// # Fields
+///
+/// * `key` - The key of the combined shares.
+/// * `secret_b64` - The rebuilt secret, base64-encoded. Left out when it was written to `out`.
+/// * `out` - The file the secret was written to, if any.
+/// * `shares_used` - The number of shares the secret, or each chunk of a file, was rebuilt from.
+/// * `chunks` - The number of chunks of a file that was split in chunks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CombineOutput {
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_b64: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub out: Option<String>,
+    pub shares_used: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<u64>,
+}
+
+/// What `ls --watch` prints with `--json`, once for the providers first found and once for
+/// every change after.
+///
+/// # Fields
+///
+/// * `time` - When the change was seen, in seconds since the Unix epoch.
+/// * `key` - The key that is watched.
+/// * `providers` - Every provider of the key after the change.
+/// * `added` - The providers found since the last change.
+/// * `removed` - The providers no longer found since the last change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvidersChangeOutput {
+    pub time: u64,
+    pub key: String,
+    pub providers: Vec<String>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// What `ls` prints with `--json`.
+///
+/// # Fields
+///
+/// * `key` - The key that was looked up.
+/// * `providers` - The providers of the key's shares.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LsOutput {
+    pub key: String,
+    pub providers: Vec<String>,
+}
+
+/// What `refresh` prints with `--json`.
+///
+/// # Fields
+///
+/// * `key` - The key of the refreshed shares.
+/// * `refreshed` - The providers that applied the refresh.
+/// * `failed` - The providers that did not.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefreshOutput {
+    pub key: String,
+    pub refreshed: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+/// What `grant` and `revoke` print with `--json`.
+///
+/// # Fields
+///
+/// * `key` - The key of the shares.
+/// * `reader` - The peer whose access changed.
+/// * `granted` - Whether access was granted rather than revoked.
+/// * `changed` - The number of providers that applied the change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessOutput {
+    pub key: String,
+    pub reader: String,
+    pub granted: bool,
+    pub changed: usize,
+}
+
+/// What `rotate-owner` prints with `--json`.
+///
+/// # Fields
+///
+/// * `report` - The shares handed over to the new owner, and the transfers left to resume.
+/// * `resumed` - Whether a rotation interrupted before was resumed.
+/// * `identity_replaced` - Whether every transfer completed, so that the new key is now the
+///   identity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RotateOwnerOutput {
+    #[serde(flatten)]
+    pub report: RotationReport,
+    pub resumed: bool,
+    pub identity_replaced: bool,
+}
+
+/// A key the client holds shares under, as `keys` lists it.
+///
+/// # Fields
+///
+/// * `key` - The key.
+/// * `providers` - The providers holding a share under the key.
+/// * `threshold` - The threshold the secret was split with, if a provider described its share.
+/// * `last_refreshed_unix` - When that provider last refreshed its share.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyListing {
+    pub key: String,
+    pub providers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_refreshed_unix: Option<u64>,
+}
+
+/// A provider that did not list its keys.
+///
+/// # Fields
+///
+/// * `peer` - The provider's peer id.
+/// * `reason` - Why it did not.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnansweredProvider {
+    pub peer: String,
+    pub reason: String,
+}
+
+/// What `keys` prints with `--json`.
+///
+/// # Fields
+///
+/// * `keys` - Every key found, in order.
+/// * `unanswered` - The providers asked that did not list their keys.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeysOutput {
+    pub keys: Vec<KeyListing>,
+    pub unanswered: Vec<UnansweredProvider>,
+}
+
+/// A provider's health status, as `info --network` prints it with `--json`.
+///
+/// # Fields
+///
+/// * `peer` - The provider's peer id, or its hex-encoded bytes if they do not parse.
+/// * `shares` - The number of shares the provider stores.
+/// * `db_bytes` - The size of the provider's stored shares.
+/// * `uptime_secs` - How long the provider has been running.
+/// * `version` - The provider's version.
+/// * `reachable` - Whether the provider is reachable from outside its network.
+/// * `metrics` - The provider's counters.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderInfo {
+    pub peer: String,
+    pub shares: u64,
+    pub db_bytes: u64,
+    pub uptime_secs: u64,
+    pub version: String,
+    pub reachable: bool,
+    pub metrics: MetricsSnapshot,
+}
+
+impl From<&ProviderStatus> for ProviderInfo {
+    fn from(status: &ProviderStatus) -> Self {
+        ProviderInfo {
+            peer: PeerId::from_bytes(&status.peer)
+                .map_or_else(|_| hex::encode(&status.peer), |peer| peer.to_string()),
+            shares: status.shares,
+            db_bytes: status.db_bytes,
+            uptime_secs: status.uptime_secs,
+            version: status.version.clone(),
+            reachable: status.reachable,
+            metrics: status.metrics.clone(),
+        }
+    }
+}
+
+/// What `info` prints with `--json`.
+///
+/// # Fields
+///
+/// * `peer_id` - The local peer id.
+/// * `version` - The version of the binary.
+/// * `agent_version` - The agent version the node identifies itself with.
+/// * `listen_addrs` - The addresses the node is listening on, with their bound ports.
+/// * `external_addrs` - The addresses the node is known to be reachable at from outside.
+/// * `connected_peers` - The number of peers the node is connected to.
+/// * `routing_table_size` - The number of peers in the node's Kademlia routing table.
+/// * `reachable` - Whether the node believes it is publicly reachable.
+/// * `providers` - The status of every provider heard from, with `--network`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InfoOutput {
+    pub peer_id: String,
+    pub version: String,
+    pub agent_version: String,
+    pub listen_addrs: Vec<String>,
+    pub external_addrs: Vec<String>,
+    pub connected_peers: usize,
+    pub routing_table_size: usize,
+    pub reachable: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub providers: Option<Vec<ProviderInfo>>,
+}
+
+impl InfoOutput {
+    /// Describes the local node from its view of the network.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The node's view of the network.
+    /// * `providers` - The status of every provider heard from, if they were asked for.
+    pub fn new(info: &NetworkInfo, providers: Option<Vec<ProviderInfo>>) -> Self {
+        InfoOutput {
+            peer_id: info.peer_id.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            agent_version: info.agent_version.clone(),
+            listen_addrs: info.listen_addrs.iter().map(ToString::to_string).collect(),
+            external_addrs: info
+                .external_addrs
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            connected_peers: info.connected_peers,
+            routing_table_size: info.routing_table_size,
+            reachable: info.reachable,
+            providers,
+        }
+    }
+}
+
+/// What `provide` prints with `--json` once it is listening.
+///
+/// # Fields
+///
+/// * `peer_id` - The provider's peer id.
+/// * `listen_addrs` - The addresses the provider is listening on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvideOutput {
+    pub peer_id: String,
+    pub listen_addrs: Vec<String>,
+}
+
+/// What `provide --print-events` prints with `--json` for each event of the provider, tagged
+/// with its `event`.
+///
+/// # Variants
+///
+/// * `Registered` - A share was registered under `key` by its `owner`.
+/// * `Served` - The share under `key` was sent to its owner or a reader.
+/// * `Refreshed` - The share under `key` was refreshed to `epoch`.
+/// * `Rejected` - A request for `key` was not served, for `reason`.
+/// * `RefreshInitiated` - The provider coordinated the refresh of the share under `key` with the
+///   other providers `peers`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProviderEventOutput {
+    Registered { key: String, owner: String },
+    Served { key: String },
+    Refreshed { key: String, epoch: u64 },
+    Rejected { key: String, reason: String },
+    RefreshInitiated { key: String, peers: Vec<String> },
+}
+
+impl From<&ProviderEvent> for ProviderEventOutput {
+    fn from(event: &ProviderEvent) -> Self {
+        match event.clone() {
+            ProviderEvent::Registered { key, owner } => ProviderEventOutput::Registered {
+                key,
+                owner: owner.to_string(),
+            },
+            ProviderEvent::Served { key } => ProviderEventOutput::Served { key },
+            ProviderEvent::Refreshed { key, epoch } => {
+                ProviderEventOutput::Refreshed { key, epoch }
+            }
+            ProviderEvent::Rejected { key, reason } => {
+                ProviderEventOutput::Rejected { key, reason }
+            }
+            ProviderEvent::RefreshInitiated { key, peers } => {
+                ProviderEventOutput::RefreshInitiated {
+                    key,
+                    peers: peer_ids(peers),
+                }
+            }
+        }
+    }
+}
+
+/// What `keygen` prints with `--json`.
+///
+/// # Fields
+///
+/// * `peer_id` - The peer id of the identity key.
+/// * `key_file` - The file the key is persisted in.
+/// * `generated` - Whether the key was generated by this run, rather than shown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeygenOutput {
+    pub peer_id: String,
+    pub key_file: String,
+    pub generated: bool,
+}
+
+/// What `profile list` prints with `--json`.
+///
+/// # Fields
+///
+/// * `profiles` - Every profile of the configuration directory, the default one first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfilesOutput {
+    pub profiles: Vec<ProfileListing>,
+}
+
+/// A profile, as listed by `profile list`.
+///
+/// # Fields
+///
+/// * `name` - The name of the profile, given with `--profile`.
+/// * `dir` - The directory holding its configuration and identity key.
+/// * `peer_id` - The peer id of its identity key, or `None` if it has none yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileListing {
+    pub name: String,
+    pub dir: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_id: Option<String>,
+}
+
+/// What a command prints on stderr with `--json` when it fails.
+///
+/// # Fields
+///
+/// * `error` - The failure.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorOutput {
+    pub error: ErrorDetail,
+}
+
+/// A failed command, as printed in an `ErrorOutput`.
+///
+/// # Fields
+///
+/// * `code` - The code the process exits with.
+/// * `kind` - The class of the failure, such as `no_providers` or `denied`.
+/// * `message` - What went wrong.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorDetail {
+    pub code: u8,
+    pub kind: String,
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation::{PendingTransfer, TransferredShare};
+
+    const PEER: &str = "12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN";
+
+    fn assert_snapshot<T>(output: &T, expected: &str)
+    where
+        T: Serialize + for<'de> Deserialize<'de> + PartialEq + std::fmt::Debug,
+    {
+        let json = to_json(output).unwrap();
+        assert_eq!(json, expected);
+        assert_eq!(&serde_json::from_str::<T>(&json).unwrap(), output);
+    }
+
+    #[test]
+    fn test_split_output() {
+        assert_snapshot(
+            &SplitOutput {
+                key: "key".to_string(),
+                threshold: 2,
+                shares: 3,
+                providers: vec![
+                    ProviderOutcome {
+                        peer: PEER.to_string(),
+                        status: RegistrationOutcome::Registered,
+                        reason: None,
+                    },
+                    ProviderOutcome {
+                        peer: PEER.to_string(),
+                        status: RegistrationOutcome::QuotaExceeded,
+                        reason: Some("max_total_bytes is 10, 10 used".to_string()),
+                    },
+                ],
+                chunks: None,
+            },
+            &format!(
+                concat!(
+                    r#"{{"key":"key","threshold":2,"shares":3,"providers":["#,
+                    r#"{{"peer":"{0}","status":"registered"}},"#,
+                    r#"{{"peer":"{0}","status":"quota_exceeded","#,
+                    r#""reason":"max_total_bytes is 10, 10 used"}}]}}"#
+                ),
+                PEER
+            ),
+        );
+    }
+
+    #[test]
+    fn test_split_plan_output() {
+        assert_snapshot(
+            &SplitPlanOutput {
+                key: "key".to_string(),
+                threshold: 2,
+                shares: 3,
+                share_bytes: 7,
+                chunks: None,
+                discovered: 2,
+                pinned: false,
+                providers: vec![],
+                spares: vec![],
+                feasible: false,
+                reason: Some("Not enough providers".to_string()),
+            },
+            concat!(
+                r#"{"key":"key","threshold":2,"shares":3,"share_bytes":7,"discovered":2,"#,
+                r#""pinned":false,"providers":[],"spares":[],"feasible":false,"#,
+                r#""reason":"Not enough providers"}"#
+            ),
+        );
+    }
+
+    #[test]
+    fn test_providers_change_output() {
+        assert_snapshot(
+            &ProvidersChangeOutput {
+                time: 1700000000,
+                key: "key".to_string(),
+                providers: vec!["a".to_string(), "b".to_string()],
+                added: vec!["b".to_string()],
+                removed: vec![],
+            },
+            r#"{"time":1700000000,"key":"key","providers":["a","b"],"added":["b"],"removed":[]}"#,
+        );
+    }
+
+    #[test]
+    fn test_share_files_output() {
+        assert_snapshot(
+            &ShareFilesOutput {
+                key: "key".to_string(),
+                threshold: 2,
+                epoch: 1,
+                files: vec!["shares/share-1.json".to_string()],
+            },
+            r#"{"key":"key","threshold":2,"epoch":1,"files":["shares/share-1.json"]}"#,
+        );
+    }
+
+    #[test]
+    fn test_combine_output() {
+        assert_snapshot(
+            &CombineOutput {
+                key: "key".to_string(),
+                secret_b64: Some("AP8Q".to_string()),
+                out: None,
+                shares_used: 2,
+                chunks: None,
+            },
+            r#"{"key":"key","secret_b64":"AP8Q","shares_used":2}"#,
+        );
+        assert_snapshot(
+            &CombineOutput {
+                key: "key".to_string(),
+                secret_b64: None,
+                out: Some("secret.bin".to_string()),
+                shares_used: 2,
+                chunks: None,
+            },
+            r#"{"key":"key","out":"secret.bin","shares_used":2}"#,
+        );
+        assert_snapshot(
+            &CombineOutput {
+                key: "key".to_string(),
+                secret_b64: None,
+                out: Some("file.bin".to_string()),
+                shares_used: 2,
+                chunks: Some(3),
+            },
+            r#"{"key":"key","out":"file.bin","shares_used":2,"chunks":3}"#,
+        );
+    }
+
+    #[test]
+    fn test_ls_and_refresh_output() {
+        assert_snapshot(
+            &LsOutput {
+                key: "key".to_string(),
+                providers: vec![PEER.to_string()],
+            },
+            &format!(r#"{{"key":"key","providers":["{}"]}}"#, PEER),
+        );
+        assert_snapshot(
+            &RefreshOutput {
+                key: "key".to_string(),
+                refreshed: vec![PEER.to_string()],
+                failed: vec![],
+            },
+            &format!(r#"{{"key":"key","refreshed":["{}"],"failed":[]}}"#, PEER),
+        );
+    }
+
+    #[test]
+    fn test_access_info_and_provide_output() {
+        assert_snapshot(
+            &AccessOutput {
+                key: "key".to_string(),
+                reader: PEER.to_string(),
+                granted: true,
+                changed: 3,
+            },
+            &format!(
+                r#"{{"key":"key","reader":"{}","granted":true,"changed":3}}"#,
+                PEER
+            ),
+        );
+        assert_snapshot(
+            &InfoOutput {
+                peer_id: PEER.to_string(),
+                version: "0.1.0".to_string(),
+                agent_version: "shard/0.1.0".to_string(),
+                listen_addrs: vec!["/ip4/127.0.0.1/tcp/4001".to_string()],
+                external_addrs: vec![],
+                connected_peers: 2,
+                routing_table_size: 5,
+                reachable: false,
+                providers: None,
+            },
+            &format!(
+                concat!(
+                    r#"{{"peer_id":"{}","version":"0.1.0","agent_version":"shard/0.1.0","#,
+                    r#""listen_addrs":["/ip4/127.0.0.1/tcp/4001"],"external_addrs":[],"#,
+                    r#""connected_peers":2,"routing_table_size":5,"reachable":false}}"#
+                ),
+                PEER
+            ),
+        );
+        assert_snapshot(
+            &KeygenOutput {
+                peer_id: PEER.to_string(),
+                key_file: "/home/me/.shard/identity.key".to_string(),
+                generated: true,
+            },
+            &format!(
+                r#"{{"peer_id":"{}","key_file":"/home/me/.shard/identity.key","generated":true}}"#,
+                PEER
+            ),
+        );
+        assert_snapshot(
+            &ProfilesOutput {
+                profiles: vec![ProfileListing {
+                    name: "owner".to_string(),
+                    dir: "/home/me/.shard/profiles/owner".to_string(),
+                    peer_id: None,
+                }],
+            },
+            r#"{"profiles":[{"name":"owner","dir":"/home/me/.shard/profiles/owner"}]}"#,
+        );
+        assert_snapshot(
+            &ErrorOutput {
+                error: ErrorDetail {
+                    code: 5,
+                    kind: "denied".to_string(),
+                    message: "share not readable by sender".to_string(),
+                },
+            },
+            r#"{"error":{"code":5,"kind":"denied","message":"share not readable by sender"}}"#,
+        );
+        assert_snapshot(
+            &ProvideOutput {
+                peer_id: PEER.to_string(),
+                listen_addrs: vec!["/ip4/127.0.0.1/tcp/4001".to_string()],
+            },
+            &format!(
+                r#"{{"peer_id":"{}","listen_addrs":["/ip4/127.0.0.1/tcp/4001"]}}"#,
+                PEER
+            ),
+        );
+    }
+
+    #[test]
+    fn test_keys_output() {
+        assert_snapshot(
+            &KeysOutput {
+                keys: vec![
+                    KeyListing {
+                        key: "a".to_string(),
+                        providers: vec![PEER.to_string()],
+                        threshold: Some(2),
+                        last_refreshed_unix: Some(1700000000),
+                    },
+                    KeyListing {
+                        key: "b".to_string(),
+                        providers: vec![PEER.to_string()],
+                        threshold: None,
+                        last_refreshed_unix: None,
+                    },
+                ],
+                unanswered: vec![UnansweredProvider {
+                    peer: PEER.to_string(),
+                    reason: "timeout".to_string(),
+                }],
+            },
+            &format!(
+                concat!(
+                    r#"{{"keys":[{{"key":"a","providers":["{0}"],"threshold":2,"#,
+                    r#""last_refreshed_unix":1700000000}},{{"key":"b","providers":["{0}"]}}],"#,
+                    r#""unanswered":[{{"peer":"{0}","reason":"timeout"}}]}}"#
+                ),
+                PEER
+            ),
+        );
+    }
+
+    #[test]
+    fn test_rotate_owner_output() {
+        let report = RotationReport {
+            old_owner: PEER.to_string(),
+            new_owner: PEER.to_string(),
+            transferred: vec![TransferredShare {
+                provider: PEER.to_string(),
+                key: "a".to_string(),
+            }],
+            pending: vec![PendingTransfer {
+                provider: PEER.to_string(),
+                key: None,
+                reason: "request failed: timeout".to_string(),
+            }],
+        };
+        assert_snapshot(
+            &RotateOwnerOutput {
+                report,
+                resumed: true,
+                identity_replaced: false,
+            },
+            &format!(
+                concat!(
+                    r#"{{"old_owner":"{0}","new_owner":"{0}","#,
+                    r#""transferred":[{{"provider":"{0}","key":"a"}}],"#,
+                    r#""pending":[{{"provider":"{0}","key":null,"#,
+                    r#""reason":"request failed: timeout"}}],"#,
+                    r#""resumed":true,"identity_replaced":false}}"#
+                ),
+                PEER
+            ),
+        );
+    }
+
+    #[test]
+    fn test_provider_info_from_status() {
+        let peer = PeerId::random();
+        let status = ProviderStatus {
+            peer: peer.to_bytes(),
+            shares: 4,
+            db_bytes: 512,
+            uptime_secs: 60,
+            version: "0.1.0".to_string(),
+            reachable: true,
+            interval_secs: 10,
+            metrics: MetricsSnapshot::default(),
+        };
+        let info = ProviderInfo::from(&status);
+        assert_eq!(info.peer, peer.to_string());
+        assert_eq!(
+            to_json(&info).unwrap(),
+            format!(
+                concat!(
+                    r#"{{"peer":"{}","shares":4,"db_bytes":512,"uptime_secs":60,"#,
+                    r#""version":"0.1.0","reachable":true,"metrics":{{"registrations":0,"#,
+                    r#""gets_served":0,"refreshes_applied":0,"refreshes_initiated":0,"#,
+                    r#""invalid_requests":0,"rate_limited_requests":0,"failures":{{}},"#,
+                    r#""oldest_refresh_age_secs":null}}}}"#
+                ),
+                peer
+            )
+        );
+
+        let garbled = ProviderStatus {
+            peer: vec![0xff, 0x00],
+            ..status
+        };
+        assert_eq!(ProviderInfo::from(&garbled).peer, "ff00");
+    }
+
+    #[test]
+    fn test_info_output_from_network_info() {
+        let peer = PeerId::random();
+        let info = NetworkInfo {
+            peer_id: peer,
+            agent_version: crate::constants::AGENT_VERSION.to_string(),
+            listen_addrs: vec!["/ip4/127.0.0.1/tcp/4001".parse().unwrap()],
+            external_addrs: vec!["/ip4/1.2.3.4/tcp/4001".parse().unwrap()],
+            connected_peers: 1,
+            routing_table_size: 3,
+            reachable: true,
+        };
+        let output = InfoOutput::new(&info, Some(vec![]));
+        assert_eq!(output.peer_id, peer.to_string());
+        assert_eq!(output.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            output.agent_version,
+            format!("shard/{}", env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(output.listen_addrs, vec!["/ip4/127.0.0.1/tcp/4001"]);
+        assert_eq!(output.external_addrs, vec!["/ip4/1.2.3.4/tcp/4001"]);
+        assert_eq!((output.connected_peers, output.routing_table_size), (1, 3));
+        assert!(output.reachable);
+        assert_eq!(output.providers, Some(vec![]));
+    }
+
+    #[test]
+    fn test_peer_ids_are_sorted() {
+        let peers = [PeerId::random(), PeerId::random(), PeerId::random()];
+        let ids = peer_ids(peers);
+        assert!(ids.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(ids.len(), 3);
+    }
+}
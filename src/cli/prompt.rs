@@ -0,0 +1,217 @@
+use std::error::Error;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use crate::cli::error::{CliError, ErrorKind};
+
+/// The environment variable a passphrase is read from instead of prompting for it, for scripts
+/// and services that run without a terminal.
+pub const PASSPHRASE_ENV: &str = "SHARD_PASSPHRASE";
+
+/// Prints `prompt` on stderr and reads a line from the terminal on stdin without echoing it.
+///
+/// # Arguments
+///
+/// * `prompt` - What is asked for.
+///
+/// # Returns
+///
+/// The line read, without its line ending, or an error if stdin is not a terminal.
+#[cfg(unix)]
+pub fn read_hidden(prompt: &str) -> io::Result<String> {
+    use std::os::unix::io::AsRawFd;
+
+    let stdin = io::stdin();
+    let fd = stdin.as_raw_fd();
+    let mut term = std::mem::MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: tcgetattr fills the termios it is given when it succeeds
+    if unsafe { libc::tcgetattr(fd, term.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let original = unsafe { term.assume_init() };
+    let mut hidden = original;
+    hidden.c_lflag &= !libc::ECHO;
+    hidden.c_lflag |= libc::ECHONL;
+
+    eprint!("{}: ", prompt);
+    io::stderr().flush()?;
+    // SAFETY: both termios are valid, and the original is restored whatever the read returns
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &hidden) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut line = String::new();
+    let read = stdin.lock().read_line(&mut line);
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    read?;
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(line)
+}
+
+/// Reading without echo needs the terminal attributes of unix.
+#[cfg(not(unix))]
+pub fn read_hidden(_prompt: &str) -> io::Result<String> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "hidden prompts are only supported on unix",
+    ))
+}
+
+/// Gets a passphrase from `env` or, without one, by prompting for it with `read`.
+///
+/// # Arguments
+///
+/// * `prompt` - What the passphrase is for.
+/// * `env` - The value of `PASSPHRASE_ENV`, which is used as is when set and not empty.
+/// * `is_terminal` - Whether stdin is a terminal that can be prompted.
+/// * `read` - Prompts for a line without echoing it.
+///
+/// # Returns
+///
+/// The passphrase, or a usage error if there is neither a value in `env` nor a terminal.
+pub fn passphrase_from(
+    prompt: &str,
+    env: Option<String>,
+    is_terminal: bool,
+    read: &mut dyn FnMut(&str) -> io::Result<String>,
+) -> Result<String, Box<dyn Error>> {
+    if let Some(passphrase) = env.filter(|passphrase| !passphrase.is_empty()) {
+        return Ok(passphrase);
+    }
+    if !is_terminal {
+        let message = format!(
+            "{} cannot be prompted for without a terminal, set {}",
+            prompt, PASSPHRASE_ENV
+        );
+        return Err(CliError::new(ErrorKind::Usage, message).into());
+    }
+    Ok(read(prompt)?)
+}
+
+/// Gets a passphrase from `PASSPHRASE_ENV`, or by prompting for it on the terminal.
+///
+/// # Arguments
+///
+/// * `prompt` - What the passphrase is for.
+///
+/// # Returns
+///
+/// The passphrase, or an error if there is neither the variable nor a terminal.
+pub fn passphrase(prompt: &str) -> Result<String, Box<dyn Error>> {
+    let env = std::env::var(PASSPHRASE_ENV).ok();
+    passphrase_from(prompt, env, io::stdin().is_terminal(), &mut read_hidden)
+}
+
+/// Prompts twice for a secret with `read`, and checks that both entries match.
+///
+/// # Arguments
+///
+/// * `prompt` - What the secret is.
+/// * `is_terminal` - Whether stdin is a terminal that can be prompted.
+/// * `read` - Prompts for a line without echoing it.
+///
+/// # Returns
+///
+/// The secret, or an error if there is no terminal, the entries differ or the secret is empty.
+pub fn confirmed_from(
+    prompt: &str,
+    is_terminal: bool,
+    read: &mut dyn FnMut(&str) -> io::Result<String>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if !is_terminal {
+        let message = format!(
+            "{} cannot be prompted for without a terminal, pass --secret - or --secret-file",
+            prompt
+        );
+        return Err(CliError::new(ErrorKind::Usage, message).into());
+    }
+    let secret = read(prompt)?;
+    if secret.is_empty() {
+        return Err("the secret is empty".into());
+    }
+    if read(&format!("{} again", prompt))? != secret {
+        return Err(CliError::new(ErrorKind::Usage, "the entries do not match").into());
+    }
+    Ok(secret.into_bytes())
+}
+
+/// Prompts twice on the terminal for a secret, without echoing it.
+///
+/// # Arguments
+///
+/// * `prompt` - What the secret is.
+///
+/// # Returns
+///
+/// The secret, or an error if stdin is not a terminal or the entries differ.
+pub fn confirmed_secret(prompt: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    confirmed_from(prompt, io::stdin().is_terminal(), &mut read_hidden)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::error::classify;
+
+    /// Answers prompts with `answers` in turn, recording what was asked.
+    fn answering<'a>(
+        answers: &'a [&str],
+        asked: &'a mut Vec<String>,
+    ) -> impl FnMut(&str) -> io::Result<String> + 'a {
+        let mut answers = answers.iter();
+        move |prompt| {
+            asked.push(prompt.to_string());
+            Ok(answers.next().expect("no more answers").to_string())
+        }
+    }
+
+    #[test]
+    fn test_passphrase_falls_back_to_the_environment() {
+        let mut asked = Vec::new();
+        let mut read = answering(&["typed"], &mut asked);
+        let env = Some("from env".to_string());
+        assert_eq!(
+            passphrase_from("DB passphrase", env.clone(), false, &mut read).unwrap(),
+            "from env"
+        );
+        assert_eq!(
+            passphrase_from("DB passphrase", env, true, &mut read).unwrap(),
+            "from env"
+        );
+        assert_eq!(
+            passphrase_from("DB passphrase", Some(String::new()), true, &mut read).unwrap(),
+            "typed"
+        );
+        drop(read);
+        assert_eq!(asked, ["DB passphrase"]);
+    }
+
+    #[test]
+    fn test_no_terminal_and_no_fallback_is_an_error() {
+        let mut asked = Vec::new();
+        let mut read = answering(&[], &mut asked);
+        let err = passphrase_from("DB passphrase", None, false, &mut read).unwrap_err();
+        assert_eq!(classify(&*err), ErrorKind::Usage);
+        assert!(err.to_string().contains(PASSPHRASE_ENV));
+        let err = confirmed_from("Secret", false, &mut read).unwrap_err();
+        assert_eq!(classify(&*err), ErrorKind::Usage);
+        drop(read);
+        assert!(asked.is_empty());
+    }
+
+    #[test]
+    fn test_secret_is_confirmed_by_entering_it_again() {
+        let mut asked = Vec::new();
+        let mut read = answering(&["hunter2", "hunter2"], &mut asked);
+        assert_eq!(
+            confirmed_from("Secret", true, &mut read).unwrap(),
+            b"hunter2"
+        );
+        drop(read);
+        assert_eq!(asked, ["Secret", "Secret again"]);
+
+        let mut asked = Vec::new();
+        let mut read = answering(&["hunter2", "hunter3"], &mut asked);
+        let err = confirmed_from("Secret", true, &mut read).unwrap_err();
+        assert_eq!(err.to_string(), "the entries do not match");
+    }
+}
@@ -1,13 +1,28 @@
 use futures::channel::{mpsc, oneshot};
 use futures::prelude::*;
-use libp2p::{core::Multiaddr, request_response::ResponseChannel, PeerId};
+use libp2p::core::transport::TransportError;
+use libp2p::identity::{Keypair, SigningError};
+use libp2p::swarm::DialError;
+use libp2p::{
+    core::Multiaddr, gossipsub, kad, multiaddr::Protocol, request_response::ResponseChannel, PeerId,
+};
+use sha2::{Digest, Sha256};
+use tracing::{debug, instrument, warn};
 
 use std::collections::HashSet;
 use std::error::Error;
+use std::fmt;
+use std::time::Duration;
 
 use crate::command::Command;
-use crate::protocol::Response;
-use crate::sss::Polynomial;
+use crate::constants::{MAX_LIST_KEYS_LIMIT, READY_BOOTSTRAP_ATTEMPT_MILLIS, READY_POLL_MILLIS};
+use crate::network::NetworkInfo;
+use crate::protocol::{
+    DeleteShareStatus, Failure, FieldShare, GossipMessage, ListKeysResponse, ProviderStatus,
+    RefreshKey, RegisterShareStatus, RelayGrant, Response, ShareIndex, StatShareStatus, TraceId,
+    TransferOwnershipRequest,
+};
+use crate::runtime::{self, Instant};
 
 /// Represents a client in the network capable of issuing commands.
 ///
@@ -17,6 +32,7 @@ use crate::sss::Polynomial;
 /// # Fields
 ///
 /// * `sender` - A channel sender used to send commands to the network event loop.
+/// * `trace_id` - The trace id the requests of the client are sent with, set by `with_trace`.
 ///
 /// # Examples
 ///
@@ -28,14 +44,223 @@ use crate::sss::Polynomial;
 /// use shard::command::Command;
 ///
 /// let (sender, receiver) = mpsc::channel::<Command>(10);
-/// let client = Client { sender };
+/// let client = Client { sender, trace_id: None };
 /// ```
 #[derive(Clone)]
 pub struct Client {
     pub sender: mpsc::Sender<Command>,
+    pub trace_id: Option<TraceId>,
+}
+
+/// What `Client::await_ready` waits for before the network is ready for a command.
+///
+/// # Fields
+///
+/// * `min_connections` - The number of peers the node must be connected to.
+/// * `bootstrap` - Whether a Kademlia bootstrap of the routing table must have completed.
+/// * `min_providers` - The number of providers that must be found.
+/// * `provider_key` - The DHT key the providers are looked up under, or `None` to count every
+///   provider the node knows of.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::client::ReadyCriteria;
+///
+/// // connected and bootstrapped, without looking for providers
+/// let criteria = ReadyCriteria {
+///     min_connections: 1,
+///     bootstrap: true,
+///     ..Default::default()
+/// };
+/// assert_eq!(criteria.min_providers, 0);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadyCriteria {
+    pub min_connections: usize,
+    pub bootstrap: bool,
+    pub min_providers: usize,
+    pub provider_key: Option<String>,
+}
+
+/// What the network had not reached when `Client::await_ready` gave up on it.
+///
+/// # Variants
+///
+/// * `Connections { connected, wanted }` - Too few peers were connected.
+/// * `Bootstrap(Option<String>)` - The Kademlia bootstrap did not complete; carries why its last
+///   attempt failed, if it did rather than running out of time.
+/// * `Providers { found, wanted }` - Too few providers were found.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::client::NotReady;
+///
+/// let error = NotReady::Providers { found: 1, wanted: 3 };
+/// assert_eq!(error.to_string(), "timed out waiting for providers (found 1 of 3)");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotReady {
+    Connections { connected: usize, wanted: usize },
+    Bootstrap(Option<String>),
+    Providers { found: usize, wanted: usize },
+}
+
+impl fmt::Display for NotReady {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotReady::Connections { connected, wanted } => write!(
+                f,
+                "timed out waiting for connections (connected to {} of {})",
+                connected, wanted
+            ),
+            NotReady::Bootstrap(None) => write!(f, "timed out waiting for the Kademlia bootstrap"),
+            NotReady::Bootstrap(Some(reason)) => write!(
+                f,
+                "timed out waiting for the Kademlia bootstrap: {}",
+                reason
+            ),
+            NotReady::Providers { found, wanted } => write!(
+                f,
+                "timed out waiting for providers (found {} of {})",
+                found, wanted
+            ),
+        }
+    }
+}
+
+impl Error for NotReady {}
+
+/// Why a command sent to the network through a `Client` failed.
+///
+/// # Variants
+///
+/// * `Failure(Failure)` - The provider refused the request and said why.
+/// * `Refused(String)` - The provider refused the request, or turned it down as invalid, rate
+///   limited or busy, without a `Failure`; carries its reason.
+/// * `RequestFailed(String)` - The request got no response at all; carries why.
+/// * `NotReady(NotReady)` - The network did not get ready in time.
+/// * `Listen(TransportError)` - The swarm could not listen on the address.
+/// * `Dial(DialError)` - The peer could not be dialed.
+/// * `NoKnownPeers` - A bootstrap was started with an empty routing table.
+/// * `Bootstrap(BootstrapError)` - The Kademlia bootstrap failed.
+/// * `Publish(PublishError)` - A gossip message could not be published.
+/// * `Encode(serde_cbor::Error)` - A gossip message could not be encoded.
+/// * `Sign(SigningError)` - A request could not be signed with the identity key.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::client::ClientError;
+/// use shard::protocol::Failure;
+///
+/// let error = ClientError::from(Failure::NotReader);
+/// assert_eq!(error.to_string(), Failure::NotReader.to_string());
+/// ```
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error(transparent)]
+    Failure(#[from] Failure),
+    #[error("{0}")]
+    Refused(String),
+    #[error("request failed: {0}")]
+    RequestFailed(String),
+    #[error(transparent)]
+    NotReady(#[from] NotReady),
+    #[error(transparent)]
+    Listen(#[from] TransportError<std::io::Error>),
+    #[error(transparent)]
+    Dial(#[from] DialError),
+    #[error("no known peers to bootstrap from")]
+    NoKnownPeers,
+    #[error(transparent)]
+    Bootstrap(#[from] kad::BootstrapError),
+    #[error(transparent)]
+    Publish(#[from] gossipsub::PublishError),
+    #[error(transparent)]
+    Encode(#[from] serde_cbor::Error),
+    #[error(transparent)]
+    Sign(#[from] SigningError),
+}
+
+/// A change of the providers of a key, as reported by `Client::watch_providers`.
+///
+/// # Fields
+///
+/// * `providers` - Every provider of the key after the change.
+/// * `added` - The providers found since the last change, or all of them in the first report.
+/// * `removed` - The providers no longer found since the last change.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProvidersChange {
+    pub providers: HashSet<PeerId>,
+    pub added: HashSet<PeerId>,
+    pub removed: HashSet<PeerId>,
 }
 
 impl Client {
+    /// Computes the DHT record a share is provided under.
+    ///
+    /// Providers store shares in the namespace of their owner, so the record is a hash of the
+    /// owner and the key rather than the key alone. Clients and providers must both use it.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The `PeerId` of the share owner.
+    /// * `key` - The key chosen by the owner.
+    ///
+    /// # Returns
+    ///
+    /// The hex-encoded SHA-256 of the owner's peer id bytes followed by the key.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use libp2p::PeerId;
+    /// use shard::client::Client;
+    ///
+    /// let owner = PeerId::random();
+    /// assert_ne!(
+    ///     Client::provider_key(&owner, "my_key"),
+    ///     Client::provider_key(&PeerId::random(), "my_key")
+    /// );
+    /// ```
+    pub fn provider_key(owner: &PeerId, key: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(owner.to_bytes());
+        hasher.update(key.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    /// Returns a client sending its requests as part of the operation `trace_id`, so that the
+    /// providers asked log them with it.
+    ///
+    /// # Arguments
+    ///
+    /// * `trace_id` - The trace id of the operation.
+    ///
+    /// # Returns
+    ///
+    /// A client sending its commands to the same event loop.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use futures::channel::mpsc;
+    /// use shard::client::Client;
+    /// use shard::protocol::TraceId;
+    ///
+    /// let (sender, _receiver) = mpsc::channel(10);
+    /// let client = Client { sender, trace_id: None };
+    /// assert_eq!(client.with_trace(TraceId(7)).trace_id, Some(TraceId(7)));
+    /// ```
+    pub fn with_trace(&self, trace_id: TraceId) -> Client {
+        Client {
+            sender: self.sender.clone(),
+            trace_id: Some(trace_id),
+        }
+    }
+
     /// Listen for incoming connections on the given address.
     ///
     /// # Arguments
@@ -51,7 +276,7 @@ impl Client {
     /// ```ignore
     /// client.start_listening("/ip4/0.0.0.0/tcp/0".parse()?).await?;
     /// ```
-    pub async fn start_listening(&mut self, addr: Multiaddr) -> Result<(), Box<dyn Error + Send>> {
+    pub async fn start_listening(&mut self, addr: Multiaddr) -> Result<(), ClientError> {
         let (sender, receiver) = oneshot::channel();
         self.sender
             .send(Command::StartListening { addr, sender })
@@ -76,11 +301,7 @@ impl Client {
     /// ```ignore
     /// client.dial(peer_id, peer_addr).await?;
     /// ```
-    pub async fn dial(
-        &mut self,
-        peer_id: PeerId,
-        peer_addr: Multiaddr,
-    ) -> Result<(), Box<dyn Error + Send>> {
+    pub async fn dial(&mut self, peer_id: PeerId, peer_addr: Multiaddr) -> Result<(), ClientError> {
         let (sender, receiver) = oneshot::channel();
         self.sender
             .send(Command::Dial {
@@ -93,6 +314,49 @@ impl Client {
         receiver.await.expect("Sender not to be dropped.")
     }
 
+    /// Dial each of the given bootstrappers in turn, skipping the local node. A bootstrapper that
+    /// cannot be reached is logged and skipped, so that one of several being down does not keep
+    /// the node off the network.
+    ///
+    /// # Arguments
+    ///
+    /// * `bootstrappers` - The multiaddresses of the bootstrappers, each ending with its peer ID.
+    /// * `local_peer_id` - The `PeerId` of the local node.
+    ///
+    /// # Returns
+    ///
+    /// The number of bootstrappers connected to.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// if client.bootstrap(&config.bootstrap_addrs(), local_peer_id).await == 0 {
+    ///     warn!("No bootstrapper reached.");
+    /// }
+    /// ```
+    pub async fn bootstrap(&mut self, bootstrappers: &[Multiaddr], local_peer_id: PeerId) -> usize {
+        let mut connected = 0;
+        for addr in bootstrappers {
+            let Some(Protocol::P2p(peer_id)) = addr.iter().last() else {
+                warn!(
+                    "Bootstrapper {} does not end with a peer ID, skipping it.",
+                    addr
+                );
+                continue;
+            };
+            // if the peer is the same as the local peer, don't dial
+            if peer_id == local_peer_id {
+                continue;
+            }
+            debug!(%addr, "Bootstrapping to peer.");
+            match self.dial(peer_id, addr.clone()).await {
+                Ok(()) => connected += 1,
+                Err(e) => warn!("Failed to dial bootstrapper {}: {}", addr, e),
+            }
+        }
+        connected
+    }
+
     /// Advertise the local node as the provider of the given key on the DHT.
     ///
     /// # Arguments
@@ -113,6 +377,24 @@ impl Client {
         receiver.await.expect("Sender not to be dropped.");
     }
 
+    /// Stop advertising the local node as the provider of the given key on the DHT.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to stop providing on the DHT.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.stop_providing("my_key".to_string()).await;
+    /// ```
+    pub async fn stop_providing(&mut self, key: String) {
+        self.sender
+            .send(Command::StopProviding { key })
+            .await
+            .expect("Command receiver not to be dropped.");
+    }
+
     /// Find the providers for the given key on the DHT.
     ///
     /// # Arguments
@@ -137,6 +419,31 @@ impl Client {
         receiver.await.expect("Sender not to be dropped.")
     }
 
+    /// Find every provider of the given key on the DHT. Unlike `get_providers`, which answers
+    /// with the providers of the first result, the lookup runs to completion.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key for which to find providers.
+    ///
+    /// # Returns
+    ///
+    /// A set of `PeerId` representing every provider of the key found.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let providers = client.find_providers("my_key".to_string()).await;
+    /// ```
+    pub async fn find_providers(&mut self, key: String) -> HashSet<PeerId> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::FindProviders { key, sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
     /// Find all providers on the DHT.
     ///
     /// # Returns
@@ -157,6 +464,205 @@ impl Client {
         receiver.await.expect("Sender not to be dropped.")
     }
 
+    /// Watch the providers of the given key on the DHT, looking every one of them up every
+    /// `interval`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key for which to watch the providers.
+    /// * `interval` - The time between two lookups.
+    ///
+    /// # Returns
+    ///
+    /// A stream reporting the providers found by the first lookup, then every change a later
+    /// lookup finds. It never ends; drop it to stop watching.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let mut changes = Box::pin(client.watch_providers("my_key".to_string(), Duration::from_secs(5)));
+    /// while let Some(change) = changes.next().await {
+    ///     println!("{} providers, {} new", change.providers.len(), change.added.len());
+    /// }
+    /// ```
+    pub fn watch_providers(
+        &self,
+        key: String,
+        interval: Duration,
+    ) -> impl Stream<Item = ProvidersChange> + Send {
+        let state = (self.clone(), None::<HashSet<PeerId>>);
+        stream::unfold(state, move |(mut client, last)| {
+            let key = key.clone();
+            async move {
+                if last.is_some() {
+                    runtime::sleep(interval).await;
+                }
+                loop {
+                    let providers = client.find_providers(key.clone()).await;
+                    let previous = last.clone().unwrap_or_default();
+                    if last.as_ref() != Some(&providers) {
+                        let change = ProvidersChange {
+                            added: providers.difference(&previous).copied().collect(),
+                            removed: previous.difference(&providers).copied().collect(),
+                            providers: providers.clone(),
+                        };
+                        return Some((change, (client, Some(providers))));
+                    }
+                    runtime::sleep(interval).await;
+                }
+            }
+        })
+    }
+
+    /// Refresh the routing table with a Kademlia bootstrap, waiting for it to complete.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the bootstrap completed, or an error if the routing table has no peers to
+    /// bootstrap from or the bootstrap failed.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.dial(peer_id, peer_addr).await?;
+    /// client.bootstrap_routing().await?;
+    /// ```
+    pub async fn bootstrap_routing(&mut self) -> Result<(), ClientError> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::Bootstrap { sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Wait for the network to be ready for a command: connected to enough peers, with the
+    /// routing table bootstrapped, and with enough providers found, as `criteria` asks.
+    ///
+    /// # Arguments
+    ///
+    /// * `criteria` - What the network must reach to be ready.
+    /// * `timeout` - How long to wait for it before giving up.
+    ///
+    /// # Returns
+    ///
+    /// The providers found, none unless `criteria` asks for some, or an error saying what the
+    /// network had not reached once `timeout` passed.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let criteria = ReadyCriteria {
+    ///     min_connections: 1,
+    ///     bootstrap: true,
+    ///     min_providers: 3,
+    ///     provider_key: Some(Client::provider_key(&owner, "my_key")),
+    /// };
+    /// let providers = client.await_ready(&criteria, Duration::from_secs(30)).await?;
+    /// ```
+    pub async fn await_ready(
+        &mut self,
+        criteria: &ReadyCriteria,
+        timeout: Duration,
+    ) -> Result<HashSet<PeerId>, NotReady> {
+        self.await_ready_with_progress(criteria, timeout, |_, _| {})
+            .await
+    }
+
+    /// Like `await_ready`, calling `progress` with the number of providers found and wanted
+    /// each time more are found, so that a caller waiting on providers still joining the network
+    /// can report how far along it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `criteria` - What the network must reach to be ready.
+    /// * `timeout` - How long to wait for it before giving up.
+    /// * `progress` - Called with the providers found and wanted after each lookup finding more.
+    ///
+    /// # Returns
+    ///
+    /// The providers found, or an error saying what the network had not reached once `timeout`
+    /// passed.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let providers = client
+    ///     .await_ready_with_progress(&criteria, Duration::from_secs(60), |found, wanted| {
+    ///         eprintln!("found {}/{} providers…", found, wanted)
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn await_ready_with_progress(
+        &mut self,
+        criteria: &ReadyCriteria,
+        timeout: Duration,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<HashSet<PeerId>, NotReady> {
+        let deadline = Instant::now() + timeout;
+        let poll = Duration::from_millis(READY_POLL_MILLIS);
+
+        loop {
+            let connected = self.connected_peers().await.len();
+            if connected >= criteria.min_connections {
+                break;
+            }
+            if Instant::now() >= deadline {
+                return Err(NotReady::Connections {
+                    connected,
+                    wanted: criteria.min_connections,
+                });
+            }
+            runtime::sleep_until((Instant::now() + poll).min(deadline)).await;
+        }
+
+        if criteria.bootstrap {
+            let attempt = Duration::from_millis(READY_BOOTSTRAP_ATTEMPT_MILLIS);
+            loop {
+                let attempt_deadline = (Instant::now() + attempt).min(deadline);
+                match runtime::timeout_at(attempt_deadline, self.bootstrap_routing()).await {
+                    Ok(Ok(())) => break,
+                    Ok(Err(e)) if Instant::now() >= deadline => {
+                        return Err(NotReady::Bootstrap(Some(e.to_string())));
+                    }
+                    Err(_) if Instant::now() >= deadline => return Err(NotReady::Bootstrap(None)),
+                    // the routing table fills in as connected peers identify themselves
+                    Ok(Err(e)) => debug!("Kademlia bootstrap failed, retrying: {}", e),
+                    Err(_) => debug!("Kademlia bootstrap stalled, retrying"),
+                }
+                runtime::sleep_until((Instant::now() + poll).min(deadline)).await;
+            }
+        }
+
+        let mut found = HashSet::new();
+        while found.len() < criteria.min_providers {
+            let lookup = async {
+                match &criteria.provider_key {
+                    Some(key) => self.find_providers(key.clone()).await,
+                    None => self.get_all_providers().await,
+                }
+            };
+            if let Ok(providers) = runtime::timeout_at(deadline, lookup).await {
+                let before = found.len();
+                found.extend(providers);
+                if found.len() > before {
+                    progress(found.len(), criteria.min_providers);
+                }
+            }
+            if found.len() >= criteria.min_providers {
+                break;
+            }
+            if Instant::now() >= deadline {
+                return Err(NotReady::Providers {
+                    found: found.len(),
+                    wanted: criteria.min_providers,
+                });
+            }
+            runtime::sleep_until((Instant::now() + poll).min(deadline)).await;
+        }
+        Ok(found)
+    }
+
     /// Request the content of the given share from the given peer.
     ///
     /// # Arguments
@@ -164,6 +670,8 @@ impl Client {
     /// * `peer` - The `PeerId` of the peer from whom to request the share.
     /// * `key` - The key of the share to request.
     /// * `sender` - The `PeerId` of the sender making the request.
+    /// * `owner` - The `PeerId` of the share owner when it granted the sender access to its
+    ///   share, or `None` to request the sender's own share.
     ///
     /// # Returns
     ///
@@ -172,20 +680,60 @@ impl Client {
     /// # Examples
     ///
     /// ```ignore
-    /// let share_content = client.request_share(peer_id, "my_key".to_string(), sender_id).await?;
+    /// let share_content = client.request_share(peer_id, "my_key".to_string(), sender_id, None).await?;
     /// ```
     pub async fn request_share(
         &mut self,
         peer: PeerId,
         key: String,
         sender: PeerId,
-    ) -> Result<(u8, Vec<u8>), Box<dyn Error + Send>> {
+        owner: Option<PeerId>,
+    ) -> Result<(ShareIndex, Vec<u8>), ClientError> {
+        self.request_field_share(peer, key, sender, owner)
+            .await
+            .map(|share| share.share)
+    }
+
+    /// Request a share from a given peer along with the field its secret was split over, which
+    /// the share has to be combined in.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The `PeerId` of the peer from whom to request the share.
+    /// * `key` - The key of the share to request.
+    /// * `sender` - The `PeerId` of the sender making the request.
+    /// * `owner` - The `PeerId` of the share owner when it granted the sender access to its
+    ///   share, or `None` to request the sender's own share.
+    ///
+    /// # Returns
+    ///
+    /// The requested share upon success.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let share = client.request_field_share(peer_id, "my_key".to_string(), sender_id, None).await?;
+    /// ```
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(%peer, request = "get_share", trace_id = self.trace_id.map(display))
+    )]
+    pub async fn request_field_share(
+        &mut self,
+        peer: PeerId,
+        key: String,
+        sender: PeerId,
+        owner: Option<PeerId>,
+    ) -> Result<FieldShare, ClientError> {
         let (sender_chan, receiver) = oneshot::channel();
         self.sender
             .send(Command::RequestShare {
                 key,
                 peer,
                 sender,
+                owner,
+                trace_id: self.trace_id,
                 sender_chan,
             })
             .await
@@ -197,27 +745,21 @@ impl Client {
     ///
     /// # Arguments
     ///
-    /// * `share` - The share to respond with.
-    /// * `success` - Whether the response is successful.
+    /// * `result` - The share to respond with, or why no share is returned.
     /// * `channel` - The response channel to send the response.
     ///
     /// # Examples
     ///
     /// ```ignore
-    /// client.respond_share((1, vec![1, 2, 3]), true, response_channel).await;
+    /// client.respond_share(Ok(FieldShare::from((1, vec![1, 2, 3]))), response_channel).await;
     /// ```
     pub async fn respond_share(
         &mut self,
-        share: (u8, Vec<u8>),
-        success: bool,
+        result: Result<FieldShare, Failure>,
         channel: ResponseChannel<Response>,
     ) {
         self.sender
-            .send(Command::RespondShare {
-                share,
-                success,
-                channel,
-            })
+            .send(Command::RespondShare { result, channel })
             .await
             .expect("Command receiver not to be dropped.");
     }
@@ -226,36 +768,67 @@ impl Client {
     ///
     /// # Arguments
     ///
-    /// * `share` - The share to register.
+    /// * `share` - The share to register, split over GF(2^8) unless it is a `FieldShare` that
+    ///   names another field.
     /// * `key` - The key associated with the share.
+    /// * `threshold` - The threshold the secret was split with.
+    /// * `ttl_secs` - An optional lifetime in seconds after which the provider destroys the share.
+    /// * `recreate` - Register the share even if this sender deleted it recently.
+    /// * `refresh_interval_secs` - How often providers refresh the share, in seconds, or `None`
+    ///   for their default interval.
+    /// * `replace` - Replace a share this sender stored under the key with a different share
+    ///   index, length or threshold.
+    /// * `relay_grant` - The sender's grant for the peer to relay refreshes of the share, or
+    ///   `None` to leave the peer unable to coordinate one (see `RelayGrant::signed`).
     /// * `peer` - The `PeerId` of the peer to register the share with.
     /// * `sender` - The `PeerId` of the sender making the request.
     ///
     /// # Returns
     ///
-    /// `true` if the share was successfully registered.
+    /// Whether the provider registered the share, or why it refused it. A share refused with
+    /// `RegisterShareStatus::QuotaExceeded` can be registered with another provider instead, and
+    /// one refused with `RegisterShareStatus::Conflict` can be registered again with `replace`.
     ///
     /// # Examples
     ///
     /// ```ignore
-    /// let result = client.request_register_share((1, vec![1, 2, 3]), "my_key".to_string(), peer_id, sender_id).await?;
+    /// let grant = RelayGrant::signed("my_key", peer_id, &keypair)?;
+    /// let status = client.request_register_share((1, vec![1, 2, 3]), "my_key".to_string(), 2, None, false, None, false, Some(grant), peer_id, sender_id).await?;
     /// ```
+    // mirrors the fields of RegisterShareRequest one to one
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(%peer, request = "register_share", trace_id = self.trace_id.map(display))
+    )]
     pub async fn request_register_share(
         &mut self,
-        share: (u8, Vec<u8>),
+        share: impl Into<FieldShare>,
         key: String,
         threshold: u64,
+        ttl_secs: Option<u64>,
+        recreate: bool,
+        refresh_interval_secs: Option<u64>,
+        replace: bool,
+        relay_grant: Option<RelayGrant>,
         peer: PeerId,
         sender: PeerId,
-    ) -> Result<bool, Box<dyn Error + Send>> {
+    ) -> Result<RegisterShareStatus, ClientError> {
         let (sender_chan, receiver) = oneshot::channel();
         self.sender
             .send(Command::RequestRegisterShare {
-                share,
+                share: share.into(),
                 key,
                 peer,
                 threshold,
+                ttl_secs,
+                recreate,
+                refresh_interval_secs,
+                replace,
+                relay_grant,
                 sender,
+                trace_id: self.trace_id,
                 sender_chan,
             })
             .await
@@ -267,21 +840,21 @@ impl Client {
     ///
     /// # Arguments
     ///
-    /// * `success` - Whether the registration was successful.
+    /// * `result` - Whether the share was registered, or why it was refused.
     /// * `channel` - The response channel to send the response.
     ///
     /// # Examples
     ///
     /// ```ignore
-    /// client.respond_register_share(true, response_channel).await;
+    /// client.respond_register_share(Ok(()), response_channel).await;
     /// ```
     pub async fn respond_register_share(
         &mut self,
-        success: bool,
+        result: Result<(), Failure>,
         channel: ResponseChannel<Response>,
     ) {
         self.sender
-            .send(Command::RespondRegisterShare { success, channel })
+            .send(Command::RespondRegisterShare { result, channel })
             .await
             .expect("Command receiver not to be dropped.");
     }
@@ -291,9 +864,13 @@ impl Client {
     /// # Arguments
     ///
     /// * `key` - The key of the shares to refresh.
-    /// * `refresh_key` - A list of polynomials for the refreshing process.
+    /// * `refresh_key` - A list of polynomials for the refreshing process, over the field the
+    ///   secret of the shares was split over.
     /// * `peer` - The `PeerId` of the peer to refresh the shares with.
-    /// * `sender` - The `PeerId` of the sender making the request.
+    /// * `sender` - The `PeerId` of the share owner on whose behalf the refresh is made.
+    /// * `epoch` - The epoch the refresh advances the share to, or `None` to let the peer bump it.
+    /// * `relay_grant` - The owner's grant of the local node, when it relays the refresh on behalf
+    ///   of the owner, or `None` when the owner sends it itself.
     ///
     /// # Returns
     ///
@@ -302,22 +879,32 @@ impl Client {
     /// # Examples
     ///
     /// ```ignore
-    /// let result = client.request_refresh_shares("my_key".to_string(), vec![Polynomial::new(2, gf256::new(5))], peer_id, sender_id).await?;
+    /// let result = client.request_refresh_shares("my_key".to_string(), vec![Polynomial::new(2, gf256::new(5))], peer_id, sender_id, None, None).await?;
     /// ```
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(%peer, request = "refresh_share", trace_id = self.trace_id.map(display))
+    )]
     pub async fn request_refresh_shares(
         &mut self,
         key: String,
-        refresh_key: Vec<Polynomial>,
+        refresh_key: impl Into<RefreshKey>,
         peer: PeerId,
         sender: PeerId,
-    ) -> Result<bool, Box<dyn Error + Send>> {
+        epoch: Option<u64>,
+        relay_grant: Option<RelayGrant>,
+    ) -> Result<bool, ClientError> {
         let (sender_chan, receiver) = oneshot::channel();
         self.sender
             .send(Command::RequestRefreshShare {
                 key,
-                refresh_key,
+                refresh_key: refresh_key.into(),
                 peer,
                 sender,
+                epoch,
+                relay_grant,
+                trace_id: self.trace_id,
                 sender_chan,
             })
             .await
@@ -329,22 +916,1028 @@ impl Client {
     ///
     /// # Arguments
     ///
-    /// * `success` - Whether the refresh was successful.
+    /// * `result` - Whether the share was refreshed, or why it was not.
     /// * `channel` - The response channel to send the response.
     ///
     /// # Examples
     ///
     /// ```ignore
-    /// client.respond_refresh_shares(true, response_channel).await;
+    /// client.respond_refresh_shares(Ok(()), response_channel).await;
     /// ```
     pub async fn respond_refresh_shares(
         &mut self,
-        success: bool,
+        result: Result<(), Failure>,
+        channel: ResponseChannel<Response>,
+    ) {
+        self.sender
+            .send(Command::RespondRefreshShare { result, channel })
+            .await
+            .expect("Command receiver not to be dropped.");
+    }
+
+    /// Request the deletion of a share.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the share to delete.
+    /// * `peer` - The `PeerId` of the peer holding the share.
+    /// * `sender` - The `PeerId` of the sender making the request.
+    ///
+    /// # Returns
+    ///
+    /// The `DeleteShareStatus` reported by the peer.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let status = client.request_delete_share("my_key".to_string(), peer_id, sender_id).await?;
+    /// ```
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(%peer, request = "delete_share", trace_id = self.trace_id.map(display))
+    )]
+    pub async fn request_delete_share(
+        &mut self,
+        key: String,
+        peer: PeerId,
+        sender: PeerId,
+    ) -> Result<DeleteShareStatus, ClientError> {
+        let (sender_chan, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::RequestDeleteShare {
+                key,
+                peer,
+                sender,
+                trace_id: self.trace_id,
+                sender_chan,
+            })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not be dropped.")
+    }
+
+    /// Respond to a share deletion request.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - What was done with the share.
+    /// * `channel` - The response channel to send the response.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.respond_delete_share(DeleteShareStatus::Deleted, response_channel).await;
+    /// ```
+    pub async fn respond_delete_share(
+        &mut self,
+        status: DeleteShareStatus,
         channel: ResponseChannel<Response>,
     ) {
         self.sender
-            .send(Command::RespondRefreshShare { success, channel })
+            .send(Command::RespondDeleteShare { status, channel })
             .await
             .expect("Command receiver not to be dropped.");
     }
+
+    /// Request a page of the keys a peer holds shares under for the sender.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The `PeerId` of the peer to list the keys of.
+    /// * `sender` - The `PeerId` of the sender whose keys are listed.
+    /// * `cursor` - The last key of the previous page, or `None` for the first page.
+    /// * `limit` - The most keys to return.
+    ///
+    /// # Returns
+    ///
+    /// The keys on the page and the cursor of the next page, or `None` on the last page.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let (keys, next_cursor) = client.request_list_keys(peer_id, sender_id, None, 100).await?;
+    /// ```
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(%peer, request = "list_keys", trace_id = self.trace_id.map(display))
+    )]
+    pub async fn request_list_keys(
+        &mut self,
+        peer: PeerId,
+        sender: PeerId,
+        cursor: Option<String>,
+        limit: u32,
+    ) -> Result<(Vec<String>, Option<String>), ClientError> {
+        let (sender_chan, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::RequestListKeys {
+                peer,
+                sender,
+                cursor,
+                limit,
+                trace_id: self.trace_id,
+                sender_chan,
+            })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not be dropped.")
+    }
+
+    /// Request every key a peer holds shares under for the sender, walking the pages of its
+    /// listing.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The `PeerId` of the peer to list the keys of.
+    /// * `sender` - The `PeerId` of the sender whose keys are listed.
+    ///
+    /// # Returns
+    ///
+    /// The keys, in order, or the error of the first page that could not be listed.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let keys = client.request_all_keys(peer_id, sender_id).await?;
+    /// ```
+    pub async fn request_all_keys(
+        &mut self,
+        peer: PeerId,
+        sender: PeerId,
+    ) -> Result<Vec<String>, ClientError> {
+        let mut keys = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (page, next_cursor) = self
+                .request_list_keys(peer, sender, cursor, MAX_LIST_KEYS_LIMIT)
+                .await?;
+            keys.extend(page);
+            match next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(keys),
+            }
+        }
+    }
+
+    /// Respond to a key listing request.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The page of keys, or why they could not be listed.
+    /// * `channel` - The response channel to send the response.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.respond_list_keys(response, response_channel).await;
+    /// ```
+    pub async fn respond_list_keys(
+        &mut self,
+        response: ListKeysResponse,
+        channel: ResponseChannel<Response>,
+    ) {
+        self.sender
+            .send(Command::RespondListKeys { response, channel })
+            .await
+            .expect("Command receiver not to be dropped.");
+    }
+
+    /// Request the metadata of a share, without the share itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the share to describe.
+    /// * `peer` - The `PeerId` of the peer holding the share.
+    /// * `sender` - The `PeerId` of the sender making the request.
+    ///
+    /// # Returns
+    ///
+    /// The `StatShareStatus` reported by the peer.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let status = client.request_stat_share("my_key".to_string(), peer_id, sender_id).await?;
+    /// ```
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(%peer, request = "stat_share", trace_id = self.trace_id.map(display))
+    )]
+    pub async fn request_stat_share(
+        &mut self,
+        key: String,
+        peer: PeerId,
+        sender: PeerId,
+    ) -> Result<StatShareStatus, ClientError> {
+        let (sender_chan, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::RequestStatShare {
+                key,
+                peer,
+                sender,
+                trace_id: self.trace_id,
+                sender_chan,
+            })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not be dropped.")
+    }
+
+    /// Find the providers still holding a share `owner` registered under `key`, to tell whether
+    /// splitting another secret under the key would mix its shares with those of the first.
+    /// Provider records are namespaced by owner, so only the owner's own shares can collide, and
+    /// each provider found is asked for the share's metadata so that stale records are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The `PeerId` the shares were registered by.
+    /// * `key` - The key the shares were registered under.
+    ///
+    /// # Returns
+    ///
+    /// The providers that reported holding a share of `key`, empty if the key is unused.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// if !client.providers_holding(sender_id, "my_key").await.is_empty() {
+    ///     return Err("my_key is in use".into());
+    /// }
+    /// ```
+    pub async fn providers_holding(&mut self, owner: PeerId, key: &str) -> HashSet<PeerId> {
+        let providers = self.get_providers(Self::provider_key(&owner, key)).await;
+        let stats = providers.into_iter().map(|peer| {
+            let mut client = self.clone();
+            let key = key.to_string();
+            async move { (peer, client.request_stat_share(key, peer, owner).await) }
+        });
+        future::join_all(stats)
+            .await
+            .into_iter()
+            .filter(|(_, status)| matches!(status, Ok(StatShareStatus::Found(_))))
+            .map(|(peer, _)| peer)
+            .collect()
+    }
+
+    /// Respond to a share metadata request.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The metadata of the share, or why there is none.
+    /// * `channel` - The response channel to send the response.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.respond_stat_share(StatShareStatus::NotFound, response_channel).await;
+    /// ```
+    pub async fn respond_stat_share(
+        &mut self,
+        status: StatShareStatus,
+        channel: ResponseChannel<Response>,
+    ) {
+        self.sender
+            .send(Command::RespondStatShare { status, channel })
+            .await
+            .expect("Command receiver not to be dropped.");
+    }
+
+    /// Reject a request that could not be handled because it is malformed.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - What was wrong with the request.
+    /// * `channel` - The response channel to send the response.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.respond_invalid_request("malformed sender".to_string(), response_channel).await;
+    /// ```
+    pub async fn respond_invalid_request(
+        &mut self,
+        reason: String,
+        channel: ResponseChannel<Response>,
+    ) {
+        self.sender
+            .send(Command::RespondInvalidRequest { reason, channel })
+            .await
+            .expect("Command receiver not to be dropped.");
+    }
+
+    /// Turn down a request because its sender exceeded the rate limit for its operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `retry_after` - How long until the sender may retry the operation.
+    /// * `channel` - The response channel to send the response.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.respond_rate_limited(Duration::from_millis(250), response_channel).await;
+    /// ```
+    pub async fn respond_rate_limited(
+        &mut self,
+        retry_after: Duration,
+        channel: ResponseChannel<Response>,
+    ) {
+        self.sender
+            .send(Command::RespondRateLimited {
+                retry_after_millis: u64::try_from(retry_after.as_millis()).unwrap_or(u64::MAX),
+                channel,
+            })
+            .await
+            .expect("Command receiver not to be dropped.");
+    }
+
+    /// Turn down a request the local node cannot take on, such as while it is shutting down.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the request is turned down.
+    /// * `channel` - The response channel to send the response.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.respond_busy("shutting down".to_string(), response_channel).await;
+    /// ```
+    pub async fn respond_busy(&mut self, reason: String, channel: ResponseChannel<Response>) {
+        self.sender
+            .send(Command::RespondBusy { reason, channel })
+            .await
+            .expect("Command receiver not to be dropped.");
+    }
+
+    /// Request that a peer holding a share lets `reader` get it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the share.
+    /// * `peer` - The `PeerId` of the peer holding the share.
+    /// * `sender` - The `PeerId` of the share owner making the request.
+    /// * `reader` - The `PeerId` of the peer to grant access to.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the access was granted.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.request_grant_access("my_key".to_string(), peer_id, sender_id, reader_id).await?;
+    /// ```
+    pub async fn request_grant_access(
+        &mut self,
+        key: String,
+        peer: PeerId,
+        sender: PeerId,
+        reader: PeerId,
+    ) -> Result<bool, ClientError> {
+        self.request_access(key, peer, sender, reader, true).await
+    }
+
+    /// Request that a peer holding a share stops letting `reader` get it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the share.
+    /// * `peer` - The `PeerId` of the peer holding the share.
+    /// * `sender` - The `PeerId` of the share owner making the request.
+    /// * `reader` - The `PeerId` of the peer to revoke access from.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the access was revoked.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.request_revoke_access("my_key".to_string(), peer_id, sender_id, reader_id).await?;
+    /// ```
+    pub async fn request_revoke_access(
+        &mut self,
+        key: String,
+        peer: PeerId,
+        sender: PeerId,
+        reader: PeerId,
+    ) -> Result<bool, ClientError> {
+        self.request_access(key, peer, sender, reader, false).await
+    }
+
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(%peer, grant, trace_id = self.trace_id.map(display))
+    )]
+    async fn request_access(
+        &mut self,
+        key: String,
+        peer: PeerId,
+        sender: PeerId,
+        reader: PeerId,
+        grant: bool,
+    ) -> Result<bool, ClientError> {
+        let (sender_chan, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::RequestAccess {
+                key,
+                peer,
+                sender,
+                reader,
+                grant,
+                trace_id: self.trace_id,
+                sender_chan,
+            })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not be dropped.")
+    }
+
+    /// Respond to an access change request.
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - Whether the access was changed, or why it was not.
+    /// * `channel` - The response channel to send the response.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.respond_access(Ok(()), response_channel).await;
+    /// ```
+    pub async fn respond_access(
+        &mut self,
+        result: Result<(), Failure>,
+        channel: ResponseChannel<Response>,
+    ) {
+        self.sender
+            .send(Command::RespondAccess { result, channel })
+            .await
+            .expect("Command receiver not to be dropped.");
+    }
+
+    /// Request that a peer holding a share hands it over to another owner. The request is
+    /// signed with `owner`, whose node must be the one sending it, and carries the grant of
+    /// `new_owner` for the peer to keep relaying refreshes of the share.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the share.
+    /// * `peer` - The `PeerId` of the peer holding the share.
+    /// * `owner` - The identity key of the share owner making the request.
+    /// * `new_owner` - The identity key of the owner to hand the share over to.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the share was handed over.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.request_transfer_ownership("my_key".to_string(), peer_id, &keypair, &new_keypair).await?;
+    /// ```
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(%peer, new_owner = %new_owner.public().to_peer_id(), trace_id = self.trace_id.map(display))
+    )]
+    pub async fn request_transfer_ownership(
+        &mut self,
+        key: String,
+        peer: PeerId,
+        owner: &Keypair,
+        new_owner: &Keypair,
+    ) -> Result<bool, ClientError> {
+        let new_owner_id = new_owner.public().to_peer_id();
+        let mut request =
+            TransferOwnershipRequest::signed(&key, peer, owner, new_owner_id, self.trace_id)?;
+        request.relay_grant = Some(RelayGrant::signed(&key, peer, new_owner)?);
+        let (sender_chan, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::RequestTransferOwnership {
+                peer,
+                request,
+                sender_chan,
+            })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not be dropped.")
+    }
+
+    /// Respond to an ownership transfer request.
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - Whether the share was handed over, or why it was not.
+    /// * `channel` - The response channel to send the response.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.respond_transfer_ownership(Ok(()), response_channel).await;
+    /// ```
+    pub async fn respond_transfer_ownership(
+        &mut self,
+        result: Result<(), Failure>,
+        channel: ResponseChannel<Response>,
+    ) {
+        self.sender
+            .send(Command::RespondTransferOwnership { result, channel })
+            .await
+            .expect("Command receiver not to be dropped.");
+    }
+
+    /// Broadcast a message to every node over gossipsub.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message to publish.
+    ///
+    /// # Returns
+    ///
+    /// An error if the message could not be published, such as when no peer is subscribed yet.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.publish(GossipMessage::UnderReplicated(alert)).await?;
+    /// ```
+    pub async fn publish(&mut self, message: GossipMessage) -> Result<(), ClientError> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::Publish { message, sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Broadcast the local provider's health status on the health topic. Whether the provider is
+    /// reachable is filled in from the swarm.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The status to publish.
+    ///
+    /// # Returns
+    ///
+    /// An error if the status could not be published, such as when no peer is subscribed yet.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.publish_status(status).await?;
+    /// ```
+    pub async fn publish_status(&mut self, status: ProviderStatus) -> Result<(), ClientError> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::PublishStatus { status, sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Get the latest health status of every provider heard from, leaving out providers that
+    /// stopped publishing.
+    ///
+    /// # Returns
+    ///
+    /// The statuses, ordered by peer id.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// for status in client.provider_statuses().await {
+    ///     println!("{} holds {} shares", PeerId::from_bytes(&status.peer)?, status.shares);
+    /// }
+    /// ```
+    pub async fn provider_statuses(&mut self) -> Vec<ProviderStatus> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::ProviderStatuses { sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Get the addresses the local node is listening on. An address passed to `start_listening`
+    /// is only listed once the transport has bound it, with any unspecified port resolved.
+    ///
+    /// # Returns
+    ///
+    /// The listen addresses.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// for addr in client.listen_addrs().await {
+    ///     println!("listening on {}", addr);
+    /// }
+    /// ```
+    pub async fn listen_addrs(&mut self) -> Vec<Multiaddr> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::ListenAddrs { sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Announce an address the local node is reachable at, such as the public address of a
+    /// provider behind a port forward, which peers learn through identify and the DHT.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address, without the local peer id.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.add_external_address("/dns4/shard.example.com/tcp/40837".parse()?).await;
+    /// ```
+    pub async fn add_external_address(&mut self, addr: Multiaddr) {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::AddExternalAddress { addr, sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Block a peer: its connections are closed, and any new one is refused, until it is allowed
+    /// again.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The peer to block.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.block_peer(peer_id).await;
+    /// ```
+    pub async fn block_peer(&mut self, peer: PeerId) {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::BlockPeer { peer, sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Allow a peer blocked with `block_peer` to connect again.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The peer to allow.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.allow_peer(peer_id).await;
+    /// ```
+    pub async fn allow_peer(&mut self, peer: PeerId) {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::AllowPeer { peer, sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Get the peers the local node is connected to.
+    ///
+    /// # Returns
+    ///
+    /// The connected peers, each listed once however many connections it has.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// println!("{} peers connected", client.connected_peers().await.len());
+    /// ```
+    pub async fn connected_peers(&mut self) -> Vec<PeerId> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::ConnectedPeers { sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Get a snapshot of the local node's view of the network: its identity, addresses, peers
+    /// and whether it believes it is publicly reachable.
+    ///
+    /// # Returns
+    ///
+    /// The `NetworkInfo` of the local node.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let info = client.network_info().await;
+    /// println!("{} knows {} peers", info.peer_id, info.routing_table_size);
+    /// ```
+    pub async fn network_info(&mut self) -> NetworkInfo {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::NetworkInfo { sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Count the operations the event loop is waiting on an answer for, such as dials, DHT
+    /// queries and requests to providers.
+    ///
+    /// # Returns
+    ///
+    /// The number of pending operations of each kind, every kind listed even when none are
+    /// pending.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// for (kind, count) in client.pending_requests().await {
+    ///     println!("{kind}: {count}");
+    /// }
+    /// ```
+    pub async fn pending_requests(&mut self) -> Vec<(&'static str, usize)> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::PendingRequests { sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Stop the network event loop. Commands sent after it stopped panic, as with any closed
+    /// command channel.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// client.shutdown().await;
+    /// ```
+    pub async fn shutdown(&mut self) {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(Command::Shutdown { sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        receiver.await.expect("Sender not to be dropped.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network;
+    use libp2p::identity::Keypair;
+
+    /// Starts a node listening on an in-process memory address, returning its client, peer ID,
+    /// address and event stream, which must be kept for the event loop to run.
+    async fn memory_node() -> (Client, PeerId, Multiaddr, impl Stream) {
+        memory_node_on(None).await
+    }
+
+    /// Like `memory_node`, on the network `network_id`.
+    async fn memory_node_on(network_id: Option<&str>) -> (Client, PeerId, Multiaddr, impl Stream) {
+        let key = Keypair::generate_ed25519();
+        let (mut client, events, event_loop, peer_id) =
+            network::with_network(key, Duration::from_secs(5), network_id)
+                .await
+                .unwrap();
+        tokio::spawn(event_loop.run(None));
+        let addr: Multiaddr = format!("/memory/{}", rand::random::<u64>())
+            .parse()
+            .unwrap();
+        client.start_listening(addr.clone()).await.unwrap();
+        (client, peer_id, addr, events)
+    }
+
+    #[tokio::test]
+    async fn test_nodes_only_route_within_their_network() {
+        let (mut provider, provider_id, addr, _provider_events) =
+            memory_node_on(Some("staging")).await;
+        provider.start_providing("key".to_string()).await;
+        let criteria = ReadyCriteria {
+            min_connections: 1,
+            bootstrap: true,
+            min_providers: 1,
+            provider_key: Some("key".to_string()),
+        };
+
+        let (mut peer, _, _, _peer_events) = memory_node_on(Some("staging")).await;
+        peer.dial(provider_id, addr.clone()).await.unwrap();
+        let found = peer.await_ready(&criteria, Duration::from_secs(10)).await;
+        assert_eq!(found.unwrap(), HashSet::from([provider_id]));
+
+        // connected, but neither the DHT nor requests are spoken across networks
+        let (mut stranger, _, _, _stranger_events) = memory_node().await;
+        stranger.dial(provider_id, addr).await.unwrap();
+        let result = stranger
+            .await_ready(&criteria, Duration::from_secs(2))
+            .await;
+        assert!(result.is_err());
+        let stat = stranger
+            .request_stat_share("key".to_string(), provider_id, provider_id)
+            .await;
+        assert!(stat.is_err());
+
+        let invalid = network::with_network(
+            Keypair::generate_ed25519(),
+            Duration::from_secs(1),
+            Some("a/b"),
+        );
+        assert!(invalid.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_external_addresses_are_announced() {
+        let (mut node, _, _, _events) = memory_node().await;
+        assert!(node.network_info().await.external_addrs.is_empty());
+        let addr: Multiaddr = "/dns4/shard.example.com/tcp/40837".parse().unwrap();
+        node.add_external_address(addr.clone()).await;
+        let info = node.network_info().await;
+        assert_eq!(info.external_addrs, vec![addr]);
+        assert!(info.reachable);
+    }
+
+    #[tokio::test]
+    async fn test_blocked_peers_are_disconnected_until_allowed() {
+        let (mut node, node_id, addr, _node_events) = memory_node().await;
+        let (mut peer, peer_id, _, _peer_events) = memory_node().await;
+        peer.dial(node_id, addr.clone()).await.unwrap();
+        assert_eq!(node.connected_peers().await, vec![peer_id]);
+
+        node.block_peer(peer_id).await;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !node.connected_peers().await.is_empty() {
+            assert!(
+                Instant::now() < deadline,
+                "the blocked peer is still connected"
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        // the dial may complete before the node refuses the connection
+        let _ = peer.dial(node_id, addr.clone()).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(node.connected_peers().await.is_empty());
+
+        node.allow_peer(peer_id).await;
+        peer.dial(node_id, addr).await.unwrap();
+        assert_eq!(node.connected_peers().await, vec![peer_id]);
+    }
+
+    #[tokio::test]
+    async fn test_ready_once_connected_bootstrapped_and_provided() {
+        let (mut provider, provider_id, addr, _provider_events) = memory_node().await;
+        provider.start_providing("key".to_string()).await;
+        let (mut client, _, _, _events) = memory_node().await;
+        client.dial(provider_id, addr).await.unwrap();
+
+        let criteria = ReadyCriteria {
+            min_connections: 1,
+            bootstrap: true,
+            min_providers: 1,
+            provider_key: Some("key".to_string()),
+        };
+        let started = Instant::now();
+        let providers = client
+            .await_ready(&criteria, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(providers, HashSet::from([provider_id]));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_ready_once_a_late_provider_appears() {
+        let (mut first, first_id, first_addr, _first_events) = memory_node().await;
+        first.start_providing("key".to_string()).await;
+        let (mut second, second_id, _, _second_events) = memory_node().await;
+        let (mut client, _, _, _events) = memory_node().await;
+        client.dial(first_id, first_addr.clone()).await.unwrap();
+
+        let criteria = ReadyCriteria {
+            min_providers: 2,
+            provider_key: Some("key".to_string()),
+            ..Default::default()
+        };
+        let (reports, mut reported) = mpsc::unbounded();
+        let mut waiting = client.clone();
+        let wait = waiting.await_ready_with_progress(
+            &criteria,
+            Duration::from_secs(5),
+            |found, wanted| reports.unbounded_send((found, wanted)).unwrap(),
+        );
+        // the second provider only joins, announcing itself to the first, once the first has
+        // been found
+        let join = async {
+            assert_eq!(reported.next().await, Some((1, 2)));
+            second.dial(first_id, first_addr).await.unwrap();
+            second.start_providing("key".to_string()).await;
+        };
+        let (providers, ()) = futures::join!(wait, join);
+        assert_eq!(providers.unwrap(), HashSet::from([first_id, second_id]));
+        assert_eq!(reported.next().await, Some((2, 2)));
+    }
+
+    #[tokio::test]
+    async fn test_watch_reports_a_provider_added_mid_watch() {
+        let (mut first, first_id, first_addr, _first_events) = memory_node().await;
+        first.start_providing("key".to_string()).await;
+        let (mut second, second_id, _, _second_events) = memory_node().await;
+        let (mut client, _, _, _events) = memory_node().await;
+        client.dial(first_id, first_addr.clone()).await.unwrap();
+
+        let mut changes = Box::pin(
+            client.watch_providers("key".to_string(), Duration::from_millis(READY_POLL_MILLIS)),
+        );
+        let timeout = Duration::from_secs(5);
+        let change = tokio::time::timeout(timeout, changes.next()).await.unwrap();
+        assert_eq!(
+            change.unwrap(),
+            ProvidersChange {
+                providers: HashSet::from([first_id]),
+                added: HashSet::from([first_id]),
+                removed: HashSet::new(),
+            }
+        );
+
+        second.dial(first_id, first_addr).await.unwrap();
+        second.start_providing("key".to_string()).await;
+        let change = tokio::time::timeout(timeout, changes.next()).await.unwrap();
+        assert_eq!(
+            change.unwrap(),
+            ProvidersChange {
+                providers: HashSet::from([first_id, second_id]),
+                added: HashSet::from([second_id]),
+                removed: HashSet::new(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ready_times_out_saying_what_is_missing() {
+        let (mut client, _, _, _events) = memory_node().await;
+        let timeout = Duration::from_millis(100);
+
+        let criteria = ReadyCriteria {
+            min_connections: 1,
+            ..Default::default()
+        };
+        let err = client.await_ready(&criteria, timeout).await.unwrap_err();
+        assert_eq!(
+            err,
+            NotReady::Connections {
+                connected: 0,
+                wanted: 1
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "timed out waiting for connections (connected to 0 of 1)"
+        );
+
+        let criteria = ReadyCriteria {
+            bootstrap: true,
+            ..Default::default()
+        };
+        let err = client.await_ready(&criteria, timeout).await.unwrap_err();
+        assert!(matches!(err, NotReady::Bootstrap(_)), "{}", err);
+        assert!(
+            err.to_string()
+                .starts_with("timed out waiting for the Kademlia bootstrap"),
+            "{}",
+            err
+        );
+
+        let criteria = ReadyCriteria {
+            min_providers: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            client.await_ready(&criteria, timeout).await.unwrap_err(),
+            NotReady::Providers {
+                found: 0,
+                wanted: 2
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ready_at_once_without_criteria() {
+        let (mut client, _, _, _events) = memory_node().await;
+        let providers = client
+            .await_ready(&ReadyCriteria::default(), Duration::ZERO)
+            .await
+            .unwrap();
+        assert!(providers.is_empty());
+    }
 }
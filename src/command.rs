@@ -1,16 +1,27 @@
 use futures::channel::oneshot;
+use libp2p::gossipsub::IdentTopic;
 use libp2p::request_response::ResponseChannel;
 use libp2p::{core::Multiaddr, multiaddr::Protocol, PeerId};
 
+use crate::client::ClientError;
+use crate::constants::{AGENT_VERSION, GOSSIP_TOPIC, HEALTH_TOPIC};
 use crate::event::EventLoop;
+use crate::network::NetworkInfo;
 use crate::protocol::{
-    GetShareRequest, GetShareResponse, RefreshShareRequest, RefreshShareResponse,
-    RegisterShareRequest, RegisterShareResponse, Request, Response,
+    AccessRequest, AccessResponse, BusyResponse, DeleteShareRequest, DeleteShareResponse,
+    DeleteShareStatus, Failure, FieldShare, GetShareRequest, GetShareResponse, GossipMessage,
+    InvalidRequestResponse, ListKeysRequest, ListKeysResponse, ProviderStatus, RateLimitedResponse,
+    RefreshKey, RefreshShareRequest, RefreshShareResponse, RegisterShareRequest,
+    RegisterShareResponse, RegisterShareStatus, RelayGrant, Request, Response, StatShareRequest,
+    StatShareResponse, StatShareStatus, TraceId, TransferOwnershipRequest,
+    TransferOwnershipResponse,
 };
-use crate::sss::Polynomial;
 use std::collections::{hash_map, HashSet};
-use std::error::Error;
-use tracing::debug;
+use std::time::Instant;
+use tracing::{debug, debug_span, warn, Span};
+
+/// The result delivered back to a `Client` once the event loop has processed a command.
+pub type CommandResult<T> = Result<T, ClientError>;
 
 /// Represents commands that can be issued to the network.
 ///
@@ -22,14 +33,44 @@ use tracing::debug;
 /// * `StartListening` - Command to start listening on a specified address.
 /// * `Dial` - Command to dial a specific peer.
 /// * `StartProviding` - Command to start providing a key in the Kademlia DHT.
+/// * `StopProviding` - Command to stop providing a key in the Kademlia DHT.
 /// * `GetProviders` - Command to get providers for a key in the DHT.
+/// * `FindProviders` - Command to get every provider of a key in the DHT, answered once the lookup
+///   completes rather than on its first result.
 /// * `GetAllProviders` - Command to get all providers in the network.
+/// * `Bootstrap` - Command to refresh the routing table with a Kademlia bootstrap, answered once
+///   the bootstrap completes.
 /// * `RequestShare` - Command to request a share from a peer.
 /// * `RespondShare` - Command to respond to a share request.
 /// * `RequestRegisterShare` - Command to request registration of a share.
 /// * `RespondRegisterShare` - Command to respond to a share registration request.
 /// * `RequestRefreshShare` - Command to request the refreshing of shares.
 /// * `RespondRefreshShare` - Command to respond to a share refresh request.
+/// * `RequestDeleteShare` - Command to request the deletion of a share.
+/// * `RespondDeleteShare` - Command to respond to a share deletion request.
+/// * `RequestListKeys` - Command to request a page of the sender's keys from a peer.
+/// * `RespondListKeys` - Command to respond to a key listing request.
+/// * `RequestStatShare` - Command to request the metadata of a share.
+/// * `RespondStatShare` - Command to respond to a share metadata request.
+/// * `RespondInvalidRequest` - Command to reject a malformed request.
+/// * `RespondRateLimited` - Command to turn down a request from a sender over its rate limit.
+/// * `RespondBusy` - Command to turn down a request the local node cannot take on.
+/// * `RequestAccess` - Command to grant or revoke a peer's read access to a share.
+/// * `RespondAccess` - Command to respond to an access change request.
+/// * `RequestTransferOwnership` - Command to hand a share over to another owner.
+/// * `RespondTransferOwnership` - Command to respond to an ownership transfer request.
+/// * `Publish` - Command to broadcast a message to every node over gossipsub.
+/// * `PublishStatus` - Command to broadcast the local provider's health status.
+/// * `ProviderStatuses` - Command to get the latest unexpired status of every provider heard
+///   from.
+/// * `ListenAddrs` - Command to get the addresses the local node is listening on.
+/// * `AddExternalAddress` - Command to announce an address the local node is reachable at.
+/// * `BlockPeer` - Command to close the connections of a peer and refuse any new one.
+/// * `AllowPeer` - Command to accept the connections of a blocked peer again.
+/// * `ConnectedPeers` - Command to get the peers the local node is connected to.
+/// * `NetworkInfo` - Command to get a snapshot of the local node's view of the network.
+/// * `PendingRequests` - Command to count the operations awaiting an answer, by kind.
+/// * `Shutdown` - Command to stop the network event loop.
 ///
 /// # Examples
 ///
@@ -45,58 +86,239 @@ use tracing::debug;
 pub enum Command {
     StartListening {
         addr: Multiaddr,
-        sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+        sender: oneshot::Sender<CommandResult<()>>,
     },
     Dial {
         peer_id: PeerId,
         peer_addr: Multiaddr,
-        sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+        sender: oneshot::Sender<CommandResult<()>>,
     },
     StartProviding {
         key: String,
         sender: oneshot::Sender<()>,
     },
+    StopProviding {
+        key: String,
+    },
     GetProviders {
         key: String,
         sender: oneshot::Sender<HashSet<PeerId>>,
     },
+    FindProviders {
+        key: String,
+        sender: oneshot::Sender<HashSet<PeerId>>,
+    },
     GetAllProviders {
         sender: oneshot::Sender<HashSet<PeerId>>,
     },
+    Bootstrap {
+        sender: oneshot::Sender<CommandResult<()>>,
+    },
     RequestShare {
         key: String,
         peer: PeerId,
         sender: PeerId,
-        sender_chan: oneshot::Sender<Result<(u8, Vec<u8>), Box<dyn Error + Send>>>,
+        owner: Option<PeerId>,
+        trace_id: Option<TraceId>,
+        sender_chan: oneshot::Sender<CommandResult<FieldShare>>,
     },
     RespondShare {
-        share: (u8, Vec<u8>),
-        success: bool,
+        result: Result<FieldShare, Failure>,
         channel: ResponseChannel<Response>,
     },
     RequestRegisterShare {
-        share: (u8, Vec<u8>),
+        share: FieldShare,
         key: String,
         peer: PeerId,
         sender: PeerId,
         threshold: u64,
-        sender_chan: oneshot::Sender<Result<bool, Box<dyn Error + Send>>>,
+        ttl_secs: Option<u64>,
+        recreate: bool,
+        refresh_interval_secs: Option<u64>,
+        replace: bool,
+        relay_grant: Option<RelayGrant>,
+        trace_id: Option<TraceId>,
+        sender_chan: oneshot::Sender<CommandResult<RegisterShareStatus>>,
     },
     RespondRegisterShare {
-        success: bool,
+        result: Result<(), Failure>,
         channel: ResponseChannel<Response>,
     },
     RequestRefreshShare {
         key: String,
-        refresh_key: Vec<Polynomial>,
+        refresh_key: RefreshKey,
         peer: PeerId,
         sender: PeerId,
-        sender_chan: oneshot::Sender<Result<bool, Box<dyn Error + Send>>>,
+        epoch: Option<u64>,
+        relay_grant: Option<RelayGrant>,
+        trace_id: Option<TraceId>,
+        sender_chan: oneshot::Sender<CommandResult<bool>>,
     },
     RespondRefreshShare {
-        success: bool,
+        result: Result<(), Failure>,
         channel: ResponseChannel<Response>,
     },
+    RequestDeleteShare {
+        key: String,
+        peer: PeerId,
+        sender: PeerId,
+        trace_id: Option<TraceId>,
+        sender_chan: oneshot::Sender<CommandResult<DeleteShareStatus>>,
+    },
+    RespondDeleteShare {
+        status: DeleteShareStatus,
+        channel: ResponseChannel<Response>,
+    },
+    RequestListKeys {
+        peer: PeerId,
+        sender: PeerId,
+        cursor: Option<String>,
+        limit: u32,
+        trace_id: Option<TraceId>,
+        sender_chan: oneshot::Sender<CommandResult<(Vec<String>, Option<String>)>>,
+    },
+    RespondListKeys {
+        response: ListKeysResponse,
+        channel: ResponseChannel<Response>,
+    },
+    RequestStatShare {
+        key: String,
+        peer: PeerId,
+        sender: PeerId,
+        trace_id: Option<TraceId>,
+        sender_chan: oneshot::Sender<CommandResult<StatShareStatus>>,
+    },
+    RespondStatShare {
+        status: StatShareStatus,
+        channel: ResponseChannel<Response>,
+    },
+    RespondInvalidRequest {
+        reason: String,
+        channel: ResponseChannel<Response>,
+    },
+    RespondRateLimited {
+        retry_after_millis: u64,
+        channel: ResponseChannel<Response>,
+    },
+    RespondBusy {
+        reason: String,
+        channel: ResponseChannel<Response>,
+    },
+    RequestAccess {
+        key: String,
+        peer: PeerId,
+        sender: PeerId,
+        reader: PeerId,
+        grant: bool,
+        trace_id: Option<TraceId>,
+        sender_chan: oneshot::Sender<CommandResult<bool>>,
+    },
+    RespondAccess {
+        result: Result<(), Failure>,
+        channel: ResponseChannel<Response>,
+    },
+    RequestTransferOwnership {
+        peer: PeerId,
+        request: TransferOwnershipRequest,
+        sender_chan: oneshot::Sender<CommandResult<bool>>,
+    },
+    RespondTransferOwnership {
+        result: Result<(), Failure>,
+        channel: ResponseChannel<Response>,
+    },
+    Publish {
+        message: GossipMessage,
+        sender: oneshot::Sender<CommandResult<()>>,
+    },
+    PublishStatus {
+        status: ProviderStatus,
+        sender: oneshot::Sender<CommandResult<()>>,
+    },
+    ProviderStatuses {
+        sender: oneshot::Sender<Vec<ProviderStatus>>,
+    },
+    ListenAddrs {
+        sender: oneshot::Sender<Vec<Multiaddr>>,
+    },
+    AddExternalAddress {
+        addr: Multiaddr,
+        sender: oneshot::Sender<()>,
+    },
+    BlockPeer {
+        peer: PeerId,
+        sender: oneshot::Sender<()>,
+    },
+    AllowPeer {
+        peer: PeerId,
+        sender: oneshot::Sender<()>,
+    },
+    ConnectedPeers {
+        sender: oneshot::Sender<Vec<PeerId>>,
+    },
+    NetworkInfo {
+        sender: oneshot::Sender<NetworkInfo>,
+    },
+    PendingRequests {
+        sender: oneshot::Sender<Vec<(&'static str, usize)>>,
+    },
+    Shutdown {
+        sender: oneshot::Sender<()>,
+    },
+}
+
+/// Splits the result of a handler into the status fields of its response, so that a response is
+/// only ever successful without a failure.
+///
+/// # Arguments
+///
+/// * `result` - What the handler did, or why it failed.
+///
+/// # Returns
+///
+/// The `success` flag, the human readable `reason`, and the `failure` of the response.
+fn response_status<T>(result: &Result<T, Failure>) -> (bool, Option<String>, Option<Failure>) {
+    match result {
+        Ok(_) => (true, None, None),
+        Err(failure) => (false, Some(failure.to_string()), Some(failure.clone())),
+    }
+}
+
+/// Opens the span an outbound request is sent in.
+///
+/// # Arguments
+///
+/// * `peer` - The `PeerId` the request is sent to.
+/// * `request` - The type of the request, such as `get_share`.
+/// * `trace_id` - The trace id of the client operation the request is part of, if any.
+///
+/// # Returns
+///
+/// The span, which the event loop runs outside of the span of the client operation and which the
+/// trace id ties back to it.
+fn request_span(peer: &PeerId, request: &'static str, trace_id: Option<TraceId>) -> Span {
+    debug_span!("outbound_request", %peer, request, trace_id = trace_id.map(display))
+}
+
+/// Sends the response to an inbound request.
+///
+/// A requester whose request timed out, or whose connection closed, is gone by the time a slow
+/// handler answers, so the response is then dropped rather than failing the event loop.
+///
+/// # Arguments
+///
+/// * `eventloop` - The event loop the request arrived on.
+/// * `channel` - The channel of the request.
+/// * `response` - The response to send.
+fn respond(eventloop: &mut EventLoop, channel: ResponseChannel<Response>, response: Response) {
+    if eventloop
+        .swarm
+        .behaviour_mut()
+        .request_response
+        .send_response(channel, response)
+        .is_err()
+    {
+        debug!("The requester is gone, dropped the response.");
+    }
 }
 
 /// Handles incoming commands for the network event loop.
@@ -121,7 +343,7 @@ pub async fn command_handler(eventloop: &mut EventLoop, command: Command) {
         Command::StartListening { addr, sender } => {
             let _ = match eventloop.swarm.listen_on(addr) {
                 Ok(_) => sender.send(Ok(())),
-                Err(e) => sender.send(Err(Box::new(e))),
+                Err(e) => sender.send(Err(e.into())),
             };
         }
         Command::Dial {
@@ -140,7 +362,7 @@ pub async fn command_handler(eventloop: &mut EventLoop, command: Command) {
                         e.insert(sender);
                     }
                     Err(e) => {
-                        let _ = sender.send(Err(Box::new(e)));
+                        let _ = sender.send(Err(e.into()));
                     }
                 }
             } else {
@@ -159,9 +381,16 @@ pub async fn command_handler(eventloop: &mut EventLoop, command: Command) {
                 .expect("No store error.");
             eventloop.pending_start_providing.insert(query_id, sender);
         }
+        Command::StopProviding { key } => {
+            eventloop
+                .swarm
+                .behaviour_mut()
+                .kademlia
+                .stop_providing(&key.into_bytes().into());
+        }
         Command::GetProviders { key, sender } => {
             if let Err(e) = eventloop.swarm.behaviour_mut().kademlia.bootstrap() {
-                println!("Failed to run Kademlia bootstrap: {e:?}");
+                warn!("Failed to run Kademlia bootstrap: {e:?}");
             }
             let query_id = eventloop
                 .swarm
@@ -170,9 +399,19 @@ pub async fn command_handler(eventloop: &mut EventLoop, command: Command) {
                 .get_providers(key.into_bytes().into());
             eventloop.pending_get_providers.insert(query_id, sender);
         }
+        Command::FindProviders { key, sender } => {
+            let query_id = eventloop
+                .swarm
+                .behaviour_mut()
+                .kademlia
+                .get_providers(key.into_bytes().into());
+            eventloop
+                .pending_find_providers
+                .insert(query_id, (HashSet::new(), sender));
+        }
         Command::GetAllProviders { sender } => {
             if let Err(e) = eventloop.swarm.behaviour_mut().kademlia.bootstrap() {
-                println!("Failed to run Kademlia bootstrap: {e:?}");
+                warn!("Failed to run Kademlia bootstrap: {e:?}");
             }
             // build a list of all peers in the routing table
             let peers: Vec<PeerId> = eventloop
@@ -180,20 +419,34 @@ pub async fn command_handler(eventloop: &mut EventLoop, command: Command) {
                 .behaviour_mut()
                 .gossipsub
                 .all_peers()
-                .map(|p| p.0.clone())
+                .map(|p| *p.0)
                 .collect();
             debug!("Found {} peers", peers.len());
             debug!("Peers: {:?}", peers);
             let set: HashSet<PeerId> = peers.into_iter().collect();
-            sender.send(set).expect("Receiver not to be dropped.");
+            // the caller may have stopped waiting for the lookup
+            let _ = sender.send(set);
             debug!("Completed get all providers");
         }
+        Command::Bootstrap { sender } => {
+            match eventloop.swarm.behaviour_mut().kademlia.bootstrap() {
+                Ok(query_id) => {
+                    eventloop.pending_bootstrap.insert(query_id, sender);
+                }
+                Err(_) => {
+                    let _ = sender.send(Err(ClientError::NoKnownPeers));
+                }
+            }
+        }
         Command::RequestShare {
             key,
             peer,
             sender,
+            owner,
+            trace_id,
             sender_chan,
         } => {
+            let _span = request_span(&peer, "get_share", trace_id).entered();
             let request_id = eventloop
                 .swarm
                 .behaviour_mut()
@@ -204,35 +457,45 @@ pub async fn command_handler(eventloop: &mut EventLoop, command: Command) {
                         key,
                         peer: peer.into(),
                         sender: sender.into(),
+                        owner: owner.map(PeerId::into),
+                        trace_id,
                     }),
                 );
             eventloop
                 .pending_request_share
                 .insert(request_id, sender_chan);
         }
-        Command::RespondShare {
-            share,
-            success,
-            channel,
-        } => {
-            eventloop
-                .swarm
-                .behaviour_mut()
-                .request_response
-                .send_response(
-                    channel,
-                    Response::GetShare(GetShareResponse { share, success }),
-                )
-                .expect("Connection to peer to be still open.");
+        Command::RespondShare { result, channel } => {
+            let (success, reason, failure) = response_status(&result);
+            let share = result.unwrap_or_default();
+            respond(
+                eventloop,
+                channel,
+                Response::GetShare(GetShareResponse {
+                    share: share.share,
+                    #[cfg(feature = "sss16")]
+                    field: share.field,
+                    success,
+                    reason,
+                    failure,
+                }),
+            );
         }
         Command::RequestRegisterShare {
             share,
             key,
             peer,
             threshold,
+            ttl_secs,
+            recreate,
+            refresh_interval_secs,
+            replace,
+            relay_grant,
             sender,
+            trace_id,
             sender_chan,
         } => {
+            let _span = request_span(&peer, "register_share", trace_id).entered();
             debug!("Sending request to register share {}.", key);
             let request_id = eventloop
                 .swarm
@@ -241,11 +504,19 @@ pub async fn command_handler(eventloop: &mut EventLoop, command: Command) {
                 .send_request(
                     &peer,
                     Request::RegisterShare(RegisterShareRequest {
-                        share,
+                        share: share.share,
+                        #[cfg(feature = "sss16")]
+                        field: share.field,
                         key,
                         threshold,
+                        ttl_secs,
+                        recreate,
+                        refresh_interval_secs,
+                        replace,
                         peer: peer.into(),
                         sender: sender.into(),
+                        relay_grant,
+                        trace_id,
                     }),
                 );
             eventloop
@@ -253,25 +524,37 @@ pub async fn command_handler(eventloop: &mut EventLoop, command: Command) {
                 .insert(request_id, sender_chan);
             debug!("Sent request to register share");
         }
-        Command::RespondRegisterShare { success, channel } => {
-            eventloop
-                .swarm
-                .behaviour_mut()
-                .request_response
-                .send_response(
-                    channel,
-                    Response::RegisterShare(RegisterShareResponse { success }),
-                )
-                .expect("Connection to peer should still be open.");
+        Command::RespondRegisterShare { result, channel } => {
+            let (success, reason, failure) = response_status(&result);
+            respond(
+                eventloop,
+                channel,
+                Response::RegisterShare(RegisterShareResponse {
+                    success,
+                    reason,
+                    failure,
+                }),
+            );
         }
         Command::RequestRefreshShare {
             key,
             refresh_key,
             peer,
             sender,
+            epoch,
+            relay_grant,
+            trace_id,
             sender_chan,
         } => {
+            let _span = request_span(&peer, "refresh_share", trace_id).entered();
             debug!("Sending request to refresh shares {}.", key);
+            #[cfg(feature = "sss16")]
+            let (refresh_key, wide_refresh_key) = match refresh_key {
+                RefreshKey::Gf256(polynomials) => (polynomials, Vec::new()),
+                RefreshKey::Gf65536(polynomials) => (Vec::new(), polynomials),
+            };
+            #[cfg(not(feature = "sss16"))]
+            let RefreshKey::Gf256(refresh_key) = refresh_key;
             let request_id = eventloop
                 .swarm
                 .behaviour_mut()
@@ -281,8 +564,13 @@ pub async fn command_handler(eventloop: &mut EventLoop, command: Command) {
                     Request::RefreshShare(RefreshShareRequest {
                         key,
                         refresh_key,
+                        #[cfg(feature = "sss16")]
+                        wide_refresh_key,
                         peer: peer.into(),
                         sender: sender.into(),
+                        epoch,
+                        relay_grant,
+                        trace_id,
                     }),
                 );
             eventloop
@@ -290,16 +578,300 @@ pub async fn command_handler(eventloop: &mut EventLoop, command: Command) {
                 .insert(request_id, sender_chan);
             debug!("Sent request to refresh shares");
         }
-        Command::RespondRefreshShare { success, channel } => {
+        Command::RespondRefreshShare { result, channel } => {
+            let (success, reason, failure) = response_status(&result);
+            respond(
+                eventloop,
+                channel,
+                Response::RefreshShares(RefreshShareResponse {
+                    success,
+                    reason,
+                    failure,
+                }),
+            );
+        }
+        Command::RequestDeleteShare {
+            key,
+            peer,
+            sender,
+            trace_id,
+            sender_chan,
+        } => {
+            let _span = request_span(&peer, "delete_share", trace_id).entered();
+            debug!("Sending request to delete share {}.", key);
+            let request_id = eventloop
+                .swarm
+                .behaviour_mut()
+                .request_response
+                .send_request(
+                    &peer,
+                    Request::DeleteShare(DeleteShareRequest {
+                        key,
+                        peer: peer.into(),
+                        sender: sender.into(),
+                        trace_id,
+                    }),
+                );
             eventloop
+                .pending_delete_share
+                .insert(request_id, sender_chan);
+            debug!("Sent request to delete share");
+        }
+        Command::RespondDeleteShare { status, channel } => {
+            respond(
+                eventloop,
+                channel,
+                Response::DeleteShare(DeleteShareResponse { status }),
+            );
+        }
+        Command::RequestListKeys {
+            peer,
+            sender,
+            cursor,
+            limit,
+            trace_id,
+            sender_chan,
+        } => {
+            let _span = request_span(&peer, "list_keys", trace_id).entered();
+            debug!("Sending request to list keys to {}.", peer);
+            let request_id = eventloop
+                .swarm
+                .behaviour_mut()
+                .request_response
+                .send_request(
+                    &peer,
+                    Request::ListKeys(ListKeysRequest {
+                        peer: peer.into(),
+                        sender: sender.into(),
+                        cursor,
+                        limit,
+                        trace_id,
+                    }),
+                );
+            eventloop.pending_list_keys.insert(request_id, sender_chan);
+            debug!("Sent request to list keys");
+        }
+        Command::RespondListKeys { response, channel } => {
+            respond(eventloop, channel, Response::ListKeys(response));
+        }
+        Command::RequestStatShare {
+            key,
+            peer,
+            sender,
+            trace_id,
+            sender_chan,
+        } => {
+            let _span = request_span(&peer, "stat_share", trace_id).entered();
+            debug!("Sending request to stat share {}.", key);
+            let request_id = eventloop
+                .swarm
+                .behaviour_mut()
+                .request_response
+                .send_request(
+                    &peer,
+                    Request::StatShare(StatShareRequest {
+                        key,
+                        peer: peer.into(),
+                        sender: sender.into(),
+                        trace_id,
+                    }),
+                );
+            eventloop.pending_stat_share.insert(request_id, sender_chan);
+            debug!("Sent request to stat share");
+        }
+        Command::RespondStatShare { status, channel } => {
+            respond(eventloop, channel, Response::StatShare(StatShareResponse { status }));
+        }
+        Command::RespondInvalidRequest { reason, channel } => {
+            // the peer sending a malformed request may well be gone already
+            let _ = eventloop
+                .swarm
+                .behaviour_mut()
+                .request_response
+                .send_response(
+                    channel,
+                    Response::InvalidRequest(InvalidRequestResponse { reason }),
+                );
+        }
+        Command::RespondRateLimited {
+            retry_after_millis,
+            channel,
+        } => {
+            // a peer over its rate limit may not wait for the answer
+            let _ = eventloop
                 .swarm
                 .behaviour_mut()
                 .request_response
                 .send_response(
                     channel,
-                    Response::RefreshShares(RefreshShareResponse { success }),
-                )
-                .expect("Connection to peer to be still open.");
+                    Response::RateLimited(RateLimitedResponse { retry_after_millis }),
+                );
+        }
+        Command::RespondBusy { reason, channel } => {
+            // a peer turned away may not wait for the answer
+            let _ = eventloop
+                .swarm
+                .behaviour_mut()
+                .request_response
+                .send_response(channel, Response::Busy(BusyResponse { reason }));
+        }
+        Command::RequestAccess {
+            key,
+            peer,
+            sender,
+            reader,
+            grant,
+            trace_id,
+            sender_chan,
+        } => {
+            let operation = if grant { "grant_access" } else { "revoke_access" };
+            let _span = request_span(&peer, operation, trace_id).entered();
+            debug!("Sending request to change access to share {}.", key);
+            let access = AccessRequest {
+                key,
+                peer: peer.into(),
+                sender: sender.into(),
+                reader: reader.into(),
+                trace_id,
+            };
+            let request = if grant {
+                Request::GrantAccess(access)
+            } else {
+                Request::RevokeAccess(access)
+            };
+            let request_id = eventloop
+                .swarm
+                .behaviour_mut()
+                .request_response
+                .send_request(&peer, request);
+            eventloop.pending_access.insert(request_id, sender_chan);
+            debug!("Sent request to change access");
+        }
+        Command::RespondAccess { result, channel } => {
+            let (success, reason, failure) = response_status(&result);
+            respond(
+                eventloop,
+                channel,
+                Response::Access(AccessResponse {
+                    success,
+                    reason,
+                    failure,
+                }),
+            );
+        }
+        Command::RequestTransferOwnership {
+            peer,
+            request,
+            sender_chan,
+        } => {
+            let _span = request_span(&peer, "transfer_ownership", request.trace_id).entered();
+            debug!("Sending request to transfer share {}.", request.key);
+            let request_id = eventloop
+                .swarm
+                .behaviour_mut()
+                .request_response
+                .send_request(&peer, Request::TransferOwnership(request));
+            eventloop.pending_transfer.insert(request_id, sender_chan);
+            debug!("Sent request to transfer share");
+        }
+        Command::RespondTransferOwnership { result, channel } => {
+            let (success, reason, failure) = response_status(&result);
+            respond(
+                eventloop,
+                channel,
+                Response::TransferOwnership(TransferOwnershipResponse {
+                    success,
+                    reason,
+                    failure,
+                }),
+            );
+        }
+        Command::Publish { message, sender } => {
+            let published = match message.to_bytes() {
+                Ok(data) => eventloop
+                    .swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .publish(IdentTopic::new(GOSSIP_TOPIC), data)
+                    .map(|_| ())
+                    .map_err(ClientError::from),
+                Err(e) => Err(e.into()),
+            };
+            let _ = sender.send(published);
+        }
+        Command::PublishStatus { mut status, sender } => {
+            // only the swarm knows whether the provider found an external address
+            status.reachable = eventloop.swarm.external_addresses().next().is_some();
+            let published = match status.to_bytes() {
+                Ok(data) => eventloop
+                    .swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .publish(IdentTopic::new(HEALTH_TOPIC), data)
+                    .map(|_| ())
+                    .map_err(ClientError::from),
+                Err(e) => Err(e.into()),
+            };
+            let _ = sender.send(published);
+        }
+        Command::ProviderStatuses { sender } => {
+            let now = Instant::now();
+            eventloop
+                .provider_statuses
+                .retain(|_, (_, expires_at)| *expires_at > now);
+            let mut statuses: Vec<ProviderStatus> = eventloop
+                .provider_statuses
+                .values()
+                .map(|(status, _)| status.clone())
+                .collect();
+            statuses.sort_by(|a, b| a.peer.cmp(&b.peer));
+            let _ = sender.send(statuses);
+        }
+        Command::ListenAddrs { sender } => {
+            let _ = sender.send(eventloop.swarm.listeners().cloned().collect());
+        }
+        Command::AddExternalAddress { addr, sender } => {
+            eventloop.swarm.add_external_address(addr);
+            let _ = sender.send(());
+        }
+        Command::BlockPeer { peer, sender } => {
+            eventloop.swarm.behaviour_mut().blocked.block_peer(peer);
+            let _ = sender.send(());
+        }
+        Command::AllowPeer { peer, sender } => {
+            eventloop.swarm.behaviour_mut().blocked.unblock_peer(peer);
+            let _ = sender.send(());
+        }
+        Command::ConnectedPeers { sender } => {
+            let _ = sender.send(eventloop.swarm.connected_peers().copied().collect());
+        }
+        Command::NetworkInfo { sender } => {
+            let external_addrs: Vec<Multiaddr> =
+                eventloop.swarm.external_addresses().cloned().collect();
+            let routing_table_size = eventloop
+                .swarm
+                .behaviour_mut()
+                .kademlia
+                .kbuckets()
+                .map(|bucket| bucket.num_entries())
+                .sum();
+            let _ = sender.send(NetworkInfo {
+                peer_id: *eventloop.swarm.local_peer_id(),
+                agent_version: AGENT_VERSION.to_string(),
+                listen_addrs: eventloop.swarm.listeners().cloned().collect(),
+                connected_peers: eventloop.swarm.connected_peers().count(),
+                routing_table_size,
+                // as in the health status, a node with an external address is reachable
+                reachable: !external_addrs.is_empty(),
+                external_addrs,
+            });
+        }
+        Command::PendingRequests { sender } => {
+            let _ = sender.send(eventloop.pending_counts());
+        }
+        // `EventLoop::run` stops before handing a shutdown over, there is nothing left to do
+        Command::Shutdown { sender } => {
+            let _ = sender.send(());
         }
     }
 }
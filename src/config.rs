@@ -1,59 +1,1825 @@
-use config::{Config, ConfigError};
-use libp2p::Multiaddr;
-use serde::{Serialize, Deserialize};
+use config::Config;
+use libp2p::identity::Keypair;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserializer, Serializer, Serialize, Deserialize};
 use tracing::debug;
-use std::{path::PathBuf, fs};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io;
+use std::str::FromStr;
+use std::time::Duration;
+use std::{path::{Path, PathBuf}, fs};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The file in the configuration directory holding the node's identity key, protobuf-encoded.
+pub const KEY_FILE: &str = "identity.key";
+
+/// The file in the configuration directory holding the identity key replaced by
+/// `ShardConfig::replace_key`, protobuf-encoded.
+pub const RETIRED_KEY_FILE: &str = "identity.key.retired";
+
+/// The file in the configuration directory holding the configuration.
+pub const CONFIG_FILE: &str = "conf.toml";
+
+/// The file in the configuration directory held while `conf.toml` is saved, so that processes
+/// saving it at the same time do not lose each other's changes.
+pub const LOCK_FILE: &str = "conf.toml.lock";
+
+/// How old a lock file is taken to have been left behind by a process that died while saving,
+/// and is removed.
+const STALE_LOCK: Duration = Duration::from_secs(10);
+
+/// The keys of `conf.toml`, which `ShardConfig::set` takes.
+pub const SETTINGS: [&str; 10] = [
+    "bootstrapper",
+    "bootstrappers",
+    "db_path",
+    "refresh_interval_secs",
+    "listen_addresses",
+    "external_addresses",
+    "network_id",
+    "request_timeout_secs",
+    "denied_peers",
+    "allowed_peers",
+];
+
+/// The directory in the configuration directory holding a directory per named profile, laid out
+/// like the configuration directory itself.
+pub const PROFILES_DIR: &str = "profiles";
+
+/// The profile kept in the configuration directory itself, as every configuration was before
+/// profiles existed.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Errors raised loading the configuration or the identity key, with the file or variable at
+/// fault so that it can be fixed.
+///
+/// # Variants
+///
+/// * `Io` - A file or directory could not be accessed; carries what was being done, the path
+///   and the error.
+/// * `Malformed` - `conf.toml` could not be parsed, or holds an invalid setting; carries the
+///   path and why.
+/// * `CorruptKey` - The identity key file does not hold a key; carries the path and why.
+/// * `InvalidEnv` - An environment variable holds a setting that does not parse; carries the
+///   variable and why.
+/// * `InvalidProfile` - A profile name cannot name a directory; carries the name and why.
+/// * `InvalidSetting` - A setting given to be saved is unknown or invalid; carries the key and
+///   why.
+/// * `NotSaved` - The configuration was read from the environment, and has no file to save to.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        error: io::Error,
+    },
+    Malformed(PathBuf, String),
+    CorruptKey(PathBuf, String),
+    InvalidEnv(String, String),
+    InvalidProfile(String, String),
+    InvalidSetting(String, String),
+    NotSaved,
+}
+
+impl ConfigError {
+    /// Wraps `error`, raised doing `action` on `path`.
+    fn io(action: &'static str, path: &Path, error: io::Error) -> Self {
+        ConfigError::Io {
+            action,
+            path: path.to_path_buf(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { action, path, error } => {
+                write!(f, "cannot {} {}: {}", action, path.display(), error)
+            }
+            ConfigError::Malformed(path, reason) => {
+                write!(f, "config file {} is malformed: {}", path.display(), reason)
+            }
+            ConfigError::CorruptKey(path, reason) => {
+                write!(f, "config key file {} is corrupt: {}", path.display(), reason)
+            }
+            ConfigError::InvalidEnv(name, reason) => write!(f, "invalid {}: {}", name, reason),
+            ConfigError::InvalidProfile(name, reason) => {
+                write!(f, "invalid profile {:?}: {}", name, reason)
+            }
+            ConfigError::InvalidSetting(key, reason) => write!(f, "invalid {}: {}", key, reason),
+            ConfigError::NotSaved => {
+                write!(f, "the configuration was read from the environment and has no file")
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// The environment variable naming the configuration directory, used without `--config`.
+pub const CONFIG_DIR_ENV: &str = "SHARD_CONFIG_DIR";
+
+/// The environment variable naming the profile to run as, used without `--profile`.
+pub const PROFILE_ENV: &str = "SHARD_PROFILE";
+
+/// The environment variable naming a bootstrapper to dial instead of the configured ones.
+pub const BOOTSTRAPPER_ENV: &str = "SHARD_BOOTSTRAPPER";
+
+/// The environment variable listing further bootstrappers, separated by commas, dialled along
+/// with `BOOTSTRAPPER_ENV` instead of the configured ones.
+pub const BOOTSTRAPPERS_ENV: &str = "SHARD_BOOTSTRAPPERS";
+
+/// The environment variable holding the identity key when the configuration is read from the
+/// environment: either the protobuf-encoded key in hex, or the path of a file holding it.
+pub const KEY_ENV: &str = "SHARD_KEY";
+
+/// The environment variable that, set to `1`, reads the configuration from the environment
+/// alone, as `--no-config` does.
+pub const NO_CONFIG_DIR_ENV: &str = "SHARD_NO_CONFIG_DIR";
+
+/// The environment variable naming the database of a provider, used without `--db-path`.
+pub const DB_PATH_ENV: &str = "SHARD_DB_PATH";
+
+/// The environment variable setting the refresh interval of a provider in seconds, used without
+/// `--refresh-interval`.
+pub const REFRESH_INTERVAL_ENV: &str = "SHARD_REFRESH_INTERVAL_SECS";
+
+/// The environment variable listing the addresses to listen on, separated by commas, used
+/// without `--listen-address`.
+pub const LISTEN_ADDRESSES_ENV: &str = "SHARD_LISTEN_ADDRESSES";
+
+/// The environment variable listing the addresses the node is reachable at, separated by
+/// commas.
+pub const EXTERNAL_ADDRESSES_ENV: &str = "SHARD_EXTERNAL_ADDRESSES";
+
+/// The environment variable naming the network to join, used without `--network-id`.
+pub const NETWORK_ID_ENV: &str = "SHARD_NETWORK_ID";
+
+/// The environment variable setting how long to wait on the network in seconds, used without
+/// `--timeout`.
+pub const REQUEST_TIMEOUT_ENV: &str = "SHARD_REQUEST_TIMEOUT_SECS";
+
+/// Resolves a setting that can be given in several places. Every setting is resolved by this
+/// one function, so that they all follow the same precedence: the command line flag, then the
+/// environment, then the configuration file, then the built-in default.
+///
+/// # Arguments
+///
+/// * `flag` - The value given on the command line, if any.
+/// * `env` - The value read from the environment, if any.
+/// * `file` - The value read from `conf.toml`, if any.
+/// * `default` - The built-in default, or `None` when the setting is off by default.
+///
+/// # Returns
+///
+/// The value of the first of them that is set, or `None` if none is.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::config::resolve;
+///
+/// assert_eq!(resolve(None, Some(60), Some(600), Some(1800)), Some(60));
+/// assert_eq!(resolve(None, None, None, Some(1800)), Some(1800));
+/// ```
+pub fn resolve<T>(
+    flag: Option<T>,
+    env: Option<T>,
+    file: Option<T>,
+    default: Option<T>,
+) -> Option<T> {
+    flag.or(env).or(file).or(default)
+}
+
+/// Reads a setting from the environment variable `name`.
+///
+/// # Returns
+///
+/// The value parsed, or `None` if the variable is not set or empty.
+///
+/// # Errors
+///
+/// Returns an error naming the variable if its value does not parse.
+pub fn env_setting<T>(name: &str) -> Result<Option<T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    parse_setting(name, std::env::var(name).ok())
+}
+
+/// Reads a list of settings separated by commas from the environment variable `name`.
+///
+/// # Returns
+///
+/// The values parsed, or `None` if the variable is not set or empty.
+///
+/// # Errors
+///
+/// Returns an error naming the variable if one of its values does not parse.
+pub fn env_list<T>(name: &str) -> Result<Option<Vec<T>>, ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    parse_list(name, std::env::var(name).ok())
+}
+
+/// Reads whether the switch held by the environment variable `name` is on.
+///
+/// # Returns
+///
+/// `true` for `1` or `true`, and `false` for `0`, `false` or when the variable is not set.
+///
+/// # Errors
+///
+/// Returns an error naming the variable if it holds anything else.
+pub fn env_flag(name: &str) -> Result<bool, ConfigError> {
+    parse_flag(name, std::env::var(name).ok())
+}
+
+/// Parses the `value` of the environment variable `name` as a switch.
+fn parse_flag(name: &str, value: Option<String>) -> Result<bool, ConfigError> {
+    match value.as_deref().map(str::trim) {
+        None | Some("") | Some("0") | Some("false") => Ok(false),
+        Some("1") | Some("true") => Ok(true),
+        Some(value) => {
+            let reason = format!("{:?}: expected 1 or 0", value);
+            Err(ConfigError::InvalidEnv(name.to_string(), reason))
+        }
+    }
+}
+
+/// Parses the `value` of `KEY_ENV`: the path of a file holding the protobuf-encoded key, or
+/// the key itself in hex.
+fn parse_key(value: &str) -> Result<Keypair, ConfigError> {
+    let invalid = |reason: String| ConfigError::InvalidEnv(KEY_ENV.to_string(), reason);
+    let value = value.trim();
+    let path = Path::new(value);
+    let bytes = match path.is_file() {
+        true => fs::read(path).map_err(|e| invalid(format!("cannot read {}: {}", value, e)))?,
+        false => hex::decode(value).map_err(|e| {
+            invalid(format!("neither a key file nor a hex-encoded key: {}", e))
+        })?,
+    };
+    Keypair::from_protobuf_encoding(&bytes).map_err(|e| invalid(format!("not a key: {}", e)))
+}
+
+/// Parses the `value` of the environment variable `name` as a list separated by commas.
+fn parse_list<T>(name: &str, value: Option<String>) -> Result<Option<Vec<T>>, ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match value.filter(|value| !value.trim().is_empty()) {
+        Some(value) => value
+            .split(',')
+            .filter(|item| !item.trim().is_empty())
+            .map(|item| {
+                item.trim().parse().map_err(|e| {
+                    ConfigError::InvalidEnv(name.to_string(), format!("{:?}: {}", item.trim(), e))
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Parses the `value` of the environment variable `name`, treating an empty value as unset.
+fn parse_setting<T>(name: &str, value: Option<String>) -> Result<Option<T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match value.filter(|value| !value.trim().is_empty()) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| ConfigError::InvalidEnv(name.to_string(), format!("{:?}: {}", value, e))),
+        None => Ok(None),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ShardConfig {
     pub bootstrapper: Option<Multiaddr>,
+    /// Further bootstrappers, dialled along with `bootstrapper`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bootstrappers: Vec<Multiaddr>,
+    /// The database providers persist shares to, when `--db-path` is not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub db_path: Option<String>,
+    /// The refresh interval of providers in seconds, when `--refresh-interval` is not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_interval_secs: Option<u64>,
+    /// The addresses to listen on, when `--listen-address` is not given.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub listen_addresses: Vec<Multiaddr>,
+    /// The addresses the node is reachable at, announced to its peers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub external_addresses: Vec<Multiaddr>,
+    /// The network to join, when `--network-id` is not given. Nodes only talk to nodes of the
+    /// same network.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_id: Option<String>,
+    /// How long to wait on the network in seconds, when `--timeout` is not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_timeout_secs: Option<u64>,
+    /// The peers whose connections are refused, unless they are also in `allowed_peers`.
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_peer_ids",
+        deserialize_with = "deserialize_peer_ids"
+    )]
+    pub denied_peers: Vec<PeerId>,
+    /// The peers never refused, even when denied, so that a peer can be let back in without
+    /// editing a deny list shared between nodes.
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_peer_ids",
+        deserialize_with = "deserialize_peer_ids"
+    )]
+    pub allowed_peers: Vec<PeerId>,
+    /// The directory the configuration was loaded from, which also holds the identity key.
+    #[serde(skip)]
+    pub dir: PathBuf,
+    /// Whether the configuration was read from the environment alone, with `from_env`. Nothing
+    /// is then read from or written to `dir`.
+    #[serde(skip)]
+    pub from_env: bool,
+    /// The protobuf-encoded identity key read from `KEY_ENV`, which replaces the key file of
+    /// `dir` when the configuration is read from the environment.
+    #[serde(skip)]
+    pub env_key: Option<Vec<u8>>,
 }
 
 impl ShardConfig {
+    /// Loads the configuration from the default directory or, when `NO_CONFIG_DIR_ENV` is set,
+    /// from the environment alone.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration cannot be loaded.
     pub fn new() -> Result<Self, ConfigError> {
-        let config_path = PathBuf::from(".shard/conf.toml");
+        match env_flag(NO_CONFIG_DIR_ENV)? {
+            true => Self::from_env(),
+            false => Self::load(&Self::default_dir()),
+        }
+    }
+
+    /// Builds the configuration from the `SHARD_` environment variables alone, for deployments
+    /// with no writable configuration directory. Nothing is written to the filesystem: the
+    /// identity key is read from `KEY_ENV`, and none is generated without it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the variable at fault if one does not parse.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Builds the configuration from the variables `var` looks up.
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let key = var(KEY_ENV).filter(|value| !value.trim().is_empty());
+        let env_key = match key {
+            Some(value) => {
+                let key = parse_key(&value)?;
+                let encoded = key
+                    .to_protobuf_encoding()
+                    .map_err(|e| ConfigError::InvalidEnv(KEY_ENV.to_string(), e.to_string()))?;
+                Some(encoded)
+            }
+            None => None,
+        };
+        let network_id: Option<String> = parse_setting(NETWORK_ID_ENV, var(NETWORK_ID_ENV))?;
+        if let Some(id) = &network_id {
+            crate::network::check_network_id(id)
+                .map_err(|reason| ConfigError::InvalidEnv(NETWORK_ID_ENV.to_string(), reason))?;
+        }
+        let list = |name| parse_list(name, var(name)).map(Option::unwrap_or_default);
+        Ok(ShardConfig {
+            bootstrapper: parse_setting(BOOTSTRAPPER_ENV, var(BOOTSTRAPPER_ENV))?,
+            bootstrappers: list(BOOTSTRAPPERS_ENV)?,
+            db_path: parse_setting(DB_PATH_ENV, var(DB_PATH_ENV))?,
+            refresh_interval_secs: parse_setting(
+                REFRESH_INTERVAL_ENV,
+                var(REFRESH_INTERVAL_ENV),
+            )?,
+            listen_addresses: list(LISTEN_ADDRESSES_ENV)?,
+            external_addresses: list(EXTERNAL_ADDRESSES_ENV)?,
+            network_id,
+            request_timeout_secs: parse_setting(REQUEST_TIMEOUT_ENV, var(REQUEST_TIMEOUT_ENV))?,
+            // only reloaded from conf.toml, which there is none of here
+            denied_peers: Vec::new(),
+            allowed_peers: Vec::new(),
+            dir: PathBuf::new(),
+            from_env: true,
+            env_key,
+        })
+    }
+
+    /// The configuration directory to use, resolved with `resolve` from the `--config` flag,
+    /// `CONFIG_DIR_ENV` and `default_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `CONFIG_DIR_ENV` holds an invalid path.
+    pub fn resolve_dir(flag: Option<PathBuf>) -> Result<PathBuf, ConfigError> {
+        let env = env_setting(CONFIG_DIR_ENV)?;
+        Ok(resolve(flag, env, None, Some(Self::default_dir())).unwrap_or_default())
+    }
+
+    /// The configuration directory used when none is given: `.shard` in the home directory, or
+    /// in the working directory when there is no home directory.
+    pub fn default_dir() -> PathBuf {
+        std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_default()
+            .join(".shard")
+    }
+
+    /// The directory of the profile `name` in the configuration directory `dir`: `dir` itself for
+    /// `DEFAULT_PROFILE`, and a directory of `PROFILES_DIR` for any other.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` is not made of ASCII letters, digits, `-`, `_` and `.`, or
+    /// starts with a `.`.
+    pub fn profile_dir(dir: &Path, name: &str) -> Result<PathBuf, ConfigError> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+        if name.is_empty() || name.starts_with('.') || !name.chars().all(valid) {
+            let reason = "use letters, digits, '-', '_' and '.', not starting with '.'";
+            return Err(ConfigError::InvalidProfile(name.to_string(), reason.to_string()));
+        }
+        Ok(match name {
+            DEFAULT_PROFILE => dir.to_path_buf(),
+            name => dir.join(PROFILES_DIR).join(name),
+        })
+    }
+
+    /// Loads the profile `name` of the configuration directory `dir`, creating it on first use.
+    /// The default profile is the configuration and key kept in `dir` itself, so that a
+    /// directory laid out before profiles existed is adopted as it is, and older releases
+    /// still read it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is invalid, or the profile cannot be loaded.
+    pub fn load_profile(dir: &Path, name: &str) -> Result<Self, ConfigError> {
+        Self::load(&Self::profile_dir(dir, name)?)
+    }
+
+    /// Lists the profiles of the configuration directory `dir`, without creating any.
+    ///
+    /// # Returns
+    ///
+    /// The default profile first if `dir` holds a configuration or a key, then every other
+    /// profile by name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the profiles cannot be listed.
+    pub fn profiles(dir: &Path) -> Result<Vec<String>, ConfigError> {
+        let mut profiles = Vec::new();
+        if dir.join(CONFIG_FILE).exists() || dir.join(KEY_FILE).exists() {
+            profiles.push(DEFAULT_PROFILE.to_string());
+        }
+        let profiles_dir = dir.join(PROFILES_DIR);
+        let entries = match fs::read_dir(&profiles_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(profiles),
+            Err(e) => return Err(ConfigError::io("list", &profiles_dir, e)),
+        };
+        let mut named = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| ConfigError::io("list", &profiles_dir, e))?;
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            let is_dir = entry.file_type().map(|kind| kind.is_dir()).unwrap_or(false);
+            if is_dir && name != DEFAULT_PROFILE && Self::profile_dir(dir, &name).is_ok() {
+                named.push(name);
+            }
+        }
+        named.sort();
+        profiles.extend(named);
+        Ok(profiles)
+    }
+
+    /// Reads the identity key of the profile `name` of the configuration directory `dir`,
+    /// without creating the profile.
+    ///
+    /// # Returns
+    ///
+    /// The key, or `None` if the profile has none yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is invalid, or the key file cannot be read.
+    pub fn profile_key(dir: &Path, name: &str) -> Result<Option<Keypair>, ConfigError> {
+        read_key(&Self::profile_dir(dir, name)?.join(KEY_FILE))
+    }
+
+    /// Reads the protobuf-encoded identity key of the key file `path`, such as the `KEY_FILE` of
+    /// another configuration directory.
+    ///
+    /// # Returns
+    ///
+    /// The key, or `None` if there is no such file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the file if it cannot be read or does not hold a key.
+    pub fn key_file(path: &Path) -> Result<Option<Keypair>, ConfigError> {
+        read_key(path)
+    }
+
+    /// The peers to block: every denied peer that is not also allowed.
+    pub fn blocked_peers(&self) -> HashSet<PeerId> {
+        let allowed: HashSet<&PeerId> = self.allowed_peers.iter().collect();
+        self.denied_peers
+            .iter()
+            .filter(|peer| !allowed.contains(peer))
+            .copied()
+            .collect()
+    }
+
+    /// Compares the configuration with the one it replaces on a reload.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous` - The configuration running until now.
+    ///
+    /// # Returns
+    ///
+    /// What changed: the bootstrappers added and removed, the peers to block and to allow again,
+    /// and the settings changed that are only read at startup.
+    pub fn changes_since(&self, previous: &ShardConfig) -> ConfigChanges {
+        let (old_addrs, new_addrs) = (previous.bootstrap_addrs(), self.bootstrap_addrs());
+        let (old_blocked, new_blocked) = (previous.blocked_peers(), self.blocked_peers());
+        let mut needs_restart = Vec::new();
+        let settings = [
+            ("db_path", previous.db_path != self.db_path),
+            ("refresh_interval_secs", previous.refresh_interval_secs != self.refresh_interval_secs),
+            ("listen_addresses", previous.listen_addresses != self.listen_addresses),
+            ("external_addresses", previous.external_addresses != self.external_addresses),
+            ("network_id", previous.network_id != self.network_id),
+            ("request_timeout_secs", previous.request_timeout_secs != self.request_timeout_secs),
+        ];
+        for (key, changed) in settings {
+            if changed {
+                needs_restart.push(key);
+            }
+        }
+        let mut blocked: Vec<PeerId> = new_blocked.difference(&old_blocked).copied().collect();
+        let mut allowed: Vec<PeerId> = old_blocked.difference(&new_blocked).copied().collect();
+        blocked.sort();
+        allowed.sort();
+        ConfigChanges {
+            added_bootstrappers: new_addrs
+                .iter()
+                .filter(|addr| !old_addrs.contains(addr))
+                .cloned()
+                .collect(),
+            removed_bootstrappers: old_addrs
+                .into_iter()
+                .filter(|addr| !new_addrs.contains(addr))
+                .collect(),
+            blocked,
+            allowed,
+            needs_restart,
+        }
+    }
+
+    /// Every configured bootstrapper, `bootstrapper` first, without duplicates.
+    pub fn bootstrap_addrs(&self) -> Vec<Multiaddr> {
+        let mut addrs: Vec<Multiaddr> = Vec::new();
+        for addr in self.bootstrapper.iter().chain(&self.bootstrappers) {
+            if !addrs.contains(addr) {
+                addrs.push(addr.clone());
+            }
+        }
+        addrs
+    }
+
+    /// Loads `conf.toml` from `dir`, writing the default configuration there first if it does
+    /// not exist yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the path at fault if the directory or the file cannot be created
+    /// or read, or the file is malformed.
+    pub fn load(dir: &Path) -> Result<Self, ConfigError> {
+        let config_path = dir.join(CONFIG_FILE);
 
         if !config_path.exists() {
-            if let Some(dir) = config_path.parent() {
-                if !dir.exists() {
-                    fs::create_dir_all(dir).unwrap();
-                }
+            fs::create_dir_all(dir).map_err(|e| ConfigError::io("create", dir, e))?;
+            let toml = toml::to_string_pretty(&ShardConfig::default())
+                .map_err(|e| ConfigError::Malformed(config_path.clone(), e.to_string()))?;
+            // written whole under another name first, so that a process starting at the same
+            // time never reads it half written, and keeps the one written first
+            match write_new_file(&config_path, toml.as_bytes(), false) {
+                Err(ConfigError::Io { error, .. })
+                    if error.kind() == io::ErrorKind::AlreadyExists => {}
+                result => result?,
             }
-    
-            let toml = toml::to_string_pretty(&ShardConfig::default()).map_err(|err| ConfigError::Foreign(Box::new(err)))?;
-            fs::write(&config_path, toml).unwrap();
         }
 
-        debug!("📝 Loaded config at path: {:?}", config_path);
+        debug!(path = %config_path.display(), "Loaded config.");
 
+        // the environment is not a source here: it takes precedence over the file setting by
+        // setting, through `resolve`
+        let malformed =
+            |e: config::ConfigError| ConfigError::Malformed(config_path.clone(), e.to_string());
         let settings = Config::builder()
-            // Add in `./.shard/conf.toml`
-            .add_source(config::File::with_name(".shard/conf"))
-            // Add in settings from the environment (with a prefix of APP)
-            // Eg.. `SHARD_DEBUG=1 ./target/shard` would set the `debug` key
-            .add_source(config::Environment::with_prefix("SHARD"))
+            // Add in `<dir>/conf.toml`
+            .add_source(config::File::from(config_path.as_path()))
             .build()
-            .unwrap();
+            .map_err(malformed)?;
 
-        let my_config: ShardConfig = settings.try_into()?;
+        let mut my_config: ShardConfig = settings.try_into().map_err(malformed)?;
+        my_config.dir = dir.to_path_buf();
         Ok(my_config)
     }
 
+    /// Saves the configuration to `conf.toml` in its directory. The file is written whole
+    /// under another name and then renamed, so that a crash never leaves it half written, while
+    /// the lock file is held.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration was read from the environment, or the file cannot
+    /// be written.
+    pub fn save(&self) -> Result<(), ConfigError> {
+        let _lock = self.lock()?;
+        self.save_locked()
+    }
+
+    /// Changes the configuration with `change` and saves it, holding the lock file throughout.
+    /// The configuration is reloaded from its file first, so that the changes another process
+    /// saved meanwhile are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `change` - Changes the configuration, with the setters such as `add_bootstrapper`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration was read from the environment, cannot be
+    /// reloaded or saved, or `change` fails, in which case nothing is saved.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use shard::config::ShardConfig;
+    ///
+    /// let dir = std::env::temp_dir().join(format!("shard-doc-{}", rand::random::<u64>()));
+    /// let mut config = ShardConfig::load(&dir)?;
+    /// config.update(|config| config.set_refresh_interval(Some(600)))?;
+    /// assert_eq!(ShardConfig::load(&dir)?.refresh_interval_secs, Some(600));
+    /// # std::fs::remove_dir_all(dir)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn update<F>(&mut self, change: F) -> Result<(), ConfigError>
+    where
+        F: FnOnce(&mut ShardConfig) -> Result<(), ConfigError>,
+    {
+        let _lock = self.lock()?;
+        let mut updated = Self::load(&self.dir)?;
+        change(&mut updated)?;
+        updated.save_locked()?;
+        *self = updated;
+        Ok(())
+    }
+
+    /// Takes the lock file of the configuration directory, removing a stale one.
+    fn lock(&self) -> Result<ConfigLock, ConfigError> {
+        if self.from_env {
+            return Err(ConfigError::NotSaved);
+        }
+        fs::create_dir_all(&self.dir).map_err(|e| ConfigError::io("create", &self.dir, e))?;
+        ConfigLock::acquire(&self.dir.join(LOCK_FILE))
+    }
+
+    /// Writes `conf.toml`, with the lock file held.
+    fn save_locked(&self) -> Result<(), ConfigError> {
+        let path = self.dir.join(CONFIG_FILE);
+        let toml = toml::to_string_pretty(self)
+            .map_err(|e| ConfigError::Malformed(path.clone(), e.to_string()))?;
+        write_new_file(&path, toml.as_bytes(), true)?;
+        debug!(path = %path.display(), "Saved config.");
+        Ok(())
+    }
+
+    /// Sets the setting `key` of `conf.toml` from its text, as `shard config set` takes it. A
+    /// list is given separated by commas, and an empty value unsets the setting.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the key if it is not one of `SETTINGS`, or the value is invalid.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let value = Some(value.trim()).filter(|value| !value.is_empty());
+        match key {
+            "bootstrapper" => self.set_bootstrapper(one_setting(key, value)?),
+            "bootstrappers" => {
+                self.bootstrappers.clear();
+                for addr in list_setting(key, value)? {
+                    self.add_bootstrapper(addr)?;
+                }
+                Ok(())
+            }
+            "db_path" => self.set_db_path(value.map(str::to_string)),
+            "refresh_interval_secs" => self.set_refresh_interval(one_setting(key, value)?),
+            "listen_addresses" => self.set_listen_addresses(list_setting(key, value)?),
+            "external_addresses" => self.set_external_addresses(list_setting(key, value)?),
+            "network_id" => self.set_network_id(value.map(str::to_string)),
+            "request_timeout_secs" => self.set_request_timeout(one_setting(key, value)?),
+            "denied_peers" => self.set_denied_peers(list_setting(key, value)?),
+            "allowed_peers" => self.set_allowed_peers(list_setting(key, value)?),
+            _ => {
+                let reason = format!("unknown setting, expected one of {}", SETTINGS.join(", "));
+                Err(ConfigError::InvalidSetting(key.to_string(), reason))
+            }
+        }
+    }
+
+    /// Sets the first bootstrapper dialled, or unsets it with `None`. It is removed from
+    /// `bootstrappers` if it was there.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address does not end with the bootstrapper's peer id.
+    pub fn set_bootstrapper(&mut self, addr: Option<Multiaddr>) -> Result<(), ConfigError> {
+        if let Some(addr) = &addr {
+            check_bootstrapper("bootstrapper", addr)?;
+            self.bootstrappers.retain(|configured| configured != addr);
+        }
+        self.bootstrapper = addr;
+        Ok(())
+    }
+
+    /// Adds a bootstrapper to `bootstrappers`, unless it is configured already.
+    ///
+    /// # Returns
+    ///
+    /// Whether the bootstrapper was added.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address does not end with the bootstrapper's peer id.
+    pub fn add_bootstrapper(&mut self, addr: Multiaddr) -> Result<bool, ConfigError> {
+        check_bootstrapper("bootstrappers", &addr)?;
+        if self.bootstrap_addrs().contains(&addr) {
+            return Ok(false);
+        }
+        self.bootstrappers.push(addr);
+        Ok(true)
+    }
+
+    /// Removes a bootstrapper, whether it is `bootstrapper` or one of `bootstrappers`.
+    ///
+    /// # Returns
+    ///
+    /// Whether the bootstrapper was configured.
+    pub fn remove_bootstrapper(&mut self, addr: &Multiaddr) -> bool {
+        let configured = self.bootstrap_addrs().contains(addr);
+        if self.bootstrapper.as_ref() == Some(addr) {
+            self.bootstrapper = None;
+        }
+        self.bootstrappers.retain(|configured| configured != addr);
+        configured
+    }
+
+    /// Sets the database providers persist shares to, or unsets it with `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path is empty.
+    pub fn set_db_path(&mut self, path: Option<String>) -> Result<(), ConfigError> {
+        if path.as_deref().is_some_and(|path| path.trim().is_empty()) {
+            return Err(ConfigError::InvalidSetting("db_path".to_string(), "empty".to_string()));
+        }
+        self.db_path = path;
+        Ok(())
+    }
+
+    /// Sets the refresh interval of providers in seconds, or unsets it with `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the interval is zero.
+    pub fn set_refresh_interval(&mut self, secs: Option<u64>) -> Result<(), ConfigError> {
+        self.refresh_interval_secs = positive("refresh_interval_secs", secs)?;
+        Ok(())
+    }
+
+    /// Sets how long to wait on the network in seconds, or unsets it with `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the timeout is zero.
+    pub fn set_request_timeout(&mut self, secs: Option<u64>) -> Result<(), ConfigError> {
+        self.request_timeout_secs = positive("request_timeout_secs", secs)?;
+        Ok(())
+    }
+
+    /// Sets the network to join, or the default one with `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the id cannot name a network (see `check_network_id`).
+    pub fn set_network_id(&mut self, network_id: Option<String>) -> Result<(), ConfigError> {
+        if let Some(id) = &network_id {
+            crate::network::check_network_id(id)
+                .map_err(|reason| ConfigError::InvalidSetting("network_id".to_string(), reason))?;
+        }
+        self.network_id = network_id;
+        Ok(())
+    }
+
+    /// Sets the addresses to listen on, without duplicates.
+    pub fn set_listen_addresses(&mut self, addrs: Vec<Multiaddr>) -> Result<(), ConfigError> {
+        self.listen_addresses = deduplicated(addrs);
+        Ok(())
+    }
+
+    /// Sets the addresses the node is reachable at, without duplicates.
+    pub fn set_external_addresses(&mut self, addrs: Vec<Multiaddr>) -> Result<(), ConfigError> {
+        self.external_addresses = deduplicated(addrs);
+        Ok(())
+    }
+
+    /// Sets the peers whose connections are refused, without duplicates.
+    pub fn set_denied_peers(&mut self, peers: Vec<PeerId>) -> Result<(), ConfigError> {
+        self.denied_peers = deduplicated(peers);
+        Ok(())
+    }
+
+    /// Sets the peers never refused, without duplicates.
+    pub fn set_allowed_peers(&mut self, peers: Vec<PeerId>) -> Result<(), ConfigError> {
+        self.allowed_peers = deduplicated(peers);
+        Ok(())
+    }
+
+    /// Reads the identity key persisted in the configuration directory, or the one given in
+    /// `KEY_ENV` when the configuration was read from the environment.
+    ///
+    /// # Returns
+    ///
+    /// The key, or `None` if none has been persisted or given yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the key file if it cannot be read or does not hold a key.
+    pub fn key(&self) -> Result<Option<Keypair>, ConfigError> {
+        if self.from_env {
+            return match &self.env_key {
+                Some(encoded) => Keypair::from_protobuf_encoding(encoded)
+                    .map(Some)
+                    .map_err(|e| ConfigError::InvalidEnv(KEY_ENV.to_string(), e.to_string())),
+                None => Ok(None),
+            };
+        }
+        read_key(&self.dir.join(KEY_FILE))
+    }
+
+    /// Reads the identity key persisted in the configuration directory, generating and
+    /// persisting a new one on first use. The key file is only readable by its owner. When
+    /// another process persists a key first, that key is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key cannot be read, or a new one cannot be written, which it
+    /// never is when the configuration was read from the environment.
+    pub fn key_or_generate(&self) -> Result<Keypair, ConfigError> {
+        if let Some(key) = self.key()? {
+            return Ok(key);
+        }
+        match self.write_new_key(false) {
+            // another process persisted its key first
+            Err(ConfigError::Io { error, .. }) if error.kind() == io::ErrorKind::AlreadyExists => {
+                let removed = "removed as it was read".to_string();
+                self.key()?
+                    .ok_or_else(|| ConfigError::CorruptKey(self.dir.join(KEY_FILE), removed))
+            }
+            result => result,
+        }
+    }
+
+    /// Generates a new ed25519 identity key and persists it in the configuration directory,
+    /// replacing the key persisted there before, if any. The key file is only readable by its
+    /// owner.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the new key cannot be written.
+    pub fn generate_key(&self) -> Result<Keypair, ConfigError> {
+        self.write_new_key(true)
+    }
+
+    /// Makes `key` the identity key persisted in the configuration directory, such as once the
+    /// shares of the current key are handed over to it. The current key, if any, is kept in
+    /// `RETIRED_KEY_FILE`, replacing the key retired before.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either key cannot be written, which they never are when the
+    /// configuration was read from the environment.
+    pub fn replace_key(&self, key: &Keypair) -> Result<(), ConfigError> {
+        if let Some(current) = self.key()? {
+            self.write_key(&current, RETIRED_KEY_FILE, true)?;
+        }
+        self.write_key(key, KEY_FILE, true)?;
+        debug!(peer = %key.public().to_peer_id(), "Replaced identity.");
+        Ok(())
+    }
+
+    /// Generates an ed25519 key and writes it to the key file, which must not exist yet unless
+    /// `replace` is set.
+    fn write_new_key(&self, replace: bool) -> Result<Keypair, ConfigError> {
+        let key = Keypair::generate_ed25519();
+        self.write_key(&key, KEY_FILE, replace)?;
+        debug!(peer = %key.public().to_peer_id(), "Generated identity.");
+        Ok(key)
+    }
+
+    /// Writes `key` to the file `name` of the configuration directory, which must not exist yet
+    /// unless `replace` is set.
+    fn write_key(&self, key: &Keypair, name: &str, replace: bool) -> Result<(), ConfigError> {
+        if self.from_env {
+            let reason = "not set, and no key is persisted without a configuration directory";
+            return Err(ConfigError::InvalidEnv(KEY_ENV.to_string(), reason.to_string()));
+        }
+        let path = self.dir.join(name);
+        let encoded = key
+            .to_protobuf_encoding()
+            .map_err(|e| ConfigError::CorruptKey(path.clone(), e.to_string()))?;
+        fs::create_dir_all(&self.dir).map_err(|e| ConfigError::io("create", &self.dir, e))?;
+        write_new_file(&path, &encoded, replace)
+    }
+
     fn default() -> Self {
         ShardConfig {
             bootstrapper: Some("/ip4/127.0.0.1/tcp/40837/p2p/12D3KooWPjceQrSwdWXPyLLeABRXmuqt69Rg3sBYbU1Nft9HyQ6X".parse().unwrap()),
+            bootstrappers: Vec::new(),
+            db_path: None,
+            refresh_interval_secs: None,
+            listen_addresses: Vec::new(),
+            external_addresses: Vec::new(),
+            network_id: None,
+            request_timeout_secs: None,
+            denied_peers: Vec::new(),
+            allowed_peers: Vec::new(),
+            dir: PathBuf::new(),
+            from_env: false,
+            env_key: None,
+        }
+    }
+}
+
+/// What changed in the configuration on a reload, as found by `ShardConfig::changes_since`.
+///
+/// # Fields
+///
+/// * `added_bootstrappers` - The bootstrappers to dial.
+/// * `removed_bootstrappers` - The bootstrappers no longer configured, which stay connected.
+/// * `blocked` - The peers newly denied, to block.
+/// * `allowed` - The peers no longer denied, or newly allowed, to accept again.
+/// * `needs_restart` - The keys of the settings changed that are only read at startup.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigChanges {
+    pub added_bootstrappers: Vec<Multiaddr>,
+    pub removed_bootstrappers: Vec<Multiaddr>,
+    pub blocked: Vec<PeerId>,
+    pub allowed: Vec<PeerId>,
+    pub needs_restart: Vec<&'static str>,
+}
+
+impl ConfigChanges {
+    /// Whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        *self == ConfigChanges::default()
+    }
+}
+
+impl Display for ConfigChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        let list = |items: Vec<String>| items.join(", ");
+        if !self.added_bootstrappers.is_empty() {
+            let added = self.added_bootstrappers.iter().map(ToString::to_string).collect();
+            parts.push(format!("bootstrappers added: {}", list(added)));
+        }
+        if !self.removed_bootstrappers.is_empty() {
+            let removed = self.removed_bootstrappers.iter().map(ToString::to_string).collect();
+            parts.push(format!("bootstrappers removed: {}", list(removed)));
+        }
+        if !self.blocked.is_empty() {
+            let blocked = self.blocked.iter().map(ToString::to_string).collect();
+            parts.push(format!("peers blocked: {}", list(blocked)));
+        }
+        if !self.allowed.is_empty() {
+            let allowed = self.allowed.iter().map(ToString::to_string).collect();
+            parts.push(format!("peers allowed: {}", list(allowed)));
+        }
+        match parts.is_empty() {
+            true => write!(f, "nothing changed"),
+            false => write!(f, "{}", parts.join("; ")),
         }
     }
 }
 
+/// Writes peer ids as their base58 strings, as `conf.toml` holds them.
+fn serialize_peer_ids<S: Serializer>(peers: &[PeerId], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(peers.iter().map(ToString::to_string))
+}
+
+/// Reads peer ids from their base58 strings.
+fn deserialize_peer_ids<'de, D>(deserializer: D) -> Result<Vec<PeerId>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|peer| peer.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// The lock file of a configuration directory, removed when dropped.
+struct ConfigLock {
+    path: PathBuf,
+}
+
+impl ConfigLock {
+    /// Creates the lock file `path`, waiting for the process holding it to remove it. A lock
+    /// file older than `STALE_LOCK` was left behind by a process that died, and is removed.
+    fn acquire(path: &Path) -> Result<Self, ConfigError> {
+        loop {
+            match fs::OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(_) => return Ok(ConfigLock { path: path.to_path_buf() }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    let age = fs::metadata(path)
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok());
+                    if age.is_some_and(|age| age > STALE_LOCK) {
+                        debug!("Removing the stale lock {:?}", path);
+                        let _ = fs::remove_file(path);
+                    } else {
+                        std::thread::sleep(Duration::from_millis(10));
+                    }
+                }
+                Err(e) => return Err(ConfigError::io("lock", path, e)),
+            }
+        }
+    }
+}
+
+impl Drop for ConfigLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Parses `value` as the setting `key`, or one of its values.
+fn setting<T>(key: &str, value: &str) -> Result<T, ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|e| ConfigError::InvalidSetting(key.to_string(), format!("{:?}: {}", value, e)))
+}
+
+/// Parses `value`, the setting `key`, if it is set.
+fn one_setting<T>(key: &str, value: Option<&str>) -> Result<Option<T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value.map(|value| setting(key, value)).transpose()
+}
+
+/// Parses `value`, the setting `key`, as a list separated by commas, empty if it is not set.
+fn list_setting<T>(key: &str, value: Option<&str>) -> Result<Vec<T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| setting(key, item))
+        .collect()
+}
+
+/// Checks that `secs`, the setting `key`, is not zero.
+fn positive(key: &str, secs: Option<u64>) -> Result<Option<u64>, ConfigError> {
+    match secs {
+        Some(0) => Err(ConfigError::InvalidSetting(key.to_string(), "zero".to_string())),
+        secs => Ok(secs),
+    }
+}
+
+/// Checks that `addr`, the setting `key`, ends with the peer id of the bootstrapper.
+fn check_bootstrapper(key: &str, addr: &Multiaddr) -> Result<(), ConfigError> {
+    match addr.iter().last() {
+        Some(libp2p::multiaddr::Protocol::P2p(_)) => Ok(()),
+        _ => {
+            let reason = format!("{} does not end with the bootstrapper's /p2p/ peer id", addr);
+            Err(ConfigError::InvalidSetting(key.to_string(), reason))
+        }
+    }
+}
+
+/// Drops the repeated items of `items`, keeping the first of each.
+fn deduplicated<T: PartialEq>(items: Vec<T>) -> Vec<T> {
+    let mut unique = Vec::with_capacity(items.len());
+    for item in items {
+        if !unique.contains(&item) {
+            unique.push(item);
+        }
+    }
+    unique
+}
+
+/// Reads the protobuf-encoded key of the key file `path`, or `None` if there is none.
+fn read_key(path: &Path) -> Result<Option<Keypair>, ConfigError> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(ConfigError::io("read", path, e)),
+    };
+    let key = Keypair::from_protobuf_encoding(&bytes)
+        .map_err(|e| ConfigError::CorruptKey(path.to_path_buf(), e.to_string()))?;
+    Ok(Some(key))
+}
+
+/// Writes `contents` to a file next to `path` only its owner can read, and then moves it to
+/// `path` whole, so that no other process reads it half written.
+///
+/// # Arguments
+///
+/// * `path` - The file to write.
+/// * `contents` - What to write to it.
+/// * `replace` - Whether to replace the file at `path` if there is one.
+///
+/// # Errors
+///
+/// Returns an error naming the file if it cannot be written, which is of kind `AlreadyExists`
+/// when `path` exists and `replace` is not set.
+fn write_new_file(path: &Path, contents: &[u8], replace: bool) -> Result<(), ConfigError> {
+    let temp = write_temp_file(path, contents)?;
+    // a hard link fails if the file exists, where a rename would replace it
+    let moved = match replace {
+        true => fs::rename(&temp, path),
+        false => fs::hard_link(&temp, path),
+    };
+    let _ = fs::remove_file(&temp);
+    moved.map_err(|e| ConfigError::io("write", path, e))
+}
+
+/// Writes `contents` to a new file next to `path` only its owner can read, synced to disk, for
+/// `write_new_file` to move to `path`.
+///
+/// # Returns
+///
+/// The file written, or an error naming `path` if it cannot be written.
+fn write_temp_file(path: &Path, contents: &[u8]) -> Result<PathBuf, ConfigError> {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("config");
+    let temp = path.with_file_name(format!(".{}.{}.tmp", name, rand::random::<u64>()));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let written = options.open(&temp).and_then(|mut file| {
+        io::Write::write_all(&mut file, contents)?;
+        file.sync_all()
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(ConfigError::io("write", path, e));
+    }
+    Ok(temp)
+}
+
+/// Reads the setting `key`, if it is set.
+fn optional<T>(
+    key: &str,
+    get: Result<T, config::ConfigError>,
+) -> Result<Option<T>, config::ConfigError> {
+    match get {
+        Ok(value) => Ok(Some(value)),
+        Err(config::ConfigError::NotFound(_)) => Ok(None),
+        Err(config::ConfigError::Type { unexpected, expected, .. }) => {
+            let message = format!("invalid {}: expected {}, found {}", key, expected, unexpected);
+            Err(config::ConfigError::Message(message))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads the setting `key` as a number of seconds, if it is set.
+fn seconds(config: &Config, key: &str) -> Result<Option<u64>, config::ConfigError> {
+    match optional(key, config.get_int(key))? {
+        Some(seconds) => u64::try_from(seconds).map(Some).map_err(|_| {
+            config::ConfigError::Message(format!("invalid {}: {}", key, seconds))
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Parses `addr`, the setting `key` or one of its values, as a multiaddr.
+fn multiaddr(key: &str, addr: String) -> Result<Multiaddr, config::ConfigError> {
+    addr.parse::<Multiaddr>()
+        .map_err(|e| config::ConfigError::Message(format!("invalid {} {}: {}", key, addr, e)))
+}
+
+/// Reads the setting `key` as a list of multiaddrs, empty if it is not set.
+fn multiaddrs(config: &Config, key: &str) -> Result<Vec<Multiaddr>, config::ConfigError> {
+    optional(key, config.get_array(key))?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|value| multiaddr(key, value.into_string()?))
+        .collect()
+}
+
+/// Reads the setting `key` as a list of peer ids, empty if it is not set.
+fn peer_ids(config: &Config, key: &str) -> Result<Vec<PeerId>, config::ConfigError> {
+    optional(key, config.get_array(key))?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|value| {
+            let peer = value.into_string()?;
+            peer.parse::<PeerId>().map_err(|e| {
+                config::ConfigError::Message(format!("invalid {} {}: {}", key, peer, e))
+            })
+        })
+        .collect()
+}
+
 impl TryFrom<Config> for ShardConfig {
-    type Error = ConfigError;
+    type Error = config::ConfigError;
 
     fn try_from(config: Config) -> Result<Self, Self::Error> {
+        let bootstrapper = match optional("bootstrapper", config.get_string("bootstrapper"))? {
+            Some(addr) => Some(multiaddr("bootstrapper", addr)?),
+            None => None,
+        };
         Ok(
             ShardConfig {
-                bootstrapper: Some(config.get_string("bootstrapper")?.parse().unwrap()),
+                bootstrapper,
+                bootstrappers: multiaddrs(&config, "bootstrappers")?,
+                db_path: optional("db_path", config.get_string("db_path"))?,
+                refresh_interval_secs: seconds(&config, "refresh_interval_secs")?,
+                listen_addresses: multiaddrs(&config, "listen_addresses")?,
+                external_addresses: multiaddrs(&config, "external_addresses")?,
+                network_id: optional("network_id", config.get_string("network_id"))?,
+                request_timeout_secs: seconds(&config, "request_timeout_secs")?,
+                denied_peers: peer_ids(&config, "denied_peers")?,
+                allowed_peers: peer_ids(&config, "allowed_peers")?,
+                dir: PathBuf::new(),
+                from_env: false,
+                env_key: None,
             }
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("shard-{}-{}", name, rand::random::<u64>()))
+    }
+
+    #[test]
+    fn test_key_is_generated_once_and_persisted() {
+        let dir = temp_dir("config-key");
+        let config = ShardConfig::load(&dir).unwrap();
+        assert!(config.key().unwrap().is_none());
+
+        let key = config.key_or_generate().unwrap();
+        let reloaded = ShardConfig::load(&dir).unwrap();
+        assert_eq!(reloaded.key().unwrap().unwrap().public(), key.public());
+        assert_eq!(reloaded.key_or_generate().unwrap().public(), key.public());
+
+        let other_dir = temp_dir("config-key");
+        let other = ShardConfig::load(&other_dir).unwrap();
+        assert_ne!(other.key_or_generate().unwrap().public(), key.public());
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(other_dir).unwrap();
+    }
+
+    #[test]
+    fn test_replaced_key_is_retired_next_to_the_new_one() {
+        let dir = temp_dir("config-replace");
+        let config = ShardConfig::load(&dir).unwrap();
+        let old = config.key_or_generate().unwrap();
+        let new = Keypair::generate_ed25519();
+        config.replace_key(&new).unwrap();
+        assert_eq!(config.key().unwrap().unwrap().public(), new.public());
+        let retired = ShardConfig::key_file(&dir.join(RETIRED_KEY_FILE)).unwrap();
+        assert_eq!(retired.unwrap().public(), old.public());
+        assert!(ShardConfig::key_file(&dir.join("missing.key")).unwrap().is_none());
+
+        let from_env = ShardConfig {
+            from_env: true,
+            ..config
+        };
+        assert!(from_env.replace_key(&old).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_generated_key_replaces_the_persisted_one() {
+        let dir = temp_dir("config-generate");
+        let config = ShardConfig::load(&dir).unwrap();
+        let first = config.generate_key().unwrap();
+        assert_eq!(config.key().unwrap().unwrap().public(), first.public());
+
+        let second = config.generate_key().unwrap();
+        assert_ne!(second.public(), first.public());
+        assert_eq!(config.key().unwrap().unwrap().public(), second.public());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(dir.join(KEY_FILE)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_bootstrappers_are_read_from_the_config_dir() {
+        let dir = temp_dir("config-bootstrappers");
+        fs::create_dir_all(&dir).unwrap();
+        let first = format!("/ip4/127.0.0.1/tcp/1/p2p/{}", libp2p::PeerId::random());
+        let second = format!("/ip4/127.0.0.1/tcp/2/p2p/{}", libp2p::PeerId::random());
+        fs::write(
+            dir.join("conf.toml"),
+            format!("bootstrapper = \"{first}\"\nbootstrappers = [\"{second}\", \"{first}\"]\n"),
+        )
+        .unwrap();
+
+        let config = ShardConfig::load(&dir).unwrap();
+        let expected: Vec<Multiaddr> = vec![first.parse().unwrap(), second.parse().unwrap()];
+        assert_eq!(config.bootstrap_addrs(), expected);
+
+        fs::write(dir.join("conf.toml"), "bootstrappers = [\"nonsense\"]\n").unwrap();
+        assert!(ShardConfig::load(&dir).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_flag_takes_precedence_over_env_over_file_over_default() {
+        // every combination of the four sources being set, each with a value naming it
+        for set in 0..16u8 {
+            let source = |bit: u8, name: &'static str| (set & (1 << bit) != 0).then_some(name);
+            let (flag, env, file, default) =
+                (source(3, "flag"), source(2, "env"), source(1, "file"), source(0, "default"));
+            let expected = [flag, env, file, default].into_iter().flatten().next();
+            assert_eq!(resolve(flag, env, file, default), expected, "sources set: {:04b}", set);
+        }
+        assert_eq!(resolve(None, None, Some(600), Some(1800)), Some(600));
+        assert_eq!(resolve::<u64>(None, None, None, None), None);
+    }
+
+    #[test]
+    fn test_settings_are_parsed_from_the_environment() {
+        let parse = |value: Option<&str>| {
+            parse_setting::<u64>(REFRESH_INTERVAL_ENV, value.map(str::to_string))
+        };
+        assert_eq!(parse(Some("600")).unwrap(), Some(600));
+        assert_eq!(parse(Some(" 600\n")).unwrap(), Some(600));
+        assert_eq!(parse(Some("")).unwrap(), None);
+        assert_eq!(parse(None).unwrap(), None);
+        let err = parse(Some("soon")).unwrap_err();
+        assert!(err.to_string().contains(REFRESH_INTERVAL_ENV));
+    }
+
+    #[test]
+    fn test_lists_are_parsed_from_the_environment() {
+        let parse = |value: &str| {
+            parse_list::<Multiaddr>(LISTEN_ADDRESSES_ENV, Some(value.to_string()))
+        };
+        let addrs = parse("/ip4/0.0.0.0/tcp/40837, /ip6/::/tcp/40837,").unwrap().unwrap();
+        assert_eq!(addrs.len(), 2);
+        assert_eq!(addrs[1], "/ip6/::/tcp/40837".parse().unwrap());
+        assert_eq!(parse(" ").unwrap(), None);
+        assert!(parse("/ip4/0.0.0.0/tcp/1,nonsense").is_err());
+    }
+
+    #[test]
+    fn test_every_setting_round_trips_through_the_config_file() {
+        let dir = temp_dir("config-round-trip");
+        let addr = |port: u16| -> Multiaddr {
+            format!("/ip4/127.0.0.1/tcp/{}/p2p/{}", port, libp2p::PeerId::random()).parse().unwrap()
+        };
+        let config = ShardConfig {
+            bootstrapper: Some(addr(1)),
+            bootstrappers: vec![addr(2), addr(3)],
+            db_path: Some("/var/lib/shard".to_string()),
+            refresh_interval_secs: Some(600),
+            listen_addresses: vec!["/ip4/0.0.0.0/tcp/40837".parse().unwrap()],
+            external_addresses: vec!["/dns4/shard.example.com/tcp/40837".parse().unwrap()],
+            network_id: Some("staging".to_string()),
+            request_timeout_secs: Some(90),
+            denied_peers: vec![libp2p::PeerId::random()],
+            allowed_peers: vec![libp2p::PeerId::random()],
+            dir: dir.clone(),
+            from_env: false,
+            env_key: None,
+        };
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(CONFIG_FILE), toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(ShardConfig::load(&dir).unwrap(), config);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_config_takes_the_defaults() {
+        let dir = temp_dir("config-legacy");
+        fs::create_dir_all(&dir).unwrap();
+        let bootstrapper = ShardConfig::default().bootstrapper.unwrap();
+        fs::write(dir.join(CONFIG_FILE), format!("bootstrapper = \"{}\"\n", bootstrapper)).unwrap();
+        let config = ShardConfig::load(&dir).unwrap();
+        assert_eq!(config, ShardConfig { dir: dir.clone(), ..ShardConfig::default() });
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_provider_defaults_are_read_from_the_config_dir() {
+        let dir = temp_dir("config-defaults");
+        let config = ShardConfig::load(&dir).unwrap();
+        assert_eq!((config.db_path, config.refresh_interval_secs), (None, None));
+
+        let toml = "db_path = \"/var/lib/shard\"\nrefresh_interval_secs = 600\n";
+        fs::write(dir.join(CONFIG_FILE), toml).unwrap();
+        let config = ShardConfig::load(&dir).unwrap();
+        assert_eq!(config.db_path.as_deref(), Some("/var/lib/shard"));
+        assert_eq!(config.refresh_interval_secs, Some(600));
+
+        fs::write(dir.join("conf.toml"), "refresh_interval_secs = -1\n").unwrap();
+        assert!(ShardConfig::load(&dir).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_dir_is_created_with_a_default_config() {
+        let dir = temp_dir("config-missing").join("nested");
+        let config = ShardConfig::load(&dir).unwrap();
+        assert_eq!(config.dir, dir);
+        assert!(dir.join(CONFIG_FILE).exists());
+        assert_eq!(config.bootstrap_addrs(), ShardConfig::default().bootstrap_addrs());
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_unwritable_dir_is_an_error_naming_it() {
+        // a file in the way fails whatever the permissions of the user running the tests
+        let file = temp_dir("config-unwritable");
+        fs::write(&file, "not a directory").unwrap();
+        let dir = file.join("config");
+        let err = ShardConfig::load(&dir).unwrap_err();
+        assert!(matches!(err, ConfigError::Io { action: "create", .. }));
+        assert!(err.to_string().contains(&dir.display().to_string()));
+
+        let config = ShardConfig { dir: dir.clone(), ..ShardConfig::default() };
+        assert!(matches!(config.key_or_generate(), Err(ConfigError::Io { .. })));
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_key_file_is_an_error_naming_it() {
+        let dir = temp_dir("config-corrupt");
+        let config = ShardConfig::load(&dir).unwrap();
+        let key = config.key_or_generate().unwrap().to_protobuf_encoding().unwrap();
+        fs::write(dir.join(KEY_FILE), &key[..key.len() / 2]).unwrap();
+
+        let err = config.key().unwrap_err();
+        assert!(matches!(err, ConfigError::CorruptKey(..)));
+        let message = err.to_string();
+        assert!(message.starts_with("config key file "));
+        assert!(message.contains(&dir.join(KEY_FILE).display().to_string()));
+        assert!(config.key_or_generate().is_err());
+
+        // a new key replaces the corrupt one
+        let key = config.generate_key().unwrap();
+        assert_eq!(config.key().unwrap().unwrap().public(), key.public());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_malformed_config_is_an_error_naming_it() {
+        let dir = temp_dir("config-malformed");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(CONFIG_FILE), "bootstrapper = [unterminated\n").unwrap();
+        let err = ShardConfig::load(&dir).unwrap_err();
+        assert!(matches!(err, ConfigError::Malformed(..)));
+        assert!(err.to_string().contains(&dir.join(CONFIG_FILE).display().to_string()));
+
+        fs::write(dir.join(CONFIG_FILE), "bootstrapper = \"nonsense\"\n").unwrap();
+        assert!(matches!(ShardConfig::load(&dir), Err(ConfigError::Malformed(..))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_processes_starting_together_share_one_config_and_key() {
+        let dir = temp_dir("config-race");
+        let keys: Vec<_> = std::thread::scope(|scope| {
+            let loads: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| ShardConfig::load(&dir)?.key_or_generate()))
+                .collect();
+            loads.into_iter().map(|load| load.join().unwrap().unwrap().public()).collect()
+        });
+        assert!(keys.iter().all(|key| *key == keys[0]));
+        assert_eq!(ShardConfig::load(&dir).unwrap().key().unwrap().unwrap().public(), keys[0]);
+        // no file written on the way is left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_config_is_read_from_the_environment_alone() {
+        // the only test setting these variables, as the tests of the crate run in one process
+        let key = Keypair::generate_ed25519();
+        let first = format!("/ip4/10.0.0.1/tcp/40837/p2p/{}", libp2p::PeerId::random());
+        let second = format!("/dns4/boot.example.com/tcp/40837/p2p/{}", libp2p::PeerId::random());
+        let vars = [
+            (NO_CONFIG_DIR_ENV, "1".to_string()),
+            (KEY_ENV, hex::encode(key.to_protobuf_encoding().unwrap())),
+            (BOOTSTRAPPER_ENV, first.clone()),
+            (BOOTSTRAPPERS_ENV, format!("{},{}", second, first)),
+            (DB_PATH_ENV, "/data/shares.db".to_string()),
+            (REFRESH_INTERVAL_ENV, "600".to_string()),
+            (LISTEN_ADDRESSES_ENV, "/ip4/0.0.0.0/tcp/40837".to_string()),
+            (NETWORK_ID_ENV, "staging".to_string()),
+            (REQUEST_TIMEOUT_ENV, "90".to_string()),
+        ];
+        for (name, value) in &vars {
+            std::env::set_var(name, value);
+        }
+        let config = ShardConfig::new();
+        let from_file = temp_dir("config-env");
+        fs::create_dir_all(&from_file).unwrap();
+        let key_file = from_file.join(KEY_FILE);
+        fs::write(&key_file, key.to_protobuf_encoding().unwrap()).unwrap();
+        std::env::set_var(KEY_ENV, &key_file);
+        let keyed_by_file = ShardConfig::from_env();
+        std::env::remove_var(KEY_ENV);
+        let keyless = ShardConfig::from_env();
+        for (name, _) in &vars {
+            std::env::remove_var(name);
+        }
+
+        let config = config.unwrap();
+        assert!(config.from_env);
+        assert_eq!(config.key().unwrap().unwrap().public(), key.public());
+        assert_eq!(config.key_or_generate().unwrap().public(), key.public());
+        let expected: Vec<Multiaddr> = vec![first.parse().unwrap(), second.parse().unwrap()];
+        assert_eq!(config.bootstrap_addrs(), expected);
+        assert_eq!(config.db_path.as_deref(), Some("/data/shares.db"));
+        assert_eq!(config.refresh_interval_secs, Some(600));
+        assert_eq!(config.listen_addresses, vec!["/ip4/0.0.0.0/tcp/40837".parse().unwrap()]);
+        assert!(config.external_addresses.is_empty());
+        assert_eq!(config.network_id.as_deref(), Some("staging"));
+        assert_eq!(config.request_timeout_secs, Some(90));
+
+        let keyed_by_file = keyed_by_file.unwrap();
+        assert_eq!(keyed_by_file.key().unwrap().unwrap().public(), key.public());
+
+        // without a key none is generated, as there is nowhere to persist it
+        let keyless = keyless.unwrap();
+        assert!(keyless.key().unwrap().is_none());
+        let err = keyless.key_or_generate().unwrap_err();
+        assert!(err.to_string().contains(KEY_ENV));
+        assert!(keyless.generate_key().is_err());
+        assert_eq!(fs::read_dir(&from_file).unwrap().count(), 1);
+        fs::remove_dir_all(from_file).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_variables_are_named() {
+        let invalid = |name: &str, value: &str| {
+            let err = ShardConfig::from_vars(|var| (var == name).then(|| value.to_string()))
+                .unwrap_err();
+            assert!(matches!(&err, ConfigError::InvalidEnv(var, _) if var == name), "{}", err);
+            assert!(err.to_string().contains(name));
+        };
+        invalid(KEY_ENV, "not hex");
+        invalid(KEY_ENV, "deadbeef");
+        invalid(BOOTSTRAPPER_ENV, "nonsense");
+        invalid(BOOTSTRAPPERS_ENV, "/ip4/10.0.0.1/tcp/1,nonsense");
+        invalid(REFRESH_INTERVAL_ENV, "-1");
+        invalid(NETWORK_ID_ENV, "has/slash");
+        invalid(REQUEST_TIMEOUT_ENV, "soon");
+
+        let empty = ShardConfig::from_vars(|_| None).unwrap();
+        assert!(empty.bootstrap_addrs().is_empty());
+        assert!(empty.env_key.is_none());
+        assert!(!parse_flag(NO_CONFIG_DIR_ENV, None).unwrap());
+        assert!(parse_flag(NO_CONFIG_DIR_ENV, Some("1".to_string())).unwrap());
+        assert!(parse_flag(NO_CONFIG_DIR_ENV, Some("yes".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_settings_are_saved_and_reloaded() {
+        let dir = temp_dir("config-save");
+        let mut config = ShardConfig::load(&dir).unwrap();
+        let peer = libp2p::PeerId::random();
+        let bootstrapper: Multiaddr =
+            format!("/dns4/boot.example.com/tcp/40837/p2p/{}", peer).parse().unwrap();
+        assert!(config.add_bootstrapper(bootstrapper.clone()).unwrap());
+        assert!(!config.add_bootstrapper(bootstrapper.clone()).unwrap());
+        assert!(config.add_bootstrapper("/ip4/10.0.0.1/tcp/1".parse().unwrap()).is_err());
+        config.set_refresh_interval(Some(600)).unwrap();
+        assert!(config.set_refresh_interval(Some(0)).is_err());
+        config.set("listen_addresses", "/ip4/0.0.0.0/tcp/1, /ip4/0.0.0.0/tcp/1").unwrap();
+        config.set("denied_peers", &peer.to_string()).unwrap();
+        config.set("network_id", "staging").unwrap();
+        config.save().unwrap();
+
+        let reloaded = ShardConfig::load(&dir).unwrap();
+        assert_eq!(reloaded, config);
+        assert_eq!(reloaded.bootstrap_addrs().last(), Some(&bootstrapper));
+        assert_eq!(reloaded.listen_addresses.len(), 1);
+        assert_eq!(reloaded.denied_peers, vec![peer]);
+
+        config.set("network_id", "").unwrap();
+        assert_eq!(config.network_id, None);
+        for (key, value) in [
+            ("network_id", "a/b"),
+            ("request_timeout_secs", "soon"),
+            ("bootstrappers", "nonsense"),
+            ("denied_peers", "nonsense"),
+            ("colour", "blue"),
+        ] {
+            let err = config.set(key, value).unwrap_err();
+            assert!(matches!(&err, ConfigError::InvalidSetting(k, _) if k == key), "{}", err);
+        }
+        assert!(matches!(
+            ShardConfig { from_env: true, ..config }.save(),
+            Err(ConfigError::NotSaved)
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_crash_before_the_rename_leaves_the_old_file_intact() {
+        let dir = temp_dir("config-crash");
+        let mut config = ShardConfig::load(&dir).unwrap();
+        config.set_refresh_interval(Some(600)).unwrap();
+        config.save().unwrap();
+
+        // the process dies with the new file written under its temporary name, and the lock
+        // file left behind
+        config.set_refresh_interval(Some(60)).unwrap();
+        let toml = toml::to_string_pretty(&config).unwrap();
+        let temp = write_temp_file(&dir.join(CONFIG_FILE), toml.as_bytes()).unwrap();
+        fs::write(dir.join(LOCK_FILE), "").unwrap();
+        let stale = fs::File::options().write(true).open(dir.join(LOCK_FILE)).unwrap();
+        let past = std::time::SystemTime::now() - 2 * STALE_LOCK;
+        stale.set_modified(past).unwrap();
+
+        assert_eq!(ShardConfig::load(&dir).unwrap().refresh_interval_secs, Some(600));
+        assert!(temp.exists());
+        config.save().unwrap();
+        assert_eq!(ShardConfig::load(&dir).unwrap().refresh_interval_secs, Some(60));
+        assert!(!dir.join(LOCK_FILE).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_updates_are_all_kept() {
+        let dir = temp_dir("config-concurrent");
+        ShardConfig::load(&dir).unwrap();
+        let peers: Vec<PeerId> = (0..8).map(|_| libp2p::PeerId::random()).collect();
+        std::thread::scope(|scope| {
+            for peer in &peers {
+                let dir = &dir;
+                scope.spawn(move || {
+                    let mut config = ShardConfig::load(dir).unwrap();
+                    let addr = format!("/ip4/10.0.0.1/tcp/40837/p2p/{}", peer).parse().unwrap();
+                    config.update(|config| config.add_bootstrapper(addr).map(|_| ())).unwrap();
+                });
+            }
+        });
+        let config = ShardConfig::load(&dir).unwrap();
+        assert_eq!(config.bootstrappers.len(), peers.len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_changes_are_found_between_configs() {
+        let (first, second) = (libp2p::PeerId::random(), libp2p::PeerId::random());
+        let addr = |peer: PeerId| -> Multiaddr {
+            format!("/ip4/10.0.0.1/tcp/40837/p2p/{}", peer).parse().unwrap()
+        };
+        let previous = ShardConfig {
+            bootstrapper: None,
+            bootstrappers: vec![addr(first)],
+            denied_peers: vec![first],
+            ..ShardConfig::default()
+        };
+        assert!(previous.changes_since(&previous).is_empty());
+
+        let reloaded = ShardConfig {
+            bootstrappers: vec![addr(second)],
+            denied_peers: vec![first, second],
+            allowed_peers: vec![first],
+            listen_addresses: vec!["/ip4/0.0.0.0/tcp/1".parse().unwrap()],
+            ..previous.clone()
+        };
+        assert_eq!(reloaded.blocked_peers(), HashSet::from([second]));
+        let changes = reloaded.changes_since(&previous);
+        assert_eq!(changes.added_bootstrappers, vec![addr(second)]);
+        assert_eq!(changes.removed_bootstrappers, vec![addr(first)]);
+        assert_eq!((changes.blocked, changes.allowed), (vec![second], vec![first]));
+        assert_eq!(changes.needs_restart, ["listen_addresses"]);
+    }
+
+    #[test]
+    fn test_profiles_are_kept_apart() {
+        let dir = temp_dir("config-profiles");
+        assert!(ShardConfig::profiles(&dir).unwrap().is_empty());
+
+        // a directory laid out before profiles is the default profile
+        let flat = ShardConfig::load(&dir).unwrap().key_or_generate().unwrap();
+        let default = ShardConfig::load_profile(&dir, DEFAULT_PROFILE).unwrap();
+        assert_eq!(default.dir, dir);
+        assert_eq!(default.key().unwrap().unwrap().public(), flat.public());
+
+        let owner = ShardConfig::load_profile(&dir, "owner").unwrap();
+        let provider = ShardConfig::load_profile(&dir, "provider-1").unwrap();
+        assert_eq!(owner.dir, dir.join(PROFILES_DIR).join("owner"));
+        let owner_key = owner.key_or_generate().unwrap();
+        let provider_key = provider.key_or_generate().unwrap();
+        assert_ne!(owner_key.public(), provider_key.public());
+        assert_ne!(owner_key.public(), flat.public());
+        let reloaded = ShardConfig::profile_key(&dir, "owner").unwrap().unwrap();
+        assert_eq!(reloaded.public(), owner_key.public());
+        assert!(ShardConfig::profile_key(&dir, "unused").unwrap().is_none());
+
+        fs::write(dir.join(PROFILES_DIR).join("stray file"), "").unwrap();
+        assert_eq!(
+            ShardConfig::profiles(&dir).unwrap(),
+            [DEFAULT_PROFILE, "owner", "provider-1"]
+        );
+        for name in ["", "../escape", "a/b", ".hidden", "with space"] {
+            let err = ShardConfig::load_profile(&dir, name).unwrap_err();
+            assert!(matches!(err, ConfigError::InvalidProfile(..)), "{}", name);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_reaches_a_configured_bootstrapper_without_a_peer() {
+        let (mut node, _node_events, node_loop, node_id) = crate::network::new(None).await.unwrap();
+        tokio::spawn(node_loop.run(None));
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap();
+        node.start_listening(addr.clone()).await.unwrap();
+        node.start_providing("key".to_string()).await;
+
+        let dir = temp_dir("config-bootstrap");
+        fs::create_dir_all(&dir).unwrap();
+        let bootstrapper = format!("{}/p2p/{}", addr, node_id);
+        fs::write(dir.join("conf.toml"), format!("bootstrapper = \"{bootstrapper}\"\n")).unwrap();
+        let config = ShardConfig::load(&dir).unwrap();
+
+        let identity = config.key_or_generate().unwrap();
+        let (mut client, _events, event_loop, client_id) =
+            crate::network::with_identity(identity).await.unwrap();
+        tokio::spawn(event_loop.run(None));
+        let connected = client.bootstrap(&config.bootstrap_addrs(), client_id).await;
+        assert_eq!(connected, 1);
+
+        // what `shard ls` does once connected
+        let providers = client.get_providers("key".to_string()).await;
+        assert!(providers.contains(&node_id));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
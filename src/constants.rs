@@ -1,2 +1,113 @@
 /// The default number of seconds between each refresh of the data.
 pub const DEFAULT_REFRESH_SECONDS: u64 = 60 * 30;
+
+/// The number of seconds between each check for shares due a refresh. Shares are refreshed on
+/// the first check after their own interval, or the provider's default, has passed.
+pub const REFRESH_TICK_SECONDS: u64 = 60;
+
+/// The default spread of the refresh schedule, in percent: how far the due time of each share is
+/// moved either way from its interval, and how much of a tick the first check is delayed by.
+pub const DEFAULT_REFRESH_JITTER_PERCENT: u8 = 10;
+
+/// The number of milliseconds the refresh task waits between two due shares, so that a pass over
+/// many shares does not send their refreshes in one burst.
+pub const REFRESH_KEY_DELAY_MILLIS: u64 = 10;
+
+/// The default number of seconds between each purge of expired shares.
+pub const DEFAULT_PURGE_SECONDS: u64 = 60;
+
+/// The number of shares read from the DAO at a time when walking the whole store.
+pub const DAO_PAGE_SIZE: usize = 256;
+
+/// The most keys a provider returns in one page of a key listing.
+pub const MAX_LIST_KEYS_LIMIT: u32 = 1000;
+
+/// The default length of the chunks `split --file` splits a file into, well below the largest
+/// share providers accept by default.
+pub const DEFAULT_CHUNK_BYTES: u64 = 32 * 1024;
+
+/// The default number of seconds between each flush of the share database to disk.
+pub const DEFAULT_FLUSH_SECONDS: u64 = 1;
+
+/// The default number of seconds a deleted share's tombstone blocks its owner from registering it
+/// again without asking to recreate it.
+pub const DEFAULT_TOMBSTONE_SECONDS: u64 = 60 * 60 * 24;
+
+/// The number of refresh intervals a share goes without a refresh before the next provider in
+/// line takes over as its refresh coordinator.
+pub const REFRESH_TAKEOVER_INTERVALS: u64 = 3;
+
+/// The number of times a refresh pass is attempted when the share database cannot be read.
+pub const REFRESH_READ_ATTEMPTS: u32 = 3;
+
+/// The number of seconds the refresh task waits before retrying a failed read of the share
+/// database, doubled on each further attempt, and before restarting after it stopped.
+pub const REFRESH_RETRY_SECONDS: u64 = 5;
+
+/// The default number of seconds a stopping provider waits for the refresh in flight before
+/// abandoning it, flushing its shares and exiting.
+pub const DEFAULT_SHUTDOWN_GRACE_SECONDS: u64 = 30;
+
+/// The default largest share, in bytes, a provider accepts for registration.
+pub const DEFAULT_MAX_SHARE_BYTES: usize = 64 * 1024;
+
+/// The default number of requests of one operation a peer can send a rate-limited provider in a
+/// burst, before being held to the provider's rate.
+pub const DEFAULT_RATE_LIMIT_BURST: u32 = 10;
+
+/// The most peers and operations a provider tracks the request rate of. Past it, peers whose
+/// allowance has refilled are forgotten.
+pub const MAX_RATE_LIMIT_BUCKETS: usize = 10_000;
+
+/// The gossipsub topic nodes publish and receive network-wide messages on.
+pub const GOSSIP_TOPIC: &str = "/shard/pubsub/1.0.0";
+
+/// The default number of providers beyond its threshold a share should have. A provider holding
+/// a share with fewer providers than its threshold plus this margin raises an alert.
+pub const DEFAULT_REPLICATION_MARGIN: u64 = 1;
+
+/// The gossipsub topic providers publish their health status on.
+pub const HEALTH_TOPIC: &str = "/shard/health/1.0.0";
+
+/// The default number of seconds between each health status a provider publishes.
+pub const DEFAULT_STATUS_SECONDS: u64 = 10;
+
+/// The number of status periods a node keeps the last status of a provider for. A provider that
+/// misses this many in a row is dropped from the cache.
+pub const STATUS_EXPIRY_PERIODS: u32 = 3;
+
+/// The agent version nodes identify themselves with to their peers.
+pub const AGENT_VERSION: &str = concat!("shard/", env!("CARGO_PKG_VERSION"));
+
+/// The number of network events the event loop queues for the node before waiting for it to read
+/// them. A node busy with a request sends commands to the event loop, so the event loop must be
+/// able to take in the requests arriving meanwhile rather than wait for the node to read them.
+pub const EVENT_BUFFER: usize = 128;
+
+/// The number of provider events `provide --print-events` queues before dropping them, when it
+/// cannot print them as fast as the provider publishes them.
+pub const PROVIDER_EVENT_BUFFER: usize = 256;
+
+/// The addresses a node listens on when none are given: every IPv4 and IPv6 interface, on a port
+/// picked by the system.
+pub const DEFAULT_LISTEN_ADDRS: [&str; 2] = ["/ip4/0.0.0.0/tcp/0", "/ip6/::/tcp/0"];
+
+/// How many times, 50ms apart, `wait_for_listen_addrs` checks for listen addresses before
+/// reporting none.
+pub const LISTEN_ADDR_POLLS: usize = 20;
+
+/// The default number of seconds a client waits for the network: for the response to a request,
+/// for the result of a DHT query, and for enough providers to turn up.
+pub const DEFAULT_TIMEOUT_SECONDS: u64 = 30;
+
+/// The default number of seconds between two lookups of the providers watched by `ls --watch`.
+pub const DEFAULT_WATCH_SECONDS: u64 = 5;
+
+/// The number of milliseconds between two checks of whether the network is ready for a command:
+/// connected, bootstrapped, and with the providers it is waiting for.
+pub const READY_POLL_MILLIS: u64 = 50;
+
+/// The number of milliseconds a single Kademlia bootstrap may take while waiting for the network
+/// to be ready before it is abandoned and retried, so that one stalled query does not use up the
+/// whole wait.
+pub const READY_BOOTSTRAP_ATTEMPT_MILLIS: u64 = 2_000;
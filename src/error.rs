@@ -0,0 +1,191 @@
+#[cfg(feature = "net")]
+use crate::client::ClientError;
+#[cfg(feature = "net")]
+use crate::config::ConfigError;
+#[cfg(feature = "net")]
+use crate::protocol::Failure;
+#[cfg(feature = "storage")]
+use crate::repository::RepoError;
+#[cfg(feature = "sss")]
+use crate::sss;
+
+/// The result of the fallible functions of the crate that can fail in more than one module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error of the crate, wrapping the error of the module that failed so that callers can
+/// match on it rather than on a message.
+///
+/// A variant wrapping an error shows its message and has its `source`, so that printing an
+/// `Error` reads the same as printing the module error, and walking the chain from it reaches
+/// the root cause, such as the `std::io::Error` of a store.
+///
+/// A variant is only there when the feature of its module is: `Sss` with `sss`, `Storage` with
+/// `storage`, and `Client`, `Config`, `Failure` and `Network` with `net`.
+///
+/// # Variants
+///
+/// * `Sss(sss::Error)` - Splitting, combining or refreshing shares failed.
+/// * `Client(ClientError)` - A command sent to the network failed, or a provider refused it.
+/// * `Config(ConfigError)` - The configuration or the identity key could not be loaded or saved.
+/// * `Storage(RepoError)` - The share store failed.
+/// * `Failure(Failure)` - A provider could not serve a request, and answered with why.
+/// * `Network(Box<dyn Error>)` - The libp2p swarm could not be set up.
+/// * `Invalid(String)` - Settings that cannot be used together or at all; carries why.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "storage")] {
+/// use shard::repository::RepoError;
+///
+/// let error = shard::Error::from(RepoError::ReadOnly);
+/// assert!(matches!(error, shard::Error::Storage(RepoError::ReadOnly)));
+/// assert_eq!(error.to_string(), RepoError::ReadOnly.to_string());
+///
+/// // binaries keep using boxed errors
+/// let boxed: Box<dyn std::error::Error> = error.into();
+/// assert!(boxed.downcast_ref::<shard::Error>().is_some());
+/// # }
+/// ```
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[cfg(feature = "sss")]
+    #[error(transparent)]
+    Sss(#[from] sss::Error),
+    #[cfg(feature = "net")]
+    #[error(transparent)]
+    Client(#[from] ClientError),
+    #[cfg(feature = "net")]
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[cfg(feature = "storage")]
+    #[error(transparent)]
+    Storage(#[from] RepoError),
+    #[cfg(feature = "net")]
+    #[error(transparent)]
+    Failure(Failure),
+    #[cfg(feature = "net")]
+    #[error(transparent)]
+    Network(Box<dyn std::error::Error + Send + Sync>),
+    #[error("{0}")]
+    Invalid(String),
+}
+
+#[cfg(feature = "net")]
+impl From<crate::client::NotReady> for Error {
+    fn from(error: crate::client::NotReady) -> Self {
+        Error::Client(error.into())
+    }
+}
+
+#[cfg(all(test, feature = "net"))]
+mod tests {
+    use super::*;
+    use crate::client::NotReady;
+    use std::error::Error as _;
+    use std::io;
+
+    /// Walks the `source` chain of `error`, starting with the error itself.
+    fn chain(error: &(dyn std::error::Error + 'static)) -> Vec<String> {
+        let mut chain = vec![error.to_string()];
+        let mut source = error.source();
+        while let Some(error) = source {
+            chain.push(error.to_string());
+            source = error.source();
+        }
+        chain
+    }
+
+    #[test]
+    fn test_io_error_keeps_its_source_through_storage() {
+        let io_error = io::Error::new(io::ErrorKind::PermissionDenied, "disk says no");
+        let error: Error = RepoError::from(io_error).into();
+        assert!(matches!(error, Error::Storage(RepoError::Backend(_))));
+
+        let boxed: Box<dyn std::error::Error> = error.into();
+        assert_eq!(boxed.to_string(), "disk says no");
+        let source = boxed.source().expect("the io error to be the source");
+        let io_error = source.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_error.kind(), io::ErrorKind::PermissionDenied);
+        match boxed.downcast_ref::<Error>() {
+            Some(Error::Storage(RepoError::Backend(backend))) => {
+                assert!(backend.get_ref().is::<io::Error>())
+            }
+            other => panic!("expected a storage backend error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_sled_error_keeps_its_source_through_storage() {
+        let sled_error = sled::Error::Unsupported("no such thing".to_string());
+        let error = Error::from(RepoError::from(sled_error));
+        let source = error.source().expect("the sled error to be the source");
+        assert!(matches!(
+            source.downcast_ref::<sled::Error>(),
+            Some(sled::Error::Unsupported(reason)) if reason == "no such thing"
+        ));
+    }
+
+    #[test]
+    fn test_sss_error_chains_through_refresh_key_rejection() {
+        let error = Error::from(RepoError::InvalidRefreshKey(sss::Error::NonZeroConstant(2)));
+        assert_eq!(
+            chain(&error),
+            vec![
+                "invalid refresh key: polynomial 2 has a non-zero constant term",
+                "polynomial 2 has a non-zero constant term",
+            ]
+        );
+        let source = error.source().unwrap();
+        assert_eq!(
+            source.downcast_ref::<sss::Error>(),
+            Some(&sss::Error::NonZeroConstant(2))
+        );
+    }
+
+    #[test]
+    fn test_sss_error_converts_directly() {
+        let error: Error = sss::Error::InvalidThreshold.into();
+        assert!(matches!(error, Error::Sss(sss::Error::InvalidThreshold)));
+        assert_eq!(error.to_string(), "Invalid threshold");
+    }
+
+    #[test]
+    fn test_failure_stays_matchable_through_client() {
+        let error: Box<dyn std::error::Error> =
+            Error::from(ClientError::from(Failure::NotReader)).into();
+        assert_eq!(error.to_string(), Failure::NotReader.to_string());
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::Client(ClientError::Failure(Failure::NotReader)))
+        ));
+    }
+
+    #[test]
+    fn test_not_ready_converts_through_client() {
+        let error = Error::from(NotReady::Providers {
+            found: 1,
+            wanted: 3,
+        });
+        assert!(matches!(
+            error,
+            Error::Client(ClientError::NotReady(NotReady::Providers { found: 1, wanted: 3 }))
+        ));
+    }
+
+    #[test]
+    fn test_config_error_keeps_its_source() {
+        let io_error = io::Error::new(io::ErrorKind::NotFound, "gone");
+        let error = Error::from(ConfigError::Io {
+            action: "read",
+            path: std::path::PathBuf::from("/tmp/conf.toml"),
+            error: io_error,
+        });
+        assert_eq!(error.to_string(), "cannot read /tmp/conf.toml: gone");
+        let source = error.source().expect("the io error to be the source");
+        assert_eq!(
+            source.downcast_ref::<io::Error>().unwrap().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
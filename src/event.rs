@@ -1,6 +1,7 @@
 use futures::channel::{mpsc, oneshot};
 use futures::prelude::*;
 
+use libp2p::gossipsub::{self, IdentTopic};
 use libp2p::identify;
 use libp2p::multiaddr::Protocol;
 use libp2p::{
@@ -11,14 +12,19 @@ use libp2p::{
 };
 
 use std::collections::{HashMap, HashSet};
-use std::error::Error;
 use std::net::IpAddr;
-use tracing::debug;
+use std::time::{Duration, Instant};
+use tracing::{debug, debug_span, Instrument};
 
+use crate::client::ClientError;
 use crate::command::command_handler;
-use crate::command::Command;
+use crate::command::{Command, CommandResult};
 use crate::network::{Behaviour, BehaviourEvent};
-use crate::protocol::Request;
+use crate::constants::{HEALTH_TOPIC, STATUS_EXPIRY_PERIODS};
+use crate::protocol::{
+    DeleteShareStatus, Failure, FieldShare, GossipMessage, ProviderStatus, RegisterShareStatus,
+    Request, StatShareStatus,
+};
 use crate::protocol::Response;
 
 /// Represents various events that can occur in the network.
@@ -27,7 +33,9 @@ use crate::protocol::Response;
 ///
 /// # Variants
 ///
-/// * `InboundRequest` - Represents an inbound request event with the request data and a response channel.
+/// * `InboundRequest` - Represents an inbound request event with the request data, the
+///   authenticated peer the request arrived from, and a response channel.
+/// * `Gossip` - Represents a message broadcast over gossipsub, with the peer that published it.
 ///
 /// # Examples
 ///
@@ -35,7 +43,7 @@ use crate::protocol::Response;
 ///
 /// ```ignore
 /// match event {
-///     Event::InboundRequest { request, channel } => {
+///     Event::InboundRequest { request, peer, channel } => {
 ///         // Handle the request and possibly send a response back using the channel.
 ///     },
 /// }
@@ -44,8 +52,43 @@ use crate::protocol::Response;
 pub enum Event {
     InboundRequest {
         request: Request,
+        peer: PeerId,
         channel: ResponseChannel<Response>,
     },
+    Gossip {
+        source: Option<PeerId>,
+        message: GossipMessage,
+    },
+}
+
+/// Outbound requests awaiting a response, keyed by request id.
+pub type PendingRequests<T> = HashMap<OutboundRequestId, oneshot::Sender<CommandResult<T>>>;
+
+/// Builds the error a client gets for a failed response: the provider's `Failure` when it sent
+/// one, so that callers can match on it, or else its reason.
+///
+/// # Arguments
+///
+/// * `failure` - What the provider reported went wrong, if anything.
+/// * `reason` - The human readable reason of the response.
+/// * `fallback` - The reason to report when the provider gave none.
+fn response_error(
+    failure: Option<Failure>,
+    reason: Option<String>,
+    fallback: &str,
+) -> ClientError {
+    match failure {
+        Some(failure) => ClientError::Failure(failure),
+        None => ClientError::Refused(reason.unwrap_or_else(|| fallback.to_string())),
+    }
+}
+
+/// Answers the client waiting on a request that got no response, if it is in `pending`, with
+/// the reason, so that a peer that cannot be reached fails the request rather than the client.
+fn fail_pending<T>(pending: &mut PendingRequests<T>, request_id: &OutboundRequestId, reason: &str) {
+    if let Some(sender) = pending.remove(request_id) {
+        let _ = sender.send(Err(ClientError::RequestFailed(reason.to_string())));
+    }
 }
 
 /// Manages the event loop for network operations.
@@ -61,9 +104,19 @@ pub enum Event {
 /// * `pending_dial` - Tracks pending dial operations.
 /// * `pending_start_providing` - Tracks pending operations to start providing a record in the Kademlia DHT.
 /// * `pending_get_providers` - Tracks pending operations to get providers for a record in the Kademlia DHT.
+/// * `pending_find_providers` - Tracks pending operations to find every provider of a record in the Kademlia DHT, with those found so far.
+/// * `pending_bootstrap` - Tracks pending Kademlia bootstraps of the routing table.
 /// * `pending_request_share` - Tracks pending share request operations.
 /// * `pending_register_share` - Tracks pending operations to register a share.
 /// * `pending_refresh_share` - Tracks pending operations to refresh a share.
+/// * `pending_delete_share` - Tracks pending operations to delete a share.
+/// * `pending_list_keys` - Tracks pending operations to list keys.
+/// * `pending_stat_share` - Tracks pending operations to describe a share.
+/// * `pending_access` - Tracks pending operations to grant or revoke access to a share.
+/// * `pending_transfer` - Tracks pending operations to hand a share over to another owner.
+/// * `provider_statuses` - The latest health status heard from each provider, and when it
+///   expires.
+/// * `metrics` - The libp2p metrics the swarm events are recorded in, if they are served.
 ///
 /// # Examples
 ///
@@ -77,15 +130,23 @@ pub struct EventLoop {
     pub swarm: Swarm<Behaviour>,
     pub command_receiver: mpsc::Receiver<Command>,
     pub event_sender: mpsc::Sender<Event>,
-    pub pending_dial: HashMap<PeerId, oneshot::Sender<Result<(), Box<dyn Error + Send>>>>,
+    pub pending_dial: HashMap<PeerId, oneshot::Sender<CommandResult<()>>>,
     pub pending_start_providing: HashMap<kad::QueryId, oneshot::Sender<()>>,
     pub pending_get_providers: HashMap<kad::QueryId, oneshot::Sender<HashSet<PeerId>>>,
-    pub pending_request_share:
-        HashMap<OutboundRequestId, oneshot::Sender<Result<(u8, Vec<u8>), Box<dyn Error + Send>>>>,
-    pub pending_register_share:
-        HashMap<OutboundRequestId, oneshot::Sender<Result<bool, Box<dyn Error + Send>>>>,
-    pub pending_refresh_share:
-        HashMap<OutboundRequestId, oneshot::Sender<Result<bool, Box<dyn Error + Send>>>>,
+    pub pending_find_providers:
+        HashMap<kad::QueryId, (HashSet<PeerId>, oneshot::Sender<HashSet<PeerId>>)>,
+    pub pending_bootstrap: HashMap<kad::QueryId, oneshot::Sender<CommandResult<()>>>,
+    pub pending_request_share: PendingRequests<FieldShare>,
+    pub pending_register_share: PendingRequests<RegisterShareStatus>,
+    pub pending_refresh_share: PendingRequests<bool>,
+    pub pending_delete_share: PendingRequests<DeleteShareStatus>,
+    pub pending_list_keys: PendingRequests<(Vec<String>, Option<String>)>,
+    pub pending_stat_share: PendingRequests<StatShareStatus>,
+    pub pending_access: PendingRequests<bool>,
+    pub pending_transfer: PendingRequests<bool>,
+    pub provider_statuses: HashMap<PeerId, (ProviderStatus, Instant)>,
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<libp2p::metrics::Metrics>,
 }
 
 impl EventLoop {
@@ -112,10 +173,104 @@ impl EventLoop {
             pending_dial: Default::default(),
             pending_start_providing: Default::default(),
             pending_get_providers: Default::default(),
+            pending_find_providers: Default::default(),
+            pending_bootstrap: Default::default(),
             pending_request_share: Default::default(),
             pending_register_share: Default::default(),
             pending_refresh_share: Default::default(),
+            pending_delete_share: Default::default(),
+            pending_list_keys: Default::default(),
+            pending_stat_share: Default::default(),
+            pending_access: Default::default(),
+            pending_transfer: Default::default(),
+            provider_statuses: Default::default(),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Records the events of the swarm in libp2p metrics registered in `registry`, to be served
+    /// along with the provider's own (see `metrics::serve_metrics`).
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry the libp2p metrics are added to, under the `libp2p` prefix.
+    ///
+    /// # Returns
+    ///
+    /// The `EventLoop`, recording its events.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, registry: &mut libp2p::metrics::Registry) -> Self {
+        self.metrics = Some(libp2p::metrics::Metrics::new(registry));
+        self
+    }
+
+    /// Caches a health status received on the health topic, unless it cannot be decoded or was
+    /// not signed by the provider it describes.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The verified publisher of the status.
+    /// * `data` - The payload of the gossipsub message.
+    fn cache_status(&mut self, source: Option<PeerId>, data: &[u8]) {
+        let status = match ProviderStatus::from_bytes(data) {
+            Ok(status) => status,
+            Err(e) => return debug!("Ignoring malformed provider status: {e}"),
+        };
+        let peer = match status.verify(source) {
+            Ok(peer) => peer,
+            Err(e) => return debug!("Rejected provider status: {e}"),
+        };
+        let ttl = Duration::from_secs(status.interval_secs).saturating_mul(STATUS_EXPIRY_PERIODS);
+        let Some(expires_at) = Instant::now().checked_add(ttl) else {
+            return debug!("Rejected provider status of {peer}: interval out of range");
+        };
+        self.provider_statuses.insert(peer, (status, expires_at));
+    }
+
+    /// Counts the operations awaiting an answer in each of the pending maps.
+    ///
+    /// # Returns
+    ///
+    /// The number of pending operations of each kind, named after its map.
+    pub fn pending_counts(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("dial", self.pending_dial.len()),
+            ("start_providing", self.pending_start_providing.len()),
+            ("get_providers", self.pending_get_providers.len()),
+            ("find_providers", self.pending_find_providers.len()),
+            ("bootstrap", self.pending_bootstrap.len()),
+            ("request_share", self.pending_request_share.len()),
+            ("register_share", self.pending_register_share.len()),
+            ("refresh_share", self.pending_refresh_share.len()),
+            ("delete_share", self.pending_delete_share.len()),
+            ("list_keys", self.pending_list_keys.len()),
+            ("stat_share", self.pending_stat_share.len()),
+            ("access", self.pending_access.len()),
+            ("transfer", self.pending_transfer.len()),
+        ]
+    }
+
+    /// Fails the pending request `request_id`, whichever kind of request it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - The id of the outbound request.
+    /// * `reason` - Why the request failed, reported to the waiting client.
+    fn fail_pending_request(&mut self, request_id: OutboundRequestId, reason: &str) {
+        fn fail<T>(pending: &mut PendingRequests<T>, request_id: OutboundRequestId, reason: &str) {
+            if let Some(sender) = pending.remove(&request_id) {
+                let _ = sender.send(Err(ClientError::Refused(reason.to_string())));
+            }
         }
+        fail(&mut self.pending_request_share, request_id, reason);
+        fail(&mut self.pending_register_share, request_id, reason);
+        fail(&mut self.pending_refresh_share, request_id, reason);
+        fail(&mut self.pending_delete_share, request_id, reason);
+        fail(&mut self.pending_list_keys, request_id, reason);
+        fail(&mut self.pending_stat_share, request_id, reason);
+        fail(&mut self.pending_access, request_id, reason);
+        fail(&mut self.pending_transfer, request_id, reason);
     }
 
     /// Runs the event loop.
@@ -135,6 +290,11 @@ impl EventLoop {
             futures::select! {
                 event = self.swarm.next() => self.handle_event(event.expect("Swarm stream to be infinite."), external_address).await,
                 command = self.command_receiver.next() => match command {
+                    // Asked to stop, thus shutting down the network event loop.
+                    Some(Command::Shutdown { sender }) => {
+                        let _ = sender.send(());
+                        return;
+                    }
                     Some(c) => self.handle_command(c).await,
                     // Command channel closed, thus shutting down the network event loop.
                     None => return,
@@ -149,6 +309,10 @@ impl EventLoop {
     ///
     /// * `event` - The event to handle.
     async fn handle_event(&mut self, event: SwarmEvent<BehaviourEvent>, external_address: Option<IpAddr>) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            crate::metrics::record(metrics, &event);
+        }
         match event {
             SwarmEvent::Behaviour(BehaviourEvent::Kademlia(
                 kad::Event::OutboundQueryProgressed {
@@ -175,7 +339,8 @@ impl EventLoop {
                 },
             )) => {
                 if let Some(sender) = self.pending_get_providers.remove(&id) {
-                    sender.send(providers).expect("Receiver not to be dropped");
+                    // the caller may have stopped waiting for the lookup
+                    let _ = sender.send(providers);
 
                     // Finish the query. We are only interested in the first result.
                     self.swarm
@@ -184,6 +349,8 @@ impl EventLoop {
                         .query_mut(&id)
                         .unwrap()
                         .finish();
+                } else if let Some((found, _)) = self.pending_find_providers.get_mut(&id) {
+                    found.extend(providers);
                 }
             }
             SwarmEvent::Behaviour(BehaviourEvent::Identify(e)) => {
@@ -204,7 +371,7 @@ impl EventLoop {
                     self.swarm.add_external_address(observed_addr);
                     
                      // TODO: The following should no longer be necessary after https://github.com/libp2p/rust-libp2p/pull/4371.
-                    if protocols.iter().any(|p| *p == kad::PROTOCOL_NAME) {
+                    if protocols.contains(&kad::PROTOCOL_NAME) {
                         for addr in listen_addrs {
                             self.swarm
                                 .behaviour_mut()
@@ -217,13 +384,24 @@ impl EventLoop {
             }
             SwarmEvent::Behaviour(BehaviourEvent::Kademlia(
                 kad::Event::OutboundQueryProgressed {
+                    id,
                     result:
-                        kad::QueryResult::GetProviders(Ok(
-                            kad::GetProvidersOk::FinishedWithNoAdditionalRecord { .. },
-                        )),
+                        kad::QueryResult::GetProviders(
+                            Ok(kad::GetProvidersOk::FinishedWithNoAdditionalRecord { .. })
+                            | Err(_),
+                        ),
                     ..
                 },
-            )) => {}
+            )) => {
+                // the lookup ended without finding a provider, which is an answer too, or a
+                // lookup for every provider completed
+                if let Some(sender) = self.pending_get_providers.remove(&id) {
+                    let _ = sender.send(HashSet::new());
+                }
+                if let Some((found, sender)) = self.pending_find_providers.remove(&id) {
+                    let _ = sender.send(found);
+                }
+            }
             SwarmEvent::Behaviour(BehaviourEvent::Kademlia(kad::Event::RoutingUpdated {
                 peer,
                 addresses,
@@ -243,19 +421,66 @@ impl EventLoop {
 
                 let _ = self.swarm.behaviour_mut().kademlia.bootstrap();
             }
+            SwarmEvent::Behaviour(BehaviourEvent::Kademlia(
+                kad::Event::OutboundQueryProgressed {
+                    id,
+                    result: kad::QueryResult::Bootstrap(result),
+                    step,
+                    ..
+                },
+            )) => {
+                // A bootstrap progresses once per bucket it refreshes, and is only done on its
+                // last step, or as soon as it fails.
+                if step.last || result.is_err() {
+                    if let Some(sender) = self.pending_bootstrap.remove(&id) {
+                        let _ = sender.send(
+                            result
+                                .map(|_| ())
+                                .map_err(ClientError::from),
+                        );
+                    }
+                }
+            }
             SwarmEvent::Behaviour(BehaviourEvent::Kademlia(_)) => {}
+            SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                message,
+                ..
+            })) if message.topic == IdentTopic::new(HEALTH_TOPIC).hash() => {
+                self.cache_status(message.source, &message.data);
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                message,
+                ..
+            })) => match GossipMessage::from_bytes(&message.data) {
+                // gossip is best effort: a node not reading its events drops it
+                Ok(gossip) => {
+                    let event = Event::Gossip {
+                        source: message.source,
+                        message: gossip,
+                    };
+                    if let Err(e) = self.event_sender.try_send(event) {
+                        debug!("Dropped gossip message: {e}");
+                    }
+                }
+                Err(e) => debug!("Ignoring malformed gossip message: {e}"),
+            },
             SwarmEvent::Behaviour(BehaviourEvent::RequestResponse(
-                request_response::Event::Message { message, .. },
+                request_response::Event::Message { peer, message },
             )) => match message {
                 request_response::Message::Request {
                     request, channel, ..
                 } => {
-                    debug!("Received request: {request:?} from {channel:?}");
+                    let trace_id = request.trace_id();
+                    let span =
+                        debug_span!("inbound_request", %peer, trace_id = trace_id.map(display));
+                    span.in_scope(|| debug!("Received request: {request:?} from {peer}"));
                     self.event_sender
                         .send(Event::InboundRequest {
-                            request: request,
+                            request,
+                            peer,
                             channel,
                         })
+                        .instrument(span)
                         .await
                         .expect("Event receiver not to be dropped.");
                 }
@@ -265,11 +490,20 @@ impl EventLoop {
                 } => match response {
                     Response::GetShare(res) => {
                         debug!("Received response for share {}.", request_id);
+                        let result: CommandResult<FieldShare> = if res.success {
+                            Ok(FieldShare {
+                                share: res.share,
+                                #[cfg(feature = "sss16")]
+                                field: res.field,
+                            })
+                        } else {
+                            Err(response_error(res.failure, res.reason, "share refused"))
+                        };
                         let _ = self
                             .pending_request_share
                             .remove(&request_id)
                             .expect("Request to still be pending.")
-                            .send(Ok(res.share));
+                            .send(result);
                     }
                     Response::RegisterShare(res) => {
                         debug!("Received response to register share {}.", res.success);
@@ -277,15 +511,94 @@ impl EventLoop {
                             .pending_register_share
                             .remove(&request_id)
                             .expect("Request to still be pending.")
-                            .send(Ok(res.success));
+                            .send(Ok(res.into()));
                     }
                     Response::RefreshShares(res) => {
                         debug!("Received response to refresh shares {}.", res.success);
+                        let result: CommandResult<bool> = match (res.failure, res.reason) {
+                            (None, None) => Ok(res.success),
+                            _ if res.success => Ok(true),
+                            (failure, reason) => {
+                                Err(response_error(failure, reason, "refresh refused"))
+                            }
+                        };
                         let _ = self
                             .pending_refresh_share
                             .remove(&request_id)
                             .expect("Request to still be pending.")
-                            .send(Ok(res.success));
+                            .send(result);
+                    }
+                    Response::DeleteShare(res) => {
+                        debug!("Received response to delete share {:?}.", res.status);
+                        let _ = self
+                            .pending_delete_share
+                            .remove(&request_id)
+                            .expect("Request to still be pending.")
+                            .send(Ok(res.status));
+                    }
+                    Response::ListKeys(res) => {
+                        debug!("Received response to list keys {}.", res.success);
+                        let result: CommandResult<(Vec<String>, Option<String>)> = if res.success {
+                            Ok((res.keys, res.next_cursor))
+                        } else {
+                            Err(response_error(res.failure, res.reason, "listing refused"))
+                        };
+                        let _ = self
+                            .pending_list_keys
+                            .remove(&request_id)
+                            .expect("Request to still be pending.")
+                            .send(result);
+                    }
+                    Response::StatShare(res) => {
+                        debug!("Received response to stat share {:?}.", res.status);
+                        let _ = self
+                            .pending_stat_share
+                            .remove(&request_id)
+                            .expect("Request to still be pending.")
+                            .send(Ok(res.status));
+                    }
+                    Response::Access(res) => {
+                        debug!("Received response to change access {}.", res.success);
+                        let result: CommandResult<bool> = match (res.failure, res.reason) {
+                            (None, None) => Ok(res.success),
+                            _ if res.success => Ok(true),
+                            (failure, reason) => {
+                                Err(response_error(failure, reason, "access change refused"))
+                            }
+                        };
+                        let _ = self
+                            .pending_access
+                            .remove(&request_id)
+                            .expect("Request to still be pending.")
+                            .send(result);
+                    }
+                    Response::TransferOwnership(res) => {
+                        debug!("Received response to transfer ownership {}.", res.success);
+                        let result: CommandResult<bool> = if res.success {
+                            Ok(true)
+                        } else {
+                            Err(response_error(res.failure, res.reason, "transfer refused"))
+                        };
+                        let _ = self
+                            .pending_transfer
+                            .remove(&request_id)
+                            .expect("Request to still be pending.")
+                            .send(result);
+                    }
+                    Response::InvalidRequest(res) => {
+                        debug!("Request {} was rejected: {}.", request_id, res.reason);
+                        self.fail_pending_request(request_id, &res.reason);
+                    }
+                    Response::RateLimited(res) => {
+                        let reason =
+                            format!("rate limited, retry after {} ms", res.retry_after_millis);
+                        debug!("Request {} was turned down: {}.", request_id, reason);
+                        self.fail_pending_request(request_id, &reason);
+                    }
+                    Response::Busy(res) => {
+                        let reason = format!("peer is busy: {}", res.reason);
+                        debug!("Request {} was turned down: {}.", request_id, reason);
+                        self.fail_pending_request(request_id, &reason);
                     }
                 },
             },
@@ -299,9 +612,15 @@ impl EventLoop {
                 },
             )) => {
                 debug!("Request to {peer} failed with error: {error}");
-                let _ = self.pending_register_share.remove(&request_id);
-                let _ = self.pending_request_share.remove(&request_id);
-                let _ = self.pending_refresh_share.remove(&request_id);
+                let error = error.to_string();
+                fail_pending(&mut self.pending_register_share, &request_id, &error);
+                fail_pending(&mut self.pending_request_share, &request_id, &error);
+                fail_pending(&mut self.pending_refresh_share, &request_id, &error);
+                fail_pending(&mut self.pending_delete_share, &request_id, &error);
+                fail_pending(&mut self.pending_list_keys, &request_id, &error);
+                fail_pending(&mut self.pending_stat_share, &request_id, &error);
+                fail_pending(&mut self.pending_access, &request_id, &error);
+                fail_pending(&mut self.pending_transfer, &request_id, &error);
             }
 
             SwarmEvent::Behaviour(BehaviourEvent::RequestResponse(
@@ -350,7 +669,7 @@ impl EventLoop {
             SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
                 if let Some(peer_id) = peer_id {
                     if let Some(sender) = self.pending_dial.remove(&peer_id) {
-                        let _ = sender.send(Err(Box::new(error)));
+                        let _ = sender.send(Err(error.into()));
                     }
                 }
             }
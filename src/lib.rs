@@ -42,6 +42,7 @@
 //! To split a secret using Shamir's Secret Sharing:
 //!
 //! ```rust
+//! # #[cfg(feature = "sss")] {
 //! use shard::sss::Polynomial;
 //! use gf256::gf256;
 //!
@@ -52,6 +53,7 @@
 //! // Generate shares
 //! let shares: Vec<(u8, gf256)> = (1..=5).map(|i| (i, poly.evaluate(gf256::new(i)))).collect();
 //! // Now `shares` holds 5 shares of the secret
+//! # }
 //! ```
 //!
 //! ### Example: Refreshing Shares
@@ -77,58 +79,185 @@
 //!
 //! ## Modules
 //!
+//! - `chunked`: Splits files too large for one share into chunks.
+//! - `cli`: Defines the documents the command line prints with `--json`.
 //! - `client`: Defines the network client functionality.
 //! - `command`: Contains commands used in network operations.
+//! - `error`: Defines `shard::Error`, the error wrapping the errors of the other modules.
 //! - `event`: Defines various network events.
+//! - `metrics`: Serves the metrics of a provider to Prometheus.
 //! - `network`: Implements network behaviors and utilities.
 //! - `protocol`: Defines the network communication protocol.
 //! - `repository`: Manages data storage and retrieval.
+//! - `runtime`: Spawns tasks and keeps time on the async runtime the crate is built for.
 //! - `sss`: Implements Shamir's Secret Sharing and proactive secret refreshing.
+//! - `sss16`: Splits secrets into up to 65,535 shares over GF(2^16), with the `sss16` feature;
+//!   providers store, serve and refresh the shares over the field they were split over.
+//! - `testing`: Starts networks of providers and clients in the process for tests, with the
+//!   `test-util` feature.
+//!
+//! ## Features
+//!
+//! - `sss` (default): The `sss`, `offline` and `chunked` modules, with no networking or storage.
+//! - `sss16`: The `sss16` module, 16-bit share indexes in share entries and the protocol, and
+//!   splits into more than 255 shares with `shard split`; enables `sss`.
+//! - `rayon`: Splits large secrets with `sss::split_secret` on rayon's thread pool; enables `sss`.
+//! - `storage` (default): The `repository` module and its sled store; enables `sss`.
+//! - `net` (default): The `client`, `command`, `config`, `event`, `network`, `protocol`,
+//!   `provider` and `runtime` modules, over libp2p; enables `sss` and `storage`, and needs one of
+//!   the runtimes below.
+//! - `rt-tokio` (default): Runs the network and the provider tasks on tokio; enables `net`.
+//! - `rt-async-std`: Runs them on async-std instead, for applications built on it; enables
+//!   `net`, and cannot be combined with `rt-tokio` or the features enabling it.
+//! - `cli`: The `cli` module used by the `shard` binary, which needs it; enables `rt-tokio`.
+//! - `metrics`: The `metrics` module, served by hyper on tokio, and `--metrics-addr` in the
+//!   `shard` binary; enables `rt-tokio`.
+//! - `sqlite`: The SQLite share store; enables `storage`.
+//! - `test-util`: The `testing` module; enables `net` and `rt-tokio`.
+//!
+//! Applications that only split and combine secrets can depend on the crate with
+//! `default-features = false, features = ["sss"]`, leaving out libp2p, tokio and sled, and those
+//! on async-std with `default-features = false, features = ["rt-async-std"]`.
 //!
 //! [More detailed documentation and examples are provided in each module.]
 
 /// The `client` module defines the network client functionalities, enabling interactions with the
 /// network, such as sending and receiving messages, handling requests, and other peer-to-peer
 /// communication features.
+#[cfg(feature = "net")]
 pub mod client;
 
 /// The `command` module contains definitions of various commands used in network operations. These
 /// commands represent different actions that can be performed in the network, such as dialing other
 /// peers, starting to listen for connections, and managing secret shares.
+#[cfg(feature = "net")]
 pub mod command;
 
+/// The `error` module defines `Error`, the error of the crate, which wraps the error of the
+/// module that failed so that callers can match on it and convert it with `?`.
+pub mod error;
+pub use error::{Error, Result};
+
 /// The `event` module defines the different types of events that can occur in the network, such as
 /// inbound requests or updates in the network state. This module helps in handling asynchronous
 /// network events in a structured manner.
+#[cfg(feature = "net")]
 pub mod event;
 
 /// The `network` module implements the necessary network behaviors and utilities. It encapsulates
 /// the logic for network interactions, including setting up the network, handling peer discovery,
 /// and managing communication protocols.
+#[cfg(feature = "net")]
 pub mod network;
 
 /// The `protocol` module defines the communication protocols used in the network. It includes the
 /// specifications for various request and response formats, ensuring standardized communication
 /// across different network nodes.
+#[cfg(feature = "net")]
 pub mod protocol;
 
 /// The `repository` module manages data storage and retrieval. It is responsible for persisting
 /// important data, like secret shares, and provides interfaces for accessing and updating this data.
+#[cfg(feature = "storage")]
 pub mod repository;
 
+/// The `runtime` module is what the library spawns its tasks and keeps its time with: tokio's
+/// own functions and types with `rt-tokio`, or the same names implemented over async-std with
+/// `rt-async-std`. Only one of them can be enabled.
+#[cfg(feature = "net")]
+pub mod runtime;
+
 /// The `sss` (Shamir's Secret Sharing) module is a crucial component of the library. It implements
 /// the Shamir's Secret Sharing algorithm and proactive secret sharing. This module provides
 /// functionalities to split secrets into shares, distribute them, and proactively refresh these
 /// shares to enhance security.
+#[cfg(feature = "sss")]
 pub mod sss;
 
+/// The `sss16` module splits secrets the way `sss` does but over GF(2^16), with 16-bit share
+/// indexes, for splits into more than the 255 shares GF(2^8) allows. Its shares are twice as long
+/// and are not interchangeable with those of `sss`. With the feature, share entries and the
+/// protocol carry 16-bit indexes with the field the shares were split over, tagged
+/// `ShareField::Gf65536` for these.
+#[cfg(feature = "sss16")]
+pub mod sss16;
+
 /// The `provider` module defines the `Provider` trait, which is used to implement different
 /// providers for the network. A provider is responsible for managing the network state, including
 /// the secret shares and the peer list.
+#[cfg(feature = "net")]
 pub mod provider;
 
+/// The `rotation` module hands every share of an owner over to a new identity, such as when its
+/// key is compromised or rotated, and reports the transfers left to resume.
+#[cfg(feature = "net")]
+pub mod rotation;
+
 /// The `constants` module defines various constants used in the library.
 pub mod constants;
 
 /// The `config` module defines the `Config` struct, which is used to configure the network.
-pub mod config;
\ No newline at end of file
+#[cfg(feature = "net")]
+pub mod config;
+
+/// The `chunked` module splits files too large for a single share into chunks, each split into
+/// shares of its own, and describes them in a manifest registered under the file's key.
+#[cfg(feature = "sss")]
+pub mod chunked;
+
+/// The `offline` module splits secrets into share files and rebuilds and refreshes them from
+/// those files, for air-gapped machines with no network at all.
+#[cfg(feature = "sss")]
+pub mod offline;
+
+/// The `metrics` module serves the metrics of a provider and of its libp2p swarm over HTTP, in the
+/// Prometheus text format, along with a health check for supervisors.
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+/// The `testing` module starts networks of providers and clients in the process, connected over
+/// the memory transport, for tests of the crate and of the applications built on it.
+#[cfg(all(feature = "net", any(test, feature = "test-util")))]
+pub mod testing;
+
+/// The `cli` module defines what the command line prints, so that tools driving it can parse its
+/// output.
+#[cfg(feature = "cli")]
+pub mod cli;
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    /// Collects the lines of the Rust sources under `dir` that call a printing macro, skipping
+    /// comments and the `cli` module, whose prompts write to the terminal.
+    fn printing_lines(dir: &Path, found: &mut Vec<String>) {
+        let macros = ["println", "print", "eprintln", "eprint", "dbg"].map(|m| format!("{}!(", m));
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                if !path.ends_with("bin") && !path.ends_with("cli") {
+                    printing_lines(&path, found);
+                }
+                continue;
+            }
+            if path.extension().is_none_or(|extension| extension != "rs") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).unwrap();
+            for (number, line) in source.lines().enumerate() {
+                let line = line.trim_start();
+                if !line.starts_with("//") && macros.iter().any(|m| line.contains(m.as_str())) {
+                    found.push(format!("{}:{}: {}", path.display(), number + 1, line));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_library_never_prints() {
+        let mut found = Vec::new();
+        printing_lines(&Path::new(env!("CARGO_MANIFEST_DIR")).join("src"), &mut found);
+        assert!(found.is_empty(), "library code prints:\n{}", found.join("\n"));
+    }
+}
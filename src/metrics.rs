@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use libp2p::metrics::{Metrics, Recorder};
+use libp2p::swarm::SwarmEvent;
+use prometheus_client::collector::Collector;
+use prometheus_client::encoding::{DescriptorEncoder, EncodeMetric};
+use prometheus_client::metrics::counter::ConstCounter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::{ConstGauge, Gauge};
+use prometheus_client::metrics::MetricType;
+use prometheus_client::registry::Registry;
+use tokio_util::sync::CancellationToken;
+
+use crate::client::Client;
+use crate::network::BehaviourEvent;
+use crate::provider::SharedMetrics;
+
+/// The content type of the OpenMetrics text format, which Prometheus scrapes.
+const METRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Records an event of the swarm in `metrics`, along with the events of the behaviours libp2p
+/// keeps metrics for.
+///
+/// # Arguments
+///
+/// * `metrics` - The libp2p metrics of the event loop.
+/// * `event` - The event the swarm emitted.
+pub(crate) fn record(metrics: &Metrics, event: &SwarmEvent<BehaviourEvent>) {
+    match event {
+        SwarmEvent::Behaviour(BehaviourEvent::Kademlia(e)) => metrics.record(e),
+        SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(e)) => metrics.record(e),
+        SwarmEvent::Behaviour(BehaviourEvent::Identify(e)) => metrics.record(e),
+        _ => {}
+    }
+    metrics.record(event);
+}
+
+/// Encodes the counters and gauges of a provider (see `ProviderMetrics`) on each scrape, so that
+/// they are never out of step with those published in its health status.
+#[derive(Debug)]
+struct ProviderCollector(SharedMetrics);
+
+impl Collector for ProviderCollector {
+    fn encode(&self, mut encoder: DescriptorEncoder) -> Result<(), fmt::Error> {
+        let snapshot = self.0.snapshot();
+        let counters = [
+            ("shard_registrations", "Shares registered.", snapshot.registrations),
+            ("shard_gets_served", "Shares sent to their owners or readers.", snapshot.gets_served),
+            (
+                "shard_refreshes_applied",
+                "Refreshes applied to stored shares.",
+                snapshot.refreshes_applied,
+            ),
+            (
+                "shard_refreshes_initiated",
+                "Refreshes initiated as the coordinator of a share.",
+                snapshot.refreshes_initiated,
+            ),
+            (
+                "shard_invalid_requests",
+                "Requests rejected as malformed.",
+                snapshot.invalid_requests,
+            ),
+            (
+                "shard_rate_limited_requests",
+                "Requests turned down by the rate limiter.",
+                snapshot.rate_limited_requests,
+            ),
+            (
+                "shard_provider_events_dropped",
+                "Provider events dropped because their subscriber was behind.",
+                self.0.events_dropped(),
+            ),
+        ];
+        for (name, help, value) in counters {
+            let counter = ConstCounter::new(value);
+            counter.encode(encoder.encode_descriptor(name, help, None, counter.metric_type())?)?;
+        }
+        encode_counts(
+            &mut encoder,
+            ("shard_failures", "Failed requests, by class of failure."),
+            "class",
+            &snapshot.failures,
+        )?;
+        encode_counts(
+            &mut encoder,
+            ("shard_inbound_requests", "Requests received, by operation."),
+            "operation",
+            &self.0.inbound_requests(),
+        )?;
+
+        let stored = ConstGauge::new(self.0.shares_stored() as i64);
+        let help = "Shares stored, as of the last health status.";
+        stored.encode(encoder.encode_descriptor(
+            "shard_shares_stored",
+            help,
+            None,
+            stored.metric_type(),
+        )?)?;
+        // a provider holding no shares, or yet to refresh them, has no age to report
+        if let Some(age) = snapshot.oldest_refresh_age_secs {
+            let age = ConstGauge::new(age as i64);
+            let help = "Seconds since the least recently refreshed share was refreshed.";
+            age.encode(encoder.encode_descriptor(
+                "shard_oldest_refresh_age_seconds",
+                help,
+                None,
+                age.metric_type(),
+            )?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Encodes a counter family with one counter for each entry of `counts`.
+///
+/// # Arguments
+///
+/// * `encoder` - The encoder of the scrape.
+/// * `(name, help)` - The name and description of the family.
+/// * `label` - The label the keys of `counts` are given under.
+/// * `counts` - The value of each counter, by label value.
+fn encode_counts(
+    encoder: &mut DescriptorEncoder,
+    (name, help): (&str, &str),
+    label: &str,
+    counts: &BTreeMap<String, u64>,
+) -> Result<(), fmt::Error> {
+    let mut family = encoder.encode_descriptor(name, help, None, MetricType::Counter)?;
+    for (value, count) in counts {
+        ConstCounter::new(*count).encode(family.encode_family(&[(label, value.as_str())])?)?;
+    }
+    Ok(())
+}
+
+/// The metrics a provider serves on its `/metrics` endpoint: those libp2p keeps of its swarm,
+/// registered in the registry given to `EventLoop::with_metrics`, and its own.
+///
+/// # Fields
+///
+/// * `registry` - The registry every metric is encoded from.
+/// * `pending` - The number of operations of the event loop awaiting an answer, by kind, read
+///   from the event loop on each scrape.
+/// * `provider` - The metrics of the provider.
+/// * `client` - The client of the provider's network, which the event loop is asked through.
+pub struct MetricsExporter {
+    registry: Registry,
+    pending: Family<Vec<(String, String)>, Gauge>,
+    provider: SharedMetrics,
+    client: Client,
+}
+
+impl MetricsExporter {
+    /// Creates the exporter of a provider.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry holding the libp2p metrics, which the provider's are added to.
+    /// * `provider` - The metrics of the provider, as given to `run_loop`.
+    /// * `client` - The client of the provider's network.
+    ///
+    /// # Returns
+    ///
+    /// The exporter, to be served with `serve_metrics`.
+    pub fn new(mut registry: Registry, provider: SharedMetrics, client: Client) -> Self {
+        let pending = Family::default();
+        registry.register(
+            "shard_pending_requests",
+            "Operations of the event loop awaiting an answer, by kind.",
+            pending.clone(),
+        );
+        registry.register_collector(Box::new(ProviderCollector(Arc::clone(&provider))));
+        MetricsExporter {
+            registry,
+            pending,
+            provider,
+            client,
+        }
+    }
+
+    /// Encodes every metric in the OpenMetrics text format, after reading the pending operations
+    /// from the event loop.
+    pub async fn render(&self) -> Result<String, fmt::Error> {
+        for (kind, count) in self.client.clone().pending_requests().await {
+            let labels = vec![("kind".to_string(), kind.to_string())];
+            self.pending.get_or_create(&labels).set(count as i64);
+        }
+        let mut text = String::new();
+        prometheus_client::encoding::text::encode(&mut text, &self.registry)?;
+        Ok(text)
+    }
+
+    /// Returns whether the provider is healthy: its swarm is listening, and it is serving
+    /// requests from its opened database.
+    pub async fn is_healthy(&self) -> bool {
+        self.provider.is_ready() && !self.client.clone().listen_addrs().await.is_empty()
+    }
+
+    /// Answers a request to the endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The HTTP request received.
+    ///
+    /// # Returns
+    ///
+    /// The metrics for `GET /metrics`; 200 for `GET /healthz` once the provider is healthy, and
+    /// 503 until then; and 404 for anything else.
+    async fn respond(&self, request: Request<Body>) -> Response<Body> {
+        match (request.method(), request.uri().path()) {
+            (&Method::GET, "/metrics") => match self.render().await {
+                Ok(text) => {
+                    let mut response = Response::new(Body::from(text));
+                    let content_type = METRICS_CONTENT_TYPE.parse().expect("a valid header");
+                    response.headers_mut().insert(CONTENT_TYPE, content_type);
+                    response
+                }
+                Err(_) => plain(StatusCode::INTERNAL_SERVER_ERROR, "cannot encode the metrics"),
+            },
+            (&Method::GET, "/healthz") if self.is_healthy().await => plain(StatusCode::OK, "ok"),
+            (&Method::GET, "/healthz") => plain(StatusCode::SERVICE_UNAVAILABLE, "not ready"),
+            _ => plain(StatusCode::NOT_FOUND, "not found"),
+        }
+    }
+}
+
+/// Builds a plain text response.
+fn plain(status: StatusCode, body: &'static str) -> Response<Body> {
+    let mut response = Response::new(Body::from(format!("{body}\n")));
+    *response.status_mut() = status;
+    response
+}
+
+/// Binds the metrics endpoint of a provider to `addr`, serving `/metrics` and `/healthz`.
+///
+/// # Arguments
+///
+/// * `addr` - The address to listen on, with port 0 for any free port.
+/// * `exporter` - The metrics served.
+/// * `shutdown` - The token cancelled to stop the provider, which stops the endpoint too.
+///
+/// # Returns
+///
+/// The address bound, and the server to run, which resolves once `shutdown` is cancelled and the
+/// requests in flight are answered. An error if `addr` cannot be bound.
+///
+/// # Examples
+///
+/// ```ignore
+/// let exporter = MetricsExporter::new(registry, metrics, client.clone());
+/// let (addr, server) = serve_metrics("127.0.0.1:9464".parse()?, exporter, shutdown.clone())?;
+/// tokio::spawn(server);
+/// ```
+pub fn serve_metrics(
+    addr: SocketAddr,
+    exporter: MetricsExporter,
+    shutdown: CancellationToken,
+) -> Result<(SocketAddr, impl Future<Output = Result<(), hyper::Error>>), hyper::Error> {
+    let exporter = Arc::new(exporter);
+    let make_service = make_service_fn(move |_| {
+        let exporter = Arc::clone(&exporter);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let exporter = Arc::clone(&exporter);
+                async move { Ok::<_, Infallible>(exporter.respond(request).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    let bound = server.local_addr();
+    Ok((bound, server.with_graceful_shutdown(shutdown.cancelled_owned())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network;
+    use crate::repository::AuditOperation;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    /// Gets `path` from the endpoint at `addr`, returning the status line and the body.
+    async fn get(addr: SocketAddr, path: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_metrics_and_health_are_served_until_shutdown() {
+        let (mut client, _events, event_loop, _) = network::new(None).await.unwrap();
+        let mut registry = Registry::default();
+        tokio::spawn(event_loop.with_metrics(&mut registry).run(None));
+        let provider = SharedMetrics::default();
+        provider.record_registration();
+        provider.record_inbound_request(AuditOperation::Register);
+        provider.set_shares_stored(1);
+
+        let shutdown = CancellationToken::new();
+        let exporter = MetricsExporter::new(registry, Arc::clone(&provider), client.clone());
+        let any_port = "127.0.0.1:0".parse().unwrap();
+        let (addr, server) = serve_metrics(any_port, exporter, shutdown.clone()).unwrap();
+        let server = tokio::spawn(server);
+
+        assert_eq!(get(addr, "/healthz").await.0, "HTTP/1.1 503 Service Unavailable");
+        client
+            .start_listening("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .await
+            .unwrap();
+        provider.set_ready(true);
+        let mut health = String::new();
+        for _ in 0..50 {
+            health = get(addr, "/healthz").await.0;
+            if health.ends_with("200 OK") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(health, "HTTP/1.1 200 OK");
+
+        let (status, body) = get(addr, "/metrics").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        for expected in [
+            "shard_registrations_total 1\n",
+            "shard_inbound_requests_total{operation=\"register\"} 1\n",
+            "shard_shares_stored 1\n",
+            "shard_pending_requests{kind=\"dial\"} 0\n",
+            "# TYPE libp2p_swarm_new_listen_addr counter\n",
+            "# TYPE libp2p_kad_",
+        ] {
+            assert!(body.contains(expected), "{expected:?} missing from\n{body}");
+        }
+        assert!(!body.contains("shard_oldest_refresh_age_seconds"));
+        assert_eq!(get(addr, "/other").await.0, "HTTP/1.1 404 Not Found");
+
+        shutdown.cancel();
+        let stopped = tokio::time::timeout(Duration::from_secs(5), server).await;
+        assert!(matches!(stopped, Ok(Ok(Ok(())))));
+    }
+}
@@ -1,24 +1,86 @@
 use crate::client::Client;
+use crate::constants::{
+    AGENT_VERSION, DEFAULT_LISTEN_ADDRS, DEFAULT_TIMEOUT_SECONDS, EVENT_BUFFER, GOSSIP_TOPIC,
+    HEALTH_TOPIC, LISTEN_ADDR_POLLS,
+};
 use crate::event::{Event, EventLoop};
 use crate::protocol::{Request, Response};
+use crate::runtime;
 
 use futures::channel::mpsc;
 use futures::prelude::*;
 
+use libp2p::core::{transport::MemoryTransport, upgrade, Transport};
 use libp2p::gossipsub::IdentTopic;
 use libp2p::request_response::ProtocolSupport;
-use libp2p::PeerId;
+use libp2p::{Multiaddr, PeerId};
 use libp2p::{
-    gossipsub, identify, identity, kad, noise, request_response, swarm::NetworkBehaviour, tcp,
-    yamux, StreamProtocol,
+    allow_block_list, gossipsub, identify, identity, kad, noise, request_response,
+    swarm::NetworkBehaviour, tcp, yamux, StreamProtocol, Swarm,
 };
 use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
 use std::error::Error;
 use std::hash::Hash;
 use std::hash::Hasher;
+use std::net::IpAddr;
 use std::time::Duration;
 use std::vec;
-use tracing::debug;
+use tracing::{debug, error};
+
+/// How a node joins the network: the identity it runs as, the network it joins, where it listens
+/// and whom it dials first. `provider::ShardNode` is built from one.
+///
+/// # Fields
+///
+/// * `keypair` - The keypair the local peer ID is derived from.
+/// * `network_id` - The network to join, or `None` for the default one (see `with_network`).
+/// * `timeout` - How long a request waits for its response, and a DHT query for its result.
+/// * `listen_addrs` - The addresses to listen on, or every IPv4 and IPv6 interface when empty
+///   (see `start_listeners`).
+/// * `external_addrs` - The addresses the node announces itself as reachable at, as given.
+/// * `external_address` - The IP the listen addresses are announced with instead of their own,
+///   for a node behind a NAT.
+/// * `bootstrappers` - The multiaddresses, each ending with its peer ID, dialed to join the
+///   network.
+/// * `blocked_peers` - The peers denied, refused before any connection is made.
+///
+/// # Examples
+///
+/// ```ignore
+/// let config = NetworkConfig {
+///     network_id: Some("staging".to_string()),
+///     bootstrappers: config.bootstrap_addrs(),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    pub keypair: identity::Keypair,
+    pub network_id: Option<String>,
+    pub timeout: Duration,
+    pub listen_addrs: Vec<Multiaddr>,
+    pub external_addrs: Vec<Multiaddr>,
+    pub external_address: Option<IpAddr>,
+    pub bootstrappers: Vec<Multiaddr>,
+    pub blocked_peers: HashSet<PeerId>,
+}
+
+impl Default for NetworkConfig {
+    /// A fresh identity on the default network, listening on every interface and dialing nobody.
+    fn default() -> Self {
+        NetworkConfig {
+            keypair: identity::Keypair::generate_ed25519(),
+            network_id: None,
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECONDS),
+            listen_addrs: Vec::new(),
+            external_addrs: Vec::new(),
+            external_address: None,
+            bootstrappers: Vec::new(),
+            blocked_peers: HashSet::new(),
+        }
+    }
+}
 
 /// Represents the combined network behaviour for the libp2p Swarm.
 ///
@@ -31,6 +93,7 @@ use tracing::debug;
 /// * `kademlia` - Kademlia distributed hash table behaviour for peer discovery and content routing.
 /// * `identify` - Protocol for identifying other peers on the network.
 /// * `gossipsub` - Gossipsub protocol for pub/sub messaging.
+/// * `blocked` - The peers denied, whose connections are closed and refused.
 ///
 /// # Examples
 ///
@@ -42,6 +105,7 @@ use tracing::debug;
 ///     kademlia: /* kademlia behaviour */,
 ///     identify: /* identify behaviour */,
 ///     gossipsub: /* gossipsub behaviour */,
+///     blocked: Default::default(),
 /// };
 /// ```
 #[derive(NetworkBehaviour)]
@@ -50,6 +114,30 @@ pub struct Behaviour {
     pub kademlia: kad::Behaviour<kad::store::MemoryStore>,
     pub identify: identify::Behaviour,
     pub gossipsub: gossipsub::Behaviour,
+    pub blocked: allow_block_list::Behaviour<allow_block_list::BlockedPeers>,
+}
+
+/// A snapshot of the local node's view of the network.
+///
+/// # Fields
+///
+/// * `peer_id` - The local peer id.
+/// * `agent_version` - The agent version the node identifies itself with.
+/// * `listen_addrs` - The addresses the node is listening on, with their bound ports.
+/// * `external_addrs` - The addresses the node is known to be reachable at from outside.
+/// * `connected_peers` - The number of peers the node is connected to.
+/// * `routing_table_size` - The number of peers in the node's Kademlia routing table.
+/// * `reachable` - Whether the node believes it is publicly reachable, having an external
+///   address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkInfo {
+    pub peer_id: PeerId,
+    pub agent_version: String,
+    pub listen_addrs: Vec<Multiaddr>,
+    pub external_addrs: Vec<Multiaddr>,
+    pub connected_peers: usize,
+    pub routing_table_size: usize,
+    pub reachable: bool,
 }
 
 /// Creates a new libp2p Swarm instance with specified behaviours and returns a `Client` for network operations.
@@ -78,26 +166,206 @@ pub struct Behaviour {
 /// ```
 pub async fn new(
     secret_key_seed: Option<u8>,
-) -> Result<(Client, impl Stream<Item = Event>, EventLoop, PeerId), Box<dyn Error>> {
+) -> Result<(Client, impl Stream<Item = Event>, EventLoop, PeerId), crate::Error> {
     // Create a public/private key pair, either random or based on a seed.
     let id_keys = match secret_key_seed {
-        Some(seed) => {
-            let mut bytes = [0u8; 32];
-            bytes[0] = seed;
-            identity::Keypair::ed25519_from_bytes(bytes).unwrap()
-        }
+        Some(seed) => seeded_keypair(seed),
         None => identity::Keypair::generate_ed25519(),
     };
+    with_identity(id_keys).await
+}
+
+/// Derives the deterministic keypair `new` uses for a secret key seed.
+///
+/// # Arguments
+///
+/// * `seed` - The seed, used as the first byte of an otherwise zeroed ed25519 secret key.
+///
+/// # Returns
+///
+/// The keypair, the same for every call with the same seed.
+pub fn seeded_keypair(seed: u8) -> identity::Keypair {
+    let mut bytes = [0u8; 32];
+    bytes[0] = seed;
+    identity::Keypair::ed25519_from_bytes(bytes).unwrap()
+}
+
+/// Like `new`, with the swarm using the given identity, such as a key persisted in the
+/// configuration directory.
+///
+/// # Arguments
+///
+/// * `id_keys` - The keypair the local peer ID is derived from.
+///
+/// # Returns
+///
+/// A `Result` containing a tuple of `Client`, an event stream, `EventLoop` and the local peer ID,
+/// or an error.
+///
+/// # Examples
+///
+/// ```ignore
+/// let key = ShardConfig::new()?.key_or_generate()?;
+/// let (client, event_stream, event_loop, peer_id) = with_identity(key).await?;
+/// ```
+pub async fn with_identity(
+    id_keys: identity::Keypair,
+) -> Result<(Client, impl Stream<Item = Event>, EventLoop, PeerId), crate::Error> {
+    with_timeout(id_keys, Duration::from_secs(DEFAULT_TIMEOUT_SECONDS)).await
+}
+
+/// Like `with_identity`, giving up on requests to peers and on DHT queries after `timeout`.
+///
+/// # Arguments
+///
+/// * `id_keys` - The keypair the local peer ID is derived from.
+/// * `timeout` - How long a request waits for its response, and a DHT query for its result.
+///
+/// # Returns
+///
+/// A `Result` containing a tuple of `Client`, an event stream, `EventLoop` and the local peer ID,
+/// or an error.
+///
+/// # Examples
+///
+/// ```ignore
+/// let key = identity::Keypair::generate_ed25519();
+/// let (client, event_stream, event_loop, peer_id) = with_timeout(key, Duration::from_secs(5)).await?;
+/// ```
+pub async fn with_timeout(
+    id_keys: identity::Keypair,
+    timeout: Duration,
+) -> Result<(Client, impl Stream<Item = Event>, EventLoop, PeerId), crate::Error> {
+    with_network(id_keys, timeout, None).await
+}
+
+/// The name of `protocol` on the network `network_id`: `/shard/<protocol>` on the default
+/// network, and `/shard/<network id>/<protocol>` on any other.
+///
+/// # Arguments
+///
+/// * `network_id` - The network, or `None` for the default one.
+/// * `protocol` - The protocol and its version, such as `reqres/1.0.0`.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::network::protocol_name;
+///
+/// assert_eq!(protocol_name(None, "reqres/1.0.0"), "/shard/reqres/1.0.0");
+/// assert_eq!(protocol_name(Some("staging"), "reqres/1.0.0"), "/shard/staging/reqres/1.0.0");
+/// ```
+pub fn protocol_name(network_id: Option<&str>, protocol: &str) -> String {
+    match network_id {
+        Some(id) => format!("/shard/{}/{}", id, protocol),
+        None => format!("/shard/{}", protocol),
+    }
+}
+
+/// Checks that `network_id` can name a network: it is not empty and only holds ASCII letters,
+/// digits, `-`, `_` and `.`.
+///
+/// # Returns
+///
+/// Why the id is invalid, if it is.
+pub fn check_network_id(network_id: &str) -> Result<(), String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+    if network_id.is_empty() || !network_id.chars().all(valid) {
+        return Err(format!(
+            "invalid network id {:?}: use letters, digits, '-', '_' and '.'",
+            network_id
+        ));
+    }
+    Ok(())
+}
+
+/// Like `with_timeout`, on the network `network_id`. The request-response and Kademlia protocols
+/// are named after the network, so that nodes of different networks never exchange shares or
+/// routing records, even when connected.
+///
+/// # Arguments
+///
+/// * `id_keys` - The keypair the local peer ID is derived from.
+/// * `timeout` - How long a request waits for its response, and a DHT query for its result.
+/// * `network_id` - The network to join, or `None` for the default one.
+///
+/// # Returns
+///
+/// A `Result` containing a tuple of `Client`, an event stream, `EventLoop` and the local peer ID,
+/// or an error if `network_id` is invalid.
+///
+/// # Examples
+///
+/// ```ignore
+/// let key = identity::Keypair::generate_ed25519();
+/// let (client, event_stream, event_loop, peer_id) =
+///     with_network(key, Duration::from_secs(5), Some("staging")).await?;
+/// ```
+pub async fn with_network(
+    id_keys: identity::Keypair,
+    timeout: Duration,
+    network_id: Option<&str>,
+) -> Result<(Client, impl Stream<Item = Event>, EventLoop, PeerId), crate::Error> {
     let peer_id = id_keys.public().to_peer_id();
     debug!("Peer ID: {}", peer_id);
 
-    let mut swarm = libp2p::SwarmBuilder::with_existing_identity(id_keys)
-        .with_tokio()
+    if let Some(id) = network_id {
+        check_network_id(id).map_err(crate::Error::Invalid)?;
+    }
+    let swarm = build_swarm(id_keys, timeout, network_id).map_err(crate::Error::Network)?;
+
+    let (command_sender, command_receiver) = mpsc::channel(0);
+    let (event_sender, event_receiver) = mpsc::channel(EVENT_BUFFER);
+
+    Ok((
+        Client {
+            sender: command_sender,
+            trace_id: None,
+        },
+        event_receiver,
+        EventLoop::new(swarm, command_receiver, event_sender),
+        peer_id,
+    ))
+}
+
+/// Builds the swarm of `with_network`, with its transports and behaviours, subscribed to the
+/// gossip and health topics.
+///
+/// # Returns
+///
+/// The swarm, or the error of the transport or behaviour that could not be set up.
+fn build_swarm(
+    id_keys: identity::Keypair,
+    timeout: Duration,
+    network_id: Option<&str>,
+) -> Result<Swarm<Behaviour>, Box<dyn Error + Send + Sync>> {
+    let peer_id = id_keys.public().to_peer_id();
+    let reqres_protocol =
+        StreamProtocol::try_from_owned(protocol_name(network_id, "reqres/1.0.0"))?;
+    // the default network keeps the standard Kademlia protocol
+    let kad_protocols = match network_id {
+        Some(_) => vec![StreamProtocol::try_from_owned(protocol_name(network_id, "kad/1.0.0"))?],
+        None => Vec::new(),
+    };
+
+    let builder = libp2p::SwarmBuilder::with_existing_identity(id_keys);
+    #[cfg(feature = "rt-async-std")]
+    let builder = builder.with_async_std();
+    #[cfg(all(feature = "rt-tokio", not(feature = "rt-async-std")))]
+    let builder = builder.with_tokio();
+    let mut swarm = builder
         .with_tcp(
             tcp::Config::default(),
             noise::Config::new,
             yamux::Config::default,
         )?
+        // in-process connections, for nodes sharing a process such as in tests
+        .with_other_transport(|key| -> Result<_, Box<dyn Error + Send + Sync>> {
+            Ok(MemoryTransport::default()
+                .upgrade(upgrade::Version::V1)
+                .authenticate(noise::Config::new(key)?)
+                .multiplex(yamux::Config::default()))
+        })?
         .with_behaviour(|key| {
             // To content-address message, we can take the hash of message and use it as an ID.
             let message_id_fn = |message: &gossipsub::Message| {
@@ -120,28 +388,32 @@ pub async fn new(
                 gossipsub_config,
             )?;
 
-            let kademlia = kad::Behaviour::new(
+            let mut kademlia_config = kad::Config::default();
+            kademlia_config.set_query_timeout(timeout);
+            if !kad_protocols.is_empty() {
+                kademlia_config.set_protocol_names(kad_protocols);
+            }
+            let kademlia = kad::Behaviour::with_config(
                 peer_id,
                 kad::store::MemoryStore::new(key.public().to_peer_id()),
+                kademlia_config,
             );
             let request_response = request_response::cbor::Behaviour::new(
-                [(
-                    StreamProtocol::new("/shard/reqres/1.0.0"),
-                    ProtocolSupport::Full,
-                )],
-                request_response::Config::default(),
+                [(reqres_protocol, ProtocolSupport::Full)],
+                request_response::Config::default().with_request_timeout(timeout),
             );
 
-            let identify = identify::Behaviour::new(identify::Config::new(
-                "/shard/id/1.0.0".to_string(),
-                key.public(),
-            ));
+            let identify = identify::Behaviour::new(
+                identify::Config::new(protocol_name(network_id, "id/1.0.0"), key.public())
+                    .with_agent_version(AGENT_VERSION.to_string()),
+            );
 
             Ok(Behaviour {
                 kademlia,
                 request_response,
                 identify,
                 gossipsub,
+                blocked: Default::default(),
             })
         })?
         .build();
@@ -152,19 +424,64 @@ pub async fn new(
         .set_mode(Some(kad::Mode::Server));
 
     // Create a Gossipsub topic
-    let topic = IdentTopic::new("/shard/pubsub/1.0.0".to_string());
+    let topic = IdentTopic::new(GOSSIP_TOPIC);
     // subscribes to our topic
     swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
+    // and to the provider health statuses
+    swarm
+        .behaviour_mut()
+        .gossipsub
+        .subscribe(&IdentTopic::new(HEALTH_TOPIC))?;
+    Ok(swarm)
+}
 
-    let (command_sender, command_receiver) = mpsc::channel(0);
-    let (event_sender, event_receiver) = mpsc::channel(0);
+/// Starts a listener on each of `addrs`, or on every IPv4 and IPv6 interface when none are
+/// given. A listener that fails to start is reported and skipped, as long as another one starts.
+///
+/// # Returns
+///
+/// The number of listeners started, or an error naming why each failed if none did.
+pub async fn start_listeners(
+    network_client: &mut Client,
+    addrs: Vec<Multiaddr>,
+) -> Result<usize, String> {
+    let addrs = match addrs.is_empty() {
+        true => DEFAULT_LISTEN_ADDRS
+            .iter()
+            .map(|addr| addr.parse().expect("default listen address to be valid"))
+            .collect(),
+        false => addrs,
+    };
+    let mut started = 0;
+    let mut failed = Vec::new();
+    for addr in addrs {
+        match network_client.start_listening(addr.clone()).await {
+            Ok(()) => started += 1,
+            Err(e) => {
+                error!("Could not listen on {}: {}", addr, e);
+                failed.push(format!("{}: {}", addr, e));
+            }
+        }
+    }
+    match started {
+        0 => Err(format!(
+            "could not listen on any address ({})",
+            failed.join(", ")
+        )),
+        started => Ok(started),
+    }
+}
 
-    Ok((
-        Client {
-            sender: command_sender,
-        },
-        event_receiver,
-        EventLoop::new(swarm, command_receiver, event_sender),
-        peer_id,
-    ))
+/// Gets the addresses the local node is listening on. Listeners come up asynchronously, so an
+/// empty list is asked for again a few times before it is reported.
+pub async fn wait_for_listen_addrs(network_client: &mut Client) -> Vec<Multiaddr> {
+    let mut listen_addrs = network_client.listen_addrs().await;
+    for _ in 0..LISTEN_ADDR_POLLS {
+        if !listen_addrs.is_empty() {
+            break;
+        }
+        runtime::sleep(Duration::from_millis(50)).await;
+        listen_addrs = network_client.listen_addrs().await;
+    }
+    listen_addrs
 }
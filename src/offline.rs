@@ -0,0 +1,425 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::sss::{combine_shares, generate_refresh_key, refresh_share, split_secret};
+
+/// The version of the share and manifest files written by `split_to_dir`. Files of another
+/// version are refused rather than misread.
+pub const SHARE_FILE_VERSION: u8 = 1;
+
+/// The name of the manifest file in a directory of share files.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// The name of the file holding the share of `index`.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::offline::share_file_name;
+///
+/// assert_eq!(share_file_name(3), "share-3.json");
+/// ```
+pub fn share_file_name(index: u8) -> String {
+    format!("share-{}.json", index)
+}
+
+/// A share of a secret kept in a file of its own, to split and combine secrets without a network.
+///
+/// # Fields
+///
+/// * `version` - The version of the file format.
+/// * `key` - The key the secret was split under.
+/// * `threshold` - The number of shares needed to rebuild the secret.
+/// * `epoch` - The number of times the share was refreshed. Shares of different epochs do not
+///   combine.
+/// * `index` - The index of the share, from 1.
+/// * `share` - The hex-encoded share.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareFile {
+    pub version: u8,
+    pub key: String,
+    pub threshold: usize,
+    pub epoch: u64,
+    pub index: u8,
+    pub share: String,
+}
+
+/// Describes a secret split to share files, written next to them.
+///
+/// # Fields
+///
+/// * `version` - The version of the file format.
+/// * `key` - The key the secret was split under.
+/// * `threshold` - The number of shares needed to rebuild the secret.
+/// * `shares` - The number of shares the secret was split into.
+/// * `sha256` - The hex-encoded SHA-256 of the secret, checked once it is rebuilt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareManifest {
+    pub version: u8,
+    pub key: String,
+    pub threshold: usize,
+    pub shares: usize,
+    pub sha256: String,
+}
+
+/// The share files and the manifest read by `read_share_files`.
+///
+/// # Fields
+///
+/// * `shares` - The share files, with the path each was read from.
+/// * `manifest` - The manifest, if one was read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShareFiles {
+    pub shares: Vec<(PathBuf, ShareFile)>,
+    pub manifest: Option<ShareManifest>,
+}
+
+/// Writes `contents` as JSON to `path`, which must not exist, in a file only its owner can read
+/// and write.
+fn write_new(path: &Path, contents: &impl Serialize) -> Result<(), Box<dyn Error>> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    file.write_all(&serde_json::to_vec_pretty(contents)?)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Splits `secret` into `shares` share files and a manifest in `dir`, created if missing.
+///
+/// # Arguments
+///
+/// * `secret` - The secret to split.
+/// * `key` - The key the secret is split under.
+/// * `threshold` - The number of shares needed to rebuild the secret.
+/// * `shares` - The number of shares.
+/// * `dir` - The directory to write the files to.
+///
+/// # Returns
+///
+/// The paths of the share files, by index, or an error if the threshold is invalid or a file
+/// already exists in `dir`.
+pub fn split_to_dir(
+    secret: &[u8],
+    key: &str,
+    threshold: usize,
+    shares: usize,
+    dir: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let split = split_secret(secret, threshold, shares)?;
+    fs::create_dir_all(dir)?;
+    let mut paths = Vec::with_capacity(shares);
+    for index in 1..=shares as u8 {
+        let path = dir.join(share_file_name(index));
+        let share = ShareFile {
+            version: SHARE_FILE_VERSION,
+            key: key.to_string(),
+            threshold,
+            epoch: 0,
+            index,
+            share: hex::encode(&split[&index]),
+        };
+        write_new(&path, &share)?;
+        paths.push(path);
+    }
+    let manifest = ShareManifest {
+        version: SHARE_FILE_VERSION,
+        key: key.to_string(),
+        threshold,
+        shares,
+        sha256: hex::encode(Sha256::digest(secret)),
+    };
+    write_new(&dir.join(MANIFEST_FILE), &manifest)?;
+    Ok(paths)
+}
+
+/// Reads a share or manifest file, refusing another version of the format.
+fn read_json<T: for<'de> Deserialize<'de>>(
+    path: &Path,
+    version: impl Fn(&T) -> u8,
+) -> Result<T, Box<dyn Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let contents: T = serde_json::from_slice(&bytes)
+        .map_err(|e| format!("{} is not a share file: {}", path.display(), e))?;
+    if version(&contents) != SHARE_FILE_VERSION {
+        return Err(format!(
+            "{} has version {} of the share file format, expected {}",
+            path.display(),
+            version(&contents),
+            SHARE_FILE_VERSION
+        )
+        .into());
+    }
+    Ok(contents)
+}
+
+/// Reads share files and their manifest from `paths`. A directory is read for every `.json` file
+/// in it; a file is read as a manifest if it is named `MANIFEST_FILE`, and as a share otherwise.
+///
+/// # Arguments
+///
+/// * `paths` - The share files, or the directories holding them.
+///
+/// # Returns
+///
+/// The shares and the manifest, or an error if a file cannot be read or holds something else.
+pub fn read_share_files(paths: &[PathBuf]) -> Result<ShareFiles, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<_, _>>()?;
+            entries.retain(|entry| entry.extension().is_some_and(|ext| ext == "json"));
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    let mut read = ShareFiles::default();
+    for path in files {
+        if path.file_name().is_some_and(|name| name == MANIFEST_FILE) {
+            read.manifest = Some(read_json(&path, |m: &ShareManifest| m.version)?);
+        } else {
+            let share = read_json(&path, |s: &ShareFile| s.version)?;
+            read.shares.push((path, share));
+        }
+    }
+    Ok(read)
+}
+
+/// Checks that `files` hold shares of one secret, of one epoch, with no index twice, and agree
+/// with their manifest.
+///
+/// # Returns
+///
+/// The key and the threshold the shares were split with, or an error saying how they disagree.
+fn check_share_files(files: &ShareFiles) -> Result<(String, usize), Box<dyn Error>> {
+    let Some((_, first)) = files.shares.first() else {
+        return Err("no share files found".into());
+    };
+    let mut indexes = Vec::new();
+    for (path, share) in &files.shares {
+        if (&share.key, share.threshold, share.epoch) != (&first.key, first.threshold, first.epoch)
+        {
+            return Err(format!(
+                "{} is a share of {} at threshold {} and epoch {}, the others of {} at threshold {} and epoch {}",
+                path.display(),
+                share.key,
+                share.threshold,
+                share.epoch,
+                first.key,
+                first.threshold,
+                first.epoch
+            )
+            .into());
+        }
+        if indexes.contains(&share.index) {
+            return Err(format!("share {} was given twice", share.index).into());
+        }
+        indexes.push(share.index);
+    }
+    if let Some(manifest) = &files.manifest {
+        if (&manifest.key, manifest.threshold) != (&first.key, first.threshold) {
+            return Err(format!(
+                "the manifest describes {} at threshold {}, the shares are of {} at threshold {}",
+                manifest.key, manifest.threshold, first.key, first.threshold
+            )
+            .into());
+        }
+    }
+    Ok((first.key.clone(), first.threshold))
+}
+
+/// Rebuilds a secret from the share files in `paths`, checking it against the manifest's hash
+/// when a manifest is among them.
+///
+/// # Arguments
+///
+/// * `paths` - The share files, or the directories holding them.
+///
+/// # Returns
+///
+/// The key and the threshold the secret was split with and the secret, or an error if there are
+/// fewer shares than the threshold, they disagree, or the secret does not match the manifest.
+pub fn combine_from_files(paths: &[PathBuf]) -> Result<(String, usize, Vec<u8>), Box<dyn Error>> {
+    let files = read_share_files(paths)?;
+    let (key, threshold) = check_share_files(&files)?;
+    if files.shares.len() < threshold {
+        return Err(format!(
+            "{} shares of {} were found, {} are needed",
+            files.shares.len(),
+            key,
+            threshold
+        )
+        .into());
+    }
+    let mut shares = HashMap::new();
+    for (path, share) in files.shares.iter().take(threshold) {
+        let bytes = hex::decode(&share.share)
+            .map_err(|e| format!("{} holds an invalid share: {}", path.display(), e))?;
+        shares.insert(share.index, bytes);
+    }
+    let secret = combine_shares(&shares)
+        .map_err(|e| format!("cannot combine the shares of {}: {}", key, e))?;
+    if let Some(manifest) = &files.manifest {
+        if hex::encode(Sha256::digest(&secret)) != manifest.sha256 {
+            return Err(format!(
+                "the secret rebuilt from the shares of {} does not match its manifest",
+                key
+            )
+            .into());
+        }
+    }
+    Ok((key, threshold, secret))
+}
+
+/// Refreshes every share file in `dir` with one refresh key, without changing the secret, and
+/// moves them to the next epoch. Every share of the secret must be there, or those elsewhere would
+/// no longer combine with the refreshed ones.
+///
+/// # Arguments
+///
+/// * `dir` - The directory holding the share files and their manifest.
+///
+/// # Returns
+///
+/// The refreshed share files and their manifest, or an error if the shares disagree or some are
+/// missing.
+pub fn refresh_dir(dir: &Path) -> Result<ShareFiles, Box<dyn Error>> {
+    let files = read_share_files(&[dir.to_path_buf()])?;
+    let (key, threshold) = check_share_files(&files)?;
+    let Some(manifest) = &files.manifest else {
+        return Err(format!("no {} in {}", MANIFEST_FILE, dir.display()).into());
+    };
+    if files.shares.len() != manifest.shares {
+        return Err(format!(
+            "{} of the {} shares of {} are in {}, refresh them all at once",
+            files.shares.len(),
+            manifest.shares,
+            key,
+            dir.display()
+        )
+        .into());
+    }
+
+    let mut refreshed = Vec::with_capacity(files.shares.len());
+    let mut refresh_key = None;
+    for (path, share) in files.shares {
+        let mut bytes = hex::decode(&share.share)
+            .map_err(|e| format!("{} holds an invalid share: {}", path.display(), e))?;
+        let polynomials = match &refresh_key {
+            Some(polynomials) => polynomials,
+            None => refresh_key.insert(generate_refresh_key(threshold, bytes.len())?),
+        };
+        refresh_share((&share.index, &mut bytes), polynomials)?;
+        let share = ShareFile {
+            epoch: share.epoch + 1,
+            share: hex::encode(bytes),
+            ..share
+        };
+        refreshed.push((path, share));
+    }
+
+    // every share is refreshed before any is written, so that a failure leaves the epoch whole
+    for (path, share) in &refreshed {
+        let tmp = path.with_extension("json.tmp");
+        // a temporary file left by an interrupted refresh holds nothing the shares still need
+        let _ = fs::remove_file(&tmp);
+        write_new(&tmp, share)?;
+        fs::rename(tmp, path)?;
+    }
+    Ok(ShareFiles {
+        shares: refreshed,
+        manifest: files.manifest,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("shard-{}-{}", name, rand::random::<u64>()))
+    }
+
+    #[test]
+    fn test_split_refresh_combine_round_trip() {
+        let dir = temp_dir("offline");
+        let paths = split_to_dir(b"hunter2", "vault", 2, 3, &dir).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                dir.join("share-1.json"),
+                dir.join("share-2.json"),
+                dir.join("share-3.json")
+            ]
+        );
+        let before = fs::read(&paths[0]).unwrap();
+
+        assert_eq!(
+            combine_from_files(std::slice::from_ref(&dir)).unwrap(),
+            ("vault".to_string(), 2, b"hunter2".to_vec())
+        );
+        let refreshed = refresh_dir(&dir).unwrap();
+        assert_eq!(refreshed.shares.len(), 3);
+        assert!(refreshed.shares.iter().all(|(_, share)| share.epoch == 1));
+        assert_ne!(fs::read(&paths[0]).unwrap(), before);
+
+        // any two refreshed shares still rebuild the secret, checked against the manifest
+        let subset = [paths[2].clone(), paths[0].clone(), dir.join(MANIFEST_FILE)];
+        assert_eq!(combine_from_files(&subset).unwrap().2, b"hunter2");
+        assert!(combine_from_files(&paths[..1]).is_err());
+
+        // the shares stay readable by their owner alone once refreshed
+        #[cfg(unix)]
+        for path in paths.iter().chain([&dir.join(MANIFEST_FILE)]) {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", path.display());
+        }
+
+        // splitting over existing files is refused
+        assert!(split_to_dir(b"other", "vault", 2, 3, &dir).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_mismatched_share_files_are_refused() {
+        let dir = temp_dir("offline-mixed");
+        let paths = split_to_dir(b"hunter2", "vault", 2, 3, &dir).unwrap();
+        let stale = temp_dir("offline-stale");
+        fs::create_dir_all(&stale).unwrap();
+        fs::copy(&paths[0], stale.join("share-1.json")).unwrap();
+        refresh_dir(&dir).unwrap();
+
+        // shares of different epochs would rebuild garbage
+        let mixed = [stale.join("share-1.json"), paths[1].clone()];
+        let err = combine_from_files(&mixed).unwrap_err().to_string();
+        assert!(err.contains("epoch"), "{}", err);
+        let twice = [paths[1].clone(), paths[1].clone()];
+        assert!(combine_from_files(&twice).is_err());
+
+        // a refresh of part of the shares would leave the others behind
+        fs::copy(dir.join(MANIFEST_FILE), stale.join(MANIFEST_FILE)).unwrap();
+        assert!(refresh_dir(&stale).is_err());
+
+        let mut share: ShareFile = serde_json::from_slice(&fs::read(&paths[1]).unwrap()).unwrap();
+        share.version = SHARE_FILE_VERSION + 1;
+        fs::write(&paths[1], serde_json::to_vec(&share).unwrap()).unwrap();
+        assert!(combine_from_files(std::slice::from_ref(&dir)).is_err());
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(stale).unwrap();
+    }
+}
@@ -1,16 +1,112 @@
+#[cfg(feature = "sss16")]
+pub use crate::repository::ShareField;
+pub use crate::repository::{RelayGrant, ShareIndex};
 use crate::sss::Polynomial;
+use libp2p::identity::{Keypair, PublicKey, SigningError};
+use libp2p::PeerId;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A share as a client registers it with a provider and gets it back.
+///
+/// # Fields
+///
+/// * `share` - A tuple containing the share identifier (`ShareIndex`) and the share data (Vec<u8>).
+/// * `field` - The field the secret was split over, which the share is combined and refreshed in.
+///   Only with the `sss16` feature; shares are otherwise split over GF(2^8).
+///
+/// # Examples
+///
+/// A share split over GF(2^8), such as one of `sss::split_secret`:
+///
+/// ```rust
+/// use shard::protocol::FieldShare;
+///
+/// let share = FieldShare::from((1, vec![7, 8, 9]));
+/// assert_eq!(share.share, (1, vec![7, 8, 9]));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldShare {
+    pub share: (ShareIndex, Vec<u8>),
+    #[cfg(feature = "sss16")]
+    pub field: ShareField,
+}
+
+impl From<(u8, Vec<u8>)> for FieldShare {
+    fn from((index, share): (u8, Vec<u8>)) -> Self {
+        // widens the index with the `sss16` feature
+        #[allow(clippy::useless_conversion)]
+        let share = (ShareIndex::from(index), share);
+        FieldShare {
+            share,
+            #[cfg(feature = "sss16")]
+            field: ShareField::Gf256,
+        }
+    }
+}
+
+/// The polynomials of a refresh, over the field the secret of the refreshed share was split over.
+///
+/// # Variants
+///
+/// * `Gf256(Vec<Polynomial>)` - A refresh key over GF(2^8) (see `sss::generate_refresh_key`).
+/// * `Gf65536(Vec<sss16::Polynomial>)` - A refresh key over GF(2^16) (see
+///   `sss16::generate_refresh_key`). Only with the `sss16` feature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefreshKey {
+    Gf256(Vec<Polynomial>),
+    #[cfg(feature = "sss16")]
+    Gf65536(Vec<crate::sss16::Polynomial>),
+}
+
+impl RefreshKey {
+    /// Returns the number of polynomials of the refresh key, one per field element of the share.
+    pub fn len(&self) -> usize {
+        match self {
+            RefreshKey::Gf256(polynomials) => polynomials.len(),
+            #[cfg(feature = "sss16")]
+            RefreshKey::Gf65536(polynomials) => polynomials.len(),
+        }
+    }
+
+    /// Returns whether the refresh key has no polynomials.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl From<Vec<Polynomial>> for RefreshKey {
+    fn from(polynomials: Vec<Polynomial>) -> Self {
+        RefreshKey::Gf256(polynomials)
+    }
+}
+
+#[cfg(feature = "sss16")]
+impl From<Vec<crate::sss16::Polynomial>> for RefreshKey {
+    fn from(polynomials: Vec<crate::sss16::Polynomial>) -> Self {
+        RefreshKey::Gf65536(polynomials)
+    }
+}
 
 /// Represents a request in a simple share exchange protocol.
 ///
 /// This enum encapsulates different types of requests that can be made, such as getting a share,
-/// registering a new share, or refreshing shares.
+/// registering a new share, refreshing shares, deleting a share, listing keys, describing a
+/// share, or changing who may read a share.
 ///
 /// # Variants
 ///
 /// * `GetShare(GetShareRequest)` - Represents a request to get a share.
 /// * `RegisterShare(RegisterShareRequest)` - Represents a request to register a new share.
 /// * `RefreshShares(RefreshShareRequest)` - Represents a request to refresh existing shares.
+/// * `DeleteShare(DeleteShareRequest)` - Represents a request to delete a share.
+/// * `ListKeys(ListKeysRequest)` - Represents a request to list the keys of the sender's shares.
+/// * `StatShare(StatShareRequest)` - Represents a request for the metadata of a share.
+/// * `GrantAccess(AccessRequest)` - Represents a request to let another peer read a share.
+/// * `RevokeAccess(AccessRequest)` - Represents a request to stop another peer reading a share.
+/// * `TransferOwnership(TransferOwnershipRequest)` - Represents a request to hand a share over to
+///   another owner.
 ///
 /// # Examples
 ///
@@ -25,6 +121,8 @@ use serde::{Deserialize, Serialize};
 ///     key: "share_key".to_string(),
 ///     peer: vec![1, 2, 3],
 ///     sender: vec![4, 5, 6],
+///     owner: None,
+///     trace_id: None,
 /// });
 /// ```
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -32,6 +130,71 @@ pub enum Request {
     GetShare(GetShareRequest),
     RegisterShare(RegisterShareRequest),
     RefreshShare(RefreshShareRequest),
+    DeleteShare(DeleteShareRequest),
+    ListKeys(ListKeysRequest),
+    StatShare(StatShareRequest),
+    GrantAccess(AccessRequest),
+    RevokeAccess(AccessRequest),
+    TransferOwnership(TransferOwnershipRequest),
+}
+
+impl Request {
+    /// Returns the peer id bytes the sender put in the request, which are not validated.
+    pub fn sender(&self) -> &[u8] {
+        match self {
+            Request::GetShare(req) => &req.sender,
+            Request::RegisterShare(req) => &req.sender,
+            Request::RefreshShare(req) => &req.sender,
+            Request::DeleteShare(req) => &req.sender,
+            Request::ListKeys(req) => &req.sender,
+            Request::StatShare(req) => &req.sender,
+            Request::GrantAccess(req) | Request::RevokeAccess(req) => &req.sender,
+            Request::TransferOwnership(req) => &req.sender,
+        }
+    }
+
+    /// Returns the trace id of the operation the request is part of, if the sender gave one.
+    pub fn trace_id(&self) -> Option<TraceId> {
+        match self {
+            Request::GetShare(req) => req.trace_id,
+            Request::RegisterShare(req) => req.trace_id,
+            Request::RefreshShare(req) => req.trace_id,
+            Request::DeleteShare(req) => req.trace_id,
+            Request::ListKeys(req) => req.trace_id,
+            Request::StatShare(req) => req.trace_id,
+            Request::GrantAccess(req) | Request::RevokeAccess(req) => req.trace_id,
+            Request::TransferOwnership(req) => req.trace_id,
+        }
+    }
+}
+
+/// Identifies a client operation, such as a split, across every request it sends, so that the
+/// logs of the client and of each provider it asks can be matched up.
+///
+/// Requests from peers that do not send one carry `None`.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::protocol::TraceId;
+///
+/// let trace_id = TraceId(0x2a);
+/// assert_eq!(trace_id.to_string(), "000000000000002a");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TraceId(pub u64);
+
+impl TraceId {
+    /// Returns a new random trace id.
+    pub fn random() -> Self {
+        TraceId(rand::random())
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
 }
 
 /// Represents a response in a simple share exchange protocol.
@@ -43,6 +206,17 @@ pub enum Request {
 /// * `GetShare(GetShareResponse)` - Response to a `GetShare` request.
 /// * `RegisterShare(RegisterShareResponse)` - Response to a `RegisterShare` request.
 /// * `RefreshShares(RefreshSharesResponse)` - Response to a `RefreshShares` request.
+/// * `DeleteShare(DeleteShareResponse)` - Response to a `DeleteShare` request.
+/// * `ListKeys(ListKeysResponse)` - Response to a `ListKeys` request.
+/// * `StatShare(StatShareResponse)` - Response to a `StatShare` request.
+/// * `Access(AccessResponse)` - Response to a `GrantAccess` or `RevokeAccess` request.
+/// * `TransferOwnership(TransferOwnershipResponse)` - Response to a `TransferOwnership` request.
+/// * `InvalidRequest(InvalidRequestResponse)` - Response to any request the provider could not
+///   make sense of, such as one with a malformed sender.
+/// * `RateLimited(RateLimitedResponse)` - Response to any request the provider turned down because
+///   the sender made too many requests of its kind.
+/// * `Busy(BusyResponse)` - Response to any request the provider turned down because it is
+///   shutting down.
 ///
 /// # Examples
 ///
@@ -55,7 +229,11 @@ pub enum Request {
 ///
 /// let response = Response::GetShare(GetShareResponse {
 ///     share: (1, vec![7, 8, 9]),
+/// #   #[cfg(feature = "sss16")]
+/// #   field: shard::protocol::ShareField::Gf256,
 ///     success: true,
+///     reason: None,
+///     failure: None,
 /// });
 /// ```
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -63,6 +241,14 @@ pub enum Response {
     GetShare(GetShareResponse),
     RegisterShare(RegisterShareResponse),
     RefreshShares(RefreshShareResponse),
+    DeleteShare(DeleteShareResponse),
+    ListKeys(ListKeysResponse),
+    StatShare(StatShareResponse),
+    Access(AccessResponse),
+    TransferOwnership(TransferOwnershipResponse),
+    InvalidRequest(InvalidRequestResponse),
+    RateLimited(RateLimitedResponse),
+    Busy(BusyResponse),
 }
 
 /// Represents a request to get a share.
@@ -74,6 +260,10 @@ pub enum Response {
 /// * `key` - A string representing the key of the share.
 /// * `peer` - A byte vector representing the peer from whom the share is requested.
 /// * `sender` - A byte vector representing the sender of the request.
+/// * `owner` - A byte vector representing the owner of the share, when the sender reads a share
+///   another peer granted it access to. `None` requests the sender's own share.
+/// * `trace_id` - The trace id of the operation the request is part of, if any, which the
+///   provider logs the request under.
 ///
 /// # Examples
 ///
@@ -88,6 +278,8 @@ pub enum Response {
 ///     key: "share_key".to_string(),
 ///     peer: vec![1, 2, 3],
 ///     sender: vec![4, 5, 6],
+///     owner: None,
+///     trace_id: None,
 /// };
 /// ```
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -95,6 +287,10 @@ pub struct GetShareRequest {
     pub key: String,
     pub peer: Vec<u8>,
     pub sender: Vec<u8>,
+    #[serde(default)]
+    pub owner: Option<Vec<u8>>,
+    #[serde(default)]
+    pub trace_id: Option<TraceId>,
 }
 
 /// Represents a response to a `GetShare` request.
@@ -103,8 +299,12 @@ pub struct GetShareRequest {
 ///
 /// # Fields
 ///
-/// * `share` - A tuple containing the share identifier (u8) and the share data (Vec<u8>).
+/// * `share` - A tuple containing the share identifier (`ShareIndex`) and the share data (Vec<u8>).
+/// * `field` - The field the secret was split over, which the share is combined in. Only with the
+///   `sss16` feature.
 /// * `success` - A boolean indicating whether the request was successful.
+/// * `reason` - Why no share was returned, when `success` is false.
+/// * `failure` - What kept the provider from returning the share, when `success` is false.
 ///
 /// # Examples
 ///
@@ -115,13 +315,24 @@ pub struct GetShareRequest {
 ///
 /// let response = GetShareResponse {
 ///     share: (1, vec![7, 8, 9]),
+/// #   #[cfg(feature = "sss16")]
+/// #   field: shard::protocol::ShareField::Gf256,
 ///     success: true,
+///     reason: None,
+///     failure: None,
 /// };
 /// ```
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GetShareResponse {
-    pub share: (u8, Vec<u8>),
+    pub share: (ShareIndex, Vec<u8>),
+    #[cfg(feature = "sss16")]
+    #[serde(default)]
+    pub field: ShareField,
     pub success: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub failure: Option<Failure>,
 }
 
 /// Represents a request to register a new share.
@@ -131,9 +342,24 @@ pub struct GetShareResponse {
 /// # Fields
 ///
 /// * `key` - A string representing the key of the share.
-/// * `share` - A tuple containing the share identifier (u8) and the share data (Vec<u8>).
+/// * `share` - A tuple containing the share identifier (`ShareIndex`) and the share data (Vec<u8>).
+/// * `field` - The field the secret was split over, which bounds the share identifier. Only with
+///   the `sss16` feature.
 /// * `peer` - A byte vector representing the peer with whom the share is associated.
 /// * `sender` - A byte vector representing the sender of the request.
+/// * `threshold` - The threshold the secret was split with.
+/// * `ttl_secs` - An optional lifetime in seconds after which the provider destroys the share.
+/// * `recreate` - Register the share even if the sender deleted it recently. Without it, a
+///   provider holding a live tombstone for the key refuses the registration.
+/// * `refresh_interval_secs` - How often providers refresh the share, in seconds. `None` leaves
+///   it to each provider's default interval.
+/// * `replace` - Replace a share the sender stored under the key with a different share index,
+///   length or threshold. Without it, such a registration is refused with `Failure::Conflict`.
+/// * `relay_grant` - The sender's permission for the provider to relay refreshes of the share to
+///   its other providers (see `RelayGrant::signed`). Without it, the provider applies refreshes
+///   but never coordinates one.
+/// * `trace_id` - The trace id of the operation the request is part of, if any, which the
+///   provider logs the request under.
 ///
 /// # Examples
 ///
@@ -146,18 +372,41 @@ pub struct GetShareResponse {
 /// let request = RegisterShareRequest {
 ///     key: "share_key".to_string(),
 ///     share: (1, vec![1, 2, 3]),
+/// #   #[cfg(feature = "sss16")]
+/// #   field: shard::protocol::ShareField::Gf256,
 ///     peer: vec![4, 5, 6],
 ///     sender: vec![7, 8, 9],
 ///     threshold: 2,
+///     ttl_secs: None,
+///     recreate: false,
+///     refresh_interval_secs: None,
+///     replace: false,
+///     relay_grant: None,
+///     trace_id: None,
 /// };
 /// ```
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RegisterShareRequest {
     pub key: String,
-    pub share: (u8, Vec<u8>),
+    pub share: (ShareIndex, Vec<u8>),
+    #[cfg(feature = "sss16")]
+    #[serde(default)]
+    pub field: ShareField,
     pub peer: Vec<u8>,
     pub sender: Vec<u8>,
     pub threshold: u64,
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    #[serde(default)]
+    pub recreate: bool,
+    #[serde(default)]
+    pub refresh_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub replace: bool,
+    #[serde(default)]
+    pub relay_grant: Option<RelayGrant>,
+    #[serde(default)]
+    pub trace_id: Option<TraceId>,
 }
 
 /// Represents a response to a `RegisterShare` request.
@@ -167,6 +416,8 @@ pub struct RegisterShareRequest {
 /// # Fields
 ///
 /// * `success` - A boolean indicating whether the share was successfully registered.
+/// * `reason` - Why the share was refused, when `success` is false.
+/// * `failure` - Why the provider refused the share, when `success` is false.
 ///
 /// # Examples
 ///
@@ -177,11 +428,98 @@ pub struct RegisterShareRequest {
 ///
 /// let response = RegisterShareResponse {
 ///     success: true,
+///     reason: None,
+///     failure: None,
 /// };
 /// ```
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RegisterShareResponse {
     pub success: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub failure: Option<Failure>,
+}
+
+/// A storage limit of a provider, and how much of it was used when a share was refused.
+///
+/// # Fields
+///
+/// * `limit` - The name of the limit, such as `max_entries_per_owner`.
+/// * `max` - The value of the limit.
+/// * `used` - How much of the limit was already used.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::protocol::QuotaUsage;
+///
+/// let usage = QuotaUsage {
+///     limit: "max_entries_per_owner".to_string(),
+///     max: 100,
+///     used: 100,
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    pub limit: String,
+    pub max: u64,
+    pub used: u64,
+}
+
+/// The outcome of a `RegisterShare` request, as seen by the requester.
+///
+/// A share refused with `QuotaExceeded` can be registered with another provider instead.
+///
+/// # Variants
+///
+/// * `Registered` - The provider stored the share and provides it.
+/// * `QuotaExceeded(QuotaUsage)` - The share would have exceeded a storage limit of the provider.
+/// * `Conflict(String)` - The provider holds a different share of the sender under the key, which
+///   a registration with `replace` set overwrites; carries how they differ.
+/// * `Refused(String)` - The provider refused the share, with the reason why.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::protocol::{RegisterShareResponse, RegisterShareStatus};
+///
+/// let response = RegisterShareResponse {
+///     success: true,
+///     reason: None,
+///     failure: None,
+/// };
+/// assert_eq!(RegisterShareStatus::from(response), RegisterShareStatus::Registered);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegisterShareStatus {
+    Registered,
+    QuotaExceeded(QuotaUsage),
+    Conflict(String),
+    Refused(String),
+}
+
+impl From<RegisterShareResponse> for RegisterShareStatus {
+    fn from(response: RegisterShareResponse) -> Self {
+        match response {
+            RegisterShareResponse { success: true, .. } => RegisterShareStatus::Registered,
+            RegisterShareResponse {
+                failure: Some(Failure::QuotaExceeded(quota)),
+                ..
+            } => RegisterShareStatus::QuotaExceeded(quota),
+            RegisterShareResponse {
+                failure: Some(Failure::Conflict(reason)),
+                ..
+            } => RegisterShareStatus::Conflict(reason),
+            RegisterShareResponse {
+                failure: Some(failure),
+                ..
+            } => RegisterShareStatus::Refused(failure.to_string()),
+            RegisterShareResponse { reason, .. } => RegisterShareStatus::Refused(
+                reason.unwrap_or_else(|| "registration refused".to_string()),
+            ),
+        }
+    }
 }
 
 /// Represents a request to refresh share.
@@ -192,9 +530,17 @@ pub struct RegisterShareResponse {
 /// # Fields
 ///
 /// * `key` - A string representing the key associated with the share.
-/// * `refresh_key` - A vector of `Polynomial` objects used in the refresh process.
+/// * `refresh_key` - A vector of `Polynomial` objects used in the refresh process of a share split
+///   over GF(2^8), empty for one split over GF(2^16).
+/// * `wide_refresh_key` - The polynomials of the refresh of a share split over GF(2^16), empty for
+///   one split over GF(2^8). Only with the `sss16` feature.
 /// * `peer` - A byte vector representing the peer involved in the refresh process.
 /// * `sender` - A byte vector representing the sender of the request.
+/// * `epoch` - The epoch the refresh advances the share to, if the initiator knows it.
+/// * `relay_grant` - The owner's grant of the provider relaying the refresh on the owner's
+///   behalf. Only a refresh sent by the owner itself may go without one.
+/// * `trace_id` - The trace id of the operation the request is part of, if any, which the
+///   provider logs the request under.
 ///
 /// # Examples
 ///
@@ -208,16 +554,41 @@ pub struct RegisterShareResponse {
 /// let request = RefreshShareRequest {
 ///     key: "share_key".to_string(),
 ///     refresh_key: vec![Polynomial::new(2, gf256::new(5))],
+/// #   #[cfg(feature = "sss16")]
+/// #   wide_refresh_key: vec![],
 ///     peer: vec![1, 2, 3],
 ///     sender: vec![4, 5, 6],
+///     epoch: Some(1),
+///     relay_grant: None,
+///     trace_id: None,
 /// };
 /// ```
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RefreshShareRequest {
     pub key: String,
     pub refresh_key: Vec<Polynomial>,
+    #[cfg(feature = "sss16")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wide_refresh_key: Vec<crate::sss16::Polynomial>,
     pub peer: Vec<u8>,
     pub sender: Vec<u8>,
+    #[serde(default)]
+    pub epoch: Option<u64>,
+    #[serde(default)]
+    pub relay_grant: Option<RelayGrant>,
+    #[serde(default)]
+    pub trace_id: Option<TraceId>,
+}
+
+impl RefreshShareRequest {
+    /// Returns the refresh key the request carries, over the field it was generated in.
+    pub fn refresh_key(&self) -> RefreshKey {
+        #[cfg(feature = "sss16")]
+        if !self.wide_refresh_key.is_empty() {
+            return RefreshKey::Gf65536(self.wide_refresh_key.clone());
+        }
+        RefreshKey::Gf256(self.refresh_key.clone())
+    }
 }
 
 /// Represents a response to a `RefreshShare` request.
@@ -227,6 +598,8 @@ pub struct RefreshShareRequest {
 /// # Fields
 ///
 /// * `success` - A boolean indicating whether the shares were successfully refreshed.
+/// * `reason` - Why the refresh was refused, when `success` is false.
+/// * `failure` - What kept the provider from refreshing the share, when `success` is false.
 ///
 /// # Examples
 ///
@@ -237,104 +610,1608 @@ pub struct RefreshShareRequest {
 ///
 /// let response = RefreshShareResponse {
 ///     success: true,
+///     reason: None,
+///     failure: None,
 /// };
 /// ```
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RefreshShareResponse {
     pub success: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub failure: Option<Failure>,
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::sss::Polynomial;
-    use gf256::gf256;
-    use libp2p::PeerId;
+/// Represents a request to delete a share.
+///
+/// This struct is used when the owner of a share wants a provider to destroy it.
+///
+/// # Fields
+///
+/// * `key` - A string representing the key of the share.
+/// * `peer` - A byte vector representing the peer asked to delete the share.
+/// * `sender` - A byte vector representing the sender of the request.
+/// * `trace_id` - The trace id of the operation the request is part of, if any, which the
+///   provider logs the request under.
+///
+/// # Examples
+///
+/// Creating a new `DeleteShareRequest`:
+///
+/// ```rust
+/// use shard::protocol::DeleteShareRequest;
+///
+/// let request = DeleteShareRequest {
+///     key: "share_key".to_string(),
+///     peer: vec![1, 2, 3],
+///     sender: vec![4, 5, 6],
+///     trace_id: None,
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeleteShareRequest {
+    pub key: String,
+    pub peer: Vec<u8>,
+    pub sender: Vec<u8>,
+    #[serde(default)]
+    pub trace_id: Option<TraceId>,
+}
 
-    use super::*;
-    use cbor4ii::serde::to_vec;
-    use serde::Deserialize;
+/// The outcome of a `DeleteShare` request.
+///
+/// # Variants
+///
+/// * `Deleted` - The share was deleted and the provider stopped providing it.
+/// * `NotFound` - The provider holds no share under the key for the sender.
+/// * `NotOwner` - The share under the key belongs to another peer.
+/// * `Failed(String)` - The provider could not delete the share, with the reason why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeleteShareStatus {
+    Deleted,
+    NotFound,
+    NotOwner,
+    Failed(String),
+}
 
-    #[track_caller]
-    fn de<'a, T>(bytes: &'a [u8], _value: &T) -> T
-    where
-        T: Deserialize<'a>,
-    {
-        serde_cbor::from_slice(bytes).unwrap()
+impl DeleteShareStatus {
+    /// Returns the class of the failure the status reports, named as by `Failure::class`, or
+    /// `None` if the share was deleted.
+    pub fn failure_class(&self) -> Option<&'static str> {
+        match self {
+            DeleteShareStatus::Deleted => None,
+            DeleteShareStatus::NotFound => Some("not_found"),
+            DeleteShareStatus::NotOwner => Some("not_owner"),
+            DeleteShareStatus::Failed(_) => Some("storage_error"),
+        }
     }
+}
 
-    macro_rules! assert_test {
-        ( $value:expr ) => {{
-            let buf = to_vec(Vec::new(), &$value).unwrap();
-            let value = de(&buf, &$value);
-            assert_eq!(value, $value);
-        }};
-    }
+/// Represents a response to a `DeleteShare` request.
+///
+/// # Fields
+///
+/// * `status` - What the provider did with the share.
+///
+/// # Examples
+///
+/// Creating a new `DeleteShareResponse`:
+///
+/// ```rust
+/// use shard::protocol::{DeleteShareResponse, DeleteShareStatus};
+///
+/// let response = DeleteShareResponse {
+///     status: DeleteShareStatus::Deleted,
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeleteShareResponse {
+    pub status: DeleteShareStatus,
+}
 
-    #[test]
-    fn test_serialize_deserialize_get_share_request() {
-        let request = GetShareRequest {
-            key: "share_id".to_string(),
-            peer: PeerId::random().into(),
-            sender: PeerId::random().into(),
-        };
-        assert_test!(request);
-    }
+/// Represents a request to list the keys of the shares a provider holds for the sender.
+///
+/// Keys are listed in order, a page at a time.
+///
+/// # Fields
+///
+/// * `peer` - A byte vector representing the peer asked for the keys.
+/// * `sender` - A byte vector representing the sender of the request, whose keys are listed.
+/// * `cursor` - The last key of the previous page, or `None` for the first page.
+/// * `limit` - The most keys to return. Providers cap it at their own maximum.
+/// * `trace_id` - The trace id of the operation the request is part of, if any, which the
+///   provider logs the request under.
+///
+/// # Examples
+///
+/// Creating a new `ListKeysRequest`:
+///
+/// ```rust
+/// use shard::protocol::ListKeysRequest;
+///
+/// let request = ListKeysRequest {
+///     peer: vec![1, 2, 3],
+///     sender: vec![4, 5, 6],
+///     cursor: None,
+///     limit: 100,
+///     trace_id: None,
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListKeysRequest {
+    pub peer: Vec<u8>,
+    pub sender: Vec<u8>,
+    pub cursor: Option<String>,
+    pub limit: u32,
+    #[serde(default)]
+    pub trace_id: Option<TraceId>,
+}
 
-    #[test]
-    fn test_serialize_deserialize_get_share_response() {
-        let response = GetShareResponse {
-            share: (1u8, vec![1, 2, 3, 4]),
-            success: true,
-        };
-        assert_test!(response);
-    }
+/// Represents a response to a `ListKeys` request.
+///
+/// # Fields
+///
+/// * `keys` - The sender's keys on this page, in order.
+/// * `next_cursor` - The cursor to request the next page with, or `None` on the last page.
+/// * `success` - A boolean indicating whether the keys could be listed.
+/// * `reason` - Why the keys could not be listed, when `success` is false.
+/// * `failure` - What kept the provider from listing the keys, when `success` is false.
+///
+/// # Examples
+///
+/// Creating a new `ListKeysResponse`:
+///
+/// ```rust
+/// use shard::protocol::ListKeysResponse;
+///
+/// let response = ListKeysResponse {
+///     keys: vec!["share_key".to_string()],
+///     next_cursor: None,
+///     success: true,
+///     reason: None,
+///     failure: None,
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListKeysResponse {
+    pub keys: Vec<String>,
+    pub next_cursor: Option<String>,
+    pub success: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub failure: Option<Failure>,
+}
 
-    #[test]
-    fn test_serialize_deserialize_register_share_request() {
-        let request = RegisterShareRequest {
-            share: (1u8, vec![1, 2, 3, 4]),
-            key: "unique_id".to_string(),
-            peer: PeerId::random().into(),
-            sender: PeerId::random().into(),
-            threshold: 2,
-        };
-        assert_test!(request);
-    }
+/// Represents a request for the metadata of a share, without the share itself.
+///
+/// # Fields
+///
+/// * `key` - A string representing the key of the share.
+/// * `peer` - A byte vector representing the peer holding the share.
+/// * `sender` - A byte vector representing the sender of the request.
+/// * `trace_id` - The trace id of the operation the request is part of, if any, which the
+///   provider logs the request under.
+///
+/// # Examples
+///
+/// Creating a new `StatShareRequest`:
+///
+/// ```rust
+/// use shard::protocol::StatShareRequest;
+///
+/// let request = StatShareRequest {
+///     key: "share_key".to_string(),
+///     peer: vec![1, 2, 3],
+///     sender: vec![4, 5, 6],
+///     trace_id: None,
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatShareRequest {
+    pub key: String,
+    pub peer: Vec<u8>,
+    pub sender: Vec<u8>,
+    #[serde(default)]
+    pub trace_id: Option<TraceId>,
+}
 
-    #[test]
-    fn test_serialize_deserialize_register_share_response() {
-        let response = RegisterShareResponse { success: true };
-        assert_test!(response);
-    }
+/// The metadata a provider reports about a share it holds. It never carries the share bytes.
+///
+/// # Fields
+///
+/// * `index` - The share identifier, the x coordinate the share was evaluated at.
+/// * `field` - The field the secret was split over. Only with the `sss16` feature.
+/// * `length` - The length of the share data in bytes.
+/// * `threshold` - The threshold the secret was split with.
+/// * `epoch` - The number of refreshes the share has been through since it was registered.
+/// * `last_refreshed_unix` - The unix timestamp (seconds) of the last refresh, or of the
+///   registration if the share has not been refreshed yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareMetadata {
+    pub index: ShareIndex,
+    #[cfg(feature = "sss16")]
+    #[serde(default)]
+    pub field: ShareField,
+    pub length: u64,
+    pub threshold: u64,
+    pub epoch: u64,
+    pub last_refreshed_unix: u64,
+}
 
-    #[test]
-    fn test_serialize_deserialize_request_enum() {
-        let get_share_req = Request::GetShare(GetShareRequest {
-            key: "share_id".to_string(),
-            peer: PeerId::random().into(),
-            sender: PeerId::random().into(),
-        });
-        assert_test!(get_share_req);
+/// The outcome of a `StatShare` request.
+///
+/// # Variants
+///
+/// * `Found(ShareMetadata)` - The provider holds the share, described by its metadata.
+/// * `NotFound` - The provider holds no share under the key for the sender.
+/// * `NotOwner` - The share under the key belongs to another peer.
+/// * `Failed(String)` - The provider could not read the share, with the reason why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StatShareStatus {
+    Found(ShareMetadata),
+    NotFound,
+    NotOwner,
+    Failed(String),
+}
 
-        let register_share_req = Request::RegisterShare(RegisterShareRequest {
-            share: (1u8, vec![1, 2, 3, 4]),
-            key: "unique_id".to_string(),
-            peer: PeerId::random().into(),
-            sender: PeerId::random().into(),
-            threshold: 2,
-        });
-        assert_test!(register_share_req);
+impl StatShareStatus {
+    /// Returns the class of the failure the status reports, named as by `Failure::class`, or
+    /// `None` if the share was found.
+    pub fn failure_class(&self) -> Option<&'static str> {
+        match self {
+            StatShareStatus::Found(_) => None,
+            StatShareStatus::NotFound => Some("not_found"),
+            StatShareStatus::NotOwner => Some("not_owner"),
+            StatShareStatus::Failed(_) => Some("storage_error"),
+        }
+    }
+}
+
+/// Represents a response to a `StatShare` request.
+///
+/// # Fields
+///
+/// * `status` - The metadata of the share, or why there is none.
+///
+/// # Examples
+///
+/// Creating a new `StatShareResponse`:
+///
+/// ```rust
+/// use shard::protocol::{StatShareResponse, StatShareStatus};
+///
+/// let response = StatShareResponse {
+///     status: StatShareStatus::NotFound,
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatShareResponse {
+    pub status: StatShareStatus,
+}
+
+/// Represents a request from the owner of a share to grant or revoke a peer's read access.
+///
+/// A peer granted access can get the share, but not register, refresh, delete, or share it
+/// further.
+///
+/// # Fields
+///
+/// * `key` - A string representing the key of the share.
+/// * `peer` - A byte vector representing the peer holding the share.
+/// * `sender` - A byte vector representing the sender of the request, the owner of the share.
+/// * `reader` - A byte vector representing the peer whose access changes.
+/// * `trace_id` - The trace id of the operation the request is part of, if any, which the
+///   provider logs the request under.
+///
+/// # Examples
+///
+/// Creating a new `AccessRequest`:
+///
+/// ```rust
+/// use shard::protocol::{AccessRequest, Request};
+///
+/// let request = Request::GrantAccess(AccessRequest {
+///     key: "share_key".to_string(),
+///     peer: vec![1, 2, 3],
+///     sender: vec![4, 5, 6],
+///     reader: vec![7, 8, 9],
+///     trace_id: None,
+/// });
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessRequest {
+    pub key: String,
+    pub peer: Vec<u8>,
+    pub sender: Vec<u8>,
+    pub reader: Vec<u8>,
+    #[serde(default)]
+    pub trace_id: Option<TraceId>,
+}
+
+/// Represents a response to a `GrantAccess` or `RevokeAccess` request.
+///
+/// # Fields
+///
+/// * `success` - A boolean indicating whether the access was changed.
+/// * `reason` - Why the change was refused, when `success` is false.
+/// * `failure` - What kept the provider from changing the access, when `success` is false.
+///
+/// # Examples
+///
+/// Creating a new `AccessResponse`:
+///
+/// ```rust
+/// use shard::protocol::AccessResponse;
+///
+/// let response = AccessResponse {
+///     success: true,
+///     reason: None,
+///     failure: None,
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessResponse {
+    pub success: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub failure: Option<Failure>,
+}
+
+/// Represents a request to hand the share the sender registered under a key over to another
+/// owner, such as when the sender rotates its identity key.
+///
+/// The request is signed with the key of the sender, so that a provider can check that the
+/// sender itself asked for the transfer, of this share, to this owner, at this provider (see
+/// `TransferOwnershipRequest::signed`).
+///
+/// # Fields
+///
+/// * `key` - A string representing the key of the share.
+/// * `peer` - A byte vector representing the peer holding the share.
+/// * `sender` - A byte vector representing the sender of the request, the current owner.
+/// * `new_owner` - A byte vector representing the peer the share is handed over to.
+/// * `public_key` - The protobuf encoding of the sender's public key.
+/// * `signature` - The sender's signature of the transfer (see `signing_payload`).
+/// * `relay_grant` - The new owner's grant for the provider to relay refreshes of the share once
+///   it is handed over, which the signature of the sender does not cover. Without it, the
+///   provider stops coordinating refreshes of the share.
+/// * `trace_id` - The trace id of the operation the request is part of, if any, which the
+///   provider logs the request under.
+///
+/// # Examples
+///
+/// Signing a transfer and checking it:
+///
+/// ```rust
+/// use libp2p::identity::Keypair;
+/// use libp2p::PeerId;
+/// use shard::protocol::TransferOwnershipRequest;
+///
+/// let owner = Keypair::generate_ed25519();
+/// let provider = PeerId::random();
+/// let new_owner = PeerId::random();
+/// let request =
+///     TransferOwnershipRequest::signed("share_key", provider, &owner, new_owner, None).unwrap();
+/// assert_eq!(request.verify(), Ok(new_owner));
+///
+/// let forged = TransferOwnershipRequest {
+///     new_owner: PeerId::random().to_bytes(),
+///     ..request
+/// };
+/// assert!(forged.verify().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferOwnershipRequest {
+    pub key: String,
+    pub peer: Vec<u8>,
+    pub sender: Vec<u8>,
+    pub new_owner: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+    #[serde(default)]
+    pub relay_grant: Option<RelayGrant>,
+    #[serde(default)]
+    pub trace_id: Option<TraceId>,
+}
+
+impl TransferOwnershipRequest {
+    /// The domain the signature of a transfer is made in, so that it cannot pass for the
+    /// signature of anything else.
+    const SIGNING_DOMAIN: &'static [u8] = b"shard/transfer-ownership/1";
+
+    /// Builds a transfer of the share `owner` registered under `key` to `new_owner`, signed by
+    /// `owner`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the share.
+    /// * `peer` - The `PeerId` of the peer holding the share.
+    /// * `owner` - The identity key of the current owner, who sends the request.
+    /// * `new_owner` - The `PeerId` of the peer the share is handed over to.
+    /// * `trace_id` - The trace id of the operation the request is part of, if any.
+    ///
+    /// # Returns
+    ///
+    /// The signed request, or the error of a key that cannot sign.
+    pub fn signed(
+        key: &str,
+        peer: PeerId,
+        owner: &Keypair,
+        new_owner: PeerId,
+        trace_id: Option<TraceId>,
+    ) -> Result<Self, SigningError> {
+        let sender = owner.public().to_peer_id().to_bytes();
+        let new_owner = new_owner.to_bytes();
+        let peer = peer.to_bytes();
+        let signature = owner.sign(&Self::signing_payload(key, &peer, &sender, &new_owner))?;
+        Ok(TransferOwnershipRequest {
+            key: key.to_string(),
+            peer,
+            sender,
+            new_owner,
+            public_key: owner.public().encode_protobuf(),
+            signature,
+            relay_grant: None,
+            trace_id,
+        })
+    }
+
+    /// Returns the bytes the owner signs: the signing domain followed by the key, the provider,
+    /// the owner and the new owner, each prefixed with its length.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the share.
+    /// * `peer` - The peer id bytes of the peer holding the share.
+    /// * `sender` - The peer id bytes of the current owner.
+    /// * `new_owner` - The peer id bytes of the new owner.
+    pub fn signing_payload(key: &str, peer: &[u8], sender: &[u8], new_owner: &[u8]) -> Vec<u8> {
+        let mut payload = Self::SIGNING_DOMAIN.to_vec();
+        for field in [key.as_bytes(), peer, sender, new_owner] {
+            payload.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            payload.extend_from_slice(field);
+        }
+        payload
+    }
+
+    /// Checks that the request was signed by its sender and names a valid new owner.
+    ///
+    /// # Returns
+    ///
+    /// The `PeerId` of the new owner, or the reason the request cannot be trusted.
+    pub fn verify(&self) -> Result<PeerId, String> {
+        let new_owner = PeerId::from_bytes(&self.new_owner)
+            .map_err(|e| format!("malformed new owner: {e}"))?;
+        let public_key = PublicKey::try_decode_protobuf(&self.public_key)
+            .map_err(|e| format!("malformed public key: {e}"))?;
+        if public_key.to_peer_id().to_bytes() != self.sender {
+            return Err("public key is not the sender's".to_string());
+        }
+        let payload =
+            Self::signing_payload(&self.key, &self.peer, &self.sender, &self.new_owner);
+        if !public_key.verify(&payload, &self.signature) {
+            return Err("invalid signature".to_string());
+        }
+        Ok(new_owner)
+    }
+}
+
+/// Signing and checking of the grant an owner gives each provider of its share, so that the
+/// provider coordinating a refresh can show the others that the owner let it relay refreshes.
+///
+/// # Examples
+///
+/// ```rust
+/// use libp2p::identity::Keypair;
+/// use libp2p::PeerId;
+/// use shard::protocol::RelayGrant;
+///
+/// let owner = Keypair::generate_ed25519();
+/// let sender = owner.public().to_peer_id().to_bytes();
+/// let provider = PeerId::random();
+/// let grant = RelayGrant::signed("share_key", provider, &owner).unwrap();
+/// assert_eq!(grant.verify("share_key", &provider.to_bytes(), &sender), Ok(()));
+///
+/// // the grant of one provider does not let another relay
+/// let other = PeerId::random().to_bytes();
+/// assert!(grant.verify("share_key", &other, &sender).is_err());
+/// ```
+impl RelayGrant {
+    /// The domain the signature of a grant is made in, so that it cannot pass for the signature
+    /// of anything else.
+    const SIGNING_DOMAIN: &'static [u8] = b"shard/relay-grant/1";
+
+    /// Builds the grant letting `peer` relay refreshes of the share `owner` registers under
+    /// `key`, signed by `owner`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the share.
+    /// * `peer` - The `PeerId` of the provider the share is registered with.
+    /// * `owner` - The identity key of the owner of the share.
+    ///
+    /// # Returns
+    ///
+    /// The signed grant, or the error of a key that cannot sign.
+    pub fn signed(key: &str, peer: PeerId, owner: &Keypair) -> Result<Self, SigningError> {
+        let sender = owner.public().to_peer_id().to_bytes();
+        let payload = Self::signing_payload(key, &peer.to_bytes(), &sender);
+        Ok(RelayGrant {
+            public_key: owner.public().encode_protobuf(),
+            signature: owner.sign(&payload)?,
+        })
+    }
+
+    /// Returns the bytes the owner signs: the signing domain followed by the key, the provider
+    /// and the owner, each prefixed with its length.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the share.
+    /// * `peer` - The peer id bytes of the provider given the grant.
+    /// * `sender` - The peer id bytes of the owner.
+    pub fn signing_payload(key: &str, peer: &[u8], sender: &[u8]) -> Vec<u8> {
+        let mut payload = Self::SIGNING_DOMAIN.to_vec();
+        for field in [key.as_bytes(), peer, sender] {
+            payload.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            payload.extend_from_slice(field);
+        }
+        payload
+    }
+
+    /// Checks that the grant was signed by `sender` for `peer` to relay refreshes of the share
+    /// under `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the share.
+    /// * `peer` - The peer id bytes of the provider showing the grant.
+    /// * `sender` - The peer id bytes of the owner.
+    ///
+    /// # Returns
+    ///
+    /// The reason the grant cannot be trusted, if it cannot.
+    pub fn verify(&self, key: &str, peer: &[u8], sender: &[u8]) -> Result<(), String> {
+        let public_key = PublicKey::try_decode_protobuf(&self.public_key)
+            .map_err(|e| format!("malformed public key: {e}"))?;
+        if public_key.to_peer_id().to_bytes() != sender {
+            return Err("public key is not the owner's".to_string());
+        }
+        if !public_key.verify(&Self::signing_payload(key, peer, sender), &self.signature) {
+            return Err("invalid signature".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Represents a response to a `TransferOwnership` request.
+///
+/// # Fields
+///
+/// * `success` - A boolean indicating whether the share was handed over.
+/// * `reason` - Why the transfer was refused, when `success` is false.
+/// * `failure` - What kept the provider from handing the share over, when `success` is false.
+///
+/// # Examples
+///
+/// Creating a new `TransferOwnershipResponse`:
+///
+/// ```rust
+/// use shard::protocol::TransferOwnershipResponse;
+///
+/// let response = TransferOwnershipResponse {
+///     success: true,
+///     reason: None,
+///     failure: None,
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferOwnershipResponse {
+    pub success: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub failure: Option<Failure>,
+}
+
+/// Why a provider did not carry out a request, sent along with the reason of a failed response.
+///
+/// # Variants
+///
+/// * `NotFound` - The provider holds no live share under the key.
+/// * `NotOwner { expected, actual }` - The share under the key belongs to another peer; carries
+///   the prefixes of the expected and actual owners' peer ids.
+/// * `NotReader` - The sender is neither the owner nor a reader of the share.
+/// * `InvalidRequest(String)` - The request cannot be carried out as sent, with the reason why.
+/// * `QuotaExceeded(QuotaUsage)` - Storing the share would exceed a storage limit.
+/// * `RecentlyDeleted` - The owner deleted the share under the key recently.
+/// * `Conflict(String)` - The owner stored a share under the key with a different share index,
+///   length or threshold; carries how they differ.
+/// * `Corrupt` - The share under the key failed its integrity check and must be registered again.
+/// * `StaleEpoch { stored, requested }` - The refresh is for an epoch the share already moved
+///   past.
+/// * `StorageError(String)` - The provider could not read or write its store.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::protocol::Failure;
+///
+/// let failure = Failure::StaleEpoch { stored: 4, requested: 3 };
+/// assert_eq!(failure.to_string(), "stale refresh epoch 3, the share is at epoch 4");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Failure {
+    NotFound,
+    NotOwner { expected: String, actual: String },
+    NotReader,
+    InvalidRequest(String),
+    QuotaExceeded(QuotaUsage),
+    RecentlyDeleted,
+    Conflict(String),
+    Corrupt,
+    StaleEpoch { stored: u64, requested: u64 },
+    StorageError(String),
+}
+
+impl Failure {
+    /// Returns the class of the failure: the snake case name of its variant, without the details
+    /// it carries, suitable as a metrics label.
+    pub fn class(&self) -> &'static str {
+        match self {
+            Failure::NotFound => "not_found",
+            Failure::NotOwner { .. } => "not_owner",
+            Failure::NotReader => "not_reader",
+            Failure::InvalidRequest(_) => "invalid_request",
+            Failure::QuotaExceeded(_) => "quota_exceeded",
+            Failure::RecentlyDeleted => "recently_deleted",
+            Failure::Conflict(_) => "conflict",
+            Failure::Corrupt => "corrupt",
+            Failure::StaleEpoch { .. } => "stale_epoch",
+            Failure::StorageError(_) => "storage_error",
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::NotFound => write!(f, "share not found"),
+            Failure::NotOwner { expected, actual } => write!(
+                f,
+                "share not owned by sender: expected {}, owned by {}",
+                expected, actual
+            ),
+            Failure::NotReader => write!(f, "share not readable by sender"),
+            Failure::InvalidRequest(reason) => write!(f, "{}", reason),
+            Failure::QuotaExceeded(quota) => write!(
+                f,
+                "storage quota exceeded: {} is {}, {} already used",
+                quota.limit, quota.max, quota.used
+            ),
+            Failure::RecentlyDeleted => write!(f, "share was recently deleted by its owner"),
+            Failure::Conflict(reason) => {
+                write!(
+                    f,
+                    "registration conflicts with the stored share: {}",
+                    reason
+                )
+            }
+            Failure::Corrupt => write!(f, "share failed its integrity check, register it again"),
+            Failure::StaleEpoch { stored, requested } => write!(
+                f,
+                "stale refresh epoch {}, the share is at epoch {}",
+                requested, stored
+            ),
+            Failure::StorageError(reason) => write!(f, "storage error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// Represents a response to a request that was rejected as malformed before being handled.
+///
+/// # Fields
+///
+/// * `reason` - What was wrong with the request.
+///
+/// # Examples
+///
+/// Creating a new `InvalidRequestResponse`:
+///
+/// ```rust
+/// use shard::protocol::InvalidRequestResponse;
+///
+/// let response = InvalidRequestResponse {
+///     reason: "malformed sender".to_string(),
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvalidRequestResponse {
+    pub reason: String,
+}
+
+/// Represents a response to a request that was turned down because the sender exceeded the
+/// provider's rate limit for its operation.
+///
+/// # Fields
+///
+/// * `retry_after_millis` - How long, in milliseconds, until the provider accepts the operation
+///   from the sender again.
+///
+/// # Examples
+///
+/// Creating a new `RateLimitedResponse`:
+///
+/// ```rust
+/// use shard::protocol::RateLimitedResponse;
+///
+/// let response = RateLimitedResponse {
+///     retry_after_millis: 250,
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimitedResponse {
+    pub retry_after_millis: u64,
+}
+
+/// Represents a response to a request that was turned down because the provider cannot take it
+/// on, such as while it is shutting down.
+///
+/// # Fields
+///
+/// * `reason` - Why the provider turned the request down.
+///
+/// # Examples
+///
+/// Creating a new `BusyResponse`:
+///
+/// ```rust
+/// use shard::protocol::BusyResponse;
+///
+/// let response = BusyResponse {
+///     reason: "shutting down".to_string(),
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BusyResponse {
+    pub reason: String,
+}
+
+/// Represents a message broadcast to every node over gossipsub.
+///
+/// # Variants
+///
+/// * `UnderReplicated(UnderReplicatedAlert)` - A provider found a share it holds with too few
+///   providers left to stay safely recoverable.
+///
+/// # Examples
+///
+/// Encoding a message for publishing:
+///
+/// ```rust
+/// use libp2p::PeerId;
+/// use shard::protocol::{GossipMessage, UnderReplicatedAlert};
+///
+/// let message = GossipMessage::UnderReplicated(UnderReplicatedAlert {
+///     key: "share_id".to_string(),
+///     owner: PeerId::random().into(),
+///     providers: 2,
+///     required: 3,
+/// });
+/// let bytes = message.to_bytes().unwrap();
+/// assert_eq!(GossipMessage::from_bytes(&bytes).unwrap(), message);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GossipMessage {
+    UnderReplicated(UnderReplicatedAlert),
+}
+
+impl GossipMessage {
+    /// Encodes the message as the payload of a gossipsub message.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, serde_cbor::Error> {
+        serde_cbor::to_vec(self)
+    }
+
+    /// Decodes the payload of a gossipsub message.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The payload received.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, serde_cbor::Error> {
+        serde_cbor::from_slice(bytes)
+    }
+}
+
+/// Represents an alert that a share has fewer providers than it should, so that its owner can
+/// register it with more.
+///
+/// # Fields
+///
+/// * `key` - The key the owner registered the share under.
+/// * `owner` - A byte vector representing the owner of the share.
+/// * `providers` - The number of providers the alerting provider found for the share.
+/// * `required` - The number of providers the share should have: its threshold plus the
+///   provider's safety margin.
+///
+/// # Examples
+///
+/// Creating a new `UnderReplicatedAlert`:
+///
+/// ```rust
+/// use libp2p::PeerId;
+/// use shard::protocol::UnderReplicatedAlert;
+///
+/// let alert = UnderReplicatedAlert {
+///     key: "share_id".to_string(),
+///     owner: PeerId::random().into(),
+///     providers: 2,
+///     required: 3,
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnderReplicatedAlert {
+    pub key: String,
+    pub owner: Vec<u8>,
+    pub providers: u64,
+    pub required: u64,
+}
+
+/// Represents the health status a provider publishes about itself on the health topic.
+///
+/// Gossipsub signs every message with the key of its publisher, so a status is only trusted if
+/// `peer` is the peer that signed it (see `verify`).
+///
+/// # Fields
+///
+/// * `peer` - A byte vector representing the provider the status describes.
+/// * `shares` - The number of shares the provider holds.
+/// * `db_bytes` - The size in bytes of the shares the provider holds.
+/// * `uptime_secs` - The number of seconds the provider has been running.
+/// * `version` - The version of shard the provider runs.
+/// * `reachable` - Whether the provider knows of an external address peers can reach it on.
+/// * `interval_secs` - The number of seconds until the provider publishes its next status.
+/// * `metrics` - The counters and gauges of the provider. Statuses of providers that predate
+///   them carry none.
+///
+/// # Examples
+///
+/// Creating a new `ProviderStatus` and checking it against its signer:
+///
+/// ```rust
+/// use libp2p::PeerId;
+/// use shard::protocol::{MetricsSnapshot, ProviderStatus};
+///
+/// let provider = PeerId::random();
+/// let status = ProviderStatus {
+///     peer: provider.to_bytes(),
+///     shares: 12,
+///     db_bytes: 4096,
+///     uptime_secs: 3600,
+///     version: "0.1.0".to_string(),
+///     reachable: true,
+///     interval_secs: 10,
+///     metrics: MetricsSnapshot::default(),
+/// };
+/// assert_eq!(status.verify(Some(provider)), Ok(provider));
+/// assert!(status.verify(Some(PeerId::random())).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderStatus {
+    pub peer: Vec<u8>,
+    pub shares: u64,
+    pub db_bytes: u64,
+    pub uptime_secs: u64,
+    pub version: String,
+    pub reachable: bool,
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub metrics: MetricsSnapshot,
+}
+
+/// The counters and gauges a provider keeps about its work, as published in its health status.
+///
+/// Counters start at zero when the provider starts.
+///
+/// # Fields
+///
+/// * `registrations` - The number of shares registered.
+/// * `gets_served` - The number of shares sent to their owners or readers.
+/// * `refreshes_applied` - The number of refreshes applied to stored shares, whichever provider
+///   initiated them.
+/// * `refreshes_initiated` - The number of refreshes the provider initiated as the coordinator of
+///   a share.
+/// * `invalid_requests` - The number of requests rejected as malformed.
+/// * `rate_limited_requests` - The number of requests turned down by the rate limiter.
+/// * `failures` - The number of failed requests by class of `Failure` (see `Failure::class`).
+/// * `oldest_refresh_age_secs` - The number of seconds since the least recently refreshed share
+///   was refreshed, as of the last refresh pass, or `None` before the first pass or if the
+///   provider holds no shares.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::protocol::MetricsSnapshot;
+///
+/// let metrics = MetricsSnapshot {
+///     registrations: 3,
+///     gets_served: 5,
+///     oldest_refresh_age_secs: Some(120),
+///     ..Default::default()
+/// };
+/// assert!(metrics.failures.is_empty());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub registrations: u64,
+    pub gets_served: u64,
+    pub refreshes_applied: u64,
+    pub refreshes_initiated: u64,
+    pub invalid_requests: u64,
+    pub rate_limited_requests: u64,
+    pub failures: BTreeMap<String, u64>,
+    pub oldest_refresh_age_secs: Option<u64>,
+}
+
+impl ProviderStatus {
+    /// Encodes the status as the payload of a gossipsub message.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, serde_cbor::Error> {
+        serde_cbor::to_vec(self)
+    }
+
+    /// Decodes the payload of a gossipsub message.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The payload received.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, serde_cbor::Error> {
+        serde_cbor::from_slice(bytes)
+    }
+
+    /// Checks that the status describes the peer that signed it.
+    ///
+    /// # Arguments
+    ///
+    /// * `signer` - The verified publisher of the gossipsub message the status arrived in.
+    ///
+    /// # Returns
+    ///
+    /// The `PeerId` the status describes, or the reason it cannot be trusted.
+    pub fn verify(&self, signer: Option<PeerId>) -> Result<PeerId, String> {
+        let peer = PeerId::from_bytes(&self.peer).map_err(|e| format!("malformed peer: {e}"))?;
+        match signer {
+            Some(signer) if signer == peer => Ok(peer),
+            Some(signer) => Err(format!("status of {peer} signed by {signer}")),
+            None => Err(format!("unsigned status of {peer}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sss::Polynomial;
+    use gf256::gf256;
+    use libp2p::PeerId;
+
+    use super::*;
+    use cbor4ii::serde::to_vec;
+    use serde::Deserialize;
+
+    #[track_caller]
+    fn de<'a, T>(bytes: &'a [u8], _value: &T) -> T
+    where
+        T: Deserialize<'a>,
+    {
+        serde_cbor::from_slice(bytes).unwrap()
+    }
+
+    macro_rules! assert_test {
+        ( $value:expr ) => {{
+            let buf = to_vec(Vec::new(), &$value).unwrap();
+            let value = de(&buf, &$value);
+            assert_eq!(value, $value);
+        }};
+    }
+
+    #[test]
+    fn test_serialize_deserialize_get_share_request() {
+        let request = GetShareRequest {
+            key: "share_id".to_string(),
+            peer: PeerId::random().into(),
+            sender: PeerId::random().into(),
+            owner: Some(PeerId::random().into()),
+            trace_id: None,
+        };
+        assert_test!(request);
+    }
+
+    #[test]
+    fn test_serialize_deserialize_get_share_response() {
+        let response = GetShareResponse {
+            share: (1, vec![1, 2, 3, 4]),
+            #[cfg(feature = "sss16")]
+            field: ShareField::Gf256,
+            success: true,
+            reason: None,
+            failure: None,
+        };
+        assert_test!(response);
+
+        let response = GetShareResponse {
+            share: (0, vec![]),
+            #[cfg(feature = "sss16")]
+            field: ShareField::Gf256,
+            success: false,
+            reason: Some("share not found".to_string()),
+            failure: Some(Failure::NotFound),
+        };
+        assert_test!(response);
+    }
+
+    #[test]
+    fn test_serialize_deserialize_register_share_request() {
+        let request = RegisterShareRequest {
+            share: (1, vec![1, 2, 3, 4]),
+            #[cfg(feature = "sss16")]
+            field: ShareField::Gf256,
+            key: "unique_id".to_string(),
+            peer: PeerId::random().into(),
+            sender: PeerId::random().into(),
+            threshold: 2,
+            ttl_secs: Some(60),
+            recreate: true,
+            refresh_interval_secs: Some(3600),
+            replace: true,
+            relay_grant: None,
+            trace_id: None,
+        };
+        assert_test!(request);
+    }
+
+    #[test]
+    fn test_serialize_deserialize_register_share_response() {
+        let response = RegisterShareResponse {
+            success: true,
+            reason: None,
+            failure: None,
+        };
+        assert_test!(response);
+
+        let response = RegisterShareResponse {
+            success: false,
+            reason: Some(
+                "storage quota exceeded: max_entries_per_owner is 3, 3 already used".to_string(),
+            ),
+            failure: Some(Failure::QuotaExceeded(QuotaUsage {
+                limit: "max_entries_per_owner".to_string(),
+                max: 3,
+                used: 3,
+            })),
+        };
+        assert_test!(response);
+    }
+
+    #[test]
+    fn test_register_share_status_from_response() {
+        let quota = QuotaUsage {
+            limit: "max_total_bytes".to_string(),
+            max: 1024,
+            used: 1000,
+        };
+        let refused = RegisterShareResponse {
+            success: false,
+            reason: Some("storage quota exceeded".to_string()),
+            failure: Some(Failure::QuotaExceeded(quota.clone())),
+        };
+        assert_eq!(
+            RegisterShareStatus::from(refused),
+            RegisterShareStatus::QuotaExceeded(quota)
+        );
+
+        let refused = RegisterShareResponse {
+            success: false,
+            reason: Some("share for key was recently deleted".to_string()),
+            failure: Some(Failure::RecentlyDeleted),
+        };
+        assert_eq!(
+            RegisterShareStatus::from(refused),
+            RegisterShareStatus::Refused("share was recently deleted by its owner".to_string())
+        );
+
+        let refused = RegisterShareResponse {
+            success: false,
+            reason: Some("registration refused by an older provider".to_string()),
+            failure: None,
+        };
+        assert_eq!(
+            RegisterShareStatus::from(refused),
+            RegisterShareStatus::Refused("registration refused by an older provider".to_string())
+        );
+    }
+
+    #[test]
+    fn test_serialize_deserialize_failure() {
+        let failures = vec![
+            Failure::NotFound,
+            Failure::NotOwner {
+                expected: "12D3KooWAbCdEfGh".to_string(),
+                actual: "12D3KooWZyXwVuTs".to_string(),
+            },
+            Failure::NotReader,
+            Failure::InvalidRequest("malformed reader".to_string()),
+            Failure::QuotaExceeded(QuotaUsage {
+                limit: "max_total_bytes".to_string(),
+                max: 1024,
+                used: 1000,
+            }),
+            Failure::RecentlyDeleted,
+            Failure::StaleEpoch {
+                stored: 4,
+                requested: 3,
+            },
+            Failure::StorageError("checksum mismatch".to_string()),
+        ];
+        for failure in failures {
+            assert_test!(failure);
+        }
+    }
+
+    #[test]
+    fn test_response_without_failure_field_deserializes() {
+        #[derive(Serialize)]
+        struct LegacyAccessResponse {
+            success: bool,
+            reason: Option<String>,
+        }
+        let legacy = LegacyAccessResponse {
+            success: false,
+            reason: Some("share not found".to_string()),
+        };
+        let bytes = serde_cbor::to_vec(&legacy).unwrap();
+        let response: AccessResponse = serde_cbor::from_slice(&bytes).unwrap();
+        assert_eq!(response.failure, None);
+        assert_eq!(response.reason.as_deref(), Some("share not found"));
+    }
+
+    #[test]
+    fn test_share_with_an_8_bit_index_and_no_field_deserializes_over_gf256() {
+        #[derive(Serialize)]
+        struct LegacyGetShareResponse {
+            share: (u8, Vec<u8>),
+            success: bool,
+            reason: Option<String>,
+        }
+        let legacy = LegacyGetShareResponse {
+            share: (200, vec![1, 2, 3]),
+            success: true,
+            reason: None,
+        };
+        let bytes = to_vec(Vec::new(), &legacy).unwrap();
+        let response: GetShareResponse = serde_cbor::from_slice(&bytes).unwrap();
+        assert_eq!(response.share, (200, vec![1, 2, 3]));
+        #[cfg(feature = "sss16")]
+        assert_eq!(response.field, ShareField::Gf256);
+    }
+
+    #[cfg(feature = "sss16")]
+    #[test]
+    fn test_share_over_gf65536_roundtrips() {
+        let wide = GetShareResponse {
+            share: (1000, vec![0, 1]),
+            field: ShareField::Gf65536,
+            success: true,
+            reason: None,
+            failure: None,
+        };
+        assert_test!(wide);
+    }
+
+    #[test]
+    fn test_serialize_deserialize_refresh_share_response() {
+        let response = RefreshShareResponse {
+            success: true,
+            reason: None,
+            failure: None,
+        };
+        assert_test!(response);
+
+        let response = RefreshShareResponse {
+            success: false,
+            reason: Some("stale refresh epoch 3, the share is at epoch 4".to_string()),
+            failure: Some(Failure::StaleEpoch {
+                stored: 4,
+                requested: 3,
+            }),
+        };
+        assert_test!(response);
+    }
+
+    #[test]
+    fn test_serialize_deserialize_delete_share() {
+        let request = DeleteShareRequest {
+            key: "share_id".to_string(),
+            peer: PeerId::random().into(),
+            sender: PeerId::random().into(),
+            trace_id: None,
+        };
+        assert_test!(request);
+        let request = Request::DeleteShare(request);
+        assert_test!(request);
+
+        for status in [
+            DeleteShareStatus::Deleted,
+            DeleteShareStatus::NotFound,
+            DeleteShareStatus::NotOwner,
+            DeleteShareStatus::Failed("repository is read-only".to_string()),
+        ] {
+            let response = Response::DeleteShare(DeleteShareResponse { status });
+            assert_test!(response);
+        }
+    }
+
+    #[test]
+    fn test_serialize_deserialize_list_keys() {
+        let request = Request::ListKeys(ListKeysRequest {
+            peer: PeerId::random().into(),
+            sender: PeerId::random().into(),
+            cursor: Some("share_id".to_string()),
+            limit: 10,
+            trace_id: None,
+        });
+        assert_test!(request);
+
+        let response = Response::ListKeys(ListKeysResponse {
+            keys: vec!["a".to_string(), "b".to_string()],
+            next_cursor: Some("b".to_string()),
+            success: true,
+            reason: None,
+            failure: None,
+        });
+        assert_test!(response);
+    }
+
+    #[test]
+    fn test_serialize_deserialize_stat_share() {
+        let request = Request::StatShare(StatShareRequest {
+            key: "share_id".to_string(),
+            peer: PeerId::random().into(),
+            sender: PeerId::random().into(),
+            trace_id: None,
+        });
+        assert_test!(request);
+
+        let metadata = ShareMetadata {
+            index: 3,
+            #[cfg(feature = "sss16")]
+            field: ShareField::Gf256,
+            length: 32,
+            threshold: 2,
+            epoch: 4,
+            last_refreshed_unix: 1_700_000_000,
+        };
+        for status in [
+            StatShareStatus::Found(metadata),
+            StatShareStatus::NotFound,
+            StatShareStatus::NotOwner,
+            StatShareStatus::Failed("share failed its integrity check".to_string()),
+        ] {
+            let response = Response::StatShare(StatShareResponse { status });
+            assert_test!(response);
+        }
+    }
+
+    #[test]
+    fn test_serialize_deserialize_invalid_request_response() {
+        let response = Response::InvalidRequest(InvalidRequestResponse {
+            reason: "malformed sender".to_string(),
+        });
+        assert_test!(response);
+    }
+
+    #[test]
+    fn test_serialize_deserialize_rate_limited_response() {
+        let response = Response::RateLimited(RateLimitedResponse {
+            retry_after_millis: 250,
+        });
+        assert_test!(response);
+    }
+
+    #[test]
+    fn test_serialize_deserialize_busy_response() {
+        let response = Response::Busy(BusyResponse {
+            reason: "shutting down".to_string(),
+        });
+        assert_test!(response);
+    }
+
+    #[test]
+    fn test_serialize_deserialize_gossip_message() {
+        let message = GossipMessage::UnderReplicated(UnderReplicatedAlert {
+            key: "share_id".to_string(),
+            owner: PeerId::random().into(),
+            providers: 2,
+            required: 3,
+        });
+        assert_test!(message);
+        let bytes = message.to_bytes().unwrap();
+        assert_eq!(GossipMessage::from_bytes(&bytes).unwrap(), message);
+        assert!(GossipMessage::from_bytes(b"not a message").is_err());
+    }
+
+    fn status(peer: PeerId) -> ProviderStatus {
+        ProviderStatus {
+            peer: peer.to_bytes(),
+            shares: 12,
+            db_bytes: 4096,
+            uptime_secs: 3600,
+            version: "0.1.0".to_string(),
+            reachable: false,
+            interval_secs: 10,
+            metrics: MetricsSnapshot {
+                registrations: 4,
+                gets_served: 9,
+                refreshes_applied: 2,
+                refreshes_initiated: 1,
+                invalid_requests: 0,
+                rate_limited_requests: 3,
+                failures: BTreeMap::from([("not_found".to_string(), 2)]),
+                oldest_refresh_age_secs: Some(600),
+            },
+        }
+    }
+
+    #[test]
+    fn test_serialize_deserialize_provider_status() {
+        let status = status(PeerId::random());
+        assert_test!(status);
+        let bytes = status.to_bytes().unwrap();
+        assert_eq!(ProviderStatus::from_bytes(&bytes).unwrap(), status);
+    }
+
+    #[test]
+    fn test_provider_status_without_metrics_deserializes() {
+        #[derive(Serialize)]
+        struct LegacyProviderStatus {
+            peer: Vec<u8>,
+            shares: u64,
+            db_bytes: u64,
+            uptime_secs: u64,
+            version: String,
+            reachable: bool,
+            interval_secs: u64,
+        }
+        let legacy = LegacyProviderStatus {
+            peer: PeerId::random().to_bytes(),
+            shares: 1,
+            db_bytes: 2,
+            uptime_secs: 3,
+            version: "0.1.0".to_string(),
+            reachable: true,
+            interval_secs: 10,
+        };
+        let bytes = serde_cbor::to_vec(&legacy).unwrap();
+        let status = ProviderStatus::from_bytes(&bytes).unwrap();
+        assert_eq!(status.metrics, MetricsSnapshot::default());
+    }
+
+    #[test]
+    fn test_failure_classes_are_distinct() {
+        let failures = [
+            Failure::NotFound,
+            Failure::NotOwner {
+                expected: String::new(),
+                actual: String::new(),
+            },
+            Failure::NotReader,
+            Failure::InvalidRequest(String::new()),
+            Failure::QuotaExceeded(QuotaUsage {
+                limit: String::new(),
+                max: 0,
+                used: 0,
+            }),
+            Failure::RecentlyDeleted,
+            Failure::Conflict(String::new()),
+            Failure::Corrupt,
+            Failure::StaleEpoch {
+                stored: 0,
+                requested: 0,
+            },
+            Failure::StorageError(String::new()),
+        ];
+        let classes: std::collections::HashSet<_> = failures.iter().map(Failure::class).collect();
+        assert_eq!(classes.len(), failures.len());
+        assert_eq!(
+            DeleteShareStatus::NotOwner.failure_class(),
+            Some(failures[1].class())
+        );
+        assert_eq!(
+            StatShareStatus::Failed(String::new()).failure_class(),
+            Some(failures[9].class())
+        );
+    }
+
+    #[test]
+    fn test_provider_status_must_be_signed_by_its_peer() {
+        let provider = PeerId::random();
+        let status = status(provider);
+
+        assert_eq!(status.verify(Some(provider)), Ok(provider));
+        assert!(status.verify(Some(PeerId::random())).is_err());
+        assert!(status.verify(None).is_err());
+        let malformed = ProviderStatus {
+            peer: vec![1, 2, 3],
+            ..status
+        };
+        assert!(malformed.verify(Some(provider)).is_err());
+    }
+
+    #[test]
+    fn test_serialize_deserialize_access() {
+        let access = AccessRequest {
+            key: "share_id".to_string(),
+            peer: PeerId::random().into(),
+            sender: PeerId::random().into(),
+            reader: PeerId::random().into(),
+            trace_id: None,
+        };
+        let request = Request::GrantAccess(access.clone());
+        assert_test!(request);
+        let request = Request::RevokeAccess(access);
+        assert_test!(request);
+
+        let response = Response::Access(AccessResponse {
+            success: false,
+            reason: Some("share not found".to_string()),
+            failure: Some(Failure::NotFound),
+        });
+        assert_test!(response);
+    }
+
+    #[test]
+    fn test_serialize_deserialize_transfer_ownership() {
+        let owner = Keypair::generate_ed25519();
+        let request = TransferOwnershipRequest::signed(
+            "share_id",
+            PeerId::random(),
+            &owner,
+            PeerId::random(),
+            Some(TraceId(7)),
+        )
+        .unwrap();
+        assert_test!(request);
+        let request = Request::TransferOwnership(request);
+        assert_eq!(request.sender(), owner.public().to_peer_id().to_bytes());
+        assert_eq!(request.trace_id(), Some(TraceId(7)));
+        assert_test!(request);
+
+        let response = Response::TransferOwnership(TransferOwnershipResponse {
+            success: false,
+            reason: Some("share not found".to_string()),
+            failure: Some(Failure::NotFound),
+        });
+        assert_test!(response);
+    }
+
+    #[test]
+    fn test_transfer_ownership_signature_binds_every_field() {
+        let owner = Keypair::generate_ed25519();
+        let new_owner = PeerId::random();
+        let request =
+            TransferOwnershipRequest::signed("share_id", PeerId::random(), &owner, new_owner, None)
+                .unwrap();
+        assert_eq!(request.verify(), Ok(new_owner));
+
+        let other_key = TransferOwnershipRequest {
+            key: "other_id".to_string(),
+            ..request.clone()
+        };
+        assert_eq!(other_key.verify(), Err("invalid signature".to_string()));
+        let other_provider = TransferOwnershipRequest {
+            peer: PeerId::random().to_bytes(),
+            ..request.clone()
+        };
+        assert_eq!(other_provider.verify(), Err("invalid signature".to_string()));
+
+        // a thief signing with its own key cannot pass for the owner
+        let thief = Keypair::generate_ed25519();
+        let stolen = TransferOwnershipRequest {
+            public_key: thief.public().encode_protobuf(),
+            ..request.clone()
+        };
+        assert_eq!(
+            stolen.verify(),
+            Err("public key is not the sender's".to_string())
+        );
+        let malformed = TransferOwnershipRequest {
+            new_owner: vec![1, 2, 3],
+            ..request
+        };
+        assert!(malformed.verify().unwrap_err().starts_with("malformed new owner"));
+    }
+
+    #[test]
+    fn test_relay_grant_binds_the_key_provider_and_owner() {
+        let owner = Keypair::generate_ed25519();
+        let sender = owner.public().to_peer_id().to_bytes();
+        let provider = PeerId::random().to_bytes();
+        let grant =
+            RelayGrant::signed("share_id", PeerId::from_bytes(&provider).unwrap(), &owner).unwrap();
+        assert_eq!(grant.verify("share_id", &provider, &sender), Ok(()));
+
+        let invalid = Err("invalid signature".to_string());
+        assert_eq!(grant.verify("other_id", &provider, &sender), invalid);
+        let other_provider = PeerId::random().to_bytes();
+        assert_eq!(grant.verify("share_id", &other_provider, &sender), invalid);
+
+        // a grant signed by anyone but the owner does not pass for the owner's
+        let thief = Keypair::generate_ed25519();
+        let forged = RelayGrant {
+            public_key: thief.public().encode_protobuf(),
+            ..grant.clone()
+        };
+        assert_eq!(
+            forged.verify("share_id", &provider, &sender),
+            Err("public key is not the owner's".to_string())
+        );
+        let malformed = RelayGrant {
+            public_key: vec![1, 2, 3],
+            ..grant
+        };
+        let err = malformed
+            .verify("share_id", &provider, &sender)
+            .unwrap_err();
+        assert!(err.starts_with("malformed public key"));
+    }
+
+    #[test]
+    fn test_serialize_deserialize_request_enum() {
+        let get_share_req = Request::GetShare(GetShareRequest {
+            key: "share_id".to_string(),
+            peer: PeerId::random().into(),
+            sender: PeerId::random().into(),
+            owner: None,
+            trace_id: None,
+        });
+        assert_test!(get_share_req);
+
+        let register_share_req = Request::RegisterShare(RegisterShareRequest {
+            share: (1, vec![1, 2, 3, 4]),
+            #[cfg(feature = "sss16")]
+            field: ShareField::Gf256,
+            key: "unique_id".to_string(),
+            peer: PeerId::random().into(),
+            sender: PeerId::random().into(),
+            threshold: 2,
+            ttl_secs: None,
+            recreate: false,
+            refresh_interval_secs: None,
+            replace: false,
+            relay_grant: None,
+            trace_id: None,
+        });
+        assert_test!(register_share_req);
     }
 
     #[test]
     fn test_serialize_deserialize_response_enum() {
         let get_share_res = Response::GetShare(GetShareResponse {
-            share: (1u8, vec![1, 2, 3, 4]),
+            share: (1, vec![1, 2, 3, 4]),
+            #[cfg(feature = "sss16")]
+            field: ShareField::Gf256,
             success: true,
+            reason: None,
+            failure: None,
         });
         assert_test!(get_share_res);
 
-        let register_share_res = Response::RegisterShare(RegisterShareResponse { success: true });
+        let register_share_res = Response::RegisterShare(RegisterShareResponse {
+            success: true,
+            reason: None,
+            failure: None,
+        });
         assert_test!(register_share_res);
     }
 
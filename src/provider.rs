@@ -1,416 +1,7238 @@
 use crate::event::Event;
 use crate::{
     client::Client,
-    constants::DEFAULT_REFRESH_SECONDS,
-    protocol::{Request, Response},
-    repository::{HashMapShareEntryDao, ShareEntry, ShareEntryDaoTrait, SledShareEntryDao},
-    sss::{generate_refresh_key, refresh_share, Polynomial},
+    config::{ConfigChanges, ConfigError, ShardConfig},
+    constants::{
+        DAO_PAGE_SIZE, DEFAULT_MAX_SHARE_BYTES, DEFAULT_PURGE_SECONDS,
+        DEFAULT_REFRESH_JITTER_PERCENT, DEFAULT_REFRESH_SECONDS, DEFAULT_REPLICATION_MARGIN,
+        DEFAULT_SHUTDOWN_GRACE_SECONDS, DEFAULT_STATUS_SECONDS, DEFAULT_TOMBSTONE_SECONDS,
+        MAX_LIST_KEYS_LIMIT,
+        MAX_RATE_LIMIT_BUCKETS, REFRESH_KEY_DELAY_MILLIS, REFRESH_READ_ATTEMPTS,
+        REFRESH_RETRY_SECONDS, REFRESH_TAKEOVER_INTERVALS, REFRESH_TICK_SECONDS,
+    },
+    protocol::{
+        AccessRequest, DeleteShareStatus, Failure, FieldShare, GossipMessage, ListKeysResponse,
+        MetricsSnapshot, ProviderStatus, QuotaUsage, RefreshKey, RegisterShareRequest, RelayGrant,
+        Request, Response, ShareMetadata, StatShareStatus, TraceId, TransferOwnershipRequest,
+        UnderReplicatedAlert,
+    },
+    repository::{
+        owner_key, split_owner_key, AuditEvent, AuditLog, AuditOperation, AuditOutcome, DaoQuotas,
+        EncryptionKey, FlushPolicy, HashMapShareEntryDao, IsolatedShareEntryDao, RepoError,
+        ShareEntry, ShareEntryDaoTrait, SledShareEntryDao, Tombstone,
+    },
+    runtime::{self, spawn, Interval, JoinHandle},
+    sss::{self, generate_refresh_key, refresh_share, validate_refresh_key},
+    Error,
 };
+#[cfg(feature = "sss16")]
+use crate::{repository::ShareField, sss16};
 use futures::future::FutureExt;
 use futures::prelude::*;
 use libp2p::request_response::ResponseChannel;
 use libp2p::PeerId;
-use std::time::Duration;
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
-use tokio::{
-    spawn,
-    time::{self, Interval},
-};
-use tracing::{debug, error};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+// tokio's channels and `select!` do not need its runtime, and run on async-std as well.
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
-/// Checks if the given `PeerId` is the owner of the `ShareEntry`.
+mod node;
+
+pub use node::{ShardNode, ShardNodeBuilder};
+
+/// The reason given to a requester when no share can be returned for its key.
+const NOT_FOUND: &str = "share not found";
+
+/// The reason given to a requester asking for a share registered by another peer.
+const NOT_OWNER: &str = "share not owned by sender";
+
+/// The reason given to a requester whose request carries a sender that is not a valid peer id.
+const INVALID_SENDER: &str = "malformed sender";
+
+/// The reason given to a requester claiming to be another peer than the one it connected as.
+const SPOOFED_SENDER: &str = "sender does not match the authenticated peer";
+
+/// Reason given when a share request names an owner that is not a valid peer id.
+const INVALID_OWNER: &str = "malformed owner";
+
+/// The number of characters of a peer id kept when it is reported in a `Failure::NotOwner`.
+const PEER_PREFIX_LEN: usize = 16;
+
+/// Reason given for requests that arrive while the provider is shutting down.
+const SHUTTING_DOWN: &str = "provider is shutting down";
+
+/// The reason recorded for requests turned down by the rate limiter.
+const RATE_LIMITED: &str = "rate limited";
+
+/// The reason recorded in the tombstones of shares deleted by `purge_owner`.
+const OWNER_PURGED: &str = "owner purged";
+
+/// The reason recorded in the tombstones of shares their owner asked to delete.
+const OWNER_DELETED: &str = "deleted by owner";
+
+/// The number of times a refresh is re-applied when the share changes underneath it.
+const MAX_REFRESH_ATTEMPTS: usize = 8;
+
+/// A thread-safe, shared handle to the share DAO used by the provider handlers.
+pub type SharedDao = Arc<Mutex<Box<dyn ShareEntryDaoTrait>>>;
+
+/// A shared handle to the audit log the provider records its operations in, if auditing is
+/// enabled.
+pub type SharedAudit = Option<Arc<AuditLog>>;
+
+/// Returns the current unix timestamp in seconds.
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Retrieves a `ShareEntry` from the DAO, treating expired entries as absent.
 ///
 /// # Arguments
-/// * `entry` - A reference to the `ShareEntry` to check.
-/// * `sender_id` - The `PeerId` to validate against the owner in the `ShareEntry`.
+/// * `key` - The key identifying the `ShareEntry`.
+/// * `dao` - A shared reference to the DAO trait object.
 ///
 /// # Returns
-/// Returns `true` if `sender_id` matches the owner in the `ShareEntry`, otherwise `false`.
-pub fn check_share_owner(entry: &ShareEntry, sender_id: &PeerId) -> bool {
-    PeerId::from_bytes(&entry.sender).unwrap() == *sender_id
+/// Returns a `Result` containing the entry, or `None` if it does not exist or has expired.
+pub fn get_live_entry(
+    key: &str,
+    dao: &SharedDao,
+) -> Result<Option<ShareEntry>, RepoError> {
+    let entry = dao.lock().unwrap().get(key)?;
+    Ok(entry.filter(|entry| !entry.is_expired(now_unix())))
 }
 
-/// Executes the share refresh logic asynchronously.
-///
-/// This function retrieves the specified `ShareEntry` from the database, refreshes its share,
-/// and then updates the entry in the database. If a response channel is provided, it sends a
-/// response back to the network client.
+/// Retrieves the share `owner` registered under `key`, treating expired entries as absent.
 ///
 /// # Arguments
-/// * `key` - The key identifying the `ShareEntry` to refresh.
-/// * `sender` - The `PeerId` of the sender requesting the refresh.
-/// * `refresh_key` - A slice of `Polynomial` used for refreshing the share.
-/// * `channel` - An optional `ResponseChannel<Response>` for sending responses.
-/// * `dao` - A shared and mutable reference to the data access object (DAO) trait object.
-/// * `network_client` - A mutable reference to the network client for responding to requests.
+/// * `owner` - The `PeerId` of the share owner, whose namespace the key is looked up in.
+/// * `key` - The key chosen by the owner.
+/// * `dao` - A shared reference to the DAO trait object.
 ///
 /// # Returns
-/// Returns a `Result<(), Box<dyn std::error::Error>>`, indicating success or failure.
-pub async fn execute_refresh_share(
+/// Returns a `Result` containing the entry, or `None` if the owner has no live share under `key`.
+pub fn get_owned_live_entry(
+    owner: &PeerId,
     key: &str,
-    sender: &PeerId,
-    refresh_key: &[Polynomial],
-    channel: Option<ResponseChannel<Response>>,
-    dao: &Arc<Mutex<Box<dyn ShareEntryDaoTrait>>>,
-    network_client: &mut Client,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut share_entry: ShareEntry = dao
-        .lock()
-        .unwrap()
-        .get(key)
-        .unwrap()
-        .ok_or("Share not found")?;
-
-    //let sender = PeerId::from_bytes(&sender).unwrap();
-    debug!("-- Sender: {:#?}.", sender);
+    dao: &SharedDao,
+) -> Result<Option<ShareEntry>, RepoError> {
+    get_live_entry(&owner_key(&owner.to_bytes(), key), dao)
+}
 
-    // check that the peer requesting the share is the owner
-    // only if the channel is not None
-    if channel.is_some() {
-        if !check_share_owner(&share_entry, sender) {
-            println!(
-                "⚠️ Share not owned by sender {:?}, actual owner: {:?}",
-                sender,
-                PeerId::from_bytes(&share_entry.sender).unwrap()
-            );
+/// Records an operation in the audit log, if auditing is enabled. A failure to record is logged
+/// rather than failing the operation.
+///
+/// # Arguments
+/// * `audit` - The shared audit log.
+/// * `operation` - The operation performed.
+/// * `key` - The key the operation applied to.
+/// * `peer` - The requesting peer, or `None` for operations the provider started itself.
+/// * `outcome` - How the operation ended.
+pub fn record_audit(
+    audit: &SharedAudit,
+    operation: AuditOperation,
+    key: &str,
+    peer: Option<&PeerId>,
+    outcome: AuditOutcome,
+) {
+    let Some(audit) = audit else {
+        return;
+    };
+    let event = AuditEvent {
+        timestamp: now_unix(),
+        operation,
+        key: key.to_string(),
+        peer: peer.map(|peer| peer.to_bytes()),
+        outcome,
+    };
+    if let Err(e) = audit.record(event) {
+        error!("Failed to record {operation} of {key:?} in the audit log: {e}");
+    }
+}
 
-            network_client
-                .respond_refresh_shares(false, channel.unwrap())
-                .await;
+/// What a provider did, published to the application embedding it (see `publish_event`).
+///
+/// # Variants
+/// * `Registered` - A share was registered under `key` by its `owner`.
+/// * `Served` - The share under `key` was sent to its owner or a reader.
+/// * `Refreshed` - The share under `key` was refreshed, and is now at `epoch`.
+/// * `Rejected` - A request for `key` was not served, for `reason`. Key listings are rejected
+///   under their cursor.
+/// * `RefreshInitiated` - The provider coordinated the refresh of the share under `key`, and
+///   sent it to the other providers `peers`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderEvent {
+    Registered { key: String, owner: PeerId },
+    Served { key: String },
+    Refreshed { key: String, epoch: u64 },
+    Rejected { key: String, reason: String },
+    RefreshInitiated { key: String, peers: Vec<PeerId> },
+}
 
-            return Ok(());
+impl std::fmt::Display for ProviderEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderEvent::Registered { key, owner } => {
+                write!(f, "registered {} for {}", key, owner)
+            }
+            ProviderEvent::Served { key } => write!(f, "served {}", key),
+            ProviderEvent::Refreshed { key, epoch } => {
+                write!(f, "refreshed {} to epoch {}", key, epoch)
+            }
+            ProviderEvent::Rejected { key, reason } => write!(f, "rejected {}: {}", key, reason),
+            ProviderEvent::RefreshInitiated { key, peers } => {
+                write!(f, "initiated the refresh of {} on {} peers", key, peers.len())
+            }
         }
     }
+}
 
-    debug!("-- share before refresh: {:?}", share_entry.share);
-    let _ = refresh_share(
-        (&mut share_entry.share.0, &mut share_entry.share.1),
-        refresh_key,
-    );
-    dao.lock().unwrap().insert(key, &share_entry)?;
-    debug!("-- share after refresh:  {:?}", share_entry.share);
-
-    let test = dao
-        .lock()
-        .unwrap()
-        .get(&key)
-        .unwrap()
-        .ok_or("Share not found")?;
-    debug!("-- test share from dao: {:?}", test.share);
-
-    if channel.is_some() {
-        network_client
-            .respond_refresh_shares(true, channel.unwrap())
-            .await;
+/// The channel the provider publishes its events to, if the application embedding it
+/// subscribed to them.
+pub type ProviderEvents = Option<mpsc::Sender<ProviderEvent>>;
+
+/// Publishes an event of the provider.
+///
+/// The event is dropped, and counted in `events_dropped`, when the subscriber has fallen behind
+/// and the channel is full, so that a slow subscriber never holds up the request handlers or the
+/// refresh loop.
+///
+/// # Arguments
+/// * `events` - The channel to publish to, if any.
+/// * `metrics` - The metrics to count a dropped event in.
+/// * `event` - The event to publish.
+pub fn publish_event(events: &ProviderEvents, metrics: &ProviderMetrics, event: ProviderEvent) {
+    let Some(events) = events else {
+        return;
+    };
+    match events.try_send(event) {
+        Ok(()) => {}
+        Err(mpsc::error::TrySendError::Full(event)) => {
+            metrics.record_event_dropped();
+            debug!("Dropped a provider event, the subscriber is behind: {event}");
+        }
+        // the subscriber stopped listening
+        Err(mpsc::error::TrySendError::Closed(_)) => {}
     }
-    println!("🔄 Refreshed share for key: {:?}", key);
-    Ok(())
 }
 
-/// Executes the share registration logic asynchronously.
+/// A shared handle to the metrics of the provider.
+pub type SharedMetrics = Arc<ProviderMetrics>;
+
+/// The counters and gauges of a provider, updated by the request handlers and the refresh loop.
 ///
-/// This function checks for the existence of a share in the database and registers a new
-/// share if it doesn't exist or if the sender is the owner. It then sends a response back
-/// to the network client.
+/// The metrics are published in the provider's health status (see `snapshot`). The requests
+/// received by operation, the number of shares stored and whether the provider is ready are only
+/// scraped locally, from the `/metrics` endpoint.
+#[derive(Debug, Default)]
+pub struct ProviderMetrics {
+    registrations: AtomicU64,
+    gets_served: AtomicU64,
+    refreshes_applied: AtomicU64,
+    refreshes_initiated: AtomicU64,
+    invalid_requests: AtomicU64,
+    rate_limited_requests: AtomicU64,
+    failures: Mutex<BTreeMap<&'static str, u64>>,
+    oldest_refresh_age_secs: Mutex<Option<u64>>,
+    inbound_requests: Mutex<BTreeMap<String, u64>>,
+    shares_stored: AtomicU64,
+    ready: AtomicBool,
+    events_dropped: AtomicU64,
+}
+
+impl ProviderMetrics {
+    /// Counts a share registered.
+    pub fn record_registration(&self) {
+        self.registrations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a share sent to its owner or a reader.
+    pub fn record_get_served(&self) {
+        self.gets_served.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a refresh applied to a stored share.
+    pub fn record_refresh_applied(&self) {
+        self.refreshes_applied.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a refresh the provider initiated as the coordinator of a share.
+    pub fn record_refresh_initiated(&self) {
+        self.refreshes_initiated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a request rejected as malformed.
+    pub fn record_invalid_request(&self) {
+        self.invalid_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a request turned down by the rate limiter.
+    pub fn record_rate_limited(&self) {
+        self.rate_limited_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a failed request.
+    ///
+    /// # Arguments
+    /// * `class` - The class of the failure (see `Failure::class`).
+    pub fn record_failure(&self, class: &'static str) {
+        *self.failures.lock().unwrap().entry(class).or_default() += 1;
+    }
+
+    /// Sets the age of the least recently refreshed share, as found by a refresh pass.
+    ///
+    /// # Arguments
+    /// * `age_secs` - The number of seconds since the share was refreshed, or `None` if the
+    ///   provider holds no shares.
+    pub fn set_oldest_refresh_age(&self, age_secs: Option<u64>) {
+        *self.oldest_refresh_age_secs.lock().unwrap() = age_secs;
+    }
+
+    /// Counts a request received, whether or not it is served.
+    ///
+    /// # Arguments
+    /// * `operation` - The operation the request asks for.
+    pub fn record_inbound_request(&self, operation: AuditOperation) {
+        *self.inbound_requests.lock().unwrap().entry(operation.to_string()).or_default() += 1;
+    }
+
+    /// Returns the number of requests received by operation.
+    pub fn inbound_requests(&self) -> BTreeMap<String, u64> {
+        self.inbound_requests.lock().unwrap().clone()
+    }
+
+    /// Sets the number of shares stored, as last read from the database.
+    pub fn set_shares_stored(&self, shares: u64) {
+        self.shares_stored.store(shares, Ordering::Relaxed);
+    }
+
+    /// Returns the number of shares stored, as of the last health status.
+    pub fn shares_stored(&self) -> u64 {
+        self.shares_stored.load(Ordering::Relaxed)
+    }
+
+    /// Marks the provider as ready to serve requests, with its database open, or as stopping.
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::Relaxed);
+    }
+
+    /// Returns whether the provider is serving requests.
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    /// Counts a provider event dropped because its subscriber was behind.
+    pub fn record_event_dropped(&self) {
+        self.events_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of provider events dropped because their subscriber was behind.
+    pub fn events_dropped(&self) -> u64 {
+        self.events_dropped.load(Ordering::Relaxed)
+    }
+
+    /// Returns the current value of every counter and gauge.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            registrations: self.registrations.load(Ordering::Relaxed),
+            gets_served: self.gets_served.load(Ordering::Relaxed),
+            refreshes_applied: self.refreshes_applied.load(Ordering::Relaxed),
+            refreshes_initiated: self.refreshes_initiated.load(Ordering::Relaxed),
+            invalid_requests: self.invalid_requests.load(Ordering::Relaxed),
+            rate_limited_requests: self.rate_limited_requests.load(Ordering::Relaxed),
+            failures: self
+                .failures
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(class, count)| (class.to_string(), *count))
+                .collect(),
+            oldest_refresh_age_secs: *self.oldest_refresh_age_secs.lock().unwrap(),
+        }
+    }
+}
+
+/// Describes a request as the operation and key it is recorded under in the audit log.
 ///
 /// # Arguments
-/// * `key` - The key identifying the share to register.
-/// * `sender` - The `PeerId` of the sender requesting the registration.
-/// * `share` - A tuple containing the share identifier and data.
-/// * `threshold` - The threshold value for the share.
-/// * `channel` - The `ResponseChannel<Response>` for sending responses.
-/// * `dao` - A shared and mutable reference to the DAO trait object.
-/// * `network_client` - A mutable reference to the network client.
+/// * `request` - The request received.
 ///
 /// # Returns
-/// Returns a `Result<(), Box<dyn std::error::Error>>`, indicating success or failure.
-pub async fn execute_register_share(
-    key: &str,
-    sender: &PeerId,
-    share: (u8, Vec<u8>),
-    threshold: u64,
-    channel: ResponseChannel<Response>,
-    dao: &Arc<Mutex<Box<dyn ShareEntryDaoTrait>>>,
-    network_client: &mut Client,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // check if the share already exists and if so, check that the peer requesting the share is the owner
-    if let Some(share_entry) = dao.lock().unwrap().get(key)? {
-        debug!("Retrieved Entry: {:?}", share_entry);
-        debug!("-- Sender: {:#?}.", sender);
-
-        // check that the peer requesting the share is the owner
-        if !check_share_owner(&share_entry, &sender) {
-            println!(
-                "⚠️ Share exists, not owned by sender {:?}, actual owner: {:?}",
-                sender, share_entry.sender
-            );
-            network_client.respond_register_share(false, channel).await;
-            return Ok(());
-        }
+/// Returns the audited operation and key. Key listings are recorded under their cursor.
+fn audit_summary(request: &Request) -> (AuditOperation, String) {
+    match request {
+        Request::RegisterShare(req) => (AuditOperation::Register, req.key.clone()),
+        Request::GetShare(req) => (AuditOperation::Get, req.key.clone()),
+        Request::RefreshShare(req) => (AuditOperation::Refresh, req.key.clone()),
+        Request::DeleteShare(req) => (AuditOperation::Delete, req.key.clone()),
+        Request::ListKeys(req) => (AuditOperation::List, req.cursor.clone().unwrap_or_default()),
+        Request::StatShare(req) => (AuditOperation::Stat, req.key.clone()),
+        Request::GrantAccess(req) => (AuditOperation::Grant, req.key.clone()),
+        Request::RevokeAccess(req) => (AuditOperation::Revoke, req.key.clone()),
+        Request::TransferOwnership(req) => (AuditOperation::Transfer, req.key.clone()),
     }
-
-    network_client.start_providing(key.to_string()).await;
-    debug!("-- Sender: {:#?}.", sender);
-    dao.lock().unwrap().insert(
-        key,
-        &ShareEntry {
-            share,
-            sender: sender.to_bytes(),
-            threshold,
-        },
-    )?;
-    network_client.respond_register_share(true, channel).await;
-    println!("🚀 Registered share for key: {:?}.", key);
-
-    Ok(())
 }
 
-/// Executes the logic to retrieve and send a share asynchronously.
+/// Handles an inbound request and records it in the audit log.
 ///
-/// This function retrieves a `ShareEntry` from the database and sends it back to the requester
-/// via the network client, if the requester is the owner of the share.
+/// A request from a peer over its rate limit for the operation is answered with a `RateLimited`
+/// response and counted in `rate_limited_requests`, before anything else is checked. Only the
+/// local node is exempt; refreshes relayed by another provider count against the relaying peer.
+/// A request whose sender, or named share owner, is not a valid peer id is answered with an
+/// `InvalidRequest` response and counted in `invalid_requests`, without reaching a handler. So is a request whose sender is
+/// not the authenticated peer it arrived from, except for refreshes relayed by another provider
+/// of the share, which the provider coordinating the refresh sends on behalf of the share owner
+/// with the `RelayGrant` the owner signed for it (see `relayed_by_grant`).
 ///
 /// # Arguments
-/// * `key` - The key identifying the share to retrieve.
-/// * `sender` - The `PeerId` of the sender requesting the share.
-/// * `channel` - The `ResponseChannel<Response>` for sending the share.
-/// * `dao` - A shared and mutable reference to the DAO trait object.
+/// * `request` - The request received.
+/// * `peer` - The authenticated `PeerId` of the connection the request arrived on.
+/// * `channel` - The `ResponseChannel<Response>` to respond on.
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `audit` - The shared audit log.
+/// * `metrics` - The metrics of the provider.
+/// * `events` - The channel to publish the outcome of the request to (see `request_event`).
+/// * `max_share_bytes` - The largest share accepted for registration.
+/// * `limiter` - The rate limiter throttling each peer.
 /// * `network_client` - A mutable reference to the network client.
 ///
 /// # Returns
-/// Returns a `Result<(), Box<dyn std::error::Error>>`, indicating success or failure.
-pub async fn execute_get_share(
-    key: &str,
-    sender: &PeerId,
+/// Returns a `Result<(), Error>`, the error of a failed handler.
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_request(
+    request: Request,
+    peer: PeerId,
     channel: ResponseChannel<Response>,
-    dao: &Arc<Mutex<Box<dyn ShareEntryDaoTrait>>>,
+    dao: &SharedDao,
+    audit: &SharedAudit,
+    metrics: &ProviderMetrics,
+    events: &ProviderEvents,
+    max_share_bytes: usize,
+    limiter: &mut RateLimiter,
     network_client: &mut Client,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let share_entry = dao
-        .lock()
-        .unwrap()
-        .get(&key)
-        .unwrap()
-        .ok_or("Share not found")?;
-
-    debug!("-- Sender: {:#?}.", sender);
-
-    // check that the peer requesting the share is the owner
-    if !check_share_owner(&share_entry, &sender) {
-        println!(
-            "⚠️ Share not owned by sender {:?}, actual owner: {:?}",
-            sender, share_entry.sender
+) -> Result<(), Error> {
+    let (operation, key) = audit_summary(&request);
+    metrics.record_inbound_request(operation);
+    if let Err(retry_after) = limiter.check(&peer, operation, Instant::now()) {
+        metrics.record_rate_limited();
+        debug!(
+            %operation, key, %peer, outcome = "rate_limited",
+            "Rate limited request."
         );
         network_client
-            .respond_share((0u8, vec![]), false, channel)
+            .respond_rate_limited(retry_after, channel)
             .await;
+        let outcome = AuditOutcome::Refused(RATE_LIMITED.to_string());
+        if let Some(event) = request_event(operation, &key, &peer, &outcome, dao) {
+            publish_event(events, metrics, event);
+        }
+        record_audit(audit, operation, &key, Some(&peer), outcome);
         return Ok(());
     }
-    network_client
-        .respond_share(share_entry.share.clone(), true, channel)
-        .await;
-    println!("💡 Sent share for key: {:?}.", key);
-
-    Ok(())
+    let sender = match PeerId::from_bytes(request.sender()) {
+        Ok(sender) if sender == peer => Ok(sender),
+        Ok(sender) => match relayed_by_grant(&request, &peer, &key) {
+            Ok(()) => Ok(sender),
+            Err(reason) => Err(format!(
+                "{}: {} claimed to be {} ({})",
+                SPOOFED_SENDER, peer, sender, reason
+            )),
+        },
+        Err(e) => Err(format!("{}: {}", INVALID_SENDER, e)),
+    };
+    let sender = match sender {
+        Ok(sender) => sender,
+        Err(reason) => {
+            metrics.record_invalid_request();
+            warn!(
+                %operation, key, %peer, outcome = "refused", reason,
+                "Rejected invalid request."
+            );
+            network_client
+                .respond_invalid_request(reason.clone(), channel)
+                .await;
+            let outcome = AuditOutcome::Refused(reason);
+            if let Some(event) = request_event(operation, &key, &peer, &outcome, dao) {
+                publish_event(events, metrics, event);
+            }
+            record_audit(audit, operation, &key, Some(&peer), outcome);
+            return Ok(());
+        }
+    };
+    let result = match request {
+        Request::RegisterShare(req) => {
+            execute_register_share(
+                &sender,
+                req,
+                channel,
+                dao,
+                metrics,
+                max_share_bytes,
+                network_client,
+            )
+            .await
+        }
+        Request::GetShare(req) => match req.owner.as_deref().map(PeerId::from_bytes).transpose() {
+            Ok(owner) => {
+                let owner = owner.unwrap_or(sender);
+                execute_get_share(
+                    &req.key,
+                    &sender,
+                    &owner,
+                    channel,
+                    dao,
+                    metrics,
+                    network_client,
+                )
+                .await
+            }
+            Err(e) => {
+                metrics.record_invalid_request();
+                let reason = format!("{}: {}", INVALID_OWNER, e);
+                warn!(
+                    operation = "get", key, peer = %sender, outcome = "refused", reason,
+                    "Rejected invalid request."
+                );
+                network_client
+                    .respond_invalid_request(reason.clone(), channel)
+                    .await;
+                Ok(AuditOutcome::Refused(reason))
+            }
+        },
+        Request::RefreshShare(req) => {
+            execute_refresh_share(
+                &req.key,
+                &sender,
+                &req.refresh_key(),
+                req.epoch,
+                Some(channel),
+                dao,
+                metrics,
+                network_client,
+            )
+            .await
+        }
+        Request::DeleteShare(req) => {
+            execute_delete_share(&req.key, &sender, channel, dao, metrics, network_client).await
+        }
+        Request::ListKeys(req) => {
+            let cursor = req.cursor.unwrap_or_default();
+            let limit = req.limit;
+            execute_list_keys(
+                &sender,
+                &cursor,
+                limit,
+                channel,
+                dao,
+                metrics,
+                network_client,
+            )
+            .await
+        }
+        Request::StatShare(req) => {
+            execute_stat_share(&req.key, &sender, channel, dao, metrics, network_client).await
+        }
+        Request::GrantAccess(req) => {
+            let grant = AccessChange::Grant;
+            execute_update_access(&req, &sender, grant, channel, dao, metrics, network_client).await
+        }
+        Request::RevokeAccess(req) => {
+            let revoke = AccessChange::Revoke;
+            execute_update_access(&req, &sender, revoke, channel, dao, metrics, network_client)
+                .await
+        }
+        Request::TransferOwnership(req) => {
+            execute_transfer_ownership(&req, &sender, channel, dao, metrics, network_client).await
+        }
+    };
+    let outcome = match &result {
+        Ok(outcome) => outcome.clone(),
+        Err(e) => AuditOutcome::Failed(e.to_string()),
+    };
+    if let Some(event) = request_event(operation, &key, &sender, &outcome, dao) {
+        publish_event(events, metrics, event);
+    }
+    record_audit(audit, operation, &key, Some(&sender), outcome);
+    result.map(|_| ())
 }
 
-/// Creates and returns a DAO instance based on the specified database path.
-///
-/// If a path is provided, a Sled database DAO is created; otherwise, an in-memory HashMap
-/// DAO is used. This allows flexibility in choosing the underlying storage mechanism.
+/// Describes the outcome of a request as the event the provider publishes for it.
 ///
 /// # Arguments
-/// * `db_path` - An optional string slice representing the path to the database.
+/// * `operation` - The operation the request asked for.
+/// * `key` - The key the request named.
+/// * `sender` - The owner of the share, on whose behalf the request was made.
+/// * `outcome` - How the request ended.
+/// * `dao` - A shared reference to the DAO trait object, to read the epoch a share was
+///   refreshed to.
 ///
 /// # Returns
-/// Returns a `Result<Arc<Mutex<Box<dyn ShareEntryDaoTrait>>>>`, encapsulating the DAO in a
-/// thread-safe, reference-counted pointer, or an error if the database cannot be initialized.
-pub fn dao(
-    db_path: Option<String>,
-) -> Result<Arc<Mutex<Box<dyn ShareEntryDaoTrait>>>, Box<dyn std::error::Error>> {
-    // check if the db_path is set, if so use sled, otherwise use HashMap
-    let dao: Arc<Mutex<Box<dyn ShareEntryDaoTrait>>> = if db_path.is_some() {
-        debug!("Using Sled DB");
-        Arc::new(Mutex::new(Box::new(SledShareEntryDao::new(
-            &db_path.unwrap(),
-        )?)))
-    } else {
-        debug!("Using HashMap DB");
-        Arc::new(Mutex::new(Box::new(HashMapShareEntryDao {
-            map: Mutex::new(HashMap::new()),
-        })))
-    };
-    Ok(dao)
+/// Returns the event of a request that was not served, or that registered, served or refreshed
+/// a share, and `None` for any other request served.
+fn request_event(
+    operation: AuditOperation,
+    key: &str,
+    sender: &PeerId,
+    outcome: &AuditOutcome,
+    dao: &SharedDao,
+) -> Option<ProviderEvent> {
+    let key = key.to_string();
+    match outcome {
+        AuditOutcome::Refused(reason) | AuditOutcome::Failed(reason) => {
+            let reason = reason.clone();
+            Some(ProviderEvent::Rejected { key, reason })
+        }
+        AuditOutcome::Success => match operation {
+            AuditOperation::Register => Some(ProviderEvent::Registered { key, owner: *sender }),
+            AuditOperation::Get => Some(ProviderEvent::Served { key }),
+            AuditOperation::Refresh => {
+                let stored_key = owner_key(&sender.to_bytes(), &key);
+                let entry = get_live_entry(&stored_key, dao).ok().flatten()?;
+                Some(ProviderEvent::Refreshed { key, epoch: entry.epoch })
+            }
+            _ => None,
+        },
+    }
 }
 
-/// Runs the main event loop asynchronously.
+/// Computes the DHT record of a share from the key it is stored under.
+///
+/// # Arguments
+/// * `stored_key` - The owner-scoped key the share is stored under.
 ///
-/// This function initializes the DAO and starts a periodic refresh task. It also listens for
-/// incoming network events and handles them appropriately.
+/// # Returns
+/// Returns the record to provide the share under, or `None` if the key is not owner-scoped.
+fn provider_record(stored_key: &str) -> Option<String> {
+    let (owner, key) = split_owner_key(stored_key)?;
+    let owner = PeerId::from_bytes(&owner).ok()?;
+    Some(Client::provider_key(&owner, key))
+}
+
+/// Checks if the given `PeerId` is the owner of the `ShareEntry`.
 ///
 /// # Arguments
-/// * `db_path` - An optional string slice for the database path.
-/// * `refresh` - An optional duration in seconds for the refresh interval.
-/// * `local_peer_id` - The `PeerId` of the local node.
-/// * `network_client` - A mutable reference to the network client.
-/// * `network_events` - A stream of network events to listen to.
-pub async fn run_loop(
-    db_path: Option<String>,
-    refresh: Option<u64>,
-    local_peer_id: PeerId,
-    network_client: &mut Client,
-    mut network_events: impl Stream<Item = Event> + Unpin,
-) {
-    // check if the db_path is set, if so use sled, otherwise use HashMap
-    let dao: Arc<Mutex<Box<dyn ShareEntryDaoTrait>>> = dao(db_path).unwrap();
+/// * `entry` - A reference to the `ShareEntry` to check.
+/// * `sender_id` - The `PeerId` to validate against the owner in the `ShareEntry`.
+///
+/// # Returns
+/// Returns `true` if `sender_id` matches the owner in the `ShareEntry`, otherwise `false`. An
+/// entry whose stored owner is not a valid peer id is logged as corrupt and owned by nobody.
+pub fn check_share_owner(entry: &ShareEntry, sender_id: &PeerId) -> bool {
+    match PeerId::from_bytes(&entry.sender) {
+        Ok(owner) => owner == *sender_id,
+        Err(e) => {
+            error!(
+                owner = hex::encode(&entry.sender), error = %e,
+                "Share has a corrupt owner."
+            );
+            false
+        }
+    }
+}
 
-    // check if refresh is set, if not use a default of 30 minutes
-    let refresh = refresh.unwrap_or(DEFAULT_REFRESH_SECONDS);
-    debug!("Using refresh_seconds: {}", refresh);
+/// Shortens the peer id bytes of a share owner to a prefix of its base58 form, or of its hex form
+/// if the bytes are not a valid peer id, for reporting in a `Failure::NotOwner`.
+///
+/// # Arguments
+/// * `peer` - The peer id bytes to shorten.
+///
+/// # Returns
+/// Returns the first `PEER_PREFIX_LEN` characters of the peer id.
+fn peer_prefix(peer: &[u8]) -> String {
+    let id = match PeerId::from_bytes(peer) {
+        Ok(peer) => peer.to_base58(),
+        Err(_) => hex::encode(peer),
+    };
+    id.chars().take(PEER_PREFIX_LEN).collect()
+}
 
-    // spawn a refresh task to run every refresh_seconds seconds
-    let dao_clone = Arc::clone(&dao);
-    let mut network_client_clone = network_client.clone();
-    spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(refresh));
-        refresh_loop(
-            &mut interval,
-            dao_clone,
-            &mut network_client_clone,
-            local_peer_id,
-        )
-        .await;
-    });
+/// Builds the failure reported when `share_entry` is not owned by the peer it was looked up for.
+///
+/// # Arguments
+/// * `expected` - The `PeerId` the share was looked up for.
+/// * `share_entry` - The stored share.
+///
+/// # Returns
+/// Returns a `Failure::NotOwner` naming both peers by their prefixes.
+fn not_owner(expected: &PeerId, share_entry: &ShareEntry) -> Failure {
+    Failure::NotOwner {
+        expected: peer_prefix(&expected.to_bytes()),
+        actual: peer_prefix(&share_entry.sender),
+    }
+}
 
-    loop {
-        match network_events.next().await {
-            // Reply with the content of the file on incoming requests.
-            Some(Event::InboundRequest { request, channel }) => match request {
-                Request::RegisterShare(req) => {
-                    let sender = PeerId::from_bytes(&req.sender).unwrap();
-                    let _ = execute_register_share(
-                        &req.key,
-                        &sender,
-                        req.share,
-                        req.threshold,
-                        channel,
-                        &dao,
-                        network_client,
-                    )
-                    .await;
-                }
-                Request::GetShare(req) => {
-                    let sender = PeerId::from_bytes(&req.sender).unwrap();
-                    let _ =
-                        execute_get_share(&req.key, &sender, channel, &dao, network_client).await;
-                }
-                Request::RefreshShare(req) => {
-                    let sender = PeerId::from_bytes(&req.sender).unwrap();
-                    let _ = execute_refresh_share(
-                        &req.key,
-                        &sender,
-                        &req.refresh_key,
-                        Some(channel),
-                        &dao,
-                        network_client,
-                    )
-                    .await;
-                }
-            },
-            e => debug!("unhandled client event: {e:?}"),
-        }
+/// Maps an error of the share DAO to the failure reported to the requester.
+///
+/// Refusals of the repository keep their meaning: an exceeded quota carries its usage, and a
+/// refresh key that does not fit the share is an invalid request. Any other error, including a
+/// share failing its integrity check, is a storage error.
+///
+/// # Arguments
+/// * `e` - The error returned by the DAO.
+///
+/// # Returns
+/// Returns the `Failure` to respond with.
+fn storage_failure(e: RepoError) -> Failure {
+    match e {
+        RepoError::QuotaExceeded { limit, max, used } => Failure::QuotaExceeded(QuotaUsage {
+            limit: limit.to_string(),
+            max,
+            used,
+        }),
+        RepoError::RecentlyDeleted(_) => Failure::RecentlyDeleted,
+        RepoError::StaleEpoch { stored, requested } => Failure::StaleEpoch { stored, requested },
+        RepoError::InvalidRefreshKey(_) => Failure::InvalidRequest(e.to_string()),
+        RepoError::ConflictingShare(reason) => Failure::Conflict(reason),
+        RepoError::CorruptEntry { .. } => Failure::Corrupt,
+        _ => Failure::StorageError(e.to_string()),
     }
 }
 
-/// Periodically refreshes shares in a separate asynchronous task.
+/// Tells why no live share is stored under `stored_key`: `Corrupt` if the integrity scan
+/// quarantined it, so that the owner learns to register it again, and `NotFound` otherwise.
 ///
-/// This function iterates over all shares in the database at regular intervals and refreshes
-/// them. It also communicates with other peers in the network to synchronize the refreshed shares.
+/// # Arguments
+/// * `stored_key` - The key the share is stored under, in its owner's namespace.
+/// * `dao` - A shared reference to the DAO trait object.
+///
+/// # Returns
+/// Returns the `Failure` to respond with.
+fn missing_share(stored_key: &str, dao: &SharedDao) -> Failure {
+    match dao.lock().unwrap().is_quarantined(stored_key) {
+        Ok(true) => Failure::Corrupt,
+        Ok(false) => Failure::NotFound,
+        Err(e) => storage_failure(e),
+    }
+}
+
+/// Maps the result a handler responded with to the outcome it audits, counting a failure in the
+/// metrics by its class.
 ///
 /// # Arguments
-/// * `interval` - A mutable reference to a time interval generator.
-/// * `dao_clone` - A cloned reference to the DAO, wrapped in an Arc and Mutex.
+/// * `result` - What the handler responded with.
+/// * `metrics` - The metrics of the provider.
+///
+/// # Returns
+/// Returns `Success` for a successful response and `Refused` with the reason for a refusal. A
+/// storage error fails the handler, so that a handler never reports success for a failed
+/// response.
+fn handler_outcome<T>(
+    result: &Result<T, Failure>,
+    metrics: &ProviderMetrics,
+) -> Result<AuditOutcome, Error> {
+    if let Err(failure) = result {
+        metrics.record_failure(failure.class());
+    }
+    match result {
+        Ok(_) => Ok(AuditOutcome::Success),
+        Err(failure @ Failure::StorageError(_)) => Err(Error::Failure(failure.clone())),
+        Err(failure) => Ok(AuditOutcome::Refused(failure.to_string())),
+    }
+}
+
+/// Checks that a request whose sender is not the peer it arrived from is a refresh relayed by
+/// a provider the owner granted it to, so that no other peer can refresh one copy of a share out
+/// of step with the others. The grant is verified locally against the relaying peer and the
+/// owner the refresh names.
+///
+/// # Arguments
+/// * `request` - The request received.
+/// * `peer` - The authenticated `PeerId` the request arrived from.
+/// * `key` - The key the owner registered the share under.
+///
+/// # Returns
+/// Returns why the request cannot be taken as relayed on behalf of its sender, if it cannot.
+fn relayed_by_grant(request: &Request, peer: &PeerId, key: &str) -> Result<(), String> {
+    let Request::RefreshShare(req) = request else {
+        return Err("only refreshes are relayed".to_string());
+    };
+    let grant = req
+        .relay_grant
+        .as_ref()
+        .ok_or_else(|| "no relay grant".to_string())?;
+    grant.verify(key, &peer.to_bytes(), &req.sender)
+}
+
+/// Executes the share refresh logic asynchronously.
+///
+/// This function retrieves the specified `ShareEntry` from the database, refreshes its share,
+/// and then updates the entry in the database. If a response channel is provided, it sends a
+/// response back to the network client. A missing share, a share owned by another peer, a refresh
+/// for an epoch the share has already moved past and a refresh key that does not fit the share
+/// are refused with their `Failure`; a storage error is reported to the requester as well, so a
+/// failed refresh is never acknowledged.
+///
+/// # Arguments
+/// * `key` - The key the owner registered the share under.
+/// * `sender` - The `PeerId` of the share owner on whose behalf the refresh is made. The share is
+///   looked up in its namespace.
+/// * `refresh_key` - The `RefreshKey` used for refreshing the share.
+/// * `epoch` - The epoch the refresh advances the share to, or `None` to bump the stored one.
+///   Redelivering a refresh that was already applied succeeds without refreshing again.
+/// * `channel` - An optional `ResponseChannel<Response>` for sending responses.
+/// * `dao` - A shared and mutable reference to the data access object (DAO) trait object.
+/// * `metrics` - The metrics of the provider.
+/// * `network_client` - A mutable reference to the network client for responding to requests.
+///
+/// # Returns
+/// Returns a `Result` containing the `AuditOutcome` of the refresh, or the error of a storage
+/// failure.
+#[allow(clippy::too_many_arguments)]
+pub async fn execute_refresh_share(
+    key: &str,
+    sender: &PeerId,
+    refresh_key: &RefreshKey,
+    epoch: Option<u64>,
+    channel: Option<ResponseChannel<Response>>,
+    dao: &SharedDao,
+    metrics: &ProviderMetrics,
+    network_client: &mut Client,
+) -> Result<AuditOutcome, Error> {
+    debug!("-- Sender: {:#?}.", sender);
+    // only a peer asking for the refresh is checked to be the owner
+    let result = refresh_owned_share(key, sender, refresh_key, epoch, channel.is_some(), dao);
+    match &result {
+        Ok(_) => {
+            metrics.record_refresh_applied();
+            info!(key, peer = %sender, outcome = "refreshed", "Refreshed share.")
+        }
+        Err(failure) => warn!(
+            key, peer = %sender, outcome = "refused", reason = %failure,
+            "Could not refresh share."
+        ),
+    }
+    if let Some(channel) = channel {
+        let response = result.as_ref().map(|_| ()).map_err(Failure::clone);
+        network_client
+            .respond_refresh_shares(response, channel)
+            .await;
+    }
+    handler_outcome(&result, metrics)
+}
+
+/// Refreshes the share `sender` registered under `key` for `execute_refresh_share`.
+///
+/// The refreshed share is flushed before it is reported, so that a refresh is never acknowledged
+/// and then lost in a crash. A refresh whose flush failed is reported as a storage error; the
+/// refresh stays applied, so the coordinator retrying it at the same epoch is acknowledged once
+/// the flush succeeds.
+///
+/// # Arguments
+/// * `key` - The key the owner registered the share under.
+/// * `sender` - The `PeerId` of the share owner on whose behalf the refresh is made.
+/// * `refresh_key` - The `RefreshKey` used for refreshing the share.
+/// * `epoch` - The epoch the refresh advances the share to, or `None` to bump the stored one.
+/// * `check_owner` - Whether the stored share must be owned by `sender`.
+/// * `dao` - A shared reference to the DAO trait object.
+///
+/// # Returns
+/// Returns a `Result` containing the refreshed entry as stored, or the `Failure` to respond with.
+fn refresh_owned_share(
+    key: &str,
+    sender: &PeerId,
+    refresh_key: &RefreshKey,
+    epoch: Option<u64>,
+    check_owner: bool,
+    dao: &SharedDao,
+) -> Result<ShareEntry, Failure> {
+    let stored_key = owner_key(&sender.to_bytes(), key);
+    let share_entry = get_live_entry(&stored_key, dao)
+        .map_err(storage_failure)?
+        .ok_or_else(|| missing_share(&stored_key, dao))?;
+    if check_owner && !check_share_owner(&share_entry, sender) {
+        return Err(not_owner(sender, &share_entry));
+    }
+    debug!("-- share before refresh: {:?}", share_entry.share);
+    let refreshed =
+        refresh_stored_share(&stored_key, refresh_key, epoch, dao).map_err(storage_failure)?;
+    debug!("-- share after refresh:  {:?}", refreshed.share);
+    dao.lock().unwrap().flush().map_err(storage_failure)?;
+    Ok(refreshed)
+}
+
+/// Computes the digest a refresh key is recorded under, so that a redelivered refresh can be told
+/// apart from a different refresh at the same epoch.
+///
+/// # Arguments
+/// * `refresh_key` - The polynomials of the refresh key.
+///
+/// # Returns
+/// Returns the SHA-256 digest of the coefficients of every polynomial, in order, each hashed as
+/// the big-endian bytes of its field element.
+pub fn refresh_digest(refresh_key: &RefreshKey) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    match refresh_key {
+        RefreshKey::Gf256(polynomials) => {
+            for polynomial in polynomials {
+                hasher.update((polynomial.coefficients.len() as u64).to_be_bytes());
+                for coefficient in &polynomial.coefficients {
+                    hasher.update([u8::from(*coefficient)]);
+                }
+            }
+        }
+        #[cfg(feature = "sss16")]
+        RefreshKey::Gf65536(polynomials) => {
+            for polynomial in polynomials {
+                hasher.update((polynomial.coefficients.len() as u64).to_be_bytes());
+                for coefficient in &polynomial.coefficients {
+                    hasher.update(u16::from(*coefficient).to_be_bytes());
+                }
+            }
+        }
+    }
+    hasher.finalize().into()
+}
+
+/// Applies a refresh key to the stored share under `key`.
+///
+/// The share is read, refreshed, and written back with `compare_and_swap`. If another writer
+/// changed the entry in between, the refresh is re-applied to the new value, so concurrent
+/// refreshes compose instead of one overwriting the other. The epoch, refresh time, and digest of
+/// the refresh key are written in the same swap as the share, so they always describe the stored
+/// share.
+///
+/// A refresh for an epoch the share already reached is not applied again: a redelivery of the
+/// refresh that moved the share to that epoch succeeds without changing it, and anything else is
+/// refused with `RepoError::StaleEpoch`. A refresh key that would change the secret, or that does
+/// not match the length and threshold of the share, is refused with
+/// `RepoError::InvalidRefreshKey` before the share is touched.
+///
+/// # Arguments
+/// * `key` - The key identifying the `ShareEntry` to refresh.
+/// * `refresh_key` - The `RefreshKey` used for refreshing the share, over the field its secret
+///   was split over.
+/// * `epoch` - The epoch the refresh advances the share to, which must be past the stored one.
+///   With `None` the stored epoch is bumped by one.
+/// * `dao` - A shared reference to the DAO trait object.
+///
+/// # Returns
+/// Returns a `Result` containing the refreshed entry as stored.
+pub fn refresh_stored_share(
+    key: &str,
+    refresh_key: &RefreshKey,
+    epoch: Option<u64>,
+    dao: &SharedDao,
+) -> Result<ShareEntry, RepoError> {
+    let digest = refresh_digest(refresh_key);
+    for _ in 0..MAX_REFRESH_ATTEMPTS {
+        let current =
+            get_live_entry(key, dao)?.ok_or_else(|| RepoError::KeyNotFound(key.to_string()))?;
+        let next_epoch = match epoch {
+            Some(requested)
+                if requested == current.epoch && current.refresh_digest == Some(digest) =>
+            {
+                debug!(
+                    "Refresh of key {:?} to epoch {} already applied.",
+                    key, requested
+                );
+                return Ok(current);
+            }
+            Some(requested) if requested <= current.epoch => {
+                return Err(RepoError::StaleEpoch {
+                    stored: current.epoch,
+                    requested,
+                });
+            }
+            Some(requested) => requested,
+            None => current.epoch + 1,
+        };
+        let mut refreshed = current.clone();
+        apply_refresh_key(&mut refreshed, refresh_key).map_err(RepoError::InvalidRefreshKey)?;
+        refreshed.epoch = next_epoch;
+        refreshed.last_refreshed_unix = now_unix();
+        refreshed.refresh_digest = Some(digest);
+        if dao
+            .lock()
+            .unwrap()
+            .compare_and_swap(key, &current, &refreshed)?
+        {
+            return Ok(refreshed);
+        }
+        debug!("Share for key {:?} changed during refresh, retrying.", key);
+    }
+    Err(RepoError::Contended(key.to_string()))
+}
+
+/// Refreshes the share of an entry in place, after checking that the refresh key is over the
+/// field the secret of the share was split over and fits its length and threshold.
+///
+/// # Arguments
+/// * `entry` - The entry whose share is refreshed.
+/// * `refresh_key` - The `RefreshKey` to apply.
+///
+/// # Returns
+/// Returns `Ok(())` once the share is refreshed, or the `sss::Error` the refresh key was refused
+/// with, leaving the share untouched.
+fn apply_refresh_key(entry: &mut ShareEntry, refresh_key: &RefreshKey) -> Result<(), sss::Error> {
+    let threshold = entry.threshold as usize;
+    let (index, share) = (&entry.share.0, &mut entry.share.1);
+    match refresh_key {
+        #[cfg(not(feature = "sss16"))]
+        RefreshKey::Gf256(polynomials) => {
+            validate_refresh_key(polynomials, threshold, share.len())?;
+            refresh_share((index, share), polynomials)
+        }
+        #[cfg(feature = "sss16")]
+        RefreshKey::Gf256(polynomials) if entry.field == ShareField::Gf256 => {
+            validate_refresh_key(polynomials, threshold, share.len())?;
+            let index = u8::try_from(*index).map_err(|_| sss::Error::InvalidIndex)?;
+            refresh_share((&index, share), polynomials)
+        }
+        #[cfg(feature = "sss16")]
+        RefreshKey::Gf65536(polynomials) if entry.field == ShareField::Gf65536 => {
+            sss16::validate_refresh_key(polynomials, threshold, share.len())?;
+            sss16::refresh_share((index, share), polynomials)
+        }
+        #[cfg(feature = "sss16")]
+        _ => Err(sss::Error::KeyField),
+    }
+}
+
+/// Executes the share registration logic asynchronously.
+///
+/// Registers the share with `register_share` and sends the response back to the network client.
+///
+/// # Arguments
+/// * `sender` - The `PeerId` of the sender requesting the registration.
+/// * `request` - The `RegisterShareRequest` carrying the key, share, threshold, and lifetime.
+/// * `channel` - The `ResponseChannel<Response>` for sending responses.
+/// * `dao` - A shared and mutable reference to the DAO trait object.
+/// * `metrics` - The metrics of the provider.
+/// * `max_share_bytes` - The largest share accepted.
+/// * `network_client` - A mutable reference to the network client.
+///
+/// # Returns
+/// Returns a `Result` containing the `AuditOutcome` of the registration, or the error of a
+/// storage failure.
+pub async fn execute_register_share(
+    sender: &PeerId,
+    request: RegisterShareRequest,
+    channel: ResponseChannel<Response>,
+    dao: &SharedDao,
+    metrics: &ProviderMetrics,
+    max_share_bytes: usize,
+    network_client: &mut Client,
+) -> Result<AuditOutcome, Error> {
+    debug!("-- Sender: {:#?}.", sender);
+    let result = register_share(sender, &request, dao, max_share_bytes, network_client).await;
+    if result.is_ok() {
+        metrics.record_registration();
+    }
+    network_client
+        .respond_register_share(result.clone(), channel)
+        .await;
+
+    handler_outcome(&result, metrics)
+}
+
+/// Stores a registered share and provides it on the DHT.
+///
+/// The share is stored in the namespace of the sender, replacing any share the sender registered
+/// under the same key, and provided on the DHT under the record of (sender, key) once it is
+/// flushed to disk. Other owners' shares under the same key are unaffected. Structurally invalid
+/// shares (see `validate_registration`), registrations over a quota, or of a key the sender
+/// deleted recently without `recreate` set, are refused with the `Failure`, and the share is
+/// neither stored nor provided. Quota refusals carry the exceeded limit and its usage, so that the
+/// requester can pick another provider.
+///
+/// # Arguments
+/// * `sender` - The `PeerId` of the sender requesting the registration.
+/// * `request` - The `RegisterShareRequest` carrying the key, share, threshold, and lifetime.
+/// * `dao` - A shared and mutable reference to the DAO trait object.
+/// * `max_share_bytes` - The largest share accepted.
+/// * `network_client` - A mutable reference to the network client.
+///
+/// # Returns
+/// Returns a `Result` indicating the share was registered, or the `Failure` to respond with.
+pub async fn register_share(
+    sender: &PeerId,
+    request: &RegisterShareRequest,
+    dao: &SharedDao,
+    max_share_bytes: usize,
+    network_client: &mut Client,
+) -> Result<(), Failure> {
+    let key = request.key.as_str();
+    let stored = validate_registration(request, max_share_bytes)
+        .and_then(|()| store_registered_share(sender, request, dao).map_err(storage_failure));
+    if let Err(failure) = stored {
+        warn!(
+            key, peer = %sender, outcome = "refused", reason = %failure,
+            "Refused share registration."
+        );
+        return Err(failure);
+    }
+    network_client
+        .start_providing(Client::provider_key(sender, key))
+        .await;
+    info!(key, peer = %sender, outcome = "registered", "Registered share.");
+
+    Ok(())
+}
+
+/// Checks that a registration carries a share that can take part in a reconstruction.
+///
+/// The share index must be non-zero, since the share at index 0 is the secret itself, the share
+/// data must be non-empty and at most `max_share_bytes` long, and the threshold must be between 2
+/// and the most shares a secret can be split into, 255 over GF(2^8). With the `sss16` feature,
+/// the share must also fit the field its secret was split over. A relay grant, if any, must be
+/// signed by the sender for the peer the share is registered with.
+///
+/// # Arguments
+/// * `request` - The `RegisterShareRequest` to check.
+/// * `max_share_bytes` - The largest share accepted.
+///
+/// # Returns
+/// Returns `Ok(())` for a valid share, or `Failure::InvalidRequest` naming the violated
+/// constraint.
+pub fn validate_registration(
+    request: &RegisterShareRequest,
+    max_share_bytes: usize,
+) -> Result<(), Failure> {
+    let (index, data) = &request.share;
+    #[cfg(feature = "sss16")]
+    let max_index = request.field.max_index();
+    #[cfg(not(feature = "sss16"))]
+    let max_index = u8::MAX;
+    let violation = if *index == 0 {
+        Some("share index must be non-zero".to_string())
+    } else if data.is_empty() {
+        Some("share data must not be empty".to_string())
+    } else if data.len() > max_share_bytes {
+        Some(format!(
+            "share of {} bytes exceeds the limit of {} bytes",
+            data.len(),
+            max_share_bytes
+        ))
+    } else if let Some(reason) = field_violation(request) {
+        Some(reason)
+    } else if !(2..=u64::from(max_index)).contains(&request.threshold) {
+        Some(format!(
+            "threshold {} is outside the range 2 to {}",
+            request.threshold, max_index
+        ))
+    } else if let Some(Err(e)) = request
+        .relay_grant
+        .as_ref()
+        .map(|grant| grant.verify(&request.key, &request.peer, &request.sender))
+    {
+        Some(format!("relay grant rejected: {}", e))
+    } else {
+        None
+    };
+    violation.map_or(Ok(()), |reason| Err(Failure::InvalidRequest(reason)))
+}
+
+/// Describes how the share of a registration does not fit the field its secret was split over:
+/// an index beyond the field, or data of a partial field element.
+#[cfg(feature = "sss16")]
+fn field_violation(request: &RegisterShareRequest) -> Option<String> {
+    let (index, data) = &request.share;
+    if *index > request.field.max_index() {
+        Some(format!("share index {} is beyond {}", index, request.field))
+    } else if request.field == ShareField::Gf65536 && !data.len().is_multiple_of(2) {
+        Some("share data over GF(2^16) must be of an even length".to_string())
+    } else {
+        None
+    }
+}
+
+/// Describes how the share of a registration does not fit the field its secret was split over.
+/// Every share fits GF(2^8), the only field without the `sss16` feature.
+#[cfg(not(feature = "sss16"))]
+fn field_violation(_request: &RegisterShareRequest) -> Option<String> {
+    None
+}
+
+/// Stores a registered share in the namespace of its sender, and flushes it so that it survives
+/// a crash once the registration is acknowledged.
+///
+/// A live tombstone the sender left under the key refuses the registration, so that a late or
+/// replayed registration cannot bring back a deleted share, unless the request sets `recreate`.
+/// A stored share replaces the tombstone. If the flush fails, the share and tombstone stored
+/// before are put back, so that a registration reported as failed leaves nothing behind.
+///
+/// A live share the sender already stored under the key is only overwritten by a registration
+/// with the same share index, length and threshold, so that providers never disagree on the
+/// threshold of a key. A registration that differs is refused with `RepoError::ConflictingShare`
+/// unless it sets `replace`, which overwrites the share and restarts it at epoch 0.
+///
+/// # Arguments
+/// * `sender` - The `PeerId` of the share owner.
+/// * `request` - The `RegisterShareRequest` carrying the key, share, threshold, and lifetime.
+/// * `dao` - A shared reference to the DAO trait object.
+///
+/// # Returns
+/// Returns a `Result<(), RepoError>`, indicating success,
+/// `RepoError::RecentlyDeleted` if a tombstone refused the registration, or
+/// `RepoError::ConflictingShare` if the stored share did.
+pub fn store_registered_share(
+    sender: &PeerId,
+    request: &RegisterShareRequest,
+    dao: &SharedDao,
+) -> Result<(), RepoError> {
+    let now = now_unix();
+    let owner = sender.to_bytes();
+    let stored_key = owner_key(&owner, &request.key);
+    let dao = dao.lock().unwrap();
+    let tombstone = dao.get_tombstone(&stored_key)?;
+    if let Some(tombstone) = &tombstone {
+        if !request.recreate && tombstone.owner == owner && tombstone.is_live(now) {
+            return Err(RepoError::RecentlyDeleted(request.key.clone()));
+        }
+    }
+    let previous = dao.get(&stored_key)?;
+    let live = previous
+        .as_ref()
+        .filter(|previous| !previous.is_expired(now));
+    if let Some(conflict) = live.and_then(|previous| registration_conflict(previous, request)) {
+        if !request.replace {
+            return Err(RepoError::ConflictingShare(conflict));
+        }
+        info!(
+            key = request.key, peer = %sender, reason = %conflict,
+            "Replacing share."
+        );
+    }
+    dao.insert(&stored_key, &registered_entry(sender, request, now))?;
+    dao.remove_tombstone(&stored_key)?;
+    if let Err(e) = dao.flush() {
+        if let Err(rollback) = restore_registration(&**dao, &stored_key, previous, tombstone) {
+            error!(
+                "Could not roll back unflushed registration of key {:?}: {}",
+                stored_key, rollback
+            );
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Describes how a registration differs from the share stored under its key.
+///
+/// # Arguments
+/// * `stored` - The share stored under the key.
+/// * `request` - The `RegisterShareRequest` for the key.
+///
+/// # Returns
+/// Returns `None` if the registration has the share index, field, length and threshold of the
+/// stored share, or what differs first.
+fn registration_conflict(stored: &ShareEntry, request: &RegisterShareRequest) -> Option<String> {
+    #[cfg(feature = "sss16")]
+    if request.field != stored.field {
+        return Some(format!(
+            "field {} differs from the stored {}",
+            request.field, stored.field
+        ));
+    }
+    let (index, data) = &request.share;
+    if *index != stored.share.0 {
+        Some(format!(
+            "share index {} differs from the stored {}",
+            index, stored.share.0
+        ))
+    } else if data.len() != stored.share.1.len() {
+        Some(format!(
+            "share length {} differs from the stored {}",
+            data.len(),
+            stored.share.1.len()
+        ))
+    } else if request.threshold != stored.threshold {
+        Some(format!(
+            "threshold {} differs from the stored {}",
+            request.threshold, stored.threshold
+        ))
+    } else {
+        None
+    }
+}
+
+/// Puts back the share and tombstone stored under `stored_key` before a registration.
+///
+/// # Arguments
+/// * `dao` - The DAO the registration was stored in.
+/// * `stored_key` - The key the registration was stored under, in its owner's namespace.
+/// * `previous` - The share stored under the key before, if any.
+/// * `tombstone` - The tombstone stored under the key before, if any.
+///
+/// # Returns
+/// Returns a `Result<(), RepoError>`, indicating success or failure.
+fn restore_registration(
+    dao: &dyn ShareEntryDaoTrait,
+    stored_key: &str,
+    previous: Option<ShareEntry>,
+    tombstone: Option<Tombstone>,
+) -> Result<(), RepoError> {
+    match previous {
+        Some(previous) => dao.insert(stored_key, &previous)?,
+        None => dao.delete(stored_key)?,
+    }
+    if let Some(tombstone) = tombstone {
+        dao.put_tombstone(stored_key, &tombstone)?;
+    }
+    Ok(())
+}
+
+/// Builds the entry stored for a registration, starting a fresh refresh history at epoch 0
+/// with no readers besides the owner.
+///
+/// # Arguments
+/// * `sender` - The `PeerId` of the share owner.
+/// * `request` - The `RegisterShareRequest` carrying the share, threshold, and lifetime.
+/// * `now` - The current unix timestamp (seconds).
+fn registered_entry(sender: &PeerId, request: &RegisterShareRequest, now: u64) -> ShareEntry {
+    ShareEntry {
+        share: request.share.clone(),
+        #[cfg(feature = "sss16")]
+        field: request.field,
+        sender: sender.to_bytes(),
+        threshold: request.threshold,
+        expires_at: request.ttl_secs.map(|ttl| now.saturating_add(ttl)),
+        epoch: 0,
+        last_refreshed_unix: now,
+        refresh_digest: None,
+        refresh_interval_secs: request.refresh_interval_secs,
+        readers: vec![],
+        relay_grant: request.relay_grant.clone(),
+    }
+}
+
+/// Executes the logic to retrieve and send a share asynchronously.
+///
+/// This function reads the share `owner` registered under `key` with `read_share` and sends it
+/// back via the network client, provided the sender is the owner or one of the share's readers.
+/// Otherwise the `Failure` is sent.
+///
+/// # Arguments
+/// * `key` - The key identifying the share to retrieve.
+/// * `sender` - The `PeerId` of the sender requesting the share.
+/// * `owner` - The `PeerId` of the share's owner, which is the sender unless reading as a reader.
+/// * `channel` - The `ResponseChannel<Response>` for sending the share.
+/// * `dao` - A shared and mutable reference to the DAO trait object.
+/// * `metrics` - The metrics of the provider.
+/// * `network_client` - A mutable reference to the network client.
+///
+/// # Returns
+/// Returns a `Result` containing the `AuditOutcome` of the request, or the error of a storage
+/// failure.
+pub async fn execute_get_share(
+    key: &str,
+    sender: &PeerId,
+    owner: &PeerId,
+    channel: ResponseChannel<Response>,
+    dao: &SharedDao,
+    metrics: &ProviderMetrics,
+    network_client: &mut Client,
+) -> Result<AuditOutcome, Error> {
+    debug!("-- Sender: {:#?}.", sender);
+    let result = read_share(key, sender, owner, dao);
+    if let Err(failure) = &result {
+        warn!(
+            key, %owner, peer = %sender, outcome = "refused", reason = %failure,
+            "Refused share."
+        );
+    }
+    network_client.respond_share(result.clone(), channel).await;
+    if result.is_ok() {
+        metrics.record_get_served();
+        info!(key, %owner, peer = %sender, outcome = "sent", "Sent share.");
+    }
+
+    handler_outcome(&result, metrics)
+}
+
+/// Reads the share `owner` registered under `key` on behalf of `sender`.
+///
+/// # Arguments
+/// * `key` - The key identifying the share to read.
+/// * `sender` - The `PeerId` of the sender requesting the share.
+/// * `owner` - The `PeerId` of the share's owner.
+/// * `dao` - A shared reference to the DAO trait object.
+///
+/// # Returns
+/// Returns a `Result` containing the share and the field its secret was split over, or
+/// `NotFound` if `owner` has no live share under `key`, `Corrupt` if the share failed its
+/// integrity check or was quarantined by the integrity scan, `NotOwner` if the share stored
+/// there belongs to another peer, `NotReader` if the sender may not read it, or `StorageError` if
+/// it could not be read.
+pub fn read_share(
+    key: &str,
+    sender: &PeerId,
+    owner: &PeerId,
+    dao: &SharedDao,
+) -> Result<FieldShare, Failure> {
+    let share_entry = match get_owned_live_entry(owner, key, dao) {
+        Ok(Some(share_entry)) => share_entry,
+        Ok(None) => return Err(missing_share(&owner_key(&owner.to_bytes(), key), dao)),
+        Err(e) => {
+            if matches!(e, RepoError::CorruptEntry { .. }) {
+                error!(key, error = %e, "Share failed its integrity check.");
+            }
+            return Err(storage_failure(e));
+        }
+    };
+    // check that the share belongs to the owner it was looked up for
+    if !check_share_owner(&share_entry, owner) {
+        return Err(not_owner(owner, &share_entry));
+    }
+    // check that the peer requesting the share may read it
+    if !share_entry.can_read(&sender.to_bytes()) {
+        return Err(Failure::NotReader);
+    }
+    Ok(FieldShare {
+        share: share_entry.share,
+        #[cfg(feature = "sss16")]
+        field: share_entry.field,
+    })
+}
+
+/// Deletes the share `sender` registered under `key`, leaving a tombstone that refuses
+/// registrations under the key for `tombstone_window`.
+///
+/// # Arguments
+/// * `key` - The key chosen by the owner.
+/// * `sender` - The `PeerId` of the peer asking for the delete.
+/// * `tombstone_window` - How long registrations of the deleted key are refused.
+/// * `dao` - A shared reference to the DAO trait object.
+///
+/// # Returns
+/// Returns a `Result` containing `Deleted` if the share was deleted, `NotFound` if the sender has
+/// no live share under `key`, or `NotOwner` if the share stored there belongs to another peer.
+pub fn delete_owned_share(
+    key: &str,
+    sender: &PeerId,
+    tombstone_window: Duration,
+    dao: &SharedDao,
+) -> Result<DeleteShareStatus, RepoError> {
+    let Some(share_entry) = get_owned_live_entry(sender, key, dao)? else {
+        return Ok(DeleteShareStatus::NotFound);
+    };
+    if !check_share_owner(&share_entry, sender) {
+        return Ok(DeleteShareStatus::NotOwner);
+    }
+    let stored_key = owner_key(&sender.to_bytes(), key);
+    let deleted = dao.lock().unwrap().delete_with_tombstone(
+        &stored_key,
+        OWNER_DELETED,
+        now_unix(),
+        tombstone_window,
+    )?;
+    Ok(match deleted {
+        Some(_) => DeleteShareStatus::Deleted,
+        None => DeleteShareStatus::NotFound,
+    })
+}
+
+/// Executes the delete share operation.
+///
+/// Deletes the share the sender registered under `key`, stops providing it on the DHT and
+/// responds with the resulting `DeleteShareStatus`.
+///
+/// # Arguments
+/// * `key` - The key of the share to delete.
+/// * `sender` - The `PeerId` of the sender asking for the delete.
+/// * `channel` - The `ResponseChannel<Response>` to respond on.
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `metrics` - The metrics of the provider.
+/// * `network_client` - A mutable reference to the network client.
+///
+/// # Returns
+/// Returns a `Result` containing the outcome to audit. A missing or foreign share is refused
+/// rather than failing the handler.
+pub async fn execute_delete_share(
+    key: &str,
+    sender: &PeerId,
+    channel: ResponseChannel<Response>,
+    dao: &SharedDao,
+    metrics: &ProviderMetrics,
+    network_client: &mut Client,
+) -> Result<AuditOutcome, Error> {
+    let window = Duration::from_secs(DEFAULT_TOMBSTONE_SECONDS);
+    let status = match delete_owned_share(key, sender, window, dao) {
+        Ok(status) => status,
+        Err(e) => DeleteShareStatus::Failed(e.to_string()),
+    };
+    if let Some(class) = status.failure_class() {
+        metrics.record_failure(class);
+    }
+    let outcome = match &status {
+        DeleteShareStatus::Deleted => {
+            network_client
+                .stop_providing(Client::provider_key(sender, key))
+                .await;
+            info!(key, peer = %sender, outcome = "deleted", "Deleted share.");
+            Ok(AuditOutcome::Success)
+        }
+        DeleteShareStatus::NotFound => Ok(AuditOutcome::Refused(NOT_FOUND.to_string())),
+        DeleteShareStatus::NotOwner => {
+            warn!(
+                key, peer = %sender, outcome = "refused", reason = NOT_OWNER,
+                "Refused share deletion."
+            );
+            Ok(AuditOutcome::Refused(NOT_OWNER.to_string()))
+        }
+        DeleteShareStatus::Failed(reason) => {
+            error!(
+                key, peer = %sender, outcome = "failed", reason,
+                "Failed to delete share."
+            );
+            Err(Error::Failure(Failure::StorageError(reason.clone())))
+        }
+    };
+    network_client.respond_delete_share(status, channel).await;
+    outcome
+}
+
+/// Lists a page of the keys `sender` holds shares under, in key order.
+///
+/// Only the sender's own namespace is read, so the cursor can only move within the sender's keys.
+///
+/// # Arguments
+/// * `sender` - The `PeerId` whose keys are listed.
+/// * `cursor` - The last key of the previous page; only keys after it are listed. An empty
+///   cursor starts from the first key.
+/// * `limit` - The most keys to list, capped at `MAX_LIST_KEYS_LIMIT`.
+/// * `dao` - A shared reference to the DAO trait object.
+///
+/// # Returns
+/// Returns a `Result` containing the keys on the page and the cursor of the next page, or `None`
+/// if no keys follow.
+pub fn list_owned_keys(
+    sender: &PeerId,
+    cursor: &str,
+    limit: u32,
+    dao: &SharedDao,
+) -> Result<(Vec<String>, Option<String>), RepoError> {
+    let limit = limit.clamp(1, MAX_LIST_KEYS_LIMIT) as usize;
+    let owner = sender.to_bytes();
+    let stored_keys = dao.lock().unwrap().keys_by_owner(&owner)?;
+    let mut keys: Vec<String> = stored_keys
+        .iter()
+        .filter_map(|stored_key| match split_owner_key(stored_key) {
+            Some((key_owner, key)) if key_owner == owner && key > cursor => Some(key.to_string()),
+            _ => None,
+        })
+        .collect();
+    keys.sort();
+    let next_cursor = if keys.len() > limit {
+        keys.truncate(limit);
+        keys.last().cloned()
+    } else {
+        None
+    };
+    Ok((keys, next_cursor))
+}
+
+/// Executes the list keys operation.
+///
+/// Responds with a page of the keys the sender holds shares under on this provider.
+///
+/// # Arguments
+/// * `sender` - The `PeerId` of the sender whose keys are listed.
+/// * `cursor` - The last key of the previous page, or an empty string for the first page.
+/// * `limit` - The most keys to list.
+/// * `channel` - The `ResponseChannel<Response>` to respond on.
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `metrics` - The metrics of the provider.
+/// * `network_client` - A mutable reference to the network client.
+///
+/// # Returns
+/// Returns a `Result` containing the outcome to audit.
+pub async fn execute_list_keys(
+    sender: &PeerId,
+    cursor: &str,
+    limit: u32,
+    channel: ResponseChannel<Response>,
+    dao: &SharedDao,
+    metrics: &ProviderMetrics,
+    network_client: &mut Client,
+) -> Result<AuditOutcome, Error> {
+    let (response, outcome) = match list_owned_keys(sender, cursor, limit, dao) {
+        Ok((keys, next_cursor)) => {
+            let response = ListKeysResponse {
+                keys,
+                next_cursor,
+                success: true,
+                reason: None,
+                failure: None,
+            };
+            (response, Ok(AuditOutcome::Success))
+        }
+        Err(e) => {
+            let reason = e.to_string();
+            error!("Failed to list keys of {:?}: {}", sender, reason);
+            let failure = Failure::StorageError(reason.clone());
+            metrics.record_failure(failure.class());
+            let response = ListKeysResponse {
+                keys: vec![],
+                next_cursor: None,
+                success: false,
+                reason: Some(reason.clone()),
+                failure: Some(failure.clone()),
+            };
+            (response, Err(Error::Failure(failure)))
+        }
+    };
+    network_client.respond_list_keys(response, channel).await;
+    outcome
+}
+
+/// Builds the metadata reported for a share, leaving the share bytes out.
+///
+/// # Arguments
+/// * `entry` - The stored share.
+///
+/// # Returns
+/// Returns the `ShareMetadata` of `entry`.
+pub fn share_metadata(entry: &ShareEntry) -> ShareMetadata {
+    ShareMetadata {
+        index: entry.share.0,
+        #[cfg(feature = "sss16")]
+        field: entry.field,
+        length: entry.share.1.len() as u64,
+        threshold: entry.threshold,
+        epoch: entry.epoch,
+        last_refreshed_unix: entry.last_refreshed_unix,
+    }
+}
+
+/// Looks up the metadata of the share `sender` registered under `key`.
+///
+/// # Arguments
+/// * `key` - The key chosen by the owner.
+/// * `sender` - The `PeerId` of the peer asking for the metadata.
+/// * `dao` - A shared reference to the DAO trait object.
+///
+/// # Returns
+/// Returns a `Result` containing `Found` with the share's metadata, `NotFound` if the sender has
+/// no live share under `key`, or `NotOwner` if the share stored there belongs to another peer.
+pub fn stat_owned_share(
+    key: &str,
+    sender: &PeerId,
+    dao: &SharedDao,
+) -> Result<StatShareStatus, RepoError> {
+    let Some(share_entry) = get_owned_live_entry(sender, key, dao)? else {
+        return Ok(StatShareStatus::NotFound);
+    };
+    if !check_share_owner(&share_entry, sender) {
+        return Ok(StatShareStatus::NotOwner);
+    }
+    Ok(StatShareStatus::Found(share_metadata(&share_entry)))
+}
+
+/// Executes the stat share operation.
+///
+/// Responds with the metadata of the share the sender registered under `key`. The share bytes
+/// are never sent.
+///
+/// # Arguments
+/// * `key` - The key of the share to describe.
+/// * `sender` - The `PeerId` of the sender asking for the metadata.
+/// * `channel` - The `ResponseChannel<Response>` to respond on.
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `metrics` - The metrics of the provider.
+/// * `network_client` - A mutable reference to the network client.
+///
+/// # Returns
+/// Returns a `Result` containing the outcome to audit. A missing or foreign share is refused
+/// rather than failing the handler.
+pub async fn execute_stat_share(
+    key: &str,
+    sender: &PeerId,
+    channel: ResponseChannel<Response>,
+    dao: &SharedDao,
+    metrics: &ProviderMetrics,
+    network_client: &mut Client,
+) -> Result<AuditOutcome, Error> {
+    let status = match stat_owned_share(key, sender, dao) {
+        Ok(status) => status,
+        Err(e) => {
+            error!("Failed to read share for key {:?}: {}", key, e);
+            StatShareStatus::Failed(e.to_string())
+        }
+    };
+    if let Some(class) = status.failure_class() {
+        metrics.record_failure(class);
+    }
+    let outcome = match &status {
+        StatShareStatus::Found(_) => AuditOutcome::Success,
+        StatShareStatus::NotFound => AuditOutcome::Refused(NOT_FOUND.to_string()),
+        StatShareStatus::NotOwner => AuditOutcome::Refused(NOT_OWNER.to_string()),
+        StatShareStatus::Failed(reason) => AuditOutcome::Failed(reason.clone()),
+    };
+    network_client.respond_stat_share(status, channel).await;
+    Ok(outcome)
+}
+
+/// A change to the readers of a share.
+///
+/// # Variants
+/// * `Grant` - The peer is added to the share's readers.
+/// * `Revoke` - The peer is removed from the share's readers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessChange {
+    Grant,
+    Revoke,
+}
+
+/// Grants or revokes a peer's read access to the share `sender` registered under `key`.
+///
+/// Only the owner can change the readers of a share: the share is looked up in the sender's
+/// namespace, so a reader asking to grant access finds no share there.
+///
+/// # Arguments
+/// * `key` - The key chosen by the owner.
+/// * `sender` - The `PeerId` of the peer asking for the change.
+/// * `reader` - The peer id bytes of the reader to grant or revoke.
+/// * `change` - Whether to grant or revoke access.
+/// * `dao` - A shared reference to the DAO trait object.
+///
+/// # Returns
+/// Returns a `Result` indicating the change was stored, or `NotFound` if the sender has no live
+/// share under `key`, `NotOwner` if the share belongs to another peer, or `StorageError` if the
+/// share could not be read or written.
+pub fn update_readers(
+    key: &str,
+    sender: &PeerId,
+    reader: &[u8],
+    change: AccessChange,
+    dao: &SharedDao,
+) -> Result<(), Failure> {
+    let Some(mut share_entry) = get_owned_live_entry(sender, key, dao).map_err(storage_failure)?
+    else {
+        return Err(Failure::NotFound);
+    };
+    if !check_share_owner(&share_entry, sender) {
+        return Err(not_owner(sender, &share_entry));
+    }
+    match change {
+        AccessChange::Grant if !share_entry.readers.iter().any(|r| r == reader) => {
+            share_entry.readers.push(reader.to_vec())
+        }
+        AccessChange::Grant => {}
+        AccessChange::Revoke => share_entry.readers.retain(|r| r != reader),
+    }
+    let dao = dao.lock().unwrap();
+    dao.update(&owner_key(&sender.to_bytes(), key), &share_entry)
+        .map_err(storage_failure)?;
+    dao.flush().map_err(storage_failure)
+}
+
+/// Executes a grant or revoke access operation.
+///
+/// # Arguments
+/// * `request` - The access request received.
+/// * `sender` - The `PeerId` of the sender asking for the change.
+/// * `change` - Whether to grant or revoke access.
+/// * `channel` - The `ResponseChannel<Response>` to respond on.
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `metrics` - The metrics of the provider.
+/// * `network_client` - A mutable reference to the network client.
+///
+/// # Returns
+/// Returns a `Result` containing the outcome to audit, or the error of a storage failure. A reader
+/// that is not a valid peer id is refused as an invalid request.
+pub async fn execute_update_access(
+    request: &AccessRequest,
+    sender: &PeerId,
+    change: AccessChange,
+    channel: ResponseChannel<Response>,
+    dao: &SharedDao,
+    metrics: &ProviderMetrics,
+    network_client: &mut Client,
+) -> Result<AuditOutcome, Error> {
+    let result = match PeerId::from_bytes(&request.reader) {
+        Ok(_) => update_readers(&request.key, sender, &request.reader, change, dao),
+        Err(e) => Err(Failure::InvalidRequest(format!("malformed reader: {}", e))),
+    };
+    if let Err(failure) = &result {
+        warn!(
+            key = request.key, peer = %sender, outcome = "refused", reason = %failure,
+            "Could not update the readers of share."
+        );
+    }
+    network_client.respond_access(result.clone(), channel).await;
+    handler_outcome(&result, metrics)
+}
+
+/// Hands the share `sender` registered under `key` over to `new_owner`, who owns it from then
+/// on: the share moves to the namespace of the new owner, and the sender can no longer get,
+/// refresh or delete it.
+///
+/// A transfer that was interrupted after storing the share under the new owner is completed
+/// rather than refused, so that an owner can repeat a transfer until it is acknowledged. The
+/// relay grant of the sender does not carry over, since it only speaks for the sender; the
+/// share keeps the grant of the new owner instead, if one is given.
+///
+/// # Arguments
+/// * `key` - The key chosen by the owner.
+/// * `sender` - The `PeerId` of the peer asking for the transfer.
+/// * `new_owner` - The `PeerId` of the peer to hand the share over to.
+/// * `relay_grant` - The verified grant of the new owner for the provider to relay refreshes.
+/// * `dao` - A shared reference to the DAO trait object.
+///
+/// # Returns
+/// Returns a `Result` indicating the share was handed over, or `NotFound` if the sender has no
+/// live share under `key`, `NotOwner` if the share belongs to another peer, `Conflict` if the new
+/// owner already registered another share under `key`, or the failure of the store.
+pub fn transfer_owned_share(
+    key: &str,
+    sender: &PeerId,
+    new_owner: &PeerId,
+    relay_grant: Option<RelayGrant>,
+    dao: &SharedDao,
+) -> Result<(), Failure> {
+    if new_owner == sender {
+        return Err(Failure::InvalidRequest("new owner is the sender".to_string()));
+    }
+    let Some(mut share_entry) = get_owned_live_entry(sender, key, dao).map_err(storage_failure)?
+    else {
+        return Err(missing_share(&owner_key(&sender.to_bytes(), key), dao));
+    };
+    if !check_share_owner(&share_entry, sender) {
+        return Err(not_owner(sender, &share_entry));
+    }
+    let new_owner = new_owner.to_bytes();
+    share_entry.sender = new_owner.clone();
+    share_entry.readers.retain(|r| *r != new_owner);
+    share_entry.relay_grant = relay_grant;
+    let dao = dao.lock().unwrap();
+    let stored = dao.get_owned(&new_owner, key).map_err(storage_failure)?;
+    match stored.filter(|entry| !entry.is_expired(now_unix())) {
+        Some(entry) if entry == share_entry => {}
+        Some(_) => {
+            return Err(Failure::Conflict(
+                "the new owner already registered another share under the key".to_string(),
+            ))
+        }
+        None => dao.insert_owned(key, &share_entry).map_err(storage_failure)?,
+    }
+    dao.delete_owned(&sender.to_bytes(), key)
+        .map_err(storage_failure)?;
+    dao.flush().map_err(storage_failure)
+}
+
+/// Executes a transfer ownership operation.
+///
+/// Checks the signature of the request, hands the share over with `transfer_owned_share`, and
+/// provides it on the DHT under the record of the new owner instead of the sender's. A relay
+/// grant that the new owner did not sign for this provider is dropped, leaving the provider
+/// unable to coordinate refreshes of the share until the new owner registers it again.
+///
+/// # Arguments
+/// * `request` - The transfer request received.
+/// * `sender` - The `PeerId` of the sender asking for the transfer.
+/// * `channel` - The `ResponseChannel<Response>` to respond on.
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `metrics` - The metrics of the provider.
+/// * `network_client` - A mutable reference to the network client.
+///
+/// # Returns
+/// Returns a `Result` containing the outcome to audit, or the error of a storage failure. A
+/// request that is not signed by the sender, or names a new owner that is not a valid peer id,
+/// is refused as an invalid request.
+pub async fn execute_transfer_ownership(
+    request: &TransferOwnershipRequest,
+    sender: &PeerId,
+    channel: ResponseChannel<Response>,
+    dao: &SharedDao,
+    metrics: &ProviderMetrics,
+    network_client: &mut Client,
+) -> Result<AuditOutcome, Error> {
+    let key = request.key.as_str();
+    let result = request
+        .verify()
+        .map_err(Failure::InvalidRequest)
+        .and_then(|new_owner| {
+            let relay_grant = request.relay_grant.clone().filter(|grant| {
+                grant
+                    .verify(key, &request.peer, &new_owner.to_bytes())
+                    .is_ok()
+            });
+            transfer_owned_share(key, sender, &new_owner, relay_grant, dao).map(|()| new_owner)
+        });
+    match &result {
+        Ok(new_owner) => {
+            network_client
+                .stop_providing(Client::provider_key(sender, key))
+                .await;
+            network_client
+                .start_providing(Client::provider_key(new_owner, key))
+                .await;
+            info!(
+                key, peer = %sender, %new_owner, outcome = "transferred",
+                "Transferred share."
+            );
+        }
+        Err(failure) => warn!(
+            key, peer = %sender, outcome = "refused", reason = %failure,
+            "Refused share transfer."
+        ),
+    }
+    let result = result.map(|_| ());
+    network_client
+        .respond_transfer_ownership(result.clone(), channel)
+        .await;
+    handler_outcome(&result, metrics)
+}
+
+/// The storage engine backing the provider's share DAO.
+///
+/// # Variants
+/// * `Memory` - An in-memory `HashMapShareEntryDao`; shares are lost on restart.
+/// * `Sled` - A `SledShareEntryDao` embedded database.
+/// * `Sqlite` - A `SqliteShareEntryDao` database. Requires the `sqlite` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbBackend {
+    Memory,
+    Sled,
+    Sqlite,
+}
+
+impl std::str::FromStr for DbBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(DbBackend::Memory),
+            "sled" => Ok(DbBackend::Sled),
+            "sqlite" => Ok(DbBackend::Sqlite),
+            other => Err(format!(
+                "unknown database backend {}, expected one of sled, sqlite, memory",
+                other
+            )),
+        }
+    }
+}
+
+/// Options controlling how the provider's share DAO is opened.
+///
+/// # Fields
+/// * `backend` - The storage engine to use. Defaults to sled when `db_path` is set and to memory
+///   otherwise.
+/// * `db_path` - The path to the database. Required by the sled and sqlite backends.
+/// * `encryption_key` - The key used to encrypt entries at rest. Only supported by sled.
+/// * `flush_policy` - When sled flushes writes to disk between ack points. With
+///   `FlushPolicy::Interval`, `run_loop` runs the flush task, which also saves snapshots.
+/// * `snapshot_path` - The file the memory backend snapshots its entries to. Only supported by
+///   the memory backend.
+/// * `quotas` - The storage limits enforced on writes. Not supported by sqlite.
+/// * `read_only` - Open the database without writing to it, for inspection and export. Only
+///   supported by sled; a read-only DAO cannot back a running provider.
+/// * `isolate_owners` - Keep each owner's entries in trees of their own with an
+///   `IsolatedShareEntryDao`. Only supported by sled.
+#[derive(Debug, Clone, Default)]
+pub struct DaoOptions {
+    pub backend: Option<DbBackend>,
+    pub db_path: Option<String>,
+    pub encryption_key: Option<EncryptionKey>,
+    pub flush_policy: FlushPolicy,
+    pub snapshot_path: Option<PathBuf>,
+    pub quotas: DaoQuotas,
+    pub read_only: bool,
+    pub isolate_owners: bool,
+}
+
+/// Opens the SQLite DAO, or fails if the crate was built without SQLite support.
+#[cfg(feature = "sqlite")]
+fn sqlite_dao(db_path: &str) -> Result<SharedDao, Error> {
+    Ok(Arc::new(Mutex::new(Box::new(
+        crate::repository::SqliteShareEntryDao::new(db_path)?,
+    ))))
+}
+
+/// Opens the SQLite DAO, or fails if the crate was built without SQLite support.
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_dao(_db_path: &str) -> Result<SharedDao, Error> {
+    Err(Error::Invalid(
+        "the sqlite backend requires building with the sqlite feature".to_string(),
+    ))
+}
+
+/// Creates and returns a DAO instance based on the specified options.
+///
+/// The backend is picked from `options.backend`; if none is given, a Sled database DAO is
+/// created when a path is provided, and an in-memory HashMap DAO otherwise. Entries stored in an
+/// older schema version, in plaintext when an encryption key is given, or under a key that is not
+/// scoped to their owner are migrated before the DAO is returned, unless it is opened read-only.
+///
+/// # Arguments
+/// * `options` - The `DaoOptions` describing the database to open.
+///
+/// # Returns
+/// Returns a `Result<SharedDao>`, encapsulating the DAO in a
+/// thread-safe, reference-counted pointer, or an `Error::Invalid` if the options cannot be used
+/// together, or an `Error::Storage` if the database cannot be initialized.
+pub fn dao(options: DaoOptions) -> Result<SharedDao, Error> {
+    let invalid = |reason: &str| Err(Error::Invalid(reason.to_string()));
+    let backend = options.backend.unwrap_or(if options.db_path.is_some() {
+        DbBackend::Sled
+    } else {
+        DbBackend::Memory
+    });
+    if options.encryption_key.is_some() && backend != DbBackend::Sled {
+        return invalid("encryption at rest is only supported by the sled backend");
+    }
+    if options.snapshot_path.is_some() && backend != DbBackend::Memory {
+        return invalid("snapshots are only supported by the memory backend");
+    }
+    if options.quotas != DaoQuotas::default() && backend == DbBackend::Sqlite {
+        return invalid("quotas are not supported by the sqlite backend");
+    }
+    if options.read_only && backend != DbBackend::Sled {
+        return invalid("read-only mode is only supported by the sled backend");
+    }
+    if options.isolate_owners && backend != DbBackend::Sled {
+        return invalid("owner isolation is only supported by the sled backend");
+    }
+
+    let dao: SharedDao = match (backend, options.db_path) {
+        (DbBackend::Memory, None) => {
+            debug!("Using HashMap DB");
+            let memory_dao = match options.snapshot_path {
+                Some(snapshot_path) => HashMapShareEntryDao::new_with_snapshot(&snapshot_path),
+                None => HashMapShareEntryDao::new(),
+            };
+            Arc::new(Mutex::new(Box::new(memory_dao.with_quotas(options.quotas))))
+        }
+        (DbBackend::Memory, Some(_)) => {
+            return invalid("the memory backend does not take a database path");
+        }
+        (DbBackend::Sled, Some(db_path)) if options.isolate_owners => {
+            debug!("Using Sled DB with a tree per owner");
+            let isolated_dao = if options.read_only {
+                IsolatedShareEntryDao::open_read_only(&db_path, options.encryption_key)?
+            } else {
+                IsolatedShareEntryDao::new(&db_path, options.encryption_key)?
+            };
+            Arc::new(Mutex::new(Box::new(
+                isolated_dao
+                    .with_flush_policy(options.flush_policy)
+                    .with_quotas(options.quotas),
+            )))
+        }
+        (DbBackend::Sled, Some(db_path)) => {
+            debug!("Using Sled DB");
+            let sled_dao = match (options.encryption_key, options.read_only) {
+                (Some(encryption_key), true) => {
+                    SledShareEntryDao::open_read_only_encrypted(&db_path, encryption_key)?
+                }
+                (Some(encryption_key), false) => {
+                    debug!("Encrypting entries with key {:?}", encryption_key);
+                    SledShareEntryDao::new_encrypted(&db_path, encryption_key)?
+                }
+                (None, true) => SledShareEntryDao::open_read_only(&db_path)?,
+                (None, false) => SledShareEntryDao::new(&db_path)?,
+            };
+            Arc::new(Mutex::new(Box::new(
+                sled_dao
+                    .with_flush_policy(options.flush_policy)
+                    .with_quotas(options.quotas),
+            )))
+        }
+        (DbBackend::Sqlite, Some(db_path)) => {
+            debug!("Using SQLite DB");
+            sqlite_dao(&db_path)?
+        }
+        (backend, None) => {
+            return invalid(&format!("the {:?} backend requires a database path", backend));
+        }
+    };
+
+    if options.read_only {
+        return Ok(dao);
+    }
+    let migrated = dao.lock().unwrap().migrate_all()?;
+    if migrated > 0 {
+        info!("Migrated {} share entries to the current schema.", migrated);
+    }
+    let moved = dao.lock().unwrap().migrate_owner_keys()?;
+    if moved > 0 {
+        info!(
+            "Moved {} share entries into their owner's namespace.",
+            moved
+        );
+    }
+    Ok(dao)
+}
+
+/// Checks that a DAO can back a running provider, which must be able to store registrations and
+/// refreshes.
+///
+/// # Arguments
+/// * `dao` - A shared reference to the DAO trait object.
+///
+/// # Returns
+/// Returns a `Result<(), Error>`, failing with `Error::Invalid` if the DAO is read-only.
+pub fn ensure_writable(dao: &SharedDao) -> Result<(), Error> {
+    if dao.lock().unwrap().is_read_only() {
+        return Err(Error::Invalid(
+            "the share database is open read-only; a provider needs a writable database"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// How the integrity scan classified the entries of a share database.
+///
+/// # Fields
+/// * `ok` - The number of entries that read back with a valid owner.
+/// * `corrupt` - The keys of the entries that could not be read.
+/// * `bad_owner` - The keys of the entries whose owner is not a valid peer id.
+/// * `quarantined` - The number of entries quarantined by earlier scans.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    pub ok: usize,
+    pub corrupt: Vec<String>,
+    pub bad_owner: Vec<String>,
+    pub quarantined: usize,
+}
+
+impl IntegrityReport {
+    /// Whether the scan found no entry to quarantine.
+    pub fn is_clean(&self) -> bool {
+        self.corrupt.is_empty() && self.bad_owner.is_empty()
+    }
+}
+
+impl std::fmt::Display for IntegrityReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ok, {} corrupt, {} with an unparsable owner, {} quarantined before",
+            self.ok,
+            self.corrupt.len(),
+            self.bad_owner.len(),
+            self.quarantined
+        )
+    }
+}
+
+/// Reads back every entry of the share database, so that entries damaged by a crash or a disk
+/// fault are found at startup rather than by the refresh task or a handler.
+///
+/// Each entry is classified as ok, corrupt if it cannot be read, or as having a bad owner if its
+/// sender is not a valid peer id. With `quarantine` set, the bad entries are moved into the DAO's
+/// quarantine, where their values are kept as stored; requests for them are then refused with
+/// `Failure::Corrupt`. Nothing is ever deleted.
+///
+/// # Arguments
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `quarantine` - Whether to quarantine the bad entries, or only report them.
+///
+/// # Returns
+/// Returns a `Result` containing the `IntegrityReport`, or the error of a failed listing or
+/// quarantine.
+pub fn scan_integrity(
+    dao: &SharedDao,
+    quarantine: bool,
+) -> Result<IntegrityReport, RepoError> {
+    let mut report = IntegrityReport {
+        quarantined: dao.lock().unwrap().quarantined_keys()?.len(),
+        ..Default::default()
+    };
+    let mut after: Option<String> = None;
+    loop {
+        let keys = dao
+            .lock()
+            .unwrap()
+            .keys_page(after.as_deref(), DAO_PAGE_SIZE)?;
+        let last_page = keys.len() < DAO_PAGE_SIZE;
+        for key in &keys {
+            let read = dao.lock().unwrap().get(key);
+            let problem = match read {
+                Ok(None) => continue,
+                Ok(Some(entry)) => match PeerId::from_bytes(&entry.sender) {
+                    Ok(_) => {
+                        report.ok += 1;
+                        continue;
+                    }
+                    Err(e) => {
+                        report.bad_owner.push(key.clone());
+                        format!("unparsable owner: {}", e)
+                    }
+                },
+                Err(e) => {
+                    report.corrupt.push(key.clone());
+                    e.to_string()
+                }
+            };
+            if quarantine {
+                dao.lock().unwrap().quarantine(key)?;
+                error!(key, problem, "Quarantined share.");
+            } else {
+                error!(key, problem, "Share is damaged.");
+            }
+        }
+        after = keys.last().cloned();
+        if last_page {
+            return Ok(report);
+        }
+    }
+}
+
+/// A task aborted when dropped, so that the tasks `run_with_dao` and `ShardNode` spawn stop
+/// with them when they are dropped rather than running on unowned.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// The settings of a running provider, each falling back to its default when unset. The
+/// `ShardNode` builder fills them in from its own settings.
+///
+/// # Fields
+/// * `audit` - The audit log to record operations in, if auditing is enabled.
+/// * `metrics` - The metrics the handlers and the refresh task update, published in the health
+///   status.
+/// * `events` - The channel to publish the `ProviderEvent`s of the handlers and the refresh task
+///   to, or `None` to publish none (see `publish_event`).
+/// * `refresh` - The refresh interval in seconds.
+/// * `refresh_jitter` - The spread of the refresh schedule in percent (see `RefreshSchedule`).
+/// * `replication_margin` - The number of providers beyond its threshold each share should have
+///   (see `replication_alert`).
+/// * `status_interval` - The duration in seconds between each health status published.
+/// * `max_share_bytes` - The largest share accepted for registration.
+/// * `rate_limit` - The allowance of each peer for each operation, or `None` to accept every
+///   request (see `RateLimiter`).
+/// * `shutdown_grace` - The number of seconds to wait for the refresh in flight once the provider
+///   is asked to stop.
+#[derive(Clone, Default)]
+pub struct ProviderOptions {
+    pub audit: SharedAudit,
+    pub metrics: SharedMetrics,
+    pub events: ProviderEvents,
+    pub refresh: Option<u64>,
+    pub refresh_jitter: Option<u8>,
+    pub replication_margin: Option<u64>,
+    pub status_interval: Option<u64>,
+    pub max_share_bytes: Option<usize>,
+    pub rate_limit: Option<RateLimit>,
+    pub shutdown_grace: Option<u64>,
+}
+
+/// Runs the main event loop asynchronously.
+///
+/// This function initializes the DAO, quarantines the entries the integrity scan finds damaged
+/// (see `scan_integrity`), and starts periodic refresh and expiry purge tasks. It also listens for
+/// incoming network events and handles them appropriately. The refresh task is restarted if it ever
+/// stops, so that shares never silently stop being refreshed.
+///
+/// Once `shutdown` is cancelled, the loop stops taking requests on and answers them with `Busy`
+/// until the refresh in flight, if any, finishes, abandoning it once the grace period passes. It
+/// then flushes the DAO and audit log, saving the snapshot of an in-memory DAO, stops the network
+/// event loop, and returns. Supervisors can follow the provider through the `state` field of the
+/// `ready`, `stopping` and `stopped` log lines.
+///
+/// # Arguments
+/// * `dao_options` - The `DaoOptions` describing the database to open.
+/// * `options` - The `ProviderOptions` of the provider.
+/// * `local_peer_id` - The `PeerId` of the local node.
+/// * `network_client` - A mutable reference to the network client.
+/// * `network_events` - A stream of network events to listen to.
+/// * `shutdown` - The token cancelled to stop the provider.
+///
+/// # Returns
+/// Once the provider stopped, or an error if the DAO cannot be opened, is not writable or fails
+/// its integrity scan, in which case no share is provided.
+pub async fn run_loop(
+    dao_options: DaoOptions,
+    options: ProviderOptions,
+    local_peer_id: PeerId,
+    network_client: &mut Client,
+    network_events: impl Stream<Item = Event> + Unpin,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let flush_policy = dao_options.flush_policy;
+    // check if the db_path is set, if so use sled, otherwise use HashMap
+    let dao: SharedDao = dao(dao_options)?;
+    run_with_dao(
+        dao,
+        flush_policy,
+        options,
+        local_peer_id,
+        network_client,
+        network_events,
+        shutdown,
+    )
+    .await
+}
+
+/// Like `run_loop`, providing the shares of a DAO already opened, which the caller can keep a
+/// handle on, as the `testing` harness does to inspect what each provider stores.
+///
+/// # Arguments
+/// * `dao` - The DAO to store the shares in.
+/// * `flush_policy` - The flush policy `dao` was opened with. With `FlushPolicy::Interval`, the
+///   flush task is run.
+///
+/// The other arguments and the result are those of `run_loop`.
+pub async fn run_with_dao(
+    dao: SharedDao,
+    flush_policy: FlushPolicy,
+    options: ProviderOptions,
+    local_peer_id: PeerId,
+    network_client: &mut Client,
+    mut network_events: impl Stream<Item = Event> + Unpin,
+    shutdown: CancellationToken,
+) -> Result<(), Error> {
+    let ProviderOptions {
+        audit,
+        metrics,
+        events,
+        refresh,
+        refresh_jitter,
+        replication_margin,
+        status_interval,
+        max_share_bytes,
+        rate_limit,
+        shutdown_grace,
+    } = options;
+    if let Err(e) = ensure_writable(&dao) {
+        error!("Refusing to provide shares: {e}");
+        return Err(e);
+    }
+    match scan_integrity(&dao, true) {
+        Ok(report) if report.is_clean() => info!("Integrity scan: {}.", report),
+        Ok(report) => warn!(%report, "Integrity scan quarantined damaged shares."),
+        Err(e) => {
+            error!("Refusing to provide shares, the integrity scan failed: {e}");
+            return Err(e.into());
+        }
+    }
+
+    // check if refresh is set, if not use a default of 30 minutes
+    let refresh = refresh.unwrap_or(DEFAULT_REFRESH_SECONDS);
+    debug!("Using refresh_seconds: {}", refresh);
+    let schedule = RefreshSchedule::new(
+        refresh,
+        refresh_jitter.unwrap_or(DEFAULT_REFRESH_JITTER_PERCENT),
+    )
+    .with_replication_margin(replication_margin.unwrap_or(DEFAULT_REPLICATION_MARGIN));
+
+    // spawn a refresh task checking for due shares every tick, restarted if it ever stops, and
+    // stopped mid-pass if a shutdown outlasts its grace period
+    let dao_clone = Arc::clone(&dao);
+    let audit_clone = audit.clone();
+    let metrics_clone = Arc::clone(&metrics);
+    let events_clone = events.clone();
+    let network_client_clone = network_client.clone();
+    let shutdown_clone = shutdown.clone();
+    let abandon = CancellationToken::new();
+    let abandon_clone = abandon.clone();
+    let mut refresh_task = AbortOnDrop(spawn(async move {
+        loop {
+            let dao_clone = Arc::clone(&dao_clone);
+            let audit_clone = audit_clone.clone();
+            let metrics_clone = Arc::clone(&metrics_clone);
+            let events_clone = events_clone.clone();
+            let mut network_client_clone = network_client_clone.clone();
+            let shutdown = shutdown_clone.clone();
+            let mut refresh_task = AbortOnDrop(spawn(async move {
+                let mut interval = schedule.ticker(&mut rand::thread_rng());
+                refresh_loop(
+                    &mut interval,
+                    schedule,
+                    dao_clone,
+                    audit_clone,
+                    metrics_clone,
+                    events_clone,
+                    &mut network_client_clone,
+                    local_peer_id,
+                    &shutdown,
+                )
+                .await;
+            }));
+            let stopped = tokio::select! {
+                stopped = &mut refresh_task.0 => stopped,
+                _ = abandon_clone.cancelled() => return,
+            };
+            match stopped {
+                Ok(()) if shutdown_clone.is_cancelled() => return,
+                Ok(()) => error!("Refresh task stopped, restarting it."),
+                Err(e) => error!("Refresh task died, restarting it: {e}"),
+            }
+            tokio::select! {
+                _ = runtime::sleep(Duration::from_secs(REFRESH_RETRY_SECONDS)) => {}
+                _ = shutdown_clone.cancelled() => return,
+            }
+        }
+    }));
+
+    // spawn a purge task to destroy expired shares
+    let dao_clone = Arc::clone(&dao);
+    let audit_clone = audit.clone();
+    let mut network_client_clone = network_client.clone();
+    let purge_task = AbortOnDrop(spawn(async move {
+        let mut interval = runtime::interval(Duration::from_secs(DEFAULT_PURGE_SECONDS));
+        purge_loop(
+            &mut interval,
+            dao_clone,
+            audit_clone,
+            &mut network_client_clone,
+        )
+        .await;
+    }));
+
+    // spawn a status task publishing the health of the provider
+    let dao_clone = Arc::clone(&dao);
+    let metrics_clone = Arc::clone(&metrics);
+    let mut network_client_clone = network_client.clone();
+    let status_interval = status_interval.unwrap_or(DEFAULT_STATUS_SECONDS).max(1);
+    let status_task = AbortOnDrop(spawn(async move {
+        let mut interval = runtime::interval(Duration::from_secs(status_interval));
+        status_loop(
+            &mut interval,
+            dao_clone,
+            metrics_clone,
+            local_peer_id,
+            &mut network_client_clone,
+        )
+        .await;
+    }));
+
+    // spawn a flush task when the DAO leaves flushing to a timer
+    let flush_task = match flush_policy {
+        FlushPolicy::Interval(period) => {
+            let dao_clone = Arc::clone(&dao);
+            Some(AbortOnDrop(spawn(async move {
+                let mut interval = runtime::interval(period);
+                flush_loop(&mut interval, dao_clone).await;
+            })))
+        }
+        _ => None,
+    };
+
+    let max_share_bytes = max_share_bytes.unwrap_or(DEFAULT_MAX_SHARE_BYTES);
+    let mut limiter = RateLimiter::new(rate_limit, local_peer_id);
+    metrics.set_ready(true);
+    info!(state = "ready", peer_id = %local_peer_id, "Provider ready.");
+    loop {
+        let event = tokio::select! {
+            event = network_events.next() => event,
+            _ = shutdown.cancelled() => break,
+        };
+        match event {
+            // Reply with the content of the file on incoming requests.
+            Some(Event::InboundRequest {
+                request,
+                peer,
+                channel,
+            }) => {
+                let (operation, key) = audit_summary(&request);
+                let span = info_span!(
+                    "request",
+                    trace_id = request.trace_id().map(display),
+                    key,
+                    requester = %peer,
+                    %operation
+                );
+                let handled = handle_request(
+                    request,
+                    peer,
+                    channel,
+                    &dao,
+                    &audit,
+                    &metrics,
+                    &events,
+                    max_share_bytes,
+                    &mut limiter,
+                    network_client,
+                )
+                .instrument(span.clone())
+                .await;
+                // the requester was answered with the failure, and the provider carries on
+                if let Err(e) = handled {
+                    span.in_scope(|| error!("Failed to handle a request from {}: {}", peer, e));
+                }
+            }
+            e => debug!("unhandled client event: {e:?}"),
+        }
+    }
+
+    let grace = Duration::from_secs(shutdown_grace.unwrap_or(DEFAULT_SHUTDOWN_GRACE_SECONDS));
+    metrics.set_ready(false);
+    info!(
+        state = "stopping",
+        grace_secs = grace.as_secs(),
+        "Shutting down, waiting for the refresh in flight."
+    );
+    drop((purge_task, status_task, flush_task));
+    let grace_period = runtime::sleep(grace);
+    tokio::pin!(grace_period);
+    loop {
+        tokio::select! {
+            _ = &mut refresh_task.0 => break,
+            _ = &mut grace_period => {
+                warn!("The refresh in flight outlasted the grace period, abandoning it.");
+                abandon.cancel();
+                let _ = (&mut refresh_task.0).await;
+                break;
+            }
+            event = network_events.next() => match event {
+                Some(Event::InboundRequest { channel, .. }) => {
+                    network_client
+                        .respond_busy(SHUTTING_DOWN.to_string(), channel)
+                        .await;
+                }
+                Some(e) => debug!("unhandled client event: {e:?}"),
+                None => {
+                    let _ = (&mut refresh_task.0).await;
+                    break;
+                }
+            },
+        }
+    }
+    flush_on_shutdown(&dao, &audit);
+    network_client.shutdown().await;
+    info!(state = "stopped", "Provider stopped.");
+    Ok(())
+}
+
+/// Periodically refreshes shares in a separate asynchronous task.
+///
+/// This function checks the shares in the database on every tick of `interval` and refreshes
+/// those that are due. It also communicates with other peers in the network to synchronize the
+/// refreshed shares. A share is due once its own refresh interval, or the default interval of
+/// `schedule` if its owner chose none, has passed since its last refresh, whichever provider made
+/// it, give or take the jitter of `schedule`. A share is only refreshed by its coordinator (see
+/// `RefreshCoordinator`).
+///
+/// Failures never end the loop: shares that cannot be read are skipped and reported, and a pass
+/// that cannot list the shares is retried with a growing delay before waiting for the next tick.
+/// The loop returns once `shutdown` is cancelled, after the pass in flight, if any, finishes.
+///
+/// # Arguments
+/// * `interval` - A mutable reference to the time interval the shares are checked on.
+/// * `schedule` - When shares are due and how the refreshes of a pass are spread out.
+/// * `dao_clone` - A cloned reference to the DAO, wrapped in an Arc and Mutex.
+/// * `audit` - The audit log to record the refreshes in, if auditing is enabled.
+/// * `metrics` - The metrics to count the refreshes and the age of the oldest share in.
+/// * `events` - The channel to publish the refreshes to, if any.
+/// * `network_client_clone` - A cloned mutable reference to the network client.
+/// * `local_peer_id` - The `PeerId` of the local node.
+/// * `shutdown` - The token cancelled to stop the loop.
+#[allow(clippy::too_many_arguments)]
+pub async fn refresh_loop(
+    interval: &mut Interval,
+    schedule: RefreshSchedule,
+    dao_clone: SharedDao,
+    audit: SharedAudit,
+    metrics: SharedMetrics,
+    events: ProviderEvents,
+    network_client_clone: &mut Client,
+    local_peer_id: PeerId,
+    shutdown: &CancellationToken,
+) {
+    let mut coordinator = RefreshCoordinator::new(local_peer_id, schedule.default_interval);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.cancelled() => return,
+        }
+        debug!("Starting refresh.");
+
+        let mut retry = Duration::from_secs(REFRESH_RETRY_SECONDS);
+        for attempt in 1..=REFRESH_READ_ATTEMPTS {
+            let pass = refresh_pass(
+                &schedule,
+                &dao_clone,
+                &audit,
+                &metrics,
+                &events,
+                network_client_clone,
+                &mut coordinator,
+            )
+            .await;
+            match pass {
+                Ok(skipped) => {
+                    if !skipped.is_empty() {
+                        error!(
+                            "Skipped {} shares that could not be read, run an integrity scan: {:?}",
+                            skipped.len(),
+                            skipped
+                        );
+                    }
+                    break;
+                }
+                Err(e) if attempt < REFRESH_READ_ATTEMPTS => {
+                    error!("Failed to read shares to refresh, retrying in {retry:?}: {e}");
+                    tokio::select! {
+                        _ = runtime::sleep(retry) => {}
+                        _ = shutdown.cancelled() => return,
+                    }
+                    retry *= 2;
+                }
+                Err(e) => {
+                    error!("Failed to read shares to refresh, waiting for the next tick: {e}")
+                }
+            }
+        }
+    }
+}
+
+/// Describes when the refresh task checks for due shares and how it spreads its work, so that
+/// providers started together do not refresh in lockstep.
+///
+/// # Fields
+/// * `default_interval` - The refresh interval in seconds of shares that do not set their own.
+/// * `jitter_percent` - How far, in percent of its interval, the due time of each share is moved
+///   either way, and how much of a tick the first check is delayed by at most.
+/// * `key_delay` - The pause between two due shares within a pass.
+/// * `replication_margin` - How many providers beyond its threshold a due share should have
+///   before an `UnderReplicated` alert is raised for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefreshSchedule {
+    pub default_interval: u64,
+    pub jitter_percent: u8,
+    pub key_delay: Duration,
+    pub replication_margin: u64,
+}
+
+impl RefreshSchedule {
+    /// Creates a schedule pausing `REFRESH_KEY_DELAY_MILLIS` between due shares and asking for
+    /// `DEFAULT_REPLICATION_MARGIN` providers beyond the threshold of each share.
+    ///
+    /// # Arguments
+    /// * `default_interval` - The refresh interval in seconds of shares that do not set their own.
+    /// * `jitter_percent` - The spread of the schedule in percent, capped at 100.
+    pub fn new(default_interval: u64, jitter_percent: u8) -> Self {
+        RefreshSchedule {
+            default_interval,
+            jitter_percent: jitter_percent.min(100),
+            key_delay: Duration::from_millis(REFRESH_KEY_DELAY_MILLIS),
+            replication_margin: DEFAULT_REPLICATION_MARGIN,
+        }
+    }
+
+    /// Sets how many providers beyond its threshold a share should have.
+    ///
+    /// # Arguments
+    /// * `margin` - The number of spare providers.
+    pub fn with_replication_margin(mut self, margin: u64) -> Self {
+        self.replication_margin = margin;
+        self
+    }
+
+    /// Returns the period of the checks for due shares, at least as short as the default refresh
+    /// interval.
+    pub fn tick(&self) -> Duration {
+        Duration::from_secs(REFRESH_TICK_SECONDS.min(self.default_interval).max(1))
+    }
+
+    /// Creates the interval the refresh task checks for due shares on. The first check is delayed
+    /// by a random part of `jitter_percent` of a tick.
+    ///
+    /// # Arguments
+    /// * `rng` - The source of the delay of the first check.
+    pub fn ticker(&self, rng: &mut impl Rng) -> Interval {
+        let tick = self.tick();
+        let delay = tick.mul_f64(rng.gen_range(0.0..=1.0) * self.jitter_percent as f64 / 100.0);
+        runtime::interval_at(runtime::Instant::now() + delay, tick)
+    }
+
+    /// Computes the number of seconds after its last refresh at which a share is due, moved by up
+    /// to `jitter_percent` of its interval either way.
+    ///
+    /// The offset is derived from the key and the time of the last refresh, so it stays the same
+    /// between checks and changes with every refresh.
+    ///
+    /// # Arguments
+    /// * `key` - The key the share is stored under.
+    /// * `share_entry` - The stored share.
+    pub fn due_after(&self, key: &str, share_entry: &ShareEntry) -> u64 {
+        let interval = share_entry.refresh_interval(self.default_interval);
+        let spread = interval.saturating_mul(self.jitter_percent as u64) / 100;
+        if spread == 0 {
+            return interval;
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(key.as_bytes());
+        hasher.update(share_entry.last_refreshed_unix.to_be_bytes());
+        let digest = hasher.finalize();
+        let mut seed = [0u8; 8];
+        seed.copy_from_slice(&digest[..8]);
+        let offset = u64::from_be_bytes(seed) % (2 * spread + 1);
+        (interval - spread).saturating_add(offset)
+    }
+
+    /// Checks whether a share is due for a refresh (see `due_after`).
+    ///
+    /// # Arguments
+    /// * `key` - The key the share is stored under.
+    /// * `share_entry` - The stored share.
+    /// * `now` - The current unix timestamp in seconds.
+    pub fn is_due(&self, key: &str, share_entry: &ShareEntry, now: u64) -> bool {
+        now.saturating_sub(share_entry.last_refreshed_unix) >= self.due_after(key, share_entry)
+    }
+}
+
+/// Walks the share database once, refreshing every share that is due.
+///
+/// Keys are listed a page at a time and each entry is read on its own, so an entry that fails to
+/// decode, or whose owner is not a valid `PeerId`, is skipped without holding up the others. Due
+/// shares are handled one at a time, `key_delay` apart, so at most the fan-out of one share is in
+/// flight. Once every share is walked, the age of the least recently refreshed live share is
+/// recorded in the metrics, counting shares refreshed by the pass as refreshed now.
+///
+/// # Arguments
+/// * `schedule` - When shares are due and how the refreshes of the pass are spread out.
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `audit` - The audit log to record the refreshes in, if auditing is enabled.
+/// * `metrics` - The metrics to count the refreshes and the age of the oldest share in.
+/// * `events` - The channel to publish the refreshes to, if any.
+/// * `network_client` - A mutable reference to the network client.
+/// * `coordinator` - Decides whether the local node refreshes each share.
+///
+/// # Returns
+/// Returns the keys of the shares that were skipped, or an error if the keys could not be listed.
+async fn refresh_pass(
+    schedule: &RefreshSchedule,
+    dao: &SharedDao,
+    audit: &SharedAudit,
+    metrics: &ProviderMetrics,
+    events: &ProviderEvents,
+    network_client: &mut Client,
+    coordinator: &mut RefreshCoordinator,
+) -> Result<Vec<String>, String> {
+    let mut skipped = Vec::new();
+    let mut oldest_refresh: Option<u64> = None;
+    let mut cursor: Option<String> = None;
+    loop {
+        // the DAO lock is only held while a page of keys or a single entry is read, leaving
+        // expired shares to the purge task
+        let keys = dao
+            .lock()
+            .map_err(|e| e.to_string())?
+            .keys_page(cursor.as_deref(), DAO_PAGE_SIZE)
+            .map_err(|e| e.to_string())?;
+        let Some(last_key) = keys.last() else {
+            break;
+        };
+        cursor = Some(last_key.clone());
+
+        for key in &keys {
+            let read = dao
+                .lock()
+                .map_err(|e| e.to_string())?
+                .get(key)
+                .map_err(|e| e.to_string());
+            let share_entry = match read {
+                Ok(Some(share_entry)) => share_entry,
+                // deleted since the keys were listed
+                Ok(None) => continue,
+                Err(e) => {
+                    error!("Could not read share {key} to refresh it: {e}");
+                    skipped.push(key.clone());
+                    continue;
+                }
+            };
+            let Ok(sender) = PeerId::from_bytes(&share_entry.sender) else {
+                error!("Share {key} has an owner that is not a valid peer id.");
+                skipped.push(key.clone());
+                continue;
+            };
+
+            let now = now_unix();
+            if share_entry.is_expired(now) {
+                continue;
+            }
+            let mut last_refreshed = share_entry.last_refreshed_unix;
+            if schedule.is_due(key, &share_entry, now) {
+                let refreshed = refresh_entry(
+                    key,
+                    sender,
+                    &share_entry,
+                    schedule.replication_margin,
+                    dao,
+                    audit,
+                    metrics,
+                    events,
+                    network_client,
+                    coordinator,
+                )
+                .await;
+                if refreshed {
+                    last_refreshed = now;
+                }
+                if !schedule.key_delay.is_zero() {
+                    runtime::sleep(schedule.key_delay).await;
+                }
+            }
+            oldest_refresh = Some(oldest_refresh.map_or(last_refreshed, |t| t.min(last_refreshed)));
+        }
+        if keys.len() < DAO_PAGE_SIZE {
+            break;
+        }
+    }
+    let now = now_unix();
+    metrics.set_oldest_refresh_age(oldest_refresh.map(|t| now.saturating_sub(t)));
+    Ok(skipped)
+}
+
+/// Checks whether a share has fewer providers than its threshold plus a safety margin.
+///
+/// Providers cannot recreate the shares of other providers, so the alert is all they can do: it
+/// names the share and its owner, who can register the share with more providers.
+///
+/// # Arguments
+/// * `key` - The key the owner registered the share under.
+/// * `owner` - The owner of the share.
+/// * `share_entry` - The stored share.
+/// * `providers` - The providers of the share, including the local node.
+/// * `margin` - How many providers beyond its threshold the share should have.
+///
+/// # Returns
+/// Returns the alert to publish, or `None` if the share has enough providers.
+pub fn replication_alert(
+    key: &str,
+    owner: &PeerId,
+    share_entry: &ShareEntry,
+    providers: &[PeerId],
+    margin: u64,
+) -> Option<UnderReplicatedAlert> {
+    let required = share_entry.threshold.saturating_add(margin);
+    let found = providers.iter().collect::<HashSet<_>>().len() as u64;
+    (found < required).then(|| UnderReplicatedAlert {
+        key: key.to_string(),
+        owner: owner.to_bytes(),
+        providers: found,
+        required,
+    })
+}
+
+/// Generates a refresh key for a stored share.
+///
+/// The polynomials take their degree from the threshold the share was registered with, so that a
+/// refresh keeps the secret recoverable from exactly `threshold` shares. One polynomial is
+/// generated per field element of the share, over the field its secret was split over.
+///
+/// # Arguments
+/// * `share_entry` - The stored share.
+///
+/// # Returns
+/// Returns a `Result` containing the refresh key, or an error if the threshold is below 2.
+pub fn refresh_key_for(share_entry: &ShareEntry) -> Result<RefreshKey, sss::Error> {
+    let threshold = share_entry.threshold as usize;
+    #[cfg(feature = "sss16")]
+    if share_entry.field == ShareField::Gf65536 {
+        let elements = share_entry.share.1.len() / 2;
+        return sss16::generate_refresh_key(threshold, elements).map(RefreshKey::from);
+    }
+    generate_refresh_key(threshold, share_entry.share.1.len()).map(RefreshKey::from)
+}
+
+/// Ranks the providers of a share by their XOR distance to the record of the share, closest
+/// first. The closest provider is the share's refresh coordinator.
+///
+/// # Arguments
+/// * `record` - The DHT record the share is provided under (see `Client::provider_key`).
+/// * `providers` - The providers of the share. Duplicates are ranked once.
+///
+/// # Returns
+/// Returns the providers in coordination order.
+pub fn refresh_order(record: &str, providers: &[PeerId]) -> Vec<PeerId> {
+    let target = Sha256::digest(record.as_bytes());
+    let mut ranked: Vec<([u8; 32], PeerId)> = providers
+        .iter()
+        .map(|peer| {
+            let hash = Sha256::digest(&peer.to_bytes());
+            let mut distance = [0u8; 32];
+            for (i, byte) in distance.iter_mut().enumerate() {
+                *byte = hash[i] ^ target[i];
+            }
+            (distance, *peer)
+        })
+        .collect();
+    ranked.sort();
+    ranked.dedup();
+    ranked.into_iter().map(|(_, peer)| peer).collect()
+}
+
+/// Decides which shares the local provider starts network refreshes for.
+///
+/// Only the coordinator of a share, the provider first in `refresh_order`, refreshes it each
+/// interval; the others apply the refresh keys it sends. If the coordinator stops refreshing, the
+/// provider ranked `r` takes over once the share has gone `r * REFRESH_TAKEOVER_INTERVALS` of its
+/// refresh intervals without a refresh, so a failed coordinator is replaced by exactly one successor. A
+/// successor keeps coordinating until it sees a refresh it did not start.
+///
+/// # Fields
+/// * `local_peer_id` - The `PeerId` of the local node.
+/// * `interval` - The refresh interval in seconds of shares that do not set their own.
+/// * `taken_over` - The epoch the local node last refreshed each share it took over to, by record.
+#[derive(Debug)]
+pub struct RefreshCoordinator {
+    local_peer_id: PeerId,
+    interval: u64,
+    taken_over: HashMap<String, u64>,
+}
+
+impl RefreshCoordinator {
+    /// Creates a coordinator for the local node.
+    ///
+    /// # Arguments
+    /// * `local_peer_id` - The `PeerId` of the local node.
+    /// * `interval` - The refresh interval in seconds of shares that do not set their own.
+    pub fn new(local_peer_id: PeerId, interval: u64) -> Self {
+        RefreshCoordinator {
+            local_peer_id,
+            interval,
+            taken_over: HashMap::new(),
+        }
+    }
+
+    /// Decides whether the local provider starts a network refresh of a share that is due.
+    ///
+    /// # Arguments
+    /// * `record` - The DHT record the share is provided under.
+    /// * `providers` - The known providers of the share; the local node is ranked even if
+    ///   missing.
+    /// * `share_entry` - The stored share.
+    /// * `now` - The current unix timestamp in seconds.
+    ///
+    /// # Returns
+    /// Returns `true` if the local provider should refresh the share now.
+    pub fn should_initiate(
+        &mut self,
+        record: &str,
+        providers: &[PeerId],
+        share_entry: &ShareEntry,
+        now: u64,
+    ) -> bool {
+        let rank = self.rank(record, providers);
+        if rank == 0 {
+            self.taken_over.remove(record);
+            return true;
+        }
+        if self.taken_over.get(record) == Some(&share_entry.epoch) {
+            return true;
+        }
+        self.taken_over.remove(record);
+        let age = now.saturating_sub(share_entry.last_refreshed_unix);
+        age >= rank
+            .saturating_mul(REFRESH_TAKEOVER_INTERVALS)
+            .saturating_mul(share_entry.refresh_interval(self.interval))
+    }
+
+    /// Records that the local provider refreshed a share to `epoch`, so that it keeps
+    /// coordinating a share it took over.
+    ///
+    /// # Arguments
+    /// * `record` - The DHT record the share is provided under.
+    /// * `providers` - The known providers of the share.
+    /// * `epoch` - The epoch the share was refreshed to.
+    pub fn initiated(&mut self, record: &str, providers: &[PeerId], epoch: u64) {
+        if self.rank(record, providers) > 0 {
+            self.taken_over.insert(record.to_string(), epoch);
+        }
+    }
+
+    /// Computes the position of the local node in the `refresh_order` of a share.
+    fn rank(&self, record: &str, providers: &[PeerId]) -> u64 {
+        let mut candidates = providers.to_vec();
+        candidates.push(self.local_peer_id);
+        refresh_order(record, &candidates)
+            .iter()
+            .position(|peer| *peer == self.local_peer_id)
+            .unwrap_or(0) as u64
+    }
+}
+
+/// How many requests of one operation a rate-limited provider accepts from a peer: a burst of
+/// `burst` requests, after which the allowance refills at `per_second` requests a second.
+///
+/// # Fields
+/// * `burst` - The most requests of an operation a peer can send at once.
+/// * `per_second` - The rate the allowance of a peer refills at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+/// Throttles the inbound requests of each peer with a token bucket per peer and operation, so
+/// that a peer passing the ownership checks still cannot saturate the share database.
+///
+/// # Fields
+/// * `limit` - The allowance of each peer, or `None` to accept every request.
+/// * `exempt` - The `PeerId` of the local node, whose own requests are never throttled.
+/// * `buckets` - The tokens left to each peer for each operation, and when they were counted.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: Option<RateLimit>,
+    exempt: PeerId,
+    buckets: HashMap<(PeerId, AuditOperation), (f64, Instant)>,
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter for the local node.
+    ///
+    /// # Arguments
+    /// * `limit` - The allowance of each peer, or `None` to accept every request.
+    /// * `local_peer_id` - The `PeerId` of the local node, exempt from the limit.
+    pub fn new(limit: Option<RateLimit>, local_peer_id: PeerId) -> Self {
+        RateLimiter {
+            limit,
+            exempt: local_peer_id,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Takes a token from the bucket of `peer` for `operation`.
+    ///
+    /// # Arguments
+    /// * `peer` - The authenticated `PeerId` the request arrived from.
+    /// * `operation` - The operation requested.
+    /// * `now` - The current time.
+    ///
+    /// # Returns
+    /// Returns `Ok(())` if the request is accepted, or the time until the peer may send the
+    /// operation again.
+    pub fn check(
+        &mut self,
+        peer: &PeerId,
+        operation: AuditOperation,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let Some(limit) = self.limit else {
+            return Ok(());
+        };
+        if *peer == self.exempt {
+            return Ok(());
+        }
+        let burst = limit.burst.max(1) as f64;
+        if self.buckets.len() >= MAX_RATE_LIMIT_BUCKETS {
+            // a peer whose bucket refilled is indistinguishable from one never seen
+            self.buckets.retain(|_, (tokens, counted)| {
+                *tokens + now.duration_since(*counted).as_secs_f64() * limit.per_second < burst
+            });
+        }
+        let (tokens, counted) = self
+            .buckets
+            .entry((*peer, operation))
+            .or_insert((burst, now));
+        let refilled = now.saturating_duration_since(*counted).as_secs_f64() * limit.per_second;
+        *tokens = (*tokens + refilled).min(burst);
+        *counted = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            return Ok(());
+        }
+        Err(
+            Duration::try_from_secs_f64((1.0 - *tokens) / limit.per_second)
+                .unwrap_or(Duration::MAX),
+        )
+    }
+}
+
+/// Refreshes a single share locally and on every other provider of its key, if the local node
+/// coordinates its refreshes.
+///
+/// Every provider of the share, coordinator or not, also checks that the share has enough
+/// providers left and publishes an `UnderReplicated` alert if it does not (see
+/// `replication_alert`).
+///
+/// The refresh is only sent to the other providers once it is applied locally, together with
+/// the relay grant the owner gave the local node, and every provider that does not apply it is
+/// logged and recorded in the audit log under its peer id. A coordinator without a grant leaves
+/// a share with other providers alone, since they would refuse the refresh it applied.
+///
+/// # Arguments
+/// * `stored_key` - The owner-scoped key the share is stored under.
+/// * `sender` - The owner of the share.
+/// * `share_entry` - The stored share.
+/// * `replication_margin` - How many providers beyond its threshold the share should have.
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `audit` - The audit log to record the local refresh and the failed providers in, if
+///   auditing is enabled.
+/// * `metrics` - The metrics to count the refresh in.
+/// * `events` - The channel to publish the refresh to, if any.
+/// * `network_client` - A mutable reference to the network client.
+/// * `coordinator` - Decides whether the local node refreshes the share.
+///
+/// # Returns
+/// Returns whether the share was refreshed locally.
+#[allow(clippy::too_many_arguments)]
+async fn refresh_entry(
+    stored_key: &str,
+    sender: PeerId,
+    share_entry: &ShareEntry,
+    replication_margin: u64,
+    dao: &SharedDao,
+    audit: &SharedAudit,
+    metrics: &ProviderMetrics,
+    events: &ProviderEvents,
+    network_client: &mut Client,
+    coordinator: &mut RefreshCoordinator,
+) -> bool {
+    let Some((_, key)) = split_owner_key(stored_key) else {
+        error!("Share {stored_key} is not scoped to its owner, skipping refresh.");
+        return false;
+    };
+    debug!("key: {:?}", key);
+    debug!("share_entry: {:?}", share_entry);
+    debug!("sender: {:?}", sender);
+
+    let refresh_key = match refresh_key_for(share_entry) {
+        Ok(refresh_key) => refresh_key,
+        Err(e) => {
+            error!("Could not generate a refresh key for share {key}: {e}");
+            return false;
+        }
+    };
+    debug!(key, polynomials = refresh_key.len(), "Generated refresh key.");
+
+    // get the providers for the share
+    let record = Client::provider_key(&sender, key);
+    let providers = network_client.get_providers(record.clone()).await;
+    if providers.is_empty() {
+        error!("Could not find provider for share {key}.");
+        return false;
+    }
+
+    debug!("Found {} providers for share {}.", providers.len(), key);
+
+    let mut providers: Vec<PeerId> = providers.into_iter().collect();
+    if !providers.contains(&coordinator.local_peer_id) {
+        providers.push(coordinator.local_peer_id);
+    }
+    if let Some(alert) =
+        replication_alert(key, &sender, share_entry, &providers, replication_margin)
+    {
+        warn!(
+            key, owner = %sender, providers = alert.providers, required = alert.required,
+            "Share is under-replicated."
+        );
+        let message = GossipMessage::UnderReplicated(alert);
+        if let Err(e) = network_client.publish(message).await {
+            error!("Could not publish the replication alert for share {key}: {e}");
+        }
+    }
+    if !coordinator.should_initiate(&record, &providers, share_entry, now_unix()) {
+        debug!("Leaving the refresh of share {key} to its coordinator.");
+        return false;
+    }
+    if share_entry.relay_grant.is_none() && providers.len() > 1 {
+        error!("Share {key} has no relay grant from its owner, not refreshing it.");
+        return false;
+    }
+
+    // refresh the share locally, and have every provider move to the same epoch
+    let trace_id = TraceId::random();
+    let span = info_span!("refresh", %trace_id, key, owner = %sender);
+    let network_client = network_client.with_trace(trace_id);
+    let epoch = Some(share_entry.epoch + 1);
+    let outcome = execute_refresh_share(
+        key,
+        &sender,
+        &refresh_key,
+        epoch,
+        None,
+        dao,
+        metrics,
+        &mut network_client.clone(),
+    )
+    .instrument(span.clone())
+    .await
+    .unwrap_or_else(|e| AuditOutcome::Failed(e.to_string()));
+    let refreshed = outcome == AuditOutcome::Success;
+    if refreshed {
+        metrics.record_refresh_initiated();
+        coordinator.initiated(&record, &providers, share_entry.epoch + 1);
+        let epoch = share_entry.epoch + 1;
+        publish_event(events, metrics, ProviderEvent::Refreshed { key: key.to_string(), epoch });
+    }
+    record_audit(audit, AuditOperation::Refresh, stored_key, None, outcome);
+    if !refreshed {
+        // the other providers would move to an epoch the local share never reached
+        error!("Could not refresh share {key} locally, not sending the refresh to its providers.");
+        return false;
+    }
+
+    // remove local_peer_id from providers
+    let providers = providers
+        .into_iter()
+        .filter(|p| p != &coordinator.local_peer_id)
+        .collect::<Vec<_>>();
+
+    let requests = providers.clone().into_iter().map(|p| {
+        let k = key.to_string();
+        let ref_key = refresh_key.clone();
+        let relay_grant = share_entry.relay_grant.clone();
+        let mut network_client = network_client.clone();
+        debug!(key = k, peer = %p, "Refreshing share.");
+        async move {
+            let result = network_client
+                .request_refresh_shares(k, ref_key, p, sender, epoch, relay_grant)
+                .await;
+            (p, result)
+        }
+        .instrument(span.clone())
+        .boxed()
+    });
+
+    // a provider that did not apply the refresh holds a share out of step with the others, which
+    // is left in the log and the audit log for the owner to repair
+    for (peer, result) in futures::future::join_all(requests).await {
+        let reason = match result {
+            Ok(true) => continue,
+            Ok(false) => "refresh not applied".to_string(),
+            Err(e) => e.to_string(),
+        };
+        error!(key, %peer, reason, "Provider did not apply the refresh of share.");
+        let outcome = AuditOutcome::Failed(reason);
+        record_audit(audit, AuditOperation::Refresh, stored_key, Some(&peer), outcome);
+    }
+
+    debug!(key, shares = providers.len(), "Refreshed shares.");
+    let key = key.to_string();
+    let peers = providers;
+    publish_event(events, metrics, ProviderEvent::RefreshInitiated { key, peers });
+    refreshed
+}
+
+/// Deletes every expired share and stops providing its key on the DHT.
+///
+/// # Arguments
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `audit` - The audit log to record the deletes in, if auditing is enabled.
+/// * `network_client` - A mutable reference to the network client.
+///
+/// # Returns
+/// Returns a `Result` containing the keys of the purged shares.
+pub async fn purge_expired(
+    dao: &SharedDao,
+    audit: &SharedAudit,
+    network_client: &mut Client,
+) -> Result<Vec<String>, Error> {
+    let expired = dao.lock().unwrap().expired_keys(now_unix())?;
+    for key in expired.iter() {
+        if let Err(e) = dao.lock().unwrap().delete(key) {
+            let outcome = AuditOutcome::Failed(e.to_string());
+            record_audit(audit, AuditOperation::Delete, key, None, outcome);
+            return Err(e.into());
+        }
+        record_audit(
+            audit,
+            AuditOperation::Delete,
+            key,
+            None,
+            AuditOutcome::Success,
+        );
+        if let Some(record) = provider_record(key) {
+            network_client.stop_providing(record).await;
+        }
+        debug!(key, "Purged expired share.");
+    }
+    Ok(expired)
+}
+
+/// Deletes every share registered by `owner` and stops providing its key on the DHT.
+///
+/// This is the provider side of retiring an owner identity or banning a peer. Each share leaves a
+/// tombstone that refuses registrations under its key for `tombstone_window`.
+///
+/// # Arguments
+/// * `owner` - The `PeerId` whose shares are deleted.
+/// * `tombstone_window` - How long registrations of the deleted keys are refused.
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `audit` - The audit log to record the deletes in, if auditing is enabled.
+/// * `network_client` - A mutable reference to the network client.
+///
+/// # Returns
+/// Returns a `Result` containing the keys of the purged shares.
+pub async fn purge_owner(
+    owner: &PeerId,
+    tombstone_window: Duration,
+    dao: &SharedDao,
+    audit: &SharedAudit,
+    network_client: &mut Client,
+) -> Result<Vec<String>, Error> {
+    let purged = dao.lock().unwrap().tombstone_by_owner(
+        &owner.to_bytes(),
+        OWNER_PURGED,
+        now_unix(),
+        tombstone_window,
+    )?;
+    for key in &purged {
+        record_audit(
+            audit,
+            AuditOperation::Delete,
+            key,
+            None,
+            AuditOutcome::Success,
+        );
+    }
+    for key in purged.iter().filter_map(|key| provider_record(key)) {
+        network_client.stop_providing(key).await;
+    }
+    info!("Purged {} shares owned by {}.", purged.len(), owner);
+    Ok(purged)
+}
+
+/// Periodically purges expired shares and tombstones in a separate asynchronous task.
+///
+/// # Arguments
+/// * `interval` - A mutable reference to a time interval generator.
+/// * `dao_clone` - A cloned reference to the DAO, wrapped in an Arc and Mutex.
+/// * `audit` - The audit log to record the deletes in, if auditing is enabled.
 /// * `network_client_clone` - A cloned mutable reference to the network client.
+pub async fn purge_loop(
+    interval: &mut Interval,
+    dao_clone: SharedDao,
+    audit: SharedAudit,
+    network_client_clone: &mut Client,
+) {
+    loop {
+        interval.tick().await;
+        match purge_expired(&dao_clone, &audit, network_client_clone).await {
+            Ok(purged) if !purged.is_empty() => {
+                info!("Purged {} expired shares.", purged.len())
+            }
+            Ok(_) => {}
+            Err(e) => error!("Failed to purge expired shares: {e}"),
+        }
+        let purged = dao_clone.lock().unwrap().purge_tombstones(now_unix());
+        match purged {
+            Ok(purged) if purged > 0 => info!("Purged {} expired tombstones.", purged),
+            Ok(_) => {}
+            Err(e) => error!("Failed to purge expired tombstones: {e}"),
+        }
+    }
+}
+
+/// Describes the local provider for its health status. Whether it is reachable is left to the
+/// network client to fill in.
+///
+/// # Arguments
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `metrics` - The metrics of the provider.
 /// * `local_peer_id` - The `PeerId` of the local node.
-pub async fn refresh_loop(
+/// * `started` - When the provider started.
+/// * `period` - The time until the provider publishes its next status.
+///
+/// # Returns
+/// Returns a `Result` containing the status, or an error if the database could not be read.
+pub fn provider_status(
+    dao: &SharedDao,
+    metrics: &ProviderMetrics,
+    local_peer_id: &PeerId,
+    started: Instant,
+    period: Duration,
+) -> Result<ProviderStatus, Error> {
+    let stats = dao.lock().unwrap().stats()?;
+    Ok(ProviderStatus {
+        peer: local_peer_id.to_bytes(),
+        shares: stats.entries,
+        db_bytes: stats.total_value_bytes,
+        uptime_secs: started.elapsed().as_secs(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        reachable: false,
+        interval_secs: period.as_secs().max(1),
+        metrics: metrics.snapshot(),
+    })
+}
+
+/// Periodically publishes the health status of the local provider in a separate asynchronous
+/// task, keeping the number of shares stored in `metrics` up to date as it reads it.
+///
+/// # Arguments
+/// * `interval` - A mutable reference to the time interval the status is published on.
+/// * `dao_clone` - A cloned reference to the DAO, wrapped in an Arc and Mutex.
+/// * `metrics` - The metrics of the provider.
+/// * `local_peer_id` - The `PeerId` of the local node.
+/// * `network_client_clone` - A cloned mutable reference to the network client.
+pub async fn status_loop(
     interval: &mut Interval,
-    dao_clone: Arc<Mutex<Box<dyn ShareEntryDaoTrait>>>,
+    dao_clone: SharedDao,
+    metrics: SharedMetrics,
+    local_peer_id: PeerId,
     network_client_clone: &mut Client,
+) {
+    let started = Instant::now();
+    loop {
+        interval.tick().await;
+        let period = interval.period();
+        let status = match provider_status(&dao_clone, &metrics, &local_peer_id, started, period) {
+            Ok(status) => status,
+            Err(e) => {
+                error!("Failed to read the share database for the health status: {e}");
+                continue;
+            }
+        };
+        metrics.set_shares_stored(status.shares);
+        // a provider without peers yet has no one to publish to
+        if let Err(e) = network_client_clone.publish_status(status).await {
+            debug!("Could not publish the health status: {e}");
+        }
+    }
+}
+
+/// Resolves when the process is asked to stop, by SIGTERM or Ctrl-C. Needs a tokio runtime.
+#[cfg(feature = "rt-tokio")]
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("SIGTERM handler to install");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Yields each time the process is asked to reload its configuration, by SIGHUP. Needs a tokio
+/// runtime.
+#[cfg(feature = "rt-tokio")]
+pub fn reload_signals() -> stream::BoxStream<'static, ()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::hangup()) {
+            Ok(hangup) => stream::unfold(hangup, |mut hangup| async move {
+                hangup.recv().await.map(|()| ((), hangup))
+            })
+            .boxed(),
+            Err(e) => {
+                warn!("Cannot reload the configuration on SIGHUP: {e}");
+                stream::pending().boxed()
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    stream::pending().boxed()
+}
+
+/// Re-reads `conf.toml` from the directory of `config` and applies what changed in it to the
+/// running node: the bootstrappers added are dialled, and the peers newly denied or allowed are
+/// blocked or accepted again.
+///
+/// # Arguments
+/// * `config` - The configuration running, replaced by the one reloaded.
+/// * `local_peer_id` - The `PeerId` of the local node, never dialled as a bootstrapper.
+/// * `network_client` - The client of the running node.
+///
+/// # Returns
+/// What changed, or an error if the configuration cannot be loaded, in which case `config` is
+/// kept as it was.
+pub async fn reload_config(
+    config: &mut ShardConfig,
+    local_peer_id: PeerId,
+    network_client: &mut Client,
+) -> Result<ConfigChanges, ConfigError> {
+    let reloaded = ShardConfig::load(&config.dir)?;
+    let changes = reloaded.changes_since(config);
+    network_client
+        .bootstrap(&changes.added_bootstrappers, local_peer_id)
+        .await;
+    for peer in &changes.blocked {
+        network_client.block_peer(*peer).await;
+    }
+    for peer in &changes.allowed {
+        network_client.allow_peer(*peer).await;
+    }
+    *config = reloaded;
+    Ok(changes)
+}
+
+/// Reloads the configuration with `reload_config` each time `reloads` yields, logging what
+/// changed, until `reloads` ends. The identity key, the listen addresses and the other settings
+/// are only read at startup: a change to them is logged as needing a restart. A configuration
+/// that fails to load is logged, and the previous one kept.
+///
+/// # Arguments
+/// * `config` - The configuration the node was started with, loaded from a directory.
+/// * `local_peer_id` - The `PeerId` of the local node.
+/// * `network_client` - The client of the running node.
+/// * `reloads` - Yields each time to reload, such as `reload_signals`.
+pub async fn watch_config(
+    mut config: ShardConfig,
     local_peer_id: PeerId,
+    mut network_client: Client,
+    mut reloads: impl Stream<Item = ()> + Unpin,
 ) {
+    while reloads.next().await.is_some() {
+        match reload_config(&mut config, local_peer_id, &mut network_client).await {
+            Ok(changes) => {
+                info!(%changes, "Reloaded the configuration.");
+                if !changes.needs_restart.is_empty() {
+                    warn!(
+                        settings = changes.needs_restart.join(", "),
+                        "Changed settings that only take effect on restart."
+                    );
+                }
+            }
+            Err(e) => error!("Keeping the previous configuration, the reload failed: {e}"),
+        }
+    }
+}
+
+/// Flushes the DAO and audit log before the provider exits, saving the snapshot of an in-memory
+/// DAO.
+///
+/// # Arguments
+/// * `dao` - A shared reference to the DAO trait object.
+/// * `audit` - The shared audit log.
+pub fn flush_on_shutdown(dao: &SharedDao, audit: &SharedAudit) {
+    info!("Shutting down, flushing shares.");
+    if let Err(e) = dao.lock().unwrap().flush() {
+        error!("Failed to flush shares on shutdown: {e}");
+    }
+    if let Some(Err(e)) = audit.as_ref().map(|audit| audit.flush()) {
+        error!("Failed to flush the audit log on shutdown: {e}");
+    }
+}
+
+/// Periodically flushes the DAO to disk in a separate asynchronous task.
+///
+/// # Arguments
+/// * `interval` - A mutable reference to a time interval generator.
+/// * `dao_clone` - A cloned reference to the DAO, wrapped in an Arc and Mutex.
+pub async fn flush_loop(interval: &mut Interval, dao_clone: SharedDao) {
     loop {
         interval.tick().await;
-        debug!("Starting refresh.");
+        let flushed = dao_clone.lock().unwrap().flush();
+        if let Err(e) = flushed {
+            error!("Failed to flush shares: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+    use crate::config::ShardConfig;
+    use crate::constants::STATUS_EXPIRY_PERIODS;
+    use futures::channel::mpsc;
+    use gf256::gf256;
+    use libp2p::identity::Keypair;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use tokio::time;
+
+    fn test_dao() -> SharedDao {
+        dao(DaoOptions::default()).unwrap()
+    }
+
+    fn test_client() -> (Client, mpsc::Receiver<Command>) {
+        let (sender, receiver) = mpsc::channel(16);
+        let client = Client {
+            sender,
+            trace_id: None,
+        };
+        (client, receiver)
+    }
+
+    /// A share whose owner granted the provider storing it to relay its refreshes, as the CLI
+    /// does for every share it registers.
+    fn entry(expires_at: Option<u64>) -> ShareEntry {
+        let owner = Keypair::generate_ed25519();
+        let grant = RelayGrant::signed("key", PeerId::random(), &owner).unwrap();
+        ShareEntry {
+            share: (1, vec![1, 2, 3]),
+            sender: owner.public().to_peer_id().to_bytes(),
+            threshold: 2,
+            expires_at,
+            relay_grant: Some(grant),
+            ..Default::default()
+        }
+    }
+
+    /// Stores `entry` in its owner's namespace, returning the DHT record it is provided under.
+    fn insert_owned(dao: &SharedDao, key: &str, entry: &ShareEntry) -> String {
+        dao.lock().unwrap().insert_owned(key, entry).unwrap();
+        Client::provider_key(&PeerId::from_bytes(&entry.sender).unwrap(), key)
+    }
+
+    /// Reopens a sled-backed DAO, waiting for the background threads of a dropped handle to
+    /// release its file lock.
+    fn reopen_dao(options: impl Fn() -> DaoOptions) -> SharedDao {
+        for _ in 0..50 {
+            match dao(options()) {
+                Ok(dao) => return dao,
+                Err(e) if e.to_string().contains("could not acquire lock") => {
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+                Err(e) => panic!("could not reopen the database: {}", e),
+            }
+        }
+        dao(options()).unwrap()
+    }
+
+    /// Reopens a sled database directly, waiting for a dropped handle to release its file lock
+    /// as `reopen_dao` does.
+    fn reopen_sled(path: &str) -> sled::Db {
+        for _ in 0..50 {
+            match sled::open(path) {
+                Ok(db) => return db,
+                Err(e) if e.to_string().contains("could not acquire lock") => {
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+                Err(e) => panic!("could not reopen the database: {}", e),
+            }
+        }
+        sled::open(path).unwrap()
+    }
+
+    fn stopped_keys(receiver: &mut mpsc::Receiver<Command>) -> Vec<String> {
+        let mut keys = Vec::new();
+        while let Ok(command) = receiver.try_recv() {
+            if let Command::StopProviding { key } = command {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+
+    #[test]
+    fn test_expired_entry_reads_as_absent() {
+        let dao = test_dao();
+        dao.lock()
+            .unwrap()
+            .insert("expired", &entry(Some(1)))
+            .unwrap();
+        dao.lock().unwrap().insert("live", &entry(None)).unwrap();
+
+        assert!(get_live_entry("expired", &dao).unwrap().is_none());
+        assert!(get_live_entry("live", &dao).unwrap().is_some());
+    }
+
+    #[test]
+    fn encryption_key_requires_a_database_path() {
+        let options = DaoOptions {
+            encryption_key: Some(EncryptionKey::from_bytes([0u8; 32])),
+            ..Default::default()
+        };
+        assert!(dao(options).is_err());
+    }
+
+    #[test]
+    fn backend_selection() {
+        assert_eq!("sqlite".parse(), Ok(DbBackend::Sqlite));
+        assert!("postgres".parse::<DbBackend>().is_err());
+
+        let memory_with_path = DaoOptions {
+            backend: Some(DbBackend::Memory),
+            db_path: Some("unused".to_string()),
+            ..Default::default()
+        };
+        assert!(dao(memory_with_path).is_err());
+
+        let sled_without_path = DaoOptions {
+            backend: Some(DbBackend::Sled),
+            ..Default::default()
+        };
+        assert!(dao(sled_without_path).is_err());
+
+        let isolated_memory = DaoOptions {
+            isolate_owners: true,
+            ..Default::default()
+        };
+        assert!(dao(isolated_memory).is_err());
+    }
+
+    #[test]
+    fn read_only_dao_cannot_back_a_provider() {
+        let read_only_memory = DaoOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        assert!(dao(read_only_memory).is_err());
+
+        let path = std::env::temp_dir().join(format!("shard-ro-{}", rand::random::<u64>()));
+        let db_path = path.to_str().unwrap().to_string();
+        let writable = dao(DaoOptions {
+            db_path: Some(db_path.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+        ensure_writable(&writable).unwrap();
+        drop(writable);
+
+        let read_only = reopen_dao(|| DaoOptions {
+            db_path: Some(db_path.clone()),
+            read_only: true,
+            ..Default::default()
+        });
+        let e = ensure_writable(&read_only).unwrap_err();
+        assert!(e.to_string().contains("read-only"));
+        drop(read_only);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_purge_expired_deletes_and_stops_providing() {
+        let dao = test_dao();
+        let (mut client, mut receiver) = test_client();
+        let expired = entry(Some(1));
+        let later = entry(Some(now_unix() + 3600));
+        let record = insert_owned(&dao, "expired", &expired);
+        insert_owned(&dao, "later", &later);
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let audit = Arc::new(AuditLog::from_tree(db.open_tree("audit").unwrap()).unwrap());
+        let purged = purge_expired(&dao, &Some(Arc::clone(&audit)), &mut client)
+            .await
+            .unwrap();
+
+        assert_eq!(purged, vec![owner_key(&expired.sender, "expired")]);
+        let records = audit.read_range(0, u64::MAX).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].event.operation, AuditOperation::Delete);
+        assert_eq!(records[0].event.key, purged[0]);
+        assert_eq!(records[0].event.peer, None);
+        assert_eq!(records[0].event.outcome, AuditOutcome::Success);
+        let dao = dao.lock().unwrap();
+        assert!(dao.get_owned(&expired.sender, "expired").unwrap().is_none());
+        assert!(dao.get_owned(&later.sender, "later").unwrap().is_some());
+        assert_eq!(stopped_keys(&mut receiver), vec![record]);
+    }
+
+    #[tokio::test]
+    async fn test_purge_owner_deletes_and_stops_providing() {
+        let dao = test_dao();
+        let (mut client, mut receiver) = test_client();
+        let owner = PeerId::random();
+        let owned = ShareEntry {
+            sender: owner.to_bytes(),
+            ..entry(None)
+        };
+        let other = entry(None);
+        let record = insert_owned(&dao, "owned", &owned);
+        insert_owned(&dao, "other", &other);
+        let window = Duration::from_secs(60);
+
+        let purged = purge_owner(&owner, window, &dao, &None, &mut client)
+            .await
+            .unwrap();
+
+        assert_eq!(purged, vec![owner_key(&owned.sender, "owned")]);
+        let tombstone = dao.lock().unwrap().get_tombstone(&purged[0]).unwrap();
+        assert_eq!(tombstone.unwrap().owner, owned.sender);
+        assert!(dao
+            .lock()
+            .unwrap()
+            .get_owned(&other.sender, "other")
+            .unwrap()
+            .is_some());
+        assert_eq!(stopped_keys(&mut receiver), vec![record]);
+        assert!(purge_owner(&owner, window, &dao, &None, &mut client)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_delete_owned_share_leaves_a_tombstone() {
+        let dao = test_dao();
+        let owned = entry(None);
+        let owner = PeerId::from_bytes(&owned.sender).unwrap();
+        insert_owned(&dao, "owned", &owned);
+        let window = Duration::from_secs(60);
+
+        let status = delete_owned_share("owned", &owner, window, &dao).unwrap();
+
+        assert_eq!(status, DeleteShareStatus::Deleted);
+        let dao = dao.lock().unwrap();
+        assert!(dao.get_owned(&owned.sender, "owned").unwrap().is_none());
+        let tombstone = dao
+            .get_tombstone(&owner_key(&owned.sender, "owned"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(tombstone.owner, owned.sender);
+        assert_eq!(tombstone.reason, OWNER_DELETED);
+    }
+
+    #[test]
+    fn test_delete_owned_share_ignores_other_owners() {
+        let dao = test_dao();
+        let owned = entry(None);
+        insert_owned(&dao, "owned", &owned);
+        let window = Duration::from_secs(60);
+
+        let status = delete_owned_share("owned", &PeerId::random(), window, &dao).unwrap();
+
+        assert_eq!(status, DeleteShareStatus::NotFound);
+        let stored = dao
+            .lock()
+            .unwrap()
+            .get_owned(&owned.sender, "owned")
+            .unwrap();
+        assert_eq!(stored, Some(owned));
+    }
+
+    #[test]
+    fn test_delete_owned_share_of_missing_key() {
+        let dao = test_dao();
+        let owner = PeerId::random();
+        let window = Duration::from_secs(60);
+
+        let status = delete_owned_share("missing", &owner, window, &dao).unwrap();
+
+        assert_eq!(status, DeleteShareStatus::NotFound);
+        let stored_key = owner_key(&owner.to_bytes(), "missing");
+        let dao = dao.lock().unwrap();
+        assert!(dao.keys().unwrap().is_empty());
+        assert!(dao.get_tombstone(&stored_key).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_list_owned_keys_is_scoped_to_the_sender() {
+        let dao = test_dao();
+        let first = PeerId::random();
+        let second = PeerId::random();
+        for (owner, key) in [(&first, "a"), (&second, "b"), (&first, "c"), (&second, "a")] {
+            let owned = ShareEntry {
+                sender: owner.to_bytes(),
+                ..entry(None)
+            };
+            insert_owned(&dao, key, &owned);
+        }
+
+        let (keys, next_cursor) = list_owned_keys(&first, "", 10, &dao).unwrap();
+        assert_eq!(keys, vec!["a".to_string(), "c".to_string()]);
+        assert_eq!(next_cursor, None);
+        let (keys, _) = list_owned_keys(&second, "", 10, &dao).unwrap();
+        assert_eq!(keys, vec!["a".to_string(), "b".to_string()]);
+
+        // a cursor shaped like another owner's stored key still only walks the sender's keys
+        let foreign_cursor = owner_key(&second.to_bytes(), "");
+        let (keys, _) = list_owned_keys(&first, &foreign_cursor, 10, &dao).unwrap();
+        assert!(keys.iter().all(|key| key == "a" || key == "c"));
+    }
+
+    #[test]
+    fn test_list_owned_keys_pages_through_every_key_once() {
+        let dao = test_dao();
+        let owner = PeerId::random();
+        let owned = ShareEntry {
+            sender: owner.to_bytes(),
+            ..entry(None)
+        };
+        let expected: Vec<String> = (0..25).map(|i| format!("key-{:02}", i)).collect();
+        for key in &expected {
+            insert_owned(&dao, key, &owned);
+        }
+        insert_owned(&dao, "other", &entry(None));
+
+        let mut listed = Vec::new();
+        let mut cursor = String::new();
+        loop {
+            let (keys, next_cursor) = list_owned_keys(&owner, &cursor, 10, &dao).unwrap();
+            assert!(keys.len() <= 10);
+            listed.extend(keys);
+            match next_cursor {
+                Some(next_cursor) => cursor = next_cursor,
+                None => break,
+            }
+        }
+        assert_eq!(listed, expected);
+    }
+
+    #[test]
+    fn test_list_owned_keys_of_unknown_owner() {
+        let dao = test_dao();
+        insert_owned(&dao, "owned", &entry(None));
+
+        let (keys, next_cursor) = list_owned_keys(&PeerId::random(), "", 10, &dao).unwrap();
+
+        assert!(keys.is_empty());
+        assert_eq!(next_cursor, None);
+    }
+
+    #[test]
+    fn test_stat_owned_share_reports_metadata() {
+        let dao = test_dao();
+        let owned = ShareEntry {
+            share: (3, vec![7; 48]),
+            epoch: 5,
+            last_refreshed_unix: 1_700_000_000,
+            ..entry(None)
+        };
+        let owner = PeerId::from_bytes(&owned.sender).unwrap();
+        insert_owned(&dao, "owned", &owned);
+
+        let status = stat_owned_share("owned", &owner, &dao).unwrap();
+
+        let metadata = ShareMetadata {
+            index: 3,
+            #[cfg(feature = "sss16")]
+            field: ShareField::Gf256,
+            length: 48,
+            threshold: 2,
+            epoch: 5,
+            last_refreshed_unix: 1_700_000_000,
+        };
+        assert_eq!(status, StatShareStatus::Found(metadata));
+        assert_eq!(
+            stat_owned_share("missing", &owner, &dao).unwrap(),
+            StatShareStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn test_stat_owned_share_refuses_strangers() {
+        let dao = test_dao();
+        let owned = entry(None);
+        let owner = PeerId::from_bytes(&owned.sender).unwrap();
+        let stranger = PeerId::random();
+        insert_owned(&dao, "owned", &owned);
+        // a foreign entry planted in the stranger's namespace is still not theirs
+        dao.lock()
+            .unwrap()
+            .insert(&owner_key(&stranger.to_bytes(), "owned"), &owned)
+            .unwrap();
+
+        assert_eq!(
+            stat_owned_share("owned", &stranger, &dao).unwrap(),
+            StatShareStatus::NotOwner
+        );
+        assert!(matches!(
+            stat_owned_share("owned", &owner, &dao).unwrap(),
+            StatShareStatus::Found(_)
+        ));
+    }
+
+    #[test]
+    fn test_stat_response_leaves_the_share_out() {
+        let owned = ShareEntry {
+            share: (1, vec![0xAB; 4096]),
+            ..entry(None)
+        };
+        let response = Response::StatShare(crate::protocol::StatShareResponse {
+            status: StatShareStatus::Found(share_metadata(&owned)),
+        });
+
+        let bytes = cbor4ii::serde::to_vec(Vec::new(), &response).unwrap();
+
+        assert!(bytes.len() < 128, "stat response is {} bytes", bytes.len());
+    }
+
+    #[test]
+    fn test_update_readers_grants_and_revokes() {
+        let dao = test_dao();
+        let owned = entry(None);
+        let owner = PeerId::from_bytes(&owned.sender).unwrap();
+        let reader = PeerId::random().to_bytes();
+        insert_owned(&dao, "owned", &owned);
+        let readers = || {
+            get_owned_live_entry(&owner, "owned", &dao)
+                .unwrap()
+                .unwrap()
+                .readers
+        };
+
+        for _ in 0..2 {
+            let granted = update_readers("owned", &owner, &reader, AccessChange::Grant, &dao);
+            assert_eq!(granted, Ok(()));
+        }
+        assert_eq!(readers(), vec![reader.clone()]);
+
+        let revoked = update_readers("owned", &owner, &reader, AccessChange::Revoke, &dao);
+        assert_eq!(revoked, Ok(()));
+        assert!(readers().is_empty());
+    }
+
+    #[test]
+    fn test_readers_cannot_grant_access() {
+        let dao = test_dao();
+        let owned = entry(None);
+        let owner = PeerId::from_bytes(&owned.sender).unwrap();
+        let reader = PeerId::random();
+        insert_owned(&dao, "owned", &owned);
+        let granted = update_readers(
+            "owned",
+            &owner,
+            &reader.to_bytes(),
+            AccessChange::Grant,
+            &dao,
+        );
+        assert_eq!(granted, Ok(()));
+
+        let stranger = PeerId::random().to_bytes();
+        let regranted = update_readers("owned", &reader, &stranger, AccessChange::Grant, &dao);
+
+        assert_eq!(regranted, Err(Failure::NotFound));
+        let stored = get_owned_live_entry(&owner, "owned", &dao)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.readers, vec![reader.to_bytes()]);
+    }
+
+    #[test]
+    fn test_transferred_share_belongs_to_the_new_owner_alone() {
+        let dao = test_dao();
+        let new_owner = PeerId::random();
+        let owned = ShareEntry {
+            readers: vec![new_owner.to_bytes()],
+            ..entry(None)
+        };
+        let owner = PeerId::from_bytes(&owned.sender).unwrap();
+        insert_owned(&dao, "owned", &owned);
+
+        assert_eq!(
+            transfer_owned_share("owned", &owner, &new_owner, None, &dao),
+            Ok(())
+        );
+        assert!(get_owned_live_entry(&owner, "owned", &dao).unwrap().is_none());
+        let moved = get_owned_live_entry(&new_owner, "owned", &dao).unwrap().unwrap();
+        assert!(check_share_owner(&moved, &new_owner));
+        assert!(moved.readers.is_empty());
+        assert_eq!(moved.share, owned.share);
+        // the grant of the old owner does not speak for the new one
+        assert_eq!(moved.relay_grant, None);
+
+        // the old owner has nothing left to transfer, and the new owner cannot take it back
+        let again = transfer_owned_share("owned", &owner, &new_owner, None, &dao);
+        assert_eq!(again, Err(Failure::NotFound));
+        let stranger = PeerId::random();
+        let stolen = transfer_owned_share("owned", &stranger, &stranger, None, &dao);
+        assert!(matches!(stolen, Err(Failure::InvalidRequest(_))));
+        let stolen = transfer_owned_share("owned", &stranger, &owner, None, &dao);
+        assert_eq!(stolen, Err(Failure::NotFound));
+    }
+
+    #[test]
+    fn test_interrupted_transfer_completes_and_conflicts_are_refused() {
+        let dao = test_dao();
+        let owned = entry(None);
+        let owner = PeerId::from_bytes(&owned.sender).unwrap();
+        let new_owner = PeerId::random();
+        insert_owned(&dao, "owned", &owned);
+        let moved = ShareEntry {
+            sender: new_owner.to_bytes(),
+            relay_grant: None,
+            ..owned.clone()
+        };
+        // stored under the new owner, but not yet removed from the old one
+        insert_owned(&dao, "owned", &moved);
+        assert_eq!(
+            transfer_owned_share("owned", &owner, &new_owner, None, &dao),
+            Ok(())
+        );
+        assert!(get_owned_live_entry(&owner, "owned", &dao).unwrap().is_none());
+
+        insert_owned(&dao, "other", &owned);
+        let different = ShareEntry {
+            share: (2, vec![9, 9, 9]),
+            ..moved
+        };
+        insert_owned(&dao, "other", &different);
+        let conflict = transfer_owned_share("other", &owner, &new_owner, None, &dao);
+        assert!(matches!(conflict, Err(Failure::Conflict(_))));
+        assert!(get_owned_live_entry(&owner, "other", &dao).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_corrupt_owner_is_not_the_sender() {
+        let corrupt = ShareEntry {
+            sender: vec![1, 2, 3],
+            ..entry(None)
+        };
+
+        assert!(!check_share_owner(&corrupt, &PeerId::random()));
+    }
+
+    /// Sends `request` from a bare swarm to `provider` and waits for its response.
+    async fn send_raw_request(
+        requester: &mut libp2p::Swarm<crate::network::Behaviour>,
+        provider: PeerId,
+        request: Request,
+    ) -> Response {
+        use crate::network::BehaviourEvent;
+        use libp2p::{request_response, swarm::SwarmEvent};
+
+        requester
+            .behaviour_mut()
+            .request_response
+            .send_request(&provider, request);
+        let wait = async {
+            loop {
+                if let SwarmEvent::Behaviour(BehaviourEvent::RequestResponse(
+                    request_response::Event::Message {
+                        message: request_response::Message::Response { response, .. },
+                        ..
+                    },
+                )) = requester.select_next_some().await
+                {
+                    return response;
+                }
+            }
+        };
+        time::timeout(Duration::from_secs(10), wait)
+            .await
+            .expect("provider to answer")
+    }
+
+    /// Runs a provider listening on a local port, then runs `test` with the provider's peer id and
+    /// a bare swarm that knows the provider's address.
+    async fn with_provider<F: Future<Output = ()>>(
+        test: impl FnOnce(PeerId, libp2p::Swarm<crate::network::Behaviour>) -> F,
+    ) {
+        with_provider_and_reader(|provider, requester, _| test(provider, requester)).await
+    }
+
+    /// Like `with_provider`, with a second bare swarm for tests that need another peer.
+    async fn with_provider_and_reader<F: Future<Output = ()>>(
+        test: impl FnOnce(
+            PeerId,
+            libp2p::Swarm<crate::network::Behaviour>,
+            libp2p::Swarm<crate::network::Behaviour>,
+        ) -> F,
+    ) {
+        with_rate_limited_provider(None, Arc::default(), test).await
+    }
+
+    /// Like `with_provider_and_reader`, with the provider holding each peer to `rate_limit` and
+    /// counting its work in `metrics`.
+    async fn with_rate_limited_provider<F: Future<Output = ()>>(
+        rate_limit: Option<RateLimit>,
+        metrics: SharedMetrics,
+        test: impl FnOnce(
+            PeerId,
+            libp2p::Swarm<crate::network::Behaviour>,
+            libp2p::Swarm<crate::network::Behaviour>,
+        ) -> F,
+    ) {
+        let identities = (Keypair::generate_ed25519(), Keypair::generate_ed25519());
+        with_identified_provider(rate_limit, metrics, identities, test).await
+    }
+
+    /// Like `with_rate_limited_provider`, with the two peers running as the given identities.
+    async fn with_identified_provider<F: Future<Output = ()>>(
+        rate_limit: Option<RateLimit>,
+        metrics: SharedMetrics,
+        (requester, reader): (Keypair, Keypair),
+        test: impl FnOnce(
+            PeerId,
+            libp2p::Swarm<crate::network::Behaviour>,
+            libp2p::Swarm<crate::network::Behaviour>,
+        ) -> F,
+    ) {
+        let (mut client, events, event_loop, provider) = crate::network::new(None).await.unwrap();
+        spawn(event_loop.run(None));
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr: libp2p::Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap();
+        client.start_listening(addr.clone()).await.unwrap();
+
+        let (_, _, requester, _) = crate::network::with_identity(requester).await.unwrap();
+        let mut requester = requester.swarm;
+        requester
+            .behaviour_mut()
+            .kademlia
+            .add_address(&provider, addr.clone());
+        let (_, _, reader, _) = crate::network::with_identity(reader).await.unwrap();
+        let mut reader = reader.swarm;
+        reader.behaviour_mut().kademlia.add_address(&provider, addr);
+
+        // run_loop runs on a local set next to the test, stopping with it
+        let local = tokio::task::LocalSet::new();
+        local.spawn_local(async move {
+            run_loop(
+                DaoOptions::default(),
+                ProviderOptions {
+                    metrics,
+                    rate_limit,
+                    ..Default::default()
+                },
+                provider,
+                &mut client,
+                events,
+                CancellationToken::new(),
+            )
+            .await
+        });
+        local.run_until(test(provider, requester, reader)).await;
+    }
+
+    fn snapshot_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("shard-{}-{}", name, rand::random::<u64>()))
+    }
+
+    #[tokio::test]
+    async fn test_run_loop_returns_the_error_of_a_dao_it_cannot_open() {
+        let (mut client, events, event_loop, provider) = crate::network::new(None).await.unwrap();
+        spawn(event_loop.run(None));
+        let options = DaoOptions {
+            encryption_key: Some(EncryptionKey::from_bytes([0u8; 32])),
+            ..Default::default()
+        };
+        let run = run_loop(
+            options,
+            ProviderOptions::default(),
+            provider,
+            &mut client,
+            events,
+            CancellationToken::new(),
+        );
+        let stopped = time::timeout(Duration::from_secs(10), run).await;
+        assert!(matches!(stopped, Ok(Err(Error::Invalid(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_idle_provider_flushes_and_stops_the_network() {
+        let snapshot = snapshot_path("idle-shutdown");
+        let (mut client, events, event_loop, provider) = crate::network::new(None).await.unwrap();
+        let network = spawn(event_loop.run(None));
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr: libp2p::Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap();
+        client.start_listening(addr.clone()).await.unwrap();
+        let (_, _, requester, _) = crate::network::new(None).await.unwrap();
+        let mut requester = requester.swarm;
+        requester
+            .behaviour_mut()
+            .kademlia
+            .add_address(&provider, addr);
+        let owner = *requester.local_peer_id();
+
+        let shutdown = CancellationToken::new();
+        let options = DaoOptions {
+            snapshot_path: Some(snapshot.clone()),
+            ..Default::default()
+        };
+        let local = tokio::task::LocalSet::new();
+        let provider_loop = local.spawn_local({
+            let shutdown = shutdown.clone();
+            async move {
+                run_loop(
+                    options,
+                    ProviderOptions::default(),
+                    provider,
+                    &mut client,
+                    events,
+                    shutdown,
+                )
+                .await
+            }
+        });
+        local
+            .run_until(async move {
+                let register = Request::RegisterShare(RegisterShareRequest {
+                    peer: provider.to_bytes(),
+                    ..register_request(&owner, vec![1, 2])
+                });
+                let response = send_raw_request(&mut requester, provider, register).await;
+                assert!(matches!(
+                    response,
+                    Response::RegisterShare(r) if r.success
+                ));
+
+                shutdown.cancel();
+                time::timeout(Duration::from_secs(10), provider_loop)
+                    .await
+                    .expect("provider to stop")
+                    .unwrap()
+                    .unwrap();
+                time::timeout(Duration::from_secs(10), network)
+                    .await
+                    .expect("network event loop to stop")
+                    .unwrap();
+            })
+            .await;
+
+        let restored = HashMapShareEntryDao::new_with_snapshot(&snapshot);
+        assert!(restored
+            .get_owned(&owner.to_bytes(), "shared-name")
+            .unwrap()
+            .is_some());
+        let _ = std::fs::remove_file(&snapshot);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_provider_finishes_the_refresh_in_flight() {
+        let snapshot = snapshot_path("refresh-shutdown");
+        let due = entry(None);
+        let saved = HashMapShareEntryDao::new_with_snapshot(&snapshot);
+        saved.insert_owned("due", &due).unwrap();
+        saved.flush().unwrap();
+
+        let local_peer_id = PeerId::random();
+        let (mut client, mut receiver) = test_client();
+        let (_events_sender, events) = mpsc::channel::<Event>(1);
+        let (refreshing, refresh_sent) = tokio::sync::oneshot::channel();
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_clone = Arc::clone(&stopped);
+        spawn(async move {
+            let mut refreshing = Some(refreshing);
+            let mut released = Some(released);
+            while let Some(command) = receiver.next().await {
+                match command {
+                    Command::GetProviders { sender, .. } => {
+                        let _ = sender.send(HashSet::from([local_peer_id, PeerId::random()]));
+                    }
+                    // the refresh stays in flight until the test releases it
+                    Command::RequestRefreshShare { sender_chan, .. } => {
+                        let _ = refreshing.take().map(|refreshing| refreshing.send(()));
+                        if let Some(released) = released.take() {
+                            let _ = released.await;
+                        }
+                        let _ = sender_chan.send(Ok(true));
+                    }
+                    Command::PublishStatus { sender, .. } => {
+                        let _ = sender.send(Ok(()));
+                    }
+                    Command::Shutdown { sender } => {
+                        stopped_clone.store(true, Ordering::SeqCst);
+                        let _ = sender.send(());
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        let shutdown = CancellationToken::new();
+        let options = DaoOptions {
+            snapshot_path: Some(snapshot.clone()),
+            ..Default::default()
+        };
+        let local = tokio::task::LocalSet::new();
+        let mut provider_loop = local.spawn_local({
+            let shutdown = shutdown.clone();
+            async move {
+                run_loop(
+                    options,
+                    ProviderOptions {
+                        refresh_jitter: Some(0),
+                        replication_margin: Some(0),
+                        ..Default::default()
+                    },
+                    local_peer_id,
+                    &mut client,
+                    events,
+                    shutdown,
+                )
+                .await
+            }
+        });
+        local
+            .run_until(async move {
+                refresh_sent.await.unwrap();
+                shutdown.cancel();
+                assert!(
+                    time::timeout(Duration::from_millis(100), &mut provider_loop)
+                        .await
+                        .is_err(),
+                    "the provider stopped with a refresh in flight"
+                );
+                assert!(!stopped.load(Ordering::SeqCst));
+
+                release.send(()).unwrap();
+                time::timeout(Duration::from_secs(10), provider_loop)
+                    .await
+                    .expect("provider to stop")
+                    .unwrap()
+                    .unwrap();
+                assert!(stopped.load(Ordering::SeqCst));
+            })
+            .await;
+
+        let restored = HashMapShareEntryDao::new_with_snapshot(&snapshot);
+        let refreshed = restored.get_owned(&due.sender, "due").unwrap().unwrap();
+        assert_eq!(refreshed.epoch, 1);
+        let _ = std::fs::remove_file(&snapshot);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_provider_abandons_a_stuck_refresh_after_its_grace_period() {
+        let snapshot = snapshot_path("grace-shutdown");
+        let due = entry(None);
+        let saved = HashMapShareEntryDao::new_with_snapshot(&snapshot);
+        saved.insert_owned("due", &due).unwrap();
+        saved.flush().unwrap();
+
+        let local_peer_id = PeerId::random();
+        let (mut client, mut receiver) = test_client();
+        let (_events_sender, events) = mpsc::channel::<Event>(1);
+        let (refreshing, refresh_sent) = tokio::sync::oneshot::channel();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_clone = Arc::clone(&stopped);
+        spawn(async move {
+            let mut refreshing = Some(refreshing);
+            let mut stuck = Vec::new();
+            while let Some(command) = receiver.next().await {
+                match command {
+                    Command::GetProviders { sender, .. } => {
+                        let _ = sender.send(HashSet::from([local_peer_id, PeerId::random()]));
+                    }
+                    // the refresh is never answered
+                    Command::RequestRefreshShare { sender_chan, .. } => {
+                        let _ = refreshing.take().map(|refreshing| refreshing.send(()));
+                        stuck.push(sender_chan);
+                    }
+                    Command::PublishStatus { sender, .. } => {
+                        let _ = sender.send(Ok(()));
+                    }
+                    Command::Shutdown { sender } => {
+                        stopped_clone.store(true, Ordering::SeqCst);
+                        let _ = sender.send(());
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        let shutdown = CancellationToken::new();
+        let options = DaoOptions {
+            snapshot_path: Some(snapshot.clone()),
+            ..Default::default()
+        };
+        let local = tokio::task::LocalSet::new();
+        let provider_loop = local.spawn_local({
+            let shutdown = shutdown.clone();
+            async move {
+                run_loop(
+                    options,
+                    ProviderOptions {
+                        refresh_jitter: Some(0),
+                        replication_margin: Some(0),
+                        shutdown_grace: Some(1),
+                        ..Default::default()
+                    },
+                    local_peer_id,
+                    &mut client,
+                    events,
+                    shutdown,
+                )
+                .await
+            }
+        });
+        local
+            .run_until(async move {
+                refresh_sent.await.unwrap();
+                let cancelled = time::Instant::now();
+                shutdown.cancel();
+                time::timeout(Duration::from_secs(3), provider_loop)
+                    .await
+                    .expect("provider to stop within its grace period")
+                    .unwrap()
+                    .unwrap();
+                assert!(cancelled.elapsed() >= Duration::from_secs(1));
+                assert!(stopped.load(Ordering::SeqCst));
+
+            })
+            .await;
+
+        // the share keeps what the refresh applied locally before it got stuck
+        let restored = HashMapShareEntryDao::new_with_snapshot(&snapshot);
+        let kept = restored.get_owned(&due.sender, "due").unwrap().unwrap();
+        assert_eq!(kept.epoch, 1);
+        let _ = std::fs::remove_file(&snapshot);
+    }
+
+    fn get_share_request(provider: PeerId, sender: Vec<u8>) -> Request {
+        Request::GetShare(crate::protocol::GetShareRequest {
+            key: "key".to_string(),
+            peer: provider.to_bytes(),
+            sender,
+            owner: None,
+            trace_id: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_malformed_sender_is_answered_and_the_provider_stays_up() {
+        let metrics: SharedMetrics = Arc::default();
+        let counted = Arc::clone(&metrics);
+        with_rate_limited_provider(None, metrics, |provider, mut requester, _| async move {
+            let malformed = get_share_request(provider, vec![1, 2, 3]);
+            let response = send_raw_request(&mut requester, provider, malformed).await;
+            let Response::InvalidRequest(rejection) = response else {
+                panic!("expected the request to be rejected, got {:?}", response);
+            };
+            assert!(rejection.reason.starts_with(INVALID_SENDER));
+            assert_eq!(counted.snapshot().invalid_requests, 1);
+
+            let valid = get_share_request(provider, requester.local_peer_id().to_bytes());
+            let response = send_raw_request(&mut requester, provider, valid).await;
+            let Response::GetShare(response) = response else {
+                panic!("expected a share response, got {:?}", response);
+            };
+            assert_eq!(response.reason.as_deref(), Some(NOT_FOUND));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_sender_must_be_the_authenticated_peer() {
+        with_provider(|provider, mut requester| async move {
+            let victim = PeerId::random();
+
+            let spoofed = get_share_request(provider, victim.to_bytes());
+            let response = send_raw_request(&mut requester, provider, spoofed).await;
+            let Response::InvalidRequest(rejection) = response else {
+                panic!("expected the request to be rejected, got {:?}", response);
+            };
+            assert!(rejection.reason.starts_with(SPOOFED_SENDER));
+
+            let spoofed = Request::ListKeys(crate::protocol::ListKeysRequest {
+                peer: provider.to_bytes(),
+                sender: victim.to_bytes(),
+                cursor: None,
+                limit: 10,
+                trace_id: None,
+            });
+            let response = send_raw_request(&mut requester, provider, spoofed).await;
+            assert!(matches!(response, Response::InvalidRequest(_)));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_unknown_key_is_answered_not_found_and_the_provider_keeps_serving() {
+        with_provider(|provider, mut owner| async move {
+            let owner_id = *owner.local_peer_id();
+            let refresh = Request::RefreshShare(crate::protocol::RefreshShareRequest {
+                key: "unknown".to_string(),
+                refresh_key: generate_refresh_key(2, 3).unwrap(),
+                #[cfg(feature = "sss16")]
+                wide_refresh_key: vec![],
+                peer: provider.to_bytes(),
+                sender: owner_id.to_bytes(),
+                epoch: Some(1),
+                relay_grant: None,
+                trace_id: None,
+            });
+            let response = send_raw_request(&mut owner, provider, refresh).await;
+            assert!(matches!(
+                response,
+                Response::RefreshShares(r) if !r.success && r.failure == Some(Failure::NotFound)
+            ));
+
+            let get = |key: &str| {
+                Request::GetShare(crate::protocol::GetShareRequest {
+                    key: key.to_string(),
+                    peer: provider.to_bytes(),
+                    sender: owner_id.to_bytes(),
+                    owner: None,
+                    trace_id: None,
+                })
+            };
+            let response = send_raw_request(&mut owner, provider, get("unknown")).await;
+            assert!(matches!(
+                response,
+                Response::GetShare(r) if !r.success && r.failure == Some(Failure::NotFound)
+            ));
+
+            // the provider is still serving
+            let register = Request::RegisterShare(RegisterShareRequest {
+                key: "known".to_string(),
+                peer: provider.to_bytes(),
+                ..register_request(&owner_id, vec![1, 2, 3])
+            });
+            let response = send_raw_request(&mut owner, provider, register).await;
+            assert!(matches!(response, Response::RegisterShare(r) if r.success));
+            let response = send_raw_request(&mut owner, provider, get("known")).await;
+            assert!(matches!(
+                response,
+                Response::GetShare(r) if r.success && r.share == (1, vec![1, 2, 3])
+            ));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_granted_reader_can_get_a_share_until_revoked() {
+        with_provider_and_reader(|provider, mut owner, mut reader| async move {
+            let owner_id = *owner.local_peer_id();
+            let reader_id = *reader.local_peer_id();
+            let register = Request::RegisterShare(RegisterShareRequest {
+                key: "key".to_string(),
+                share: (1, vec![1, 2, 3]),
+                #[cfg(feature = "sss16")]
+                field: ShareField::Gf256,
+                peer: provider.to_bytes(),
+                sender: owner_id.to_bytes(),
+                threshold: 2,
+                ttl_secs: None,
+                recreate: false,
+                refresh_interval_secs: None,
+                replace: false,
+                relay_grant: None,
+                trace_id: None,
+            });
+            let response = send_raw_request(&mut owner, provider, register).await;
+            assert!(matches!(response, Response::RegisterShare(r) if r.success));
+
+            let get_as_reader = || {
+                Request::GetShare(crate::protocol::GetShareRequest {
+                    key: "key".to_string(),
+                    peer: provider.to_bytes(),
+                    sender: reader_id.to_bytes(),
+                    owner: Some(owner_id.to_bytes()),
+                    trace_id: None,
+                })
+            };
+            let access = |sender: PeerId, reader: PeerId| AccessRequest {
+                key: "key".to_string(),
+                peer: provider.to_bytes(),
+                sender: sender.to_bytes(),
+                reader: reader.to_bytes(),
+                trace_id: None,
+            };
+
+            let response = send_raw_request(&mut reader, provider, get_as_reader()).await;
+            assert!(
+                matches!(response, Response::GetShare(r) if r.failure == Some(Failure::NotReader))
+            );
+
+            let grant = Request::GrantAccess(access(owner_id, reader_id));
+            let response = send_raw_request(&mut owner, provider, grant).await;
+            assert!(matches!(response, Response::Access(r) if r.success));
+
+            let response = send_raw_request(&mut reader, provider, get_as_reader()).await;
+            let Response::GetShare(response) = response else {
+                panic!("expected a share response, got {:?}", response);
+            };
+            assert_eq!(response.share, (1, vec![1, 2, 3]));
+
+            let regrant = Request::GrantAccess(access(reader_id, PeerId::random()));
+            let response = send_raw_request(&mut reader, provider, regrant).await;
+            assert!(matches!(
+                response,
+                Response::Access(r) if !r.success && r.failure == Some(Failure::NotFound)
+            ));
+
+            let revoke = Request::RevokeAccess(access(owner_id, reader_id));
+            let response = send_raw_request(&mut owner, provider, revoke).await;
+            assert!(matches!(response, Response::Access(r) if r.success));
+
+            let response = send_raw_request(&mut reader, provider, get_as_reader()).await;
+            assert!(
+                matches!(response, Response::GetShare(r) if r.failure == Some(Failure::NotReader))
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_clients_with_different_config_keys_cannot_read_each_others_shares() {
+        let dirs: Vec<PathBuf> = (0..2).map(|_| snapshot_path("config")).collect();
+        let keys: Vec<Keypair> = dirs
+            .iter()
+            .map(|dir| ShardConfig::load(dir).unwrap().key_or_generate().unwrap())
+            .collect();
+        let identities = (keys[0].clone(), keys[1].clone());
+        with_identified_provider(
+            None,
+            Arc::default(),
+            identities,
+            |provider, mut a, mut b| {
+                async move {
+                    let (a_id, b_id) = (*a.local_peer_id(), *b.local_peer_id());
+                    assert_ne!(a_id, b_id);
+                    let register = |sender: PeerId, share: Vec<u8>| {
+                        Request::RegisterShare(RegisterShareRequest {
+                            key: "key".to_string(),
+                            share: (1, share),
+                            #[cfg(feature = "sss16")]
+                            field: ShareField::Gf256,
+                            peer: provider.to_bytes(),
+                            sender: sender.to_bytes(),
+                            threshold: 2,
+                            ttl_secs: None,
+                            recreate: false,
+                            refresh_interval_secs: None,
+                            replace: false,
+                            relay_grant: None,
+                            trace_id: None,
+                        })
+                    };
+                    let get = |sender: PeerId, owner: Option<PeerId>| {
+                        Request::GetShare(crate::protocol::GetShareRequest {
+                            key: "key".to_string(),
+                            peer: provider.to_bytes(),
+                            sender: sender.to_bytes(),
+                            owner: owner.map(|owner| owner.to_bytes()),
+                            trace_id: None,
+                        })
+                    };
+
+                    let response =
+                        send_raw_request(&mut a, provider, register(a_id, vec![1])).await;
+                    assert!(matches!(response, Response::RegisterShare(r) if r.success));
+
+                    // the same key under another identity is another share
+                    let response = send_raw_request(&mut b, provider, get(b_id, None)).await;
+                    assert!(matches!(response, Response::GetShare(r) if r.failure.is_some()));
+                    let response = send_raw_request(&mut b, provider, get(b_id, Some(a_id))).await;
+                    let not_reader = Some(Failure::NotReader);
+                    assert!(matches!(response, Response::GetShare(r) if r.failure == not_reader));
+
+                    let response =
+                        send_raw_request(&mut b, provider, register(b_id, vec![2])).await;
+                    assert!(matches!(response, Response::RegisterShare(r) if r.success));
+                    let response = send_raw_request(&mut a, provider, get(a_id, None)).await;
+                    let Response::GetShare(response) = response else {
+                        panic!("expected a share response, got {:?}", response);
+                    };
+                    assert_eq!(response.share, (1, vec![1]));
+                }
+            },
+        )
+        .await;
+        for dir in dirs {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reloaded_config_is_applied_without_restarting_the_provider() {
+        let node = || async {
+            let (mut client, events, event_loop, peer) = crate::network::new(None).await.unwrap();
+            spawn(event_loop.run(None));
+            let addr: libp2p::Multiaddr = format!("/memory/{}", rand::random::<u64>())
+                .parse()
+                .unwrap();
+            client.start_listening(addr.clone()).await.unwrap();
+            (client, events, peer, addr)
+        };
+        let (mut client, events, provider, _) = node().await;
+        let (_bootstrapper_client, _bootstrapper_events, bootstrapper, addr) = node().await;
+        let dir = snapshot_path("reload");
+        let config = ShardConfig::load(&dir).unwrap();
+        let conf = dir.join(crate::config::CONFIG_FILE);
+        let write_conf = |toml: String| std::fs::write(&conf, toml).unwrap();
+
+        let (mut reload, reloads) = mpsc::channel(0);
+        let local = tokio::task::LocalSet::new();
+        let mut provider_client = client.clone();
+        local.spawn_local(async move {
+            run_loop(
+                DaoOptions::default(),
+                ProviderOptions::default(),
+                provider,
+                &mut provider_client,
+                events,
+                CancellationToken::new(),
+            )
+            .await
+        });
+        let watched = client.clone();
+        local.spawn_local(watch_config(config.clone(), provider, watched, reloads));
+
+        let connected = |mut client: Client, connected: bool| async move {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while client.connected_peers().await.contains(&bootstrapper) != connected {
+                assert!(Instant::now() < deadline, "bootstrapper connected: {}", !connected);
+                time::sleep(Duration::from_millis(50)).await;
+            }
+        };
+        local
+            .run_until(async {
+                assert!(!client.connected_peers().await.contains(&bootstrapper));
+                let bootstrappers =
+                    format!("bootstrappers = [\"{}/p2p/{}\"]\n", addr, bootstrapper);
+                write_conf(bootstrappers.clone());
+                reload.send(()).await.unwrap();
+                connected(client.clone(), true).await;
+
+                // a malformed configuration is ignored, and the denied peer disconnected once
+                // it is fixed
+                write_conf("denied_peers = [\"nonsense\"]\n".to_string());
+                reload.send(()).await.unwrap();
+                let denied = format!("{}denied_peers = [\"{}\"]\n", bootstrappers, bootstrapper);
+                write_conf(denied);
+                reload.send(()).await.unwrap();
+                connected(client.clone(), false).await;
+            })
+            .await;
+
+        // the configuration running is only replaced by one that loads
+        let mut running = config.clone();
+        write_conf("bootstrappers = [\"nonsense\"]\n".to_string());
+        assert!(reload_config(&mut running, provider, &mut client).await.is_err());
+        assert_eq!(running, config);
+        write_conf("network_id = \"staging\"\n".to_string());
+        let changes = reload_config(&mut running, provider, &mut client).await.unwrap();
+        assert_eq!(changes.needs_restart, ["network_id"]);
+        assert!(changes.added_bootstrappers.is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rate_limiter_refills_over_time() {
+        let peer = PeerId::random();
+        let limit = RateLimit {
+            burst: 2,
+            per_second: 4.0,
+        };
+        let mut limiter = RateLimiter::new(Some(limit), PeerId::random());
+        let start = Instant::now();
+
+        assert!(limiter.check(&peer, AuditOperation::Get, start).is_ok());
+        assert!(limiter.check(&peer, AuditOperation::Get, start).is_ok());
+        let retry_after = limiter
+            .check(&peer, AuditOperation::Get, start)
+            .unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(250));
+        // each operation has its own allowance
+        assert!(limiter.check(&peer, AuditOperation::Refresh, start).is_ok());
+
+        let later = start + Duration::from_millis(250);
+        assert!(limiter.check(&peer, AuditOperation::Get, later).is_ok());
+        assert!(limiter.check(&peer, AuditOperation::Get, later).is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_exempts_the_local_peer() {
+        let local = PeerId::random();
+        let limit = RateLimit {
+            burst: 1,
+            per_second: 0.0,
+        };
+        let mut limiter = RateLimiter::new(Some(limit), local);
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            assert!(limiter.check(&local, AuditOperation::Refresh, now).is_ok());
+        }
+        let stranger = PeerId::random();
+        assert!(limiter
+            .check(&stranger, AuditOperation::Refresh, now)
+            .is_ok());
+        let retry_after = limiter.check(&stranger, AuditOperation::Refresh, now);
+        assert_eq!(retry_after, Err(Duration::MAX));
+    }
+
+    #[tokio::test]
+    async fn test_relayed_refreshes_are_throttled_per_relaying_peer() {
+        // a single request of each operation per peer, never refilled
+        let limit = RateLimit {
+            burst: 0,
+            per_second: 0.0,
+        };
+        let (owner, relay) = (Keypair::generate_ed25519(), Keypair::generate_ed25519());
+        let keys = (owner.clone(), relay.clone());
+        with_identified_provider(
+            Some(limit),
+            Arc::default(),
+            keys,
+            |provider, mut owner_swarm, mut relay_swarm| async move {
+                let owner_id = owner.public().to_peer_id();
+                let relay_id = relay.public().to_peer_id();
+                let register = Request::RegisterShare(register_request(&owner_id, vec![1, 2]));
+                let response = send_raw_request(&mut owner_swarm, provider, register).await;
+                assert!(matches!(response, Response::RegisterShare(r) if r.success));
+
+                // the coordinator of the refreshes relays them with its grant
+                let grant = RelayGrant::signed("shared-name", relay_id, &owner).unwrap();
+                let refresh = |epoch, relay_grant| {
+                    Request::RefreshShare(crate::protocol::RefreshShareRequest {
+                        key: "shared-name".to_string(),
+                        refresh_key: generate_refresh_key(2, 2).unwrap(),
+                        #[cfg(feature = "sss16")]
+                        wide_refresh_key: vec![],
+                        peer: provider.to_bytes(),
+                        sender: owner_id.to_bytes(),
+                        epoch: Some(epoch),
+                        relay_grant,
+                        trace_id: None,
+                    })
+                };
+                let relayed = refresh(1, Some(grant.clone()));
+                let response = send_raw_request(&mut relay_swarm, provider, relayed).await;
+                assert!(matches!(response, Response::RefreshShares(r) if r.success));
+
+                // a valid grant does not lift the limit of the peer relaying it
+                let relayed = refresh(2, Some(grant));
+                let response = send_raw_request(&mut relay_swarm, provider, relayed).await;
+                assert!(matches!(response, Response::RateLimited(_)));
+
+                let stat = Request::StatShare(crate::protocol::StatShareRequest {
+                    key: "shared-name".to_string(),
+                    peer: provider.to_bytes(),
+                    sender: owner_id.to_bytes(),
+                    trace_id: None,
+                });
+                let response = send_raw_request(&mut owner_swarm, provider, stat).await;
+                let Response::StatShare(response) = response else {
+                    panic!("expected the share to be described, got {:?}", response);
+                };
+                let StatShareStatus::Found(metadata) = response.status else {
+                    panic!("expected the share to be found, got {:?}", response.status);
+                };
+                assert_eq!(metadata.epoch, 1);
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_burst_beyond_the_rate_limit_is_turned_down() {
+        let limit = RateLimit {
+            burst: 3,
+            per_second: 0.001,
+        };
+        let metrics: SharedMetrics = Arc::default();
+        let counted = Arc::clone(&metrics);
+        with_rate_limited_provider(Some(limit), metrics, |provider, mut requester, _| async move {
+            let sender = requester.local_peer_id().to_bytes();
+
+            let mut responses = Vec::new();
+            for _ in 0..8 {
+                let request = get_share_request(provider, sender.clone());
+                responses.push(send_raw_request(&mut requester, provider, request).await);
+            }
+
+            // only the allowed requests reached the share database, which had no share to give
+            let (read, limited) = responses.split_at(3);
+            for response in read {
+                assert!(matches!(response, Response::GetShare(r) if r.reason.as_deref() == Some(NOT_FOUND)));
+            }
+            for response in limited {
+                let Response::RateLimited(response) = response else {
+                    panic!("expected the request to be rate limited, got {:?}", response);
+                };
+                assert!(response.retry_after_millis > 0);
+            }
+            assert_eq!(counted.snapshot().rate_limited_requests, 5);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_registrations_gets_refreshes_and_failures() {
+        let metrics: SharedMetrics = Arc::default();
+        let counted = Arc::clone(&metrics);
+        with_rate_limited_provider(None, metrics, |provider, mut owner, _| async move {
+            let owner_id = *owner.local_peer_id();
+            let register = Request::RegisterShare(RegisterShareRequest {
+                peer: provider.to_bytes(),
+                ..register_request(&owner_id, vec![1, 2])
+            });
+            let response = send_raw_request(&mut owner, provider, register).await;
+            assert!(matches!(response, Response::RegisterShare(r) if r.success));
+
+            let get = |key: &str| {
+                Request::GetShare(crate::protocol::GetShareRequest {
+                    key: key.to_string(),
+                    peer: provider.to_bytes(),
+                    sender: owner_id.to_bytes(),
+                    owner: None,
+                    trace_id: None,
+                })
+            };
+            let response = send_raw_request(&mut owner, provider, get("shared-name")).await;
+            assert!(matches!(response, Response::GetShare(r) if r.success));
+            let response = send_raw_request(&mut owner, provider, get("missing")).await;
+            assert!(matches!(response, Response::GetShare(r) if !r.success));
+
+            let refresh = Request::RefreshShare(crate::protocol::RefreshShareRequest {
+                key: "shared-name".to_string(),
+                refresh_key: generate_refresh_key(2, 2).unwrap(),
+                #[cfg(feature = "sss16")]
+                wide_refresh_key: vec![],
+                peer: provider.to_bytes(),
+                sender: owner_id.to_bytes(),
+                epoch: Some(1),
+                relay_grant: None,
+                trace_id: None,
+            });
+            let response = send_raw_request(&mut owner, provider, refresh).await;
+            assert!(matches!(response, Response::RefreshShares(r) if r.success));
+        })
+        .await;
+
+        let snapshot = counted.snapshot();
+        assert_eq!(snapshot.registrations, 1);
+        assert_eq!(snapshot.gets_served, 1);
+        assert_eq!(snapshot.refreshes_applied, 1);
+        assert_eq!(snapshot.refreshes_initiated, 0);
+        assert_eq!(
+            snapshot.failures,
+            BTreeMap::from([("not_found".to_string(), 1)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_pass_reports_the_age_of_the_oldest_share() {
+        let local_peer_id = PeerId::random();
+        let (mut client, receiver) = test_client();
+        answer_providers(receiver, HashSet::from([local_peer_id]));
+        let dao = test_dao();
+        let now = now_unix();
+        let stale = ShareEntry {
+            last_refreshed_unix: now - 1000,
+            refresh_interval_secs: Some(3600),
+            ..entry(None)
+        };
+        let due = ShareEntry {
+            last_refreshed_unix: now - 5000,
+            ..entry(None)
+        };
+        insert_owned(&dao, "stale", &stale);
+        insert_owned(&dao, "due", &due);
+        let metrics = ProviderMetrics::default();
+        assert_eq!(metrics.snapshot().oldest_refresh_age_secs, None);
+
+        let mut coordinator = RefreshCoordinator::new(local_peer_id, 60);
+        refresh_pass(
+            &exact_schedule(60),
+            &dao,
+            &None,
+            &metrics,
+            &None,
+            &mut client,
+            &mut coordinator,
+        )
+        .await
+        .unwrap();
+
+        // the due share was refreshed by the pass, leaving the stale one the oldest
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.refreshes_initiated, 1);
+        assert_eq!(snapshot.refreshes_applied, 1);
+        let age = snapshot.oldest_refresh_age_secs.unwrap();
+        assert!((1000..1010).contains(&age), "age {age}");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_pass_publishes_the_refresh_it_initiates() {
+        let local_peer_id = PeerId::random();
+        let other = PeerId::random();
+        let (mut client, receiver) = test_client();
+        let requests = answer_providers(receiver, HashSet::from([local_peer_id, other]));
+        let dao = test_dao();
+        let due = ShareEntry {
+            last_refreshed_unix: now_unix() - 5000,
+            ..entry(None)
+        };
+        insert_owned(&dao, "due", &due);
+        let (events, mut published) = tokio::sync::mpsc::channel(8);
+
+        let mut coordinator = RefreshCoordinator::new(local_peer_id, 60);
+        refresh_pass(
+            &exact_schedule(60),
+            &dao,
+            &None,
+            &ProviderMetrics::default(),
+            &Some(events),
+            &mut client,
+            &mut coordinator,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(requests.sent.load(Ordering::SeqCst), 1);
+        let refreshed = ProviderEvent::Refreshed {
+            key: "due".to_string(),
+            epoch: 1,
+        };
+        assert_eq!(published.try_recv().unwrap(), refreshed);
+        let initiated = ProviderEvent::RefreshInitiated {
+            key: "due".to_string(),
+            peers: vec![other],
+        };
+        assert_eq!(published.try_recv().unwrap(), initiated);
+        assert!(published.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_local_refresh_is_not_sent_to_other_providers() {
+        let local_peer_id = PeerId::random();
+        let (mut client, receiver) = test_client();
+        let requests = answer_providers(receiver, HashSet::from([local_peer_id, PeerId::random()]));
+        let dao = test_dao();
+        // deleted since it was listed, so that the local refresh finds nothing to refresh
+        let gone = ShareEntry {
+            last_refreshed_unix: now_unix() - 5000,
+            ..entry(None)
+        };
+        let stored_key = owner_key(&gone.sender, "gone");
+
+        let mut coordinator = RefreshCoordinator::new(local_peer_id, 60);
+        let refreshed = refresh_entry(
+            &stored_key,
+            PeerId::from_bytes(&gone.sender).unwrap(),
+            &gone,
+            0,
+            &dao,
+            &None,
+            &ProviderMetrics::default(),
+            &None,
+            &mut client,
+            &mut coordinator,
+        )
+        .await;
+
+        assert!(!refreshed);
+        assert_eq!(requests.sent.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_share_without_a_relay_grant_is_not_refreshed_by_its_coordinator() {
+        let local_peer_id = PeerId::random();
+        let (mut client, receiver) = test_client();
+        let requests = answer_providers(receiver, HashSet::from([local_peer_id, PeerId::random()]));
+        let dao = test_dao();
+        let ungranted = ShareEntry {
+            last_refreshed_unix: now_unix() - 5000,
+            relay_grant: None,
+            ..entry(None)
+        };
+        insert_owned(&dao, "ungranted", &ungranted);
+        let stored_key = owner_key(&ungranted.sender, "ungranted");
+
+        // the other provider would refuse the refresh once it was applied locally
+        let mut coordinator = RefreshCoordinator::new(local_peer_id, 60);
+        let refreshed = refresh_entry(
+            &stored_key,
+            PeerId::from_bytes(&ungranted.sender).unwrap(),
+            &ungranted,
+            0,
+            &dao,
+            &None,
+            &ProviderMetrics::default(),
+            &None,
+            &mut client,
+            &mut coordinator,
+        )
+        .await;
+
+        assert!(!refreshed);
+        assert_eq!(requests.sent.load(Ordering::SeqCst), 0);
+        let stored = dao.lock().unwrap().get(&stored_key).unwrap().unwrap();
+        assert_eq!(stored.epoch, 0);
+    }
+
+    #[tokio::test]
+    async fn test_providers_that_do_not_apply_the_refresh_are_audited() {
+        let local_peer_id = PeerId::random();
+        let (behind, down) = (PeerId::random(), PeerId::random());
+        let (mut client, mut receiver) = test_client();
+        tokio::spawn(async move {
+            while let Some(command) = receiver.next().await {
+                match command {
+                    Command::GetProviders { sender, .. } => {
+                        let _ = sender.send(HashSet::from([local_peer_id, behind, down]));
+                    }
+                    Command::RequestRefreshShare {
+                        peer, sender_chan, ..
+                    } => {
+                        let unreachable = "unreachable".to_string();
+                        let result = match peer == behind {
+                            true => Ok(false),
+                            false => Err(crate::client::ClientError::Refused(unreachable)),
+                        };
+                        let _ = sender_chan.send(result);
+                    }
+                    _ => {}
+                }
+            }
+        });
+        let dao = test_dao();
+        let due = ShareEntry {
+            last_refreshed_unix: now_unix() - 5000,
+            ..entry(None)
+        };
+        let stored_key = owner_key(&due.sender, "due");
+        insert_owned(&dao, "due", &due);
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let audit = Arc::new(AuditLog::from_tree(db.open_tree("audit").unwrap()).unwrap());
+
+        let mut coordinator = RefreshCoordinator::new(local_peer_id, 60);
+        let refreshed = refresh_entry(
+            &stored_key,
+            PeerId::from_bytes(&due.sender).unwrap(),
+            &due,
+            0,
+            &dao,
+            &Some(Arc::clone(&audit)),
+            &ProviderMetrics::default(),
+            &None,
+            &mut client,
+            &mut coordinator,
+        )
+        .await;
+
+        // the local refresh stands, and each provider left behind is on record
+        assert!(refreshed);
+        let records = audit.read_range(0, u64::MAX).unwrap();
+        let outcomes: HashMap<Option<Vec<u8>>, AuditOutcome> = records
+            .into_iter()
+            .map(|record| (record.event.peer, record.event.outcome))
+            .collect();
+        assert_eq!(outcomes.len(), 3);
+        assert_eq!(outcomes[&None], AuditOutcome::Success);
+        assert!(matches!(&outcomes[&Some(behind.to_bytes())], AuditOutcome::Failed(_)));
+        let Some(AuditOutcome::Failed(reason)) = outcomes.get(&Some(down.to_bytes())) else {
+            panic!("expected the unreachable provider to be recorded as failed");
+        };
+        assert!(reason.contains("unreachable"));
+    }
+
+    #[test]
+    fn test_events_are_dropped_and_counted_when_the_subscriber_is_behind() {
+        let metrics = ProviderMetrics::default();
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        let events = Some(sender);
+        let served = |key: &str| ProviderEvent::Served {
+            key: key.to_string(),
+        };
+        publish_event(&events, &metrics, served("first"));
+        publish_event(&events, &metrics, served("second"));
+        publish_event(&events, &metrics, served("third"));
+        assert_eq!(receiver.try_recv().unwrap(), served("first"));
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(metrics.events_dropped(), 2);
+
+        // a subscriber that is gone, or none at all, is not behind
+        drop(receiver);
+        publish_event(&events, &metrics, served("fourth"));
+        publish_event(&None, &metrics, served("fifth"));
+        assert_eq!(metrics.events_dropped(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_provider_status_is_cached_until_it_expires() {
+        let (mut client, _events, event_loop, provider) = crate::network::new(None).await.unwrap();
+        let provider_loop = spawn(event_loop.run(None));
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr: libp2p::Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap();
+        client.start_listening(addr.clone()).await.unwrap();
+        let dao = test_dao();
+        insert_owned(&dao, "key", &entry(None));
+        let status_task = spawn(async move {
+            let mut interval = runtime::interval(Duration::from_secs(1));
+            status_loop(&mut interval, dao, Arc::default(), provider, &mut client).await;
+        });
+
+        let (mut observer, _events, event_loop, _) = crate::network::new(None).await.unwrap();
+        spawn(event_loop.run(None));
+        observer.dial(provider, addr).await.unwrap();
+
+        let wait = async {
+            loop {
+                if let Some(status) = observer.provider_statuses().await.pop() {
+                    return status;
+                }
+                time::sleep(Duration::from_millis(100)).await;
+            }
+        };
+        let status = time::timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("the provider's status within a period");
+        assert_eq!(status.peer, provider.to_bytes());
+        assert_eq!(status.shares, 1);
+        assert_eq!(status.interval_secs, 1);
+
+        // a provider that stops publishing is forgotten after the expiry periods
+        status_task.abort();
+        provider_loop.abort();
+        time::sleep(Duration::from_secs(STATUS_EXPIRY_PERIODS as u64 + 1)).await;
+        assert!(observer.provider_statuses().await.is_empty());
+    }
+
+    /// A DAO that lets another refresh land between the first read and swap of a key.
+    struct InterleavingDao {
+        inner: HashMapShareEntryDao,
+        interleaved: Mutex<Option<RefreshKey>>,
+    }
+
+    impl ShareEntryDaoTrait for InterleavingDao {
+        fn insert(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+            self.inner.insert(key, entry)
+        }
+
+        fn get(&self, key: &str) -> Result<Option<ShareEntry>, RepoError> {
+            self.inner.get(key)
+        }
+
+        fn get_page(
+            &self,
+            after: Option<&str>,
+            limit: usize,
+        ) -> Result<Vec<(String, ShareEntry)>, RepoError> {
+            self.inner.get_page(after, limit)
+        }
+
+        fn update(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+            self.inner.update(key, entry)
+        }
+
+        fn delete(&self, key: &str) -> Result<(), RepoError> {
+            self.inner.delete(key)
+        }
+
+        fn compare_and_swap(
+            &self,
+            key: &str,
+            expected: &ShareEntry,
+            new: &ShareEntry,
+        ) -> Result<bool, RepoError> {
+            if let Some(refresh_key) = self.interleaved.lock().unwrap().take() {
+                let mut other = self.inner.get(key)?.unwrap();
+                apply_refresh_key(&mut other, &refresh_key)
+                    .map_err(RepoError::InvalidRefreshKey)?;
+                other.epoch += 1;
+                self.inner.insert(key, &other)?;
+            }
+            self.inner.compare_and_swap(key, expected, new)
+        }
+
+        fn put_tombstone(&self, key: &str, tombstone: &Tombstone) -> Result<(), RepoError> {
+            self.inner.put_tombstone(key, tombstone)
+        }
+
+        fn get_tombstone(&self, key: &str) -> Result<Option<Tombstone>, RepoError> {
+            self.inner.get_tombstone(key)
+        }
+
+        fn remove_tombstone(&self, key: &str) -> Result<(), RepoError> {
+            self.inner.remove_tombstone(key)
+        }
+
+        fn purge_tombstones(&self, now: u64) -> Result<usize, RepoError> {
+            self.inner.purge_tombstones(now)
+        }
+    }
+
+    #[test]
+    fn test_interleaved_refreshes_compose() {
+        let first = RefreshKey::from(generate_refresh_key(2, 3).unwrap());
+        let second = RefreshKey::from(generate_refresh_key(2, 3).unwrap());
+        let original = entry(None);
+
+        let sequential = test_dao();
+        sequential.lock().unwrap().insert("key", &original).unwrap();
+        refresh_stored_share("key", &first, None, &sequential).unwrap();
+        refresh_stored_share("key", &second, None, &sequential).unwrap();
+        let expected = sequential.lock().unwrap().get("key").unwrap().unwrap();
+
+        let interleaved: SharedDao = Arc::new(Mutex::new(Box::new(InterleavingDao {
+            inner: HashMapShareEntryDao::new(),
+            interleaved: Mutex::new(Some(first.clone())),
+        })));
+        interleaved
+            .lock()
+            .unwrap()
+            .insert("key", &original)
+            .unwrap();
+        let refreshed = refresh_stored_share("key", &second, None, &interleaved).unwrap();
+
+        assert_eq!(refreshed.share, expected.share);
+        assert_eq!(refreshed.epoch, 2);
+        assert_eq!(
+            interleaved.lock().unwrap().get("key").unwrap().unwrap(),
+            refreshed
+        );
+    }
+
+    /// Runs `ticks` refresh intervals over the providers of one share, skipping the providers in
+    /// `failed`, and returns who initiated a refresh at each tick. Every refresh reaches every
+    /// provider and advances the epoch, so a single entry stands for all of their copies.
+    fn simulate_refreshes(
+        record: &str,
+        coordinators: &mut [RefreshCoordinator],
+        failed: &[PeerId],
+        entry: &mut ShareEntry,
+        now: &mut u64,
+    ) -> Vec<PeerId> {
+        *now += 60;
+        let providers: Vec<PeerId> = coordinators.iter().map(|c| c.local_peer_id).collect();
+        let mut initiated = Vec::new();
+        for coordinator in coordinators.iter_mut() {
+            if failed.contains(&coordinator.local_peer_id) {
+                continue;
+            }
+            if coordinator.should_initiate(record, &providers, entry, *now) {
+                coordinator.initiated(record, &providers, entry.epoch + 1);
+                initiated.push(coordinator.local_peer_id);
+            }
+        }
+        if !initiated.is_empty() {
+            entry.epoch += initiated.len() as u64;
+            entry.last_refreshed_unix = *now;
+        }
+        initiated
+    }
+
+    #[test]
+    fn test_one_coordinator_refreshes_each_share() {
+        let providers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+        let record = Client::provider_key(&PeerId::random(), "key");
+        let order = refresh_order(&record, &providers);
+        assert_eq!(order.len(), 3);
+        assert_eq!(
+            order,
+            refresh_order(&record, &[providers[2], providers[0], providers[1]])
+        );
+        let mut coordinators: Vec<RefreshCoordinator> = providers
+            .iter()
+            .map(|peer| RefreshCoordinator::new(*peer, 60))
+            .collect();
+        let mut entry = entry(None);
+        let mut now = 0;
+
+        for _ in 0..5 {
+            let initiated =
+                simulate_refreshes(&record, &mut coordinators, &[], &mut entry, &mut now);
+            assert_eq!(initiated, vec![order[0]]);
+        }
+
+        // the coordinator fails: nobody refreshes until the next in line takes over, which then
+        // refreshes every interval
+        let failed = [order[0]];
+        let takeover = REFRESH_TAKEOVER_INTERVALS as usize;
+        for _ in 1..takeover {
+            let initiated =
+                simulate_refreshes(&record, &mut coordinators, &failed, &mut entry, &mut now);
+            assert!(initiated.is_empty());
+        }
+        for _ in 0..5 {
+            let initiated =
+                simulate_refreshes(&record, &mut coordinators, &failed, &mut entry, &mut now);
+            assert_eq!(initiated, vec![order[1]]);
+        }
+
+        // once the coordinator is back, the successor steps down after seeing its refresh
+        let initiated = simulate_refreshes(&record, &mut coordinators, &[], &mut entry, &mut now);
+        assert!(initiated.contains(&order[0]));
+        for _ in 0..5 {
+            let initiated =
+                simulate_refreshes(&record, &mut coordinators, &[], &mut entry, &mut now);
+            assert_eq!(initiated, vec![order[0]]);
+        }
+    }
+
+    #[test]
+    fn test_refresh_key_follows_share_threshold() {
+        let secret = b"threshold four";
+        let shares = crate::sss::split_secret(secret, 4, 7).unwrap();
+        let entry = ShareEntry {
+            share: (1, shares[&1].clone()),
+            sender: PeerId::random().to_bytes(),
+            threshold: 4,
+            ..Default::default()
+        };
+
+        #[cfg(not(feature = "sss16"))]
+        let RefreshKey::Gf256(refresh_key) = refresh_key_for(&entry).unwrap();
+        #[cfg(feature = "sss16")]
+        let RefreshKey::Gf256(refresh_key) = refresh_key_for(&entry).unwrap() else {
+            panic!("a share over GF(2^8) refreshed over GF(2^16)");
+        };
+        assert_eq!(refresh_key.len(), secret.len());
+        for polynomial in &refresh_key {
+            assert_eq!(polynomial.coefficients.len(), 4);
+            assert_eq!(u8::from(polynomial.coefficients[0]), 0);
+        }
+
+        let mut refreshed = shares.clone();
+        for (index, share) in refreshed.iter_mut() {
+            refresh_share((index, share), &refresh_key).unwrap();
+        }
+        assert_ne!(refreshed, shares);
+        let four: HashMap<u8, Vec<u8>> = refreshed
+            .iter()
+            .filter(|(index, _)| [2, 3, 5, 7].contains(*index))
+            .map(|(index, share)| (*index, share.clone()))
+            .collect();
+        assert_eq!(crate::sss::combine_shares(&four).unwrap(), secret.to_vec());
+    }
+
+    #[cfg(feature = "sss16")]
+    #[test]
+    fn test_share_over_gf65536_is_refreshed_over_its_field() {
+        let secret = b"wide";
+        let shares = sss16::split_secret(secret, 3, 300).unwrap();
+        let dao = test_dao();
+        for index in [1, 150, 300] {
+            let entry = ShareEntry {
+                share: (index, shares[&index].clone()),
+                field: ShareField::Gf65536,
+                sender: PeerId::random().to_bytes(),
+                threshold: 3,
+                ..Default::default()
+            };
+            let key = index.to_string();
+            dao.lock().unwrap().insert(&key, &entry).unwrap();
+        }
+
+        let stored = dao.lock().unwrap().get("1").unwrap().unwrap();
+        let refresh_key = refresh_key_for(&stored).unwrap();
+        assert!(matches!(&refresh_key, RefreshKey::Gf65536(key) if key.len() == secret.len()));
+        let narrow = RefreshKey::from(generate_refresh_key(3, secret.len()).unwrap());
+        let refused = refresh_stored_share("1", &narrow, None, &dao);
+        assert!(matches!(
+            refused,
+            Err(RepoError::InvalidRefreshKey(sss::Error::KeyField))
+        ));
+
+        let mut refreshed = HashMap::new();
+        for index in [1u16, 150, 300] {
+            let key = index.to_string();
+            let entry = refresh_stored_share(&key, &refresh_key, None, &dao).unwrap();
+            assert_ne!(entry.share.1, shares[&index]);
+            refreshed.insert(index, entry.share.1);
+        }
+        assert_eq!(sss16::combine_shares(&refreshed).unwrap(), secret.to_vec());
+    }
+
+    #[test]
+    fn test_refresh_records_epoch_and_time() {
+        let dao = test_dao();
+        dao.lock().unwrap().insert("key", &entry(None)).unwrap();
+        let refresh_key = RefreshKey::from(generate_refresh_key(2, 3).unwrap());
+
+        let before = now_unix();
+        let refreshed = refresh_stored_share("key", &refresh_key, Some(5), &dao).unwrap();
+        let after = now_unix();
+        assert_eq!(refreshed.epoch, 5);
+        assert!((before..=after).contains(&refreshed.last_refreshed_unix));
+        assert_eq!(dao.lock().unwrap().get("key").unwrap().unwrap(), refreshed);
+
+        // without an epoch, the stored epoch moves forward by one
+        let refreshed = refresh_stored_share("key", &refresh_key, None, &dao).unwrap();
+        assert_eq!(refreshed.epoch, 6);
+        assert_eq!(refreshed.refresh_digest, Some(refresh_digest(&refresh_key)));
+    }
+
+    #[test]
+    fn test_redelivered_refresh_is_applied_once() {
+        let dao = test_dao();
+        dao.lock().unwrap().insert("key", &entry(None)).unwrap();
+        let refresh_key = RefreshKey::from(generate_refresh_key(2, 3).unwrap());
+
+        let refreshed = refresh_stored_share("key", &refresh_key, Some(1), &dao).unwrap();
+        let redelivered = refresh_stored_share("key", &refresh_key, Some(1), &dao).unwrap();
+        assert_eq!(redelivered, refreshed);
+        assert_eq!(dao.lock().unwrap().get("key").unwrap().unwrap(), refreshed);
+
+        // a different refresh key at the same epoch is not a redelivery
+        let other_key = RefreshKey::from(generate_refresh_key(2, 3).unwrap());
+        let err = refresh_stored_share("key", &other_key, Some(1), &dao).unwrap_err();
+        assert!(matches!(
+            err,
+            RepoError::StaleEpoch {
+                stored: 1,
+                requested: 1
+            }
+        ));
+        assert_eq!(dao.lock().unwrap().get("key").unwrap().unwrap(), refreshed);
+    }
+
+    #[test]
+    fn test_stale_refresh_epoch_is_refused() {
+        let dao = test_dao();
+        dao.lock().unwrap().insert("key", &entry(None)).unwrap();
+        let older = RefreshKey::from(generate_refresh_key(2, 3).unwrap());
+        let newer = RefreshKey::from(generate_refresh_key(2, 3).unwrap());
+
+        let refreshed = refresh_stored_share("key", &newer, Some(4), &dao).unwrap();
+        let err = refresh_stored_share("key", &older, Some(3), &dao).unwrap_err();
+        assert!(matches!(
+            err,
+            RepoError::StaleEpoch {
+                stored: 4,
+                requested: 3
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "stale refresh epoch 3, the share is at epoch 4"
+        );
+        assert_eq!(dao.lock().unwrap().get("key").unwrap().unwrap(), refreshed);
+    }
+
+    /// Counts the refresh requests a provider sends to its peers, and keeps the messages it
+    /// publishes.
+    #[derive(Default)]
+    struct RefreshRequests {
+        sent: AtomicUsize,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+        published: Mutex<Vec<GossipMessage>>,
+    }
+
+    /// Answers every provider lookup with `providers`, accepts every refresh request after a
+    /// millisecond, as the network would, and publishes every message.
+    fn answer_providers(
+        mut receiver: mpsc::Receiver<Command>,
+        providers: HashSet<PeerId>,
+    ) -> Arc<RefreshRequests> {
+        let requests = Arc::new(RefreshRequests::default());
+        let counted = Arc::clone(&requests);
+        tokio::spawn(async move {
+            while let Some(command) = receiver.next().await {
+                match command {
+                    Command::GetProviders { sender, .. } => {
+                        let _ = sender.send(providers.clone());
+                    }
+                    Command::RequestRefreshShare { sender_chan, .. } => {
+                        counted.sent.fetch_add(1, Ordering::SeqCst);
+                        let in_flight = counted.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        counted.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+                        let counted = Arc::clone(&counted);
+                        tokio::spawn(async move {
+                            time::sleep(Duration::from_millis(1)).await;
+                            counted.in_flight.fetch_sub(1, Ordering::SeqCst);
+                            let _ = sender_chan.send(Ok(true));
+                        });
+                    }
+                    Command::Publish { message, sender } => {
+                        counted.published.lock().unwrap().push(message);
+                        let _ = sender.send(Ok(()));
+                    }
+                    _ => {}
+                }
+            }
+        });
+        requests
+    }
+
+    /// A schedule without jitter or pauses, so that shares are due exactly on their interval.
+    fn exact_schedule(default_interval: u64) -> RefreshSchedule {
+        RefreshSchedule {
+            default_interval,
+            jitter_percent: 0,
+            key_delay: Duration::ZERO,
+            replication_margin: 0,
+        }
+    }
+
+    /// A DAO whose entry under `corrupt` fails to decode, as a damaged sled value would.
+    struct CorruptDao {
+        inner: HashMapShareEntryDao,
+        corrupt: String,
+    }
+
+    impl CorruptDao {
+        fn check(&self, key: &str) -> Result<(), RepoError> {
+            if key == self.corrupt {
+                return Err(RepoError::CorruptEntry {
+                    key: key.to_string(),
+                });
+            }
+            Ok(())
+        }
+    }
+
+    impl ShareEntryDaoTrait for CorruptDao {
+        fn insert(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+            self.inner.insert(key, entry)
+        }
+
+        fn get(&self, key: &str) -> Result<Option<ShareEntry>, RepoError> {
+            self.check(key)?;
+            self.inner.get(key)
+        }
+
+        fn get_page(
+            &self,
+            after: Option<&str>,
+            limit: usize,
+        ) -> Result<Vec<(String, ShareEntry)>, RepoError> {
+            let page = self.inner.get_page(after, limit)?;
+            for (key, _) in &page {
+                self.check(key)?;
+            }
+            Ok(page)
+        }
+
+        fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, RepoError> {
+            self.inner.keys_with_prefix(prefix)
+        }
+
+        fn update(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+            self.inner.update(key, entry)
+        }
+
+        fn delete(&self, key: &str) -> Result<(), RepoError> {
+            self.inner.delete(key)
+        }
+
+        fn compare_and_swap(
+            &self,
+            key: &str,
+            expected: &ShareEntry,
+            new: &ShareEntry,
+        ) -> Result<bool, RepoError> {
+            self.inner.compare_and_swap(key, expected, new)
+        }
+
+        fn put_tombstone(&self, key: &str, tombstone: &Tombstone) -> Result<(), RepoError> {
+            self.inner.put_tombstone(key, tombstone)
+        }
+
+        fn get_tombstone(&self, key: &str) -> Result<Option<Tombstone>, RepoError> {
+            self.inner.get_tombstone(key)
+        }
+
+        fn remove_tombstone(&self, key: &str) -> Result<(), RepoError> {
+            self.inner.remove_tombstone(key)
+        }
+
+        fn purge_tombstones(&self, now: u64) -> Result<usize, RepoError> {
+            self.inner.purge_tombstones(now)
+        }
+    }
+
+    #[test]
+    fn test_integrity_scan_quarantines_damaged_shares() {
+        let path = snapshot_path("integrity");
+        let db_path = path.to_str().unwrap().to_string();
+        let good = entry(None);
+        let bad_owner = ShareEntry {
+            sender: b"not a peer id".to_vec(),
+            ..entry(None)
+        };
+        let corrupt_owner = PeerId::random();
+        let corrupt_key = owner_key(&corrupt_owner.to_bytes(), "corrupt");
+        let bad_owner_key = owner_key(&bad_owner.sender, "bad-owner");
+        {
+            let seeded = SledShareEntryDao::new(&db_path).unwrap();
+            seeded.insert_owned("good", &good).unwrap();
+            seeded.insert_owned("bad-owner", &bad_owner).unwrap();
+        }
+        {
+            // a value whose payload no longer matches its checksum
+            let mut value = crate::repository::encode_entry(&entry(None)).unwrap();
+            *value.last_mut().unwrap() ^= 0xff;
+            let db = reopen_sled(&db_path);
+            db.insert(corrupt_key.as_bytes(), value).unwrap();
+            db.flush().unwrap();
+        }
+        // opening the database, which migrates it, steps over the damaged entries
+        let dao = reopen_dao(|| DaoOptions {
+            backend: Some(DbBackend::Sled),
+            db_path: Some(db_path.clone()),
+            ..Default::default()
+        });
+
+        let expected = IntegrityReport {
+            ok: 1,
+            corrupt: vec![corrupt_key.clone()],
+            bad_owner: vec![bad_owner_key.clone()],
+            quarantined: 0,
+        };
+        assert_eq!(scan_integrity(&dao, false).unwrap(), expected);
+        assert_eq!(dao.lock().unwrap().keys().unwrap().len(), 3);
+
+        assert_eq!(scan_integrity(&dao, true).unwrap(), expected);
+        let clean = IntegrityReport {
+            ok: 1,
+            quarantined: 2,
+            ..Default::default()
+        };
+        assert_eq!(scan_integrity(&dao, false).unwrap(), clean);
+        {
+            let dao = dao.lock().unwrap();
+            assert_eq!(dao.keys().unwrap(), vec![owner_key(&good.sender, "good")]);
+            let mut quarantined = vec![corrupt_key, bad_owner_key];
+            quarantined.sort();
+            assert_eq!(dao.quarantined_keys().unwrap(), quarantined);
+        }
+
+        // the owner of a quarantined share is told to register it again
+        assert_eq!(
+            read_share("corrupt", &corrupt_owner, &corrupt_owner, &dao),
+            Err(Failure::Corrupt)
+        );
+        let good_owner = PeerId::from_bytes(&good.sender).unwrap();
+        assert_eq!(
+            read_share("good", &good_owner, &good_owner, &dao).map(|share| share.share),
+            Ok(good.share)
+        );
+        drop(dao);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_refresh_pass_skips_unreadable_shares() {
+        let local_peer_id = PeerId::random();
+        let (mut client, receiver) = test_client();
+        answer_providers(receiver, HashSet::from([local_peer_id]));
+
+        let good = entry(None);
+        let corrupt = entry(None);
+        let bad_owner = ShareEntry {
+            sender: b"not a peer id".to_vec(),
+            ..entry(None)
+        };
+        let corrupt_key = owner_key(&corrupt.sender, "corrupt");
+        let bad_owner_key = owner_key(&bad_owner.sender, "bad-owner");
+        let inner = HashMapShareEntryDao::new();
+        inner.insert_owned("good", &good).unwrap();
+        inner.insert_owned("corrupt", &corrupt).unwrap();
+        inner.insert_owned("bad-owner", &bad_owner).unwrap();
+        let dao: SharedDao = Arc::new(Mutex::new(Box::new(CorruptDao {
+            inner,
+            corrupt: corrupt_key.clone(),
+        })));
+        // reading the corrupt entry in a page fails the whole page
+        assert!(dao.lock().unwrap().get_all().is_err());
+
+        let mut coordinator = RefreshCoordinator::new(local_peer_id, 60);
+        let mut skipped = refresh_pass(
+            &exact_schedule(30),
+            &dao,
+            &None,
+            &ProviderMetrics::default(),
+            &None,
+            &mut client,
+            &mut coordinator,
+        )
+        .await
+        .unwrap();
+
+        skipped.sort();
+        let mut expected = vec![corrupt_key, bad_owner_key];
+        expected.sort();
+        assert_eq!(skipped, expected);
+        let refreshed = dao
+            .lock()
+            .unwrap()
+            .get_owned(&good.sender, "good")
+            .unwrap()
+            .unwrap();
+        assert_eq!(refreshed.epoch, 1);
+        assert_ne!(refreshed.share, good.share);
+    }
+
+    #[test]
+    fn test_read_share_reports_each_failure() {
+        let owned = entry(None);
+        let owner = PeerId::from_bytes(&owned.sender).unwrap();
+        let stranger = PeerId::random();
+        let foreign = entry(None);
+        let inner = HashMapShareEntryDao::new();
+        inner.insert_owned("owned", &owned).unwrap();
+        inner.insert_owned("corrupt", &owned).unwrap();
+        // a share stored in the owner's namespace that records another owner
+        inner
+            .insert(&owner_key(&owned.sender, "foreign"), &foreign)
+            .unwrap();
+        let dao: SharedDao = Arc::new(Mutex::new(Box::new(CorruptDao {
+            inner,
+            corrupt: owner_key(&owned.sender, "corrupt"),
+        })));
+
+        assert_eq!(
+            read_share("owned", &owner, &owner, &dao).map(|share| share.share),
+            Ok(owned.share)
+        );
+        assert_eq!(
+            read_share("missing", &owner, &owner, &dao),
+            Err(Failure::NotFound)
+        );
+        assert_eq!(
+            read_share("owned", &stranger, &owner, &dao),
+            Err(Failure::NotReader)
+        );
+        let expected = owner.to_base58()[..PEER_PREFIX_LEN].to_string();
+        let actual =
+            PeerId::from_bytes(&foreign.sender).unwrap().to_base58()[..PEER_PREFIX_LEN].to_string();
+        assert_eq!(
+            read_share("foreign", &owner, &owner, &dao),
+            Err(Failure::NotOwner { expected, actual })
+        );
+        assert_eq!(
+            read_share("corrupt", &owner, &owner, &dao),
+            Err(Failure::Corrupt)
+        );
+    }
+
+    #[test]
+    fn test_storage_errors_map_to_their_failure() {
+        let cases: Vec<(RepoError, Failure)> = vec![
+            (
+                RepoError::QuotaExceeded {
+                    limit: "max_total_bytes",
+                    max: 1024,
+                    used: 1000,
+                },
+                Failure::QuotaExceeded(QuotaUsage {
+                    limit: "max_total_bytes".to_string(),
+                    max: 1024,
+                    used: 1000,
+                }),
+            ),
+            (
+                RepoError::RecentlyDeleted("key".to_string()),
+                Failure::RecentlyDeleted,
+            ),
+            (
+                RepoError::StaleEpoch {
+                    stored: 4,
+                    requested: 3,
+                },
+                Failure::StaleEpoch {
+                    stored: 4,
+                    requested: 3,
+                },
+            ),
+            (
+                RepoError::InvalidRefreshKey(sss::Error::NonZeroConstant(0)),
+                Failure::InvalidRequest(
+                    "invalid refresh key: polynomial 0 has a non-zero constant term".to_string(),
+                ),
+            ),
+            (
+                RepoError::CorruptEntry {
+                    key: "key".to_string(),
+                },
+                Failure::Corrupt,
+            ),
+            (
+                RepoError::ReadOnly,
+                Failure::StorageError(RepoError::ReadOnly.to_string()),
+            ),
+        ];
+        for (error, failure) in cases {
+            assert_eq!(storage_failure(error), failure);
+        }
+        assert_eq!(
+            storage_failure(std::io::Error::other("disk full").into()),
+            Failure::StorageError("disk full".to_string())
+        );
+    }
+
+    #[test]
+    fn test_failed_handlers_never_report_success() {
+        let metrics = ProviderMetrics::default();
+        assert_eq!(
+            handler_outcome(&Ok::<(), Failure>(()), &metrics).unwrap(),
+            AuditOutcome::Success
+        );
+        let refusals = vec![
+            Failure::NotFound,
+            Failure::NotOwner {
+                expected: "a".to_string(),
+                actual: "b".to_string(),
+            },
+            Failure::NotReader,
+            Failure::InvalidRequest("malformed reader".to_string()),
+            Failure::RecentlyDeleted,
+            Failure::StaleEpoch {
+                stored: 2,
+                requested: 1,
+            },
+        ];
+        for failure in &refusals {
+            let outcome = handler_outcome(&Err::<(), _>(failure.clone()), &metrics).unwrap();
+            assert_eq!(outcome, AuditOutcome::Refused(failure.to_string()));
+        }
+        let failed = handler_outcome(
+            &Err::<(), _>(Failure::StorageError("io".to_string())),
+            &metrics,
+        );
+        assert!(matches!(
+            failed,
+            Err(Error::Failure(Failure::StorageError(ref reason))) if reason == "io"
+        ));
+
+        // every failure is counted once under its class
+        let failures = metrics.snapshot().failures;
+        assert_eq!(failures.len(), refusals.len() + 1);
+        assert!(failures.values().all(|count| *count == 1));
+        assert_eq!(failures.get("storage_error"), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_shares_are_refreshed_on_their_own_interval() {
+        let local_peer_id = PeerId::random();
+        let (mut client, receiver) = test_client();
+        answer_providers(receiver, HashSet::from([local_peer_id]));
+        let dao = test_dao();
+        let now = now_unix();
+        let every_ten_minutes = |last_refreshed_unix| ShareEntry {
+            refresh_interval_secs: Some(600),
+            last_refreshed_unix,
+            ..entry(None)
+        };
+        let early = every_ten_minutes(now - 300);
+        let due = every_ten_minutes(now - 600);
+        let default = ShareEntry {
+            last_refreshed_unix: now - 600,
+            ..entry(None)
+        };
+        insert_owned(&dao, "early", &early);
+        insert_owned(&dao, "due", &due);
+        insert_owned(&dao, "default", &default);
+
+        let mut coordinator = RefreshCoordinator::new(local_peer_id, 1800);
+        let skipped = refresh_pass(
+            &exact_schedule(1800),
+            &dao,
+            &None,
+            &ProviderMetrics::default(),
+            &None,
+            &mut client,
+            &mut coordinator,
+        )
+        .await
+        .unwrap();
+
+        assert!(skipped.is_empty());
+        let epoch = |entry: &ShareEntry, key| {
+            let dao = dao.lock().unwrap();
+            dao.get_owned(&entry.sender, key).unwrap().unwrap().epoch
+        };
+        assert_eq!(epoch(&early, "early"), 0);
+        assert_eq!(epoch(&due, "due"), 1);
+        assert_eq!(epoch(&default, "default"), 0);
+    }
+
+    #[test]
+    fn test_replication_alert_below_threshold_plus_margin() {
+        let share_entry = ShareEntry {
+            threshold: 3,
+            ..entry(None)
+        };
+        let owner = PeerId::from_bytes(&share_entry.sender).unwrap();
+        let providers: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+
+        assert_eq!(
+            replication_alert("key", &owner, &share_entry, &providers, 1),
+            None
+        );
+        let alert = replication_alert("key", &owner, &share_entry, &providers, 2).unwrap();
+        assert_eq!(
+            alert,
+            UnderReplicatedAlert {
+                key: "key".to_string(),
+                owner: owner.to_bytes(),
+                providers: 4,
+                required: 5,
+            }
+        );
+        // a provider listed twice is counted once
+        let duplicated = [providers[0], providers[0], providers[1], providers[2]];
+        assert!(replication_alert("key", &owner, &share_entry, &duplicated, 1).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_pass_alerts_on_under_replicated_shares() {
+        let local_peer_id = PeerId::random();
+        let (mut client, receiver) = test_client();
+        let requests = answer_providers(receiver, HashSet::from([PeerId::random()]));
+        let dao = test_dao();
+        let thin = entry(None);
+        let owner = PeerId::from_bytes(&thin.sender).unwrap();
+        insert_owned(&dao, "thin", &thin);
+
+        let mut coordinator = RefreshCoordinator::new(local_peer_id, 60);
+        let schedule = exact_schedule(60).with_replication_margin(1);
+        refresh_pass(
+            &schedule,
+            &dao,
+            &None,
+            &ProviderMetrics::default(),
+            &None,
+            &mut client,
+            &mut coordinator,
+        )
+        .await
+        .unwrap();
 
-        // get all the shares
-        let shares = dao_clone.lock().unwrap().get_all().unwrap();
-        debug!("shares: {:?}", shares);
-
-        // iterate over the shares and refresh them
-        for (key, share_entry) in shares.iter() {
-            debug!("key: {:?}", key);
-            debug!("share_entry: {:?}", share_entry);
-            let sender = PeerId::from_bytes(&share_entry.sender).unwrap();
-            debug!("sender: {:?}", sender);
-
-            // determine the threshold from the share
-            let secret_len = share_entry.share.1.len();
-            // generate a new refresh key
-            let refresh_key =
-                generate_refresh_key(share_entry.threshold as usize, secret_len).unwrap();
-            debug!("🔑 Refresh Key: {:#?}", refresh_key);
-
-            // get the providers for the share
-            let providers = network_client_clone.get_providers(key.clone()).await;
-            if providers.is_empty() {
-                error!("Could not find provider for share {key}.");
-                continue;
+        // the local node holds the share too, so two of the three required providers are left
+        let published = requests.published.lock().unwrap().clone();
+        assert_eq!(
+            published,
+            vec![GossipMessage::UnderReplicated(UnderReplicatedAlert {
+                key: "thin".to_string(),
+                owner: owner.to_bytes(),
+                providers: 2,
+                required: 3,
+            })]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_pass_is_quiet_with_enough_providers() {
+        let local_peer_id = PeerId::random();
+        let (mut client, receiver) = test_client();
+        let requests = answer_providers(
+            receiver,
+            HashSet::from([local_peer_id, PeerId::random(), PeerId::random()]),
+        );
+        let dao = test_dao();
+        insert_owned(&dao, "replicated", &entry(None));
+
+        let mut coordinator = RefreshCoordinator::new(local_peer_id, 60);
+        let schedule = exact_schedule(60).with_replication_margin(1);
+        refresh_pass(
+            &schedule,
+            &dao,
+            &None,
+            &ProviderMetrics::default(),
+            &None,
+            &mut client,
+            &mut coordinator,
+        )
+        .await
+        .unwrap();
+
+        assert!(requests.published.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_refresh_tick_is_at_most_the_default_interval() {
+        assert_eq!(
+            RefreshSchedule::new(DEFAULT_REFRESH_SECONDS, 10).tick(),
+            Duration::from_secs(REFRESH_TICK_SECONDS)
+        );
+        assert_eq!(RefreshSchedule::new(10, 10).tick(), Duration::from_secs(10));
+        assert_eq!(RefreshSchedule::new(0, 10).tick(), Duration::from_secs(1));
+    }
+
+    // the paused clock of tokio only drives tokio timers
+    #[cfg(not(feature = "rt-async-std"))]
+    #[tokio::test(start_paused = true)]
+    async fn test_first_refresh_check_is_delayed_within_the_jitter() {
+        use rand::SeedableRng;
+
+        let schedule = RefreshSchedule::new(DEFAULT_REFRESH_SECONDS, 10);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut delays = Vec::new();
+        for _ in 0..5 {
+            let start = time::Instant::now();
+            schedule.ticker(&mut rng).tick().await;
+            delays.push(start.elapsed());
+        }
+
+        assert!(delays.iter().all(|delay| *delay <= schedule.tick() / 10));
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+        let start = time::Instant::now();
+        RefreshSchedule::new(DEFAULT_REFRESH_SECONDS, 0)
+            .ticker(&mut rng)
+            .tick()
+            .await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_due_times_are_spread_within_the_jitter() {
+        let schedule = RefreshSchedule::new(1_000, 10);
+        let share_entry = ShareEntry {
+            last_refreshed_unix: 1_700_000_000,
+            ..entry(None)
+        };
+        let due: Vec<u64> = (0..1000)
+            .map(|i| schedule.due_after(&format!("key-{i}"), &share_entry))
+            .collect();
+
+        assert!(due.iter().all(|due| (900..=1_100).contains(due)));
+        assert!(due.iter().any(|due| *due < 950) && due.iter().any(|due| *due > 1_050));
+        // stable between checks, and follows the share's own interval
+        assert_eq!(schedule.due_after("key-0", &share_entry), due[0]);
+        let hourly = ShareEntry {
+            refresh_interval_secs: Some(3_600),
+            ..share_entry.clone()
+        };
+        assert!((3_240..=3_960).contains(&schedule.due_after("key-0", &hourly)));
+        assert_eq!(
+            exact_schedule(1_000).due_after("key-0", &share_entry),
+            1_000
+        );
+    }
+
+    // the paused clock of tokio only drives tokio timers
+    #[cfg(not(feature = "rt-async-std"))]
+    #[tokio::test(start_paused = true)]
+    async fn test_refresh_pass_bounds_in_flight_refreshes() {
+        let local_peer_id = PeerId::random();
+        let (mut client, receiver) = test_client();
+        let providers = HashSet::from([local_peer_id, PeerId::random(), PeerId::random()]);
+        let requests = answer_providers(receiver, providers);
+        let dao = test_dao();
+        for i in 0..1000 {
+            insert_owned(&dao, &format!("key-{i}"), &entry(None));
+        }
+
+        let mut coordinator = RefreshCoordinator::new(local_peer_id, 1800);
+        let schedule = RefreshSchedule::new(1800, 10);
+        let start = time::Instant::now();
+        let skipped = refresh_pass(
+            &schedule,
+            &dao,
+            &None,
+            &ProviderMetrics::default(),
+            &None,
+            &mut client,
+            &mut coordinator,
+        )
+        .await
+        .unwrap();
+
+        assert!(skipped.is_empty());
+        // every share is refreshed on both peers, one share at a time and spread over the pass
+        assert_eq!(requests.sent.load(Ordering::SeqCst), 2000);
+        assert_eq!(requests.max_in_flight.load(Ordering::SeqCst), 2);
+        assert!(start.elapsed() >= schedule.key_delay * 1000);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_refresh_keys_are_refused_without_mutation() {
+        let dao = test_dao();
+        let (mut client, _receiver) = test_client();
+        let owned = entry(None);
+        let owner = PeerId::from_bytes(&owned.sender).unwrap();
+        insert_owned(&dao, "key", &owned);
+
+        // one byte short of the share
+        let short = RefreshKey::from(generate_refresh_key(2, 2).unwrap());
+        let metrics = ProviderMetrics::default();
+        let outcome = execute_refresh_share(
+            "key",
+            &owner,
+            &short,
+            None,
+            None,
+            &dao,
+            &metrics,
+            &mut client,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            outcome,
+            AuditOutcome::Refused(
+                "invalid refresh key: refresh key has 2 polynomials for a share of 3 bytes"
+                    .to_string()
+            )
+        );
+
+        // a non-zero constant term would move the secret
+        let mut shifting = generate_refresh_key(2, 3).unwrap();
+        shifting[1].coefficients[0] = gf256::new(9);
+        let outcome = execute_refresh_share(
+            "key",
+            &owner,
+            &RefreshKey::from(shifting),
+            None,
+            None,
+            &dao,
+            &metrics,
+            &mut client,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(outcome, AuditOutcome::Refused(reason) if reason.contains("constant")));
+
+        // a degree above the threshold would need more shares to recover the secret
+        let steeper = RefreshKey::from(generate_refresh_key(3, 3).unwrap());
+        let err = refresh_stored_share(&owner_key(&owned.sender, "key"), &steeper, None, &dao)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            RepoError::InvalidRefreshKey(_)
+        ));
+
+        let stored = dao.lock().unwrap().get_owned(&owned.sender, "key").unwrap();
+        assert_eq!(stored, Some(owned));
+    }
+
+    #[test]
+    fn test_registration_resets_epoch() {
+        let sender = PeerId::random();
+        let request = RegisterShareRequest {
+            key: "key".to_string(),
+            share: (1, vec![1, 2, 3]),
+            #[cfg(feature = "sss16")]
+            field: ShareField::Gf256,
+            peer: PeerId::random().to_bytes(),
+            sender: sender.to_bytes(),
+            threshold: 2,
+            ttl_secs: Some(60),
+            recreate: false,
+            refresh_interval_secs: None,
+            replace: false,
+            relay_grant: None,
+            trace_id: None,
+        };
+
+        let registered = registered_entry(&sender, &request, 1_000);
+        assert_eq!(registered.epoch, 0);
+        assert_eq!(registered.last_refreshed_unix, 1_000);
+        assert_eq!(registered.expires_at, Some(1_060));
+        assert_eq!(registered.sender, sender.to_bytes());
+    }
+
+    /// A DAO that records, at each flush, how many entries had been written.
+    struct FlushSpyDao {
+        inner: HashMapShareEntryDao,
+        flushed_at: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl ShareEntryDaoTrait for FlushSpyDao {
+        fn insert(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+            self.inner.insert(key, entry)
+        }
+
+        fn get(&self, key: &str) -> Result<Option<ShareEntry>, RepoError> {
+            self.inner.get(key)
+        }
+
+        fn get_page(
+            &self,
+            after: Option<&str>,
+            limit: usize,
+        ) -> Result<Vec<(String, ShareEntry)>, RepoError> {
+            self.inner.get_page(after, limit)
+        }
+
+        fn update(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+            self.inner.update(key, entry)
+        }
+
+        fn delete(&self, key: &str) -> Result<(), RepoError> {
+            self.inner.delete(key)
+        }
+
+        fn compare_and_swap(
+            &self,
+            key: &str,
+            expected: &ShareEntry,
+            new: &ShareEntry,
+        ) -> Result<bool, RepoError> {
+            self.inner.compare_and_swap(key, expected, new)
+        }
+
+        fn put_tombstone(&self, key: &str, tombstone: &Tombstone) -> Result<(), RepoError> {
+            self.inner.put_tombstone(key, tombstone)
+        }
+
+        fn get_tombstone(&self, key: &str) -> Result<Option<Tombstone>, RepoError> {
+            self.inner.get_tombstone(key)
+        }
+
+        fn remove_tombstone(&self, key: &str) -> Result<(), RepoError> {
+            self.inner.remove_tombstone(key)
+        }
+
+        fn purge_tombstones(&self, now: u64) -> Result<usize, RepoError> {
+            self.inner.purge_tombstones(now)
+        }
+
+        fn flush(&self) -> Result<(), RepoError> {
+            let written = self.inner.map.lock().unwrap().len();
+            self.flushed_at.lock().unwrap().push(written);
+            Ok(())
+        }
+    }
+
+    fn flush_spy() -> (SharedDao, Arc<Mutex<Vec<usize>>>) {
+        let flushed_at = Arc::new(Mutex::new(Vec::new()));
+        let spy = FlushSpyDao {
+            inner: HashMapShareEntryDao::new(),
+            flushed_at: Arc::clone(&flushed_at),
+        };
+        (Arc::new(Mutex::new(Box::new(spy))), flushed_at)
+    }
+
+    #[test]
+    fn test_registration_is_flushed_before_it_is_acknowledged() {
+        let (dao, flushed_at) = flush_spy();
+        let owner = PeerId::random();
+
+        store_registered_share(&owner, &register_request(&owner, vec![1, 2]), &dao).unwrap();
+
+        assert_eq!(*flushed_at.lock().unwrap(), vec![1]);
+    }
+
+    // the paused clock of tokio only drives tokio timers
+    #[cfg(not(feature = "rt-async-std"))]
+    #[tokio::test(start_paused = true)]
+    async fn test_flush_loop_flushes_on_each_tick() {
+        let (dao, flushed_at) = flush_spy();
+        let dao_clone = Arc::clone(&dao);
+        let task = spawn(async move {
+            let mut interval = runtime::interval(Duration::from_secs(5));
+            flush_loop(&mut interval, dao_clone).await;
+        });
+
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(flushed_at.lock().unwrap().len(), 1);
+
+        dao.lock().unwrap().insert("key", &entry(None)).unwrap();
+        time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(*flushed_at.lock().unwrap(), vec![0, 1, 1]);
+        task.abort();
+    }
+
+    fn register_request(owner: &PeerId, share: Vec<u8>) -> RegisterShareRequest {
+        RegisterShareRequest {
+            key: "shared-name".to_string(),
+            share: (1, share),
+            #[cfg(feature = "sss16")]
+            field: ShareField::Gf256,
+            peer: PeerId::random().to_bytes(),
+            sender: owner.to_bytes(),
+            threshold: 2,
+            ttl_secs: None,
+            recreate: false,
+            refresh_interval_secs: None,
+            replace: false,
+            relay_grant: None,
+            trace_id: None,
+        }
+    }
+
+    fn tombstone_for(owner: &PeerId, expires_at: u64) -> Tombstone {
+        Tombstone {
+            owner: owner.to_bytes(),
+            deleted_at: 0,
+            expires_at,
+            reason: OWNER_PURGED.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_registration_of_recently_deleted_key_is_refused() {
+        let dao = test_dao();
+        let owner = PeerId::random();
+        let request = register_request(&owner, vec![1, 1]);
+        store_registered_share(&owner, &request, &dao).unwrap();
+        let stored_key = owner_key(&owner.to_bytes(), "shared-name");
+        let window = Duration::from_secs(60);
+        dao.lock()
+            .unwrap()
+            .delete_with_tombstone(&stored_key, OWNER_PURGED, now_unix(), window)
+            .unwrap()
+            .unwrap();
+        assert!(get_owned_live_entry(&owner, "shared-name", &dao)
+            .unwrap()
+            .is_none());
+
+        let refused = store_registered_share(&owner, &request, &dao).unwrap_err();
+        assert_eq!(
+            refused,
+            RepoError::RecentlyDeleted("shared-name".to_string())
+        );
+        assert!(get_owned_live_entry(&owner, "shared-name", &dao)
+            .unwrap()
+            .is_none());
+
+        // other owners are not blocked by the tombstone
+        let other = PeerId::random();
+        store_registered_share(&other, &register_request(&other, vec![2, 2]), &dao).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_registration_over_quota_is_refused_without_providing() {
+        let quotas = DaoQuotas {
+            max_entries_per_owner: Some(1),
+            ..Default::default()
+        };
+        let dao: SharedDao = Arc::new(Mutex::new(Box::new(
+            HashMapShareEntryDao::new().with_quotas(quotas),
+        )));
+        let owner = PeerId::random();
+        let first = RegisterShareRequest {
+            key: "first".to_string(),
+            ..register_request(&owner, vec![1, 1])
+        };
+        store_registered_share(&owner, &first, &dao).unwrap();
+        let (mut client, mut receiver) = test_client();
+
+        let request = register_request(&owner, vec![2, 2]);
+        let result =
+            register_share(&owner, &request, &dao, DEFAULT_MAX_SHARE_BYTES, &mut client).await;
+
+        assert_eq!(
+            result,
+            Err(Failure::QuotaExceeded(QuotaUsage {
+                limit: "max_entries_per_owner".to_string(),
+                max: 1,
+                used: 1,
+            }))
+        );
+        assert!(get_owned_live_entry(&owner, "shared-name", &dao)
+            .unwrap()
+            .is_none());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_registration_is_provided_once_stored() {
+        let dao = test_dao();
+        let owner = PeerId::random();
+        let (mut client, mut receiver) = test_client();
+        let request = register_request(&owner, vec![1, 1]);
+
+        let dao_clone = Arc::clone(&dao);
+        let provider = spawn(async move {
+            match receiver.next().await {
+                Some(Command::StartProviding { key, sender }) => {
+                    assert!(get_owned_live_entry(&owner, "shared-name", &dao_clone)
+                        .unwrap()
+                        .is_some());
+                    sender.send(()).unwrap();
+                    key
+                }
+                _ => panic!("expected the share to be provided"),
             }
+        });
+        let result =
+            register_share(&owner, &request, &dao, DEFAULT_MAX_SHARE_BYTES, &mut client).await;
 
-            debug!("Found {} providers for share {}.", providers.len(), key);
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            provider.await.unwrap(),
+            Client::provider_key(&owner, "shared-name")
+        );
+    }
 
-            // refresh the share locally
-            let _ = execute_refresh_share(
-                key,
-                &local_peer_id,
-                &refresh_key,
-                None,
-                &dao_clone,
-                &mut network_client_clone.clone(),
+    /// Collects what a subscriber writes.
+    #[cfg(feature = "cli")]
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    #[cfg(feature = "cli")]
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "cli")]
+    #[tokio::test]
+    async fn test_handlers_log_json_lines_with_the_key_peer_and_outcome() {
+        use crate::cli::logging::{log_filter, subscriber, LogFormat};
+        use tracing::instrument::WithSubscriber;
+        use tracing::level_filters::LevelFilter;
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let filter = log_filter(0, 0, LevelFilter::INFO, None);
+        let logs = subscriber(filter, LogFormat::Json, move || writer.clone());
+        let dao = test_dao();
+        let owner = PeerId::random();
+        let (mut client, mut receiver) = test_client();
+        spawn(async move {
+            while let Some(command) = receiver.next().await {
+                if let Command::StartProviding { sender, .. } = command {
+                    let _ = sender.send(());
+                }
+            }
+        });
+
+        async {
+            let request = register_request(&owner, vec![1, 1]);
+            let registered =
+                register_share(&owner, &request, &dao, DEFAULT_MAX_SHARE_BYTES, &mut client).await;
+            assert_eq!(registered, Ok(()));
+            let oversized = register_request(&owner, vec![1; 8]);
+            assert!(register_share(&owner, &oversized, &dao, 4, &mut client).await.is_err());
+        }
+        .with_subscriber(logs)
+        .await;
+
+        let written = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("one JSON object per line"))
+            .collect();
+        let logged = |outcome: &str| {
+            lines
+                .iter()
+                .find(|line| line["fields"]["outcome"] == outcome)
+                .unwrap_or_else(|| panic!("no {outcome} line in\n{written}"))
+        };
+        let registered = logged("registered");
+        assert_eq!(registered["level"], "INFO");
+        assert_eq!(registered["target"], "shard::provider");
+        assert_eq!(registered["fields"]["key"], "shared-name");
+        assert_eq!(registered["fields"]["peer"], owner.to_string());
+        let refused = logged("refused");
+        assert_eq!(refused["level"], "WARN");
+        assert_eq!(refused["fields"]["peer"], owner.to_string());
+        assert!(refused["fields"]["reason"].as_str().unwrap().contains("share"));
+    }
+
+    /// The name of a span and its fields.
+    type SpanRecord = (&'static str, HashMap<String, String>);
+
+    /// Collects the name and fields of every span opened.
+    #[derive(Clone, Default)]
+    struct Spans(Arc<Mutex<Vec<SpanRecord>>>);
+
+    impl Spans {
+        /// Returns the fields of the spans opened under `name`.
+        fn named(&self, name: &str) -> Vec<HashMap<String, String>> {
+            let spans = self.0.lock().unwrap();
+            spans
+                .iter()
+                .filter(|(span, _)| *span == name)
+                .map(|(_, fields)| fields.clone())
+                .collect()
+        }
+    }
+
+    #[derive(Default)]
+    struct SpanFields(HashMap<String, String>);
+
+    impl tracing::field::Visit for SpanFields {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name().to_string(), format!("{value:?}"));
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Spans {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _: &tracing::span::Id,
+            _: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let mut fields = SpanFields::default();
+            attrs.record(&mut fields);
+            let name = attrs.metadata().name();
+            self.0.lock().unwrap().push((name, fields.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provider_request_span_carries_the_trace_id_of_the_client() {
+        use crate::protocol::RegisterShareStatus;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        // the test runs on a single thread, where every task logs to the default subscriber
+        let spans = Spans::default();
+        let subscriber = tracing_subscriber::registry().with(spans.clone());
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let (mut provider_client, events, event_loop, provider) =
+            crate::network::new(None).await.unwrap();
+        spawn(event_loop.run(None));
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr: libp2p::Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap();
+        provider_client.start_listening(addr.clone()).await.unwrap();
+        let (mut client, _events, event_loop, owner) = crate::network::new(None).await.unwrap();
+        spawn(event_loop.run(None));
+
+        let local = tokio::task::LocalSet::new();
+        local.spawn_local(async move {
+            run_loop(
+                DaoOptions::default(),
+                ProviderOptions::default(),
+                provider,
+                &mut provider_client,
+                events,
+                CancellationToken::new(),
             )
+            .await
+        });
+        let trace_id = TraceId::random();
+        local
+            .run_until(async move {
+                client.dial(provider, addr).await.unwrap();
+                let status = client
+                    .with_trace(trace_id)
+                    .request_register_share(
+                        (1, vec![1, 2]),
+                        "shared-name".to_string(),
+                        2,
+                        None,
+                        false,
+                        None,
+                        false,
+                        None,
+                        provider,
+                        owner,
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(status, RegisterShareStatus::Registered);
+            })
             .await;
 
-            // remove local_peer_id from providers
-            let providers = providers
-                .into_iter()
-                .filter(|p| p != &local_peer_id)
-                .collect::<Vec<_>>();
-
-            let requests = providers.clone().into_iter().map(|p| {
-                let k = key.clone();
-                let ref_key = refresh_key.clone();
-                let mut network_client = network_client_clone.clone();
-                debug!("🔄 Refreshing share for key: {:?} to peer {:?}", &k, p);
-                async move {
-                    network_client
-                        .request_refresh_shares(k, ref_key, p, sender)
-                        .await
+        let sent = spans.named("request_register_share");
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["trace_id"], trace_id.to_string());
+        let handled = spans.named("request");
+        assert_eq!(handled.len(), 1);
+        assert_eq!(handled[0]["trace_id"], trace_id.to_string());
+        assert_eq!(handled[0]["key"], "shared-name");
+        assert_eq!(handled[0]["requester"], owner.to_string());
+    }
+
+    /// Asserts that `request` is refused as invalid with a reason mentioning `constraint`, and
+    /// that nothing is stored or provided for it.
+    async fn assert_invalid_registration(request: RegisterShareRequest, constraint: &str) {
+        let dao = test_dao();
+        let owner = PeerId::from_bytes(&request.sender).unwrap();
+        let (mut client, mut receiver) = test_client();
+
+        let result = register_share(&owner, &request, &dao, 4, &mut client).await;
+
+        match result {
+            Err(Failure::InvalidRequest(reason)) => assert!(
+                reason.contains(constraint),
+                "{:?} does not name {:?}",
+                reason,
+                constraint
+            ),
+            other => panic!("expected an invalid request, got {:?}", other),
+        }
+        assert!(dao.lock().unwrap().keys().unwrap().is_empty());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_registration_of_index_zero_is_refused() {
+        let owner = PeerId::random();
+        let request = RegisterShareRequest {
+            share: (0, vec![1, 2]),
+            ..register_request(&owner, vec![])
+        };
+        assert_invalid_registration(request, "index").await;
+    }
+
+    #[tokio::test]
+    async fn test_registration_of_empty_share_is_refused() {
+        let owner = PeerId::random();
+        assert_invalid_registration(register_request(&owner, vec![]), "empty").await;
+    }
+
+    #[tokio::test]
+    async fn test_registration_of_oversized_share_is_refused() {
+        let owner = PeerId::random();
+        assert_invalid_registration(register_request(&owner, vec![7; 5]), "limit").await;
+    }
+
+    #[tokio::test]
+    async fn test_registration_with_threshold_out_of_range_is_refused() {
+        let owner = PeerId::random();
+        for threshold in [0, 1, 256] {
+            let request = RegisterShareRequest {
+                threshold,
+                ..register_request(&owner, vec![1, 2])
+            };
+            assert_invalid_registration(request, "threshold").await;
+        }
+    }
+
+    #[test]
+    fn test_smallest_valid_registration_is_accepted() {
+        let owner = PeerId::random();
+        let request = register_request(&owner, vec![9]);
+        assert_eq!(validate_registration(&request, 1), Ok(()));
+
+        let request = RegisterShareRequest {
+            share: (255, vec![9; 4]),
+            threshold: 255,
+            ..request
+        };
+        assert_eq!(validate_registration(&request, 4), Ok(()));
+    }
+
+    #[cfg(feature = "sss16")]
+    #[test]
+    fn test_registration_is_bounded_by_its_field() {
+        let owner = PeerId::random();
+        let request = RegisterShareRequest {
+            share: (256, vec![9; 4]),
+            threshold: 300,
+            ..register_request(&owner, vec![])
+        };
+        let refused = validate_registration(&request, 4).unwrap_err();
+        assert_eq!(
+            refused,
+            Failure::InvalidRequest("share index 256 is beyond GF(2^8)".to_string())
+        );
+
+        let wide = RegisterShareRequest {
+            field: ShareField::Gf65536,
+            ..request
+        };
+        assert_eq!(validate_registration(&wide, 4), Ok(()));
+        let odd = RegisterShareRequest {
+            share: (256, vec![9; 3]),
+            ..wide
+        };
+        assert!(matches!(
+            validate_registration(&odd, 4),
+            Err(Failure::InvalidRequest(reason)) if reason.contains("even length")
+        ));
+    }
+
+    #[test]
+    fn test_identical_reregistration_overwrites_the_share() {
+        let dao = test_dao();
+        let owner = PeerId::random();
+        store_registered_share(&owner, &register_request(&owner, vec![1, 1]), &dao).unwrap();
+
+        store_registered_share(&owner, &register_request(&owner, vec![2, 2]), &dao).unwrap();
+
+        let stored = get_owned_live_entry(&owner, "shared-name", &dao)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.share, (1, vec![2, 2]));
+    }
+
+    #[test]
+    fn test_mismatched_reregistration_is_refused() {
+        let dao = test_dao();
+        let owner = PeerId::random();
+        store_registered_share(&owner, &register_request(&owner, vec![1, 1]), &dao).unwrap();
+
+        let mismatches = [
+            RegisterShareRequest {
+                threshold: 3,
+                ..register_request(&owner, vec![2, 2])
+            },
+            RegisterShareRequest {
+                share: (2, vec![2, 2]),
+                ..register_request(&owner, vec![])
+            },
+            register_request(&owner, vec![2, 2, 2]),
+        ];
+        for request in mismatches {
+            let refused = store_registered_share(&owner, &request, &dao).unwrap_err();
+            assert!(matches!(
+                storage_failure(refused),
+                Failure::Conflict(reason) if reason.contains("differs from the stored")
+            ));
+        }
+        let stored = get_owned_live_entry(&owner, "shared-name", &dao)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.share, (1, vec![1, 1]));
+        assert_eq!(stored.threshold, 2);
+
+        // an expired share does not hold back a new registration
+        let stored_key = owner_key(&owner.to_bytes(), "shared-name");
+        let expired = ShareEntry {
+            expires_at: Some(now_unix() - 1),
+            ..stored
+        };
+        dao.lock().unwrap().insert(&stored_key, &expired).unwrap();
+        let request = RegisterShareRequest {
+            threshold: 3,
+            ..register_request(&owner, vec![2, 2])
+        };
+        store_registered_share(&owner, &request, &dao).unwrap();
+    }
+
+    #[test]
+    fn test_replace_overwrites_mismatched_share_at_epoch_zero() {
+        let dao = test_dao();
+        let owner = PeerId::random();
+        store_registered_share(&owner, &register_request(&owner, vec![1, 1]), &dao).unwrap();
+        let refresh_key = RefreshKey::from(generate_refresh_key(2, 2).unwrap());
+        refresh_owned_share("shared-name", &owner, &refresh_key, Some(5), true, &dao).unwrap();
+
+        let request = RegisterShareRequest {
+            threshold: 3,
+            replace: true,
+            ..register_request(&owner, vec![2, 2, 2])
+        };
+        store_registered_share(&owner, &request, &dao).unwrap();
+
+        let stored = get_owned_live_entry(&owner, "shared-name", &dao)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.share, (1, vec![2, 2, 2]));
+        assert_eq!(stored.threshold, 3);
+        assert_eq!(stored.epoch, 0);
+    }
+
+    /// A DAO that records the writes and flushes made to it in a shared log, and fails its
+    /// flushes when `fail_flush` is set.
+    struct SpyDao {
+        inner: HashMapShareEntryDao,
+        log: Arc<Mutex<Vec<&'static str>>>,
+        fail_flush: bool,
+    }
+
+    impl SpyDao {
+        fn shared(log: &Arc<Mutex<Vec<&'static str>>>, fail_flush: bool) -> SharedDao {
+            Arc::new(Mutex::new(Box::new(SpyDao {
+                inner: HashMapShareEntryDao::new(),
+                log: Arc::clone(log),
+                fail_flush,
+            })))
+        }
+
+        fn record(&self, operation: &'static str) {
+            self.log.lock().unwrap().push(operation);
+        }
+    }
+
+    impl ShareEntryDaoTrait for SpyDao {
+        fn insert(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+            self.record("insert");
+            self.inner.insert(key, entry)
+        }
+
+        fn get(&self, key: &str) -> Result<Option<ShareEntry>, RepoError> {
+            self.inner.get(key)
+        }
+
+        fn get_page(
+            &self,
+            after: Option<&str>,
+            limit: usize,
+        ) -> Result<Vec<(String, ShareEntry)>, RepoError> {
+            self.inner.get_page(after, limit)
+        }
+
+        fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, RepoError> {
+            self.inner.keys_with_prefix(prefix)
+        }
+
+        fn update(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+            self.record("update");
+            self.inner.update(key, entry)
+        }
+
+        fn delete(&self, key: &str) -> Result<(), RepoError> {
+            self.record("delete");
+            self.inner.delete(key)
+        }
+
+        fn compare_and_swap(
+            &self,
+            key: &str,
+            expected: &ShareEntry,
+            new: &ShareEntry,
+        ) -> Result<bool, RepoError> {
+            self.record("compare_and_swap");
+            self.inner.compare_and_swap(key, expected, new)
+        }
+
+        fn put_tombstone(&self, key: &str, tombstone: &Tombstone) -> Result<(), RepoError> {
+            self.inner.put_tombstone(key, tombstone)
+        }
+
+        fn get_tombstone(&self, key: &str) -> Result<Option<Tombstone>, RepoError> {
+            self.inner.get_tombstone(key)
+        }
+
+        fn remove_tombstone(&self, key: &str) -> Result<(), RepoError> {
+            self.inner.remove_tombstone(key)
+        }
+
+        fn purge_tombstones(&self, now: u64) -> Result<usize, RepoError> {
+            self.inner.purge_tombstones(now)
+        }
+
+        fn flush(&self) -> Result<(), RepoError> {
+            self.record("flush");
+            if self.fail_flush {
+                return Err(std::io::Error::other("disk full").into());
+            }
+            self.inner.flush()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registration_is_flushed_before_it_is_provided() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let dao = SpyDao::shared(&log, false);
+        let owner = PeerId::random();
+        let (mut client, mut receiver) = test_client();
+
+        let provider_log = Arc::clone(&log);
+        let provider = spawn(async move {
+            match receiver.next().await {
+                Some(Command::StartProviding { sender, .. }) => {
+                    provider_log.lock().unwrap().push("start_providing");
+                    sender.send(()).unwrap();
                 }
-                .boxed()
-            });
+                _ => panic!("expected the share to be provided"),
+            }
+        });
+        let request = register_request(&owner, vec![1, 1]);
+        let result =
+            register_share(&owner, &request, &dao, DEFAULT_MAX_SHARE_BYTES, &mut client).await;
+        provider.await.unwrap();
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(*log.lock().unwrap(), ["insert", "flush", "start_providing"]);
+    }
 
-            // Await all of the requests and ensure they all succeed
-            futures::future::join_all(requests).await;
+    #[tokio::test]
+    async fn test_registration_that_cannot_be_flushed_is_rolled_back() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let dao = SpyDao::shared(&log, true);
+        let owner = PeerId::random();
+        let (mut client, mut receiver) = test_client();
 
-            // println!("Found {} providers for share {}.", providers.len(), key);
-            debug!(
-                "🔄 Refreshed {} shares for key: {:?}",
-                providers.len(),
-                &key
-            );
+        let request = register_request(&owner, vec![1, 1]);
+        let result =
+            register_share(&owner, &request, &dao, DEFAULT_MAX_SHARE_BYTES, &mut client).await;
+
+        assert!(matches!(result, Err(Failure::StorageError(_))));
+        assert!(get_owned_live_entry(&owner, "shared-name", &dao)
+            .unwrap()
+            .is_none());
+        assert!(receiver.try_recv().is_err());
+
+        // a share registered before is put back as it was
+        let stored_key = owner_key(&owner.to_bytes(), "shared-name");
+        let previous = registered_entry(&owner, &register_request(&owner, vec![2, 2]), 0);
+        dao.lock().unwrap().insert(&stored_key, &previous).unwrap();
+        let result =
+            register_share(&owner, &request, &dao, DEFAULT_MAX_SHARE_BYTES, &mut client).await;
+
+        assert!(matches!(result, Err(Failure::StorageError(_))));
+        assert_eq!(
+            get_owned_live_entry(&owner, "shared-name", &dao).unwrap(),
+            Some(previous)
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_refresh_is_flushed_before_it_is_reported() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let dao = SpyDao::shared(&log, false);
+        let owner = PeerId::random();
+        let stored_key = owner_key(&owner.to_bytes(), "shared-name");
+        let entry = registered_entry(&owner, &register_request(&owner, vec![1, 1]), now_unix());
+        dao.lock().unwrap().insert(&stored_key, &entry).unwrap();
+        log.lock().unwrap().clear();
+
+        let refresh_key = RefreshKey::from(generate_refresh_key(2, 2).unwrap());
+        let refreshed =
+            refresh_owned_share("shared-name", &owner, &refresh_key, Some(1), true, &dao).unwrap();
+
+        assert_eq!(refreshed.epoch, 1);
+        assert_eq!(*log.lock().unwrap(), ["compare_and_swap", "flush"]);
+    }
+
+    #[test]
+    fn test_refresh_that_cannot_be_flushed_is_reported_as_failed() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let dao = SpyDao::shared(&log, true);
+        let owner = PeerId::random();
+        let stored_key = owner_key(&owner.to_bytes(), "shared-name");
+        let entry = registered_entry(&owner, &register_request(&owner, vec![1, 1]), now_unix());
+        dao.lock().unwrap().insert(&stored_key, &entry).unwrap();
+
+        let refresh_key = RefreshKey::from(generate_refresh_key(2, 2).unwrap());
+        let result = refresh_owned_share("shared-name", &owner, &refresh_key, Some(1), true, &dao);
+
+        assert_eq!(result, Err(Failure::StorageError("disk full".to_string())));
+    }
+
+    #[test]
+    fn test_recreate_overrides_tombstone() {
+        let dao = test_dao();
+        let owner = PeerId::random();
+        let stored_key = owner_key(&owner.to_bytes(), "shared-name");
+        let tombstone = tombstone_for(&owner, now_unix() + 60);
+        dao.lock()
+            .unwrap()
+            .put_tombstone(&stored_key, &tombstone)
+            .unwrap();
+
+        let request = RegisterShareRequest {
+            recreate: true,
+            refresh_interval_secs: None,
+            ..register_request(&owner, vec![3, 3])
+        };
+        store_registered_share(&owner, &request, &dao).unwrap();
+
+        let stored = get_owned_live_entry(&owner, "shared-name", &dao)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.share.1, vec![3, 3]);
+        assert!(dao
+            .lock()
+            .unwrap()
+            .get_tombstone(&stored_key)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_expired_tombstone_allows_registration_and_is_purged() {
+        let dao = test_dao();
+        let owner = PeerId::random();
+        let now = now_unix();
+        let expired_key = owner_key(&owner.to_bytes(), "shared-name");
+        let live_key = owner_key(&owner.to_bytes(), "other-name");
+        {
+            let dao = dao.lock().unwrap();
+            dao.put_tombstone(&expired_key, &tombstone_for(&owner, now))
+                .unwrap();
+            dao.put_tombstone(&live_key, &tombstone_for(&owner, now + 60))
+                .unwrap();
+            assert_eq!(dao.purge_tombstones(now).unwrap(), 1);
+            assert!(dao.get_tombstone(&expired_key).unwrap().is_none());
+            assert!(dao.get_tombstone(&live_key).unwrap().is_some());
+            dao.put_tombstone(&expired_key, &tombstone_for(&owner, now))
+                .unwrap();
         }
+
+        store_registered_share(&owner, &register_request(&owner, vec![4, 4]), &dao).unwrap();
+        assert!(get_owned_live_entry(&owner, "shared-name", &dao)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_owners_register_the_same_key_independently() {
+        let dao = test_dao();
+        let alice = PeerId::random();
+        let bob = PeerId::random();
+
+        store_registered_share(&alice, &register_request(&alice, vec![1, 1]), &dao).unwrap();
+        store_registered_share(&bob, &register_request(&bob, vec![2, 2]), &dao).unwrap();
+
+        let alices = get_owned_live_entry(&alice, "shared-name", &dao)
+            .unwrap()
+            .unwrap();
+        let bobs = get_owned_live_entry(&bob, "shared-name", &dao)
+            .unwrap()
+            .unwrap();
+        assert_eq!(alices.share.1, vec![1, 1]);
+        assert_eq!(bobs.share.1, vec![2, 2]);
+        assert!(get_owned_live_entry(&PeerId::random(), "shared-name", &dao)
+            .unwrap()
+            .is_none());
+        assert_ne!(
+            Client::provider_key(&alice, "shared-name"),
+            Client::provider_key(&bob, "shared-name")
+        );
+    }
+
+    #[test]
+    fn test_plain_keys_move_into_owner_namespace() {
+        let dao = test_dao();
+        let owned = entry(None);
+        let newer = ShareEntry {
+            share: (1, vec![9, 9, 9]),
+            ..owned.clone()
+        };
+        dao.lock().unwrap().insert("legacy", &owned).unwrap();
+        dao.lock().unwrap().insert("both", &owned).unwrap();
+        insert_owned(&dao, "both", &newer);
+
+        let dao = dao.lock().unwrap();
+        assert_eq!(dao.migrate_owner_keys().unwrap(), 1);
+        assert_eq!(
+            dao.keys().unwrap(),
+            vec![
+                owner_key(&owned.sender, "both"),
+                owner_key(&owned.sender, "legacy")
+            ]
+        );
+        assert_eq!(
+            dao.get_owned(&owned.sender, "legacy").unwrap(),
+            Some(owned.clone())
+        );
+        assert_eq!(dao.get_owned(&owned.sender, "both").unwrap(), Some(newer));
+        assert_eq!(dao.migrate_owner_keys().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_does_not_resurrect_expired_entry() {
+        let dao = test_dao();
+        let (mut client, _receiver) = test_client();
+        let expired = entry(Some(1));
+        insert_owned(&dao, "expired", &expired);
+
+        let refresh_key = RefreshKey::from(generate_refresh_key(2, 3).unwrap());
+        let result = execute_refresh_share(
+            "expired",
+            &PeerId::from_bytes(&expired.sender).unwrap(),
+            &refresh_key,
+            None,
+            None,
+            &dao,
+            &ProviderMetrics::default(),
+            &mut client,
+        )
+        .await;
+
+        assert_eq!(
+            result.unwrap(),
+            AuditOutcome::Refused(Failure::NotFound.to_string())
+        );
+        let stored_key = owner_key(&expired.sender, "expired");
+        let stored = dao.lock().unwrap().get(&stored_key).unwrap().unwrap();
+        assert_eq!(stored.share, expired.share);
+
+        purge_expired(&dao, &None, &mut client).await.unwrap();
+        assert!(dao.lock().unwrap().get(&stored_key).unwrap().is_none());
+    }
+
+    // the paused clock of tokio only drives tokio timers
+    #[cfg(not(feature = "rt-async-std"))]
+    #[tokio::test(start_paused = true)]
+    async fn test_purge_loop_purges_on_each_tick() {
+        let dao = test_dao();
+        let (client, mut receiver) = test_client();
+        let first = entry(Some(1));
+        let second = entry(Some(1));
+        let first_record = insert_owned(&dao, "first", &first);
+
+        let dao_clone = Arc::clone(&dao);
+        let task = spawn(async move {
+            let mut client = client;
+            let mut interval = runtime::interval(Duration::from_secs(60));
+            purge_loop(&mut interval, dao_clone, None, &mut client).await;
+        });
+
+        let is_stored = |entry: &ShareEntry, key: &str| {
+            dao.lock()
+                .unwrap()
+                .get_owned(&entry.sender, key)
+                .unwrap()
+                .is_some()
+        };
+        time::sleep(Duration::from_secs(1)).await;
+        assert!(!is_stored(&first, "first"));
+
+        let second_record = insert_owned(&dao, "second", &second);
+        time::sleep(Duration::from_secs(30)).await;
+        assert!(is_stored(&second, "second"));
+
+        time::sleep(Duration::from_secs(31)).await;
+        assert!(!is_stored(&second, "second"));
+        assert_eq!(
+            stopped_keys(&mut receiver),
+            vec![first_record, second_record]
+        );
+        task.abort();
     }
 }
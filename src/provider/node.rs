@@ -0,0 +1,556 @@
+use super::{
+    dao, ensure_writable, run_with_dao, AbortOnDrop, DaoOptions, ProviderEvent, ProviderMetrics,
+    ProviderOptions, RateLimit, SharedAudit, SharedDao, SharedMetrics,
+};
+use crate::client::{Client, NotReady};
+use crate::network::{self, start_listeners, wait_for_listen_addrs, NetworkConfig};
+use crate::repository::FlushPolicy;
+use crate::runtime;
+use crate::Error;
+use libp2p::{Multiaddr, PeerId};
+#[cfg(feature = "metrics")]
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+#[cfg(feature = "metrics")]
+use tracing::{error, info};
+
+/// Builds a `ShardNode`, a provider running in the process: its network event loop, refresh
+/// task and request loop, owned by the node rather than wired up by the caller.
+///
+/// Every setting is optional. A node built from a default builder joins the default network with
+/// a fresh identity, listens on every interface and keeps its shares in memory, with the
+/// defaults `run_loop` falls back to for everything else.
+///
+/// # Examples
+///
+/// ```ignore
+/// use shard::provider::ShardNode;
+///
+/// let mut node = ShardNode::builder()
+///     .config(NetworkConfig { bootstrappers, ..Default::default() })
+///     .dao(dao)
+///     .refresh_interval(Duration::from_secs(600))
+///     .build()
+///     .await?;
+/// let mut client = node.client();
+/// node.shutdown().await?;
+/// ```
+#[derive(Default)]
+pub struct ShardNodeBuilder {
+    config: Option<NetworkConfig>,
+    dao: Option<SharedDao>,
+    dao_options: Option<DaoOptions>,
+    audit: SharedAudit,
+    metrics: Option<SharedMetrics>,
+    events: Option<usize>,
+    refresh_interval: Option<Duration>,
+    refresh_jitter: Option<u8>,
+    replication_margin: Option<u64>,
+    status_interval: Option<Duration>,
+    max_share_bytes: Option<usize>,
+    rate_limit: Option<RateLimit>,
+    shutdown_grace: Option<Duration>,
+    #[cfg(feature = "metrics")]
+    metrics_addr: Option<SocketAddr>,
+}
+
+impl ShardNodeBuilder {
+    /// Sets how the node joins the network.
+    pub fn config(mut self, config: NetworkConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Provides the shares of a DAO already opened, which the caller can keep a handle on. The
+    /// DAO is flushed on the default `FlushPolicy`. Cannot be combined with `dao_options`.
+    pub fn dao(mut self, dao: SharedDao) -> Self {
+        self.dao = Some(dao);
+        self
+    }
+
+    /// Opens the DAO the node provides the shares of when it is built (see `provider::dao`).
+    /// Cannot be combined with `dao`.
+    pub fn dao_options(mut self, options: DaoOptions) -> Self {
+        self.dao_options = Some(options);
+        self
+    }
+
+    /// Records the operations of the node in an audit log.
+    pub fn audit(mut self, audit: SharedAudit) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// Counts the work of the node in `metrics` rather than in metrics of its own.
+    pub fn metrics(mut self, metrics: SharedMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Publishes the `ProviderEvent`s of the node, queueing up to `buffer` of them for
+    /// `ShardNode::events` before dropping them (see `publish_event`).
+    pub fn events(mut self, buffer: usize) -> Self {
+        self.events = Some(buffer);
+        self
+    }
+
+    /// Sets the default interval shares are refreshed on, in whole seconds.
+    pub fn refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = Some(interval);
+        self
+    }
+
+    /// Sets the spread of the refresh schedule in percent (see `RefreshSchedule`).
+    pub fn refresh_jitter(mut self, percent: u8) -> Self {
+        self.refresh_jitter = Some(percent);
+        self
+    }
+
+    /// Sets the number of providers beyond its threshold each share should have (see
+    /// `replication_alert`).
+    pub fn replication_margin(mut self, margin: u64) -> Self {
+        self.replication_margin = Some(margin);
+        self
+    }
+
+    /// Sets the interval the health status is published on, in whole seconds.
+    pub fn status_interval(mut self, interval: Duration) -> Self {
+        self.status_interval = Some(interval);
+        self
+    }
+
+    /// Sets the largest share accepted for registration.
+    pub fn max_share_bytes(mut self, bytes: usize) -> Self {
+        self.max_share_bytes = Some(bytes);
+        self
+    }
+
+    /// Throttles the requests of each peer (see `RateLimiter`).
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Sets how long a shutdown waits for the refresh in flight, in whole seconds.
+    pub fn shutdown_grace(mut self, grace: Duration) -> Self {
+        self.shutdown_grace = Some(grace);
+        self
+    }
+
+    /// Serves the metrics of the node and of its libp2p swarm on `/metrics` at `addr`.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_addr(mut self, addr: SocketAddr) -> Self {
+        self.metrics_addr = Some(addr);
+        self
+    }
+
+    /// Checks that the settings can be run together.
+    ///
+    /// # Returns
+    ///
+    /// An error naming the first setting that cannot.
+    fn validate(&self) -> Result<(), Error> {
+        let invalid = |reason: &str| Err(Error::Invalid(reason.to_string()));
+        if self.dao.is_some() && self.dao_options.is_some() {
+            return invalid("a node is given either a DAO or the options to open one, not both");
+        }
+        if self
+            .dao_options
+            .as_ref()
+            .is_some_and(|options| options.read_only)
+        {
+            return invalid("a read-only DAO cannot back a running provider");
+        }
+        if self.events == Some(0) {
+            return invalid("the event buffer must hold at least one event");
+        }
+        if self
+            .refresh_interval
+            .is_some_and(|interval| interval.as_secs() == 0)
+        {
+            return invalid("the refresh interval must be at least a second");
+        }
+        if self
+            .status_interval
+            .is_some_and(|interval| interval.as_secs() == 0)
+        {
+            return invalid("the status interval must be at least a second");
+        }
+        if self.refresh_jitter.is_some_and(|percent| percent > 100) {
+            return invalid("the refresh jitter cannot exceed 100 percent");
+        }
+        if self.max_share_bytes == Some(0) {
+            return invalid("the largest share accepted must be at least a byte");
+        }
+        if let Some(RateLimit { burst, per_second }) = self.rate_limit {
+            if burst == 0 || per_second.is_nan() || per_second <= 0.0 {
+                return invalid("a rate limit needs a burst and a rate above zero");
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts the node: joins the network, listens, dials the bootstrappers and starts providing.
+    ///
+    /// # Returns
+    ///
+    /// The running node, or an error if the settings are incompatible, the network or the DAO
+    /// cannot be set up, no listener starts, or no bootstrapper is reached within the timeout.
+    pub async fn build(self) -> Result<ShardNode, Error> {
+        self.validate()?;
+        let config = self.config.unwrap_or_default();
+        let (dao, flush_policy) = match (self.dao, self.dao_options) {
+            (Some(dao), _) => (dao, FlushPolicy::default()),
+            (None, options) => {
+                let options = options.unwrap_or_default();
+                let flush_policy = options.flush_policy;
+                (dao(options)?, flush_policy)
+            }
+        };
+        ensure_writable(&dao)?;
+
+        let (mut client, network_events, event_loop, peer_id) =
+            network::with_network(config.keypair, config.timeout, config.network_id.as_deref())
+                .await?;
+        #[cfg(feature = "metrics")]
+        let (event_loop, registry) = match self.metrics_addr {
+            Some(_) => {
+                let mut registry = libp2p::metrics::Registry::default();
+                (event_loop.with_metrics(&mut registry), Some(registry))
+            }
+            None => (event_loop, None),
+        };
+        let network_task = AbortOnDrop(runtime::spawn(event_loop.run(config.external_address)));
+
+        for peer in config.blocked_peers {
+            client.block_peer(peer).await;
+        }
+        start_listeners(&mut client, config.listen_addrs)
+            .await
+            .map_err(|reason| Error::Network(reason.into()))?;
+        for addr in config.external_addrs {
+            client.add_external_address(addr).await;
+        }
+        if !config.bootstrappers.is_empty() {
+            let bootstrap = client.bootstrap(&config.bootstrappers, peer_id);
+            let Ok(connected) = runtime::timeout(config.timeout, bootstrap).await else {
+                let connected = client.connected_peers().await.len();
+                return Err(NotReady::Connections {
+                    connected,
+                    wanted: 1,
+                }
+                .into());
+            };
+            debug!(
+                "Connected to {} of {} bootstrappers.",
+                connected,
+                config.bootstrappers.len()
+            );
+        }
+        let listen_addrs = wait_for_listen_addrs(&mut client).await;
+
+        let shutdown = CancellationToken::new();
+        let metrics = self
+            .metrics
+            .unwrap_or_else(|| Arc::new(ProviderMetrics::default()));
+        #[cfg(feature = "metrics")]
+        let (metrics_addr, metrics_task) = match (self.metrics_addr, registry) {
+            (Some(addr), Some(registry)) => {
+                let exporter =
+                    crate::metrics::MetricsExporter::new(registry, metrics.clone(), client.clone());
+                let (addr, server) = crate::metrics::serve_metrics(
+                    addr,
+                    exporter,
+                    shutdown.clone(),
+                )
+                .map_err(|e| {
+                    Error::Network(format!("cannot serve the metrics on {}: {}", addr, e).into())
+                })?;
+                info!(address = %addr, "Serving metrics on /metrics.");
+                let task = runtime::spawn(async move {
+                    if let Err(e) = server.await {
+                        error!("The metrics endpoint failed: {e}");
+                    }
+                });
+                (Some(addr), Some(AbortOnDrop(task)))
+            }
+            _ => (None, None),
+        };
+
+        let (published, events) = match self.events {
+            Some(buffer) => {
+                let (sender, receiver) = mpsc::channel(buffer);
+                (Some(sender), Some(receiver))
+            }
+            None => (None, None),
+        };
+        let whole_secs = |duration: Duration| duration.as_secs();
+        let options = ProviderOptions {
+            audit: self.audit,
+            metrics,
+            events: published,
+            refresh: self.refresh_interval.map(whole_secs),
+            refresh_jitter: self.refresh_jitter,
+            replication_margin: self.replication_margin,
+            status_interval: self.status_interval.map(whole_secs),
+            max_share_bytes: self.max_share_bytes,
+            rate_limit: self.rate_limit,
+            shutdown_grace: self.shutdown_grace.map(whole_secs),
+        };
+        let mut provider_client = client.clone();
+        let token = shutdown.clone();
+        let run_task = runtime::spawn(async move {
+            run_with_dao(
+                dao,
+                flush_policy,
+                options,
+                peer_id,
+                &mut provider_client,
+                network_events,
+                token,
+            )
+            .await
+        });
+
+        Ok(ShardNode {
+            client,
+            peer_id,
+            listen_addrs,
+            events,
+            shutdown,
+            run_task: Some(AbortOnDrop(run_task)),
+            network_task,
+            #[cfg(feature = "metrics")]
+            metrics_addr,
+            #[cfg(feature = "metrics")]
+            _metrics_task: metrics_task,
+        })
+    }
+}
+
+/// A provider running in the process, owning its network event loop, refresh task and request
+/// loop. It runs until `shutdown` is called, stopping the way `run_loop` does on a signal, and
+/// its tasks are aborted if it is dropped first.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut node = ShardNode::builder().events(PROVIDER_EVENT_BUFFER).build().await?;
+/// let mut events = node.events().unwrap();
+/// while let Some(event) = events.recv().await {
+///     println!("{}", event);
+/// }
+/// ```
+pub struct ShardNode {
+    client: Client,
+    peer_id: PeerId,
+    listen_addrs: Vec<Multiaddr>,
+    events: Option<mpsc::Receiver<ProviderEvent>>,
+    shutdown: CancellationToken,
+    run_task: Option<AbortOnDrop<Result<(), Error>>>,
+    network_task: AbortOnDrop<()>,
+    #[cfg(feature = "metrics")]
+    metrics_addr: Option<SocketAddr>,
+    #[cfg(feature = "metrics")]
+    _metrics_task: Option<AbortOnDrop<()>>,
+}
+
+impl ShardNode {
+    /// Creates a builder of a node with the default settings.
+    pub fn builder() -> ShardNodeBuilder {
+        ShardNodeBuilder::default()
+    }
+
+    /// Returns a client of the network event loop of the node, to make requests as the node.
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    /// Returns the peer id of the node.
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    /// Returns the addresses the node listened on once built, with their bound ports.
+    pub fn listen_addrs(&self) -> &[Multiaddr] {
+        &self.listen_addrs
+    }
+
+    /// Returns the address the metrics are served on, with its bound port, if they are.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr
+    }
+
+    /// Takes the stream of the `ProviderEvent`s the node publishes.
+    ///
+    /// # Returns
+    ///
+    /// The receiver of the events, or `None` if the builder was not asked to publish them or
+    /// they were taken already.
+    pub fn events(&mut self) -> Option<mpsc::Receiver<ProviderEvent>> {
+        self.events.take()
+    }
+
+    /// Waits for the request loop to stop, on a shutdown or because the node could not start
+    /// providing.
+    ///
+    /// # Returns
+    ///
+    /// The error the request loop could not start providing with, the first time it is waited
+    /// for (see `run_loop`).
+    pub async fn stopped(&mut self) -> Result<(), Error> {
+        let Some(task) = &mut self.run_task else {
+            return Ok(());
+        };
+        let stopped = (&mut task.0).await;
+        self.run_task = None;
+        match stopped {
+            Ok(result) => result,
+            Err(e) => Err(Error::Invalid(format!("the request loop died: {e}"))),
+        }
+    }
+
+    /// Stops the node: the request loop answers `Busy` while the refresh in flight finishes,
+    /// flushes the DAO and stops the network event loop.
+    ///
+    /// # Returns
+    ///
+    /// The error the request loop could not start providing with, unless `stopped` returned it
+    /// already.
+    pub async fn shutdown(mut self) -> Result<(), Error> {
+        self.shutdown.cancel();
+        let stopped = self.stopped().await;
+        // the request loop stops the network event loop, unless it never started providing
+        self.network_task.0.abort();
+        let _ = (&mut self.network_task.0).await;
+        stopped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::PROVIDER_EVENT_BUFFER;
+    use crate::protocol::RegisterShareStatus;
+    use crate::testing::TestNet;
+    use libp2p::multiaddr::Protocol;
+
+    #[tokio::test]
+    async fn test_incompatible_options_are_refused() {
+        let both = ShardNode::builder()
+            .dao(dao(DaoOptions::default()).unwrap())
+            .dao_options(DaoOptions::default())
+            .build()
+            .await;
+        assert!(both.is_err());
+        let read_only = DaoOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        assert!(ShardNode::builder()
+            .dao_options(read_only)
+            .build()
+            .await
+            .is_err());
+        let sub_second = ShardNode::builder().refresh_interval(Duration::from_millis(500));
+        assert!(sub_second.build().await.is_err());
+        let unlimited = ShardNode::builder().rate_limit(RateLimit {
+            burst: 10,
+            per_second: 0.0,
+        });
+        assert!(unlimited.build().await.is_err());
+        assert!(ShardNode::builder().events(0).build().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_node_registers_a_share_from_another_client_and_shuts_down() {
+        let net = TestNet::new().clients(1).build().await.unwrap();
+        let owner = &net.clients[0];
+        let bootstrapper = owner.addr.clone().with(Protocol::P2p(owner.peer_id));
+        let store: SharedDao = dao(DaoOptions::default()).unwrap();
+        let mut node = ShardNode::builder()
+            .config(NetworkConfig {
+                listen_addrs: vec!["/memory/0".parse().unwrap()],
+                bootstrappers: vec![bootstrapper],
+                ..Default::default()
+            })
+            .dao(store.clone())
+            .events(PROVIDER_EVENT_BUFFER)
+            .build()
+            .await
+            .unwrap();
+        let mut events = node.events().unwrap();
+        assert!(node.events().is_none());
+        assert!(matches!(
+            node.listen_addrs()[0].iter().next(),
+            Some(Protocol::Memory(_))
+        ));
+
+        let mut client = owner.client.clone();
+        let status = client
+            .request_register_share(
+                (1, b"share".to_vec()),
+                "embedded".to_string(),
+                2,
+                None,
+                false,
+                None,
+                false,
+                None,
+                node.peer_id(),
+                owner.peer_id,
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, RegisterShareStatus::Registered);
+        let event = tokio::time::timeout(Duration::from_secs(10), events.recv()).await;
+        assert_eq!(
+            event.unwrap(),
+            Some(ProviderEvent::Registered {
+                key: "embedded".to_string(),
+                owner: owner.peer_id,
+            })
+        );
+
+        node.shutdown().await.unwrap();
+        // the event channel closes once the request loop stopped
+        assert_eq!(events.recv().await, None);
+        let stored = store
+            .lock()
+            .unwrap()
+            .get_owned(&owner.peer_id.to_bytes(), "embedded");
+        assert_eq!(stored.unwrap().unwrap().share, (1, b"share".to_vec()));
+        net.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_dropped_node_aborts_its_tasks() {
+        let store: SharedDao = dao(DaoOptions::default()).unwrap();
+        let node = ShardNode::builder()
+            .config(NetworkConfig {
+                listen_addrs: vec!["/memory/0".parse().unwrap()],
+                ..Default::default()
+            })
+            .dao(store.clone())
+            .build()
+            .await
+            .unwrap();
+        let client = node.client();
+        drop(node);
+        // the request loop and its tasks held the other references to the DAO
+        for _ in 0..100 {
+            if Arc::strong_count(&store) == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(Arc::strong_count(&store), 1);
+        // with the network event loop gone, nothing takes the commands of the client
+        assert!(client.sender.is_closed());
+    }
+}
@@ -1,8 +1,489 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
-use sled::Db;
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use sled::transaction::{
+    ConflictableTransactionError, ConflictableTransactionResult, TransactionError,
+    TransactionalTree,
+};
+use sled::{Db, Transactional, Tree};
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
-use std::sync::Mutex;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+use crate::constants::DEFAULT_FLUSH_SECONDS;
+
+mod audit;
+mod backup;
+mod isolated;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+/// Conformance checks every `ShareEntryDaoTrait` implementation must pass.
+#[cfg(any(test, feature = "test-util"))]
+pub mod testsuite;
+
+pub use audit::{verify_chain, AuditEvent, AuditLog, AuditOperation, AuditOutcome, AuditRecord};
+pub use backup::{export_entries, import_entries, ConflictPolicy, ImportReport, EXPORT_VERSION};
+pub use isolated::{owner_tree_name, IsolatedShareEntryDao};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteShareEntryDao;
+
+/// Format tag prefixed to every value written by `encode_entry`. The tag is followed by the schema
+/// version, a big-endian CRC-32 of the version and payload, and the bincode-encoded entry.
+pub const FORMAT_CHECKSUMMED: u8 = 0x03;
+
+/// Length of the checksum stored in the header of every checksummed value.
+const CHECKSUM_LEN: usize = 4;
+
+/// The schema version of `ShareEntry` written by this release.
+///
+/// Every change to the fields of `ShareEntry` must bump this version, freeze the previous shape as a
+/// `ShareEntryVn` type, and teach `decode_entry` to migrate it.
+pub const SHARE_ENTRY_VERSION: u8 = 1;
+
+/// The schema version of entries of shares split over GF(2^16), which carry a 16-bit share
+/// identifier and the field. Only builds with the `sss16` feature write or read them; every other
+/// entry keeps the `SHARE_ENTRY_VERSION` layout, so that enabling the feature migrates nothing.
+#[cfg(feature = "sss16")]
+pub const WIDE_SHARE_ENTRY_VERSION: u8 = 2;
+
+/// The page size `get_all` reads the store with.
+const DEFAULT_PAGE_SIZE: usize = 1024;
+
+/// The threshold assumed for legacy JSON entries, which did not record one. Releases that wrote
+/// them always refreshed shares with degree 1 polynomials, i.e. a threshold of 2.
+pub const LEGACY_DEFAULT_THRESHOLD: u64 = 2;
+
+/// Format tag of a value sealed with an `EncryptionKey`. The tag is followed by the key id, the
+/// nonce, and the ciphertext of the tagged binary encoding of the entry.
+pub const FORMAT_ENCRYPTED: u8 = 0x02;
+
+/// First byte of a value written by earlier releases, which stored entries as JSON objects.
+const FORMAT_LEGACY_JSON: u8 = b'{';
+
+/// Length of the key id stored in the header of every encrypted value.
+const KEY_ID_LEN: usize = 8;
+
+/// Length of the XChaCha20-Poly1305 nonce stored in the header of every encrypted value.
+const NONCE_LEN: usize = 24;
+
+/// Prefix of every owner-scoped storage key, followed by the hex-encoded owner, a `/`, and the
+/// user's key.
+const OWNER_KEY_PREFIX: &str = "o/";
+
+/// Key of the entry counter in the sled stats tree.
+const STATS_ENTRIES: &[u8] = b"entries";
+
+/// Key of the stored value size counter in the sled stats tree.
+const STATS_BYTES: &[u8] = b"bytes";
+
+/// Prefix of the per-owner entry counters in the sled stats tree, followed by the owner bytes.
+const STATS_OWNER_PREFIX: &[u8] = b"owner/";
+
+/// Prefix of the per-owner stored value size counters in the sled stats tree, followed by the
+/// owner bytes.
+const STATS_OWNER_BYTES_PREFIX: &[u8] = b"owner_bytes/";
+
+/// Key of the layout version of the sled stats tree. Trees of an older layout are rebuilt.
+const STATS_LAYOUT: &[u8] = b"layout";
+
+/// The current layout version of the sled stats tree.
+const STATS_LAYOUT_VERSION: u8 = 2;
+
+/// Key in the sled meta tree marking that the owner index covers every stored entry.
+const META_OWNER_INDEX: &[u8] = b"owner_index";
+
+/// Errors raised by the repository when a stored value cannot be interpreted.
+///
+/// # Variants
+///
+/// * `EmptyValue` - The stored value contained no bytes at all.
+/// * `UnknownFormat` - The stored value started with an unrecognized format tag.
+/// * `UnknownVersion` - The stored value carries a schema version this release cannot read.
+/// * `MissingEncryptionKey` - The stored value is encrypted but the DAO was opened without a key.
+/// * `WrongEncryptionKey` - The stored value was encrypted under a key with a different id.
+/// * `DecryptionFailed` - The stored value could not be authenticated under the DAO's key.
+/// * `EncryptionFailed` - A value could not be sealed under the DAO's key.
+/// * `KeyDerivation` - A key could not be derived from a passphrase; carries the argon2 error.
+/// * `InvalidKeyFile` - An encryption key file did not contain a hex-encoded 32 byte key.
+/// * `InvalidEntry` - An entry written in a batch failed validation; carries the key and reason.
+/// * `KeyNotFound` - A batch update named a key that is not stored.
+/// * `ChecksumMismatch` - A checksummed value does not match its checksum.
+/// * `CorruptEntry` - The value stored under `key` failed its integrity check.
+/// * `QuotaExceeded` - A write would take the store past one of its `DaoQuotas`; carries the
+///   name and value of the limit, and the usage before the write.
+/// * `BrokenAuditChain` - The audit record with the given sequence number does not match its
+///   contents or the record before it.
+/// * `RecentlyDeleted` - The key was deleted by its owner and its tombstone is still live; carries
+///   the key.
+/// * `ReadOnly` - A write was attempted through a DAO opened read-only.
+/// * `StaleEpoch` - A refresh named an epoch the stored share has already reached; carries the
+///   stored and requested epochs.
+/// * `InvalidRefreshKey` - A refresh key would change the secret of the stored share, or does not
+///   fit it; carries why.
+/// * `ConflictingShare` - A registration disagrees with the share its owner stored under the key
+///   on the share index, length or threshold; carries how.
+/// * `Contended` - The entry under the key kept changing while it was being updated; carries the
+///   key.
+/// * `NoDatabase` - There is no share database at the path given; carries the path.
+/// * `Unsupported` - The store cannot do what was asked of it; carries what.
+/// * `InvalidExport` - An export stream cannot be imported; carries why.
+/// * `Backend` - The database, the file system or the encoding of an entry failed; carries the
+///   error, which is the `source` of the `RepoError`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepoError {
+    EmptyValue,
+    UnknownFormat(u8),
+    UnknownVersion(u8),
+    MissingEncryptionKey,
+    WrongEncryptionKey,
+    DecryptionFailed,
+    EncryptionFailed,
+    KeyDerivation(argon2::Error),
+    InvalidKeyFile(String),
+    InvalidEntry(String, &'static str),
+    KeyNotFound(String),
+    ChecksumMismatch,
+    CorruptEntry {
+        key: String,
+    },
+    QuotaExceeded {
+        limit: &'static str,
+        max: u64,
+        used: u64,
+    },
+    BrokenAuditChain(u64),
+    RecentlyDeleted(String),
+    ReadOnly,
+    StaleEpoch {
+        stored: u64,
+        requested: u64,
+    },
+    InvalidRefreshKey(crate::sss::Error),
+    ConflictingShare(String),
+    Contended(String),
+    NoDatabase(String),
+    Unsupported(String),
+    InvalidExport(String),
+    Backend(BackendError),
+}
+
+impl fmt::Display for RepoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepoError::EmptyValue => write!(f, "stored value is empty"),
+            RepoError::UnknownFormat(tag) => {
+                write!(f, "stored value has unknown format tag {:#04x}", tag)
+            }
+            RepoError::UnknownVersion(version) => {
+                write!(f, "stored value has unknown schema version {}", version)
+            }
+            RepoError::MissingEncryptionKey => {
+                write!(
+                    f,
+                    "stored value is encrypted but no encryption key was provided"
+                )
+            }
+            RepoError::WrongEncryptionKey => {
+                write!(f, "stored value is encrypted under a different key")
+            }
+            RepoError::DecryptionFailed => write!(f, "stored value failed to decrypt"),
+            RepoError::EncryptionFailed => write!(f, "failed to encrypt value"),
+            RepoError::KeyDerivation(e) => write!(f, "failed to derive key: {}", e),
+            RepoError::InvalidKeyFile(path) => {
+                write!(
+                    f,
+                    "encryption key file {} must contain 32 hex-encoded bytes",
+                    path
+                )
+            }
+            RepoError::InvalidEntry(key, reason) => {
+                write!(f, "entry {} is invalid: {}", key, reason)
+            }
+            RepoError::KeyNotFound(key) => write!(f, "key {} not found", key),
+            RepoError::ChecksumMismatch => write!(f, "stored value does not match its checksum"),
+            RepoError::CorruptEntry { key } => {
+                write!(f, "stored value for key {} is corrupt", key)
+            }
+            RepoError::QuotaExceeded { limit, max, used } => write!(
+                f,
+                "storage quota exceeded: {} is {}, {} already used",
+                limit, max, used
+            ),
+            RepoError::BrokenAuditChain(seq) => {
+                write!(f, "audit record {} breaks the hash chain", seq)
+            }
+            RepoError::RecentlyDeleted(key) => {
+                write!(f, "key {} was recently deleted by its owner", key)
+            }
+            RepoError::ReadOnly => write!(f, "the share store is open read-only"),
+            RepoError::StaleEpoch { stored, requested } => write!(
+                f,
+                "stale refresh epoch {}, the share is at epoch {}",
+                requested, stored
+            ),
+            RepoError::InvalidRefreshKey(reason) => write!(f, "invalid refresh key: {}", reason),
+            RepoError::ConflictingShare(reason) => {
+                write!(
+                    f,
+                    "registration conflicts with the stored share: {}",
+                    reason
+                )
+            }
+            RepoError::Contended(key) => {
+                write!(f, "share for key {} kept changing during the update", key)
+            }
+            RepoError::NoDatabase(path) => write!(f, "no share database at {}", path),
+            RepoError::Unsupported(what) => write!(f, "{}", what),
+            RepoError::InvalidExport(reason) => write!(f, "{}", reason),
+            RepoError::Backend(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for RepoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RepoError::InvalidRefreshKey(error) => Some(error),
+            RepoError::Backend(error) => Some(error.get_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// The error of the database, the file system or the encoding behind a store, shared so that a
+/// `RepoError` carrying it can still be cloned. Backend errors are only equal to themselves.
+#[derive(Debug, Clone)]
+pub struct BackendError(Arc<dyn Error + Send + Sync>);
+
+impl BackendError {
+    /// Wraps the error of a backend.
+    pub fn new(error: impl Error + Send + Sync + 'static) -> Self {
+        BackendError(Arc::new(error))
+    }
+
+    /// Returns the error of the backend, to downcast it to its own type.
+    pub fn get_ref(&self) -> &(dyn Error + Send + Sync + 'static) {
+        &*self.0
+    }
+}
+
+impl PartialEq for BackendError {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for BackendError {}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Wraps the errors of the backends of the stores as `RepoError::Backend`.
+macro_rules! backend_error {
+    ($($error:ty),* $(,)?) => {
+        $(
+            impl From<$error> for RepoError {
+                fn from(error: $error) -> Self {
+                    RepoError::Backend(BackendError::new(error))
+                }
+            }
+        )*
+    };
+}
+
+backend_error!(
+    sled::Error,
+    std::io::Error,
+    bincode::Error,
+    serde_json::Error,
+    serde_cbor::Error,
+    std::string::FromUtf8Error,
+    std::array::TryFromSliceError,
+);
+
+#[cfg(feature = "sqlite")]
+backend_error!(rusqlite::Error);
+
+impl From<TransactionError<RepoError>> for RepoError {
+    fn from(error: TransactionError<RepoError>) -> Self {
+        match error {
+            TransactionError::Abort(error) => error,
+            TransactionError::Storage(error) => error.into(),
+        }
+    }
+}
+
+/// A symmetric key used to seal share entries at rest with XChaCha20-Poly1305.
+///
+/// Every sealed value records the id of the key it was sealed with (the first bytes of the
+/// SHA-256 digest of the key), so that values sealed under different keys can be told apart when
+/// keys are rotated.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::repository::EncryptionKey;
+///
+/// let key = EncryptionKey::from_bytes([7u8; 32]);
+/// let derived = EncryptionKey::from_passphrase(b"correct horse", b"sixteen byte salt").unwrap();
+/// assert_ne!(key.id(), derived.id());
+/// ```
+#[derive(Clone)]
+pub struct EncryptionKey {
+    id: [u8; KEY_ID_LEN],
+    cipher: XChaCha20Poly1305,
+}
+
+impl EncryptionKey {
+    /// Creates a key from 32 raw bytes.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        let digest = Sha256::digest(&bytes);
+        let mut id = [0u8; KEY_ID_LEN];
+        id.copy_from_slice(&digest[..KEY_ID_LEN]);
+        EncryptionKey {
+            id,
+            cipher: XChaCha20Poly1305::new(&bytes.into()),
+        }
+    }
+
+    /// Derives a key from a passphrase using Argon2id.
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - The passphrase to derive the key from.
+    /// * `salt` - A salt of at least 8 bytes, stored alongside the data it protects.
+    pub fn from_passphrase(passphrase: &[u8], salt: &[u8]) -> Result<Self, RepoError> {
+        let mut bytes = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase, salt, &mut bytes)
+            .map_err(RepoError::KeyDerivation)?;
+        Ok(Self::from_bytes(bytes))
+    }
+
+    /// Loads a key from a file containing 32 hex-encoded bytes, generating and writing a new
+    /// random key if the file does not exist yet. The key is written to a temporary file
+    /// readable by its owner only and then linked into place whole, so that no other process
+    /// reads it half written; if another process links its key first, that key is loaded
+    /// instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the key file.
+    pub fn load_or_create(path: &Path) -> Result<Self, RepoError> {
+        if !path.exists() {
+            let mut bytes = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("key");
+            let temp = path.with_file_name(format!(".{}.{}.tmp", name, rand::random::<u64>()));
+            let written = create_private(&temp).and_then(|mut file| {
+                file.write_all(hex::encode(bytes).as_bytes())?;
+                file.sync_all()
+            });
+            // a hard link fails if the file exists, where a rename would replace it
+            let linked = written.and_then(|()| fs::hard_link(&temp, path));
+            let _ = fs::remove_file(&temp);
+            match linked {
+                Ok(()) => return Ok(Self::from_bytes(bytes)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let invalid = || RepoError::InvalidKeyFile(path.display().to_string());
+        let contents = fs::read_to_string(path)?;
+        let bytes: [u8; 32] = hex::decode(contents.trim())
+            .map_err(|_| invalid())?
+            .try_into()
+            .map_err(|_| invalid())?;
+        Ok(Self::from_bytes(bytes))
+    }
+
+    /// Returns the id recorded in the header of values sealed with this key.
+    pub fn id(&self) -> [u8; KEY_ID_LEN] {
+        self.id
+    }
+
+    /// Seals a plaintext value, binding it to the database key it is stored under.
+    fn seal(&self, key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, RepoError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: key,
+                },
+            )
+            .map_err(|_| RepoError::EncryptionFailed)?;
+
+        let mut sealed = Vec::with_capacity(1 + KEY_ID_LEN + NONCE_LEN + ciphertext.len());
+        sealed.push(FORMAT_ENCRYPTED);
+        sealed.extend_from_slice(&self.id);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Opens a value produced by `seal` for the same database key.
+    fn open(&self, key: &[u8], sealed: &[u8]) -> Result<Vec<u8>, RepoError> {
+        let header = 1 + KEY_ID_LEN + NONCE_LEN;
+        if sealed.len() < header {
+            return Err(RepoError::DecryptionFailed);
+        }
+        if sealed[1..1 + KEY_ID_LEN] != self.id {
+            return Err(RepoError::WrongEncryptionKey);
+        }
+        self.cipher
+            .decrypt(
+                XNonce::from_slice(&sealed[1 + KEY_ID_LEN..header]),
+                Payload {
+                    msg: &sealed[header..],
+                    aad: key,
+                },
+            )
+            .map_err(|_| RepoError::DecryptionFailed)
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionKey")
+            .field("id", &hex::encode(self.id))
+            .finish()
+    }
+}
+
+/// Creates a new file at `path` that only its owner can read and write, failing with
+/// `AlreadyExists` if there is one already.
+fn create_private(path: &Path) -> std::io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
 
 /// Represents a share entry in the database.
 ///
@@ -10,8 +491,24 @@ use std::sync::Mutex;
 ///
 /// # Fields
 ///
-/// * `share` - A tuple containing the share identifier (u8) and the share data (Vec<u8>).
+/// * `share` - A tuple containing the share identifier (`ShareIndex`) and the share data (Vec<u8>).
+/// * `field` - The field the secret was split over, which bounds the share identifier. Only with
+///   the `sss16` feature; entries are otherwise split over GF(2^8).
 /// * `sender` - A vector of bytes representing the sender's information.
+/// * `threshold` - The threshold the share was split with.
+/// * `expires_at` - The unix timestamp (seconds) after which the share is destroyed, if any.
+/// * `epoch` - The number of refreshes the share has been through since it was registered.
+/// * `last_refreshed_unix` - The unix timestamp (seconds) of the last refresh, or of the
+///   registration if the share has not been refreshed yet.
+/// * `refresh_digest` - The digest of the refresh key that advanced the share to `epoch`, so that
+///   a repeated delivery of the same refresh is recognized. `None` until the first refresh.
+/// * `refresh_interval_secs` - How often the owner asked for the share to be refreshed, in
+///   seconds. `None` leaves it to the provider's default interval.
+/// * `readers` - The peer id bytes of the peers the owner allowed to get the share besides
+///   itself.
+/// * `relay_grant` - The owner's permission for the provider storing the share to relay
+///   refreshes of it to the other providers. `None` if the owner gave none, which leaves the
+///   provider unable to coordinate a refresh of the share.
 ///
 /// # Examples
 ///
@@ -24,13 +521,510 @@ use std::sync::Mutex;
 ///     share: (1, vec![2, 3, 4]),
 ///     sender: vec![5, 6, 7],
 ///     threshold: 2,
+///     ..Default::default()
 /// };
 /// ```
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct ShareEntry {
+    pub share: (ShareIndex, Vec<u8>),
+    #[cfg(feature = "sss16")]
+    pub field: ShareField,
+    pub sender: Vec<u8>,
+    pub threshold: u64,
+    pub expires_at: Option<u64>,
+    pub epoch: u64,
+    pub last_refreshed_unix: u64,
+    pub refresh_digest: Option<[u8; 32]>,
+    pub refresh_interval_secs: Option<u64>,
+    pub readers: Vec<Vec<u8>>,
+    pub relay_grant: Option<RelayGrant>,
+}
+
+/// The identifier of a share, the x coordinate it was evaluated at. Secrets are split over GF(2^8)
+/// into at most 255 shares; the `sss16` feature widens identifiers to 16 bits for secrets split
+/// over GF(2^16).
+#[cfg(not(feature = "sss16"))]
+pub type ShareIndex = u8;
+
+/// The identifier of a share, the x coordinate it was evaluated at. Secrets are split over GF(2^8)
+/// into at most 255 shares; the `sss16` feature widens identifiers to 16 bits for secrets split
+/// over GF(2^16).
+#[cfg(feature = "sss16")]
+pub type ShareIndex = u16;
+
+/// The signature with which the owner of a share lets the provider storing it relay refreshes of
+/// it to the other providers of the share, on the owner's behalf. The signature covers the key,
+/// the provider and the owner, so a grant is only good for the provider it was given to (see
+/// `protocol::RelayGrant` for signing and checking one).
+///
+/// # Fields
+///
+/// * `public_key` - The protobuf encoding of the owner's public key.
+/// * `signature` - The owner's signature of the grant.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RelayGrant {
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// The Galois field a secret was split over, which decides how its shares are combined and
+/// refreshed. Only with the `sss16` feature; secrets are otherwise split over GF(2^8).
+///
+/// # Variants
+///
+/// * `Gf256` - GF(2^8), split by `sss`: share identifiers up to 255, one byte of share per byte of
+///   secret.
+/// * `Gf65536` - GF(2^16), split by `sss16`: share identifiers up to 65,535, two bytes of share per
+///   byte of secret.
+#[cfg(feature = "sss16")]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ShareField {
+    #[default]
+    Gf256,
+    Gf65536,
+}
+
+#[cfg(feature = "sss16")]
+impl ShareField {
+    /// Returns the largest share identifier of the field.
+    pub fn max_index(self) -> u16 {
+        match self {
+            ShareField::Gf256 => u8::MAX as u16,
+            ShareField::Gf65536 => u16::MAX,
+        }
+    }
+}
+
+#[cfg(feature = "sss16")]
+impl fmt::Display for ShareField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShareField::Gf256 => write!(f, "GF(2^8)"),
+            ShareField::Gf65536 => write!(f, "GF(2^16)"),
+        }
+    }
+}
+
+impl ShareEntry {
+    /// Checks whether the entry has expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current unix timestamp in seconds.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the entry has an expiry time and it is not after `now`.
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Returns how often the share is refreshed, in seconds.
+    ///
+    /// # Arguments
+    ///
+    /// * `default` - The provider's refresh interval, used when the owner did not choose one.
+    pub fn refresh_interval(&self, default: u64) -> u64 {
+        self.refresh_interval_secs.unwrap_or(default)
+    }
+
+    /// Checks whether `peer` may get the share: its owner or one of its readers.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The peer id bytes of the requesting peer.
+    pub fn can_read(&self, peer: &[u8]) -> bool {
+        self.sender == peer || self.readers.iter().any(|reader| reader == peer)
+    }
+
+    /// Checks that the entry can be stored under `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key the entry is to be stored under.
+    ///
+    /// # Returns
+    ///
+    /// `Ok` if the entry is well-formed, otherwise `RepoError::InvalidEntry` with the reason.
+    pub fn validate(&self, key: &str) -> Result<(), RepoError> {
+        let reason = if key.is_empty() {
+            "empty key"
+        } else if self.share.0 == 0 {
+            "share index 0 is the secret itself"
+        } else if self.share.1.is_empty() {
+            "empty share"
+        } else if let Some(reason) = self.field_violation() {
+            reason
+        } else if self.sender.is_empty() {
+            "missing owner"
+        } else if self.threshold < 2 {
+            "threshold below 2"
+        } else {
+            return Ok(());
+        };
+        Err(RepoError::InvalidEntry(key.to_string(), reason))
+    }
+
+    /// Returns why the share does not fit the field its secret was split over, if it does not.
+    #[cfg(feature = "sss16")]
+    fn field_violation(&self) -> Option<&'static str> {
+        if self.share.0 > self.field.max_index() {
+            Some("share index beyond its field")
+        } else if self.field == ShareField::Gf65536 && !self.share.1.len().is_multiple_of(2) {
+            Some("odd share length over GF(2^16)")
+        } else {
+            None
+        }
+    }
+
+    /// Returns why the share does not fit the field its secret was split over, if it does not.
+    /// Every share fits GF(2^8), the only field without the `sss16` feature.
+    #[cfg(not(feature = "sss16"))]
+    fn field_violation(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+/// Validates every entry of a batch before any of it is written.
+fn validate_batch(entries: &[(String, ShareEntry)]) -> Result<(), RepoError> {
+    entries
+        .iter()
+        .try_for_each(|(key, entry)| entry.validate(key))
+}
+
+/// Checks that a refresh batch update replaces an existing entry without changing its owner.
+fn check_refresh(
+    key: &str,
+    current: Option<&ShareEntry>,
+    new: &ShareEntry,
+) -> Result<(), RepoError> {
+    match current {
+        None => Err(RepoError::KeyNotFound(key.to_string())),
+        Some(current) if current.sender != new.sender => Err(RepoError::InvalidEntry(
+            key.to_string(),
+            "refresh changes the owner",
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+/// The layout of a share entry stored as JSON by earlier releases, before the threshold was
+/// recorded.
+///
+/// # Fields
+///
+/// * `share` - A tuple containing the share identifier (u8) and the share data (Vec<u8>).
+/// * `sender` - A vector of bytes representing the sender's information.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LegacyShareEntry {
+    pub share: (u8, Vec<u8>),
+    pub sender: Vec<u8>,
+}
+
+/// Legacy entries are assumed to have been split with `LEGACY_DEFAULT_THRESHOLD` over GF(2^8).
+/// They start at epoch 0 with an unknown (zero) refresh time, so the refresh loop treats them as
+/// due, and leave their provider unable to coordinate their refresh until the owner registers them
+/// again with a relay grant.
+impl From<LegacyShareEntry> for ShareEntry {
+    fn from(legacy: LegacyShareEntry) -> Self {
+        // the identity without the `sss16` feature
+        #[allow(clippy::useless_conversion)]
+        let share = (legacy.share.0.into(), legacy.share.1);
+        ShareEntry {
+            share,
+            sender: legacy.sender,
+            threshold: LEGACY_DEFAULT_THRESHOLD,
+            ..Default::default()
+        }
+    }
+}
+
+/// The version 1 layout of a stored share entry, with an 8-bit share identifier and no field.
+///
+/// With the `sss16` feature `ShareEntry` is the `WIDE_SHARE_ENTRY_VERSION` layout, and entries of
+/// shares split over GF(2^8) are still written in this one, so that builds without the feature
+/// keep reading them.
+///
+/// # Fields
+///
+/// * `share` - A tuple containing the share identifier (u8) and the share data (Vec<u8>).
+/// * `sender` - A vector of bytes representing the sender's information.
+/// * `threshold` - The threshold the share was split with.
+/// * `expires_at` - The unix timestamp (seconds) after which the share is destroyed, if any.
+/// * `epoch` - The number of refreshes the share has been through since it was registered.
+/// * `last_refreshed_unix` - The unix timestamp (seconds) of the last refresh.
+/// * `refresh_digest` - The digest of the refresh key that advanced the share to `epoch`.
+/// * `refresh_interval_secs` - How often the owner asked for the share to be refreshed.
+/// * `readers` - The peer id bytes of the peers the owner allowed to get the share.
+/// * `relay_grant` - The owner's permission for the provider to relay refreshes of the share.
+#[cfg(feature = "sss16")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShareEntryV1 {
     pub share: (u8, Vec<u8>),
     pub sender: Vec<u8>,
     pub threshold: u64,
+    pub expires_at: Option<u64>,
+    pub epoch: u64,
+    pub last_refreshed_unix: u64,
+    pub refresh_digest: Option<[u8; 32]>,
+    pub refresh_interval_secs: Option<u64>,
+    pub readers: Vec<Vec<u8>>,
+    pub relay_grant: Option<RelayGrant>,
+}
+
+#[cfg(feature = "sss16")]
+impl ShareEntryV1 {
+    /// Returns `entry` in the version 1 layout, or `None` if its share was not split over GF(2^8).
+    fn narrowed(entry: &ShareEntry) -> Option<Self> {
+        if entry.field != ShareField::Gf256 {
+            return None;
+        }
+        Some(ShareEntryV1 {
+            share: (u8::try_from(entry.share.0).ok()?, entry.share.1.clone()),
+            sender: entry.sender.clone(),
+            threshold: entry.threshold,
+            expires_at: entry.expires_at,
+            epoch: entry.epoch,
+            last_refreshed_unix: entry.last_refreshed_unix,
+            refresh_digest: entry.refresh_digest,
+            refresh_interval_secs: entry.refresh_interval_secs,
+            readers: entry.readers.clone(),
+            relay_grant: entry.relay_grant.clone(),
+        })
+    }
+}
+
+#[cfg(feature = "sss16")]
+impl From<ShareEntryV1> for ShareEntry {
+    fn from(v1: ShareEntryV1) -> Self {
+        ShareEntry {
+            share: (v1.share.0.into(), v1.share.1),
+            field: ShareField::Gf256,
+            sender: v1.sender,
+            threshold: v1.threshold,
+            expires_at: v1.expires_at,
+            epoch: v1.epoch,
+            last_refreshed_unix: v1.last_refreshed_unix,
+            refresh_digest: v1.refresh_digest,
+            refresh_interval_secs: v1.refresh_interval_secs,
+            readers: v1.readers,
+            relay_grant: v1.relay_grant,
+        }
+    }
+}
+
+/// Builds the storage key of `key` in the namespace of `owner`, so that different owners can
+/// register the same key without colliding.
+///
+/// # Arguments
+///
+/// * `owner` - The owner's peer id bytes, as stored in `ShareEntry::sender`.
+/// * `key` - The key chosen by the owner.
+///
+/// # Returns
+///
+/// The composite key the entry is stored under.
+pub fn owner_key(owner: &[u8], key: &str) -> String {
+    format!("{}{}/{}", OWNER_KEY_PREFIX, hex::encode(owner), key)
+}
+
+/// Splits a storage key built by `owner_key` back into the owner and the owner's key.
+///
+/// # Arguments
+///
+/// * `stored_key` - The key an entry is stored under.
+///
+/// # Returns
+///
+/// The owner's peer id bytes and key, or `None` if `stored_key` is not owner-scoped.
+pub fn split_owner_key(stored_key: &str) -> Option<(Vec<u8>, &str)> {
+    let (owner, key) = stored_key.strip_prefix(OWNER_KEY_PREFIX)?.split_once('/')?;
+    Some((hex::decode(owner).ok()?, key))
+}
+
+/// Summary of what a store holds, for health reporting and quota checks.
+///
+/// # Fields
+///
+/// * `entries` - The number of stored entries.
+/// * `total_value_bytes` - The approximate number of bytes taken by the stored values.
+/// * `per_owner` - The number of entries registered by each owner, ordered by owner.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DaoStats {
+    pub entries: u64,
+    pub total_value_bytes: u64,
+    pub per_owner: Vec<(Vec<u8>, u64)>,
+}
+
+impl DaoStats {
+    /// Builds the statistics of a set of entries, sizing each by its tagged binary encoding.
+    fn from_entries<'a>(
+        entries: impl IntoIterator<Item = &'a ShareEntry>,
+    ) -> Result<Self, RepoError> {
+        let mut stats = DaoStats::default();
+        let mut per_owner: BTreeMap<Vec<u8>, u64> = BTreeMap::new();
+        for entry in entries {
+            stats.entries += 1;
+            stats.total_value_bytes += encode_entry(entry)?.len() as u64;
+            *per_owner.entry(entry.sender.clone()).or_default() += 1;
+        }
+        stats.per_owner = per_owner.into_iter().collect();
+        Ok(stats)
+    }
+}
+
+/// A record left in place of a deleted entry, so that a late or replayed registration does not
+/// bring the entry back.
+///
+/// # Fields
+///
+/// * `owner` - The owner of the deleted entry.
+/// * `deleted_at` - The unix timestamp (seconds) the entry was deleted at.
+/// * `expires_at` - The unix timestamp (seconds) after which the tombstone no longer blocks
+///   registrations and is purged.
+/// * `reason` - Why the entry was deleted.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tombstone {
+    pub owner: Vec<u8>,
+    pub deleted_at: u64,
+    pub expires_at: u64,
+    pub reason: String,
+}
+
+impl Tombstone {
+    /// Checks whether the tombstone still blocks registrations.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current unix timestamp in seconds.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the tombstone has not expired by `now`.
+    pub fn is_live(&self, now: u64) -> bool {
+        now < self.expires_at
+    }
+}
+
+/// Storage limits a DAO enforces on `insert`, `update`, `insert_batch` and
+/// `apply_refresh_batch`. A limit left as `None` is not enforced.
+///
+/// Writes that do not grow the usage a limit measures, such as replacing an entry with a smaller
+/// one, are always accepted, so that an owner over a lowered limit can still shrink.
+///
+/// # Fields
+///
+/// * `max_entries_per_owner` - The number of entries a single owner may store.
+/// * `max_bytes_per_owner` - The number of stored value bytes a single owner may take.
+/// * `max_total_bytes` - The number of stored value bytes the whole store may take.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DaoQuotas {
+    pub max_entries_per_owner: Option<u64>,
+    pub max_bytes_per_owner: Option<u64>,
+    pub max_total_bytes: Option<u64>,
+}
+
+impl DaoQuotas {
+    /// Whether no limit is set.
+    fn is_unlimited(&self) -> bool {
+        *self == DaoQuotas::default()
+    }
+
+    /// Checks that applying `delta` keeps the store within the quotas.
+    ///
+    /// # Arguments
+    ///
+    /// * `delta` - The change the writes make to the usage.
+    /// * `total_bytes` - The number of stored value bytes before the writes.
+    /// * `owner_usage` - Looks up the number of entries and stored value bytes of an owner.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RepoError::QuotaExceeded` naming the first limit the writes would exceed.
+    fn check(
+        &self,
+        delta: &QuotaDelta,
+        total_bytes: u64,
+        mut owner_usage: impl FnMut(&[u8]) -> Result<(u64, u64), RepoError>,
+    ) -> Result<(), RepoError> {
+        let check = |limit: &'static str, max: Option<u64>, used: u64, delta: i64| match max {
+            Some(max) if delta > 0 && used.saturating_add_signed(delta) > max => {
+                Err(RepoError::QuotaExceeded { limit, max, used })
+            }
+            _ => Ok(()),
+        };
+        check(
+            "max_total_bytes",
+            self.max_total_bytes,
+            total_bytes,
+            delta.total_bytes,
+        )?;
+        if self.max_entries_per_owner.is_none() && self.max_bytes_per_owner.is_none() {
+            return Ok(());
+        }
+        for (owner, &(entries, bytes)) in &delta.per_owner {
+            let (used_entries, used_bytes) = owner_usage(owner)?;
+            check(
+                "max_entries_per_owner",
+                self.max_entries_per_owner,
+                used_entries,
+                entries,
+            )?;
+            check(
+                "max_bytes_per_owner",
+                self.max_bytes_per_owner,
+                used_bytes,
+                bytes,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The change a set of writes makes to the usage limited by `DaoQuotas`.
+///
+/// # Fields
+///
+/// * `per_owner` - The change in entries and stored value bytes of each owner.
+/// * `total_bytes` - The change in stored value bytes of the whole store.
+#[derive(Debug, Default)]
+struct QuotaDelta {
+    per_owner: BTreeMap<Vec<u8>, (i64, i64)>,
+    total_bytes: i64,
+}
+
+impl QuotaDelta {
+    /// Records a write of a `new_len` byte value holding an entry of `owner`.
+    ///
+    /// # Arguments
+    ///
+    /// * `old` - The owner, if it can be read, and size of the value the write replaces.
+    /// * `owner` - The owner of the written entry.
+    /// * `new_len` - The size of the written value.
+    fn add(&mut self, old: Option<(Option<Vec<u8>>, u64)>, owner: &[u8], new_len: u64) {
+        let old_len = old.as_ref().map_or(0, |(_, len)| *len);
+        self.total_bytes += new_len as i64 - old_len as i64;
+        if let Some((Some(old_owner), old_len)) = old {
+            let usage = self.per_owner.entry(old_owner).or_default();
+            usage.0 -= 1;
+            usage.1 -= old_len as i64;
+        }
+        let usage = self.per_owner.entry(owner.to_vec()).or_default();
+        usage.0 += 1;
+        usage.1 += new_len as i64;
+    }
+}
+
+impl fmt::Display for DaoStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} entries, {} bytes, {} owners",
+            self.entries,
+            self.total_value_bytes,
+            self.per_owner.len()
+        )
+    }
 }
 
 /// Defines the Data Access Object (DAO) trait for `ShareEntry`.
@@ -48,86 +1042,1408 @@ pub trait ShareEntryDaoTrait: Send + Sync {
     /// # Returns
     ///
     /// A `Result` indicating the success or failure of the operation.
-    fn insert(&self, key: &str, entry: &ShareEntry) -> Result<(), Box<dyn Error>>;
+    fn insert(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError>;
+
+    /// Retrieves a `ShareEntry` from the data store by its key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the `ShareEntry` to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `Option<ShareEntry>`. `None` if the key does not exist.
+    fn get(&self, key: &str) -> Result<Option<ShareEntry>, RepoError>;
+
+    /// Retrieves a page of entries in key order.
+    ///
+    /// Walking a large store a page at a time bounds memory use and lets callers release any
+    /// lock on the DAO between pages.
+    ///
+    /// # Arguments
+    ///
+    /// * `after` - Only keys strictly greater than this are returned; `None` starts from the
+    ///   first key. Pass the last key of the previous page to continue.
+    /// * `limit` - The maximum number of entries to return.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing up to `limit` entries. A page shorter than `limit` is the last one.
+    fn get_page(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, ShareEntry)>, RepoError>;
+
+    /// Retrieves every entry in key order, reading the store a page at a time.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing all entries.
+    fn get_all(&self) -> Result<Vec<(String, ShareEntry)>, RepoError> {
+        let mut entries: Vec<(String, ShareEntry)> = Vec::new();
+        loop {
+            let after = entries.last().map(|(key, _)| key.as_str());
+            let page = self.get_page(after, DEFAULT_PAGE_SIZE)?;
+            let last_page = page.len() < DEFAULT_PAGE_SIZE;
+            entries.extend(page);
+            if last_page {
+                return Ok(entries);
+            }
+        }
+    }
+
+    /// Updates an existing `ShareEntry` in the data store.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the `ShareEntry` to update.
+    /// * `entry` - The new `ShareEntry` data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success or failure of the operation.
+    fn update(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError>;
+
+    /// Deletes a `ShareEntry` from the data store by its key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the `ShareEntry` to delete.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success or failure of the operation.
+    fn delete(&self, key: &str) -> Result<(), RepoError>;
+
+    /// Rewrites every entry stored in an older schema version in the current one.
+    ///
+    /// Entries are migrated lazily when they are read, so this is an optimisation the provider
+    /// runs once at startup rather than a requirement. Stores without persistent state have
+    /// nothing to migrate.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of entries that were upgraded.
+    fn migrate_all(&self) -> Result<usize, RepoError> {
+        Ok(0)
+    }
+
+    /// Lists the keys of all entries that have expired.
+    ///
+    /// The default implementation scans every entry; stores that can index entries by expiry
+    /// time should override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current unix timestamp in seconds.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the keys of the expired entries.
+    fn expired_keys(&self, now: u64) -> Result<Vec<String>, RepoError> {
+        Ok(self
+            .get_all()?
+            .into_iter()
+            .filter(|(_, entry)| entry.is_expired(now))
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    /// Replaces the entry under `key` with `new` only if it currently equals `expected`.
+    ///
+    /// This lets read-modify-write callers detect that another writer changed the entry in
+    /// between, and retry against the new value instead of overwriting it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the `ShareEntry` to replace.
+    /// * `expected` - The entry the caller read and based `new` on.
+    /// * `new` - The entry to store.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `true` if the entry was replaced, or `false` if it had changed or no
+    /// longer exists.
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: &ShareEntry,
+        new: &ShareEntry,
+    ) -> Result<bool, RepoError>;
+
+    /// Lists every key in key order.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the keys.
+    fn keys(&self) -> Result<Vec<String>, RepoError> {
+        self.keys_with_prefix("")
+    }
+
+    /// Lists a page of keys in key order without decoding their values, so that a walk over the
+    /// store can read each entry on its own and step over the ones that fail to decode.
+    ///
+    /// The default implementation lists every key; stores that can seek in key order should
+    /// override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `after` - Only keys strictly greater than this are returned; `None` starts from the
+    ///   first key.
+    /// * `limit` - The maximum number of keys to return.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing up to `limit` keys. A page shorter than `limit` is the last one.
+    fn keys_page(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>, RepoError> {
+        Ok(self
+            .keys()?
+            .into_iter()
+            .filter(|key| after.is_none_or(|after| key.as_str() > after))
+            .take(limit)
+            .collect())
+    }
+
+    /// Lists the keys starting with `prefix` in key order.
+    ///
+    /// The default implementation decodes every value; stores that can list keys alone should
+    /// override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix keys must start with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the matching keys.
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, RepoError> {
+        Ok(self
+            .get_all()?
+            .into_iter()
+            .map(|(key, _)| key)
+            .filter(|key| key.starts_with(prefix))
+            .collect())
+    }
+
+    /// Retrieves the entry `owner` registered under `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The owner's peer id bytes.
+    /// * `key` - The key chosen by the owner.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `Option<ShareEntry>`. `None` if the owner has no entry under `key`.
+    fn get_owned(&self, owner: &[u8], key: &str) -> Result<Option<ShareEntry>, RepoError> {
+        self.get(&owner_key(owner, key))
+    }
+
+    /// Stores `entry` under `key` in the namespace of its owner, `entry.sender`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key chosen by the owner.
+    /// * `entry` - The `ShareEntry` to store.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    fn insert_owned(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+        self.insert(&owner_key(&entry.sender, key), entry)
+    }
+
+    /// Deletes the entry `owner` registered under `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The owner's peer id bytes.
+    /// * `key` - The key chosen by the owner.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    fn delete_owned(&self, owner: &[u8], key: &str) -> Result<(), RepoError> {
+        self.delete(&owner_key(owner, key))
+    }
+
+    /// Moves entries stored under a plain key, as written before keys were owner-scoped, into
+    /// the namespace of their owner. If the owner has since registered the same key, the newer
+    /// owner-scoped entry is kept and the plain one dropped.
+    ///
+    /// Only the keys are listed, so a store that has already been migrated reads no values.
+    /// Entries that cannot be read are left where they are.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of entries moved.
+    fn migrate_owner_keys(&self) -> Result<usize, RepoError> {
+        let mut moved = 0;
+        for key in self.keys()? {
+            if split_owner_key(&key).is_some() {
+                continue;
+            }
+            // an entry that cannot be read is left for the integrity scan to quarantine
+            let Ok(Some(entry)) = self.get(&key) else {
+                continue;
+            };
+            if self.get_owned(&entry.sender, &key)?.is_none() {
+                self.insert_owned(&key, &entry)?;
+                moved += 1;
+            }
+            self.delete(&key)?;
+        }
+        Ok(moved)
+    }
+
+    /// Lists the keys of every entry registered by `owner` in key order.
+    ///
+    /// The default implementation inspects every value; stores that can look entries up by owner
+    /// should override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The owner's peer id bytes, as stored in `ShareEntry::sender`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the owner's keys.
+    fn keys_by_owner(&self, owner: &[u8]) -> Result<Vec<String>, RepoError> {
+        Ok(self
+            .get_all()?
+            .into_iter()
+            .filter(|(_, entry)| entry.sender == owner)
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    /// Deletes every entry registered by `owner`.
+    ///
+    /// The default implementation walks the store a page at a time; stores that can look entries
+    /// up by owner should override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The owner's peer id bytes, as stored in `ShareEntry::sender`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the keys of the deleted entries, so callers can stop providing them.
+    fn delete_by_owner(&self, owner: &[u8]) -> Result<Vec<String>, RepoError> {
+        let mut deleted = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let page = self.get_page(cursor.as_deref(), DEFAULT_PAGE_SIZE)?;
+            for (key, entry) in &page {
+                if entry.sender == owner {
+                    self.delete(key)?;
+                    deleted.push(key.clone());
+                }
+            }
+            match page.last() {
+                Some((last_key, _)) if page.len() == DEFAULT_PAGE_SIZE => {
+                    cursor = Some(last_key.clone())
+                }
+                _ => return Ok(deleted),
+            }
+        }
+    }
+
+    /// Inserts a group of entries, replacing any stored under the same keys.
+    ///
+    /// Every entry is validated first, so an invalid entry rejects the whole batch. The default
+    /// implementation then inserts the entries one at a time; stores that can apply the batch
+    /// atomically override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The keys and entries to insert.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, or the first validation or storage error.
+    fn insert_batch(&self, entries: &[(String, ShareEntry)]) -> Result<(), RepoError> {
+        validate_batch(entries)?;
+        for (key, entry) in entries {
+            self.insert(key, entry)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces a group of existing entries with their refreshed values.
+    ///
+    /// Every update must be valid, name a stored key, and keep its owner, otherwise nothing is
+    /// written. The default implementation checks the batch before updating the entries one at a
+    /// time; stores that can apply the batch atomically override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `updates` - The keys and refreshed entries.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, or the first validation or storage error.
+    fn apply_refresh_batch(&self, updates: &[(String, ShareEntry)]) -> Result<(), RepoError> {
+        validate_batch(updates)?;
+        for (key, entry) in updates {
+            check_refresh(key, self.get(key)?.as_ref(), entry)?;
+        }
+        for (key, entry) in updates {
+            self.update(key, entry)?;
+        }
+        Ok(())
+    }
+
+    /// Reports the number and size of the stored entries.
+    ///
+    /// The default implementation reads the whole store a page at a time; stores that can keep
+    /// counters should override it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `DaoStats` of the store.
+    fn stats(&self) -> Result<DaoStats, RepoError> {
+        let entries = self.get_all()?;
+        DaoStats::from_entries(entries.iter().map(|(_, entry)| entry))
+    }
+
+    /// Writes every entry to `writer` as a versioned backup stream (see `export_entries`).
+    ///
+    /// Stores that encrypt entries at rest also encrypt the stream with the same key.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The destination of the stream.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of entries exported.
+    fn export(&self, writer: &mut dyn Write) -> Result<usize, RepoError> {
+        export_entries(self, writer, None)
+    }
+
+    /// Reads a backup stream written by `export` into the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The source of the stream.
+    /// * `conflict` - How to treat keys that already exist with the same owner. Keys owned by a
+    ///   different peer are always skipped and reported.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `ImportReport`.
+    fn import(
+        &self,
+        reader: &mut dyn Read,
+        conflict: ConflictPolicy,
+    ) -> Result<ImportReport, RepoError> {
+        import_entries(self, reader, conflict, None)
+    }
+
+    /// Stores a tombstone under `key`, replacing any tombstone already there.
+    ///
+    /// Tombstones are kept apart from the entries: they are not listed, paged, counted, or
+    /// exported.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the deleted entry.
+    /// * `tombstone` - The `Tombstone` to store.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    fn put_tombstone(&self, key: &str, tombstone: &Tombstone) -> Result<(), RepoError>;
+
+    /// Retrieves the tombstone stored under `key`, whether or not it is still live.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the deleted entry.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Tombstone`, or `None` if the key has none.
+    fn get_tombstone(&self, key: &str) -> Result<Option<Tombstone>, RepoError>;
+
+    /// Removes the tombstone stored under `key`, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the deleted entry.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    fn remove_tombstone(&self, key: &str) -> Result<(), RepoError>;
+
+    /// Removes every tombstone that has expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current unix timestamp in seconds.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of tombstones removed.
+    fn purge_tombstones(&self, now: u64) -> Result<usize, RepoError>;
+
+    /// Deletes the entry under `key`, leaving a tombstone naming its owner in its place.
+    ///
+    /// The tombstone is written before the entry is deleted, so a failure in between leaves the
+    /// key blocked rather than deleted without a trace. Once this returns, `get` reports the key
+    /// absent.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the entry to delete.
+    /// * `reason` - Why the entry is deleted.
+    /// * `now` - The current unix timestamp in seconds.
+    /// * `window` - How long the tombstone blocks registrations under `key`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Tombstone` written, or `None` if no entry was stored under
+    /// `key`.
+    fn delete_with_tombstone(
+        &self,
+        key: &str,
+        reason: &str,
+        now: u64,
+        window: Duration,
+    ) -> Result<Option<Tombstone>, RepoError> {
+        let Some(entry) = self.get(key)? else {
+            return Ok(None);
+        };
+        let tombstone = Tombstone {
+            owner: entry.sender,
+            deleted_at: now,
+            expires_at: now.saturating_add(window.as_secs()),
+            reason: reason.to_string(),
+        };
+        self.put_tombstone(key, &tombstone)?;
+        self.delete(key)?;
+        Ok(Some(tombstone))
+    }
+
+    /// Deletes every entry registered by `owner`, leaving a tombstone in place of each.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The owner's peer id bytes, as stored in `ShareEntry::sender`.
+    /// * `reason` - Why the entries are deleted.
+    /// * `now` - The current unix timestamp in seconds.
+    /// * `window` - How long the tombstones block registrations.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the keys of the deleted entries, so callers can stop providing them.
+    fn tombstone_by_owner(
+        &self,
+        owner: &[u8],
+        reason: &str,
+        now: u64,
+        window: Duration,
+    ) -> Result<Vec<String>, RepoError> {
+        let mut deleted = Vec::new();
+        for key in self.keys_by_owner(owner)? {
+            if self
+                .delete_with_tombstone(&key, reason, now, window)?
+                .is_some()
+            {
+                deleted.push(key);
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Moves the entry under `key` out of the store into quarantine, keeping its value as stored
+    /// so that it can still be inspected. Once this returns, `get` reports the key absent and
+    /// `is_quarantined` reports it quarantined. The value need not decode.
+    ///
+    /// The default refuses, for stores that cannot keep quarantined values.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the entry to quarantine.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `true` if an entry was quarantined, or `false` if none was stored
+    /// under `key`.
+    fn quarantine(&self, key: &str) -> Result<bool, RepoError> {
+        Err(RepoError::Unsupported(format!(
+            "this store cannot quarantine the entry under {}",
+            key
+        )))
+    }
+
+    /// Reports whether an entry under `key` was quarantined.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to look up.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `true` if the key is quarantined.
+    fn is_quarantined(&self, _key: &str) -> Result<bool, RepoError> {
+        Ok(false)
+    }
+
+    /// Lists the keys of every quarantined entry in key order.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the quarantined keys.
+    fn quarantined_keys(&self) -> Result<Vec<String>, RepoError> {
+        Ok(Vec::new())
+    }
+
+    /// Reports whether the store refuses every write.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the store was opened read-only.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    /// Makes every write made so far durable.
+    ///
+    /// Handlers call this at their ack points, before telling a peer that its write succeeded.
+    /// The default does nothing, for stores that are durable once a write returns.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    fn flush(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
+
+/// When the sled DAO flushes writes to disk on its own, between the explicit `flush` calls made
+/// at ack points.
+///
+/// # Variants
+///
+/// * `EveryWrite` - Flush after every write.
+/// * `EveryNWrites(n)` - Flush after every `n` writes.
+/// * `Interval(period)` - Leave flushing to a background task running every `period`.
+/// * `Never` - Only flush when `flush` is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    EveryWrite,
+    EveryNWrites(u64),
+    Interval(Duration),
+    Never,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        FlushPolicy::Interval(Duration::from_secs(DEFAULT_FLUSH_SECONDS))
+    }
+}
+
+impl std::str::FromStr for FlushPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let count = |value: &str| match value.parse::<u64>() {
+            Ok(count) if count > 0 => Ok(count),
+            _ => Err(format!("expected a positive number, got {}", value)),
+        };
+        match s.split_once(':') {
+            None if s == "every-write" => Ok(FlushPolicy::EveryWrite),
+            None if s == "never" => Ok(FlushPolicy::Never),
+            Some(("every", writes)) => Ok(FlushPolicy::EveryNWrites(count(writes)?)),
+            Some(("interval", secs)) => {
+                Ok(FlushPolicy::Interval(Duration::from_secs(count(secs)?)))
+            }
+            _ => Err(format!(
+                "unknown flush policy {}, expected every-write, every:N, interval:SECONDS or never",
+                s
+            )),
+        }
+    }
+}
+
+/// Unwraps the error of a sled transaction whose closure aborts with a `RepoError`.
+fn transaction_error(e: TransactionError<RepoError>) -> RepoError {
+    match e {
+        TransactionError::Abort(e) => e,
+        TransactionError::Storage(e) => e.into(),
+    }
+}
+
+/// A `ShareEntryDaoTrait` implementation using Sled, an embedded database.
+///
+/// This struct provides methods to interact with the Sled database for operations on `ShareEntry` objects.
+///
+/// # Fields
+///
+/// * `db` - The Sled database instance.
+/// * `entries` - The tree holding the entries: the database's default tree, or the tree of the
+///   namespace the DAO was opened in.
+/// * `meta` - A tree of markers recording which migrations have run.
+/// * `expiry` - A secondary tree indexing keys by their expiry time.
+/// * `stats` - A secondary tree of counters kept up to date by every write, backing `stats`.
+/// * `owners` - A secondary tree indexing keys by their owner, written in the same transaction as
+///   the entries.
+/// * `tombstones` - The tombstones of deleted entries, by key.
+/// * `quarantine` - The values of quarantined entries, as they were stored, by key.
+/// * `encryption_key` - The key values are sealed with at rest, if encryption is enabled.
+/// * `flush_policy` - When writes are flushed to disk without an explicit `flush`.
+/// * `unflushed_writes` - The number of writes since the last flush.
+/// * `quotas` - The storage limits enforced on writes, measured by the stats counters.
+/// * `read_only` - Whether every write is refused with `RepoError::ReadOnly`.
+/// * `indexed` - Whether the stats counters and owner index cover every entry. Only a read-only
+///   DAO over a database written before they existed lacks them, and scans the entries instead.
+pub struct SledShareEntryDao {
+    db: Db,
+    entries: Tree,
+    meta: Tree,
+    expiry: Tree,
+    stats: Tree,
+    owners: Tree,
+    tombstones: Tree,
+    quarantine: Tree,
+    encryption_key: Option<EncryptionKey>,
+    flush_policy: FlushPolicy,
+    unflushed_writes: AtomicU64,
+    quotas: DaoQuotas,
+    read_only: bool,
+    indexed: bool,
+}
+
+/// Encodes a `ShareEntry` into the tagged binary representation stored in sled.
+///
+/// # Arguments
+///
+/// * `entry` - The `ShareEntry` to encode.
+///
+/// # Returns
+///
+/// A `Result` containing the format tag, schema version, and checksum followed by the
+/// bincode-encoded entry.
+pub fn encode_entry(entry: &ShareEntry) -> Result<Vec<u8>, RepoError> {
+    let (version, payload) = encode_versioned(entry)?;
+    let mut bytes = vec![FORMAT_CHECKSUMMED, version];
+    bytes.extend_from_slice(&checksum(version, &payload).to_be_bytes());
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+/// Encodes an entry into its bincode payload, and returns the schema version of the payload.
+#[cfg(not(feature = "sss16"))]
+fn encode_versioned(entry: &ShareEntry) -> Result<(u8, Vec<u8>), RepoError> {
+    Ok((SHARE_ENTRY_VERSION, bincode::serialize(entry)?))
+}
+
+/// Encodes an entry into its bincode payload, and returns the schema version of the payload:
+/// `SHARE_ENTRY_VERSION` for shares split over GF(2^8), `WIDE_SHARE_ENTRY_VERSION` for the rest.
+#[cfg(feature = "sss16")]
+fn encode_versioned(entry: &ShareEntry) -> Result<(u8, Vec<u8>), RepoError> {
+    match ShareEntryV1::narrowed(entry) {
+        Some(v1) => Ok((SHARE_ENTRY_VERSION, bincode::serialize(&v1)?)),
+        None => Ok((WIDE_SHARE_ENTRY_VERSION, bincode::serialize(entry)?)),
+    }
+}
+
+/// Computes the checksum of a schema version and bincode payload.
+fn checksum(version: u8, payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&[version]);
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// Decodes the bincode payload of a stored entry according to its schema version.
+fn decode_versioned(version: u8, payload: &[u8]) -> Result<ShareEntry, RepoError> {
+    match version {
+        #[cfg(not(feature = "sss16"))]
+        SHARE_ENTRY_VERSION => Ok(bincode::deserialize::<ShareEntry>(payload)?),
+        #[cfg(feature = "sss16")]
+        SHARE_ENTRY_VERSION => Ok(bincode::deserialize::<ShareEntryV1>(payload)?.into()),
+        #[cfg(feature = "sss16")]
+        WIDE_SHARE_ENTRY_VERSION => Ok(bincode::deserialize::<ShareEntry>(payload)?),
+        version => Err(RepoError::UnknownVersion(version)),
+    }
+}
+
+/// Decodes a value stored in sled into a `ShareEntry`.
+///
+/// Values written by earlier releases are JSON objects; they are recognized by their leading `{`
+/// and decoded as a `LegacyShareEntry` so that existing databases remain readable. Binary values
+/// carry a schema version and are migrated to the current `ShareEntry` layout. Their checksum is
+/// verified before they are decoded.
+///
+/// # Arguments
+///
+/// * `bytes` - The raw value read from sled.
+///
+/// # Returns
+///
+/// A `Result` containing the decoded `ShareEntry` and whether the value used a legacy encoding or
+/// an older schema version and should be rewritten.
+///
+/// # Errors
+///
+/// Returns a `RepoError` when the value is empty, fails its checksum, has an unrecognized format
+/// tag or an unknown schema version, or the underlying decoding error if the payload is malformed.
+pub fn decode_entry(bytes: &[u8]) -> Result<(ShareEntry, bool), RepoError> {
+    match bytes.first() {
+        Some(&FORMAT_CHECKSUMMED) => {
+            let header = 2 + CHECKSUM_LEN;
+            if bytes.len() < header {
+                return Err(RepoError::ChecksumMismatch);
+            }
+            let version = bytes[1];
+            let expected = u32::from_be_bytes(bytes[2..header].try_into()?);
+            let payload = &bytes[header..];
+            if checksum(version, payload) != expected {
+                return Err(RepoError::ChecksumMismatch);
+            }
+            Ok((decode_versioned(version, payload)?, false))
+        }
+        Some(&FORMAT_LEGACY_JSON) => {
+            let legacy = serde_json::from_slice::<LegacyShareEntry>(bytes)?;
+            Ok((legacy.into(), true))
+        }
+        Some(&tag) => Err(RepoError::UnknownFormat(tag)),
+        None => Err(RepoError::EmptyValue),
+    }
+}
+
+impl SledShareEntryDao {
+    /// Creates a new instance of `SledShareEntryDao`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - The path to the sled database.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `SledShareEntryDao` or an error.
+    ///
+    /// # Examples
+    ///
+    /// Creating a new instance:
+    ///
+    /// ```ignore
+    /// use shard::repository::SledShareEntryDao;
+    ///
+    /// let dao = SledShareEntryDao::new("path/to/db").unwrap();
+    /// ```
+    pub fn new(db_path: &str) -> Result<Self, RepoError> {
+        Self::from_db(sled::open(db_path)?, None)
+    }
+
+    /// Creates a new instance of `SledShareEntryDao` that encrypts every value at rest.
+    ///
+    /// Values already stored in plaintext remain readable and are sealed as they are read (or
+    /// all at once by `migrate_all`).
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - The path to the sled database.
+    /// * `encryption_key` - The key used to seal and open values.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `SledShareEntryDao` or an error.
+    pub fn new_encrypted(
+        db_path: &str,
+        encryption_key: EncryptionKey,
+    ) -> Result<Self, RepoError> {
+        Self::from_db(sled::open(db_path)?, Some(encryption_key))
+    }
+
+    /// Creates a new instance of `SledShareEntryDao` encrypted under a key derived from a
+    /// passphrase. The Argon2 salt is generated on first use and kept in the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - The path to the sled database.
+    /// * `passphrase` - The passphrase to derive the encryption key from.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `SledShareEntryDao` or an error.
+    pub fn new_with_passphrase(db_path: &str, passphrase: &[u8]) -> Result<Self, RepoError> {
+        let db = sled::open(db_path)?;
+        let meta = db.open_tree("meta")?;
+        let salt = match meta.get("kdf_salt")? {
+            Some(salt) => salt.to_vec(),
+            None => {
+                let mut salt = vec![0u8; 16];
+                rand::thread_rng().fill_bytes(&mut salt);
+                meta.insert("kdf_salt", salt.as_slice())?;
+                salt
+            }
+        };
+        let encryption_key = EncryptionKey::from_passphrase(passphrase, &salt)?;
+        Self::from_db(db, Some(encryption_key))
+    }
+
+    /// Opens an existing sled database without writing to it.
+    ///
+    /// Every write through the DAO, including the migrations and index repairs a writable open
+    /// would make, is refused with `RepoError::ReadOnly`, while reads, listings, and `export` work
+    /// as usual. Sled has no shared mode, so the database stays locked against other processes
+    /// while the DAO is open.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - The path to the sled database, which must already exist.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the read-only `SledShareEntryDao` or an error.
+    pub fn open_read_only(db_path: &str) -> Result<Self, RepoError> {
+        Self::open_read_only_with_key(db_path, None)
+    }
+
+    /// Opens an existing, encrypted sled database without writing to it (see `open_read_only`).
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - The path to the sled database, which must already exist.
+    /// * `encryption_key` - The key used to open values.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the read-only `SledShareEntryDao` or an error.
+    pub fn open_read_only_encrypted(
+        db_path: &str,
+        encryption_key: EncryptionKey,
+    ) -> Result<Self, RepoError> {
+        Self::open_read_only_with_key(db_path, Some(encryption_key))
+    }
+
+    /// Opens an existing sled database read-only, refusing to create a missing one.
+    fn open_read_only_with_key(
+        db_path: &str,
+        encryption_key: Option<EncryptionKey>,
+    ) -> Result<Self, RepoError> {
+        if !Path::new(db_path).exists() {
+            return Err(RepoError::NoDatabase(db_path.to_string()));
+        }
+        let mut dao = Self::wrap(sled::open(db_path)?, encryption_key)?;
+        dao.indexed = dao.stats_current()? && dao.owner_index_ready()?;
+        dao.read_only = true;
+        Ok(dao)
+    }
+
+    /// Refuses a write if the DAO was opened read-only.
+    fn check_writable(&self) -> Result<(), RepoError> {
+        if self.read_only {
+            return Err(RepoError::ReadOnly);
+        }
+        Ok(())
+    }
 
-    /// Retrieves a `ShareEntry` from the data store by its key.
+    /// Sets when writes are flushed to disk without an explicit `flush`.
+    ///
+    /// With `FlushPolicy::Interval` the DAO does not flush on its own; the owner of the DAO runs
+    /// the background task that calls `flush`.
     ///
     /// # Arguments
     ///
-    /// * `key` - The key of the `ShareEntry` to retrieve.
+    /// * `flush_policy` - The `FlushPolicy` to follow.
     ///
     /// # Returns
     ///
-    /// A `Result` containing an `Option<ShareEntry>`. `None` if the key does not exist.
-    fn get(&self, key: &str) -> Result<Option<ShareEntry>, Box<dyn Error>>;
-
-    fn get_all(&self) -> Result<Vec<(String, ShareEntry)>, Box<dyn Error>>;
+    /// The DAO, following `flush_policy`.
+    pub fn with_flush_policy(mut self, flush_policy: FlushPolicy) -> Self {
+        self.flush_policy = flush_policy;
+        self
+    }
 
-    /// Updates an existing `ShareEntry` in the data store.
+    /// Sets the storage limits enforced on writes.
+    ///
+    /// The limits are checked against the stats counters before a write, so concurrent writers
+    /// sharing the DAO without a lock can each pass the check and overshoot a limit together.
     ///
     /// # Arguments
     ///
-    /// * `key` - The key of the `ShareEntry` to update.
-    /// * `entry` - The new `ShareEntry` data.
+    /// * `quotas` - The `DaoQuotas` to enforce.
     ///
     /// # Returns
     ///
-    /// A `Result` indicating the success or failure of the operation.
-    fn update(&self, key: &str, entry: &ShareEntry) -> Result<(), Box<dyn Error>>;
+    /// The DAO, enforcing `quotas`.
+    pub fn with_quotas(mut self, quotas: DaoQuotas) -> Self {
+        self.quotas = quotas;
+        self
+    }
 
-    /// Deletes a `ShareEntry` from the data store by its key.
+    /// Checks that a set of writes keeps the database within its quotas.
     ///
     /// # Arguments
     ///
-    /// * `key` - The key of the `ShareEntry` to delete.
+    /// * `writes` - The key, entry, and encoded value size of every write.
+    fn check_quotas<'a>(
+        &self,
+        writes: impl IntoIterator<Item = (&'a str, &'a ShareEntry, usize)>,
+    ) -> Result<(), RepoError> {
+        if self.quotas.is_unlimited() {
+            return Ok(());
+        }
+        let mut delta = QuotaDelta::default();
+        for (key, entry, new_len) in writes {
+            let old = self.entries.get(key)?.map(|old_value| {
+                let old_owner = self
+                    .decode_value(key.as_bytes(), &old_value)
+                    .ok()
+                    .map(|(old_entry, _)| old_entry.sender);
+                (old_owner, old_value.len() as u64)
+            });
+            delta.add(old, &entry.sender, new_len as u64);
+        }
+        self.quotas
+            .check(&delta, self.read_counter(STATS_BYTES)?, |owner| {
+                Ok((
+                    self.read_counter(&Self::owner_stats_key(owner))?,
+                    self.read_counter(&Self::owner_bytes_stats_key(owner))?,
+                ))
+            })
+    }
+
+    /// Counts a write, flushing if the flush policy calls for it.
+    fn wrote(&self) -> Result<(), RepoError> {
+        let due = match self.flush_policy {
+            FlushPolicy::EveryWrite => true,
+            FlushPolicy::EveryNWrites(n) => {
+                self.unflushed_writes.fetch_add(1, Ordering::Relaxed) + 1 >= n
+            }
+            FlushPolicy::Interval(_) | FlushPolicy::Never => false,
+        };
+        if due {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Wraps an opened sled database, opening the secondary trees the DAO maintains.
     ///
-    /// # Returns
+    /// Databases written before the current stats layout have their counters computed once here.
+    fn from_db(db: Db, encryption_key: Option<EncryptionKey>) -> Result<Self, RepoError> {
+        Self::from_namespace(db, "", encryption_key)
+    }
+
+    /// Wraps the trees of `namespace` in an opened sled database (see `wrap_namespace`),
+    /// computing the stats counters and owner index once if they are missing.
+    fn from_namespace(
+        db: Db,
+        namespace: &str,
+        encryption_key: Option<EncryptionKey>,
+    ) -> Result<Self, RepoError> {
+        let dao = Self::wrap_namespace(db, namespace, encryption_key)?;
+        if !dao.stats_current()? {
+            dao.rebuild_stats()?;
+        }
+        if !dao.owner_index_ready()? {
+            let indexed = dao.reindex()?;
+            info!("Indexed {} share entries by owner.", indexed);
+        }
+        Ok(dao)
+    }
+
+    /// Wraps an opened sled database in a writable DAO without checking its secondary trees.
+    fn wrap(db: Db, encryption_key: Option<EncryptionKey>) -> Result<Self, RepoError> {
+        Self::wrap_namespace(db, "", encryption_key)
+    }
+
+    /// Wraps the trees of `namespace` in a writable DAO without checking its secondary trees.
     ///
-    /// A `Result` indicating the success or failure of the operation.
-    fn delete(&self, key: &str) -> Result<(), Box<dyn Error>>;
-}
+    /// The empty namespace keeps its entries in the database's default tree and its secondary
+    /// trees under their plain names. Any other namespace keeps its entries in the tree named
+    /// after it and its secondary trees in `<namespace>/<name>`.
+    fn wrap_namespace(
+        db: Db,
+        namespace: &str,
+        encryption_key: Option<EncryptionKey>,
+    ) -> Result<Self, RepoError> {
+        let open = |name: &str| match namespace {
+            "" => db.open_tree(name),
+            _ => db.open_tree(format!("{}/{}", namespace, name)),
+        };
+        let entries = match namespace {
+            "" => (*db).clone(),
+            _ => db.open_tree(namespace)?,
+        };
+        let meta = open("meta")?;
+        let expiry = open("expiry")?;
+        let stats = open("stats")?;
+        let owners = open("owners")?;
+        let tombstones = open("tombstones")?;
+        let quarantine = open("quarantine")?;
+        Ok(SledShareEntryDao {
+            db,
+            entries,
+            meta,
+            expiry,
+            stats,
+            owners,
+            tombstones,
+            quarantine,
+            encryption_key,
+            flush_policy: FlushPolicy::default(),
+            unflushed_writes: AtomicU64::new(0),
+            quotas: DaoQuotas::default(),
+            read_only: false,
+            indexed: true,
+        })
+    }
 
-/// A `ShareEntryDaoTrait` implementation using Sled, an embedded database.
-///
-/// This struct provides methods to interact with the Sled database for operations on `ShareEntry` objects.
-///
-/// # Fields
-///
-/// * `db` - The Sled database instance.
-pub struct SledShareEntryDao {
-    db: Db,
-}
+    /// Checks whether the stats tree uses the current layout.
+    fn stats_current(&self) -> Result<bool, RepoError> {
+        Ok(self.stats.get(STATS_LAYOUT)?.as_deref() == Some(&[STATS_LAYOUT_VERSION]))
+    }
 
-impl SledShareEntryDao {
-    /// Creates a new instance of `SledShareEntryDao`.
+    /// Checks whether the owner index covers every stored entry.
+    fn owner_index_ready(&self) -> Result<bool, RepoError> {
+        Ok(self.meta.contains_key(META_OWNER_INDEX)?)
+    }
+
+    /// Rebuilds the owner index from a full scan of the database.
     ///
-    /// # Arguments
+    /// Runs when a database written before the index existed is opened. It must not run
+    /// concurrently with writes, which could be dropped from the rebuilt index. Values that cannot
+    /// be decoded are left out of the index.
     ///
-    /// * `db_path` - The path to the sled database.
+    /// # Returns
+    ///
+    /// A `Result` containing the number of entries indexed.
+    pub fn reindex(&self) -> Result<usize, RepoError> {
+        self.check_writable()?;
+        let meta = &self.meta;
+        meta.remove(META_OWNER_INDEX)?;
+        self.owners.clear()?;
+        let mut indexed = 0;
+        for item in self.entries.iter() {
+            let (key, value) = item?;
+            if let Ok((entry, _)) = self.decode_value(&key, &value) {
+                self.owners
+                    .insert(Self::owner_index_key(&entry.sender, &key), &[])?;
+                indexed += 1;
+            }
+        }
+        meta.insert(META_OWNER_INDEX, &[])?;
+        Ok(indexed)
+    }
+
+    /// Builds the prefix of an owner's records in the owner index: the big-endian length of the
+    /// owner followed by the owner, so that no owner's prefix is a prefix of another's.
+    fn owner_index_prefix(owner: &[u8]) -> Vec<u8> {
+        let mut prefix = (owner.len() as u16).to_be_bytes().to_vec();
+        prefix.extend_from_slice(owner);
+        prefix
+    }
+
+    /// Builds the key of an entry's record in the owner index, the owner's prefix followed by the
+    /// entry's key, so that a prefix scan yields an owner's keys in key order.
+    fn owner_index_key(owner: &[u8], key: &[u8]) -> Vec<u8> {
+        let mut index_key = Self::owner_index_prefix(owner);
+        index_key.extend_from_slice(key);
+        index_key
+    }
+
+    /// Moves the owner index record of `key` from the owner of `old_value` to `new_owner` within
+    /// a transaction. `None` on either side means absent.
+    fn index_owner(
+        &self,
+        owners: &TransactionalTree,
+        key: &[u8],
+        old_value: Option<&[u8]>,
+        new_owner: Option<&[u8]>,
+    ) -> ConflictableTransactionResult<(), RepoError> {
+        let old_owner = old_value
+            .and_then(|value| self.decode_value(key, value).ok())
+            .map(|(entry, _)| entry.sender);
+        if let Some(old_owner) = old_owner {
+            if Some(old_owner.as_slice()) != new_owner {
+                owners.remove(Self::owner_index_key(&old_owner, key))?;
+            }
+        }
+        if let Some(new_owner) = new_owner {
+            owners.insert(Self::owner_index_key(new_owner, key), &[])?;
+        }
+        Ok(())
+    }
+
+    /// Recomputes the stats counters from a full scan of the database.
+    ///
+    /// Values that cannot be decoded are counted without an owner.
+    fn rebuild_stats(&self) -> Result<(), RepoError> {
+        let mut entries = 0u64;
+        let mut bytes = 0u64;
+        let mut per_owner: BTreeMap<Vec<u8>, (u64, u64)> = BTreeMap::new();
+        for item in self.entries.iter() {
+            let (key, value) = item?;
+            entries += 1;
+            bytes += value.len() as u64;
+            if let Ok((entry, _)) = self.decode_value(&key, &value) {
+                let usage = per_owner.entry(entry.sender).or_default();
+                usage.0 += 1;
+                usage.1 += value.len() as u64;
+            }
+        }
+        self.stats.clear()?;
+        for (owner, (count, owner_bytes)) in per_owner {
+            self.stats
+                .insert(Self::owner_stats_key(&owner), &count.to_be_bytes())?;
+            self.stats.insert(
+                Self::owner_bytes_stats_key(&owner),
+                &owner_bytes.to_be_bytes(),
+            )?;
+        }
+        self.stats.insert(STATS_BYTES, &bytes.to_be_bytes())?;
+        self.stats.insert(STATS_ENTRIES, &entries.to_be_bytes())?;
+        self.stats.insert(STATS_LAYOUT, &[STATS_LAYOUT_VERSION])?;
+        Ok(())
+    }
+
+    /// Builds the key of an owner's entry counter in the stats tree.
+    fn owner_stats_key(owner: &[u8]) -> Vec<u8> {
+        let mut stats_key = STATS_OWNER_PREFIX.to_vec();
+        stats_key.extend_from_slice(owner);
+        stats_key
+    }
+
+    /// Builds the key of an owner's stored value size counter in the stats tree.
+    fn owner_bytes_stats_key(owner: &[u8]) -> Vec<u8> {
+        let mut stats_key = STATS_OWNER_BYTES_PREFIX.to_vec();
+        stats_key.extend_from_slice(owner);
+        stats_key
+    }
+
+    /// Reads a counter of the stats tree, which is zero when absent.
+    fn read_counter(&self, counter: &[u8]) -> Result<u64, RepoError> {
+        Ok(match self.stats.get(counter)? {
+            Some(bytes) => u64::from_be_bytes(bytes.as_ref().try_into()?),
+            None => 0,
+        })
+    }
+
+    /// Atomically adds `delta` to a counter of the stats tree, removing it when `remove_at_zero`
+    /// is set and it drops to zero.
+    fn add_to_counter(&self, counter: &[u8], delta: i64, remove_at_zero: bool) -> sled::Result<()> {
+        if delta == 0 {
+            return Ok(());
+        }
+        self.stats.update_and_fetch(counter, |old| {
+            let old = old
+                .and_then(|bytes| bytes.try_into().ok())
+                .map_or(0, u64::from_be_bytes);
+            let new = old.saturating_add_signed(delta);
+            if new == 0 && remove_at_zero {
+                None
+            } else {
+                Some(new.to_be_bytes().to_vec())
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Updates the stats counters after the value under `key` went from `old_value` to a value
+    /// of `new_len` bytes holding `new_entry`. `None` on either side means absent.
+    fn record_stats(
+        &self,
+        key: &[u8],
+        old_value: Option<&[u8]>,
+        new: Option<(&ShareEntry, usize)>,
+    ) -> Result<(), RepoError> {
+        let old_owner = old_value.and_then(|value| {
+            self.decode_value(key, value)
+                .ok()
+                .map(|(entry, _)| entry.sender)
+        });
+        let new_owner = new.map(|(entry, _)| entry.sender.as_slice());
+        let old_len = old_value.map_or(0, |v| v.len() as i64);
+        let new_len = new.map_or(0, |(_, len)| len as i64);
+        let entries = new.is_some() as i64 - old_value.is_some() as i64;
+        self.add_to_counter(STATS_ENTRIES, entries, false)?;
+        self.add_to_counter(STATS_BYTES, new_len - old_len, false)?;
+        if old_owner.as_deref() != new_owner {
+            if let Some(owner) = old_owner {
+                self.add_to_counter(&Self::owner_stats_key(&owner), -1, true)?;
+                self.add_to_counter(&Self::owner_bytes_stats_key(&owner), -old_len, true)?;
+            }
+            if let Some(owner) = new_owner {
+                self.add_to_counter(&Self::owner_stats_key(owner), 1, true)?;
+                self.add_to_counter(&Self::owner_bytes_stats_key(owner), new_len, true)?;
+            }
+        } else if let Some(owner) = new_owner {
+            self.add_to_counter(&Self::owner_bytes_stats_key(owner), new_len - old_len, true)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes an entry for storage under `key`, sealing it if encryption is enabled.
+    fn encode_value(&self, key: &[u8], entry: &ShareEntry) -> Result<Vec<u8>, RepoError> {
+        let plaintext = encode_entry(entry)?;
+        match &self.encryption_key {
+            Some(encryption_key) => encryption_key.seal(key, &plaintext),
+            None => Ok(plaintext),
+        }
+    }
+
+    /// Decodes a value stored under `key`, opening it first if it is sealed.
     ///
     /// # Returns
     ///
-    /// A `Result` containing `SledShareEntryDao` or an error.
+    /// A `Result` containing the entry and whether the value should be rewritten, either because
+    /// it is outdated or because it is stored in plaintext while encryption is enabled.
     ///
-    /// # Examples
+    /// # Errors
     ///
-    /// Creating a new instance:
+    /// A value failing its checksum is reported as `RepoError::CorruptEntry` naming `key`.
+    fn decode_value(&self, key: &[u8], value: &[u8]) -> Result<(ShareEntry, bool), RepoError> {
+        let decoded = if value.first() == Some(&FORMAT_ENCRYPTED) {
+            let encryption_key = self
+                .encryption_key
+                .as_ref()
+                .ok_or(RepoError::MissingEncryptionKey)?;
+            decode_entry(&encryption_key.open(key, value)?)
+        } else {
+            decode_entry(value)
+                .map(|(entry, outdated)| (entry, outdated || self.encryption_key.is_some()))
+        };
+        decoded.map_err(|e| match e {
+            RepoError::ChecksumMismatch => RepoError::CorruptEntry {
+                key: String::from_utf8_lossy(key).into_owned(),
+            },
+            e => e,
+        })
+    }
+
+    /// Builds the key of an entry in the expiry index: the big-endian expiry time followed by the
+    /// entry's key, so that a range scan yields entries in expiry order.
+    fn expiry_index_key(expires_at: u64, key: &[u8]) -> Vec<u8> {
+        let mut index_key = expires_at.to_be_bytes().to_vec();
+        index_key.extend_from_slice(key);
+        index_key
+    }
+
+    /// Removes the expiry index record of a value that has been replaced or removed.
+    fn unindex_expiry(
+        &self,
+        key: &[u8],
+        old_value: Option<sled::IVec>,
+    ) -> Result<(), RepoError> {
+        if let Some(old_value) = old_value {
+            if let Ok((old_entry, _)) = self.decode_value(key, &old_value) {
+                if let Some(expires_at) = old_entry.expires_at {
+                    self.expiry
+                        .remove(Self::expiry_index_key(expires_at, key))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the expiry index record of a value that has just been written.
+    fn index_expiry(&self, key: &[u8], entry: &ShareEntry) -> Result<(), RepoError> {
+        if let Some(expires_at) = entry.expires_at {
+            self.expiry
+                .insert(Self::expiry_index_key(expires_at, key), &[])?;
+        }
+        Ok(())
+    }
+
+    /// Writes a batch of encoded values in one sled transaction, then updates the expiry index
+    /// and stats counters for each. With `refresh` set, every key must already hold an entry of
+    /// the same owner, checked inside the transaction.
     ///
-    /// ```ignore
-    /// use shard::repository::SledShareEntryDao;
+    /// A transaction is used rather than a plain `sled::Batch` so the replaced values, which the
+    /// secondary trees need, are read atomically with the write.
+    fn write_batch(
+        &self,
+        entries: &[(String, ShareEntry)],
+        refresh: bool,
+    ) -> Result<(), RepoError> {
+        self.check_writable()?;
+        validate_batch(entries)?;
+        let encoded = entries
+            .iter()
+            .map(|(key, entry)| self.encode_value(key.as_bytes(), entry))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.check_quotas(
+            entries
+                .iter()
+                .zip(&encoded)
+                .map(|((key, entry), value)| (key.as_str(), entry, value.len())),
+        )?;
+        let old_values = (&self.entries, &self.owners)
+            .transaction(|(tx, owners)| {
+                let mut old_values = Vec::with_capacity(entries.len());
+                for ((key, entry), value) in entries.iter().zip(&encoded) {
+                    let old_value = tx.insert(key.as_bytes(), value.as_slice())?;
+                    self.index_owner(
+                        owners,
+                        key.as_bytes(),
+                        old_value.as_deref(),
+                        Some(&entry.sender),
+                    )?;
+                    if refresh {
+                        let current = match &old_value {
+                            Some(old_value) => Some(
+                                self.decode_value(key.as_bytes(), old_value)
+                                    .map_err(ConflictableTransactionError::Abort)?
+                                    .0,
+                            ),
+                            None => None,
+                        };
+                        check_refresh(key, current.as_ref(), entry)
+                            .map_err(ConflictableTransactionError::Abort)?;
+                    }
+                    old_values.push(old_value);
+                }
+                Ok(old_values)
+            })
+            .map_err(transaction_error)?;
+        for (((key, entry), value), old_value) in entries.iter().zip(&encoded).zip(old_values) {
+            self.record_stats(
+                key.as_bytes(),
+                old_value.as_deref(),
+                Some((entry, value.len())),
+            )?;
+            self.unindex_expiry(key.as_bytes(), old_value)?;
+            self.index_expiry(key.as_bytes(), entry)?;
+        }
+        self.wrote()
+    }
+
+    /// Decodes a stored value, rewriting it in the current format if it used a legacy encoding,
+    /// an older schema version, or is stored in plaintext while encryption is enabled.
     ///
-    /// let dao = SledShareEntryDao::new("path/to/db").unwrap();
-    /// ```
-    pub fn new(db_path: &str) -> Result<Self, Box<dyn Error>> {
-        let db = sled::open(db_path)?;
-        Ok(SledShareEntryDao { db })
+    /// The rewrite is a compare-and-swap against the value that was read, so a concurrent write
+    /// to the same key is never clobbered by the migration.
+    fn read_entry(&self, key: &[u8], value: &[u8]) -> Result<ShareEntry, RepoError> {
+        let (entry, outdated) = self.decode_value(key, value)?;
+        if outdated && !self.read_only {
+            debug!("Migrating entry to schema version {}", SHARE_ENTRY_VERSION);
+            let encoded = self.encode_value(key, &entry)?;
+            let new_len = encoded.len();
+            if self
+                .entries
+                .compare_and_swap(key, Some(value), Some(encoded))?
+                .is_ok()
+            {
+                self.record_stats(key, Some(value), Some((&entry, new_len)))?;
+            }
+        }
+        Ok(entry)
     }
 }
 
 impl ShareEntryDaoTrait for SledShareEntryDao {
     /// Inserts a new `ShareEntry` into the Sled database.
     ///
-    /// This method serializes the `ShareEntry` into a JSON string and stores it in the database under the provided key.
+    /// This method encodes the `ShareEntry` into its tagged binary form and stores it in the database under the provided key.
     ///
     /// # Arguments
     ///
@@ -146,18 +2462,75 @@ impl ShareEntryDaoTrait for SledShareEntryDao {
     /// use shard::repository::ShareEntryDaoTrait;
     ///
     /// let dao = SledShareEntryDao::new("path/to/db").unwrap();
-    /// let entry = ShareEntry { share: (1, vec![1, 2, 3]), sender: vec![4, 5, 6] };
+    /// let entry = ShareEntry { share: (1, vec![1, 2, 3]), sender: vec![4, 5, 6], threshold: 2, ..Default::default() };
     /// dao.insert("some_key", &entry);
     /// ```
-    fn insert(&self, key: &str, entry: &ShareEntry) -> Result<(), Box<dyn Error>> {
-        let serialized = serde_json::to_string(entry)?;
-        self.db.insert(key, serialized.as_bytes())?;
-        Ok(())
+    fn insert(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+        self.check_writable()?;
+        let encoded = self.encode_value(key.as_bytes(), entry)?;
+        let new_len = encoded.len();
+        self.check_quotas([(key, entry, new_len)])?;
+        let old_value = (&self.entries, &self.owners)
+            .transaction(|(tx, owners)| {
+                let old_value = tx.insert(key.as_bytes(), encoded.as_slice())?;
+                self.index_owner(
+                    owners,
+                    key.as_bytes(),
+                    old_value.as_deref(),
+                    Some(&entry.sender),
+                )?;
+                Ok(old_value)
+            })
+            .map_err(transaction_error)?;
+        self.record_stats(key.as_bytes(), old_value.as_deref(), Some((entry, new_len)))?;
+        self.unindex_expiry(key.as_bytes(), old_value)?;
+        self.index_expiry(key.as_bytes(), entry)?;
+        self.wrote()
+    }
+
+    /// Replaces the entry under `key` in a transaction that checks the stored value is still the
+    /// one read, so a write that lands between reading the stored value and swapping it makes the
+    /// swap fail.
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: &ShareEntry,
+        new: &ShareEntry,
+    ) -> Result<bool, RepoError> {
+        self.check_writable()?;
+        let Some(current) = self.entries.get(key)? else {
+            return Ok(false);
+        };
+        let (entry, _) = self.decode_value(key.as_bytes(), &current)?;
+        if entry != *expected {
+            return Ok(false);
+        }
+        let encoded = self.encode_value(key.as_bytes(), new)?;
+        let new_len = encoded.len();
+        let swapped = (&self.entries, &self.owners)
+            .transaction(|(tx, owners)| {
+                if tx.get(key.as_bytes())?.as_ref() != Some(&current) {
+                    return Ok(false);
+                }
+                tx.insert(key.as_bytes(), encoded.as_slice())?;
+                self.index_owner(owners, key.as_bytes(), Some(&current), Some(&new.sender))?;
+                Ok(true)
+            })
+            .map_err(transaction_error)?;
+        if !swapped {
+            return Ok(false);
+        }
+        self.record_stats(key.as_bytes(), Some(&current), Some((new, new_len)))?;
+        self.unindex_expiry(key.as_bytes(), Some(current))?;
+        self.index_expiry(key.as_bytes(), new)?;
+        self.wrote()?;
+        Ok(true)
     }
 
     /// Retrieves a `ShareEntry` from the Sled database by its key.
     ///
-    /// If the key exists, the method deserializes the stored JSON string back into a `ShareEntry`.
+    /// If the key exists, the method decodes the stored value back into a `ShareEntry`. Values still in
+    /// the legacy JSON encoding are rewritten in the binary encoding as they are read.
     ///
     /// # Arguments
     ///
@@ -176,25 +2549,64 @@ impl ShareEntryDaoTrait for SledShareEntryDao {
     /// let dao = SledShareEntryDao::new("path/to/db").unwrap();
     /// let entry = dao.get("some_key").unwrap();
     /// ```
-    fn get(&self, key: &str) -> Result<Option<ShareEntry>, Box<dyn Error>> {
-        if let Some(found) = self.db.get(key)? {
-            let entry: ShareEntry = serde_json::from_slice(&found)?;
-            Ok(Some(entry))
+    fn get(&self, key: &str) -> Result<Option<ShareEntry>, RepoError> {
+        if let Some(found) = self.entries.get(key)? {
+            Ok(Some(self.read_entry(key.as_bytes(), &found)?))
         } else {
             Ok(None)
         }
     }
 
-    fn get_all(&self) -> Result<Vec<(String, ShareEntry)>, Box<dyn Error>> {
+    /// Retrieves a page of entries using a range scan over the sled key order.
+    fn get_page(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, ShareEntry)>, RepoError> {
+        let start = match after {
+            Some(after) => Bound::Excluded(after.as_bytes()),
+            None => Bound::Unbounded,
+        };
         let mut entries = Vec::new();
-        for entry in self.db.iter() {
-            let (key, value) = entry?;
-            let entry: ShareEntry = serde_json::from_slice(&value)?;
+        for item in self
+            .entries
+            .range::<&[u8], _>((start, Bound::Unbounded))
+            .take(limit)
+        {
+            let (key, value) = item?;
+            let entry = self.read_entry(&key, &value)?;
             entries.push((String::from_utf8(key.to_vec())?, entry));
         }
         Ok(entries)
     }
 
+    /// Lists the keys starting with `prefix` from sled's key order without reading any value.
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, RepoError> {
+        let mut keys = Vec::new();
+        for key in self.entries.scan_prefix(prefix).keys() {
+            keys.push(String::from_utf8(key?.to_vec())?);
+        }
+        Ok(keys)
+    }
+
+    /// Lists a page of keys with a range scan over the sled key order, without reading any value.
+    fn keys_page(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>, RepoError> {
+        let start = match after {
+            Some(after) => Bound::Excluded(after.as_bytes()),
+            None => Bound::Unbounded,
+        };
+        let mut keys = Vec::new();
+        for key in self
+            .entries
+            .range::<&[u8], _>((start, Bound::Unbounded))
+            .keys()
+            .take(limit)
+        {
+            keys.push(String::from_utf8(key?.to_vec())?);
+        }
+        Ok(keys)
+    }
+
     /// Updates an existing `ShareEntry` in the Sled database.
     ///
     /// This method essentially re-inserts the entry, replacing the old one.
@@ -208,6 +2620,10 @@ impl ShareEntryDaoTrait for SledShareEntryDao {
     ///
     /// A `Result` indicating success or failure.
     ///
+    /// # Errors
+    ///
+    /// Returns an error if the key does not exist in the database.
+    ///
     /// # Examples
     ///
     /// ```ignore
@@ -216,40 +2632,457 @@ impl ShareEntryDaoTrait for SledShareEntryDao {
     /// use shard::repository::ShareEntryDaoTrait;
     ///
     /// let dao = SledShareEntryDao::new("path/to/db").unwrap();
-    /// let new_entry = ShareEntry { share: (1, vec![7, 8, 9]), sender: vec![10, 11, 12] };
+    /// let new_entry = ShareEntry { share: (1, vec![7, 8, 9]), sender: vec![10, 11, 12], threshold: 2, ..Default::default() };
     /// dao.update("some_key", &new_entry).unwrap();
     /// ```
-    fn update(&self, key: &str, entry: &ShareEntry) -> Result<(), Box<dyn Error>> {
+    fn update(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+        if !self.entries.contains_key(key)? {
+            return Err(RepoError::KeyNotFound(key.to_string()));
+        }
         self.insert(key, entry)
     }
 
-    /// Deletes a `ShareEntry` from the Sled database by its key.
-    ///
-    /// # Arguments
+    /// Deletes a `ShareEntry` from the Sled database by its key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the entry to delete.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use shard::repository::ShareEntryDaoTrait;
+    /// use shard::repository::SledShareEntryDao;
+    ///
+    /// let dao = SledShareEntryDao::new("path/to/db").unwrap();
+    /// dao.delete("some_key");
+    /// ```
+    fn delete(&self, key: &str) -> Result<(), RepoError> {
+        self.check_writable()?;
+        let old_value = (&self.entries, &self.owners)
+            .transaction(|(tx, owners)| {
+                let old_value = tx.remove(key.as_bytes())?;
+                self.index_owner(owners, key.as_bytes(), old_value.as_deref(), None)?;
+                Ok(old_value)
+            })
+            .map_err(transaction_error)?;
+        self.record_stats(key.as_bytes(), old_value.as_deref(), None)?;
+        self.unindex_expiry(key.as_bytes(), old_value)?;
+        self.wrote()
+    }
+
+    /// Lists the keys of all expired entries using the expiry index.
+    ///
+    /// Index records that no longer match the stored entry are removed as they are encountered.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current unix timestamp in seconds.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the keys of the expired entries.
+    fn expired_keys(&self, now: u64) -> Result<Vec<String>, RepoError> {
+        let mut keys = Vec::new();
+        let upper = now.saturating_add(1).to_be_bytes();
+        for item in self.expiry.range(..upper.as_slice()) {
+            let (index_key, _) = item?;
+            let key = String::from_utf8(index_key[8..].to_vec())?;
+            match self.get(&key)? {
+                Some(entry) if entry.is_expired(now) => keys.push(key),
+                _ if self.read_only => {}
+                _ => {
+                    self.expiry.remove(&index_key)?;
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Lists the owner's keys from the owner index instead of reading every value.
+    fn keys_by_owner(&self, owner: &[u8]) -> Result<Vec<String>, RepoError> {
+        if !self.indexed {
+            return Ok(self
+                .get_all()?
+                .into_iter()
+                .filter(|(_, entry)| entry.sender == owner)
+                .map(|(key, _)| key)
+                .collect());
+        }
+        let prefix = Self::owner_index_prefix(owner);
+        let mut keys = Vec::new();
+        for index_key in self.owners.scan_prefix(&prefix).keys() {
+            keys.push(String::from_utf8(index_key?[prefix.len()..].to_vec())?);
+        }
+        Ok(keys)
+    }
+
+    /// Deletes the owner's entries found through the owner index.
+    fn delete_by_owner(&self, owner: &[u8]) -> Result<Vec<String>, RepoError> {
+        self.check_writable()?;
+        let keys = self.keys_by_owner(owner)?;
+        for key in &keys {
+            self.delete(key)?;
+        }
+        Ok(keys)
+    }
+
+    /// Inserts the whole batch in a single sled transaction.
+    fn insert_batch(&self, entries: &[(String, ShareEntry)]) -> Result<(), RepoError> {
+        self.write_batch(entries, false)
+    }
+
+    /// Applies the whole batch in a single sled transaction, aborting it if any key is missing
+    /// or changes owner.
+    fn apply_refresh_batch(&self, updates: &[(String, ShareEntry)]) -> Result<(), RepoError> {
+        self.write_batch(updates, true)
+    }
+
+    /// Reads the counters maintained by every write instead of scanning the database.
+    fn stats(&self) -> Result<DaoStats, RepoError> {
+        if !self.indexed {
+            let entries = self.get_all()?;
+            return DaoStats::from_entries(entries.iter().map(|(_, entry)| entry));
+        }
+        let mut per_owner = Vec::new();
+        for item in self.stats.scan_prefix(STATS_OWNER_PREFIX) {
+            let (stats_key, count) = item?;
+            per_owner.push((
+                stats_key[STATS_OWNER_PREFIX.len()..].to_vec(),
+                u64::from_be_bytes(count.as_ref().try_into()?),
+            ));
+        }
+        Ok(DaoStats {
+            entries: self.read_counter(STATS_ENTRIES)?,
+            total_value_bytes: self.read_counter(STATS_BYTES)?,
+            per_owner,
+        })
+    }
+
+    /// Writes every entry to `writer`, sealing the stream with the at-rest key if one is set.
+    fn export(&self, writer: &mut dyn Write) -> Result<usize, RepoError> {
+        export_entries(self, writer, self.encryption_key.as_ref())
+    }
+
+    /// Reads a backup stream, opening it with the at-rest key if it is encrypted.
+    fn import(
+        &self,
+        reader: &mut dyn Read,
+        conflict: ConflictPolicy,
+    ) -> Result<ImportReport, RepoError> {
+        self.check_writable()?;
+        import_entries(self, reader, conflict, self.encryption_key.as_ref())
+    }
+
+    fn put_tombstone(&self, key: &str, tombstone: &Tombstone) -> Result<(), RepoError> {
+        self.check_writable()?;
+        self.tombstones
+            .insert(key, bincode::serialize(tombstone)?)?;
+        self.wrote()
+    }
+
+    fn get_tombstone(&self, key: &str) -> Result<Option<Tombstone>, RepoError> {
+        match self.tombstones.get(key)? {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn remove_tombstone(&self, key: &str) -> Result<(), RepoError> {
+        self.check_writable()?;
+        if self.tombstones.remove(key)?.is_some() {
+            self.wrote()?;
+        }
+        Ok(())
+    }
+
+    /// Removes expired tombstones. A tombstone that cannot be decoded is removed as well.
+    fn purge_tombstones(&self, now: u64) -> Result<usize, RepoError> {
+        self.check_writable()?;
+        let mut purged = 0;
+        for item in self.tombstones.iter() {
+            let (key, value) = item?;
+            let expired = !matches!(
+                bincode::deserialize::<Tombstone>(&value),
+                Ok(tombstone) if tombstone.is_live(now)
+            );
+            if expired && self.tombstones.remove(&key)?.is_some() {
+                purged += 1;
+            }
+        }
+        if purged > 0 {
+            self.wrote()?;
+        }
+        Ok(purged)
+    }
+
+    /// Copies the raw value under `key` into the quarantine tree, then deletes the entry.
+    fn quarantine(&self, key: &str) -> Result<bool, RepoError> {
+        self.check_writable()?;
+        let Some(value) = self.entries.get(key.as_bytes())? else {
+            return Ok(false);
+        };
+        self.quarantine.insert(key.as_bytes(), value)?;
+        self.delete(key)?;
+        Ok(true)
+    }
+
+    fn is_quarantined(&self, key: &str) -> Result<bool, RepoError> {
+        Ok(self.quarantine.contains_key(key.as_bytes())?)
+    }
+
+    fn quarantined_keys(&self) -> Result<Vec<String>, RepoError> {
+        let mut keys = Vec::new();
+        for key in self.quarantine.iter().keys() {
+            keys.push(String::from_utf8(key?.to_vec())?);
+        }
+        Ok(keys)
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Flushes the database and its secondary trees to disk. A read-only DAO has nothing to flush.
+    fn flush(&self) -> Result<(), RepoError> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.unflushed_writes.store(0, Ordering::Relaxed);
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Rewrites every entry stored in a legacy encoding or an older schema version, and seals
+    /// plaintext entries when encryption is enabled. Values that cannot be decoded are skipped.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of entries that were upgraded.
+    fn migrate_all(&self) -> Result<usize, RepoError> {
+        self.check_writable()?;
+        let mut migrated = 0;
+        for item in self.entries.iter() {
+            let (key, value) = item?;
+            // a value that cannot be decoded is left for the integrity scan to quarantine
+            let Ok((entry, outdated)) = self.decode_value(&key, &value) else {
+                continue;
+            };
+            if outdated {
+                let encoded = self.encode_value(&key, &entry)?;
+                let new_len = encoded.len();
+                let swapped = self
+                    .entries
+                    .compare_and_swap(&key, Some(&value), Some(encoded))?;
+                if swapped.is_ok() {
+                    self.record_stats(&key, Some(&value), Some((&entry, new_len)))?;
+                    migrated += 1;
+                }
+            }
+        }
+        Ok(migrated)
+    }
+}
+
+/// A `ShareEntryDaoTrait` implementation holding entries in memory.
+///
+/// Entries are lost on restart unless the DAO is created with `new_with_snapshot`, in which case
+/// `flush` saves them to a snapshot file that is loaded again at startup.
+///
+/// # Fields
+///
+/// * `map` - The stored entries. Writes made directly to the map are not marked for snapshotting.
+/// * `snapshot_path` - The file entries are snapshotted to, if snapshots are enabled.
+/// * `dirty` - Whether the entries changed since the last snapshot.
+/// * `quotas` - The storage limits enforced on writes, measuring entries by their encoding.
+/// * `tombstones` - The tombstones of deleted entries, by key. They are not snapshotted.
+/// * `quarantine` - The quarantined entries, by key. They are not snapshotted.
+pub struct HashMapShareEntryDao {
+    pub map: Mutex<HashMap<String, ShareEntry>>,
+    snapshot_path: Option<PathBuf>,
+    dirty: AtomicBool,
+    quotas: DaoQuotas,
+    tombstones: Mutex<HashMap<String, Tombstone>>,
+    quarantine: Mutex<BTreeMap<String, ShareEntry>>,
+}
+
+impl Default for HashMapShareEntryDao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HashMapShareEntryDao {
+    /// Creates an empty in-memory DAO without snapshots.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use shard::repository::{HashMapShareEntryDao, ShareEntryDaoTrait};
+    ///
+    /// let dao = HashMapShareEntryDao::new();
+    /// assert!(dao.get("some_key").unwrap().is_none());
+    /// ```
+    pub fn new() -> Self {
+        HashMapShareEntryDao {
+            map: Mutex::new(HashMap::new()),
+            snapshot_path: None,
+            dirty: AtomicBool::new(false),
+            quotas: DaoQuotas::default(),
+            tombstones: Mutex::new(HashMap::new()),
+            quarantine: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Creates an in-memory DAO that snapshots its entries to `path`.
+    ///
+    /// The snapshot at `path` is loaded if there is one. A missing or unreadable snapshot does not
+    /// prevent startup: it is logged, and the DAO starts empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The snapshot file, in the format written by `export`.
+    ///
+    /// # Returns
+    ///
+    /// The DAO, holding the entries of the snapshot.
+    pub fn new_with_snapshot(path: &Path) -> Self {
+        let loaded = HashMapShareEntryDao::new();
+        let entries = match fs::File::open(path) {
+            Ok(file) => {
+                match import_entries(
+                    &loaded,
+                    &mut std::io::BufReader::new(file),
+                    ConflictPolicy::Overwrite,
+                    None,
+                ) {
+                    Ok(report) => {
+                        info!(
+                            "Loaded {} shares from snapshot {}.",
+                            report.imported,
+                            path.display()
+                        );
+                        loaded.map.into_inner().unwrap()
+                    }
+                    Err(e) => {
+                        error!(
+                            "Ignoring unreadable snapshot {}, starting empty: {}",
+                            path.display(),
+                            e
+                        );
+                        HashMap::new()
+                    }
+                }
+            }
+            Err(e) => {
+                info!("No snapshot loaded from {}: {}", path.display(), e);
+                HashMap::new()
+            }
+        };
+        HashMapShareEntryDao {
+            map: Mutex::new(entries),
+            snapshot_path: Some(path.to_path_buf()),
+            dirty: AtomicBool::new(false),
+            quotas: DaoQuotas::default(),
+            tombstones: Mutex::new(HashMap::new()),
+            quarantine: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Sets the storage limits enforced on writes.
+    ///
+    /// # Arguments
+    ///
+    /// * `quotas` - The `DaoQuotas` to enforce.
+    ///
+    /// # Returns
+    ///
+    /// The DAO, enforcing `quotas`.
+    pub fn with_quotas(mut self, quotas: DaoQuotas) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    /// Checks that a set of writes keeps `map` within the quotas, sizing every entry by its
+    /// tagged binary encoding as `stats` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `map` - The locked entries the writes apply to.
+    /// * `writes` - The key and entry of every write.
+    fn check_quotas<'a>(
+        &self,
+        map: &HashMap<String, ShareEntry>,
+        writes: impl IntoIterator<Item = (&'a str, &'a ShareEntry)>,
+    ) -> Result<(), RepoError> {
+        if self.quotas.is_unlimited() {
+            return Ok(());
+        }
+        let mut delta = QuotaDelta::default();
+        for (key, entry) in writes {
+            let old = match map.get(key) {
+                Some(old_entry) => Some((
+                    Some(old_entry.sender.clone()),
+                    encode_entry(old_entry)?.len() as u64,
+                )),
+                None => None,
+            };
+            delta.add(old, &entry.sender, encode_entry(entry)?.len() as u64);
+        }
+        let total_bytes = DaoStats::from_entries(map.values())?.total_value_bytes;
+        self.quotas.check(&delta, total_bytes, |owner| {
+            let owned = map.values().filter(|entry| entry.sender == owner);
+            let stats = DaoStats::from_entries(owned)?;
+            Ok((stats.entries, stats.total_value_bytes))
+        })
+    }
+
+    /// Writes every entry to the snapshot file.
     ///
-    /// * `key` - The key of the entry to delete.
+    /// The snapshot is written next to the file and renamed over it, so a crash while saving
+    /// leaves the previous snapshot intact.
     ///
     /// # Returns
     ///
-    /// A `Result` indicating success or failure.
-    ///
-    /// # Examples
-    ///
-    /// ```ignore
-    /// use shard::repository::ShareEntryDaoTrait;
-    /// use shard::repository::SledShareEntryDao;
-    ///
-    /// let dao = SledShareEntryDao::new("path/to/db").unwrap();
-    /// dao.delete("some_key");
-    /// ```
-    fn delete(&self, key: &str) -> Result<(), Box<dyn Error>> {
-        self.db.remove(key)?;
-        Ok(())
+    /// A `Result` containing the number of entries saved, or an error if snapshots are not
+    /// enabled or the file cannot be written.
+    pub fn save_snapshot(&self) -> Result<usize, RepoError> {
+        let path = self
+            .snapshot_path
+            .as_ref()
+            .ok_or_else(|| {
+                RepoError::Unsupported("snapshots are not enabled for this DAO".to_string())
+            })?;
+        let mut partial = path.clone().into_os_string();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        // cleared before the entries are read, so that a write made while saving is not lost,
+        // and set again unless the snapshot is renamed into place
+        self.dirty.store(false, Ordering::Relaxed);
+        let saved = self.write_snapshot(&partial, path);
+        if saved.is_err() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        saved
     }
-}
 
-pub struct HashMapShareEntryDao {
-    pub map: Mutex<HashMap<String, ShareEntry>>,
+    /// Writes every entry to `partial`, readable by its owner only, and renames it to `path`.
+    fn write_snapshot(&self, partial: &Path, path: &Path) -> Result<usize, RepoError> {
+        // left behind by a save that did not get to rename it
+        match fs::remove_file(partial) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let mut writer = std::io::BufWriter::new(create_private(partial)?);
+        let saved = export_entries(self, &mut writer, None)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(partial, path)?;
+        Ok(saved)
+    }
 }
 
 impl ShareEntryDaoTrait for HashMapShareEntryDao {
@@ -270,21 +3103,40 @@ impl ShareEntryDaoTrait for HashMapShareEntryDao {
     ///
     /// ```rust
     /// use shard::repository::ShareEntry;
-    /// use std::collections::HashMap;
-    /// use std::sync::Mutex;
     /// use shard::repository::HashMapShareEntryDao;
     /// use shard::repository::ShareEntryDaoTrait;
     ///
-    /// let dao = HashMapShareEntryDao { map: Mutex::new(HashMap::new()) };
-    /// let entry = ShareEntry { share: (1, vec![1, 2, 3]), sender: vec![4, 5, 6], threshold: 2 };
+    /// let dao = HashMapShareEntryDao::new();
+    /// let entry = ShareEntry { share: (1, vec![1, 2, 3]), sender: vec![4, 5, 6], threshold: 2, ..Default::default() };
     /// dao.insert("some_key", &entry).unwrap();
     /// ```
-    fn insert(&self, key: &str, entry: &ShareEntry) -> Result<(), Box<dyn Error>> {
+    fn insert(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
         let mut map = self.map.lock().unwrap();
+        self.check_quotas(&map, [(key, entry)])?;
         map.insert(key.to_string(), entry.clone());
+        self.dirty.store(true, Ordering::Relaxed);
         Ok(())
     }
 
+    /// Replaces the entry under `key` if it still equals `expected`, under a single lock of the
+    /// map.
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: &ShareEntry,
+        new: &ShareEntry,
+    ) -> Result<bool, RepoError> {
+        let mut map = self.map.lock().unwrap();
+        match map.get_mut(key) {
+            Some(current) if current == expected => {
+                *current = new.clone();
+                self.dirty.store(true, Ordering::Relaxed);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
     /// Retrieves a `ShareEntry` from the HashMap by its key.
     ///
     /// # Arguments
@@ -299,24 +3151,42 @@ impl ShareEntryDaoTrait for HashMapShareEntryDao {
     ///
     /// ```rust
     /// use shard::repository::{ShareEntry, ShareEntryDaoTrait, HashMapShareEntryDao};
-    /// use std::collections::HashMap;
-    /// use std::sync::Mutex;
     ///
-    /// let dao = HashMapShareEntryDao { map: Mutex::new(HashMap::new()) };
+    /// let dao = HashMapShareEntryDao::new();
     /// let entry = dao.get("some_key").unwrap();
     /// ```
-    fn get(&self, key: &str) -> Result<Option<ShareEntry>, Box<dyn Error>> {
+    fn get(&self, key: &str) -> Result<Option<ShareEntry>, RepoError> {
         let map = self.map.lock().unwrap();
         Ok(map.get(key).cloned())
     }
 
-    fn get_all(&self) -> Result<Vec<(String, ShareEntry)>, Box<dyn Error>> {
+    fn get_page(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, ShareEntry)>, RepoError> {
         let map = self.map.lock().unwrap();
-        let mut entries = Vec::new();
-        for (key, value) in map.iter() {
-            entries.push((key.clone(), value.clone()));
-        }
-        Ok(entries)
+        let mut keys: Vec<&String> = map
+            .keys()
+            .filter(|key| after.is_none_or(|after| key.as_str() > after))
+            .collect();
+        keys.sort_unstable();
+        keys.truncate(limit);
+        Ok(keys
+            .into_iter()
+            .map(|key| (key.clone(), map[key].clone()))
+            .collect())
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, RepoError> {
+        let map = self.map.lock().unwrap();
+        let mut keys: Vec<String> = map
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect();
+        keys.sort_unstable();
+        Ok(keys)
     }
 
     /// Updates an existing `ShareEntry` in the HashMap.
@@ -338,20 +3208,20 @@ impl ShareEntryDaoTrait for HashMapShareEntryDao {
     ///
     /// ```rust
     /// use shard::repository::{ShareEntry, ShareEntryDaoTrait, HashMapShareEntryDao};
-    /// use std::collections::HashMap;
-    /// use std::sync::Mutex;
     ///
-    /// let dao = HashMapShareEntryDao { map: Mutex::new(HashMap::new()) };
-    /// let new_entry = ShareEntry { share: (1, vec![7, 8, 9]), sender: vec![10, 11, 12], threshold: 2 };
+    /// let dao = HashMapShareEntryDao::new();
+    /// let new_entry = ShareEntry { share: (1, vec![7, 8, 9]), sender: vec![10, 11, 12], threshold: 2, ..Default::default() };
     /// dao.update("some_key", &new_entry);
     /// ```
-    fn update(&self, key: &str, entry: &ShareEntry) -> Result<(), Box<dyn Error>> {
+    fn update(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
         let mut map = self.map.lock().unwrap();
         if map.contains_key(key) {
+            self.check_quotas(&map, [(key, entry)])?;
             map.insert(key.to_string(), entry.clone());
+            self.dirty.store(true, Ordering::Relaxed);
             Ok(())
         } else {
-            Err("Key not found".into())
+            Err(RepoError::KeyNotFound(key.to_string()))
         }
     }
 
@@ -369,15 +3239,1068 @@ impl ShareEntryDaoTrait for HashMapShareEntryDao {
     ///
     /// ```rust
     /// use shard::repository::{ShareEntry, ShareEntryDaoTrait, HashMapShareEntryDao};
-    /// use std::collections::HashMap;
-    /// use std::sync::Mutex;
     ///
-    /// let dao = HashMapShareEntryDao { map: Mutex::new(HashMap::new()) };
+    /// let dao = HashMapShareEntryDao::new();
     /// dao.delete("some_key").unwrap();
     /// ```
-    fn delete(&self, key: &str) -> Result<(), Box<dyn Error>> {
+    fn delete(&self, key: &str) -> Result<(), RepoError> {
+        let mut map = self.map.lock().unwrap();
+        if map.remove(key).is_some() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Inserts the whole batch under a single lock of the map.
+    fn insert_batch(&self, entries: &[(String, ShareEntry)]) -> Result<(), RepoError> {
+        validate_batch(entries)?;
+        let mut map = self.map.lock().unwrap();
+        self.check_quotas(
+            &map,
+            entries.iter().map(|(key, entry)| (key.as_str(), entry)),
+        )?;
+        for (key, entry) in entries {
+            map.insert(key.clone(), entry.clone());
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Checks and applies the whole batch under a single lock of the map.
+    fn apply_refresh_batch(&self, updates: &[(String, ShareEntry)]) -> Result<(), RepoError> {
+        validate_batch(updates)?;
+        let mut map = self.map.lock().unwrap();
+        for (key, entry) in updates {
+            check_refresh(key, map.get(key), entry)?;
+        }
+        self.check_quotas(
+            &map,
+            updates.iter().map(|(key, entry)| (key.as_str(), entry)),
+        )?;
+        for (key, entry) in updates {
+            map.insert(key.clone(), entry.clone());
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Computes the statistics of the map under a single lock.
+    fn stats(&self) -> Result<DaoStats, RepoError> {
+        let map = self.map.lock().unwrap();
+        DaoStats::from_entries(map.values())
+    }
+
+    /// Deletes every entry registered by `owner` under a single lock of the map.
+    fn delete_by_owner(&self, owner: &[u8]) -> Result<Vec<String>, RepoError> {
         let mut map = self.map.lock().unwrap();
-        map.remove(key);
+        let mut deleted: Vec<String> = map
+            .iter()
+            .filter(|(_, entry)| entry.sender == owner)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &deleted {
+            map.remove(key);
+        }
+        if !deleted.is_empty() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        deleted.sort_unstable();
+        Ok(deleted)
+    }
+
+    fn put_tombstone(&self, key: &str, tombstone: &Tombstone) -> Result<(), RepoError> {
+        let mut tombstones = self.tombstones.lock().unwrap();
+        tombstones.insert(key.to_string(), tombstone.clone());
+        Ok(())
+    }
+
+    fn get_tombstone(&self, key: &str) -> Result<Option<Tombstone>, RepoError> {
+        Ok(self.tombstones.lock().unwrap().get(key).cloned())
+    }
+
+    fn remove_tombstone(&self, key: &str) -> Result<(), RepoError> {
+        self.tombstones.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn purge_tombstones(&self, now: u64) -> Result<usize, RepoError> {
+        let mut tombstones = self.tombstones.lock().unwrap();
+        let before = tombstones.len();
+        tombstones.retain(|_, tombstone| tombstone.is_live(now));
+        Ok(before - tombstones.len())
+    }
+
+    fn quarantine(&self, key: &str) -> Result<bool, RepoError> {
+        let Some(entry) = self.map.lock().unwrap().remove(key) else {
+            return Ok(false);
+        };
+        self.quarantine
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), entry);
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    fn is_quarantined(&self, key: &str) -> Result<bool, RepoError> {
+        Ok(self.quarantine.lock().unwrap().contains_key(key))
+    }
+
+    fn quarantined_keys(&self) -> Result<Vec<String>, RepoError> {
+        Ok(self.quarantine.lock().unwrap().keys().cloned().collect())
+    }
+
+    /// Saves a snapshot if snapshots are enabled and the entries changed since the last one.
+    fn flush(&self) -> Result<(), RepoError> {
+        if self.snapshot_path.is_some() && self.dirty.load(Ordering::Relaxed) {
+            self.save_snapshot()?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::testsuite::{check_batches, check_stats, populate, run_conformance, walk_pages};
+    use super::*;
+
+    fn temporary_dao() -> SledShareEntryDao {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        SledShareEntryDao::from_db(db, None).unwrap()
+    }
+
+    fn encrypted_dao(db: &Db, key: &EncryptionKey) -> SledShareEntryDao {
+        SledShareEntryDao::from_db(db.clone(), Some(key.clone())).unwrap()
+    }
+
+    fn entry() -> ShareEntry {
+        ShareEntry {
+            share: (1, vec![0, 1, 2, 255]),
+            sender: vec![4, 5, 6],
+            threshold: 3,
+            ..Default::default()
+        }
+    }
+
+    fn expiring_entry(expires_at: u64) -> ShareEntry {
+        ShareEntry {
+            expires_at: Some(expires_at),
+            ..entry()
+        }
+    }
+
+    #[test]
+    fn test_insert_writes_tagged_binary_value() {
+        let dao = temporary_dao();
+        dao.insert("key", &entry()).unwrap();
+
+        let raw = dao.db.get("key").unwrap().unwrap();
+        assert_eq!(raw[0], FORMAT_CHECKSUMMED);
+        assert_eq!(raw[1], SHARE_ENTRY_VERSION);
+
+        let read = dao.get("key").unwrap().unwrap();
+        assert_eq!(read.share, entry().share);
+        assert_eq!(read.sender, entry().sender);
+        assert_eq!(read.threshold, entry().threshold);
+    }
+
+    #[test]
+    fn test_legacy_json_value_is_read_and_migrated() {
+        let dao = temporary_dao();
+        let legacy = LegacyShareEntry {
+            share: (1, vec![0, 1, 2, 255]),
+            sender: entry().sender,
+        };
+        let json = serde_json::to_vec(&legacy).unwrap();
+        dao.db.insert("legacy", json).unwrap();
+
+        let read = dao.get("legacy").unwrap().unwrap();
+        assert_eq!(read.share, entry().share);
+        assert_eq!(read.threshold, LEGACY_DEFAULT_THRESHOLD);
+
+        let raw = dao.db.get("legacy").unwrap().unwrap();
+        assert_eq!(raw[0], FORMAT_CHECKSUMMED);
+        assert_eq!(dao.get("legacy").unwrap().unwrap().share, entry().share);
+    }
+
+    #[test]
+    fn test_two_field_json_value_is_read_into_the_current_entry() {
+        let dao = temporary_dao();
+        let json = br#"{"share":[1,[0,1,2,255]],"sender":[4,5,6]}"#;
+        dao.db.insert("two-field", json.to_vec()).unwrap();
+
+        let expected = ShareEntry {
+            share: (1, vec![0, 1, 2, 255]),
+            sender: vec![4, 5, 6],
+            threshold: LEGACY_DEFAULT_THRESHOLD,
+            ..Default::default()
+        };
+        assert_eq!(dao.get("two-field").unwrap(), Some(expected.clone()));
+        let raw = dao.db.get("two-field").unwrap().unwrap();
+        assert_eq!(&raw[..2], &[FORMAT_CHECKSUMMED, SHARE_ENTRY_VERSION]);
+        assert_eq!(dao.get("two-field").unwrap(), Some(expected));
+    }
+
+    #[test]
+    fn test_get_all_reads_mixed_encodings() {
+        let dao = temporary_dao();
+        dao.insert("new", &entry()).unwrap();
+        dao.db
+            .insert("old", serde_json::to_vec(&entry()).unwrap())
+            .unwrap();
+
+        let all = dao.get_all().unwrap();
+        assert_eq!(all.len(), 2);
+        assert!(dao
+            .db
+            .iter()
+            .values()
+            .all(|v| v.unwrap()[0] == FORMAT_CHECKSUMMED));
+    }
+
+    #[test]
+    fn test_legacy_json_entries_migrate_to_current_version() {
+        let dao = temporary_dao();
+        for i in 0..3u8 {
+            let legacy = LegacyShareEntry {
+                share: (i + 1, vec![i, 42]),
+                sender: vec![7, 7, 7],
+            };
+            dao.db
+                .insert(format!("legacy-{i}"), serde_json::to_vec(&legacy).unwrap())
+                .unwrap();
+        }
+        dao.insert("current", &entry()).unwrap();
+
+        assert_eq!(dao.migrate_all().unwrap(), 3);
+        assert_eq!(dao.migrate_all().unwrap(), 0);
+
+        for (key, value) in dao.db.iter().map(|item| item.unwrap()) {
+            assert_eq!(&value[..2], &[FORMAT_CHECKSUMMED, SHARE_ENTRY_VERSION]);
+            let read = dao
+                .get(std::str::from_utf8(&key).unwrap())
+                .unwrap()
+                .unwrap();
+            if key.starts_with(b"legacy-") {
+                assert_eq!(read.threshold, LEGACY_DEFAULT_THRESHOLD);
+                assert_eq!(read.sender, vec![7, 7, 7]);
+            }
+        }
+    }
+
+    #[cfg(feature = "sss16")]
+    #[test]
+    fn test_validate_bounds_the_share_to_its_field() {
+        let entry = ShareEntry {
+            share: (256, vec![1, 2]),
+            sender: vec![9],
+            threshold: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            entry.validate("key"),
+            Err(RepoError::InvalidEntry(
+                "key".to_string(),
+                "share index beyond its field"
+            ))
+        );
+
+        let wide = ShareEntry {
+            field: ShareField::Gf65536,
+            ..entry
+        };
+        assert_eq!(wide.validate("key"), Ok(()));
+        let odd = ShareEntry {
+            share: (256, vec![1, 2, 3]),
+            ..wide
+        };
+        assert_eq!(
+            odd.validate("key"),
+            Err(RepoError::InvalidEntry(
+                "key".to_string(),
+                "odd share length over GF(2^16)"
+            ))
+        );
+    }
+
+    #[cfg(feature = "sss16")]
+    #[test]
+    fn test_shares_split_over_gf65536_are_stored_and_combined() {
+        let dao = temporary_dao();
+        let shares = crate::sss16::split_secret(b"a thousand shares", 300, 1000).unwrap();
+        for (index, share) in &shares {
+            let entry = ShareEntry {
+                share: (*index, share.clone()),
+                field: ShareField::Gf65536,
+                sender: vec![9],
+                threshold: 300,
+                ..Default::default()
+            };
+            dao.insert(&format!("share/{}", index), &entry).unwrap();
+        }
+        let raw = dao.db.get("share/1").unwrap().unwrap();
+        assert_eq!(&raw[..2], &[FORMAT_CHECKSUMMED, WIDE_SHARE_ENTRY_VERSION]);
+
+        let stored: HashMap<ShareIndex, Vec<u8>> = (701..=1000)
+            .map(|index| {
+                let entry = dao.get(&format!("share/{}", index)).unwrap().unwrap();
+                assert_eq!(entry.field, ShareField::Gf65536);
+                entry.share
+            })
+            .collect();
+        let secret = crate::sss16::combine_shares(&stored).unwrap();
+        assert_eq!(secret, b"a thousand shares");
+    }
+
+    #[cfg(feature = "sss16")]
+    #[test]
+    fn test_gf256_entries_keep_the_narrow_layout() {
+        let dao = temporary_dao();
+        dao.insert("key", &entry()).unwrap();
+        let raw = dao.db.get("key").unwrap().unwrap();
+        assert_eq!(&raw[..2], &[FORMAT_CHECKSUMMED, SHARE_ENTRY_VERSION]);
+
+        // as written by a build without the `sss16` feature
+        let v1 = ShareEntryV1::narrowed(&entry()).unwrap();
+        let payload = bincode::serialize(&v1).unwrap();
+        assert_eq!(&raw[2 + CHECKSUM_LEN..], &payload[..]);
+        assert_eq!(dao.get("key").unwrap(), Some(entry()));
+    }
+
+    #[test]
+    fn test_refresh_interval_falls_back_to_the_default() {
+        let entry = ShareEntry {
+            refresh_interval_secs: Some(600),
+            ..Default::default()
+        };
+        assert_eq!(entry.refresh_interval(1_800), 600);
+        let default = ShareEntry {
+            refresh_interval_secs: None,
+            ..entry
+        };
+        assert_eq!(default.refresh_interval(1_800), 1_800);
+    }
+
+    #[test]
+    fn test_expired_keys_uses_expiry_index() {
+        let dao = temporary_dao();
+        dao.insert("forever", &entry()).unwrap();
+        dao.insert("early", &expiring_entry(100)).unwrap();
+        dao.insert("late", &expiring_entry(200)).unwrap();
+
+        assert!(dao.expired_keys(99).unwrap().is_empty());
+        assert_eq!(dao.expired_keys(100).unwrap(), vec!["early".to_string()]);
+        assert_eq!(
+            dao.expired_keys(500).unwrap(),
+            vec!["early".to_string(), "late".to_string()]
+        );
+        assert_eq!(dao.expiry.len(), 2);
+    }
+
+    #[test]
+    fn test_expiry_index_follows_updates_and_deletes() {
+        let dao = temporary_dao();
+        dao.insert("key", &expiring_entry(100)).unwrap();
+        dao.update("key", &expiring_entry(300)).unwrap();
+        assert!(dao.expired_keys(200).unwrap().is_empty());
+        assert_eq!(dao.expiry.len(), 1);
+
+        dao.update("key", &entry()).unwrap();
+        assert!(dao.expired_keys(u64::MAX).unwrap().is_empty());
+        assert_eq!(dao.expiry.len(), 0);
+
+        dao.insert("other", &expiring_entry(100)).unwrap();
+        dao.delete("other").unwrap();
+        assert!(dao.expired_keys(u64::MAX).unwrap().is_empty());
+        assert_eq!(dao.expiry.len(), 0);
+    }
+
+    #[test]
+    fn test_hashmap_expired_keys_scans_entries() {
+        let dao = HashMapShareEntryDao::new();
+        dao.insert("forever", &entry()).unwrap();
+        dao.insert("early", &expiring_entry(100)).unwrap();
+
+        assert_eq!(dao.expired_keys(150).unwrap(), vec!["early".to_string()]);
+    }
+
+    #[test]
+    fn test_sled_pages_cover_every_key_once() {
+        let dao = temporary_dao();
+        let keys = populate(&dao, 3000);
+
+        assert_eq!(walk_pages(&dao, 256), keys);
+        assert_eq!(walk_pages(&dao, 3000), keys);
+        assert_eq!(dao.get_page(Some(&keys[2998]), 256).unwrap().len(), 1);
+        assert!(dao.get_page(Some(&keys[2999]), 256).unwrap().is_empty());
+        assert_eq!(dao.get_all().unwrap().len(), 3000);
+    }
+
+    #[test]
+    fn test_sled_stats_survive_reopen() {
+        let path = std::env::temp_dir().join(format!("shard-stats-{}", rand::random::<u64>()));
+        let path = path.to_str().unwrap();
+        let stats = {
+            let dao = SledShareEntryDao::new(path).unwrap();
+            check_stats(&dao);
+            populate(&dao, 10);
+            let entries = dao.get_all().unwrap();
+            assert_eq!(
+                dao.stats().unwrap(),
+                DaoStats::from_entries(entries.iter().map(|(_, entry)| entry)).unwrap()
+            );
+            dao.stats().unwrap()
+        };
+
+        let dao = reopen(|| SledShareEntryDao::new(path));
+        assert_eq!(dao.stats().unwrap(), stats);
+
+        // a database without counters has them rebuilt when it is opened
+        dao.db.drop_tree("stats").unwrap();
+        let db = dao.db.clone();
+        drop(dao);
+        let dao = SledShareEntryDao::from_db(db, None).unwrap();
+        assert_eq!(dao.stats().unwrap(), stats);
+        drop(dao);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    /// Reopens a sled database, waiting for the background threads of a dropped handle to
+    /// release its file lock.
+    fn reopen(open: impl Fn() -> Result<SledShareEntryDao, RepoError>) -> SledShareEntryDao {
+        for _ in 0..50 {
+            match open() {
+                Ok(dao) => return dao,
+                Err(e) if e.to_string().contains("could not acquire lock") => {
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+                Err(e) => panic!("could not reopen the database: {}", e),
+            }
+        }
+        open().unwrap()
+    }
+
+    fn assert_read_only<T: fmt::Debug>(result: Result<T, RepoError>) {
+        let e = result.unwrap_err();
+        assert_eq!(e, RepoError::ReadOnly);
+    }
+
+    #[test]
+    fn test_sled_read_only_open() {
+        let path = std::env::temp_dir().join(format!("shard-ro-{}", rand::random::<u64>()));
+        let path = path.to_str().unwrap();
+        assert!(SledShareEntryDao::open_read_only(path).is_err());
+        assert!(!Path::new(path).exists());
+
+        let keys = {
+            let dao = SledShareEntryDao::new(path).unwrap();
+            let keys = populate(&dao, 5);
+            dao.flush().unwrap();
+            keys
+        };
+
+        let dao = reopen(|| SledShareEntryDao::open_read_only(path));
+        assert!(dao.is_read_only());
+        let stored = dao.get(&keys[0]).unwrap().unwrap();
+        assert_eq!(dao.get_all().unwrap().len(), 5);
+        assert_eq!(dao.keys().unwrap(), keys);
+        assert_eq!(dao.stats().unwrap().entries, 5);
+        assert_eq!(dao.keys_by_owner(&stored.sender).unwrap().len(), 5);
+        let mut backup = Vec::new();
+        assert_eq!(dao.export(&mut backup).unwrap(), 5);
+
+        assert_read_only(dao.insert("new", &entry()));
+        assert_read_only(dao.update(&keys[0], &entry()));
+        assert_read_only(dao.delete(&keys[0]));
+        assert_read_only(dao.compare_and_swap(&keys[0], &stored, &entry()));
+        assert_read_only(dao.insert_batch(&[("new".to_string(), entry())]));
+        assert_read_only(dao.delete_by_owner(&stored.sender));
+        assert_read_only(dao.import(&mut backup.as_slice(), ConflictPolicy::Overwrite));
+        assert_read_only(dao.put_tombstone(&keys[0], &Tombstone::default()));
+        assert_read_only(dao.purge_tombstones(0));
+        assert_read_only(dao.migrate_all());
+        assert_read_only(dao.reindex());
+        dao.flush().unwrap();
+        assert_eq!(dao.get(&keys[0]).unwrap(), Some(stored));
+        drop(dao);
+
+        let dao = reopen(|| SledShareEntryDao::new(path));
+        assert!(!dao.is_read_only());
+        assert_eq!(dao.keys().unwrap(), keys);
+        dao.delete(&keys[0]).unwrap();
+        drop(dao);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_sled_stats_follow_migration() {
+        let dao = temporary_dao();
+        let legacy = serde_json::to_vec(&entry()).unwrap();
+        dao.db.insert("legacy", legacy.as_slice()).unwrap();
+        dao.db.drop_tree("stats").unwrap();
+        let dao = SledShareEntryDao::from_db(dao.db.clone(), None).unwrap();
+        assert_eq!(dao.stats().unwrap().total_value_bytes, legacy.len() as u64);
+
+        dao.get("legacy").unwrap();
+        assert_eq!(
+            dao.stats().unwrap().total_value_bytes,
+            encode_entry(&entry()).unwrap().len() as u64
+        );
+    }
+
+    #[test]
+    fn test_sled_batches() {
+        let dao = temporary_dao();
+        check_batches(&dao);
+        let entries = dao.get_all().unwrap();
+        assert_eq!(
+            dao.stats().unwrap(),
+            DaoStats::from_entries(entries.iter().map(|(_, entry)| entry)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sled_keys_do_not_read_values() {
+        let dao = temporary_dao();
+        dao.insert("good", &entry()).unwrap();
+        dao.db.insert("malformed/1", &[0xff, 0x00][..]).unwrap();
+        dao.db.insert("malformed/2", &[][..]).unwrap();
+
+        assert!(dao.get_all().is_err());
+        assert_eq!(
+            dao.keys().unwrap(),
+            vec!["good", "malformed/1", "malformed/2"]
+        );
+        assert_eq!(
+            dao.keys_with_prefix("malformed/").unwrap(),
+            vec!["malformed/1", "malformed/2"]
+        );
+    }
+
+    #[test]
+    fn test_flush_policy_from_str() {
+        assert_eq!("every-write".parse(), Ok(FlushPolicy::EveryWrite));
+        assert_eq!("every:100".parse(), Ok(FlushPolicy::EveryNWrites(100)));
+        assert_eq!(
+            "interval:5".parse(),
+            Ok(FlushPolicy::Interval(Duration::from_secs(5)))
+        );
+        assert_eq!("never".parse(), Ok(FlushPolicy::Never));
+        for invalid in ["every:0", "interval:soon", "always", "every-write:1"] {
+            assert!(invalid.parse::<FlushPolicy>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_flush_every_n_writes() {
+        let dao = temporary_dao().with_flush_policy(FlushPolicy::EveryNWrites(3));
+        let unflushed = || dao.unflushed_writes.load(Ordering::Relaxed);
+        dao.insert("a", &entry()).unwrap();
+        dao.insert("b", &entry()).unwrap();
+        assert_eq!(unflushed(), 2);
+        dao.delete("a").unwrap();
+        assert_eq!(unflushed(), 0);
+
+        dao.insert("a", &entry()).unwrap();
+        dao.flush().unwrap();
+        assert_eq!(unflushed(), 0);
+
+        let dao = temporary_dao().with_flush_policy(FlushPolicy::Never);
+        dao.insert("a", &entry()).unwrap();
+        assert_eq!(dao.unflushed_writes.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_sled_conformance() {
+        run_conformance(temporary_dao);
+    }
+
+    #[test]
+    fn test_sled_conformance_with_encryption() {
+        run_conformance(|| {
+            let db = sled::Config::new().temporary(true).open().unwrap();
+            encrypted_dao(&db, &EncryptionKey::from_bytes([4u8; 32]))
+        });
+    }
+
+    #[test]
+    fn test_hashmap_conformance() {
+        run_conformance(HashMapShareEntryDao::new);
+    }
+
+    fn owned_entry(owner: &[u8], share: Vec<u8>) -> ShareEntry {
+        ShareEntry {
+            share: (1, share),
+            sender: owner.to_vec(),
+            ..entry()
+        }
+    }
+
+    fn assert_quota_exceeded(result: Result<(), RepoError>, limit: &'static str) {
+        let err = result.unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                RepoError::QuotaExceeded { limit: exceeded, .. } if *exceeded == limit
+            ),
+            "expected {} to be exceeded, got {}",
+            limit,
+            err
+        );
+    }
+
+    /// Fills an owner to a quota of three entries, checks that a fourth is refused but updates
+    /// and other owners are not, and that a delete releases quota.
+    fn check_entry_quota(dao: &dyn ShareEntryDaoTrait) {
+        for i in 0..3 {
+            dao.insert(&format!("a{}", i), &owned_entry(b"alice", vec![i]))
+                .unwrap();
+        }
+        let err = dao
+            .insert("a3", &owned_entry(b"alice", vec![3]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "storage quota exceeded: max_entries_per_owner is 3, 3 already used"
+        );
+        assert_quota_exceeded(
+            dao.insert_batch(&[
+                ("b0".to_string(), owned_entry(b"bob", vec![0])),
+                ("a3".to_string(), owned_entry(b"alice", vec![3])),
+            ]),
+            "max_entries_per_owner",
+        );
+        assert_eq!(dao.get("b0").unwrap(), None);
+
+        dao.insert("b0", &owned_entry(b"bob", vec![0])).unwrap();
+        dao.insert("a0", &owned_entry(b"alice", vec![9])).unwrap();
+        dao.update("a1", &owned_entry(b"alice", vec![9])).unwrap();
+
+        dao.delete("a2").unwrap();
+        dao.insert("a3", &owned_entry(b"alice", vec![3])).unwrap();
+        assert_quota_exceeded(
+            dao.insert("a4", &owned_entry(b"alice", vec![4])),
+            "max_entries_per_owner",
+        );
+    }
+
+    /// Checks the byte quotas of `byte_quotas`, which leave room for two entries holding an 8 byte
+    /// share per owner and three in total.
+    fn check_byte_quotas(dao: &dyn ShareEntryDaoTrait) {
+        dao.insert("a0", &owned_entry(b"alice", vec![0; 8]))
+            .unwrap();
+        dao.insert("a1", &owned_entry(b"alice", vec![1; 8]))
+            .unwrap();
+        assert_quota_exceeded(
+            dao.insert("a2", &owned_entry(b"alice", vec![2; 8])),
+            "max_bytes_per_owner",
+        );
+        assert_quota_exceeded(
+            dao.update("a1", &owned_entry(b"alice", vec![1; 9])),
+            "max_bytes_per_owner",
+        );
+        dao.update("a1", &owned_entry(b"alice", vec![1; 7]))
+            .unwrap();
+        dao.update("a1", &owned_entry(b"alice", vec![1; 8]))
+            .unwrap();
+
+        dao.insert("b0", &owned_entry(b"bob", vec![0; 8])).unwrap();
+        assert_quota_exceeded(
+            dao.insert("b1", &owned_entry(b"bob", vec![1; 8])),
+            "max_total_bytes",
+        );
+        dao.delete("a0").unwrap();
+        dao.insert("b1", &owned_entry(b"bob", vec![1; 8])).unwrap();
+    }
+
+    fn byte_quotas() -> DaoQuotas {
+        let len = encode_entry(&owned_entry(b"alice", vec![0; 8]))
+            .unwrap()
+            .len() as u64;
+        DaoQuotas {
+            max_bytes_per_owner: Some(2 * len),
+            max_total_bytes: Some(3 * len),
+            ..Default::default()
+        }
+    }
+
+    fn entry_quota() -> DaoQuotas {
+        DaoQuotas {
+            max_entries_per_owner: Some(3),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sled_quotas() {
+        check_entry_quota(&temporary_dao().with_quotas(entry_quota()));
+        check_byte_quotas(&temporary_dao().with_quotas(byte_quotas()));
+    }
+
+    #[test]
+    fn test_hashmap_quotas() {
+        check_entry_quota(&HashMapShareEntryDao::new().with_quotas(entry_quota()));
+        check_byte_quotas(&HashMapShareEntryDao::new().with_quotas(byte_quotas()));
+    }
+
+    #[test]
+    fn test_sled_owner_byte_counters_are_rebuilt() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let dao = SledShareEntryDao::from_db(db.clone(), None).unwrap();
+        populate(&dao, 10);
+        let owner = dao.get_all().unwrap()[0].1.sender.clone();
+        let owner_bytes = dao
+            .read_counter(&SledShareEntryDao::owner_bytes_stats_key(&owner))
+            .unwrap();
+        assert!(owner_bytes > 0);
+
+        dao.stats.remove(STATS_LAYOUT).unwrap();
+        dao.stats
+            .remove(SledShareEntryDao::owner_bytes_stats_key(&owner))
+            .unwrap();
+        let reopened = SledShareEntryDao::from_db(db, None).unwrap();
+        assert_eq!(
+            reopened
+                .read_counter(&SledShareEntryDao::owner_bytes_stats_key(&owner))
+                .unwrap(),
+            owner_bytes
+        );
+    }
+
+    #[test]
+    fn test_owner_index_follows_owner_changes_and_deletes() {
+        let dao = temporary_dao();
+        dao.insert("k0", &owned_entry(b"alice", vec![0])).unwrap();
+        dao.insert("k1", &owned_entry(b"alice", vec![1])).unwrap();
+        assert_eq!(dao.keys_by_owner(b"alice").unwrap(), vec!["k0", "k1"]);
+
+        dao.update("k0", &owned_entry(b"bob", vec![0])).unwrap();
+        assert_eq!(dao.keys_by_owner(b"alice").unwrap(), vec!["k1"]);
+        assert_eq!(dao.keys_by_owner(b"bob").unwrap(), vec!["k0"]);
+
+        assert!(dao
+            .compare_and_swap(
+                "k1",
+                &owned_entry(b"alice", vec![1]),
+                &owned_entry(b"bob", vec![1])
+            )
+            .unwrap());
+        assert!(dao.keys_by_owner(b"alice").unwrap().is_empty());
+        assert_eq!(dao.keys_by_owner(b"bob").unwrap(), vec!["k0", "k1"]);
+
+        dao.insert_batch(&[
+            ("k1".to_string(), owned_entry(b"alice", vec![1])),
+            ("k2".to_string(), owned_entry(b"alice", vec![2])),
+        ])
+        .unwrap();
+        assert_eq!(dao.keys_by_owner(b"alice").unwrap(), vec!["k1", "k2"]);
+        assert_eq!(dao.keys_by_owner(b"bob").unwrap(), vec!["k0"]);
+
+        dao.delete("k1").unwrap();
+        assert_eq!(dao.delete_by_owner(b"bob").unwrap(), vec!["k0"]);
+        assert_eq!(dao.keys_by_owner(b"alice").unwrap(), vec!["k2"]);
+        assert!(dao.keys_by_owner(b"bob").unwrap().is_empty());
+        assert_eq!(dao.owners.len(), dao.db.len());
+    }
+
+    #[test]
+    fn test_owner_index_does_not_mix_owner_prefixes() {
+        let dao = temporary_dao();
+        dao.insert("k0", &owned_entry(b"al", vec![0])).unwrap();
+        dao.insert("k1", &owned_entry(b"alice", vec![1])).unwrap();
+        assert_eq!(dao.keys_by_owner(b"al").unwrap(), vec!["k0"]);
+        assert_eq!(dao.keys_by_owner(b"alice").unwrap(), vec!["k1"]);
+    }
+
+    #[test]
+    fn test_reindex_rebuilds_owner_index() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let dao = SledShareEntryDao::from_db(db.clone(), None).unwrap();
+        for i in 0..20u8 {
+            let owner: &[u8] = if i % 3 == 0 { b"alice" } else { b"bob" };
+            dao.insert(&format!("k{:02}", i), &owned_entry(owner, vec![i]))
+                .unwrap();
+        }
+        let alice = dao.keys_by_owner(b"alice").unwrap();
+        let bob = dao.keys_by_owner(b"bob").unwrap();
+        assert_eq!(alice.len(), 7);
+        assert_eq!(bob.len(), 13);
+
+        dao.owners.clear().unwrap();
+        assert!(dao.keys_by_owner(b"alice").unwrap().is_empty());
+        assert_eq!(dao.reindex().unwrap(), 20);
+        assert_eq!(dao.keys_by_owner(b"alice").unwrap(), alice);
+        assert_eq!(dao.keys_by_owner(b"bob").unwrap(), bob);
+
+        dao.owners.clear().unwrap();
+        db.open_tree("meta")
+            .unwrap()
+            .remove(META_OWNER_INDEX)
+            .unwrap();
+        let reopened = SledShareEntryDao::from_db(db, None).unwrap();
+        assert_eq!(reopened.keys_by_owner(b"alice").unwrap(), alice);
+        assert_eq!(reopened.keys_by_owner(b"bob").unwrap(), bob);
+    }
+
+    fn sorted_entries(dao: &HashMapShareEntryDao) -> Vec<(String, ShareEntry)> {
+        let mut entries = dao.get_all().unwrap();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let path = std::env::temp_dir().join(format!("shard-snapshot-{}", rand::random::<u64>()));
+        let dao = HashMapShareEntryDao::new_with_snapshot(&path);
+        populate(&dao, 10);
+        dao.insert("expiring", &expiring_entry(42)).unwrap();
+        assert_eq!(dao.save_snapshot().unwrap(), 11);
+
+        let restored = HashMapShareEntryDao::new_with_snapshot(&path);
+        assert_eq!(sorted_entries(&restored), sorted_entries(&dao));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_or_missing_snapshot_starts_empty() {
+        let path = std::env::temp_dir().join(format!("shard-snapshot-{}", rand::random::<u64>()));
+        assert!(HashMapShareEntryDao::new_with_snapshot(&path)
+            .get_all()
+            .unwrap()
+            .is_empty());
+
+        fs::write(&path, b"not a snapshot").unwrap();
+        let dao = HashMapShareEntryDao::new_with_snapshot(&path);
+        assert!(dao.get_all().unwrap().is_empty());
+        dao.insert("key", &entry()).unwrap();
+        dao.flush().unwrap();
+        let restored = HashMapShareEntryDao::new_with_snapshot(&path);
+        assert_eq!(restored.get("key").unwrap(), Some(entry()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_flush_saves_only_when_dirty() {
+        let path = std::env::temp_dir().join(format!("shard-snapshot-{}", rand::random::<u64>()));
+        let dao = HashMapShareEntryDao::new_with_snapshot(&path);
+        dao.flush().unwrap();
+        assert!(!path.exists());
+
+        dao.insert("key", &entry()).unwrap();
+        dao.flush().unwrap();
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+        dao.flush().unwrap();
+        assert!(!path.exists());
+
+        assert!(HashMapShareEntryDao::new().save_snapshot().is_err());
+    }
+
+    #[test]
+    fn test_failed_snapshot_stays_dirty() {
+        let dir = std::env::temp_dir().join(format!("shard-snapshot-{}", rand::random::<u64>()));
+        let path = dir.join("snapshot");
+        let dao = HashMapShareEntryDao::new_with_snapshot(&path);
+        dao.insert("key", &entry()).unwrap();
+        assert!(dao.flush().is_err());
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("snapshot.partial"), "left by a crash").unwrap();
+        dao.flush().unwrap();
+        let restored = HashMapShareEntryDao::new_with_snapshot(&path);
+        assert_eq!(restored.get("key").unwrap(), Some(entry()));
+        assert!(!dir.join("snapshot.partial").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_entries_need_the_key() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let key = EncryptionKey::from_bytes([1u8; 32]);
+        encrypted_dao(&db, &key).insert("key", &entry()).unwrap();
+
+        let raw = db.get("key").unwrap().unwrap();
+        assert_eq!(raw[0], FORMAT_ENCRYPTED);
+        assert_eq!(&raw[1..1 + KEY_ID_LEN], &key.id());
+        assert!(!raw.windows(4).any(|w| w == [0, 1, 2, 255]));
+
+        let plain = SledShareEntryDao::from_db(db.clone(), None).unwrap();
+        let err = plain.get("key").unwrap_err();
+        assert_eq!(
+            err,
+            RepoError::MissingEncryptionKey
+        );
+
+        let other = encrypted_dao(&db, &EncryptionKey::from_bytes([2u8; 32]));
+        let err = other.get("key").unwrap_err();
+        assert_eq!(
+            err,
+            RepoError::WrongEncryptionKey
+        );
+
+        let read = encrypted_dao(&db, &key).get("key").unwrap().unwrap();
+        assert_eq!(read.share, entry().share);
+    }
+
+    #[test]
+    fn test_encrypted_value_is_bound_to_its_key() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let key = EncryptionKey::from_bytes([1u8; 32]);
+        let dao = encrypted_dao(&db, &key);
+        dao.insert("a", &entry()).unwrap();
+        db.insert("b", db.get("a").unwrap().unwrap()).unwrap();
+
+        let err = dao.get("b").unwrap_err();
+        assert_eq!(
+            err,
+            RepoError::DecryptionFailed
+        );
+    }
+
+    #[test]
+    fn test_plaintext_database_is_encrypted_on_migration() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let plain = SledShareEntryDao::from_db(db.clone(), None).unwrap();
+        plain.insert("read", &entry()).unwrap();
+        plain.insert("migrated", &entry()).unwrap();
+        db.insert("legacy", serde_json::to_vec(&entry()).unwrap())
+            .unwrap();
+
+        let key = EncryptionKey::from_bytes([3u8; 32]);
+        let dao = encrypted_dao(&db, &key);
+        assert_eq!(dao.get("read").unwrap().unwrap().share, entry().share);
+        assert_eq!(db.get("read").unwrap().unwrap()[0], FORMAT_ENCRYPTED);
+
+        assert_eq!(dao.migrate_all().unwrap(), 2);
+        assert!(db
+            .iter()
+            .values()
+            .all(|v| v.unwrap()[0] == FORMAT_ENCRYPTED));
+        assert_eq!(dao.get_all().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_encryption_key_file_round_trip() {
+        let dir = std::env::temp_dir().join(format!("shard-key-{}", rand::random::<u64>()));
+        let path = dir.join("db.key");
+
+        let created = EncryptionKey::load_or_create(&path).unwrap();
+        let loaded = EncryptionKey::load_or_create(&path).unwrap();
+        assert_eq!(created.id(), loaded.id());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::write(&path, "not a key").unwrap();
+        let err = EncryptionKey::load_or_create(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            RepoError::InvalidKeyFile(_)
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_key_creation_loads_one_key() {
+        let dir = std::env::temp_dir().join(format!("shard-key-{}", rand::random::<u64>()));
+        let path = dir.join("db.key");
+
+        let barrier = std::sync::Barrier::new(8);
+        let ids: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        EncryptionKey::load_or_create(&path).unwrap().id()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(ids.iter().all(|id| *id == ids[0]));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_version_is_an_error() {
+        let dao = temporary_dao();
+        let mut raw = vec![FORMAT_CHECKSUMMED, 200];
+        raw.extend_from_slice(&checksum(200, &[0]).to_be_bytes());
+        raw.push(0);
+        dao.db.insert("future", raw).unwrap();
+
+        let err = dao.get("future").unwrap_err();
+        assert_eq!(
+            err,
+            RepoError::UnknownVersion(200)
+        );
+    }
+
+    #[test]
+    fn test_unknown_format_tag_is_an_error() {
+        let dao = temporary_dao();
+        dao.db.insert("corrupt", vec![0x7f, 1, 2, 3]).unwrap();
+
+        let err = dao.get("corrupt").unwrap_err();
+        assert_eq!(
+            err,
+            RepoError::UnknownFormat(0x7f)
+        );
+
+        dao.db.insert("empty", Vec::<u8>::new()).unwrap();
+        let err = dao.get("empty").unwrap_err();
+        assert_eq!(
+            err,
+            RepoError::EmptyValue
+        );
+    }
+
+    #[test]
+    fn test_flipped_byte_is_detected_as_corruption() {
+        let dao = temporary_dao();
+        dao.insert("key", &entry()).unwrap();
+        dao.insert("other", &entry()).unwrap();
+
+        let mut raw = dao.db.get("key").unwrap().unwrap().to_vec();
+        let last = raw.len() - 1;
+        raw[last] ^= 0x01;
+        dao.db.insert("key", raw).unwrap();
+
+        let err = dao.get("key").unwrap_err();
+        assert_eq!(
+            err,
+            RepoError::CorruptEntry {
+                key: "key".to_string()
+            }
+        );
+        assert!(dao.get_all().is_err());
+        assert_eq!(dao.get("other").unwrap().unwrap(), entry());
+    }
+
+    #[test]
+    fn test_checksum_mismatch_is_reported_by_decode_entry() {
+        let mut raw = encode_entry(&entry()).unwrap();
+        raw[2] ^= 0xff;
+        let err = decode_entry(&raw).unwrap_err();
+        assert_eq!(
+            err,
+            RepoError::ChecksumMismatch
+        );
+
+        let err = decode_entry(&[FORMAT_CHECKSUMMED, SHARE_ENTRY_VERSION, 0]).unwrap_err();
+        assert_eq!(
+            err,
+            RepoError::ChecksumMismatch
+        );
+    }
+}
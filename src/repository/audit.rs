@@ -0,0 +1,422 @@
+use super::RepoError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sled::Tree;
+use std::fmt;
+use std::sync::Mutex;
+
+/// The hash the first record of a new audit log is chained to.
+const GENESIS_HASH: [u8; 32] = [0u8; 32];
+
+/// A share operation recorded in the audit log.
+///
+/// # Variants
+///
+/// * `Register` - A share was registered.
+/// * `Get` - A share was requested.
+/// * `Refresh` - A share was refreshed.
+/// * `Delete` - A share was deleted.
+/// * `List` - The keys of an owner were listed.
+/// * `Stat` - The metadata of a share was requested.
+/// * `Grant` - A peer was granted read access to a share.
+/// * `Revoke` - A peer's read access to a share was revoked.
+/// * `Transfer` - A share was handed over to another owner.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuditOperation {
+    Register,
+    Get,
+    Refresh,
+    Delete,
+    List,
+    Stat,
+    Grant,
+    Revoke,
+    Transfer,
+}
+
+impl fmt::Display for AuditOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AuditOperation::Register => "register",
+            AuditOperation::Get => "get",
+            AuditOperation::Refresh => "refresh",
+            AuditOperation::Delete => "delete",
+            AuditOperation::List => "list",
+            AuditOperation::Stat => "stat",
+            AuditOperation::Grant => "grant",
+            AuditOperation::Revoke => "revoke",
+            AuditOperation::Transfer => "transfer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How a recorded operation ended.
+///
+/// # Variants
+///
+/// * `Success` - The operation was carried out.
+/// * `Refused` - The operation was turned down, for the given reason.
+/// * `Failed` - The operation failed with the given error.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum AuditOutcome {
+    Success,
+    Refused(String),
+    Failed(String),
+}
+
+impl fmt::Display for AuditOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditOutcome::Success => write!(f, "success"),
+            AuditOutcome::Refused(reason) => write!(f, "refused: {}", reason),
+            AuditOutcome::Failed(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+/// An operation to record in the audit log.
+///
+/// # Fields
+///
+/// * `timestamp` - The unix timestamp (seconds) of the operation.
+/// * `operation` - The operation performed.
+/// * `key` - The key the operation applied to, as chosen by the share owner or as stored.
+/// * `peer` - The peer id bytes of the requesting peer, or `None` for operations the provider
+///   started itself, such as scheduled refreshes and expiry purges.
+/// * `outcome` - How the operation ended.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    pub timestamp: u64,
+    pub operation: AuditOperation,
+    pub key: String,
+    pub peer: Option<Vec<u8>>,
+    pub outcome: AuditOutcome,
+}
+
+/// An event as stored in the audit log, chained to the record before it.
+///
+/// # Fields
+///
+/// * `seq` - The position of the record in the log, starting at 0.
+/// * `event` - The recorded event.
+/// * `prev_hash` - The hash of the record before this one.
+/// * `hash` - The SHA-256 digest of `seq`, `prev_hash`, and the encoded `event`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub seq: u64,
+    pub event: AuditEvent,
+    pub prev_hash: [u8; 32],
+    pub hash: [u8; 32],
+}
+
+impl AuditRecord {
+    /// Computes the hash a record of `event` at `seq` following `prev_hash` must carry.
+    fn compute_hash(
+        seq: u64,
+        prev_hash: &[u8; 32],
+        event: &AuditEvent,
+    ) -> Result<[u8; 32], RepoError> {
+        let mut hasher = Sha256::new();
+        hasher.update(seq.to_be_bytes());
+        hasher.update(prev_hash);
+        hasher.update(bincode::serialize(event)?);
+        Ok(hasher.finalize().into())
+    }
+}
+
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let peer = match &self.event.peer {
+            Some(peer) => hex::encode(peer),
+            None => "provider".to_string(),
+        };
+        write!(
+            f,
+            "{} {} {} {:?} {} {} {}",
+            self.seq,
+            self.event.timestamp,
+            self.event.operation,
+            self.event.key,
+            peer,
+            self.event.outcome,
+            hex::encode(self.hash)
+        )
+    }
+}
+
+/// Checks that `records` form an unbroken hash chain.
+///
+/// Every record must carry the hash of its own contents, and every record after the first must
+/// directly follow the one before it. The first record is not checked against its predecessor,
+/// which retention may have removed, so a chain cut at the start is only detected against a
+/// previously seen head hash.
+///
+/// # Arguments
+///
+/// * `records` - The records to check, in log order.
+///
+/// # Errors
+///
+/// Returns `RepoError::BrokenAuditChain` with the sequence number of the first record that does
+/// not match its contents or the record before it.
+pub fn verify_chain(records: &[AuditRecord]) -> Result<(), RepoError> {
+    let mut prev: Option<&AuditRecord> = None;
+    for record in records {
+        let follows =
+            prev.is_none_or(|prev| record.seq == prev.seq + 1 && record.prev_hash == prev.hash);
+        let hash = AuditRecord::compute_hash(record.seq, &record.prev_hash, &record.event)?;
+        if !follows || hash != record.hash {
+            return Err(RepoError::BrokenAuditChain(record.seq));
+        }
+        prev = Some(record);
+    }
+    Ok(())
+}
+
+/// An append-only, hash-chained log of the share operations a provider performed.
+///
+/// Records are kept in a sled tree under their big-endian sequence number. Each record carries
+/// the hash of the record before it, so removing or altering a record breaks the chain from
+/// that point on.
+///
+/// # Fields
+///
+/// * `tree` - The tree holding the records.
+/// * `max_records` - The number of most recent records kept, or `None` to keep every record.
+/// * `head` - The sequence number and hash the next record is chained to.
+pub struct AuditLog {
+    tree: Tree,
+    max_records: Option<u64>,
+    head: Mutex<(u64, [u8; 32])>,
+}
+
+impl AuditLog {
+    /// Opens the audit log stored in the sled database at `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the sled database holding the log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `AuditLog` or an error.
+    pub fn open(path: &str) -> Result<Self, RepoError> {
+        Self::from_tree(sled::open(path)?.open_tree("audit")?)
+    }
+
+    /// Opens the audit log held in `tree`, continuing the chain from its last record.
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - The tree holding the records.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `AuditLog` or an error if the last record cannot be read.
+    pub fn from_tree(tree: Tree) -> Result<Self, RepoError> {
+        let head = match tree.last()? {
+            Some((_, value)) => {
+                let last: AuditRecord = bincode::deserialize(&value)?;
+                (last.seq + 1, last.hash)
+            }
+            None => (0, GENESIS_HASH),
+        };
+        Ok(AuditLog {
+            tree,
+            max_records: None,
+            head: Mutex::new(head),
+        })
+    }
+
+    /// Limits the log to its most recent records, dropping older ones as new ones are recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_records` - The number of records to keep, or `None` to keep every record.
+    ///
+    /// # Returns
+    ///
+    /// The log, following the retention limit.
+    pub fn with_retention(mut self, max_records: Option<u64>) -> Self {
+        self.max_records = max_records;
+        self
+    }
+
+    /// Appends an event to the log, chained to the last record.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to record.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the stored `AuditRecord`.
+    pub fn record(&self, event: AuditEvent) -> Result<AuditRecord, RepoError> {
+        let mut head = self.head.lock().unwrap();
+        let (seq, prev_hash) = *head;
+        let hash = AuditRecord::compute_hash(seq, &prev_hash, &event)?;
+        let record = AuditRecord {
+            seq,
+            event,
+            prev_hash,
+            hash,
+        };
+        self.tree
+            .insert(seq.to_be_bytes(), bincode::serialize(&record)?)?;
+        *head = (seq + 1, hash);
+
+        if let Some(max_records) = self.max_records {
+            let cutoff = (seq + 1).saturating_sub(max_records);
+            for key in self.tree.range(..cutoff.to_be_bytes()).keys() {
+                self.tree.remove(key?)?;
+            }
+        }
+        Ok(record)
+    }
+
+    /// Reads the records with sequence numbers from `from` up to, but excluding, `to`.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The first sequence number to read.
+    /// * `to` - The sequence number to stop before.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the records still held in the range, in log order.
+    pub fn read_range(&self, from: u64, to: u64) -> Result<Vec<AuditRecord>, RepoError> {
+        let mut records = Vec::new();
+        for item in self.tree.range(from.to_be_bytes()..to.to_be_bytes()) {
+            let (_, value) = item?;
+            records.push(bincode::deserialize(&value)?);
+        }
+        Ok(records)
+    }
+
+    /// Checks that every record still held forms an unbroken chain ending at the head of the log.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RepoError::BrokenAuditChain` naming the first record out of place.
+    pub fn verify(&self) -> Result<(), RepoError> {
+        let (next_seq, head_hash) = *self.head.lock().unwrap();
+        let records = self.read_range(0, u64::MAX)?;
+        verify_chain(&records)?;
+        match records.last() {
+            Some(last) if last.seq + 1 != next_seq || last.hash != head_hash => {
+                Err(RepoError::BrokenAuditChain(last.seq))
+            }
+            None if next_seq > 0 => Err(RepoError::BrokenAuditChain(next_seq - 1)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Flushes the log to disk.
+    pub fn flush(&self) -> Result<(), RepoError> {
+        self.tree.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_log() -> AuditLog {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        AuditLog::from_tree(db.open_tree("audit").unwrap()).unwrap()
+    }
+
+    fn event(key: &str) -> AuditEvent {
+        AuditEvent {
+            timestamp: 1_700_000_000,
+            operation: AuditOperation::Register,
+            key: key.to_string(),
+            peer: Some(vec![1, 2, 3]),
+            outcome: AuditOutcome::Success,
+        }
+    }
+
+    fn broken_at(result: Result<(), RepoError>) -> Option<u64> {
+        match result.unwrap_err() {
+            RepoError::BrokenAuditChain(seq) => Some(seq),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_records_are_chained() {
+        let log = temporary_log();
+        for i in 0..5 {
+            let record = log.record(event(&format!("k{}", i))).unwrap();
+            assert_eq!(record.seq, i);
+        }
+        let records = log.read_range(0, u64::MAX).unwrap();
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[0].prev_hash, GENESIS_HASH);
+        assert_eq!(records[3].prev_hash, records[2].hash);
+        assert_eq!(log.read_range(1, 3).unwrap(), records[1..3].to_vec());
+        log.verify().unwrap();
+    }
+
+    #[test]
+    fn test_removed_middle_record_breaks_the_chain() {
+        let log = temporary_log();
+        for i in 0..5 {
+            log.record(event(&format!("k{}", i))).unwrap();
+        }
+        log.tree.remove(2u64.to_be_bytes()).unwrap();
+
+        let records = log.read_range(0, u64::MAX).unwrap();
+        assert_eq!(broken_at(verify_chain(&records)), Some(3));
+        assert_eq!(broken_at(log.verify()), Some(3));
+    }
+
+    #[test]
+    fn test_altered_record_breaks_the_chain() {
+        let log = temporary_log();
+        for i in 0..3 {
+            log.record(event(&format!("k{}", i))).unwrap();
+        }
+        let mut records = log.read_range(0, u64::MAX).unwrap();
+        records[1].event.outcome = AuditOutcome::Refused("quota".to_string());
+        assert_eq!(broken_at(verify_chain(&records)), Some(1));
+    }
+
+    #[test]
+    fn test_truncated_tail_is_detected() {
+        let log = temporary_log();
+        for i in 0..3 {
+            log.record(event(&format!("k{}", i))).unwrap();
+        }
+        log.tree.remove(2u64.to_be_bytes()).unwrap();
+        assert_eq!(broken_at(log.verify()), Some(1));
+    }
+
+    #[test]
+    fn test_retention_keeps_the_most_recent_records() {
+        let log = temporary_log().with_retention(Some(3));
+        for i in 0..10 {
+            log.record(event(&format!("k{}", i))).unwrap();
+        }
+        let records = log.read_range(0, u64::MAX).unwrap();
+        assert_eq!(
+            records.iter().map(|r| r.seq).collect::<Vec<_>>(),
+            vec![7, 8, 9]
+        );
+        log.verify().unwrap();
+    }
+
+    #[test]
+    fn test_reopened_log_continues_the_chain() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let log = AuditLog::from_tree(db.open_tree("audit").unwrap()).unwrap();
+        log.record(event("k0")).unwrap();
+        log.record(event("k1")).unwrap();
+
+        let reopened = AuditLog::from_tree(db.open_tree("audit").unwrap()).unwrap();
+        assert_eq!(reopened.record(event("k2")).unwrap().seq, 2);
+        reopened.verify().unwrap();
+    }
+}
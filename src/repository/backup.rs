@@ -0,0 +1,369 @@
+use super::{decode_entry, encode_entry, EncryptionKey, RepoError, ShareEntryDaoTrait};
+use libp2p_identity::PeerId;
+use std::fmt;
+use std::io::{Read, Write};
+
+/// Magic bytes opening every export stream.
+const EXPORT_MAGIC: &[u8; 8] = b"SHARDEXP";
+
+/// The version of the export stream written by this release.
+pub const EXPORT_VERSION: u8 = 1;
+
+/// Header flag marking a stream whose records are sealed with an `EncryptionKey`.
+const FLAG_ENCRYPTED: u8 = 0x01;
+
+/// The number of entries read from the store at a time while exporting.
+const EXPORT_PAGE_SIZE: usize = 256;
+
+/// Upper bound on the length of a single record, guarding against corrupt length prefixes.
+const MAX_RECORD_LEN: usize = 16 * 1024 * 1024;
+
+/// How `import` treats a key that already exists in the store with the same owner.
+///
+/// An existing key owned by a different peer is never overwritten, whatever the policy; it is
+/// skipped and reported in `ImportReport::conflicts`.
+///
+/// # Variants
+///
+/// * `Skip` - Keep the existing entry.
+/// * `Overwrite` - Replace the existing entry with the imported one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    #[default]
+    Skip,
+    Overwrite,
+}
+
+impl std::str::FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            other => Err(format!(
+                "unknown conflict policy {}, expected skip or overwrite",
+                other
+            )),
+        }
+    }
+}
+
+/// The outcome of an `import`.
+///
+/// # Fields
+///
+/// * `imported` - The number of entries written to the store.
+/// * `skipped` - Keys that already existed with the same owner and were kept.
+/// * `conflicts` - Keys that already existed with a different owner and were kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub skipped: Vec<String>,
+    pub conflicts: Vec<String>,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "imported {} entries, skipped {} existing, {} owner conflicts",
+            self.imported,
+            self.skipped.len(),
+            self.conflicts.len()
+        )?;
+        for key in &self.conflicts {
+            write!(f, "\n  conflict: {}", key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes every entry of `dao` to `writer` as an export stream.
+///
+/// The stream is the magic bytes, the stream version, a flags byte, then one record per entry
+/// and a zero length terminator. Each record is a big-endian `u32` length followed by the
+/// length-prefixed key and the entry in the tagged binary encoding. When `encryption_key` is
+/// given every record is sealed with it, bound to its position in the stream.
+///
+/// # Arguments
+///
+/// * `dao` - The store to export.
+/// * `writer` - The destination of the stream.
+/// * `encryption_key` - The key to seal records with, if any.
+///
+/// # Returns
+///
+/// A `Result` containing the number of entries exported.
+pub fn export_entries<D: ShareEntryDaoTrait + ?Sized>(
+    dao: &D,
+    writer: &mut dyn Write,
+    encryption_key: Option<&EncryptionKey>,
+) -> Result<usize, RepoError> {
+    let flags = if encryption_key.is_some() {
+        FLAG_ENCRYPTED
+    } else {
+        0
+    };
+    writer.write_all(EXPORT_MAGIC)?;
+    writer.write_all(&[EXPORT_VERSION, flags])?;
+
+    let mut index = 0u64;
+    let mut cursor: Option<String> = None;
+    loop {
+        let page = dao.get_page(cursor.as_deref(), EXPORT_PAGE_SIZE)?;
+        for (key, entry) in &page {
+            let mut record = Vec::new();
+            record.extend_from_slice(&(key.len() as u32).to_be_bytes());
+            record.extend_from_slice(key.as_bytes());
+            record.extend_from_slice(&encode_entry(entry)?);
+            if let Some(encryption_key) = encryption_key {
+                record = encryption_key.seal(&index.to_be_bytes(), &record)?;
+            }
+            writer.write_all(&(record.len() as u32).to_be_bytes())?;
+            writer.write_all(&record)?;
+            index += 1;
+        }
+        match page.last() {
+            Some((last_key, _)) if page.len() == EXPORT_PAGE_SIZE => {
+                cursor = Some(last_key.clone())
+            }
+            _ => break,
+        }
+    }
+    writer.write_all(&0u32.to_be_bytes())?;
+    writer.flush()?;
+    Ok(index as usize)
+}
+
+/// Reads an export stream produced by `export_entries` into `dao`.
+///
+/// # Arguments
+///
+/// * `dao` - The store to import into.
+/// * `reader` - The source of the stream.
+/// * `conflict` - How to treat keys that already exist with the same owner.
+/// * `encryption_key` - The key the stream was sealed with, if it is encrypted.
+///
+/// # Returns
+///
+/// A `Result` containing an `ImportReport`, or an error if the stream is malformed. Entries read
+/// before a malformed record have already been imported.
+pub fn import_entries<D: ShareEntryDaoTrait + ?Sized>(
+    dao: &D,
+    reader: &mut dyn Read,
+    conflict: ConflictPolicy,
+    encryption_key: Option<&EncryptionKey>,
+) -> Result<ImportReport, RepoError> {
+    let mut header = [0u8; EXPORT_MAGIC.len() + 2];
+    reader.read_exact(&mut header)?;
+    if &header[..EXPORT_MAGIC.len()] != EXPORT_MAGIC {
+        return Err(RepoError::InvalidExport("not a shard export stream".to_string()));
+    }
+    let (version, flags) = (header[EXPORT_MAGIC.len()], header[EXPORT_MAGIC.len() + 1]);
+    if version != EXPORT_VERSION {
+        return Err(RepoError::InvalidExport(format!(
+            "unsupported export stream version {}",
+            version
+        )));
+    }
+    let encryption_key = match (flags & FLAG_ENCRYPTED != 0, encryption_key) {
+        (true, None) => {
+            return Err(RepoError::InvalidExport(
+                "export stream is encrypted but no key was provided".to_string(),
+            ))
+        }
+        (true, Some(encryption_key)) => Some(encryption_key),
+        (false, _) => None,
+    };
+
+    let mut report = ImportReport::default();
+    for index in 0u64.. {
+        let mut len = [0u8; 4];
+        reader.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len == 0 {
+            break;
+        }
+        if len > MAX_RECORD_LEN {
+            return Err(RepoError::InvalidExport(format!(
+                "export record of {} bytes is too large",
+                len
+            )));
+        }
+        let mut record = vec![0u8; len];
+        reader.read_exact(&mut record)?;
+        if let Some(encryption_key) = encryption_key {
+            record = encryption_key.open(&index.to_be_bytes(), &record)?;
+        }
+
+        let (key, entry) = parse_record(&record)?;
+        match dao.get(&key)? {
+            Some(existing) if !same_owner(&existing.sender, &entry.sender) => {
+                report.conflicts.push(key)
+            }
+            Some(_) if conflict == ConflictPolicy::Skip => report.skipped.push(key),
+            _ => {
+                dao.insert(&key, &entry)?;
+                report.imported += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Splits a plaintext record into its key and entry.
+fn parse_record(record: &[u8]) -> Result<(String, super::ShareEntry), RepoError> {
+    let malformed = || RepoError::InvalidExport("malformed export record".to_string());
+    let key_len = record
+        .get(..4)
+        .ok_or_else(malformed)?
+        .try_into()
+        .map(u32::from_be_bytes)? as usize;
+    let key = record.get(4..4 + key_len).ok_or_else(malformed)?;
+    let key = String::from_utf8(key.to_vec())?;
+    let (entry, _) = decode_entry(&record[4 + key_len..])?;
+    Ok((key, entry))
+}
+
+/// Compares the owners of two entries, falling back to the raw bytes if either is not a peer id.
+fn same_owner(a: &[u8], b: &[u8]) -> bool {
+    match (PeerId::from_bytes(a), PeerId::from_bytes(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "sss16")]
+    use crate::repository::ShareField;
+    use crate::repository::{HashMapShareEntryDao, ShareEntry, SledShareEntryDao};
+
+    fn entry(owner: &PeerId, share: u8) -> ShareEntry {
+        ShareEntry {
+            share: (1, vec![share, 1, 2, 255]),
+            #[cfg(feature = "sss16")]
+            field: ShareField::Gf256,
+            sender: owner.to_bytes(),
+            threshold: 3,
+            expires_at: Some(1_700_000_000),
+            epoch: 3,
+            last_refreshed_unix: 1_600_000_000,
+            refresh_digest: Some([share; 32]),
+            refresh_interval_secs: Some(3600),
+            readers: vec![vec![share; 4]],
+            relay_grant: None,
+        }
+    }
+
+    fn populated(owner: &PeerId) -> HashMapShareEntryDao {
+        let dao = HashMapShareEntryDao::new();
+        for (i, key) in ["a", "b", "c"].iter().enumerate() {
+            dao.insert(key, &entry(owner, i as u8)).unwrap();
+        }
+        dao
+    }
+
+    fn fresh_sled(encryption_key: Option<EncryptionKey>) -> SledShareEntryDao {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        SledShareEntryDao::from_db(db, encryption_key).unwrap()
+    }
+
+    #[test]
+    fn test_export_into_fresh_sled() {
+        let owner = PeerId::random();
+        let source = populated(&owner);
+        let mut stream = Vec::new();
+        assert_eq!(source.export(&mut stream).unwrap(), 3);
+
+        let target = fresh_sled(None);
+        let report = target
+            .import(&mut stream.as_slice(), ConflictPolicy::Skip)
+            .unwrap();
+        assert_eq!(report.imported, 3);
+        assert!(report.conflicts.is_empty());
+
+        let mut expected = source.get_all().unwrap();
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        let imported = target.get_all().unwrap();
+        assert_eq!(imported.len(), expected.len());
+        for ((key, entry), (expected_key, expected_entry)) in imported.iter().zip(&expected) {
+            assert_eq!(key, expected_key);
+            assert_eq!(
+                encode_entry(entry).unwrap(),
+                encode_entry(expected_entry).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_import_reports_conflicts() {
+        let owner = PeerId::random();
+        let mut stream = Vec::new();
+        populated(&owner).export(&mut stream).unwrap();
+
+        let target = fresh_sled(None);
+        let stranger = entry(&PeerId::random(), 9);
+        target.insert("a", &stranger).unwrap();
+        target.insert("b", &entry(&owner, 9)).unwrap();
+
+        let report = target
+            .import(&mut stream.as_slice(), ConflictPolicy::Skip)
+            .unwrap();
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.skipped, vec!["b"]);
+        assert_eq!(report.conflicts, vec!["a"]);
+        assert_eq!(target.get("b").unwrap().unwrap().share.1[0], 9);
+
+        let report = target
+            .import(&mut stream.as_slice(), ConflictPolicy::Overwrite)
+            .unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.conflicts, vec!["a"]);
+        assert_eq!(target.get("a").unwrap().unwrap().sender, stranger.sender);
+        assert_eq!(target.get("b").unwrap().unwrap().share.1[0], 1);
+    }
+
+    #[test]
+    fn test_encrypted_export_needs_the_key() {
+        let owner = PeerId::random();
+        let key = EncryptionKey::from_bytes([5u8; 32]);
+        let source = fresh_sled(Some(key.clone()));
+        source.insert("a", &entry(&owner, 0)).unwrap();
+
+        let mut stream = Vec::new();
+        source.export(&mut stream).unwrap();
+        assert!(!stream.windows(4).any(|w| w == [0, 1, 2, 255]));
+
+        assert!(fresh_sled(None)
+            .import(&mut stream.as_slice(), ConflictPolicy::Skip)
+            .is_err());
+        let target = fresh_sled(Some(key));
+        let report = target
+            .import(&mut stream.as_slice(), ConflictPolicy::Skip)
+            .unwrap();
+        assert_eq!(report.imported, 1);
+        assert_eq!(target.get("a").unwrap().unwrap().share.1[0], 0);
+    }
+
+    #[test]
+    fn test_truncated_stream_is_an_error() {
+        let mut stream = Vec::new();
+        populated(&PeerId::random()).export(&mut stream).unwrap();
+        stream.truncate(stream.len() - 4);
+
+        let target = fresh_sled(None);
+        assert!(target
+            .import(&mut stream.as_slice(), ConflictPolicy::Skip)
+            .is_err());
+        assert!(import_entries(
+            &target,
+            &mut &b"NOTSHARD\x01\x00"[..],
+            ConflictPolicy::Skip,
+            None
+        )
+        .is_err());
+    }
+}
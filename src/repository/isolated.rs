@@ -0,0 +1,822 @@
+use super::{
+    export_entries, import_entries, owner_key, split_owner_key, validate_batch, ConflictPolicy,
+    DaoQuotas, DaoStats, EncryptionKey, FlushPolicy, HashMapShareEntryDao, ImportReport,
+    QuotaDelta, RepoError, ShareEntry, ShareEntryDaoTrait, SledShareEntryDao, Tombstone,
+};
+use sha2::{Digest, Sha256};
+use sled::Db;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+/// Prefix of the names of the trees holding an owner's entries.
+const OWNER_TREE_PREFIX: &str = "owner/";
+
+/// The secondary trees an owner's namespace keeps its entries' indexes and counters in, which are
+/// dropped along with its entries. Tombstones and quarantined values outlive the entries.
+const OWNER_INDEX_TREES: [&str; 4] = ["meta", "expiry", "stats", "owners"];
+
+/// Builds the name of the tree holding `owner`'s entries from a hash of the owner, so that the
+/// name does not depend on what the owner's peer id bytes look like.
+///
+/// # Arguments
+///
+/// * `owner` - The owner's peer id bytes, as stored in `ShareEntry::sender`.
+///
+/// # Returns
+///
+/// The name of the owner's tree.
+pub fn owner_tree_name(owner: &[u8]) -> String {
+    format!(
+        "{}{}",
+        OWNER_TREE_PREFIX,
+        hex::encode(Sha256::digest(owner))
+    )
+}
+
+/// Finds the namespace a stored key belongs to: the owner's tree for an owner-scoped key, and
+/// the default trees, named by the empty string, for a plain one.
+fn key_namespace(key: &str) -> String {
+    split_owner_key(key).map_or_else(String::new, |(owner, _)| owner_tree_name(&owner))
+}
+
+/// A `ShareEntryDaoTrait` implementation over a sled database that keeps the entries of every
+/// owner, and the secondary trees indexing them, in trees of their own.
+///
+/// Owner-scoped keys are routed to the trees of the owner named in the key, so an operation on
+/// one owner's keys never reads or writes another owner's trees, and deleting or exporting an
+/// owner's entries only touches that owner's trees. Plain keys, as written before keys were
+/// owner-scoped, stay in the database's default trees until `migrate_owner_keys` moves them.
+/// Reads never create an owner's trees; the first write does.
+///
+/// Batches are written in one transaction per owner, so a batch spanning several owners is not
+/// atomic across them.
+///
+/// # Fields
+///
+/// * `db` - The Sled database instance.
+/// * `namespaces` - The DAOs over the namespaces opened so far, by tree name.
+/// * `encryption_key` - The key values are sealed with at rest, if encryption is enabled.
+/// * `flush_policy` - When writes are flushed to disk without an explicit `flush`.
+/// * `quotas` - The storage limits enforced on writes. Each namespace enforces the per-owner
+///   limits and this DAO the total across all of them.
+/// * `read_only` - Whether every write is refused with `RepoError::ReadOnly`.
+pub struct IsolatedShareEntryDao {
+    db: Db,
+    namespaces: Mutex<HashMap<String, Arc<SledShareEntryDao>>>,
+    encryption_key: Option<EncryptionKey>,
+    flush_policy: FlushPolicy,
+    quotas: DaoQuotas,
+    read_only: bool,
+}
+
+impl IsolatedShareEntryDao {
+    /// Creates a new instance of `IsolatedShareEntryDao`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - The path to the sled database.
+    /// * `encryption_key` - The key used to seal and open values, if encryption is enabled.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `IsolatedShareEntryDao` or an error.
+    pub fn new(
+        db_path: &str,
+        encryption_key: Option<EncryptionKey>,
+    ) -> Result<Self, RepoError> {
+        Self::from_db(sled::open(db_path)?, encryption_key)
+    }
+
+    /// Opens an existing sled database without writing to it or creating any tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - The path to the sled database, which must already exist.
+    /// * `encryption_key` - The key used to open values, if encryption is enabled.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the read-only `IsolatedShareEntryDao` or an error.
+    pub fn open_read_only(
+        db_path: &str,
+        encryption_key: Option<EncryptionKey>,
+    ) -> Result<Self, RepoError> {
+        if !Path::new(db_path).exists() {
+            return Err(RepoError::NoDatabase(db_path.to_string()));
+        }
+        let mut dao = Self::from_db(sled::open(db_path)?, encryption_key)?;
+        dao.read_only = true;
+        Ok(dao)
+    }
+
+    /// Wraps an opened sled database. Namespaces are opened as they are first used.
+    fn from_db(db: Db, encryption_key: Option<EncryptionKey>) -> Result<Self, RepoError> {
+        Ok(IsolatedShareEntryDao {
+            db,
+            namespaces: Mutex::new(HashMap::new()),
+            encryption_key,
+            flush_policy: FlushPolicy::default(),
+            quotas: DaoQuotas::default(),
+            read_only: false,
+        })
+    }
+
+    /// Sets when writes are flushed to disk without an explicit `flush`. Each namespace counts
+    /// its own writes towards `FlushPolicy::EveryNWrites`.
+    ///
+    /// # Arguments
+    ///
+    /// * `flush_policy` - The `FlushPolicy` to follow.
+    ///
+    /// # Returns
+    ///
+    /// The DAO, following `flush_policy`.
+    pub fn with_flush_policy(mut self, flush_policy: FlushPolicy) -> Self {
+        self.flush_policy = flush_policy;
+        self
+    }
+
+    /// Sets the storage limits enforced on writes.
+    ///
+    /// # Arguments
+    ///
+    /// * `quotas` - The `DaoQuotas` to enforce.
+    ///
+    /// # Returns
+    ///
+    /// The DAO, enforcing `quotas`.
+    pub fn with_quotas(mut self, quotas: DaoQuotas) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    /// Writes `owner`'s entries to `writer` in the format of `export`, reading only the owner's
+    /// trees.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The owner's peer id bytes.
+    /// * `writer` - The destination of the stream.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of entries exported.
+    pub fn export_owner(
+        &self,
+        owner: &[u8],
+        writer: &mut dyn Write,
+    ) -> Result<usize, RepoError> {
+        let name = owner_tree_name(owner);
+        match self.existing_namespace(&name)? {
+            Some(dao) => export_entries(&*dao, writer, self.encryption_key.as_ref()),
+            None => export_entries(
+                &HashMapShareEntryDao::new(),
+                writer,
+                self.encryption_key.as_ref(),
+            ),
+        }
+    }
+
+    /// Refuses a write if the DAO was opened read-only.
+    fn check_writable(&self) -> Result<(), RepoError> {
+        if self.read_only {
+            return Err(RepoError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Opens the DAO over the namespace `name`, creating its trees if they do not exist.
+    fn namespace(&self, name: &str) -> Result<Arc<SledShareEntryDao>, RepoError> {
+        let mut namespaces = self.namespaces.lock().unwrap();
+        if let Some(dao) = namespaces.get(name) {
+            return Ok(dao.clone());
+        }
+        let dao = if self.read_only {
+            let mut dao = SledShareEntryDao::wrap_namespace(
+                self.db.clone(),
+                name,
+                self.encryption_key.clone(),
+            )?;
+            dao.indexed = dao.stats_current()? && dao.owner_index_ready()?;
+            dao.read_only = true;
+            dao
+        } else {
+            SledShareEntryDao::from_namespace(self.db.clone(), name, self.encryption_key.clone())?
+                .with_flush_policy(self.flush_policy)
+                .with_quotas(DaoQuotas {
+                    max_total_bytes: None,
+                    ..self.quotas
+                })
+        };
+        let dao = Arc::new(dao);
+        namespaces.insert(name.to_string(), dao.clone());
+        Ok(dao)
+    }
+
+    /// Opens the DAO over the namespace `name` if it has been written to.
+    fn existing_namespace(
+        &self,
+        name: &str,
+    ) -> Result<Option<Arc<SledShareEntryDao>>, RepoError> {
+        let exists = name.is_empty()
+            || self.namespaces.lock().unwrap().contains_key(name)
+            || self
+                .db
+                .tree_names()
+                .iter()
+                .any(|tree| tree == name.as_bytes());
+        exists.then(|| self.namespace(name)).transpose()
+    }
+
+    /// Opens the DAO over the namespace `key` belongs to, creating its trees if needed.
+    fn route(&self, key: &str) -> Result<Arc<SledShareEntryDao>, RepoError> {
+        self.namespace(&key_namespace(key))
+    }
+
+    /// Opens the DAO over the namespace `key` belongs to, if it has been written to.
+    fn route_existing(&self, key: &str) -> Result<Option<Arc<SledShareEntryDao>>, RepoError> {
+        self.existing_namespace(&key_namespace(key))
+    }
+
+    /// Opens the DAOs over every namespace: the default trees first, then each owner's.
+    fn all_namespaces(&self) -> Result<Vec<Arc<SledShareEntryDao>>, RepoError> {
+        let mut names = vec![String::new()];
+        for tree in self.db.tree_names() {
+            let name = String::from_utf8(tree.to_vec())?;
+            if name
+                .strip_prefix(OWNER_TREE_PREFIX)
+                .is_some_and(|hash| !hash.contains('/'))
+            {
+                names.push(name);
+            }
+        }
+        names.iter().map(|name| self.namespace(name)).collect()
+    }
+
+    /// Checks that a set of writes keeps the whole database within `max_total_bytes`. The
+    /// per-owner limits are checked by the namespaces themselves.
+    fn check_total_quota(
+        &self,
+        writes: &[(&SledShareEntryDao, &str, &ShareEntry)],
+    ) -> Result<(), RepoError> {
+        if self.quotas.max_total_bytes.is_none() {
+            return Ok(());
+        }
+        let mut delta = QuotaDelta::default();
+        for (dao, key, entry) in writes {
+            let old = dao
+                .entries
+                .get(key)?
+                .map(|value| (None, value.len() as u64));
+            let new_len = dao.encode_value(key.as_bytes(), entry)?.len();
+            delta.add(old, &entry.sender, new_len as u64);
+        }
+        let total = DaoQuotas {
+            max_total_bytes: self.quotas.max_total_bytes,
+            ..DaoQuotas::default()
+        };
+        total.check(&delta, self.stats()?.total_value_bytes, |_| Ok((0, 0)))
+    }
+
+    /// Writes a batch one namespace at a time, each in a single transaction.
+    fn write_batch(
+        &self,
+        entries: &[(String, ShareEntry)],
+        refresh: bool,
+    ) -> Result<(), RepoError> {
+        self.check_writable()?;
+        validate_batch(entries)?;
+        let mut batches: BTreeMap<String, Vec<(String, ShareEntry)>> = BTreeMap::new();
+        for (key, entry) in entries {
+            batches
+                .entry(key_namespace(key))
+                .or_default()
+                .push((key.clone(), entry.clone()));
+        }
+        let batches = batches
+            .into_iter()
+            .map(|(name, batch)| Ok((self.namespace(&name)?, batch)))
+            .collect::<Result<Vec<_>, RepoError>>()?;
+        let writes: Vec<_> = batches
+            .iter()
+            .flat_map(|(dao, batch)| {
+                batch
+                    .iter()
+                    .map(move |(key, entry)| (&**dao, key.as_str(), entry))
+            })
+            .collect();
+        self.check_total_quota(&writes)?;
+        for (dao, batch) in &batches {
+            dao.write_batch(batch, refresh)?;
+        }
+        Ok(())
+    }
+}
+
+impl ShareEntryDaoTrait for IsolatedShareEntryDao {
+    fn insert(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+        self.check_writable()?;
+        let dao = self.route(key)?;
+        self.check_total_quota(&[(&dao, key, entry)])?;
+        dao.insert(key, entry)
+    }
+
+    fn get(&self, key: &str) -> Result<Option<ShareEntry>, RepoError> {
+        match self.route_existing(key)? {
+            Some(dao) => dao.get(key),
+            None => Ok(None),
+        }
+    }
+
+    /// Merges a page from every namespace, so a page reads up to `limit` entries per owner.
+    fn get_page(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, ShareEntry)>, RepoError> {
+        let mut entries = Vec::new();
+        for dao in self.all_namespaces()? {
+            entries.extend(dao.get_page(after, limit)?);
+        }
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        entries.truncate(limit);
+        Ok(entries)
+    }
+
+    fn update(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+        let Some(dao) = self.route_existing(key)? else {
+            return Err(RepoError::KeyNotFound(key.to_string()));
+        };
+        self.check_writable()?;
+        self.check_total_quota(&[(&dao, key, entry)])?;
+        dao.update(key, entry)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), RepoError> {
+        self.check_writable()?;
+        match self.route_existing(key)? {
+            Some(dao) => dao.delete(key),
+            None => Ok(()),
+        }
+    }
+
+    fn migrate_all(&self) -> Result<usize, RepoError> {
+        self.check_writable()?;
+        let mut migrated = 0;
+        for dao in self.all_namespaces()? {
+            migrated += dao.migrate_all()?;
+        }
+        Ok(migrated)
+    }
+
+    fn expired_keys(&self, now: u64) -> Result<Vec<String>, RepoError> {
+        let mut keys = Vec::new();
+        for dao in self.all_namespaces()? {
+            keys.extend(dao.expired_keys(now)?);
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: &ShareEntry,
+        new: &ShareEntry,
+    ) -> Result<bool, RepoError> {
+        self.check_writable()?;
+        match self.route_existing(key)? {
+            Some(dao) => dao.compare_and_swap(key, expected, new),
+            None => Ok(false),
+        }
+    }
+
+    /// Merges a page of keys from every namespace.
+    fn keys_page(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>, RepoError> {
+        let mut keys = Vec::new();
+        for dao in self.all_namespaces()? {
+            keys.extend(dao.keys_page(after, limit)?);
+        }
+        keys.sort();
+        keys.truncate(limit);
+        Ok(keys)
+    }
+
+    /// Reads only the owner's trees when `prefix` names an owner's namespace.
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, RepoError> {
+        if split_owner_key(prefix).is_some() {
+            return match self.route_existing(prefix)? {
+                Some(dao) => dao.keys_with_prefix(prefix),
+                None => Ok(Vec::new()),
+            };
+        }
+        let mut keys = Vec::new();
+        for dao in self.all_namespaces()? {
+            keys.extend(dao.keys_with_prefix(prefix)?);
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Moves every entry left in the default trees into its owner's trees: plain keys into their
+    /// owner's namespace, and owner-scoped keys written before isolation was enabled as they are.
+    /// Their tombstones move with them.
+    fn migrate_owner_keys(&self) -> Result<usize, RepoError> {
+        self.check_writable()?;
+        let shared = self.namespace("")?;
+        let mut moved = 0;
+        for key in shared.keys()? {
+            // an entry that cannot be read is left for the integrity scan to quarantine
+            let Ok(Some(entry)) = shared.get(&key) else {
+                continue;
+            };
+            let stored_key = match split_owner_key(&key) {
+                Some(_) => key.clone(),
+                None => owner_key(&entry.sender, &key),
+            };
+            let dao = self.route(&stored_key)?;
+            if dao.get(&stored_key)?.is_none() {
+                dao.insert(&stored_key, &entry)?;
+                moved += 1;
+            }
+            shared.delete(&key)?;
+        }
+        for item in shared.tombstones.iter() {
+            let (key, value) = item?;
+            let key = String::from_utf8(key.to_vec())?;
+            if split_owner_key(&key).is_some() {
+                self.route(&key)?.tombstones.insert(key.as_bytes(), value)?;
+                shared.tombstones.remove(key.as_bytes())?;
+            }
+        }
+        Ok(moved)
+    }
+
+    /// Lists the owner's keys from the owner's trees and any left in the default trees.
+    fn keys_by_owner(&self, owner: &[u8]) -> Result<Vec<String>, RepoError> {
+        let mut keys = self.namespace("")?.keys_by_owner(owner)?;
+        if let Some(dao) = self.existing_namespace(&owner_tree_name(owner))? {
+            keys.extend(dao.keys_by_owner(owner)?);
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Drops the trees holding the owner's entries and their indexes instead of deleting the
+    /// entries one at a time. Entries of the owner left in the default trees are deleted as well.
+    fn delete_by_owner(&self, owner: &[u8]) -> Result<Vec<String>, RepoError> {
+        self.check_writable()?;
+        let shared = self.namespace("")?;
+        let mut deleted = shared.delete_by_owner(owner)?;
+        let name = owner_tree_name(owner);
+        if let Some(dao) = self.existing_namespace(&name)? {
+            deleted.extend(dao.keys()?);
+            self.namespaces.lock().unwrap().remove(&name);
+            drop(dao);
+            self.db.drop_tree(&name)?;
+            for tree in OWNER_INDEX_TREES {
+                self.db.drop_tree(format!("{}/{}", name, tree))?;
+            }
+            shared.wrote()?;
+        }
+        deleted.sort();
+        Ok(deleted)
+    }
+
+    fn insert_batch(&self, entries: &[(String, ShareEntry)]) -> Result<(), RepoError> {
+        self.write_batch(entries, false)
+    }
+
+    fn apply_refresh_batch(&self, updates: &[(String, ShareEntry)]) -> Result<(), RepoError> {
+        self.write_batch(updates, true)
+    }
+
+    /// Adds up the counters of every namespace.
+    fn stats(&self) -> Result<DaoStats, RepoError> {
+        let mut stats = DaoStats::default();
+        let mut per_owner: BTreeMap<Vec<u8>, u64> = BTreeMap::new();
+        for dao in self.all_namespaces()? {
+            let namespace_stats = dao.stats()?;
+            stats.entries += namespace_stats.entries;
+            stats.total_value_bytes += namespace_stats.total_value_bytes;
+            for (owner, count) in namespace_stats.per_owner {
+                *per_owner.entry(owner).or_default() += count;
+            }
+        }
+        stats.per_owner = per_owner.into_iter().collect();
+        Ok(stats)
+    }
+
+    /// Writes every entry to `writer`, sealing the stream with the at-rest key if one is set.
+    fn export(&self, writer: &mut dyn Write) -> Result<usize, RepoError> {
+        export_entries(self, writer, self.encryption_key.as_ref())
+    }
+
+    /// Reads a backup stream, opening it with the at-rest key if it is encrypted.
+    fn import(
+        &self,
+        reader: &mut dyn Read,
+        conflict: ConflictPolicy,
+    ) -> Result<ImportReport, RepoError> {
+        self.check_writable()?;
+        import_entries(self, reader, conflict, self.encryption_key.as_ref())
+    }
+
+    fn put_tombstone(&self, key: &str, tombstone: &Tombstone) -> Result<(), RepoError> {
+        self.check_writable()?;
+        self.route(key)?.put_tombstone(key, tombstone)
+    }
+
+    fn get_tombstone(&self, key: &str) -> Result<Option<Tombstone>, RepoError> {
+        match self.route_existing(key)? {
+            Some(dao) => dao.get_tombstone(key),
+            None => Ok(None),
+        }
+    }
+
+    fn remove_tombstone(&self, key: &str) -> Result<(), RepoError> {
+        self.check_writable()?;
+        match self.route_existing(key)? {
+            Some(dao) => dao.remove_tombstone(key),
+            None => Ok(()),
+        }
+    }
+
+    fn purge_tombstones(&self, now: u64) -> Result<usize, RepoError> {
+        self.check_writable()?;
+        let mut purged = 0;
+        for dao in self.all_namespaces()? {
+            purged += dao.purge_tombstones(now)?;
+        }
+        Ok(purged)
+    }
+
+    fn quarantine(&self, key: &str) -> Result<bool, RepoError> {
+        self.check_writable()?;
+        match self.route_existing(key)? {
+            Some(dao) => dao.quarantine(key),
+            None => Ok(false),
+        }
+    }
+
+    fn is_quarantined(&self, key: &str) -> Result<bool, RepoError> {
+        match self.route_existing(key)? {
+            Some(dao) => dao.is_quarantined(key),
+            None => Ok(false),
+        }
+    }
+
+    fn quarantined_keys(&self) -> Result<Vec<String>, RepoError> {
+        let mut keys = Vec::new();
+        for dao in self.all_namespaces()? {
+            keys.extend(dao.quarantined_keys()?);
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Flushes the whole database, every namespace included.
+    fn flush(&self) -> Result<(), RepoError> {
+        if self.read_only {
+            return Ok(());
+        }
+        for dao in self.namespaces.lock().unwrap().values() {
+            dao.unflushed_writes.store(0, Ordering::Relaxed);
+        }
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testsuite::run_conformance;
+    use super::*;
+
+    fn temporary_db() -> Db {
+        sled::Config::new().temporary(true).open().unwrap()
+    }
+
+    fn temporary_dao() -> IsolatedShareEntryDao {
+        IsolatedShareEntryDao::from_db(temporary_db(), None).unwrap()
+    }
+
+    fn owned_entry(owner: &[u8], share: Vec<u8>) -> ShareEntry {
+        ShareEntry {
+            share: (1, share),
+            sender: owner.to_vec(),
+            threshold: 2,
+            ..Default::default()
+        }
+    }
+
+    /// Lists the keys stored in the entries tree of `owner`, read straight from sled.
+    fn tree_keys(db: &Db, owner: &[u8]) -> Vec<String> {
+        db.open_tree(owner_tree_name(owner))
+            .unwrap()
+            .iter()
+            .keys()
+            .map(|key| String::from_utf8(key.unwrap().to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_isolated_conformance() {
+        run_conformance(temporary_dao);
+    }
+
+    #[test]
+    fn test_isolated_conformance_with_encryption() {
+        run_conformance(|| {
+            IsolatedShareEntryDao::from_db(
+                temporary_db(),
+                Some(EncryptionKey::from_bytes([4u8; 32])),
+            )
+            .unwrap()
+        });
+    }
+
+    #[test]
+    fn test_owner_never_observes_another_owners_keys() {
+        let db = temporary_db();
+        let dao = IsolatedShareEntryDao::from_db(db.clone(), None).unwrap();
+        let (alice, bob) = (b"alice".as_slice(), b"bob".as_slice());
+        dao.insert_owned("secret", &owned_entry(bob, vec![1]))
+            .unwrap();
+
+        // keys alice crafts to look like bob's stay in alice's namespace
+        let crafted = [
+            owner_key(bob, "secret"),
+            format!("../{}", owner_key(bob, "secret")),
+            "/secret".to_string(),
+            String::new(),
+        ];
+        for key in &crafted {
+            dao.insert_owned(key, &owned_entry(alice, vec![2])).unwrap();
+            assert_eq!(
+                dao.get_owned(alice, key).unwrap(),
+                Some(owned_entry(alice, vec![2]))
+            );
+        }
+        for key in &crafted {
+            dao.delete_owned(alice, key).unwrap();
+        }
+        assert!(dao.get_owned(alice, "secret").unwrap().is_none());
+        assert!(dao.keys_by_owner(alice).unwrap().is_empty());
+        assert!(dao
+            .keys_with_prefix(&owner_key(alice, ""))
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            dao.get_owned(bob, "secret").unwrap(),
+            Some(owned_entry(bob, vec![1]))
+        );
+        assert_eq!(tree_keys(&db, bob), vec![owner_key(bob, "secret")]);
+        assert!(tree_keys(&db, alice).is_empty());
+        assert!(db.get(owner_key(bob, "secret")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_reads_do_not_create_owner_trees() {
+        let db = temporary_db();
+        let dao = IsolatedShareEntryDao::from_db(db.clone(), None).unwrap();
+
+        assert!(dao.get_owned(b"alice", "key").unwrap().is_none());
+        assert!(dao
+            .get_tombstone(&owner_key(b"alice", "key"))
+            .unwrap()
+            .is_none());
+        dao.delete_owned(b"alice", "key").unwrap();
+        assert!(dao.keys().unwrap().is_empty());
+        assert!(!db
+            .tree_names()
+            .iter()
+            .any(|tree| tree.starts_with(OWNER_TREE_PREFIX.as_bytes())));
+    }
+
+    #[test]
+    fn test_deleting_an_owner_drops_only_its_trees() {
+        let db = temporary_db();
+        let dao = IsolatedShareEntryDao::from_db(db.clone(), None).unwrap();
+        for i in 0..3u8 {
+            dao.insert_owned(&format!("a{}", i), &owned_entry(b"alice", vec![i]))
+                .unwrap();
+            dao.insert_owned(&format!("b{}", i), &owned_entry(b"bob", vec![i]))
+                .unwrap();
+        }
+
+        let deleted = dao.delete_by_owner(b"alice").unwrap();
+        assert_eq!(
+            deleted,
+            vec![
+                owner_key(b"alice", "a0"),
+                owner_key(b"alice", "a1"),
+                owner_key(b"alice", "a2")
+            ]
+        );
+        let alice_tree = owner_tree_name(b"alice");
+        let trees = db.tree_names();
+        assert!(!trees.iter().any(|tree| tree == alice_tree.as_bytes()));
+        for index in OWNER_INDEX_TREES {
+            let name = format!("{}/{}", alice_tree, index);
+            assert!(!trees.iter().any(|tree| tree == name.as_bytes()));
+        }
+        assert_eq!(tree_keys(&db, b"bob").len(), 3);
+
+        assert!(dao.keys_by_owner(b"alice").unwrap().is_empty());
+        assert_eq!(dao.keys_by_owner(b"bob").unwrap().len(), 3);
+        assert_eq!(
+            dao.get_owned(b"bob", "b1").unwrap(),
+            Some(owned_entry(b"bob", vec![1]))
+        );
+        let stats = dao.stats().unwrap();
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.per_owner, vec![(b"bob".to_vec(), 3)]);
+
+        // the owner starts over in fresh trees
+        dao.insert_owned("a0", &owned_entry(b"alice", vec![9]))
+            .unwrap();
+        assert_eq!(dao.stats().unwrap().entries, 4);
+    }
+
+    #[test]
+    fn test_default_trees_are_moved_into_owner_trees() {
+        let db = temporary_db();
+        let plain = SledShareEntryDao::from_db(db.clone(), None).unwrap();
+        plain
+            .insert_owned("scoped", &owned_entry(b"alice", vec![1]))
+            .unwrap();
+        plain
+            .insert("plain", &owned_entry(b"bob", vec![2]))
+            .unwrap();
+        plain
+            .put_tombstone(
+                &owner_key(b"alice", "gone"),
+                &Tombstone {
+                    owner: b"alice".to_vec(),
+                    deleted_at: 1,
+                    expires_at: u64::MAX,
+                    reason: "test".to_string(),
+                },
+            )
+            .unwrap();
+        drop(plain);
+
+        let dao = IsolatedShareEntryDao::from_db(db.clone(), None).unwrap();
+        assert_eq!(dao.migrate_owner_keys().unwrap(), 2);
+        assert_eq!(dao.migrate_owner_keys().unwrap(), 0);
+        assert!(db.is_empty());
+        assert_eq!(
+            tree_keys(&db, b"alice"),
+            vec![owner_key(b"alice", "scoped")]
+        );
+        assert_eq!(tree_keys(&db, b"bob"), vec![owner_key(b"bob", "plain")]);
+        assert!(dao
+            .get_tombstone(&owner_key(b"alice", "gone"))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_total_quota_spans_every_owner() {
+        let dao = temporary_dao().with_quotas(DaoQuotas {
+            max_entries_per_owner: Some(1),
+            ..DaoQuotas::default()
+        });
+        dao.insert_owned("a", &owned_entry(b"alice", vec![1]))
+            .unwrap();
+        dao.insert_owned("b", &owned_entry(b"bob", vec![1]))
+            .unwrap();
+        assert!(dao
+            .insert_owned("a2", &owned_entry(b"alice", vec![1]))
+            .is_err());
+
+        let total = dao.stats().unwrap().total_value_bytes;
+        let dao = temporary_dao().with_quotas(DaoQuotas {
+            max_total_bytes: Some(total),
+            ..DaoQuotas::default()
+        });
+        dao.insert_owned("a", &owned_entry(b"alice", vec![1]))
+            .unwrap();
+        dao.insert_owned("b", &owned_entry(b"bob", vec![1]))
+            .unwrap();
+        let err = dao
+            .insert_owned("c", &owned_entry(b"carol", vec![1]))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            RepoError::QuotaExceeded {
+                limit: "max_total_bytes",
+                ..
+            }
+        ));
+    }
+}
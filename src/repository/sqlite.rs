@@ -0,0 +1,658 @@
+#[cfg(feature = "sss16")]
+use super::ShareField;
+use super::{check_refresh, validate_batch, RepoError, ShareEntry, ShareEntryDaoTrait, Tombstone};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::sync::Mutex;
+
+/// Schema of the `shares`, `tombstones` and `quarantine` tables. Quarantined rows keep the
+/// columns of `shares`, without its constraints, so that any row can be moved there.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS shares (
+        key TEXT PRIMARY KEY,
+        share BLOB NOT NULL,
+        share_index INTEGER NOT NULL,
+        sender BLOB NOT NULL,
+        threshold INTEGER NOT NULL,
+        epoch INTEGER NOT NULL DEFAULT 0,
+        last_refreshed_unix INTEGER NOT NULL DEFAULT 0,
+        expires_at INTEGER,
+        refresh_digest BLOB,
+        refresh_interval_secs INTEGER,
+        readers BLOB,
+        relay_grant BLOB,
+        share_field INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS shares_expires_at ON shares (expires_at)
+        WHERE expires_at IS NOT NULL;
+    CREATE TABLE IF NOT EXISTS tombstones (
+        key TEXT PRIMARY KEY,
+        owner BLOB NOT NULL,
+        deleted_at INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL,
+        reason TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS quarantine (
+        key TEXT PRIMARY KEY,
+        share BLOB,
+        share_index INTEGER,
+        sender BLOB,
+        threshold INTEGER,
+        epoch INTEGER,
+        last_refreshed_unix INTEGER,
+        expires_at INTEGER,
+        refresh_digest BLOB,
+        refresh_interval_secs INTEGER,
+        readers BLOB,
+        relay_grant BLOB,
+        share_field INTEGER
+    );
+";
+
+/// Columns added to the `shares` and `quarantine` tables after they were first released, with
+/// their types, so that older databases can be brought up to date.
+const ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("refresh_digest", "BLOB"),
+    ("refresh_interval_secs", "INTEGER"),
+    ("readers", "BLOB"),
+    ("relay_grant", "BLOB"),
+    ("share_field", "INTEGER"),
+];
+
+/// The tables holding whole entries, which gain the columns of `ADDED_COLUMNS`.
+const ENTRY_TABLES: &[&str] = &["shares", "quarantine"];
+
+/// Columns selected by every query that reads a whole entry.
+const ENTRY_COLUMNS: &str = "key, share, share_index, sender, threshold, expires_at, epoch, \
+     last_refreshed_unix, refresh_digest, refresh_interval_secs, readers, relay_grant, \
+     share_field";
+
+/// A `ShareEntryDaoTrait` implementation using SQLite.
+///
+/// Entries are stored one row per key in a `shares` table, so the database can be inspected and
+/// backed up with standard SQLite tooling. The database is opened in WAL mode.
+///
+/// # Fields
+///
+/// * `conn` - The SQLite connection, serialised behind a mutex.
+pub struct SqliteShareEntryDao {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteShareEntryDao {
+    /// Creates a new instance of `SqliteShareEntryDao`, creating the schema if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - The path to the SQLite database file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `SqliteShareEntryDao` or an error.
+    pub fn new(db_path: &str) -> Result<Self, RepoError> {
+        Self::from_connection(Connection::open(db_path)?)
+    }
+
+    /// Wraps an opened connection, enabling WAL mode and creating the schema. Tables created
+    /// by older versions gain the columns added since (see `ADDED_COLUMNS`).
+    fn from_connection(conn: Connection) -> Result<Self, RepoError> {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(SCHEMA)?;
+        for table in ENTRY_TABLES {
+            for (column, column_type) in ADDED_COLUMNS {
+                let present: bool = conn.query_row(
+                    "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+                    params![table, column],
+                    |row| row.get(0),
+                )?;
+                if !present {
+                    conn.execute_batch(&format!(
+                        "ALTER TABLE {} ADD COLUMN {} {}",
+                        table, column, column_type
+                    ))?;
+                }
+            }
+        }
+        Ok(SqliteShareEntryDao {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+/// Maps a row selected with `ENTRY_COLUMNS` to its key and entry.
+fn row_to_entry(row: &Row) -> rusqlite::Result<(String, ShareEntry)> {
+    let expires_at: Option<i64> = row.get(5)?;
+    let refresh_digest: Option<Vec<u8>> = row.get(8)?;
+    let refresh_interval_secs: Option<i64> = row.get(9)?;
+    let readers: Option<Vec<u8>> = row.get(10)?;
+    let readers = match readers {
+        Some(readers) => bincode::deserialize(&readers)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(10, Type::Blob, e))?,
+        None => vec![],
+    };
+    let relay_grant: Option<Vec<u8>> = row.get(11)?;
+    let relay_grant = relay_grant
+        .map(|grant| bincode::deserialize(&grant))
+        .transpose()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(11, Type::Blob, e))?;
+    // a share split over GF(2^16) cannot be read without the `sss16` feature
+    #[cfg(not(feature = "sss16"))]
+    if let Some(bits) = row.get::<_, Option<i64>>(12)? {
+        return Err(rusqlite::Error::IntegralValueOutOfRange(12, bits));
+    }
+    Ok((
+        row.get(0)?,
+        ShareEntry {
+            share: (row.get(2)?, row.get(1)?),
+            #[cfg(feature = "sss16")]
+            field: share_field(row.get(12)?)?,
+            sender: row.get(3)?,
+            threshold: row.get::<_, i64>(4)? as u64,
+            expires_at: expires_at.map(|t| t as u64),
+            epoch: row.get::<_, i64>(6)? as u64,
+            last_refreshed_unix: row.get::<_, i64>(7)? as u64,
+            refresh_digest: refresh_digest.and_then(|digest| digest.try_into().ok()),
+            refresh_interval_secs: refresh_interval_secs.map(|secs| secs as u64),
+            readers,
+            relay_grant,
+        },
+    ))
+}
+
+/// Encodes the readers of an entry for the `readers` column, leaving it empty if there are none.
+fn readers_blob(entry: &ShareEntry) -> Option<Vec<u8>> {
+    if entry.readers.is_empty() {
+        return None;
+    }
+    Some(bincode::serialize(&entry.readers).expect("readers to serialize"))
+}
+
+/// Encodes the relay grant of an entry for the `relay_grant` column, if it has one.
+fn relay_grant_blob(entry: &ShareEntry) -> Option<Vec<u8>> {
+    let grant = entry.relay_grant.as_ref()?;
+    Some(bincode::serialize(grant).expect("relay grant to serialize"))
+}
+
+/// Decodes the `share_field` column, the bit width of the field the share was split over, empty
+/// for GF(2^8).
+#[cfg(feature = "sss16")]
+fn share_field(bits: Option<i64>) -> rusqlite::Result<ShareField> {
+    match bits {
+        None => Ok(ShareField::Gf256),
+        Some(16) => Ok(ShareField::Gf65536),
+        Some(bits) => Err(rusqlite::Error::IntegralValueOutOfRange(12, bits)),
+    }
+}
+
+/// Encodes the field of an entry for the `share_field` column as its bit width, leaving it empty
+/// for GF(2^8), which rows written before the column was added were split over.
+#[cfg(feature = "sss16")]
+fn share_field_bits(entry: &ShareEntry) -> Option<i64> {
+    match entry.field {
+        ShareField::Gf256 => None,
+        ShareField::Gf65536 => Some(16),
+    }
+}
+
+/// Encodes the field of an entry for the `share_field` column, always GF(2^8) and so left empty
+/// without the `sss16` feature.
+#[cfg(not(feature = "sss16"))]
+fn share_field_bits(_entry: &ShareEntry) -> Option<i64> {
+    None
+}
+
+/// Inserts or replaces the row of `key`.
+fn upsert_row(conn: &Connection, key: &str, entry: &ShareEntry) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT INTO shares
+            (key, share, share_index, sender, threshold, expires_at, epoch, last_refreshed_unix,
+             refresh_digest, refresh_interval_secs, readers, relay_grant, share_field)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+         ON CONFLICT (key) DO UPDATE SET
+            share = excluded.share,
+            share_index = excluded.share_index,
+            sender = excluded.sender,
+            threshold = excluded.threshold,
+            expires_at = excluded.expires_at,
+            epoch = excluded.epoch,
+            last_refreshed_unix = excluded.last_refreshed_unix,
+            refresh_digest = excluded.refresh_digest,
+            refresh_interval_secs = excluded.refresh_interval_secs,
+            readers = excluded.readers,
+            relay_grant = excluded.relay_grant,
+            share_field = excluded.share_field",
+        params![
+            key,
+            entry.share.1,
+            entry.share.0,
+            entry.sender,
+            entry.threshold as i64,
+            entry.expires_at.map(|t| t as i64),
+            entry.epoch as i64,
+            entry.last_refreshed_unix as i64,
+            entry
+                .refresh_digest
+                .as_ref()
+                .map(|digest| digest.as_slice()),
+            entry.refresh_interval_secs.map(|secs| secs as i64),
+            readers_blob(entry),
+            relay_grant_blob(entry),
+            share_field_bits(entry),
+        ],
+    )
+}
+
+/// Replaces the row of `key` if it exists, returning the number of rows changed.
+fn update_row(conn: &Connection, key: &str, entry: &ShareEntry) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE shares
+         SET share = ?2, share_index = ?3, sender = ?4, threshold = ?5, expires_at = ?6,
+             epoch = ?7, last_refreshed_unix = ?8, refresh_digest = ?9,
+             refresh_interval_secs = ?10, readers = ?11, relay_grant = ?12, share_field = ?13
+         WHERE key = ?1",
+        params![
+            key,
+            entry.share.1,
+            entry.share.0,
+            entry.sender,
+            entry.threshold as i64,
+            entry.expires_at.map(|t| t as i64),
+            entry.epoch as i64,
+            entry.last_refreshed_unix as i64,
+            entry
+                .refresh_digest
+                .as_ref()
+                .map(|digest| digest.as_slice()),
+            entry.refresh_interval_secs.map(|secs| secs as i64),
+            readers_blob(entry),
+            relay_grant_blob(entry),
+            share_field_bits(entry),
+        ],
+    )
+}
+
+/// Reads the entry stored under `key`, if any.
+fn select_entry(conn: &Connection, key: &str) -> rusqlite::Result<Option<ShareEntry>> {
+    let entry = conn
+        .query_row(
+            &format!("SELECT {} FROM shares WHERE key = ?1", ENTRY_COLUMNS),
+            params![key],
+            row_to_entry,
+        )
+        .optional()?;
+    Ok(entry.map(|(_, entry)| entry))
+}
+
+impl ShareEntryDaoTrait for SqliteShareEntryDao {
+    /// Inserts a `ShareEntry` into the SQLite database, replacing any entry under the same key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key associated with the `ShareEntry`.
+    /// * `entry` - The `ShareEntry` to be inserted.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success or failure of the operation.
+    fn insert(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+        upsert_row(&self.conn.lock().unwrap(), key, entry)?;
+        Ok(())
+    }
+
+    /// Retrieves a `ShareEntry` from the SQLite database by its key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the `ShareEntry` to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an `Option<ShareEntry>`. `None` if the key does not exist.
+    fn get(&self, key: &str) -> Result<Option<ShareEntry>, RepoError> {
+        Ok(select_entry(&self.conn.lock().unwrap(), key)?)
+    }
+
+    /// Retrieves a page of entries in key order using the primary key index.
+    fn get_page(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, ShareEntry)>, RepoError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM shares WHERE ?1 IS NULL OR key > ?1 ORDER BY key LIMIT ?2",
+            ENTRY_COLUMNS
+        ))?;
+        let entries = statement
+            .query_map(params![after, limit as i64], row_to_entry)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    /// Updates an existing `ShareEntry` in the SQLite database.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the `ShareEntry` to update.
+    /// * `entry` - The new `ShareEntry` data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success, or an error if the key does not exist.
+    fn update(&self, key: &str, entry: &ShareEntry) -> Result<(), RepoError> {
+        let updated = update_row(&self.conn.lock().unwrap(), key, entry)?;
+        if updated == 0 {
+            return Err(RepoError::KeyNotFound(key.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Replaces the entry under `key` if it still equals `expected`, inside one transaction.
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: &ShareEntry,
+        new: &ShareEntry,
+    ) -> Result<bool, RepoError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        if select_entry(&tx, key)?.as_ref() != Some(expected) {
+            return Ok(false);
+        }
+        update_row(&tx, key, new)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Inserts the whole batch inside one transaction.
+    fn insert_batch(&self, entries: &[(String, ShareEntry)]) -> Result<(), RepoError> {
+        validate_batch(entries)?;
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for (key, entry) in entries {
+            upsert_row(&tx, key, entry)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Checks and applies the whole batch inside one transaction, rolling it back if any key is
+    /// missing or changes owner.
+    fn apply_refresh_batch(&self, updates: &[(String, ShareEntry)]) -> Result<(), RepoError> {
+        validate_batch(updates)?;
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for (key, entry) in updates {
+            check_refresh(key, select_entry(&tx, key)?.as_ref(), entry)?;
+            update_row(&tx, key, entry)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Deletes a `ShareEntry` from the SQLite database by its key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the `ShareEntry` to delete.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating the success or failure of the operation.
+    fn delete(&self, key: &str) -> Result<(), RepoError> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM shares WHERE key = ?1", params![key])?;
+        Ok(())
+    }
+
+    /// Lists the keys starting with `prefix` without reading the share columns.
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, RepoError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare("SELECT key FROM shares WHERE substr(key, 1, length(?1)) = ?1 ORDER BY key")?;
+        let keys = statement
+            .query_map(params![prefix], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(keys)
+    }
+
+    /// Lists a page of keys in key order without reading the share columns.
+    fn keys_page(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>, RepoError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare("SELECT key FROM shares WHERE ?1 IS NULL OR key > ?1 ORDER BY key LIMIT ?2")?;
+        let keys = statement
+            .query_map(params![after, limit as i64], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(keys)
+    }
+
+    /// Lists the keys registered by `owner` without reading the share columns.
+    fn keys_by_owner(&self, owner: &[u8]) -> Result<Vec<String>, RepoError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement =
+            conn.prepare("SELECT key FROM shares WHERE sender = ?1 ORDER BY key")?;
+        let keys = statement
+            .query_map(params![owner], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(keys)
+    }
+
+    /// Deletes every entry registered by `owner` in a single statement.
+    fn delete_by_owner(&self, owner: &[u8]) -> Result<Vec<String>, RepoError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("DELETE FROM shares WHERE sender = ?1 RETURNING key")?;
+        let mut deleted = statement
+            .query_map(params![owner], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        deleted.sort_unstable();
+        Ok(deleted)
+    }
+
+    fn put_tombstone(&self, key: &str, tombstone: &Tombstone) -> Result<(), RepoError> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO tombstones (key, owner, deleted_at, expires_at, reason)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                key,
+                tombstone.owner,
+                tombstone.deleted_at as i64,
+                tombstone.expires_at as i64,
+                tombstone.reason
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn get_tombstone(&self, key: &str) -> Result<Option<Tombstone>, RepoError> {
+        let tombstone = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT owner, deleted_at, expires_at, reason FROM tombstones WHERE key = ?1",
+                params![key],
+                |row| {
+                    Ok(Tombstone {
+                        owner: row.get(0)?,
+                        deleted_at: row.get::<_, i64>(1)? as u64,
+                        expires_at: row.get::<_, i64>(2)? as u64,
+                        reason: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(tombstone)
+    }
+
+    fn remove_tombstone(&self, key: &str) -> Result<(), RepoError> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM tombstones WHERE key = ?1", params![key])?;
+        Ok(())
+    }
+
+    fn purge_tombstones(&self, now: u64) -> Result<usize, RepoError> {
+        let purged = self.conn.lock().unwrap().execute(
+            "DELETE FROM tombstones WHERE expires_at <= ?1",
+            params![now as i64],
+        )?;
+        Ok(purged)
+    }
+
+    /// Moves the row under `key` into the `quarantine` table in one transaction.
+    fn quarantine(&self, key: &str) -> Result<bool, RepoError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            &format!(
+                "INSERT OR REPLACE INTO quarantine ({columns}) \
+                 SELECT {columns} FROM shares WHERE key = ?1",
+                columns = ENTRY_COLUMNS
+            ),
+            params![key],
+        )?;
+        let moved = tx.execute("DELETE FROM shares WHERE key = ?1", params![key])?;
+        tx.commit()?;
+        Ok(moved > 0)
+    }
+
+    fn is_quarantined(&self, key: &str) -> Result<bool, RepoError> {
+        let quarantined = self.conn.lock().unwrap().query_row(
+            "SELECT COUNT(*) > 0 FROM quarantine WHERE key = ?1",
+            params![key],
+            |row| row.get(0),
+        )?;
+        Ok(quarantined)
+    }
+
+    fn quarantined_keys(&self) -> Result<Vec<String>, RepoError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT key FROM quarantine ORDER BY key")?;
+        let keys = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(keys)
+    }
+
+    /// Lists the keys of all expired entries using the index on `expires_at`.
+    fn expired_keys(&self, now: u64) -> Result<Vec<String>, RepoError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT key FROM shares WHERE expires_at IS NOT NULL AND expires_at <= ?1 ORDER BY key",
+        )?;
+        let keys = statement
+            .query_map(params![now as i64], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testsuite::run_conformance;
+    use super::super::RelayGrant;
+    use super::*;
+
+    fn temporary_dao() -> SqliteShareEntryDao {
+        SqliteShareEntryDao::from_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn entry() -> ShareEntry {
+        ShareEntry {
+            share: (1, vec![0, 1, 2, 255]),
+            sender: vec![9, 8, 7],
+            threshold: 3,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_conformance() {
+        run_conformance(temporary_dao);
+    }
+
+    #[test]
+    fn test_reopen_keeps_entries_in_wal_mode() {
+        let path = std::env::temp_dir().join(format!("shard-{}.sqlite", rand::random::<u64>()));
+        let db_path = path.to_str().unwrap();
+
+        SqliteShareEntryDao::new(db_path)
+            .unwrap()
+            .insert("key", &entry())
+            .unwrap();
+        let dao = SqliteShareEntryDao::new(db_path).unwrap();
+        assert_eq!(dao.get("key").unwrap().unwrap().share, entry().share);
+
+        let mode: String = dao
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        drop(dao);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", db_path, suffix));
+        }
+    }
+    #[test]
+    fn test_older_tables_gain_the_added_columns() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE shares (
+                key TEXT PRIMARY KEY,
+                share BLOB NOT NULL,
+                share_index INTEGER NOT NULL,
+                sender BLOB NOT NULL,
+                threshold INTEGER NOT NULL,
+                epoch INTEGER NOT NULL DEFAULT 0,
+                last_refreshed_unix INTEGER NOT NULL DEFAULT 0,
+                expires_at INTEGER
+            );
+            INSERT INTO shares (key, share, share_index, sender, threshold)
+                VALUES ('old', X'01', 1, X'09', 3);",
+        )
+        .unwrap();
+
+        let dao = SqliteShareEntryDao::from_connection(conn).unwrap();
+        let old = dao.get("old").unwrap().unwrap();
+        assert_eq!(
+            (old.refresh_digest, old.refresh_interval_secs),
+            (None, None)
+        );
+        assert!(old.readers.is_empty());
+        assert_eq!(old.relay_grant, None);
+        #[cfg(feature = "sss16")]
+        assert_eq!(old.field, ShareField::Gf256);
+        let refreshed = ShareEntry {
+            refresh_digest: Some([3; 32]),
+            refresh_interval_secs: Some(600),
+            readers: vec![vec![1, 2], vec![3]],
+            relay_grant: Some(RelayGrant {
+                public_key: vec![4],
+                signature: vec![5, 6],
+            }),
+            ..entry()
+        };
+        dao.insert("key", &refreshed).unwrap();
+        assert_eq!(dao.get("key").unwrap(), Some(refreshed));
+    }
+
+    #[cfg(feature = "sss16")]
+    #[test]
+    fn test_share_field_roundtrips() {
+        let dao = temporary_dao();
+        let wide = ShareEntry {
+            share: (300, vec![0, 1, 0, 2]),
+            field: ShareField::Gf65536,
+            ..entry()
+        };
+        dao.insert("wide", &wide).unwrap();
+        assert_eq!(dao.get("wide").unwrap(), Some(wide));
+    }
+}
@@ -0,0 +1,507 @@
+use super::{owner_key, DaoStats, RepoError, ShareEntry, ShareEntryDaoTrait, Tombstone};
+use std::time::Duration;
+
+/// The number of threads `check_concurrent_access` writes from.
+const THREADS: u64 = 8;
+
+/// The number of writes each thread makes in `check_concurrent_access`.
+const WRITES_PER_THREAD: u64 = 25;
+
+/// Runs every conformance check against fresh stores built by `make`.
+///
+/// Each backend's tests call this so that all stores agree on the semantics of
+/// `ShareEntryDaoTrait`. Every check is given a new, empty store, and panics on the first
+/// divergence.
+///
+/// # Arguments
+///
+/// * `make` - Builds a new, empty store.
+///
+/// # Examples
+///
+/// ```ignore
+/// use shard::repository::testsuite::run_conformance;
+/// use shard::repository::HashMapShareEntryDao;
+///
+/// run_conformance(HashMapShareEntryDao::new);
+/// ```
+pub fn run_conformance<D: ShareEntryDaoTrait>(make: impl Fn() -> D) {
+    check_basic_operations(&make());
+    check_edge_case_keys(&make());
+    check_pages(&make());
+    check_keys(&make());
+    check_expired_keys(&make());
+    check_owned_keys(&make());
+    check_delete_by_owner(&make());
+    check_tombstones(&make());
+    check_quarantine(&make());
+    check_compare_and_swap(&make());
+    check_batches(&make());
+    check_stats(&make());
+    check_concurrent_access(&make());
+}
+
+fn entry() -> ShareEntry {
+    ShareEntry {
+        share: (1, vec![0, 1, 2, 255]),
+        sender: vec![4, 5, 6],
+        threshold: 3,
+        ..Default::default()
+    }
+}
+
+fn expiring_entry(expires_at: u64) -> ShareEntry {
+    ShareEntry {
+        expires_at: Some(expires_at),
+        ..entry()
+    }
+}
+
+fn owned_entry(owner: u8) -> ShareEntry {
+    ShareEntry {
+        sender: vec![owner; 4],
+        ..entry()
+    }
+}
+
+/// Walks `dao` a page at a time, checking every page but the last is full and that keys
+/// strictly increase across page boundaries.
+///
+/// # Returns
+///
+/// Every key in the store, in the order the pages returned them.
+pub fn walk_pages(dao: &dyn ShareEntryDaoTrait, limit: usize) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    loop {
+        let page = dao
+            .get_page(keys.last().map(String::as_str), limit)
+            .unwrap();
+        assert!(page.len() <= limit);
+        for (key, _) in &page {
+            assert!(keys.last().is_none_or(|last| last < key));
+            keys.push(key.clone());
+        }
+        if page.len() < limit {
+            return keys;
+        }
+    }
+}
+
+/// Inserts `count` entries under distinct keys.
+///
+/// # Returns
+///
+/// The inserted keys, sorted.
+pub fn populate(dao: &dyn ShareEntryDaoTrait, count: usize) -> Vec<String> {
+    let mut keys: Vec<String> = (0..count).map(|i| format!("key-{}", i)).collect();
+    for key in &keys {
+        dao.insert(key, &entry()).unwrap();
+    }
+    keys.sort();
+    keys
+}
+
+/// Checks insert, get, update, and delete, including missing keys and overwrites.
+pub fn check_basic_operations(dao: &dyn ShareEntryDaoTrait) {
+    assert!(dao.get("key").unwrap().is_none());
+    assert!(dao.update("key", &entry()).is_err());
+    assert!(dao.get("key").unwrap().is_none());
+    dao.delete("key").unwrap();
+
+    dao.insert("key", &entry()).unwrap();
+    assert_eq!(dao.get("key").unwrap(), Some(entry()));
+
+    let overwritten = ShareEntry {
+        threshold: 5,
+        ..expiring_entry(100)
+    };
+    dao.insert("key", &overwritten).unwrap();
+    assert_eq!(dao.get("key").unwrap(), Some(overwritten));
+
+    let updated = ShareEntry {
+        share: (1, vec![42]),
+        epoch: 3,
+        last_refreshed_unix: 1_700_000_000,
+        refresh_digest: Some([7; 32]),
+        refresh_interval_secs: Some(600),
+        readers: vec![vec![5, 6]],
+        ..entry()
+    };
+    dao.update("key", &updated).unwrap();
+    assert_eq!(dao.get_all().unwrap(), vec![("key".to_string(), updated)]);
+
+    dao.delete("key").unwrap();
+    assert!(dao.get("key").unwrap().is_none());
+    assert!(dao.get_all().unwrap().is_empty());
+    dao.delete("key").unwrap();
+}
+
+/// Checks that unusual keys and empty values are stored and listed unchanged.
+pub fn check_edge_case_keys(dao: &dyn ShareEntryDaoTrait) {
+    let long_key = "k".repeat(4096);
+    let keys = ["clé/🔑", "键", "a%", "a_b", "with space", long_key.as_str()];
+    for (i, key) in keys.iter().enumerate() {
+        dao.insert(key, &owned_entry(i as u8)).unwrap();
+    }
+    let empty = ShareEntry {
+        share: (1, vec![]),
+        sender: vec![],
+        ..entry()
+    };
+    dao.insert("empty", &empty).unwrap();
+
+    for (i, key) in keys.iter().enumerate() {
+        assert_eq!(dao.get(key).unwrap(), Some(owned_entry(i as u8)));
+    }
+    assert_eq!(dao.get("empty").unwrap(), Some(empty));
+
+    let mut expected: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+    expected.push("empty".to_string());
+    expected.sort();
+    assert_eq!(dao.keys().unwrap(), expected);
+    assert_eq!(walk_pages(dao, 2), expected);
+    assert_eq!(dao.keys_with_prefix("a%").unwrap(), vec!["a%"]);
+    assert_eq!(dao.keys_with_prefix("a_").unwrap(), vec!["a_b"]);
+    assert!(dao.keys_with_prefix("_").unwrap().is_empty());
+    assert_eq!(dao.keys_with_prefix("clé/").unwrap(), vec!["clé/🔑"]);
+    assert_eq!(
+        dao.keys_with_prefix("kkkk").unwrap(),
+        vec![long_key.clone()]
+    );
+
+    dao.delete(&long_key).unwrap();
+    dao.delete("键").unwrap();
+    assert!(dao.get(&long_key).unwrap().is_none());
+    assert_eq!(dao.keys().unwrap().len(), keys.len() - 1);
+}
+
+/// Checks that pages cover every key once, in order, for several page sizes.
+pub fn check_pages(dao: &dyn ShareEntryDaoTrait) {
+    assert!(dao.get_page(None, 10).unwrap().is_empty());
+    let keys = populate(dao, 1000);
+
+    assert_eq!(walk_pages(dao, 100), keys);
+    assert_eq!(walk_pages(dao, 7), keys);
+    assert_eq!(walk_pages(dao, 1000), keys);
+    assert_eq!(dao.get_page(Some(&keys[998]), 10).unwrap().len(), 1);
+    assert!(dao.get_page(Some(&keys[999]), 10).unwrap().is_empty());
+    let all: Vec<String> = dao.get_all().unwrap().into_iter().map(|(k, _)| k).collect();
+    assert_eq!(all, keys);
+}
+
+/// Checks listing keys, by prefix, by owner, and a page at a time.
+pub fn check_keys(dao: &dyn ShareEntryDaoTrait) {
+    assert!(dao.keys().unwrap().is_empty());
+    for key in ["b/2", "a/1", "b/1", "c"] {
+        dao.insert(key, &owned_entry(key.len() as u8)).unwrap();
+    }
+
+    assert_eq!(dao.keys().unwrap(), vec!["a/1", "b/1", "b/2", "c"]);
+    assert_eq!(dao.keys_with_prefix("b/").unwrap(), vec!["b/1", "b/2"]);
+    assert!(dao.keys_with_prefix("d").unwrap().is_empty());
+    assert_eq!(dao.keys_by_owner(&[1; 4]).unwrap(), vec!["c"]);
+    assert_eq!(dao.keys_by_owner(&[3; 4]).unwrap().len(), 3);
+
+    assert_eq!(dao.keys_page(None, 2).unwrap(), vec!["a/1", "b/1"]);
+    assert_eq!(dao.keys_page(Some("b/1"), 2).unwrap(), vec!["b/2", "c"]);
+    assert!(dao.keys_page(Some("c"), 2).unwrap().is_empty());
+}
+
+/// Checks that exactly the entries expiring at or before `now` are listed, and that updates and
+/// deletes are reflected.
+pub fn check_expired_keys(dao: &dyn ShareEntryDaoTrait) {
+    let expired = |now: u64| {
+        let mut keys = dao.expired_keys(now).unwrap();
+        keys.sort();
+        keys
+    };
+    dao.insert("forever", &entry()).unwrap();
+    dao.insert("soon", &expiring_entry(10)).unwrap();
+    dao.insert("later", &expiring_entry(20)).unwrap();
+
+    assert!(expired(9).is_empty());
+    assert_eq!(expired(10), vec!["soon"]);
+    assert_eq!(expired(20), vec!["later", "soon"]);
+
+    dao.update("soon", &expiring_entry(30)).unwrap();
+    dao.delete("later").unwrap();
+    assert!(expired(20).is_empty());
+    dao.update("soon", &entry()).unwrap();
+    assert!(expired(u64::MAX).is_empty());
+}
+
+/// Checks that entries stored in an owner's namespace are isolated from other owners, and that
+/// plain keys are moved into their owner's namespace.
+pub fn check_owned_keys(dao: &dyn ShareEntryDaoTrait) {
+    dao.insert_owned("key", &owned_entry(1)).unwrap();
+    dao.insert_owned("key", &owned_entry(2)).unwrap();
+    assert_eq!(dao.get_owned(&[1; 4], "key").unwrap(), Some(owned_entry(1)));
+    assert_eq!(dao.get_owned(&[2; 4], "key").unwrap(), Some(owned_entry(2)));
+    assert!(dao.get_owned(&[3; 4], "key").unwrap().is_none());
+    assert!(dao.get("key").unwrap().is_none());
+
+    dao.delete_owned(&[1; 4], "key").unwrap();
+    assert!(dao.get_owned(&[1; 4], "key").unwrap().is_none());
+    assert_eq!(dao.get_owned(&[2; 4], "key").unwrap(), Some(owned_entry(2)));
+
+    dao.insert("plain", &owned_entry(3)).unwrap();
+    assert_eq!(dao.migrate_owner_keys().unwrap(), 1);
+    assert_eq!(dao.migrate_owner_keys().unwrap(), 0);
+    assert!(dao.get("plain").unwrap().is_none());
+    assert_eq!(
+        dao.keys().unwrap(),
+        vec![owner_key(&[2; 4], "key"), owner_key(&[3; 4], "plain")]
+    );
+}
+
+/// Checks deleting every entry of one owner.
+pub fn check_delete_by_owner(dao: &dyn ShareEntryDaoTrait) {
+    assert!(dao.delete_by_owner(&[1; 4]).unwrap().is_empty());
+
+    for i in 0..30 {
+        dao.insert(&format!("key-{:02}", i), &owned_entry(i % 3))
+            .unwrap();
+    }
+    let deleted = dao.delete_by_owner(&[1; 4]).unwrap();
+    let expected: Vec<String> = (0..30)
+        .filter(|i| i % 3 == 1)
+        .map(|i| format!("key-{:02}", i))
+        .collect();
+    assert_eq!(deleted, expected);
+
+    let remaining = dao.get_all().unwrap();
+    assert_eq!(remaining.len(), 20);
+    assert!(remaining.iter().all(|(_, entry)| entry.sender != [1; 4]));
+    assert!(dao.delete_by_owner(&[1; 4]).unwrap().is_empty());
+}
+
+/// Checks that deleting with a tombstone hides the entry and keeps the tombstone apart from the
+/// entries until it expires.
+pub fn check_tombstones(dao: &dyn ShareEntryDaoTrait) {
+    let window = Duration::from_secs(100);
+    assert!(dao.get_tombstone("key").unwrap().is_none());
+    assert!(dao
+        .delete_with_tombstone("key", "test", 10, window)
+        .unwrap()
+        .is_none());
+    assert!(dao.get_tombstone("key").unwrap().is_none());
+
+    dao.insert("key", &owned_entry(1)).unwrap();
+    dao.insert("other", &owned_entry(2)).unwrap();
+    let tombstone = dao
+        .delete_with_tombstone("key", "test", 10, window)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        tombstone,
+        Tombstone {
+            owner: vec![1; 4],
+            deleted_at: 10,
+            expires_at: 110,
+            reason: "test".to_string(),
+        }
+    );
+    assert!(tombstone.is_live(109));
+    assert!(!tombstone.is_live(110));
+    assert!(dao.get("key").unwrap().is_none());
+    assert_eq!(dao.keys().unwrap(), vec!["other"]);
+    assert_eq!(dao.stats().unwrap().entries, 1);
+    assert_eq!(dao.get_tombstone("key").unwrap(), Some(tombstone));
+
+    assert_eq!(
+        dao.tombstone_by_owner(&[2; 4], "purged", 50, window)
+            .unwrap(),
+        vec!["other"]
+    );
+    assert!(dao.keys().unwrap().is_empty());
+    assert_eq!(dao.purge_tombstones(109).unwrap(), 0);
+    assert_eq!(dao.purge_tombstones(110).unwrap(), 1);
+    assert!(dao.get_tombstone("key").unwrap().is_none());
+    assert!(dao.get_tombstone("other").unwrap().is_some());
+
+    dao.remove_tombstone("other").unwrap();
+    dao.remove_tombstone("other").unwrap();
+    assert!(dao.get_tombstone("other").unwrap().is_none());
+}
+
+/// Checks that a quarantined entry leaves the store but stays listed as quarantined.
+pub fn check_quarantine(dao: &dyn ShareEntryDaoTrait) {
+    assert!(!dao.quarantine("key").unwrap());
+    assert!(!dao.is_quarantined("key").unwrap());
+
+    dao.insert("key", &owned_entry(1)).unwrap();
+    dao.insert("other", &owned_entry(2)).unwrap();
+    assert!(dao.quarantine("key").unwrap());
+
+    assert!(dao.get("key").unwrap().is_none());
+    assert_eq!(dao.keys().unwrap(), vec!["other"]);
+    assert!(dao.is_quarantined("key").unwrap());
+    assert!(!dao.is_quarantined("other").unwrap());
+    assert_eq!(dao.quarantined_keys().unwrap(), vec!["key"]);
+
+    // registering the key again does not release the quarantined value
+    dao.insert("key", &owned_entry(1)).unwrap();
+    assert!(dao.get("key").unwrap().is_some());
+    assert_eq!(dao.quarantined_keys().unwrap(), vec!["key"]);
+}
+
+/// Checks that a swap only applies when the stored entry equals the expected one.
+pub fn check_compare_and_swap(dao: &dyn ShareEntryDaoTrait) {
+    let original = expiring_entry(10);
+    let replaced = ShareEntry {
+        share: (1, vec![9]),
+        ..expiring_entry(20)
+    };
+    assert!(!dao.compare_and_swap("key", &original, &replaced).unwrap());
+    assert!(dao.get("key").unwrap().is_none());
+
+    dao.insert("key", &original).unwrap();
+    assert!(!dao.compare_and_swap("key", &replaced, &original).unwrap());
+    assert!(dao.compare_and_swap("key", &original, &replaced).unwrap());
+    assert!(!dao.compare_and_swap("key", &original, &original).unwrap());
+    assert_eq!(dao.get("key").unwrap().unwrap(), replaced);
+    assert!(dao.expired_keys(15).unwrap().is_empty());
+    assert_eq!(dao.expired_keys(20).unwrap(), vec!["key"]);
+}
+
+/// Checks that batches are applied whole, and that an invalid member rejects the batch
+/// without writing any of it.
+pub fn check_batches(dao: &dyn ShareEntryDaoTrait) {
+    let batch: Vec<(String, ShareEntry)> = (0..5)
+        .map(|i| (format!("key/{}", i), owned_entry(1)))
+        .collect();
+    dao.insert_batch(&batch).unwrap();
+    assert_eq!(dao.get_all().unwrap(), batch);
+
+    let mut invalid: Vec<(String, ShareEntry)> = (5..10)
+        .map(|i| (format!("key/{}", i), owned_entry(1)))
+        .collect();
+    invalid[3].1.share.0 = 0;
+    let err = dao.insert_batch(&invalid).unwrap_err();
+    assert_eq!(
+        err,
+        RepoError::InvalidEntry(
+            "key/8".to_string(),
+            "share index 0 is the secret itself"
+        )
+    );
+    assert_eq!(dao.get_all().unwrap(), batch);
+
+    let mut refreshed: Vec<(String, ShareEntry)> = batch
+        .iter()
+        .map(|(key, entry)| {
+            let mut entry = entry.clone();
+            entry.share.1 = vec![9; 4];
+            entry.epoch = 1;
+            (key.clone(), entry)
+        })
+        .collect();
+    refreshed.push(("missing".to_string(), owned_entry(1)));
+    let err = dao.apply_refresh_batch(&refreshed).unwrap_err();
+    assert_eq!(
+        err,
+        RepoError::KeyNotFound("missing".to_string())
+    );
+    assert_eq!(dao.get_all().unwrap(), batch);
+
+    refreshed.pop();
+    refreshed[0].1.sender = vec![2; 4];
+    let err = dao.apply_refresh_batch(&refreshed).unwrap_err();
+    assert_eq!(
+        err,
+        RepoError::InvalidEntry(
+            "key/0".to_string(),
+            "refresh changes the owner"
+        )
+    );
+    assert_eq!(dao.get_all().unwrap(), batch);
+
+    refreshed[0].1.sender = vec![1; 4];
+    dao.apply_refresh_batch(&refreshed).unwrap();
+    assert_eq!(dao.get_all().unwrap(), refreshed);
+}
+
+/// Applies inserts, growing and shrinking updates, a swap, and deletes, checking the counts
+/// against a full scan after each step.
+///
+/// Stores may size values by what they actually write, so byte totals are only checked to move
+/// with the size of the entries.
+pub fn check_stats(dao: &dyn ShareEntryDaoTrait) {
+    let assert_counts = |dao: &dyn ShareEntryDaoTrait| {
+        let entries = dao.get_all().unwrap();
+        let scanned = DaoStats::from_entries(entries.iter().map(|(_, entry)| entry)).unwrap();
+        let stats = dao.stats().unwrap();
+        assert_eq!(
+            (stats.entries, &stats.per_owner),
+            (scanned.entries, &scanned.per_owner)
+        );
+        stats
+    };
+    assert_eq!(dao.stats().unwrap(), DaoStats::default());
+
+    dao.insert("a", &owned_entry(1)).unwrap();
+    dao.insert("b", &owned_entry(2)).unwrap();
+    dao.insert("c", &owned_entry(2)).unwrap();
+    let before = assert_counts(dao);
+    assert!(before.total_value_bytes > 0);
+
+    let grown = ShareEntry {
+        share: (1, vec![7; 100]),
+        ..owned_entry(1)
+    };
+    dao.update("a", &grown).unwrap();
+    let stats = assert_counts(dao);
+    assert_eq!(stats.total_value_bytes, before.total_value_bytes + 96);
+
+    assert!(dao.compare_and_swap("a", &grown, &owned_entry(3)).unwrap());
+    let stats = assert_counts(dao);
+    assert_eq!(stats.total_value_bytes, before.total_value_bytes);
+
+    dao.delete("b").unwrap();
+    dao.delete("missing").unwrap();
+    let stats = assert_counts(dao);
+    assert_eq!(stats.entries, 2);
+    assert_eq!(stats.per_owner, vec![(vec![2; 4], 1), (vec![3; 4], 1)]);
+
+    dao.delete("a").unwrap();
+    dao.delete("c").unwrap();
+    assert_eq!(dao.stats().unwrap(), DaoStats::default());
+}
+
+/// Writes from several threads at once: each inserts its own keys and bumps the epoch of a
+/// shared entry with compare-and-swap, so no write may be lost.
+pub fn check_concurrent_access(dao: &dyn ShareEntryDaoTrait) {
+    dao.insert("shared", &entry()).unwrap();
+
+    std::thread::scope(|scope| {
+        for thread in 0..THREADS {
+            scope.spawn(move || {
+                for i in 0..WRITES_PER_THREAD {
+                    dao.insert(&format!("thread-{}/{}", thread, i), &entry())
+                        .unwrap();
+                    loop {
+                        let current = dao.get("shared").unwrap().unwrap();
+                        let bumped = ShareEntry {
+                            epoch: current.epoch + 1,
+                            ..current.clone()
+                        };
+                        if dao.compare_and_swap("shared", &current, &bumped).unwrap() {
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let shared = dao.get("shared").unwrap().unwrap();
+    assert_eq!(shared.epoch, THREADS * WRITES_PER_THREAD);
+    assert_eq!(
+        dao.keys().unwrap().len() as u64,
+        THREADS * WRITES_PER_THREAD + 1
+    );
+    assert_eq!(
+        dao.stats().unwrap().entries,
+        THREADS * WRITES_PER_THREAD + 1
+    );
+}
@@ -0,0 +1,298 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use futures::future;
+use libp2p::identity::Keypair;
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::client::{Client, ClientError};
+
+/// The file in the configuration directory the report of a rotation that did not complete is
+/// kept in, for the rotation to be resumed.
+pub const ROTATION_REPORT_FILE: &str = "rotation.json";
+
+/// What a rotation of the owner of shares did: the shares handed over to the new owner, and the
+/// transfers left to resume.
+///
+/// # Fields
+///
+/// * `old_owner` - The peer id of the owner the shares are handed over from.
+/// * `new_owner` - The peer id of the owner the shares are handed over to.
+/// * `transferred` - The shares every provider acknowledged handing over.
+/// * `pending` - The transfers that did not complete, for the rotation to be resumed.
+///
+/// # Examples
+///
+/// ```rust
+/// use libp2p::PeerId;
+/// use shard::rotation::{PendingTransfer, RotationReport};
+///
+/// let provider = PeerId::random();
+/// let mut report = RotationReport::new(PeerId::random(), PeerId::random());
+/// assert!(report.is_complete());
+/// report.pending.push(PendingTransfer {
+///     provider: provider.to_string(),
+///     key: None,
+///     reason: "request failed: timeout".to_string(),
+/// });
+/// assert!(!report.is_complete());
+/// assert_eq!(report.pending_providers(), vec![provider]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RotationReport {
+    pub old_owner: String,
+    pub new_owner: String,
+    pub transferred: Vec<TransferredShare>,
+    pub pending: Vec<PendingTransfer>,
+}
+
+/// A share a provider acknowledged handing over to the new owner.
+///
+/// # Fields
+///
+/// * `provider` - The peer id of the provider holding the share.
+/// * `key` - The key of the share.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TransferredShare {
+    pub provider: String,
+    pub key: String,
+}
+
+/// A transfer that did not complete.
+///
+/// # Fields
+///
+/// * `provider` - The peer id of the provider.
+/// * `key` - The key of the share the provider did not hand over, or `None` if the keys held
+///   there could not be listed, or the provider stopped answering.
+/// * `reason` - Why the transfer did not complete.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PendingTransfer {
+    pub provider: String,
+    pub key: Option<String>,
+    pub reason: String,
+}
+
+impl RotationReport {
+    /// Creates the report of a rotation from `old_owner` to `new_owner` that did nothing yet.
+    pub fn new(old_owner: PeerId, new_owner: PeerId) -> Self {
+        RotationReport {
+            old_owner: old_owner.to_string(),
+            new_owner: new_owner.to_string(),
+            transferred: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Returns whether every transfer completed.
+    pub fn is_complete(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Returns the providers with a pending transfer, to resume the rotation with.
+    pub fn pending_providers(&self) -> Vec<PeerId> {
+        let providers: BTreeSet<PeerId> = self
+            .pending
+            .iter()
+            .filter_map(|pending| pending.provider.parse().ok())
+            .collect();
+        providers.into_iter().collect()
+    }
+
+    /// Adds the outcome of resuming the rotation: the shares `resumed` handed over join those
+    /// handed over before, and its pending transfers replace those retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `resumed` - The report of the rotation resumed with the pending providers.
+    pub fn merge(&mut self, resumed: RotationReport) {
+        self.transferred.extend(resumed.transferred);
+        self.transferred.sort();
+        self.pending = resumed.pending;
+    }
+
+    /// Reads the report of a rotation from the file `path`.
+    ///
+    /// # Returns
+    ///
+    /// The report, or `None` if there is no such file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or does not hold a report.
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes the report to the file `path`, replacing the report written there before.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, json)
+    }
+}
+
+/// Hands every share `owner` registered with `providers` over to `new_owner`.
+///
+/// Each provider is asked for the keys `owner` holds shares under there, and then to transfer
+/// each share with a request signed by `owner`, whose node `client` must be. A provider that
+/// cannot be reached, or that refuses a transfer, is recorded as pending rather than failing the
+/// rotation, so that it can be resumed with `RotationReport::pending_providers`. Resuming is
+/// idempotent: a provider only lists the keys `owner` still holds, and completes a transfer it
+/// was interrupted in.
+///
+/// # Arguments
+///
+/// * `client` - The client of the node of `owner`, to send the requests with.
+/// * `owner` - The identity key of the current owner.
+/// * `new_owner` - The identity key of the owner the shares are handed over to, which grants
+///   each provider to keep relaying refreshes of the shares.
+/// * `providers` - The providers to hand the shares over on.
+///
+/// # Returns
+///
+/// The report of the rotation.
+pub async fn rotate_owner(
+    client: &Client,
+    owner: &Keypair,
+    new_owner: &Keypair,
+    providers: impl IntoIterator<Item = PeerId>,
+) -> RotationReport {
+    let old_owner = owner.public().to_peer_id();
+    let new_owner_id = new_owner.public().to_peer_id();
+    let rotations = providers.into_iter().map(|provider| {
+        let mut client = client.clone();
+        async move {
+            let mut transferred = Vec::new();
+            let mut pending = Vec::new();
+            let keys = match client.request_all_keys(provider, old_owner).await {
+                Ok(keys) => keys,
+                Err(e) => {
+                    warn!(%provider, error = %e, "Could not list the keys to transfer.");
+                    pending.push(PendingTransfer {
+                        provider: provider.to_string(),
+                        key: None,
+                        reason: e.to_string(),
+                    });
+                    return (transferred, pending);
+                }
+            };
+            debug!(%provider, keys = keys.len(), "Transferring shares.");
+            for key in keys {
+                let result = client
+                    .request_transfer_ownership(key.clone(), provider, owner, new_owner)
+                    .await;
+                match result {
+                    Ok(_) => transferred.push(TransferredShare {
+                        provider: provider.to_string(),
+                        key,
+                    }),
+                    // a provider that stopped answering is retried whole
+                    Err(ClientError::RequestFailed(reason)) => {
+                        warn!(%provider, key, reason, "Provider stopped answering.");
+                        pending.push(PendingTransfer {
+                            provider: provider.to_string(),
+                            key: None,
+                            reason: format!("request failed: {}", reason),
+                        });
+                        break;
+                    }
+                    Err(e) => {
+                        warn!(%provider, key, error = %e, "Transfer refused.");
+                        pending.push(PendingTransfer {
+                            provider: provider.to_string(),
+                            key: Some(key),
+                            reason: e.to_string(),
+                        });
+                    }
+                }
+            }
+            (transferred, pending)
+        }
+    });
+
+    let mut report = RotationReport::new(old_owner, new_owner_id);
+    for (transferred, pending) in future::join_all(rotations).await {
+        report.transferred.extend(transferred);
+        report.pending.extend(pending);
+    }
+    report.transferred.sort();
+    report.pending.sort();
+    info!(
+        %old_owner, new_owner = %new_owner_id, transferred = report.transferred.len(),
+        pending = report.pending.len(), "Rotated the owner of shares."
+    );
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_round_trips_through_its_file() {
+        let name = format!("shard-rotation-{}.json", rand::random::<u64>());
+        let path = std::env::temp_dir().join(name);
+        assert_eq!(RotationReport::load(&path).unwrap(), None);
+
+        let provider = PeerId::random();
+        let mut report = RotationReport::new(PeerId::random(), PeerId::random());
+        report.transferred.push(TransferredShare {
+            provider: provider.to_string(),
+            key: "a".to_string(),
+        });
+        report.pending.push(PendingTransfer {
+            provider: provider.to_string(),
+            key: Some("b".to_string()),
+            reason: "share not found".to_string(),
+        });
+        report.save(&path).unwrap();
+        assert_eq!(RotationReport::load(&path).unwrap(), Some(report));
+
+        fs::write(&path, b"not a report").unwrap();
+        let err = RotationReport::load(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_merge_keeps_earlier_transfers_and_replaces_pending() {
+        let (first, second) = (PeerId::random(), PeerId::random());
+        let transferred = |provider: PeerId, key: &str| TransferredShare {
+            provider: provider.to_string(),
+            key: key.to_string(),
+        };
+        let mut report = RotationReport::new(PeerId::random(), PeerId::random());
+        report.transferred.push(transferred(first, "a"));
+        report.pending.push(PendingTransfer {
+            provider: second.to_string(),
+            key: None,
+            reason: "request failed: timeout".to_string(),
+        });
+        report.pending.push(PendingTransfer {
+            provider: "not a peer id".to_string(),
+            key: None,
+            reason: "request failed: timeout".to_string(),
+        });
+        assert_eq!(report.pending_providers(), vec![second]);
+
+        let mut resumed = RotationReport::new(PeerId::random(), PeerId::random());
+        resumed.transferred.push(transferred(second, "a"));
+        report.merge(resumed);
+        assert!(report.is_complete());
+        let mut expected = vec![transferred(first, "a"), transferred(second, "a")];
+        expected.sort();
+        assert_eq!(report.transferred, expected);
+    }
+}
@@ -0,0 +1,75 @@
+#[cfg(not(any(feature = "rt-tokio", feature = "rt-async-std")))]
+compile_error!("the `net` feature needs a runtime: enable `rt-tokio` or `rt-async-std`");
+
+// `cli`, `metrics` and `test-util` enable `rt-tokio`, so they cannot be combined with async-std.
+#[cfg(all(feature = "rt-tokio", feature = "rt-async-std"))]
+compile_error!(
+    "`rt-tokio` and `rt-async-std` are exclusive: enable one runtime, and leave out `cli`, \
+     `metrics` and `test-util` with `rt-async-std`"
+);
+
+#[cfg(feature = "rt-async-std")]
+mod async_std;
+
+#[cfg(feature = "rt-async-std")]
+pub use self::async_std::{
+    interval, interval_at, sleep, sleep_until, spawn, timeout, timeout_at, Elapsed, Instant,
+    Interval, JoinError, JoinHandle,
+};
+
+#[cfg(all(feature = "rt-tokio", not(feature = "rt-async-std")))]
+pub use tokio::task::{spawn, JoinError, JoinHandle};
+#[cfg(all(feature = "rt-tokio", not(feature = "rt-async-std")))]
+pub use tokio::time::{
+    error::Elapsed, interval, interval_at, sleep, sleep_until, timeout, timeout_at, Instant,
+    Interval,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_interval_ticks_at_once_then_every_period() {
+        let period = Duration::from_millis(40);
+        let mut ticks = interval(period);
+        assert_eq!(ticks.period(), period);
+        let start = Instant::now();
+        ticks.tick().await;
+        assert!(start.elapsed() < period);
+        ticks.tick().await;
+        ticks.tick().await;
+        assert!(start.elapsed() >= 2 * period);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_gives_up_on_a_pending_future() {
+        let pending = std::future::pending::<()>();
+        assert!(timeout(Duration::from_millis(10), pending).await.is_err());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        assert_eq!(timeout_at(deadline, async { 7 }).await.ok(), Some(7));
+    }
+
+    #[tokio::test]
+    async fn test_join_handle_returns_the_output_or_why_there_is_none() {
+        assert_eq!(spawn(async { 7 }).await.unwrap(), 7);
+
+        let aborted = spawn(std::future::pending::<()>());
+        aborted.abort();
+        assert!(aborted.await.unwrap_err().is_cancelled());
+
+        let panicked = spawn(async { panic!("the task panics") });
+        assert!(panicked.await.unwrap_err().is_panic());
+    }
+
+    #[tokio::test]
+    async fn test_sleep_until_waits_for_the_deadline() {
+        let deadline = Instant::now() + Duration::from_millis(20);
+        sleep_until(deadline).await;
+        assert!(Instant::now() >= deadline);
+        let start = Instant::now();
+        sleep(Duration::from_millis(20)).await;
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,164 @@
+use futures::future::{AbortHandle, Abortable, Aborted, FutureExt};
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+pub use ::async_std::future::TimeoutError as Elapsed;
+pub use std::time::Instant;
+
+/// The output of a task, or the panic it stopped with, unless it was aborted first.
+type TaskOutput<T> = Result<std::thread::Result<T>, Aborted>;
+
+/// A handle on a task spawned by `spawn`, resolving to its output. Like tokio's, dropping it
+/// detaches the task rather than stopping it, and `abort` stops it.
+pub struct JoinHandle<T> {
+    task: ::async_std::task::JoinHandle<TaskOutput<T>>,
+    abort: AbortHandle,
+}
+
+impl<T> JoinHandle<T> {
+    /// Stops the task at its next await point. Awaiting the handle then returns a cancelled
+    /// `JoinError`.
+    pub fn abort(&self) {
+        self.abort.abort();
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.task)
+            .poll(cx)
+            .map(|output| match output {
+                Ok(Ok(output)) => Ok(output),
+                Ok(Err(_)) => Err(JoinError { cancelled: false }),
+                Err(Aborted) => Err(JoinError { cancelled: true }),
+            })
+    }
+}
+
+/// Why a task spawned by `spawn` has no output: it panicked or was aborted.
+#[derive(Debug)]
+pub struct JoinError {
+    cancelled: bool,
+}
+
+impl JoinError {
+    /// Whether the task was aborted.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    /// Whether the task panicked.
+    pub fn is_panic(&self) -> bool {
+        !self.cancelled
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.cancelled {
+            true => write!(f, "task was cancelled"),
+            false => write!(f, "task panicked"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// Spawns `future` on the async-std executor.
+///
+/// # Arguments
+/// * `future` - The task to run.
+///
+/// # Returns
+/// The handle to await the output of the task with, or to abort it.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (abort, registration) = AbortHandle::new_pair();
+    let task = Abortable::new(AssertUnwindSafe(future).catch_unwind(), registration);
+    JoinHandle {
+        task: ::async_std::task::spawn(task),
+        abort,
+    }
+}
+
+/// Waits until `duration` has elapsed.
+pub async fn sleep(duration: Duration) {
+    ::async_std::task::sleep(duration).await
+}
+
+/// Waits until `deadline` is reached, returning at once if it has passed.
+pub async fn sleep_until(deadline: Instant) {
+    sleep(deadline.saturating_duration_since(Instant::now())).await
+}
+
+/// Runs `future` for at most `duration`.
+///
+/// # Returns
+/// The output of `future`, or `Elapsed` if it did not complete in time.
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, Elapsed> {
+    ::async_std::future::timeout(duration, future).await
+}
+
+/// Runs `future` until `deadline` at most (see `timeout`).
+pub async fn timeout_at<F: Future>(deadline: Instant, future: F) -> Result<F::Output, Elapsed> {
+    timeout(deadline.saturating_duration_since(Instant::now()), future).await
+}
+
+/// Ticks every `period`, the first tick completing at once.
+///
+/// # Panics
+/// If `period` is zero.
+pub fn interval(period: Duration) -> Interval {
+    interval_at(Instant::now(), period)
+}
+
+/// Ticks every `period`, the first tick completing at `start`.
+///
+/// # Panics
+/// If `period` is zero.
+pub fn interval_at(start: Instant, period: Duration) -> Interval {
+    assert!(period > Duration::ZERO, "`period` must be non-zero.");
+    Interval {
+        next: start,
+        period,
+    }
+}
+
+/// The ticks of `interval`. As with tokio's default, ticks missed while the interval was not
+/// polled complete at once, one after the other, until it has caught up.
+///
+/// # Fields
+/// * `next` - When the next tick completes.
+/// * `period` - The time between two ticks.
+#[derive(Debug)]
+pub struct Interval {
+    next: Instant,
+    period: Duration,
+}
+
+impl Interval {
+    /// Waits for the next tick. Cancelling the wait leaves the tick to the next call.
+    ///
+    /// # Returns
+    /// The instant the tick was due at.
+    pub async fn tick(&mut self) -> Instant {
+        sleep_until(self.next).await;
+        let tick = self.next;
+        self.next = tick + self.period;
+        tick
+    }
+
+    /// Returns the time between two ticks.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
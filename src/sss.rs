@@ -10,6 +10,142 @@ use serde::{
 };
 use std::collections::HashMap;
 
+mod pedersen;
+mod scalar;
+
+pub use pedersen::{
+    combine_shares_pedersen, split_secret_pedersen, verify_share_pedersen, PedersenCommitments,
+    PedersenSplit,
+};
+pub use scalar::{
+    combine_scalars, decode_scalars, encode_scalars, generate_scalar_refresh_key, refresh_scalar,
+    refresh_scalars, split_scalar, validate_scalar_refresh_key, Scalar, ScalarPolynomial,
+};
+
+/// Why a secret cannot be split, or a share or refresh key cannot be used.
+///
+/// # Variants
+///
+/// * `InvalidThreshold` - The threshold is 1 or less, so that a single share would reveal the
+///   secret.
+/// * `InvalidCount` - Fewer shares were asked for than the threshold, or more than 255.
+/// * `EmptyShares` - There are no shares to work on.
+/// * `EmptyShare` - A share holds no bytes.
+/// * `LengthMismatch` - A share and a refresh key are not of the same length.
+/// * `KeyLength { polynomials, share_length }` - A refresh key does not hold one polynomial per
+///   byte of the share it is checked against.
+/// * `NonZeroConstant(index)` - A polynomial of a refresh key would change the secret.
+/// * `KeyDegree { index, coefficients, threshold }` - A polynomial of a refresh key does not have
+///   as many coefficients as the threshold.
+/// * `InvalidIndex` - A share is of index 0, where the secret itself is.
+/// * `DuplicateIndex(index)` - Two different shares were collected under the same index.
+/// * `UnequalShareLengths { shortest, longest }` - Shares to combine are not all of the same
+///   length.
+/// * `DuplicateWideIndex(index)` - Two different shares were collected under the same 16-bit
+///   index, with `sss16`.
+/// * `KeyField` - A refresh key is over another field than the share it refreshes, with `sss16`.
+/// * `MalformedShare` - A share does not hold whole field elements, such as one canonical scalar
+///   per committed chunk.
+/// * `MalformedCommitment(chunk)` - The commitments of a chunk are not valid points, or not as
+///   many as those of the first chunk.
+/// * `CommitmentMismatch(chunk)` - A share pair does not match the commitments of a chunk.
+/// * `InconsistentShares` - Shares rebuild no secret split with `split_secret_pedersen`.
+///
+/// # Examples
+///
+/// ```rust
+/// use shard::sss::{split_secret, Error};
+///
+/// assert_eq!(split_secret(b"secret", 1, 3), Err(Error::InvalidThreshold));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    InvalidThreshold,
+    InvalidCount,
+    EmptyShares,
+    EmptyShare,
+    LengthMismatch,
+    KeyLength {
+        polynomials: usize,
+        share_length: usize,
+    },
+    NonZeroConstant(usize),
+    KeyDegree {
+        index: usize,
+        coefficients: usize,
+        threshold: usize,
+    },
+    InvalidIndex,
+    DuplicateIndex(u8),
+    DuplicateWideIndex(u16),
+    KeyField,
+    UnequalShareLengths {
+        shortest: usize,
+        longest: usize,
+    },
+    MalformedShare,
+    MalformedCommitment(usize),
+    CommitmentMismatch(usize),
+    InconsistentShares,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidThreshold => write!(f, "Invalid threshold"),
+            Error::InvalidCount => write!(f, "Invalid count"),
+            Error::EmptyShares => write!(f, "Empty shares map"),
+            Error::EmptyShare => write!(f, "Empty share"),
+            Error::LengthMismatch => write!(f, "Share length and polynomials length mismatch"),
+            Error::KeyLength {
+                polynomials,
+                share_length,
+            } => write!(
+                f,
+                "refresh key has {} polynomials for a share of {} bytes",
+                polynomials, share_length
+            ),
+            Error::NonZeroConstant(index) => {
+                write!(f, "polynomial {} has a non-zero constant term", index)
+            }
+            Error::KeyDegree {
+                index,
+                coefficients,
+                threshold,
+            } => write!(
+                f,
+                "polynomial {} has {} coefficients, the threshold is {}",
+                index, coefficients, threshold
+            ),
+            Error::InvalidIndex => write!(f, "share index 0 is the secret itself"),
+            Error::DuplicateIndex(index) => {
+                write!(f, "two different shares have the index {}", index)
+            }
+            Error::UnequalShareLengths { shortest, longest } => write!(
+                f,
+                "shares are of different lengths, from {} to {} bytes",
+                shortest, longest
+            ),
+            Error::DuplicateWideIndex(index) => {
+                write!(f, "two different shares have the index {}", index)
+            }
+            Error::KeyField => write!(f, "refresh key is over another field than the share"),
+            Error::MalformedShare => write!(f, "share does not hold whole field elements"),
+            Error::MalformedCommitment(chunk) => {
+                write!(f, "commitments of chunk {} are malformed", chunk)
+            }
+            Error::CommitmentMismatch(chunk) => {
+                write!(f, "share does not match the commitments of chunk {}", chunk)
+            }
+            Error::InconsistentShares => {
+                write!(f, "shares are too few, or not all of the same split")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 /// Represents a polynomial over the Galois field GF(2^8).
 ///
 /// Each polynomial is represented by its coefficients, stored in a vector.
@@ -121,10 +257,11 @@ impl<'de> Deserialize<'de> for Polynomial {
 /// * `shares` - The total number of shares to be created.
 ///
 /// # Returns
-/// A `Result` containing either a `HashMap` of shares (if successful) or an error message.
+/// A `Result` containing either a `HashMap` of shares (if successful) or an `Error`.
 ///
 /// # Errors
-/// Returns an error if the threshold is invalid (<= 1) or if the number of shares is less than the threshold.
+/// Returns an error if the threshold is invalid (<= 1), or if the number of shares is less than
+/// the threshold or more than 255.
 ///
 /// # Examples
 /// ```rust
@@ -140,15 +277,50 @@ pub fn split_secret(
     secret: &[u8],
     threshold: usize,
     shares: usize,
-) -> Result<HashMap<u8, Vec<u8>>, String> {
+) -> Result<HashMap<u8, Vec<u8>>, Error> {
     if threshold <= 1 {
-        return Err("Invalid threshold".to_string());
+        return Err(Error::InvalidThreshold);
     }
 
-    if shares < threshold {
-        return Err("Invalid count".to_string());
+    if shares < threshold || shares > u8::MAX as usize {
+        return Err(Error::InvalidCount);
     }
 
+    #[cfg(not(feature = "rayon"))]
+    let shares_map = split_bytes(secret, threshold, shares);
+
+    #[cfg(feature = "rayon")]
+    let shares_map = {
+        use rayon::prelude::*;
+
+        // every chunk is split on its own worker, and its shares appended in the order of the
+        // chunks, so that each share holds its bytes in the order of the secret
+        let parts: Vec<HashMap<u8, Vec<u8>>> = secret
+            .par_chunks(PARALLEL_CHUNK_BYTES)
+            .map(|chunk| split_bytes(chunk, threshold, shares))
+            .collect();
+        let mut shares_map: HashMap<u8, Vec<u8>> = HashMap::new();
+        for part in parts {
+            for (i, bytes) in part {
+                shares_map.entry(i).or_default().extend(bytes);
+            }
+        }
+        shares_map
+    };
+
+    Ok(shares_map)
+}
+
+/// The number of bytes of the secret each worker splits at a time with the `rayon` feature.
+#[cfg(feature = "rayon")]
+const PARALLEL_CHUNK_BYTES: usize = 4096;
+
+/// Splits each byte of `secret` with a polynomial of its own into the shares of indexes 1 to
+/// `shares`.
+///
+/// The coefficients are drawn from `rand::thread_rng`, so that each worker splitting a chunk of
+/// a secret in parallel draws from an RNG of its own thread rather than sharing one.
+fn split_bytes(secret: &[u8], threshold: usize, shares: usize) -> HashMap<u8, Vec<u8>> {
     let mut shares_map: HashMap<u8, Vec<u8>> = HashMap::new();
 
     for &byte in secret {
@@ -156,91 +328,148 @@ pub fn split_secret(
 
         for i in 1..=shares as u8 {
             let y = poly.evaluate(gf256::new(i));
-            shares_map.entry(i).or_insert_with(Vec::new).push(y.into());
+            shares_map.entry(i).or_default().push(y.into());
         }
     }
 
-    Ok(shares_map)
+    shares_map
+}
+
+/// Adds a share to the shares collected to combine, such as those fetched from providers or
+/// read from files.
+///
+/// A share already held under `index` is kept if it is the same. A different one means two
+/// shares ended up with the same index, so that at most one of them can be combined.
+///
+/// # Arguments
+/// * `shares_map` - The shares collected so far, by index.
+/// * `index` - The index of the share.
+/// * `share` - The share.
+///
+/// # Returns
+/// `Result<(), Error>` indicating the share was added or why it cannot be.
+///
+/// # Errors
+/// Returns `Error::DuplicateIndex` if a different share is held under `index` already.
+///
+/// # Examples
+/// ```rust
+/// use std::collections::HashMap;
+/// use shard::sss::{insert_share, Error};
+///
+/// let mut shares_map = HashMap::new();
+/// insert_share(&mut shares_map, 1, vec![1, 2]).unwrap();
+/// insert_share(&mut shares_map, 1, vec![1, 2]).unwrap();
+/// assert_eq!(insert_share(&mut shares_map, 1, vec![3, 4]), Err(Error::DuplicateIndex(1)));
+/// ```
+pub fn insert_share(
+    shares_map: &mut HashMap<u8, Vec<u8>>,
+    index: u8,
+    share: Vec<u8>,
+) -> Result<(), Error> {
+    match shares_map.get(&index) {
+        Some(held) if *held != share => Err(Error::DuplicateIndex(index)),
+        Some(_) => Ok(()),
+        None => {
+            shares_map.insert(index, share);
+            Ok(())
+        }
+    }
 }
 
 /// Combines shares to reconstruct a secret using Shamir's Secret Sharing Scheme.
 ///
+/// Shares are keyed by their index, so no two of them can share an x coordinate; shares are
+/// collected with `insert_share` to catch two shares of the same index before they get here.
+///
 /// # Arguments
 /// * `shares_map` - A `HashMap` where each key-value pair represents a share of the secret.
 ///
 /// # Returns
-/// An `Option` containing the reconstructed secret as a `Vec<u8>` if successful, or `None` if not.
+/// A `Result` containing the reconstructed secret as a `Vec<u8>`, or why the shares cannot be
+/// combined. Shares fewer than the threshold rebuild a different secret, which is not detected.
+///
+/// # Errors
+/// * Returns `Error::EmptyShares` if there are no shares.
+/// * Returns `Error::InvalidIndex` if a share is of index 0.
+/// * Returns `Error::UnequalShareLengths` if the shares are not all of the same length.
 ///
 /// # Examples
-/// ```ignore
-/// use shard::sss::{split_secret, combine_shares};
-/// // Assuming `shares_map` is a HashMap<u8, Vec<u8>> obtained from `split_secret`
-/// let reconstructed_secret = combine_shares(&shares_map).unwrap();
+/// ```rust
+/// use std::collections::HashMap;
+/// use shard::sss::{combine_shares, split_secret, Error};
+///
+/// let shares_map = split_secret(b"hello world", 2, 3).unwrap();
+/// assert_eq!(combine_shares(&shares_map).unwrap(), b"hello world");
+/// assert_eq!(combine_shares(&HashMap::new()), Err(Error::EmptyShares));
 /// ```
-pub fn combine_shares(shares_map: &HashMap<u8, Vec<u8>>) -> Option<Vec<u8>> {
-    let mut secret_length = 0;
-    for v in shares_map.values() {
-        secret_length = v.len();
-        break;
+pub fn combine_shares(shares_map: &HashMap<u8, Vec<u8>>) -> Result<Vec<u8>, Error> {
+    let lengths = shares_map.values().map(Vec::len);
+    let shortest = lengths.clone().min().ok_or(Error::EmptyShares)?;
+    let longest = lengths.max().unwrap_or(shortest);
+    if shortest != longest {
+        return Err(Error::UnequalShareLengths { shortest, longest });
     }
-
-    let mut secret = vec![0; secret_length];
-    let mut points = Vec::new();
-
-    for i in 0..secret_length {
-        points.clear();
-        for (&k, v) in shares_map {
-            if let Some(&y) = v.get(i) {
-                points.push((gf256::new(k), gf256::new(y)));
-            }
-        }
-        secret[i] = interpolate(&points, gf256::new(0)).into();
+    if shares_map.contains_key(&0) {
+        return Err(Error::InvalidIndex);
     }
-
-    Some(secret)
+    let secret_length = shortest;
+
+    // the indexes are the same for every byte, and so are the weights of their shares
+    let (xs, ys): (Vec<gf256>, Vec<&Vec<u8>>) =
+        shares_map.iter().map(|(&k, v)| (gf256::new(k), v)).unzip();
+    let weights = lagrange_weights_at_zero(&xs);
+
+    let secret = (0..secret_length)
+        .map(|i| {
+            ys.iter()
+                .zip(&weights)
+                .fold(gf256::new(0), |value, (y, &weight)| value + weight * gf256::new(y[i]))
+                .into()
+        })
+        .collect();
+
+    Ok(secret)
 }
 
-/// Performs Lagrange interpolation on a set of points to find the value of the polynomial at a specific point.
+/// Computes the Lagrange basis weights that interpolate the value at zero of the polynomial
+/// through points at `xs`, where the secret is.
 ///
-/// This function is a key part of Shamir's Secret Sharing Scheme, enabling the reconstruction of secrets.
+/// The value at zero is the sum of the weight of each point times its `y`. The weights only
+/// depend on the `x` coordinates, which are the indexes of the shares, so that `combine_shares`
+/// computes them once for every byte of the secret rather than once per byte.
 ///
 /// # Arguments
 ///
-/// * `points` - A slice of tuples, each representing a point `(x, y)` on the polynomial.
-/// * `x` - The `x` value at which to evaluate the polynomial.
+/// * `xs` - The distinct, non-zero `x` coordinates of the points.
 ///
 /// # Returns
 ///
-/// The interpolated value at `x`.
+/// The weight of each point, in the order of `xs`.
 ///
 /// # Examples
 ///
-/// Interpolating a value from points of a polynomial:
-///
-/// ```ignore
-/// let points = [(gf256::new(1), gf256::new(5)), (gf256::new(2), gf256::new(10))];
-/// let interpolated_value = interpolate(&points, gf256::new(3));
-/// // `interpolated_value` is the value of the polynomial at x = 3.
+/// ```rust
+/// use gf256::gf256;
+/// use shard::sss::{lagrange_weights_at_zero, Polynomial};
+///
+/// let poly = Polynomial::new(1, gf256::new(42));
+/// let xs = [gf256::new(1), gf256::new(2)];
+/// let weights = lagrange_weights_at_zero(&xs);
+/// let at_zero = poly.evaluate(xs[0]) * weights[0] + poly.evaluate(xs[1]) * weights[1];
+/// assert_eq!(at_zero, gf256::new(42));
 /// ```
-fn interpolate(points: &[(gf256, gf256)], x: gf256) -> gf256 {
-    let mut value = gf256::new(0);
-
-    for (i, &(a_x, a_y)) in points.iter().enumerate() {
-        let mut weight = gf256::new(1);
-
-        for (j, &(b_x, _)) in points.iter().enumerate() {
-            if i != j {
-                let top = x + b_x; // XOR in GF(2^8) is equivalent to addition
-                let bottom = a_x + b_x; // XOR in GF(2^8) is equivalent to addition
-                let factor = top / bottom; // Using gf256 division
-                weight *= factor;
-            }
-        }
-
-        value += weight * a_y; // Using gf256 multiplication and addition
-    }
-
-    value
+pub fn lagrange_weights_at_zero(xs: &[gf256]) -> Vec<gf256> {
+    xs.iter()
+        .enumerate()
+        .map(|(i, &a_x)| {
+            xs.iter()
+                .enumerate()
+                .filter(|&(j, _)| i != j)
+                // at zero, the top x + b_x is b_x, and XOR in GF(2^8) is equivalent to addition
+                .fold(gf256::new(1), |weight, (_, &b_x)| weight * (b_x / (a_x + b_x)))
+        })
+        .collect()
 }
 
 /// https://en.wikipedia.org/wiki/Proactive_secret_sharing#Mathematics
@@ -256,12 +485,12 @@ fn interpolate(points: &[(gf256, gf256)], x: gf256) -> gf256 {
 ///
 /// # Returns
 ///
-/// `Result<(), String>` indicating successful completion or an error message.
+/// `Result<(), Error>` indicating successful completion or why the shares cannot be refreshed.
 ///
 /// # Errors
 ///
-/// * Returns `Err` if `threshold` is less than or equal to 1.
-/// * Returns `Err` if the `shares_map` is empty.
+/// * Returns `Error::InvalidThreshold` if `threshold` is less than or equal to 1.
+/// * Returns `Error::EmptyShares` if the `shares_map` is empty.
 ///
 /// # Examples
 ///
@@ -274,12 +503,12 @@ fn interpolate(points: &[(gf256, gf256)], x: gf256) -> gf256 {
 pub fn refresh_shares(
     shares_map: &mut HashMap<u8, Vec<u8>>,
     threshold: usize,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if threshold <= 1 {
-        return Err("Invalid threshold".to_string());
+        return Err(Error::InvalidThreshold);
     }
 
-    let secret_length = shares_map.values().next().ok_or("Empty shares map")?.len();
+    let secret_length = shares_map.values().next().ok_or(Error::EmptyShares)?.len();
 
     for i in 0..secret_length {
         // Generate a new polynomial with a zero constant term (so it doesn't change the secret)
@@ -289,7 +518,7 @@ pub fn refresh_shares(
         for (&key, value) in shares_map.iter_mut() {
             if let Some(y) = value.get_mut(i) {
                 let new_y = poly.evaluate(gf256::new(key));
-                *y = *y ^ <gf256 as Into<u8>>::into(new_y); // XOR in GF(2^8) is equivalent to addition
+                *y ^= <gf256 as Into<u8>>::into(new_y); // XOR in GF(2^8) is equivalent to addition
             }
         }
     }
@@ -308,12 +537,13 @@ pub fn refresh_shares(
 ///
 /// # Returns
 ///
-/// A `Result<(), String>` indicating successful completion or an error message.
+/// A `Result<(), Error>` indicating successful completion or why the share cannot be refreshed.
 ///
 /// # Errors
 ///
-/// * Returns `Err` if the share is empty.
-/// * Returns `Err` if the length of the share does not match the number of polynomials.
+/// * Returns `Error::EmptyShare` if the share is empty.
+/// * Returns `Error::LengthMismatch` if the length of the share does not match the number of
+///   polynomials.
 ///
 /// # Examples
 ///
@@ -325,19 +555,19 @@ pub fn refresh_shares(
 /// refresh_share(&mut share, &polynomials).unwrap();
 /// // The share is now updated with new values.
 /// ```
-pub fn refresh_share(share: (&u8, &mut Vec<u8>), polynomials: &[Polynomial]) -> Result<(), String> {
+pub fn refresh_share(share: (&u8, &mut Vec<u8>), polynomials: &[Polynomial]) -> Result<(), Error> {
     if share.1.is_empty() {
-        return Err("Empty share".to_string());
+        return Err(Error::EmptyShare);
     }
 
     if share.1.len() != polynomials.len() {
-        return Err("Share length and polynomials length mismatch".to_string());
+        return Err(Error::LengthMismatch);
     }
 
     for (i, y) in share.1.iter_mut().enumerate() {
         let poly = &polynomials[i];
         let new_y = poly.evaluate(gf256::new(*share.0)); // Assuming share keys start from 1
-        *y = *y ^ <gf256 as Into<u8>>::into(new_y); // XOR in GF(2^8) is equivalent to addition
+        *y ^= <gf256 as Into<u8>>::into(new_y); // XOR in GF(2^8) is equivalent to addition
     }
 
     Ok(())
@@ -356,11 +586,11 @@ pub fn refresh_share(share: (&u8, &mut Vec<u8>), polynomials: &[Polynomial]) ->
 ///
 /// # Returns
 ///
-/// A `Result` containing a vector of `Polynomial` objects if successful, or an error message.
+/// A `Result` containing a vector of `Polynomial` objects if successful, or an `Error`.
 ///
 /// # Errors
 ///
-/// * Returns `Err` if the `threshold` is less than or equal to 1.
+/// * Returns `Error::InvalidThreshold` if the `threshold` is less than or equal to 1.
 ///
 /// # Examples
 ///
@@ -373,9 +603,9 @@ pub fn refresh_share(share: (&u8, &mut Vec<u8>), polynomials: &[Polynomial]) ->
 pub fn generate_refresh_key(
     threshold: usize,
     secret_length: usize,
-) -> Result<Vec<Polynomial>, String> {
+) -> Result<Vec<Polynomial>, Error> {
     if threshold <= 1 {
-        return Err("Invalid threshold".to_string());
+        return Err(Error::InvalidThreshold);
     }
 
     let mut polynomials = Vec::with_capacity(secret_length);
@@ -388,6 +618,64 @@ pub fn generate_refresh_key(
     Ok(polynomials)
 }
 
+/// Checks that a refresh key can be applied to a share without changing the secret it encodes.
+///
+/// A refresh key must hold one polynomial per byte of the share, each with a zero constant term,
+/// so that the secret is unchanged, and with `threshold` coefficients, so that the same number of
+/// shares still recovers it.
+///
+/// # Arguments
+///
+/// * `polynomials` - The refresh key to check.
+/// * `threshold` - The threshold the share was split with.
+/// * `share_length` - The length of the share in bytes.
+///
+/// # Returns
+///
+/// `Result<(), Error>` indicating a valid refresh key or why it is invalid.
+///
+/// # Errors
+///
+/// * Returns `Error::KeyLength` if the number of polynomials differs from `share_length`.
+/// * Returns `Error::NonZeroConstant` if a polynomial has a non-zero constant term.
+/// * Returns `Error::KeyDegree` if a polynomial does not have exactly `threshold` coefficients.
+///
+/// # Examples
+///
+/// Checking a generated refresh key:
+///
+/// ```ignore
+/// let polynomials = generate_refresh_key(3, 5).unwrap();
+/// assert!(validate_refresh_key(&polynomials, 3, 5).is_ok());
+/// ```
+pub fn validate_refresh_key(
+    polynomials: &[Polynomial],
+    threshold: usize,
+    share_length: usize,
+) -> Result<(), Error> {
+    if polynomials.len() != share_length {
+        return Err(Error::KeyLength {
+            polynomials: polynomials.len(),
+            share_length,
+        });
+    }
+
+    for (i, poly) in polynomials.iter().enumerate() {
+        if poly.coefficients.first() != Some(&gf256::new(0)) {
+            return Err(Error::NonZeroConstant(i));
+        }
+        if poly.coefficients.len() != threshold {
+            return Err(Error::KeyDegree {
+                index: i,
+                coefficients: poly.coefficients.len(),
+                threshold,
+            });
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use rand::seq::IteratorRandom;
@@ -421,7 +709,7 @@ mod tests {
     }
 
     #[test]
-    fn test_refresh_share_end_to_end() -> Result<(), String> {
+    fn test_refresh_share_end_to_end() -> Result<(), Error> {
         let secret = "refresh share end to end";
         let threshold = 3;
         let shares = 5;
@@ -432,7 +720,7 @@ mod tests {
         // Split the secret into shares
         let mut shares_map = split_secret(secret.as_bytes(), threshold, shares).unwrap();
 
-        let secret_length = shares_map.values().next().ok_or("Empty shares map")?.len();
+        let secret_length = shares_map.values().next().ok_or(Error::EmptyShares)?.len();
         let polynomials = generate_refresh_key(threshold, secret_length).unwrap();
 
         // Refresh each share
@@ -449,11 +737,68 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_validate_refresh_key() {
+        let polynomials = generate_refresh_key(3, 4).unwrap();
+        assert!(validate_refresh_key(&polynomials, 3, 4).is_ok());
+
+        assert!(validate_refresh_key(&polynomials[..3], 3, 4).is_err());
+        assert!(validate_refresh_key(&polynomials, 4, 4).is_err());
+
+        let mut shifted = polynomials.clone();
+        shifted[2].coefficients[0] = gf256::new(1);
+        assert!(validate_refresh_key(&shifted, 3, 4).is_err());
+    }
+
     #[test]
     fn test_invalid_threshold_and_share_count() {
         let secret = "invalid params";
         assert!(split_secret(secret.as_bytes(), 0, 5).is_err());
         assert!(split_secret(secret.as_bytes(), 6, 5).is_err());
+        assert_eq!(split_secret(secret.as_bytes(), 2, 255).unwrap().len(), 255);
+        assert_eq!(
+            split_secret(secret.as_bytes(), 2, 256),
+            Err(Error::InvalidCount)
+        );
+        assert_eq!(
+            split_secret(secret.as_bytes(), 2, 300),
+            Err(Error::InvalidCount)
+        );
+    }
+
+    #[test]
+    fn test_combine_rejects_broken_shares() {
+        assert_eq!(combine_shares(&HashMap::new()), Err(Error::EmptyShares));
+
+        let shares_map = split_secret(b"broken", 2, 3).unwrap();
+        let mut truncated = shares_map.clone();
+        truncated.get_mut(&2).unwrap().pop();
+        assert_eq!(
+            combine_shares(&truncated),
+            Err(Error::UnequalShareLengths {
+                shortest: 5,
+                longest: 6
+            })
+        );
+
+        let mut at_zero = shares_map.clone();
+        at_zero.insert(0, shares_map[&1].clone());
+        assert_eq!(combine_shares(&at_zero), Err(Error::InvalidIndex));
+
+        // two different shares of the same index are caught while collecting them
+        let mut collected = HashMap::new();
+        for (&index, share) in &shares_map {
+            insert_share(&mut collected, index, share.clone()).unwrap();
+        }
+        insert_share(&mut collected, 1, shares_map[&1].clone()).unwrap();
+        assert_eq!(
+            insert_share(&mut collected, 1, shares_map[&2].clone()),
+            Err(Error::DuplicateIndex(1))
+        );
+        assert_eq!(combine_shares(&collected).unwrap(), b"broken");
+
+        let empty: HashMap<u8, Vec<u8>> = [(1, vec![]), (2, vec![])].into();
+        assert_eq!(combine_shares(&empty).unwrap(), b"");
     }
 
     #[test]
@@ -491,8 +836,73 @@ mod tests {
         assert_eq!(secret.as_bytes(), recovered.as_slice());
     }
 
+    /// Interpolates the value at `x` of the polynomial through `points`, recomputing the weights
+    /// of the points, as `combine_shares` did for each byte before the weights were computed once.
+    fn interpolate(points: &[(gf256, gf256)], x: gf256) -> gf256 {
+        let mut value = gf256::new(0);
+
+        for (i, &(a_x, a_y)) in points.iter().enumerate() {
+            let mut weight = gf256::new(1);
+
+            for (j, &(b_x, _)) in points.iter().enumerate() {
+                if i != j {
+                    weight *= (x + b_x) / (a_x + b_x);
+                }
+            }
+
+            value += weight * a_y;
+        }
+
+        value
+    }
+
     #[test]
-    fn full_test() -> Result<(), String> {
+    fn test_weights_at_zero_combine_as_interpolation_does() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            // any shares at distinct indexes, not only those of a split
+            let count = rng.gen_range(1..=20);
+            let length = rng.gen_range(0..100);
+            let shares_map: HashMap<u8, Vec<u8>> = (1..=u8::MAX)
+                .choose_multiple(&mut rng, count)
+                .into_iter()
+                .map(|index| (index, (0..length).map(|_| rng.gen()).collect()))
+                .collect();
+
+            let interpolated: Vec<u8> = (0..length)
+                .map(|i| {
+                    let points: Vec<(gf256, gf256)> = shares_map
+                        .iter()
+                        .map(|(&k, v)| (gf256::new(k), gf256::new(v[i])))
+                        .collect();
+                    interpolate(&points, gf256::new(0)).into()
+                })
+                .collect();
+            assert_eq!(combine_shares(&shares_map).unwrap(), interpolated);
+        }
+    }
+
+    #[test]
+    fn test_secret_of_many_chunks_combines_in_order() {
+        // longer than the chunks split in parallel with `rayon`, and not a multiple of them
+        let mut rng = rand::thread_rng();
+        let secret: Vec<u8> = (0..3 * 4096 + 17).map(|_| rng.gen()).collect();
+
+        let shares_map = split_secret(&secret, 3, 5).unwrap();
+        assert_eq!(shares_map.len(), 5);
+        assert!(shares_map.values().all(|share| share.len() == secret.len()));
+        let subset: HashMap<u8, Vec<u8>> = shares_map
+            .iter()
+            .choose_multiple(&mut rng, 3)
+            .into_iter()
+            .map(|(&key, value)| (key, value.clone()))
+            .collect();
+        assert_eq!(combine_shares(&subset).unwrap(), secret);
+        assert!(split_secret(b"", 3, 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn full_test() -> Result<(), Error> {
         let secret = b"Remember what the dormouse said.";
         let threshold = 2;
         let total_shares = 5;
@@ -518,14 +928,14 @@ mod tests {
         assert!(subset.len() == threshold);
 
         let recovered = combine_shares(&subset);
-        assert!(recovered.is_some());
+        assert!(recovered.is_ok());
         assert!(recovered.unwrap().as_slice() == secret);
 
         Ok(())
     }
 
     #[test]
-    fn test_should_fail_with_shares_below_threshold() -> Result<(), String> {
+    fn test_should_fail_with_shares_below_threshold() -> Result<(), Error> {
         // test should fail if the shares to reassemble are less than the threshold
         let secret = b"Remember what the dormouse said.";
         let threshold = 12;
@@ -544,20 +954,16 @@ mod tests {
         let mut rng = rand::thread_rng();
         let subset: HashMap<u8, Vec<u8>> = shares_map
             .iter()
-            .choose_multiple(&mut rng, threshold-1)
+            .choose_multiple(&mut rng, threshold - 1)
             .into_iter()
             .map(|(&key, value)| (key, value.clone()))
             .collect();
 
         let recovered = combine_shares(&subset);
-        assert!(recovered.is_some());
-
-        println!("actual:    {}", hex::encode(&secret));
-        println!("recovered: {}", hex::encode(recovered.clone().unwrap()));
+        assert!(recovered.is_ok());
 
         assert_ne!(recovered.unwrap().as_slice(), secret);
 
         Ok(())
-
     }
 }
@@ -0,0 +1,473 @@
+use std::collections::HashMap;
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+
+use super::scalar::{evaluate, lagrange_weights_at_zero, random_polynomial, random_scalar};
+use super::Error;
+
+/// The number of secret bytes each scalar of a share encodes, so that every chunk is smaller
+/// than the order of the group.
+const CHUNK_BYTES: usize = 31;
+
+/// The length in bytes of an encoded scalar or point.
+const ELEMENT_BYTES: usize = 32;
+
+/// The byte the secret is padded with before the zeroes filling its last chunk, so that the
+/// length of the secret can be told apart from the padding.
+const PADDING_MARK: u8 = 0x80;
+
+/// What the second generator of the commitments is hashed from, so that nobody knows its
+/// discrete logarithm to the base point.
+const BLINDING_GENERATOR_DOMAIN: &[u8] = b"shard/pedersen/blinding-generator/1";
+
+/// The Pedersen commitments a secret split with `split_secret_pedersen` is verified against.
+///
+/// The secret is split in chunks of 31 bytes, each the constant term of a polynomial over the
+/// scalars of the Ristretto group, blinded by a second random polynomial. The commitment to the
+/// coefficients `a_j` and `b_j` of the two polynomials is `a_j * G + b_j * H`, which reveals
+/// nothing about the secret, not even to an unbounded adversary, unlike the `a_0 * G` a Feldman
+/// commitment publishes.
+///
+/// # Fields
+///
+/// * `chunks` - For each chunk of the secret, the compressed commitments to the coefficients of
+///   its polynomials, from the constant term up, as many as the threshold.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PedersenCommitments {
+    pub chunks: Vec<Vec<[u8; 32]>>,
+}
+
+/// A secret split with `split_secret_pedersen`.
+///
+/// # Fields
+///
+/// * `shares` - The primary shares by index, from which `combine_shares_pedersen` rebuilds the
+///   secret.
+/// * `blindings` - The blinding shares by index, which only serve to verify the primary share of
+///   the same index.
+/// * `commitments` - The commitments every share pair is verified against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PedersenSplit {
+    pub shares: HashMap<u8, Vec<u8>>,
+    pub blindings: HashMap<u8, Vec<u8>>,
+    pub commitments: PedersenCommitments,
+}
+
+/// Splits a secret into a specified number of share pairs using Shamir's Secret Sharing Scheme,
+/// committing to the polynomials with Pedersen commitments so that every share can be verified
+/// without revealing anything about the secret.
+///
+/// # Arguments
+/// * `secret` - A byte slice representing the secret to be split.
+/// * `threshold` - The minimum number of shares required to reconstruct the secret.
+/// * `shares` - The total number of share pairs to be created, at most 255.
+///
+/// # Returns
+/// A `Result` containing either the shares, blinding shares and commitments, or an `Error`.
+///
+/// # Errors
+/// Returns an error if the threshold is invalid (<= 1), or if the number of shares is less than
+/// the threshold or more than 255.
+///
+/// # Examples
+/// ```rust
+/// use shard::sss::{combine_shares_pedersen, split_secret_pedersen, verify_share_pedersen};
+///
+/// let split = split_secret_pedersen(b"hello world", 3, 5).unwrap();
+/// for (index, share) in &split.shares {
+///     let blinding = &split.blindings[index];
+///     assert!(verify_share_pedersen(*index, share, blinding, &split.commitments).is_ok());
+/// }
+/// assert_eq!(combine_shares_pedersen(&split.shares).unwrap(), b"hello world");
+/// ```
+pub fn split_secret_pedersen(
+    secret: &[u8],
+    threshold: usize,
+    shares: usize,
+) -> Result<PedersenSplit, Error> {
+    if threshold <= 1 {
+        return Err(Error::InvalidThreshold);
+    }
+
+    if shares < threshold || shares > u8::MAX as usize {
+        return Err(Error::InvalidCount);
+    }
+
+    let blinding_generator = blinding_generator();
+    let mut split = PedersenSplit {
+        shares: HashMap::new(),
+        blindings: HashMap::new(),
+        commitments: PedersenCommitments { chunks: Vec::new() },
+    };
+
+    for chunk in pad(secret).chunks(CHUNK_BYTES) {
+        let mut constant = [0u8; ELEMENT_BYTES];
+        constant[..CHUNK_BYTES].copy_from_slice(chunk);
+        let primary = random_polynomial(threshold, Scalar::from_bytes_mod_order(constant));
+        let blinding = random_polynomial(threshold, random_scalar());
+
+        split.commitments.chunks.push(
+            primary
+                .iter()
+                .zip(&blinding)
+                .map(|(a, b)| {
+                    let commitment = RISTRETTO_BASEPOINT_POINT * a + blinding_generator * b;
+                    commitment.compress().to_bytes()
+                })
+                .collect(),
+        );
+
+        for i in 1..=shares as u8 {
+            let x = Scalar::from(i);
+            let share = split.shares.entry(i).or_default();
+            share.extend_from_slice(&evaluate(&primary, x).to_bytes());
+            let blinding_share = split.blindings.entry(i).or_default();
+            blinding_share.extend_from_slice(&evaluate(&blinding, x).to_bytes());
+        }
+    }
+
+    Ok(split)
+}
+
+/// Verifies a share pair of a secret split with `split_secret_pedersen` against its commitments.
+///
+/// For every chunk of the secret, the share `s` and the blinding share `t` of index `x` must
+/// satisfy `s * G + t * H = sum(C_j * x^j)`, where `C_j` are the commitments to the
+/// coefficients of the chunk.
+///
+/// # Arguments
+/// * `index` - The index of the share.
+/// * `share` - The primary share.
+/// * `blinding` - The blinding share of the same index.
+/// * `commitments` - The commitments published with the split.
+///
+/// # Returns
+/// `Result<(), Error>` indicating a valid share pair or why it is invalid.
+///
+/// # Errors
+/// * Returns `Error::InvalidIndex` if `index` is 0, where the secret itself is.
+/// * Returns `Error::MalformedCommitment` if there are no commitments, or those of a chunk are
+///   not valid points, or not as many as those of the first chunk.
+/// * Returns `Error::MalformedShare` if a share does not hold one canonical scalar per chunk.
+/// * Returns `Error::CommitmentMismatch` if the share pair does not match the commitments of a
+///   chunk.
+///
+/// # Examples
+/// ```rust
+/// use shard::sss::{split_secret_pedersen, verify_share_pedersen, Error};
+///
+/// let split = split_secret_pedersen(b"hello world", 2, 3).unwrap();
+/// let mut forged = split.shares[&1].clone();
+/// forged[0] ^= 1;
+/// let verified = verify_share_pedersen(1, &forged, &split.blindings[&1], &split.commitments);
+/// assert_eq!(verified, Err(Error::CommitmentMismatch(0)));
+/// ```
+pub fn verify_share_pedersen(
+    index: u8,
+    share: &[u8],
+    blinding: &[u8],
+    commitments: &PedersenCommitments,
+) -> Result<(), Error> {
+    if index == 0 {
+        return Err(Error::InvalidIndex);
+    }
+
+    let chunks = commitments.chunks.len();
+    if chunks == 0 {
+        return Err(Error::MalformedCommitment(0));
+    }
+    let primary = decode_scalars(share, chunks)?;
+    let blinding = decode_scalars(blinding, chunks)?;
+    let threshold = commitments.chunks.first().map_or(0, Vec::len);
+    let blinding_generator = blinding_generator();
+    let x = Scalar::from(index);
+
+    for (i, chunk) in commitments.chunks.iter().enumerate() {
+        if chunk.is_empty() || chunk.len() != threshold {
+            return Err(Error::MalformedCommitment(i));
+        }
+        // Horner's rule from the highest coefficient down
+        let mut committed = RistrettoPoint::default();
+        for bytes in chunk.iter().rev() {
+            let point = CompressedRistretto(*bytes)
+                .decompress()
+                .ok_or(Error::MalformedCommitment(i))?;
+            committed = committed * x + point;
+        }
+        let expected = RISTRETTO_BASEPOINT_POINT * primary[i] + blinding_generator * blinding[i];
+        if committed != expected {
+            return Err(Error::CommitmentMismatch(i));
+        }
+    }
+
+    Ok(())
+}
+
+/// Combines the primary shares of a secret split with `split_secret_pedersen` to reconstruct it.
+/// The blinding shares are not needed.
+///
+/// # Arguments
+/// * `shares_map` - A `HashMap` where each key-value pair represents a primary share.
+///
+/// # Returns
+/// A `Result` containing the reconstructed secret as a `Vec<u8>`, or why the shares cannot be
+/// combined.
+///
+/// # Errors
+/// * Returns `Error::EmptyShares` if there are no shares.
+/// * Returns `Error::InvalidIndex` if a share is of index 0.
+/// * Returns `Error::UnequalShareLengths` if the shares are not all of the same length.
+/// * Returns `Error::MalformedShare` if a share is not a sequence of canonical scalars.
+/// * Returns `Error::InconsistentShares` if the shares rebuild no padded secret, as shares fewer
+///   than the threshold, or not all of the same split, almost always do. Shares that do not
+///   match the commitments should be told apart with `verify_share_pedersen` first.
+pub fn combine_shares_pedersen(shares_map: &HashMap<u8, Vec<u8>>) -> Result<Vec<u8>, Error> {
+    let lengths = shares_map.values().map(Vec::len);
+    let shortest = lengths.clone().min().ok_or(Error::EmptyShares)?;
+    let longest = lengths.max().unwrap_or(shortest);
+    if shortest != longest {
+        return Err(Error::UnequalShareLengths { shortest, longest });
+    }
+    if shares_map.contains_key(&0) {
+        return Err(Error::InvalidIndex);
+    }
+    if shortest == 0 || shortest % ELEMENT_BYTES != 0 {
+        return Err(Error::MalformedShare);
+    }
+
+    let chunks = shortest / ELEMENT_BYTES;
+    let mut points = Vec::with_capacity(shares_map.len());
+    for (&index, share) in shares_map {
+        points.push((Scalar::from(index), decode_scalars(share, chunks)?));
+    }
+
+    let xs: Vec<Scalar> = points.iter().map(|(x, _)| *x).collect();
+    let weights = lagrange_weights_at_zero(&xs);
+    let mut padded = Vec::with_capacity(chunks * CHUNK_BYTES);
+    for chunk in 0..chunks {
+        let constant: Scalar = points
+            .iter()
+            .zip(&weights)
+            .map(|((_, ys), weight)| ys[chunk] * weight)
+            .sum();
+        let bytes = constant.to_bytes();
+        // a chunk that does not fit in 31 bytes was not rebuilt from enough shares
+        if bytes[CHUNK_BYTES..].iter().any(|&b| b != 0) {
+            return Err(Error::InconsistentShares);
+        }
+        padded.extend_from_slice(&bytes[..CHUNK_BYTES]);
+    }
+
+    unpad(padded).ok_or(Error::InconsistentShares)
+}
+
+/// Derives the second generator of the commitments from `BLINDING_GENERATOR_DOMAIN`.
+fn blinding_generator() -> RistrettoPoint {
+    let mut uniform = [0u8; 64];
+    uniform.copy_from_slice(&Sha512::digest(BLINDING_GENERATOR_DOMAIN));
+    RistrettoPoint::from_uniform_bytes(&uniform)
+}
+
+/// Decodes a share holding `chunks` canonical scalars.
+fn decode_scalars(share: &[u8], chunks: usize) -> Result<Vec<Scalar>, Error> {
+    if share.len() != chunks * ELEMENT_BYTES {
+        return Err(Error::MalformedShare);
+    }
+    share
+        .chunks(ELEMENT_BYTES)
+        .map(|bytes| {
+            let mut encoded = [0u8; ELEMENT_BYTES];
+            encoded.copy_from_slice(bytes);
+            Option::from(Scalar::from_canonical_bytes(encoded)).ok_or(Error::MalformedShare)
+        })
+        .collect()
+}
+
+/// Pads `secret` with `PADDING_MARK` and as many zeroes as fill its last chunk.
+fn pad(secret: &[u8]) -> Vec<u8> {
+    let mut padded = secret.to_vec();
+    padded.push(PADDING_MARK);
+    padded.resize(padded.len().div_ceil(CHUNK_BYTES) * CHUNK_BYTES, 0);
+    padded
+}
+
+/// Strips the padding `pad` added, or returns `None` if there is none.
+fn unpad(mut padded: Vec<u8>) -> Option<Vec<u8>> {
+    let mark = padded.iter().rposition(|&b| b != 0)?;
+    if padded[mark] != PADDING_MARK {
+        return None;
+    }
+    padded.truncate(mark);
+    Some(padded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every subset of `size` of the indexes of `shares_map`.
+    fn subsets(shares_map: &HashMap<u8, Vec<u8>>, size: usize) -> Vec<Vec<u8>> {
+        let mut sorted: Vec<u8> = shares_map.keys().copied().collect();
+        sorted.sort();
+        (0u32..1 << sorted.len())
+            .filter(|mask| mask.count_ones() as usize == size)
+            .map(|mask| {
+                (0..sorted.len())
+                    .filter(|i| mask & (1 << i) != 0)
+                    .map(|i| sorted[i])
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_every_subset_at_the_threshold_combines_from_the_primary_shares() {
+        let secrets: [&[u8]; 5] = [b"", b"x", &[7; 31], &[0; 32], b"Remember the dormouse."];
+        for secret in secrets {
+            let split = split_secret_pedersen(secret, 3, 5).unwrap();
+            for (index, share) in &split.shares {
+                let blinding = &split.blindings[index];
+                verify_share_pedersen(*index, share, blinding, &split.commitments).unwrap();
+            }
+            for subset in subsets(&split.shares, 3) {
+                let primary: HashMap<u8, Vec<u8>> = subset
+                    .iter()
+                    .map(|i| (*i, split.shares[i].clone()))
+                    .collect();
+                assert_eq!(combine_shares_pedersen(&primary).unwrap(), secret);
+            }
+            // below the threshold the shares rebuild something else, if anything
+            for subset in subsets(&split.shares, 2) {
+                let primary: HashMap<u8, Vec<u8>> = subset
+                    .iter()
+                    .map(|i| (*i, split.shares[i].clone()))
+                    .collect();
+                assert_ne!(
+                    combine_shares_pedersen(&primary).ok().as_deref(),
+                    Some(secret)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_tampered_share_pairs_and_commitments_are_refused() {
+        let secret = b"a secret longer than one chunk of 31 bytes";
+        let split = split_secret_pedersen(secret, 2, 3).unwrap();
+        let (share, blinding) = (&split.shares[&2], &split.blindings[&2]);
+        assert_eq!(split.commitments.chunks.len(), 2);
+
+        let mut forged = share.clone();
+        forged[ELEMENT_BYTES] ^= 1;
+        let verified = verify_share_pedersen(2, &forged, blinding, &split.commitments);
+        assert_eq!(verified, Err(Error::CommitmentMismatch(1)));
+
+        let mut forged = blinding.clone();
+        forged[0] ^= 1;
+        let verified = verify_share_pedersen(2, share, &forged, &split.commitments);
+        assert_eq!(verified, Err(Error::CommitmentMismatch(0)));
+
+        // a share checked under another index, or the blinding share of another index
+        let verified = verify_share_pedersen(3, share, blinding, &split.commitments);
+        assert_eq!(verified, Err(Error::CommitmentMismatch(0)));
+        let other = &split.blindings[&1];
+        let verified = verify_share_pedersen(2, share, other, &split.commitments);
+        assert_eq!(verified, Err(Error::CommitmentMismatch(0)));
+        let verified = verify_share_pedersen(0, share, blinding, &split.commitments);
+        assert_eq!(verified, Err(Error::InvalidIndex));
+
+        let verified = verify_share_pedersen(2, &share[1..], blinding, &split.commitments);
+        assert_eq!(verified, Err(Error::MalformedShare));
+        let mut non_canonical = share.clone();
+        non_canonical[..ELEMENT_BYTES].fill(0xff);
+        let verified = verify_share_pedersen(2, &non_canonical, blinding, &split.commitments);
+        assert_eq!(verified, Err(Error::MalformedShare));
+
+        let mut commitments = split.commitments.clone();
+        commitments.chunks[1].pop();
+        let verified = verify_share_pedersen(2, share, blinding, &commitments);
+        assert_eq!(verified, Err(Error::MalformedCommitment(1)));
+        let mut commitments = split.commitments.clone();
+        commitments.chunks[0][1] = [0xff; 32];
+        let verified = verify_share_pedersen(2, share, blinding, &commitments);
+        assert_eq!(verified, Err(Error::MalformedCommitment(0)));
+        let empty = PedersenCommitments { chunks: Vec::new() };
+        let verified = verify_share_pedersen(2, &[], &[], &empty);
+        assert_eq!(verified, Err(Error::MalformedCommitment(0)));
+        let mut commitments = split.commitments.clone();
+        commitments.chunks[0].swap(0, 1);
+        let verified = verify_share_pedersen(2, share, blinding, &commitments);
+        assert_eq!(verified, Err(Error::CommitmentMismatch(0)));
+    }
+
+    #[test]
+    fn test_commitments_do_not_reveal_the_secret() {
+        let secret = b"the same secret";
+        let first = split_secret_pedersen(secret, 2, 3).unwrap();
+        let second = split_secret_pedersen(secret, 2, 3).unwrap();
+        assert_ne!(first.commitments, second.commitments);
+
+        // unlike a Feldman commitment, the constant term is not `secret * G`
+        let mut constant = [0u8; ELEMENT_BYTES];
+        constant[..CHUNK_BYTES].copy_from_slice(&pad(secret));
+        let feldman = RISTRETTO_BASEPOINT_POINT * Scalar::from_bytes_mod_order(constant);
+        assert_ne!(
+            first.commitments.chunks[0][0],
+            feldman.compress().to_bytes()
+        );
+    }
+
+    #[test]
+    fn test_invalid_parameters_and_malformed_shares() {
+        assert_eq!(
+            split_secret_pedersen(b"s", 1, 3),
+            Err(Error::InvalidThreshold)
+        );
+        assert_eq!(split_secret_pedersen(b"s", 4, 3), Err(Error::InvalidCount));
+        assert_eq!(
+            split_secret_pedersen(b"s", 2, 256),
+            Err(Error::InvalidCount)
+        );
+        assert_eq!(
+            split_secret_pedersen(b"s", 2, 255).unwrap().shares.len(),
+            255
+        );
+
+        let split = split_secret_pedersen(b"s", 2, 3).unwrap();
+        assert_eq!(
+            combine_shares_pedersen(&HashMap::new()),
+            Err(Error::EmptyShares)
+        );
+        let mut truncated = split.shares.clone();
+        truncated.get_mut(&1).unwrap().pop();
+        assert_eq!(
+            combine_shares_pedersen(&truncated),
+            Err(Error::UnequalShareLengths {
+                shortest: ELEMENT_BYTES - 1,
+                longest: ELEMENT_BYTES
+            })
+        );
+        let mut at_zero = split.shares.clone();
+        at_zero.insert(0, split.shares[&1].clone());
+        assert_eq!(combine_shares_pedersen(&at_zero), Err(Error::InvalidIndex));
+        let mut non_canonical = split.shares.clone();
+        non_canonical.get_mut(&1).unwrap().fill(0xff);
+        assert_eq!(
+            combine_shares_pedersen(&non_canonical),
+            Err(Error::MalformedShare)
+        );
+        // a secret of several chunks, for garbage to fit none of them but by a negligible chance
+        let split = split_secret_pedersen(&[1; 100], 2, 3).unwrap();
+        let other = split_secret_pedersen(&[1; 100], 2, 3).unwrap();
+        let mut mixed = split.shares.clone();
+        mixed.insert(2, other.shares[&2].clone());
+        assert_eq!(
+            combine_shares_pedersen(&mixed),
+            Err(Error::InconsistentShares)
+        );
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+
+pub use curve25519_dalek::scalar::Scalar;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use super::Error;
+
+/// The length in bytes of the canonical encoding of a scalar.
+const SCALAR_BYTES: usize = 32;
+
+/// Represents a polynomial over the prime field of the scalars of Ristretto, of order
+/// 2^252 + 27742317777372353535851937790883648493.
+///
+/// The coefficients serialize as their canonical 32-byte encodings, and only canonical ones
+/// deserialize.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScalarPolynomial {
+    /// The coefficients of the polynomial, from the constant term up.
+    pub coefficients: Vec<Scalar>,
+}
+
+impl ScalarPolynomial {
+    /// Constructs a new polynomial of a given degree with random coefficients,
+    /// where the constant term is the provided secret.
+    ///
+    /// # Arguments
+    ///
+    /// * `degree` - The degree of the polynomial.
+    /// * `secret` - The secret (constant term) of the polynomial.
+    pub fn new(degree: usize, secret: Scalar) -> Self {
+        ScalarPolynomial {
+            coefficients: random_polynomial(degree + 1, secret),
+        }
+    }
+
+    /// Evaluates the polynomial at a given point.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The point at which to evaluate the polynomial.
+    ///
+    /// # Returns
+    ///
+    /// The value of the polynomial at point `x`.
+    pub fn evaluate(&self, x: Scalar) -> Scalar {
+        evaluate(&self.coefficients, x)
+    }
+}
+
+/// Splits a scalar into a specified number of shares over the prime field of the scalars, such
+/// as a signing key to be shared with a threshold signature scheme.
+///
+/// Unlike `split_secret`, which shares each byte of a secret on its own, the scalar is the
+/// constant term of a single polynomial, and each share is its value at the index of the share.
+///
+/// # Arguments
+/// * `secret` - The scalar to be split.
+/// * `threshold` - The minimum number of shares required to reconstruct the scalar.
+/// * `shares` - The total number of shares to be created, at most 255.
+///
+/// # Returns
+/// A `Result` containing either a `HashMap` of scalar shares by index, or an `Error`.
+///
+/// # Errors
+/// Returns an error if the threshold is invalid (<= 1), or if the number of shares is less than
+/// the threshold or more than 255.
+///
+/// # Examples
+/// ```rust
+/// use shard::sss::{combine_scalars, split_scalar, Scalar};
+///
+/// let secret = Scalar::from(1234567890u64);
+/// let mut shares = split_scalar(&secret, 3, 5).unwrap();
+/// shares.retain(|&index, _| index > 2);
+/// assert_eq!(combine_scalars(&shares).unwrap(), secret);
+/// ```
+pub fn split_scalar(
+    secret: &Scalar,
+    threshold: usize,
+    shares: usize,
+) -> Result<HashMap<u8, Scalar>, Error> {
+    if threshold <= 1 {
+        return Err(Error::InvalidThreshold);
+    }
+
+    if shares < threshold || shares > u8::MAX as usize {
+        return Err(Error::InvalidCount);
+    }
+
+    let poly = ScalarPolynomial::new(threshold - 1, *secret);
+    Ok((1..=shares as u8)
+        .map(|i| (i, poly.evaluate(Scalar::from(i))))
+        .collect())
+}
+
+/// Combines scalar shares split with `split_scalar` to reconstruct the scalar.
+///
+/// # Arguments
+/// * `shares_map` - The scalar shares by index.
+///
+/// # Returns
+/// A `Result` containing the reconstructed scalar, or why the shares cannot be combined.
+///
+/// # Errors
+/// * Returns `Error::EmptyShares` if there are no shares.
+/// * Returns `Error::InvalidIndex` if a share is of index 0.
+///
+/// Every scalar is a valid secret, so that shares fewer than the threshold rebuild another
+/// scalar rather than fail.
+pub fn combine_scalars(shares_map: &HashMap<u8, Scalar>) -> Result<Scalar, Error> {
+    if shares_map.is_empty() {
+        return Err(Error::EmptyShares);
+    }
+    if shares_map.contains_key(&0) {
+        return Err(Error::InvalidIndex);
+    }
+
+    let xs: Vec<Scalar> = shares_map.keys().map(|&i| Scalar::from(i)).collect();
+    let weights = lagrange_weights_at_zero(&xs);
+    Ok(shares_map.values().zip(&weights).map(|(y, w)| y * w).sum())
+}
+
+/// Encodes scalar shares as the bytes of their canonical encodings, to be stored or sent as
+/// shares split with `split_secret` are.
+///
+/// # Arguments
+/// * `shares_map` - The scalar shares by index.
+///
+/// # Returns
+/// The 32-byte encodings of the shares by index.
+pub fn encode_scalars(shares_map: &HashMap<u8, Scalar>) -> HashMap<u8, Vec<u8>> {
+    shares_map
+        .iter()
+        .map(|(&index, share)| (index, share.to_bytes().to_vec()))
+        .collect()
+}
+
+/// Decodes scalar shares encoded with `encode_scalars`.
+///
+/// # Arguments
+/// * `shares_map` - The encoded shares by index.
+///
+/// # Returns
+/// A `Result` containing the scalar shares by index, or why one cannot be decoded.
+///
+/// # Errors
+/// Returns `Error::MalformedShare` if a share is not the canonical encoding of a scalar.
+pub fn decode_scalars(shares_map: &HashMap<u8, Vec<u8>>) -> Result<HashMap<u8, Scalar>, Error> {
+    shares_map
+        .iter()
+        .map(|(&index, share)| {
+            let encoded: [u8; SCALAR_BYTES] = share
+                .as_slice()
+                .try_into()
+                .map_err(|_| Error::MalformedShare)?;
+            let scalar = Option::from(Scalar::from_canonical_bytes(encoded));
+            Ok((index, scalar.ok_or(Error::MalformedShare)?))
+        })
+        .collect()
+}
+
+/// Refreshes scalar shares split with `split_scalar` without changing the scalar they encode.
+///
+/// # Arguments
+/// * `shares_map` - The scalar shares by index.
+/// * `threshold` - The minimum number of shares required to reconstruct the scalar.
+///
+/// # Errors
+/// * Returns `Error::InvalidThreshold` if `threshold` is less than or equal to 1.
+/// * Returns `Error::EmptyShares` if the `shares_map` is empty.
+/// * Returns `Error::InvalidIndex` if a share is of index 0.
+pub fn refresh_scalars(
+    shares_map: &mut HashMap<u8, Scalar>,
+    threshold: usize,
+) -> Result<(), Error> {
+    let poly = generate_scalar_refresh_key(threshold)?;
+    if shares_map.is_empty() {
+        return Err(Error::EmptyShares);
+    }
+    if shares_map.contains_key(&0) {
+        return Err(Error::InvalidIndex);
+    }
+    for share in shares_map.iter_mut() {
+        refresh_scalar(share, &poly)?;
+    }
+    Ok(())
+}
+
+/// Refreshes a single scalar share by adding the value of the refresh key at its index, as
+/// `refresh_share` does for each byte of a share split with `split_secret`.
+///
+/// # Arguments
+/// * `share` - A tuple containing the share's index and a mutable reference to the share.
+/// * `polynomial` - The refresh key, the same for every share of the scalar.
+///
+/// # Errors
+/// Returns `Error::InvalidIndex` if the share is of index 0.
+pub fn refresh_scalar(
+    share: (&u8, &mut Scalar),
+    polynomial: &ScalarPolynomial,
+) -> Result<(), Error> {
+    if *share.0 == 0 {
+        return Err(Error::InvalidIndex);
+    }
+    *share.1 += polynomial.evaluate(Scalar::from(*share.0));
+    Ok(())
+}
+
+/// Generates the polynomial to refresh scalar shares with, of a zero constant term so that the
+/// scalar is unchanged.
+///
+/// # Arguments
+/// * `threshold` - The minimum number of shares required to reconstruct the scalar.
+///
+/// # Errors
+/// Returns `Error::InvalidThreshold` if the `threshold` is less than or equal to 1.
+pub fn generate_scalar_refresh_key(threshold: usize) -> Result<ScalarPolynomial, Error> {
+    if threshold <= 1 {
+        return Err(Error::InvalidThreshold);
+    }
+
+    Ok(ScalarPolynomial::new(threshold - 1, Scalar::ZERO))
+}
+
+/// Checks that a refresh key can be applied to scalar shares split with `threshold` without
+/// changing the scalar, as `validate_refresh_key` does for shares split with `split_secret`.
+///
+/// # Arguments
+/// * `polynomial` - The refresh key to check.
+/// * `threshold` - The threshold the shares were split with.
+///
+/// # Errors
+/// * Returns `Error::NonZeroConstant(0)` if the polynomial has a non-zero constant term.
+/// * Returns `Error::KeyDegree` if the polynomial does not have exactly `threshold`
+///   coefficients.
+pub fn validate_scalar_refresh_key(
+    polynomial: &ScalarPolynomial,
+    threshold: usize,
+) -> Result<(), Error> {
+    if polynomial.coefficients.first() != Some(&Scalar::ZERO) {
+        return Err(Error::NonZeroConstant(0));
+    }
+    if polynomial.coefficients.len() != threshold {
+        return Err(Error::KeyDegree {
+            index: 0,
+            coefficients: polynomial.coefficients.len(),
+            threshold,
+        });
+    }
+    Ok(())
+}
+
+/// Samples a scalar uniformly at random.
+pub(super) fn random_scalar() -> Scalar {
+    let mut wide = [0u8; 64];
+    rand::thread_rng().fill_bytes(&mut wide);
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
+/// Samples the `threshold` coefficients of a polynomial with the constant term `constant`.
+pub(super) fn random_polynomial(threshold: usize, constant: Scalar) -> Vec<Scalar> {
+    let mut coefficients = vec![constant];
+    coefficients.extend((1..threshold).map(|_| random_scalar()));
+    coefficients
+}
+
+/// Evaluates the polynomial of `coefficients` at `x`.
+pub(super) fn evaluate(coefficients: &[Scalar], x: Scalar) -> Scalar {
+    coefficients
+        .iter()
+        .rev()
+        .fold(Scalar::ZERO, |value, coefficient| value * x + coefficient)
+}
+
+/// The weights that interpolate the value at zero of the polynomial through points at `xs`.
+pub(super) fn lagrange_weights_at_zero(xs: &[Scalar]) -> Vec<Scalar> {
+    xs.iter()
+        .enumerate()
+        .map(|(i, x_i)| {
+            let (top, bottom) = xs
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .fold((Scalar::ONE, Scalar::ONE), |(top, bottom), (_, x_j)| {
+                    (top * x_j, bottom * (x_j - x_i))
+                });
+            top * bottom.invert()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_pair_of_scalar_shares_combines() {
+        let secret = random_scalar();
+        let shares_map = split_scalar(&secret, 2, 4).unwrap();
+        assert_eq!(shares_map.len(), 4);
+        for i in 1..=4u8 {
+            for j in i + 1..=4 {
+                let pair = [(i, shares_map[&i]), (j, shares_map[&j])].into();
+                assert_eq!(combine_scalars(&pair).unwrap(), secret);
+            }
+            let alone = [(i, shares_map[&i])].into();
+            assert_ne!(combine_scalars(&alone).unwrap(), secret);
+        }
+
+        assert_eq!(split_scalar(&secret, 1, 3), Err(Error::InvalidThreshold));
+        assert_eq!(split_scalar(&secret, 3, 2), Err(Error::InvalidCount));
+        assert_eq!(split_scalar(&secret, 3, 256), Err(Error::InvalidCount));
+        assert_eq!(split_scalar(&secret, 3, 255).unwrap().len(), 255);
+        assert_eq!(combine_scalars(&HashMap::new()), Err(Error::EmptyShares));
+        let at_zero = [(0, secret), (1, secret)].into();
+        assert_eq!(combine_scalars(&at_zero), Err(Error::InvalidIndex));
+    }
+
+    #[test]
+    fn test_refreshed_scalar_shares_still_combine() {
+        let secret = random_scalar();
+        let mut shares_map = split_scalar(&secret, 3, 5).unwrap();
+        let before = shares_map.clone();
+        refresh_scalars(&mut shares_map, 3).unwrap();
+        assert!(shares_map.iter().all(|(i, share)| before[i] != *share));
+        assert_eq!(combine_scalars(&shares_map).unwrap(), secret);
+
+        // a key sent to every holder, as refresh keys of byte shares are
+        let key = generate_scalar_refresh_key(3).unwrap();
+        let sent: ScalarPolynomial =
+            serde_json::from_slice(&serde_json::to_vec(&key).unwrap()).unwrap();
+        assert_eq!(sent, key);
+        validate_scalar_refresh_key(&sent, 3).unwrap();
+        for share in shares_map.iter_mut() {
+            refresh_scalar(share, &sent).unwrap();
+        }
+        shares_map.remove(&1);
+        shares_map.remove(&4);
+        assert_eq!(combine_scalars(&shares_map).unwrap(), secret);
+
+        assert_eq!(
+            validate_scalar_refresh_key(&key, 4),
+            Err(Error::KeyDegree {
+                index: 0,
+                coefficients: 3,
+                threshold: 4
+            })
+        );
+        let shifting = ScalarPolynomial::new(2, Scalar::ONE);
+        assert_eq!(
+            validate_scalar_refresh_key(&shifting, 3),
+            Err(Error::NonZeroConstant(0))
+        );
+        assert_eq!(
+            refresh_scalars(&mut shares_map, 1),
+            Err(Error::InvalidThreshold)
+        );
+        assert_eq!(
+            refresh_scalars(&mut HashMap::new(), 3),
+            Err(Error::EmptyShares)
+        );
+        let mut at_zero = Scalar::ONE;
+        assert_eq!(
+            refresh_scalar((&0, &mut at_zero), &key),
+            Err(Error::InvalidIndex)
+        );
+    }
+
+    #[test]
+    fn test_scalar_shares_travel_as_bytes() {
+        let secret = random_scalar();
+        let shares_map = split_scalar(&secret, 2, 3).unwrap();
+        let encoded = encode_scalars(&shares_map);
+        assert!(encoded.values().all(|share| share.len() == SCALAR_BYTES));
+        assert_eq!(decode_scalars(&encoded).unwrap(), shares_map);
+
+        let json = serde_json::to_vec(&shares_map).unwrap();
+        let sent: HashMap<u8, Scalar> = serde_json::from_slice(&json).unwrap();
+        assert_eq!(combine_scalars(&sent).unwrap(), secret);
+
+        let mut truncated = encoded.clone();
+        truncated.get_mut(&1).unwrap().pop();
+        assert_eq!(decode_scalars(&truncated), Err(Error::MalformedShare));
+        let mut non_canonical = encoded;
+        non_canonical.insert(2, vec![0xff; SCALAR_BYTES]);
+        assert_eq!(decode_scalars(&non_canonical), Err(Error::MalformedShare));
+    }
+}
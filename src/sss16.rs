@@ -0,0 +1,491 @@
+use core::fmt;
+use gf256::gf2p16;
+use rand::Rng;
+use serde::{
+    de::{SeqAccess, Visitor},
+    ser::SerializeSeq,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::collections::HashMap;
+
+pub use crate::sss::Error;
+
+/// The largest number of shares a secret can be split into: every index but 0, where the secret
+/// itself is.
+pub const MAX_SHARES: usize = u16::MAX as usize;
+
+/// The length in bytes of the field element each byte of the secret is shared as.
+const ELEMENT_BYTES: usize = 2;
+
+/// Represents a polynomial over the Galois field GF(2^16).
+///
+/// Each polynomial is represented by its coefficients, stored in a vector.
+/// Coefficients are elements of the GF(2^16) field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Polynomial {
+    /// The coefficients of the polynomial, where each coefficient is an element of GF(2^16).
+    pub coefficients: Vec<gf2p16>,
+}
+
+impl Polynomial {
+    /// Constructs a new polynomial of a given degree with random coefficients,
+    /// where the constant term is the provided secret.
+    ///
+    /// # Arguments
+    ///
+    /// * `degree` - The degree of the polynomial.
+    /// * `secret` - The secret (constant term) of the polynomial.
+    pub fn new(degree: usize, secret: gf2p16) -> Self {
+        let mut rng = rand::thread_rng();
+        let mut coefficients = vec![secret; degree + 1];
+
+        for coeff in coefficients.iter_mut().skip(1) {
+            *coeff = gf2p16::new(rng.gen());
+        }
+
+        Polynomial { coefficients }
+    }
+
+    /// Evaluates the polynomial at a given point.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The point at which to evaluate the polynomial.
+    ///
+    /// # Returns
+    ///
+    /// The value of the polynomial at point `x`.
+    pub fn evaluate(&self, x: gf2p16) -> gf2p16 {
+        // Horner's rule from the highest coefficient down
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(gf2p16::new(0), |value, &coeff| value * x + coeff)
+    }
+}
+
+/// Implements serialization for `Polynomial` as a sequence of 16-bit coefficients.
+impl Serialize for Polynomial {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.coefficients.len()))?;
+        for &gf in &self.coefficients {
+            seq.serialize_element(&u16::from(gf))?;
+        }
+        seq.end()
+    }
+}
+
+/// Implements deserialization for `Polynomial` from a sequence of 16-bit coefficients.
+impl<'de> Deserialize<'de> for Polynomial {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        /// A visitor to handle the deserialization of `Polynomial`.
+        struct PolynomialVisitor;
+
+        impl<'de> Visitor<'de> for PolynomialVisitor {
+            type Value = Polynomial;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a sequence of 16-bit polynomial coefficients")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Polynomial, V::Error>
+            where
+                V: SeqAccess<'de>,
+            {
+                let mut coefficients = Vec::new();
+                while let Some(coeff) = seq.next_element()? {
+                    coefficients.push(gf2p16::new(coeff));
+                }
+                Ok(Polynomial { coefficients })
+            }
+        }
+
+        deserializer.deserialize_seq(PolynomialVisitor)
+    }
+}
+
+/// Splits a secret into a specified number of shares using Shamir's Secret Sharing Scheme over
+/// GF(2^16), so that it can be split into up to 65,535 shares. Providers store them registered
+/// with `ShareField::Gf65536`, but do not refresh them.
+///
+/// Each byte of the secret is the constant term of its own polynomial, and each share holds the
+/// value of every polynomial at its index as two big-endian bytes, so that a share is twice as
+/// long as the secret.
+///
+/// # Arguments
+/// * `secret` - A byte slice representing the secret to be split.
+/// * `threshold` - The minimum number of shares required to reconstruct the secret.
+/// * `shares` - The total number of shares to be created, at most `MAX_SHARES`.
+///
+/// # Returns
+/// A `Result` containing either a `HashMap` of shares by their 16-bit index, or an `Error`.
+///
+/// # Errors
+/// Returns an error if the threshold is invalid (<= 1), or if the number of shares is less than
+/// the threshold or more than `MAX_SHARES`.
+///
+/// # Examples
+/// ```rust
+/// use shard::sss16::{combine_shares, split_secret};
+///
+/// let shares = split_secret(b"hello world", 3, 1000).unwrap();
+/// assert_eq!(shares.len(), 1000);
+/// let subset = shares.into_iter().filter(|(index, _)| *index > 997).collect();
+/// assert_eq!(combine_shares(&subset).unwrap(), b"hello world");
+/// ```
+pub fn split_secret(
+    secret: &[u8],
+    threshold: usize,
+    shares: usize,
+) -> Result<HashMap<u16, Vec<u8>>, Error> {
+    if threshold <= 1 {
+        return Err(Error::InvalidThreshold);
+    }
+
+    if shares < threshold || shares > MAX_SHARES {
+        return Err(Error::InvalidCount);
+    }
+
+    let mut shares_map: HashMap<u16, Vec<u8>> = (1..=shares as u16)
+        .map(|i| (i, Vec::with_capacity(secret.len() * ELEMENT_BYTES)))
+        .collect();
+
+    for &byte in secret {
+        let poly = Polynomial::new(threshold - 1, gf2p16::new(u16::from(byte)));
+
+        for (&i, share) in shares_map.iter_mut() {
+            let y = u16::from(poly.evaluate(gf2p16::new(i)));
+            share.extend_from_slice(&y.to_be_bytes());
+        }
+    }
+
+    Ok(shares_map)
+}
+
+/// Adds a share to the shares collected to combine, refusing a different share under an index
+/// already held, as `sss::insert_share` does for 8-bit indexes.
+///
+/// # Arguments
+/// * `shares_map` - The shares collected so far, by index.
+/// * `index` - The index of the share.
+/// * `share` - The share.
+///
+/// # Errors
+/// Returns `Error::DuplicateWideIndex` if a different share is held under `index` already.
+pub fn insert_share(
+    shares_map: &mut HashMap<u16, Vec<u8>>,
+    index: u16,
+    share: Vec<u8>,
+) -> Result<(), Error> {
+    match shares_map.get(&index) {
+        Some(held) if *held != share => Err(Error::DuplicateWideIndex(index)),
+        Some(_) => Ok(()),
+        None => {
+            shares_map.insert(index, share);
+            Ok(())
+        }
+    }
+}
+
+/// Combines shares split with `split_secret` to reconstruct a secret.
+///
+/// # Arguments
+/// * `shares_map` - A `HashMap` where each key-value pair represents a share of the secret.
+///
+/// # Returns
+/// A `Result` containing the reconstructed secret as a `Vec<u8>`, or why the shares cannot be
+/// combined.
+///
+/// # Errors
+/// * Returns `Error::EmptyShares` if there are no shares.
+/// * Returns `Error::InvalidIndex` if a share is of index 0.
+/// * Returns `Error::UnequalShareLengths` if the shares are not all of the same length.
+/// * Returns `Error::MalformedShare` if the shares are of an odd length.
+/// * Returns `Error::InconsistentShares` if a value rebuilt is not a byte, as shares fewer than
+///   the threshold, or not all of the same split, mostly rebuild.
+pub fn combine_shares(shares_map: &HashMap<u16, Vec<u8>>) -> Result<Vec<u8>, Error> {
+    let lengths = shares_map.values().map(Vec::len);
+    let shortest = lengths.clone().min().ok_or(Error::EmptyShares)?;
+    let longest = lengths.max().unwrap_or(shortest);
+    if shortest != longest {
+        return Err(Error::UnequalShareLengths { shortest, longest });
+    }
+    if shares_map.contains_key(&0) {
+        return Err(Error::InvalidIndex);
+    }
+    if shortest % ELEMENT_BYTES != 0 {
+        return Err(Error::MalformedShare);
+    }
+
+    let xs: Vec<gf2p16> = shares_map.keys().map(|&k| gf2p16::new(k)).collect();
+    let weights = lagrange_weights_at_zero(&xs);
+    let mut secret = Vec::with_capacity(shortest / ELEMENT_BYTES);
+
+    for i in (0..shortest).step_by(ELEMENT_BYTES) {
+        let value =
+            shares_map
+                .values()
+                .zip(&weights)
+                .fold(gf2p16::new(0), |value, (share, &weight)| {
+                    let y = u16::from_be_bytes([share[i], share[i + 1]]);
+                    value + weight * gf2p16::new(y)
+                });
+        let byte = u8::try_from(u16::from(value)).map_err(|_| Error::InconsistentShares)?;
+        secret.push(byte);
+    }
+
+    Ok(secret)
+}
+
+/// The weights that interpolate the value at zero of the polynomial through points at `xs`.
+///
+/// They only depend on the indexes of the shares, so they are computed once for every byte of
+/// the secret.
+fn lagrange_weights_at_zero(xs: &[gf2p16]) -> Vec<gf2p16> {
+    xs.iter()
+        .enumerate()
+        .map(|(i, &x_i)| {
+            xs.iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                // at zero, x - x_j is x_j, and subtraction is addition in GF(2^16)
+                .fold(gf2p16::new(1), |weight, (_, &x_j)| {
+                    weight * (x_j / (x_i + x_j))
+                })
+        })
+        .collect()
+}
+
+/// Refreshes the shares of a secret split with `split_secret` without changing the secret.
+///
+/// # Arguments
+///
+/// * `shares_map` - A mutable reference to a `HashMap` representing the shares.
+/// * `threshold` - The minimum number of shares required to reconstruct the secret.
+///
+/// # Errors
+///
+/// * Returns `Error::InvalidThreshold` if `threshold` is less than or equal to 1.
+/// * Returns `Error::EmptyShares` if the `shares_map` is empty.
+pub fn refresh_shares(
+    shares_map: &mut HashMap<u16, Vec<u8>>,
+    threshold: usize,
+) -> Result<(), Error> {
+    let share_length = shares_map.values().next().ok_or(Error::EmptyShares)?.len();
+    let polynomials = generate_refresh_key(threshold, share_length / ELEMENT_BYTES)?;
+    for share in shares_map.iter_mut() {
+        refresh_share(share, &polynomials)?;
+    }
+    Ok(())
+}
+
+/// Refreshes a single share split with `split_secret`, adding the value of a polynomial of the
+/// refresh key at its index to each of its elements.
+///
+/// # Arguments
+///
+/// * `share` - A tuple containing the share's index and a mutable reference to the share's value.
+/// * `polynomials` - The refresh key, one polynomial per byte of the secret.
+///
+/// # Errors
+///
+/// * Returns `Error::EmptyShare` if the share is empty.
+/// * Returns `Error::LengthMismatch` if the share does not hold one element per polynomial.
+pub fn refresh_share(share: (&u16, &mut Vec<u8>), polynomials: &[Polynomial]) -> Result<(), Error> {
+    if share.1.is_empty() {
+        return Err(Error::EmptyShare);
+    }
+
+    if share.1.len() != polynomials.len() * ELEMENT_BYTES {
+        return Err(Error::LengthMismatch);
+    }
+
+    let x = gf2p16::new(*share.0);
+    for (element, poly) in share.1.chunks_mut(ELEMENT_BYTES).zip(polynomials) {
+        let y = gf2p16::new(u16::from_be_bytes([element[0], element[1]])) + poly.evaluate(x);
+        element.copy_from_slice(&u16::from(y).to_be_bytes());
+    }
+
+    Ok(())
+}
+
+/// Generates the polynomials to refresh shares split with `split_secret`, each with a zero
+/// constant term so that the secret is unchanged.
+///
+/// # Arguments
+///
+/// * `threshold` - The minimum number of shares required to reconstruct the secret.
+/// * `secret_length` - The length of the secret in bytes.
+///
+/// # Errors
+///
+/// * Returns `Error::InvalidThreshold` if the `threshold` is less than or equal to 1.
+pub fn generate_refresh_key(
+    threshold: usize,
+    secret_length: usize,
+) -> Result<Vec<Polynomial>, Error> {
+    if threshold <= 1 {
+        return Err(Error::InvalidThreshold);
+    }
+
+    Ok((0..secret_length)
+        .map(|_| Polynomial::new(threshold - 1, gf2p16::new(0)))
+        .collect())
+}
+
+/// Checks that a refresh key can be applied to a share split with `split_secret` without
+/// changing the secret it encodes, as `sss::validate_refresh_key` does for GF(2^8).
+///
+/// # Arguments
+///
+/// * `polynomials` - The refresh key to check.
+/// * `threshold` - The threshold the share was split with.
+/// * `share_length` - The length of the share in bytes, two per byte of the secret.
+///
+/// # Errors
+///
+/// * Returns `Error::KeyLength` if the key does not hold one polynomial per element of the share.
+/// * Returns `Error::NonZeroConstant` if a polynomial has a non-zero constant term.
+/// * Returns `Error::KeyDegree` if a polynomial does not have exactly `threshold` coefficients.
+pub fn validate_refresh_key(
+    polynomials: &[Polynomial],
+    threshold: usize,
+    share_length: usize,
+) -> Result<(), Error> {
+    if polynomials.len() * ELEMENT_BYTES != share_length {
+        return Err(Error::KeyLength {
+            polynomials: polynomials.len(),
+            share_length,
+        });
+    }
+
+    for (i, poly) in polynomials.iter().enumerate() {
+        if poly.coefficients.first() != Some(&gf2p16::new(0)) {
+            return Err(Error::NonZeroConstant(i));
+        }
+        if poly.coefficients.len() != threshold {
+            return Err(Error::KeyDegree {
+                index: i,
+                coefficients: poly.coefficients.len(),
+                threshold,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::seq::IteratorRandom;
+
+    use super::*;
+    use crate::sss;
+
+    const SECRET: &[u8] = b"Remember what the dormouse said.";
+
+    /// A random subset of `count` of `shares_map`.
+    fn subset(shares_map: &HashMap<u16, Vec<u8>>, count: usize) -> HashMap<u16, Vec<u8>> {
+        let mut rng = rand::thread_rng();
+        shares_map
+            .iter()
+            .choose_multiple(&mut rng, count)
+            .into_iter()
+            .map(|(&key, value)| (key, value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_300_of_1000_split_reconstructs() {
+        let shares_map = split_secret(SECRET, 300, 1000).unwrap();
+        assert_eq!(shares_map.len(), 1000);
+        assert!(shares_map.keys().any(|&index| index > u8::MAX as u16));
+        assert!(shares_map.values().all(|v| v.len() == 2 * SECRET.len()));
+
+        let at_threshold = subset(&shares_map, 300);
+        assert_eq!(combine_shares(&at_threshold).unwrap(), SECRET);
+        let below = subset(&shares_map, 299);
+        assert_ne!(combine_shares(&below).ok().as_deref(), Some(SECRET));
+    }
+
+    #[test]
+    fn test_wide_and_byte_splits_rebuild_the_same_secret() {
+        // what fits in GF(2^8) rebuilds alike in both fields, and GF(2^16) goes beyond it
+        let narrow = sss::split_secret(SECRET, 3, 255).unwrap();
+        let wide = split_secret(SECRET, 3, 255).unwrap();
+        assert_eq!(
+            sss::combine_shares(&narrow).unwrap(),
+            combine_shares(&wide).unwrap()
+        );
+        let beyond = split_secret(SECRET, 3, 300).unwrap();
+        assert_eq!(combine_shares(&subset(&beyond, 3)).unwrap(), SECRET);
+        assert_eq!(
+            split_secret(SECRET, 3, MAX_SHARES).unwrap().len(),
+            MAX_SHARES
+        );
+        assert_eq!(
+            split_secret(SECRET, 3, MAX_SHARES + 1),
+            Err(Error::InvalidCount)
+        );
+        assert_eq!(split_secret(SECRET, 1, 3), Err(Error::InvalidThreshold));
+    }
+
+    #[test]
+    fn test_refreshed_wide_shares_still_combine() {
+        let mut shares_map = split_secret(SECRET, 3, 400).unwrap();
+        let before = shares_map.clone();
+        refresh_shares(&mut shares_map, 3).unwrap();
+        assert_ne!(shares_map, before);
+        assert_eq!(combine_shares(&subset(&shares_map, 3)).unwrap(), SECRET);
+
+        let key = generate_refresh_key(3, SECRET.len()).unwrap();
+        assert!(validate_refresh_key(&key, 3, 2 * SECRET.len()).is_ok());
+        assert!(validate_refresh_key(&key, 3, SECRET.len()).is_err());
+        assert!(validate_refresh_key(&key, 4, 2 * SECRET.len()).is_err());
+        for share in shares_map.iter_mut() {
+            refresh_share(share, &key).unwrap();
+        }
+        assert_eq!(combine_shares(&subset(&shares_map, 3)).unwrap(), SECRET);
+
+        let bytes = serde_json::to_vec(&key[0]).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Polynomial>(&bytes).unwrap(),
+            key[0]
+        );
+    }
+
+    #[test]
+    fn test_combine_rejects_broken_shares() {
+        assert_eq!(combine_shares(&HashMap::new()), Err(Error::EmptyShares));
+
+        let shares_map = split_secret(b"broken", 2, 3).unwrap();
+        let mut truncated = shares_map.clone();
+        truncated.get_mut(&2).unwrap().pop();
+        assert_eq!(
+            combine_shares(&truncated),
+            Err(Error::UnequalShareLengths {
+                shortest: 11,
+                longest: 12
+            })
+        );
+        let odd: HashMap<u16, Vec<u8>> = [(1, vec![0]), (2, vec![0])].into();
+        assert_eq!(combine_shares(&odd), Err(Error::MalformedShare));
+
+        let mut at_zero = shares_map.clone();
+        at_zero.insert(0, shares_map[&1].clone());
+        assert_eq!(combine_shares(&at_zero), Err(Error::InvalidIndex));
+
+        let mut collected = HashMap::new();
+        insert_share(&mut collected, 300, shares_map[&1].clone()).unwrap();
+        assert_eq!(
+            insert_share(&mut collected, 300, shares_map[&2].clone()),
+            Err(Error::DuplicateWideIndex(300))
+        );
+    }
+}
@@ -0,0 +1,1263 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use futures::channel::{mpsc, oneshot};
+use futures::stream::BoxStream;
+use futures::{future, SinkExt, StreamExt};
+use libp2p::identity::Keypair;
+use libp2p::{Multiaddr, PeerId};
+use tokio_util::sync::CancellationToken;
+
+use crate::client::{Client, NotReady, ReadyCriteria};
+use crate::command::Command;
+use crate::constants::{PROVIDER_EVENT_BUFFER, READY_POLL_MILLIS};
+use crate::event::Event;
+use crate::network;
+use crate::protocol::{FieldShare, Request};
+use crate::provider::{dao, run_with_dao, DaoOptions, ProviderEvent, ProviderOptions, SharedDao};
+use crate::runtime::{self, Instant};
+
+/// How long a `TestNet` waits for its nodes, and they for their requests, unless told otherwise.
+const DEFAULT_TEST_TIMEOUT_SECONDS: u64 = 10;
+
+/// Builds a network of providers and clients running in the process, connected over the memory
+/// transport, with no TCP port, sleep or plumbing of their own for a test to write.
+///
+/// Each provider runs `run_with_dao` with an in-memory DAO on a thread of its own, with a tokio
+/// runtime of its own unless built for async-std, so that a test blocking its runtime does not
+/// stall the providers, while the clients run on the runtime of the test. Every node is dialed by
+/// those started after it, and the network is only returned once each node is connected to all
+/// the others and has bootstrapped its routing table.
+///
+/// # Examples
+///
+/// ```ignore
+/// use shard::testing::TestNet;
+///
+/// let net = TestNet::new().providers(3).clients(1).build().await?;
+/// let mut client = net.clients[0].client.clone();
+/// let owner = net.clients[0].peer_id;
+/// ```
+#[derive(Debug, Clone)]
+pub struct TestNet {
+    providers: usize,
+    clients: usize,
+    timeout: Duration,
+}
+
+impl Default for TestNet {
+    fn default() -> Self {
+        TestNet {
+            providers: 0,
+            clients: 0,
+            timeout: Duration::from_secs(DEFAULT_TEST_TIMEOUT_SECONDS),
+        }
+    }
+}
+
+impl TestNet {
+    /// Creates a builder of an empty network.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of providers started.
+    pub fn providers(mut self, providers: usize) -> Self {
+        self.providers = providers;
+        self
+    }
+
+    /// Sets the number of clients started.
+    pub fn clients(mut self, clients: usize) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    /// Sets how long the network waits for its nodes to connect, the requests of the nodes wait
+    /// for their responses, and the helpers of `RunningNet` wait for what they wait for.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Starts the nodes and connects them.
+    ///
+    /// # Returns
+    ///
+    /// The running network, or an error if a node could not be started or the nodes did not
+    /// all connect and bootstrap within the timeout.
+    pub async fn build(self) -> Result<RunningNet, Box<dyn Error>> {
+        let mut net = RunningNet {
+            providers: Vec::new(),
+            clients: Vec::new(),
+            timeout: self.timeout,
+        };
+        for _ in 0..self.providers {
+            net.providers.push(start_provider(self.timeout).await?);
+        }
+        for _ in 0..self.clients {
+            net.clients.push(start_client(self.timeout).await?);
+        }
+
+        let nodes: Vec<(PeerId, Multiaddr)> = net
+            .nodes()
+            .map(|node| (node.peer_id, node.addr.clone()))
+            .collect();
+        let mut clients: Vec<Client> = net.nodes().map(|node| node.client.clone()).collect();
+        for (i, client) in clients.iter_mut().enumerate() {
+            for (peer_id, addr) in &nodes[..i] {
+                client
+                    .dial(*peer_id, addr.clone())
+                    .await
+                    .map_err(|e| format!("cannot dial {}: {}", addr, e))?;
+            }
+        }
+        let criteria = ReadyCriteria {
+            min_connections: nodes.len().saturating_sub(1),
+            bootstrap: nodes.len() > 1,
+            ..Default::default()
+        };
+        for client in &mut clients {
+            client.await_ready(&criteria, self.timeout).await?;
+        }
+        Ok(net)
+    }
+}
+
+/// The kind of a request, to choose the requests a fault applies to.
+///
+/// # Variants
+///
+/// * `GetShare` - A request for a share.
+/// * `RegisterShare` - A request to register a share.
+/// * `RefreshShare` - A request to refresh a share.
+/// * `DeleteShare` - A request to delete a share.
+/// * `ListKeys` - A request for the keys of an owner.
+/// * `StatShare` - A request for the metadata of a share.
+/// * `GrantAccess` - A request to grant a reader access to a share.
+/// * `RevokeAccess` - A request to revoke the access of a reader.
+/// * `TransferOwnership` - A request to hand a share over to another owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestKind {
+    GetShare,
+    RegisterShare,
+    RefreshShare,
+    DeleteShare,
+    ListKeys,
+    StatShare,
+    GrantAccess,
+    RevokeAccess,
+    TransferOwnership,
+}
+
+impl RequestKind {
+    /// Returns the kind of a request.
+    pub fn of(request: &Request) -> Self {
+        match request {
+            Request::GetShare(_) => RequestKind::GetShare,
+            Request::RegisterShare(_) => RequestKind::RegisterShare,
+            Request::RefreshShare(_) => RequestKind::RefreshShare,
+            Request::DeleteShare(_) => RequestKind::DeleteShare,
+            Request::ListKeys(_) => RequestKind::ListKeys,
+            Request::StatShare(_) => RequestKind::StatShare,
+            Request::GrantAccess(_) => RequestKind::GrantAccess,
+            Request::RevokeAccess(_) => RequestKind::RevokeAccess,
+            Request::TransferOwnership(_) => RequestKind::TransferOwnership,
+        }
+    }
+}
+
+/// The faults a provider injects in the requests it answers.
+///
+/// # Fields
+///
+/// * `drop_next` - The kinds of the next requests dropped, one request for each entry.
+/// * `delay` - How long every response is held back.
+/// * `corrupt_shares` - Whether a byte of every share sent is flipped.
+#[derive(Debug, Default)]
+struct Faults {
+    drop_next: Vec<RequestKind>,
+    delay: Duration,
+    corrupt_shares: bool,
+}
+
+impl Faults {
+    /// Returns whether an inbound event is dropped, consuming the fault that drops it.
+    fn drops(&mut self, event: &Event) -> bool {
+        let Event::InboundRequest { request, .. } = event else {
+            return false;
+        };
+        let kind = RequestKind::of(request);
+        match self.drop_next.iter().position(|dropped| *dropped == kind) {
+            Some(i) => {
+                self.drop_next.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A node of a `RunningNet`.
+///
+/// # Fields
+///
+/// * `client` - The client of the node, to send commands to its network event loop.
+/// * `peer_id` - The `PeerId` of the node.
+/// * `keypair` - The identity key of the node, to sign requests with.
+/// * `addr` - The memory address the node listens on.
+/// * `dao` - The DAO a provider stores its shares in, `None` for a client.
+/// * `events` - The events a provider publishes, in the order it publishes them, `None` for a
+///   client. Events the test does not read are dropped once `PROVIDER_EVENT_BUFFER` of them are
+///   queued.
+pub struct TestNode {
+    pub client: Client,
+    pub peer_id: PeerId,
+    pub keypair: Keypair,
+    pub addr: Multiaddr,
+    pub dao: Option<SharedDao>,
+    pub events: Option<tokio::sync::mpsc::Receiver<ProviderEvent>>,
+    faults: Arc<Mutex<Faults>>,
+    running: Running,
+}
+
+/// What a node needs to be stopped.
+enum Running {
+    /// A provider loop, stopped by cancelling its token, which reports once it has stopped.
+    Provider {
+        shutdown: CancellationToken,
+        stopped: Option<oneshot::Receiver<()>>,
+    },
+    /// A client, whose events are held for its event loop to keep running.
+    Client {
+        _events: BoxStream<'static, Event>,
+        stopped: bool,
+    },
+}
+
+impl TestNode {
+    /// Returns whether the node is a provider.
+    pub fn is_provider(&self) -> bool {
+        matches!(self.running, Running::Provider { .. })
+    }
+
+    /// Drops the next request of `kind` the provider receives, unanswered, as if it were lost on
+    /// the way: the requester sees the request fail and the provider never handles it. Called
+    /// again, it drops one more request. Only providers answer requests, so the faults of a
+    /// client have no effect.
+    pub fn drop_next(&self, kind: RequestKind) {
+        self.faults().drop_next.push(kind);
+    }
+
+    /// Holds back every response of the provider by `delay`, a zero delay answering at once
+    /// again. A response held back past the timeout of the requester is never received.
+    pub fn delay_responses(&self, delay: Duration) {
+        self.faults().delay = delay;
+    }
+
+    /// Sets whether the provider flips a byte of every share it sends in answer to a
+    /// `GetShare` request, leaving the share it stores intact.
+    pub fn corrupt_shares(&self, corrupt: bool) {
+        self.faults().corrupt_shares = corrupt;
+    }
+
+    fn faults(&self) -> MutexGuard<'_, Faults> {
+        self.faults.lock().expect("the faults not to be poisoned")
+    }
+
+    /// Stops the node: a provider shuts down the way it does on a signal, and a client stops its
+    /// network event loop. Stopping a node already stopped does nothing.
+    pub async fn shutdown(&mut self) {
+        match &mut self.running {
+            Running::Provider { shutdown, stopped } => {
+                shutdown.cancel();
+                if let Some(stopped) = stopped.take() {
+                    let _ = stopped.await;
+                }
+            }
+            Running::Client { stopped, .. } => {
+                if !*stopped {
+                    *stopped = true;
+                    self.client.shutdown().await;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for TestNode {
+    fn drop(&mut self) {
+        // a provider outlives the runtime of the test on its own thread unless stopped
+        if let Running::Provider { shutdown, .. } = &self.running {
+            shutdown.cancel();
+        }
+    }
+}
+
+/// A network started by `TestNet`.
+///
+/// # Fields
+///
+/// * `providers` - The providers, in the order they were started.
+/// * `clients` - The clients, in the order they were started.
+pub struct RunningNet {
+    pub providers: Vec<TestNode>,
+    pub clients: Vec<TestNode>,
+    timeout: Duration,
+}
+
+impl RunningNet {
+    /// Returns every node, the providers first.
+    pub fn nodes(&self) -> impl Iterator<Item = &TestNode> {
+        self.providers.iter().chain(self.clients.iter())
+    }
+
+    /// Returns the peer ids of the providers.
+    pub fn provider_ids(&self) -> Vec<PeerId> {
+        self.providers.iter().map(|node| node.peer_id).collect()
+    }
+
+    /// Waits for `n` providers of a DHT record to be found, looking it up from the first client,
+    /// or the first provider of a network without clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The DHT record, such as `Client::provider_key` of a share.
+    /// * `n` - The number of providers to wait for.
+    ///
+    /// # Returns
+    ///
+    /// The providers found, or an error once the timeout of the network passed.
+    pub async fn wait_for_providers(
+        &self,
+        key: &str,
+        n: usize,
+    ) -> Result<HashSet<PeerId>, NotReady> {
+        let mut client = self
+            .clients
+            .first()
+            .or(self.providers.first())
+            .expect("a network with nodes")
+            .client
+            .clone();
+        let criteria = ReadyCriteria {
+            min_providers: n,
+            provider_key: Some(key.to_string()),
+            ..Default::default()
+        };
+        client.await_ready(&criteria, self.timeout).await
+    }
+
+    /// Polls `condition` until it holds, instead of sleeping for a time long enough.
+    ///
+    /// # Arguments
+    ///
+    /// * `condition` - Checks whether what is waited for happened.
+    ///
+    /// # Returns
+    ///
+    /// Whether the condition held before the timeout of the network passed.
+    pub async fn wait_until<F, Fut>(&self, mut condition: F) -> bool
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = bool>,
+    {
+        let deadline = Instant::now() + self.timeout;
+        let poll = Duration::from_millis(READY_POLL_MILLIS);
+        loop {
+            if condition().await {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            runtime::sleep_until((Instant::now() + poll).min(deadline)).await;
+        }
+    }
+
+    /// Severs the connectivity between two sides of the network: each node of a side blocks
+    /// every node of the other, closing their connections and refusing new ones, while nodes on
+    /// the same side stay connected.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - The peer ids of the nodes on one side.
+    /// * `other` - The peer ids of the nodes on the other side.
+    ///
+    /// # Returns
+    ///
+    /// Whether the sides were disconnected before the timeout of the network passed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a peer id is not one of a node of the network.
+    pub async fn partition(&self, side: &[PeerId], other: &[PeerId]) -> bool {
+        for a in side {
+            for b in other {
+                self.node(a).client.clone().block_peer(*b).await;
+                self.node(b).client.clone().block_peer(*a).await;
+            }
+        }
+        self.wait_until(|| async {
+            for a in side {
+                let connected = self.node(a).client.clone().connected_peers().await;
+                if other.iter().any(|b| connected.contains(b)) {
+                    return false;
+                }
+            }
+            true
+        })
+        .await
+    }
+
+    /// Reconnects the sides of a partition: the nodes unblock each other, and each node of
+    /// `side` dials every node of `other` again.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - The peer ids of the nodes on one side, as given to `partition`.
+    /// * `other` - The peer ids of the nodes on the other side.
+    ///
+    /// # Returns
+    ///
+    /// Whether every node of a side was connected to every node of the other before the
+    /// timeout of the network passed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a peer id is not one of a node of the network.
+    pub async fn heal(&self, side: &[PeerId], other: &[PeerId]) -> bool {
+        for a in side {
+            for b in other {
+                self.node(a).client.clone().allow_peer(*b).await;
+                self.node(b).client.clone().allow_peer(*a).await;
+                let addr = self.node(b).addr.clone();
+                if self.node(a).client.clone().dial(*b, addr).await.is_err() {
+                    return false;
+                }
+            }
+        }
+        self.wait_until(|| async {
+            for a in side {
+                let connected = self.node(a).client.clone().connected_peers().await;
+                if !other.iter().all(|b| connected.contains(b)) {
+                    return false;
+                }
+            }
+            true
+        })
+        .await
+    }
+
+    /// Returns the node of a peer id, panicking if the network has none.
+    fn node(&self, peer_id: &PeerId) -> &TestNode {
+        self.nodes()
+            .find(|node| node.peer_id == *peer_id)
+            .expect("a node of the network")
+    }
+
+    /// Stops every node, the clients first.
+    pub async fn shutdown(mut self) {
+        for node in self.clients.iter_mut().chain(self.providers.iter_mut()) {
+            node.shutdown().await;
+        }
+    }
+}
+
+/// Returns a memory address no other node of the process listens on.
+fn memory_addr() -> Multiaddr {
+    format!("/memory/{}", rand::random::<u64>())
+        .parse()
+        .expect("a valid memory address")
+}
+
+/// Starts a client node on the runtime of the caller.
+async fn start_client(timeout: Duration) -> Result<TestNode, Box<dyn Error>> {
+    let keypair = Keypair::generate_ed25519();
+    let (mut client, events, event_loop, peer_id) =
+        network::with_timeout(keypair.clone(), timeout).await?;
+    runtime::spawn(event_loop.run(None));
+    let addr = memory_addr();
+    client
+        .start_listening(addr.clone())
+        .await
+        .map_err(|e| format!("cannot listen on {}: {}", addr, e))?;
+    Ok(TestNode {
+        client,
+        peer_id,
+        keypair,
+        addr,
+        dao: None,
+        events: None,
+        faults: Arc::default(),
+        running: Running::Client {
+            _events: events.boxed(),
+            stopped: false,
+        },
+    })
+}
+
+/// Starts a provider on a thread of its own, running its network event loop and provider loop
+/// on a runtime of their own until the node is stopped.
+async fn start_provider(timeout: Duration) -> Result<TestNode, Box<dyn Error>> {
+    let (started, starting) = oneshot::channel();
+    let (stopped_sender, stopped) = oneshot::channel();
+    let shutdown = CancellationToken::new();
+    let token = shutdown.clone();
+    let faults = Arc::new(Mutex::new(Faults::default()));
+    let injected = faults.clone();
+    let (published, events) = tokio::sync::mpsc::channel(PROVIDER_EVENT_BUFFER);
+    let keypair = Keypair::generate_ed25519();
+    let identity = keypair.clone();
+    #[cfg(not(feature = "rt-async-std"))]
+    let own_runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    std::thread::Builder::new()
+        .name("shard-test-provider".to_string())
+        .spawn(move || {
+            let run = async move {
+                let setup = async {
+                    let (mut client, events, event_loop, peer_id) =
+                        network::with_timeout(identity, timeout).await?;
+                    runtime::spawn(event_loop.run(None));
+                    let addr = memory_addr();
+                    client
+                        .start_listening(addr.clone())
+                        .await
+                        .map_err(|e| format!("cannot listen on {}: {}", addr, e))?;
+                    let options = DaoOptions::default();
+                    let flush_policy = options.flush_policy;
+                    let dao = dao(options)?;
+                    Ok::<_, Box<dyn Error>>((client, events, peer_id, addr, dao, flush_policy))
+                };
+                let (client, events, peer_id, addr, dao, flush_policy) = match setup.await {
+                    Ok(node) => node,
+                    Err(e) => {
+                        let _ = started.send(Err(e.to_string()));
+                        return;
+                    }
+                };
+                let node = (client.clone(), peer_id, addr, dao.clone());
+                if started.send(Ok(node)).is_err() {
+                    return;
+                }
+                // the provider loop receives its requests and answers them through the faults
+                // set on the node
+                let dropping = injected.clone();
+                let events = events.filter(move |event| {
+                    let dropped = dropping
+                        .lock()
+                        .expect("the faults not to be poisoned")
+                        .drops(event);
+                    future::ready(!dropped)
+                });
+                let (sender, commands) = mpsc::channel(0);
+                runtime::spawn(forward_commands(commands, client, injected));
+                let mut client = Client {
+                    sender,
+                    trace_id: None,
+                };
+                // the provider logs why it could not start providing, if it could not
+                let _ = run_with_dao(
+                    dao,
+                    flush_policy,
+                    ProviderOptions {
+                        events: Some(published),
+                        ..Default::default()
+                    },
+                    peer_id,
+                    &mut client,
+                    events,
+                    token,
+                )
+                .await;
+                let _ = stopped_sender.send(());
+            };
+            #[cfg(not(feature = "rt-async-std"))]
+            tokio::task::LocalSet::new().block_on(&own_runtime, run);
+            #[cfg(feature = "rt-async-std")]
+            async_std::task::block_on(run);
+        })?;
+
+    let (client, peer_id, addr, dao) = starting
+        .await
+        .map_err(|_| "the provider thread stopped while starting")??;
+    Ok(TestNode {
+        client,
+        peer_id,
+        keypair,
+        addr,
+        dao: Some(dao),
+        events: Some(events),
+        faults,
+        running: Running::Provider {
+            shutdown,
+            stopped: Some(stopped),
+        },
+    })
+}
+
+/// Forwards the commands of a provider loop to its network event loop, injecting the faults
+/// set on the node into its responses.
+async fn forward_commands(
+    mut commands: mpsc::Receiver<Command>,
+    mut client: Client,
+    faults: Arc<Mutex<Faults>>,
+) {
+    while let Some(mut command) = commands.next().await {
+        let (delay, corrupt) = {
+            let faults = faults.lock().expect("the faults not to be poisoned");
+            (faults.delay, faults.corrupt_shares)
+        };
+        if let Command::RespondShare {
+            result: Ok(FieldShare {
+                share: (_, share), ..
+            }),
+            ..
+        } = &mut command
+        {
+            if let Some(byte) = share.first_mut().filter(|_| corrupt) {
+                *byte ^= 0xff;
+            }
+        }
+        let responds = matches!(
+            command,
+            Command::RespondShare { .. }
+                | Command::RespondRegisterShare { .. }
+                | Command::RespondRefreshShare { .. }
+                | Command::RespondDeleteShare { .. }
+                | Command::RespondListKeys { .. }
+                | Command::RespondStatShare { .. }
+                | Command::RespondInvalidRequest { .. }
+                | Command::RespondRateLimited { .. }
+                | Command::RespondBusy { .. }
+                | Command::RespondAccess { .. }
+                | Command::RespondTransferOwnership { .. }
+        );
+        if responds && !delay.is_zero() {
+            let mut sender = client.sender.clone();
+            runtime::spawn(async move {
+                runtime::sleep(delay).await;
+                let _ = sender.send(command).await;
+            });
+        } else if client.sender.send(command).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunked::{chunk_key, ChunkManifest, ChunkReader, ChunkWriter};
+    use crate::client::ClientError;
+    use crate::protocol::{
+        DeleteShareStatus, Failure, RegisterShareStatus, RelayGrant, ShareIndex, StatShareStatus,
+    };
+    use crate::rotation::rotate_owner;
+    use crate::sss::{combine_shares, generate_refresh_key, split_secret};
+    use std::collections::HashMap;
+
+    const SECRET: &[u8] = b"correct horse battery staple";
+
+    /// Narrows the index of a share split over GF(2^8), as providers return it, to the index
+    /// `sss` combines it under. It is 16 bits wide with the `sss16` feature.
+    #[allow(clippy::useless_conversion)]
+    fn gf256_index(index: ShareIndex) -> u8 {
+        u8::try_from(index).unwrap()
+    }
+
+    /// Splits `secret` with a threshold of 2 and registers one share with each provider of
+    /// `net` from its first client, granting each provider to relay refreshes, returning the
+    /// shares.
+    async fn split(net: &RunningNet, key: &str, secret: &[u8]) -> HashMap<u8, Vec<u8>> {
+        let owner = &net.clients[0];
+        let mut client = owner.client.clone();
+        let shares = split_secret(secret, 2, net.providers.len()).unwrap();
+        for (provider, (index, share)) in net.provider_ids().into_iter().zip(&shares) {
+            let grant = RelayGrant::signed(key, provider, &owner.keypair).unwrap();
+            let status = client
+                .request_register_share(
+                    (*index, share.clone()),
+                    key.to_string(),
+                    2,
+                    None,
+                    false,
+                    None,
+                    false,
+                    Some(grant),
+                    provider,
+                    owner.peer_id,
+                )
+                .await
+                .unwrap();
+            assert_eq!(status, RegisterShareStatus::Registered);
+        }
+        shares
+    }
+
+    /// Fetches the share of every provider holding one under `key` for the first client of
+    /// `net`, and combines them.
+    async fn combine(net: &RunningNet, key: &str) -> Vec<u8> {
+        let owner = net.clients[0].peer_id;
+        let providers = net
+            .wait_for_providers(&Client::provider_key(&owner, key), net.providers.len())
+            .await
+            .unwrap();
+        combine_shares(&fetch(net, key, providers).await).unwrap()
+    }
+
+    /// Fetches the shares of `providers` under `key` for the first client of `net`.
+    async fn fetch(
+        net: &RunningNet,
+        key: &str,
+        providers: impl IntoIterator<Item = PeerId>,
+    ) -> HashMap<u8, Vec<u8>> {
+        let owner = net.clients[0].peer_id;
+        let mut client = net.clients[0].client.clone();
+        let mut shares = HashMap::new();
+        for provider in providers {
+            let (index, share) = client
+                .request_share(provider, key.to_string(), owner, None)
+                .await
+                .unwrap();
+            shares.insert(gf256_index(index), share);
+        }
+        shares
+    }
+
+    /// Rebuilds the file split under `key` from the shares of its chunks for the first client of
+    /// `net`, checking it against its manifest.
+    async fn rebuild(
+        net: &RunningNet,
+        key: &str,
+        manifest: &ChunkManifest,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut writer = ChunkWriter::new(Vec::new());
+        for index in 0..manifest.chunks() {
+            let shares = fetch(net, &chunk_key(key, index), net.provider_ids()).await;
+            writer.write_chunk(&combine_shares(&shares).unwrap())?;
+        }
+        writer.finish(manifest)
+    }
+
+    #[tokio::test]
+    async fn test_split_secret_is_combined_from_the_providers() {
+        let net = TestNet::new()
+            .providers(3)
+            .clients(1)
+            .build()
+            .await
+            .unwrap();
+        let owner = net.clients[0].peer_id;
+
+        let shares = split(&net, "split-combine", SECRET).await;
+        for provider in &net.providers {
+            let dao = provider.dao.as_ref().unwrap().lock().unwrap();
+            let stored = dao.get_owned(&owner.to_bytes(), "split-combine").unwrap();
+            let (index, share) = stored.expect("a share on every provider").share;
+            assert_eq!(shares[&gf256_index(index)], share);
+        }
+        assert_eq!(combine(&net, "split-combine").await, SECRET);
+        net.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_refreshed_shares_change_and_still_combine() {
+        let net = TestNet::new()
+            .providers(3)
+            .clients(1)
+            .build()
+            .await
+            .unwrap();
+        let owner = net.clients[0].peer_id;
+        let shares = split(&net, "refreshed", SECRET).await;
+
+        let refresh_key = generate_refresh_key(2, SECRET.len()).unwrap();
+        let mut client = net.clients[0].client.clone();
+        for provider in net.provider_ids() {
+            let refreshed = client
+                .request_refresh_shares(
+                    "refreshed".to_string(),
+                    refresh_key.clone(),
+                    provider,
+                    owner,
+                    Some(1),
+                    None,
+                )
+                .await
+                .unwrap();
+            assert!(refreshed);
+        }
+
+        let stored = |node: &TestNode| {
+            let dao = node.dao.as_ref().unwrap().lock().unwrap();
+            dao.get_owned(&owner.to_bytes(), "refreshed")
+                .unwrap()
+                .unwrap()
+        };
+        for provider in &net.providers {
+            let entry = stored(provider);
+            assert_eq!(entry.epoch, 1);
+            assert_ne!(shares[&gf256_index(entry.share.0)], entry.share.1);
+        }
+        assert_eq!(combine(&net, "refreshed").await, SECRET);
+        net.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_relayed_without_the_owners_grant_is_refused() {
+        let net = TestNet::new()
+            .providers(2)
+            .clients(2)
+            .build()
+            .await
+            .unwrap();
+        let owner = net.clients[0].peer_id;
+        let shares = split(&net, "relayed", SECRET).await;
+        let stored = |node: &TestNode| {
+            let dao = node.dao.as_ref().unwrap().lock().unwrap();
+            dao.get_owned(&owner.to_bytes(), "relayed")
+                .unwrap()
+                .unwrap()
+        };
+        let unchanged = || {
+            for provider in &net.providers {
+                let entry = stored(provider);
+                assert_eq!(entry.epoch, 0);
+                assert_eq!(shares[&gf256_index(entry.share.0)], entry.share.1);
+            }
+        };
+
+        // another client claiming to relay a refresh for the owner changes no share, with no
+        // grant or one it signed itself
+        let refresh_key = generate_refresh_key(2, SECRET.len()).unwrap();
+        let thief = &net.clients[1];
+        for provider in net.provider_ids() {
+            let forged = RelayGrant::signed("relayed", thief.peer_id, &thief.keypair).unwrap();
+            for grant in [None, Some(forged)] {
+                let refreshed = thief
+                    .client
+                    .clone()
+                    .request_refresh_shares(
+                        "relayed".to_string(),
+                        refresh_key.clone(),
+                        provider,
+                        owner,
+                        Some(1),
+                        grant,
+                    )
+                    .await;
+                assert!(!matches!(refreshed, Ok(true)));
+            }
+        }
+        unchanged();
+
+        // the grant of one provider does not let another relay
+        let mut coordinator = net.providers[0].client.clone();
+        let target = net.providers[1].peer_id;
+        let refreshed = coordinator
+            .request_refresh_shares(
+                "relayed".to_string(),
+                refresh_key.clone(),
+                target,
+                owner,
+                Some(1),
+                stored(&net.providers[1]).relay_grant,
+            )
+            .await;
+        assert!(!matches!(refreshed, Ok(true)));
+        unchanged();
+
+        // a provider of the share relays one with its grant, as the coordinator of its refresh
+        let relayed = coordinator
+            .request_refresh_shares(
+                "relayed".to_string(),
+                refresh_key,
+                target,
+                owner,
+                Some(1),
+                stored(&net.providers[0]).relay_grant,
+            )
+            .await;
+        assert!(relayed.unwrap());
+        assert_eq!(stored(&net.providers[1]).epoch, 1);
+        net.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_stopped_provider_is_no_longer_connected() {
+        let mut net = TestNet::new()
+            .providers(2)
+            .clients(1)
+            .build()
+            .await
+            .unwrap();
+        let stopped = net.providers[1].peer_id;
+        net.providers[1].shutdown().await;
+
+        let mut client = net.clients[0].client.clone();
+        let disconnected = net
+            .wait_until(|| {
+                let mut client = client.clone();
+                async move { !client.connected_peers().await.contains(&stopped) }
+            })
+            .await;
+        assert!(disconnected);
+        assert_eq!(
+            client.connected_peers().await,
+            vec![net.providers[0].peer_id]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lost_refresh_leaves_epochs_divergent_and_detected() {
+        let net = TestNet::new()
+            .providers(3)
+            .clients(1)
+            .build()
+            .await
+            .unwrap();
+        let owner = net.clients[0].peer_id;
+        split(&net, "lost-refresh", SECRET).await;
+        net.providers[2].drop_next(RequestKind::RefreshShare);
+
+        let refresh_key = generate_refresh_key(2, SECRET.len()).unwrap();
+        let mut client = net.clients[0].client.clone();
+        let mut refreshed = Vec::new();
+        for provider in net.provider_ids() {
+            let result = client
+                .request_refresh_shares(
+                    "lost-refresh".to_string(),
+                    refresh_key.clone(),
+                    provider,
+                    owner,
+                    Some(1),
+                    None,
+                )
+                .await;
+            refreshed.push(matches!(result, Ok(true)));
+        }
+        assert_eq!(refreshed, [true, true, false]);
+
+        // the metadata of the shares reveals the provider the refresh never reached
+        let mut epochs = Vec::new();
+        for provider in net.provider_ids() {
+            let status = client
+                .request_stat_share("lost-refresh".to_string(), provider, owner)
+                .await
+                .unwrap();
+            match status {
+                StatShareStatus::Found(metadata) => epochs.push(metadata.epoch),
+                status => panic!("no metadata of the share: {:?}", status),
+            }
+        }
+        assert_eq!(epochs, [1, 1, 0]);
+
+        let ids = net.provider_ids();
+        let refreshed = fetch(&net, "lost-refresh", [ids[0], ids[1]]).await;
+        assert_eq!(combine_shares(&refreshed).unwrap(), SECRET);
+        let mixed = fetch(&net, "lost-refresh", [ids[0], ids[2]]).await;
+        assert_ne!(combine_shares(&mixed).unwrap(), SECRET);
+        net.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_share_is_caught_by_the_checksum_of_the_file() {
+        let net = TestNet::new()
+            .providers(2)
+            .clients(1)
+            .build()
+            .await
+            .unwrap();
+        let file = b"a file rebuilt from the shares of its chunks".repeat(3);
+        let chunk_bytes = 64;
+
+        let mut reader = ChunkReader::new(&file[..], chunk_bytes);
+        let mut index = 0;
+        while let Some(chunk) = reader.next_chunk().unwrap() {
+            split(&net, &chunk_key("corrupted", index), &chunk).await;
+            index += 1;
+        }
+        let manifest = ChunkManifest {
+            length: file.len() as u64,
+            chunk_bytes,
+            threshold: 2,
+            providers: net.provider_ids().iter().map(PeerId::to_string).collect(),
+            sha256: reader.sha256(),
+        };
+
+        net.providers[1].corrupt_shares(true);
+        assert!(rebuild(&net, "corrupted", &manifest).await.is_err());
+
+        // the shares stored are intact, only those sent were corrupted
+        net.providers[1].corrupt_shares(false);
+        assert_eq!(rebuild(&net, "corrupted", &manifest).await.unwrap(), file);
+        net.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_partitioned_combine_times_out_cleanly() {
+        let timeout = Duration::from_secs(3);
+        let net = TestNet::new()
+            .providers(2)
+            .clients(1)
+            .timeout(timeout)
+            .build()
+            .await
+            .unwrap();
+        let owner = net.clients[0].peer_id;
+        split(&net, "partitioned", SECRET).await;
+        let providers = net.provider_ids();
+        assert!(net.partition(&[owner], &providers).await);
+
+        // every share is out of reach, and the attempt to fetch them fails within the timeout
+        // rather than hanging
+        let mut client = net.clients[0].client.clone();
+        let attempt = async {
+            let mut fetched = Vec::new();
+            for provider in &providers {
+                let share = client
+                    .request_share(*provider, "partitioned".to_string(), owner, None)
+                    .await;
+                fetched.push(share.is_ok());
+            }
+            fetched
+        };
+        let fetched = tokio::time::timeout(2 * timeout, attempt).await;
+        assert_eq!(fetched.unwrap(), [false, false]);
+
+        // the providers still reach each other across their side of the partition
+        let mut provider = net.providers[0].client.clone();
+        assert!(provider.connected_peers().await.contains(&providers[1]));
+
+        assert!(net.heal(&[owner], &providers).await);
+        assert_eq!(combine(&net, "partitioned").await, SECRET);
+        net.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_slow_provider_answers_late_and_outlives_a_timed_out_request() {
+        let timeout = Duration::from_secs(2);
+        let net = TestNet::new()
+            .providers(1)
+            .clients(1)
+            .timeout(timeout)
+            .build()
+            .await
+            .unwrap();
+        let owner = net.clients[0].peer_id;
+        let provider = net.providers[0].peer_id;
+        let mut client = net.clients[0].client.clone();
+        let stat = || {
+            let mut client = client.clone();
+            async move {
+                client
+                    .request_stat_share("slow".to_string(), provider, owner)
+                    .await
+            }
+        };
+
+        let delay = Duration::from_millis(500);
+        net.providers[0].delay_responses(delay);
+        let started = Instant::now();
+        assert_eq!(stat().await.unwrap(), StatShareStatus::NotFound);
+        assert!(started.elapsed() >= delay);
+
+        let delay = timeout + Duration::from_secs(1);
+        net.providers[0].delay_responses(delay);
+        assert!(stat().await.is_err());
+        // the response is sent once the requester is gone, which the provider survives
+        tokio::time::sleep(delay - timeout).await;
+        net.providers[0].delay_responses(Duration::ZERO);
+        assert_eq!(stat().await.unwrap(), StatShareStatus::NotFound);
+        assert!(client.connected_peers().await.contains(&provider));
+        net.shutdown().await;
+    }
+
+    /// Reads the next `n` events a provider publishes, waiting up to `timeout` for each: a
+    /// provider answers a request before it publishes the event of it.
+    async fn next_events(node: &mut TestNode, n: usize, timeout: Duration) -> Vec<ProviderEvent> {
+        let events = node.events.as_mut().expect("the events of a provider");
+        let mut published = Vec::new();
+        for _ in 0..n {
+            let event = tokio::time::timeout(timeout, events.recv()).await;
+            published.push(event.unwrap().unwrap());
+        }
+        published
+    }
+
+    /// Fetches the shares `owner` registered under `key` with `providers` from the node of
+    /// `owner`, and combines them.
+    async fn combine_as(owner: &TestNode, key: &str, providers: Vec<PeerId>) -> Vec<u8> {
+        let mut client = owner.client.clone();
+        let mut shares = HashMap::new();
+        for provider in providers {
+            let (index, share) = client
+                .request_share(provider, key.to_string(), owner.peer_id, None)
+                .await
+                .unwrap();
+            shares.insert(gf256_index(index), share);
+        }
+        combine_shares(&shares).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rotation_is_resumed_and_locks_the_old_identity_out() {
+        let net = TestNet::new()
+            .providers(2)
+            .clients(2)
+            .build()
+            .await
+            .unwrap();
+        let (old, new) = (&net.clients[0], &net.clients[1]);
+        split(&net, "rotated", SECRET).await;
+        split(&net, "rotated-too", SECRET).await;
+        let unreachable = net.providers[1].peer_id;
+        net.providers[1].drop_next(RequestKind::TransferOwnership);
+
+        let mut report =
+            rotate_owner(&old.client, &old.keypair, &new.keypair, net.provider_ids()).await;
+        assert!(!report.is_complete());
+        assert_eq!(report.pending_providers(), vec![unreachable]);
+        let reached = net.providers[0].peer_id.to_string();
+        assert!(report.transferred.iter().all(|t| t.provider == reached));
+        assert_eq!(report.transferred.len(), 2);
+
+        // the provider left behind still holds the shares of the old identity
+        let mut client = old.client.clone();
+        let keys = client.request_all_keys(unreachable, old.peer_id).await;
+        assert_eq!(keys.unwrap(), ["rotated", "rotated-too"]);
+
+        let pending = report.pending_providers();
+        let resumed = rotate_owner(&old.client, &old.keypair, &new.keypair, pending).await;
+        report.merge(resumed);
+        assert!(report.is_complete(), "{:?}", report.pending);
+        assert_eq!(report.transferred.len(), 4);
+        assert_eq!(report.new_owner, new.peer_id.to_string());
+
+        // a rotation run again finds nothing left to transfer
+        let again = rotate_owner(&old.client, &old.keypair, &new.keypair, net.provider_ids()).await;
+        assert!(again.is_complete());
+        assert!(again.transferred.is_empty());
+
+        // every provider keeps the grant of the new owner to relay refreshes
+        for provider in &net.providers {
+            let dao = provider.dao.as_ref().unwrap().lock().unwrap();
+            let entry = dao.get_owned(&new.peer_id.to_bytes(), "rotated").unwrap();
+            let grant = entry.unwrap().relay_grant.unwrap();
+            let (peer, owner) = (provider.peer_id.to_bytes(), new.peer_id.to_bytes());
+            assert_eq!(grant.verify("rotated", &peer, &owner), Ok(()));
+        }
+
+        // the new owner gets, refreshes and deletes the shares
+        let record = Client::provider_key(&new.peer_id, "rotated");
+        assert!(net.wait_for_providers(&record, 2).await.is_ok());
+        assert_eq!(combine_as(new, "rotated", net.provider_ids()).await, SECRET);
+        let refresh_key = generate_refresh_key(2, SECRET.len()).unwrap();
+        let mut client = new.client.clone();
+        for provider in net.provider_ids() {
+            let refreshed = client
+                .request_refresh_shares(
+                    "rotated".to_string(),
+                    refresh_key.clone(),
+                    provider,
+                    new.peer_id,
+                    Some(1),
+                    None,
+                )
+                .await;
+            assert!(refreshed.unwrap());
+        }
+        assert_eq!(combine_as(new, "rotated", net.provider_ids()).await, SECRET);
+        for provider in net.provider_ids() {
+            let deleted = client
+                .request_delete_share("rotated-too".to_string(), provider, new.peer_id)
+                .await;
+            assert_eq!(deleted.unwrap(), DeleteShareStatus::Deleted);
+        }
+
+        // the old identity is locked out, and cannot hand the shares to anyone else
+        let thief = Keypair::generate_ed25519();
+        let mut client = old.client.clone();
+        for provider in net.provider_ids() {
+            let got = client
+                .request_share(provider, "rotated".to_string(), old.peer_id, None)
+                .await;
+            assert!(matches!(got, Err(ClientError::Failure(Failure::NotFound))));
+            let deleted = client
+                .request_delete_share("rotated".to_string(), provider, old.peer_id)
+                .await;
+            assert_eq!(deleted.unwrap(), DeleteShareStatus::NotFound);
+            let taken = client
+                .request_transfer_ownership("rotated".to_string(), provider, &old.keypair, &thief)
+                .await;
+            assert!(matches!(
+                taken,
+                Err(ClientError::Failure(Failure::NotFound))
+            ));
+            let keys = client.request_all_keys(provider, old.peer_id).await;
+            assert!(keys.unwrap().is_empty());
+        }
+        net.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_provider_publishes_the_register_get_refresh_flow() {
+        let mut net = TestNet::new()
+            .providers(2)
+            .clients(1)
+            .build()
+            .await
+            .unwrap();
+        let owner = net.clients[0].peer_id;
+        split(&net, "observed", SECRET).await;
+        assert_eq!(combine(&net, "observed").await, SECRET);
+        let refresh_key = generate_refresh_key(2, SECRET.len()).unwrap();
+        let mut client = net.clients[0].client.clone();
+        for provider in net.provider_ids() {
+            let refreshed = client
+                .request_refresh_shares(
+                    "observed".to_string(),
+                    refresh_key.clone(),
+                    provider,
+                    owner,
+                    Some(1),
+                    None,
+                )
+                .await;
+            assert!(refreshed.unwrap());
+        }
+        let missing = client
+            .request_share(net.providers[0].peer_id, "missing".to_string(), owner, None)
+            .await;
+        assert!(missing.is_err());
+
+        let key = "observed".to_string();
+        let flow = [
+            ProviderEvent::Registered {
+                key: key.clone(),
+                owner,
+            },
+            ProviderEvent::Served { key: key.clone() },
+            ProviderEvent::Refreshed { key, epoch: 1 },
+        ];
+        let timeout = net.timeout;
+        for provider in &mut net.providers {
+            assert_eq!(next_events(provider, flow.len(), timeout).await, flow);
+        }
+        let rejected = next_events(&mut net.providers[0], 1, timeout).await;
+        assert!(matches!(
+            &rejected[0],
+            ProviderEvent::Rejected { key, .. } if key == "missing"
+        ));
+        for provider in &mut net.providers {
+            assert!(provider.events.as_mut().unwrap().try_recv().is_err());
+        }
+        net.shutdown().await;
+    }
+}